@@ -32,8 +32,8 @@ use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_m
     )
 ))]
 use video_hw::{
-    Backend, BackendDecoderOptions, BackendError, BitstreamInput, Codec, DecodeSession,
-    DecoderConfig,
+    Backend, BackendDecoderOptions, BackendError, BitstreamFileReader, BitstreamInput, Codec,
+    DecodeSession, DecoderConfig, FallbackPolicy,
 };
 
 #[cfg(any(
@@ -54,9 +54,14 @@ fn run_decode(
         backend,
         DecoderConfig {
             codec,
-            fps: 30,
+            fps: 30.into(),
             require_hardware,
+            force_software: false,
+            fallback_policy: FallbackPolicy::default(),
             backend_options: BackendDecoderOptions::Default,
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
         },
     );
 
@@ -71,6 +76,42 @@ fn run_decode(
     Ok(())
 }
 
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+fn run_decode_access_units(
+    backend: Backend,
+    reader: &mut BitstreamFileReader,
+    require_hardware: bool,
+) -> Result<(), BackendError> {
+    let mut decoder = DecodeSession::new(
+        backend,
+        DecoderConfig {
+            codec: reader.codec(),
+            fps: 30.into(),
+            require_hardware,
+            force_software: false,
+            fallback_policy: FallbackPolicy::default(),
+            backend_options: BackendDecoderOptions::Default,
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
+        },
+    );
+
+    reader.rewind();
+    for input in reader.by_ref() {
+        decoder.submit(input)?;
+        while decoder.try_reap()?.is_some() {}
+    }
+    let _ = decoder.flush()?;
+    Ok(())
+}
+
 #[cfg(any(
     all(target_os = "macos", feature = "backend-vt"),
     all(
@@ -98,7 +139,12 @@ fn decode_benchmark(c: &mut Criterion) {
     let backends = vec![("nv", Backend::Nvidia)];
 
     for (backend_label, backend) in backends {
-        for (label, codec, data) in [("h264", Codec::H264, &h264), ("hevc", Codec::Hevc, &hevc)] {
+        for (label, codec, data, path) in [
+            ("h264", Codec::H264, &h264, "sample-videos/sample-10s.h264"),
+            ("hevc", Codec::Hevc, &hevc, "sample-videos/sample-10s.h265"),
+        ] {
+            let mut reader = BitstreamFileReader::open(path, codec)
+                .expect("sample bitstream should be readable for benchmark");
             for require_hardware in [false, true] {
                 let mode = if require_hardware {
                     "hw_required"
@@ -121,6 +167,19 @@ fn decode_benchmark(c: &mut Criterion) {
                         },
                     );
                 }
+                group.throughput(Throughput::Bytes(data.len() as u64));
+                group.bench_function(
+                    BenchmarkId::new(
+                        format!("{backend_label}/{label}"),
+                        format!("{mode}/access_unit"),
+                    ),
+                    |b| {
+                        b.iter(|| {
+                            run_decode_access_units(backend, &mut reader, require_hardware)
+                                .expect("decode should succeed in benchmark");
+                        });
+                    },
+                );
             }
         }
     }
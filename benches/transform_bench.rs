@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use video_hw::{make_argb_to_nv12_dummy, nv12_to_rgb24};
+
+// Run with and without `--features transform-rayon`; 1080p60 needs < 16.6 ms per frame.
+fn transform_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("nv12_to_rgb24");
+    group.sample_size(30);
+    group.measurement_time(Duration::from_secs(5));
+    group.warm_up_time(Duration::from_secs(1));
+
+    for (label, width, height) in [("720p", 1280, 720), ("1080p", 1920, 1080)] {
+        let frame = make_argb_to_nv12_dummy(width, height);
+        group.throughput(Throughput::Elements(1));
+        group.bench_with_input(BenchmarkId::from_parameter(label), &frame, |b, frame| {
+            b.iter(|| nv12_to_rgb24(frame).expect("conversion should succeed"));
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, transform_benchmark);
+criterion_main!(benches);
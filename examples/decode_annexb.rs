@@ -2,10 +2,8 @@ use std::{fs, path::PathBuf};
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use video_hw::{
-    Backend, BackendDecoderOptions, BitstreamInput, Codec, DecodeSession, DecoderConfig,
-    NvidiaDecoderOptions,
-};
+use video_hw::prelude::*;
+use video_hw::{BackendDecoderOptions, FallbackPolicy, NvidiaDecoderOptions};
 
 #[derive(Parser, Debug)]
 #[command(about = "Decode Annex-B stream")]
@@ -16,24 +14,45 @@ struct Args {
     codec: String,
     #[arg(long)]
     input: Option<PathBuf>,
-    #[arg(long, default_value_t = 30)]
-    fps: i32,
+    #[arg(long, default_value = "30")]
+    fps: FrameRate,
     #[arg(long, default_value_t = 65536)]
     chunk_bytes: usize,
     #[arg(long, default_value_t = false)]
     require_hardware: bool,
+    #[arg(long, default_value_t = false)]
+    force_software: bool,
     #[arg(long)]
     nv_report_metrics: Option<bool>,
+    #[arg(long)]
+    nv_decode_surfaces: Option<u32>,
+    #[arg(long)]
+    nv_output_surfaces: Option<u32>,
+    #[arg(long)]
+    nv_low_latency: Option<bool>,
+    #[arg(long)]
+    nv_histogram: Option<bool>,
+    #[arg(long)]
+    nv_slice_fast_start: Option<bool>,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
     let codec = parse_codec(&args.codec)?;
     let backend = parse_backend(&args.backend)?;
-    let input_path = args.input.unwrap_or_else(|| default_decode_input(codec));
+    let input_path = match args.input {
+        Some(path) => path,
+        None => default_decode_input(codec)?,
+    };
     let backend_options = if backend_is_nvidia(backend) {
         BackendDecoderOptions::Nvidia(NvidiaDecoderOptions {
             report_metrics: args.nv_report_metrics,
+            software_decoder: None,
+            decode_surfaces: args.nv_decode_surfaces,
+            output_surfaces: args.nv_output_surfaces,
+            low_latency: args.nv_low_latency,
+            histogram: args.nv_histogram,
+            slice_fast_start: args.nv_slice_fast_start,
         })
     } else {
         BackendDecoderOptions::Default
@@ -45,7 +64,12 @@ fn main() -> Result<()> {
             codec,
             fps: args.fps,
             require_hardware: args.require_hardware,
+            force_software: args.force_software,
+            fallback_policy: FallbackPolicy::default(),
             backend_options,
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
         },
     );
 
@@ -87,6 +111,7 @@ fn parse_codec(raw: &str) -> Result<Codec> {
     match raw.to_ascii_lowercase().as_str() {
         "h264" => Ok(Codec::H264),
         "hevc" | "h265" => Ok(Codec::Hevc),
+        "mjpeg" | "jpeg" => Ok(Codec::Mjpeg),
         other => anyhow::bail!("unsupported codec: {other}"),
     }
 }
@@ -128,9 +153,10 @@ fn backend_is_nvidia(_backend: Backend) -> bool {
     false
 }
 
-fn default_decode_input(codec: Codec) -> PathBuf {
+fn default_decode_input(codec: Codec) -> Result<PathBuf> {
     match codec {
-        Codec::H264 => PathBuf::from("sample-videos/sample-10s.h264"),
-        Codec::Hevc => PathBuf::from("sample-videos/sample-10s.h265"),
+        Codec::H264 => Ok(PathBuf::from("sample-videos/sample-10s.h264")),
+        Codec::Hevc => Ok(PathBuf::from("sample-videos/sample-10s.h265")),
+        Codec::Mjpeg => anyhow::bail!("no bundled mjpeg sample; pass --input"),
     }
 }
@@ -4,7 +4,7 @@ use anyhow::{Context, Result};
 use clap::Parser;
 use video_hw::{
     Backend, BackendEncoderOptions, Codec, Dimensions, EncodeFrame, EncodeSession, EncoderConfig,
-    NvidiaEncoderOptions, RawFrameBuffer, Timestamp90k,
+    FrameRate, NvBufferLifetimeMode, NvidiaEncoderOptions, RawFrameBuffer, Timestamp90k,
 };
 
 #[derive(Parser, Debug)]
@@ -14,8 +14,8 @@ struct Args {
     backend: String,
     #[arg(long, default_value = "h264")]
     codec: String,
-    #[arg(long, default_value_t = 30)]
-    fps: i32,
+    #[arg(long, default_value = "30")]
+    fps: FrameRate,
     #[arg(long, default_value_t = true)]
     require_hardware: bool,
     #[arg(long)]
@@ -77,7 +77,13 @@ fn main() -> Result<()> {
         options.gop_length = args.nv_gop_length;
         options.frame_interval_p = args.nv_frame_interval_p;
         options.report_metrics = args.nv_report_metrics;
-        options.safe_lifetime_mode = args.nv_safe_lifetime_mode;
+        options.buffer_lifetime_mode = args.nv_safe_lifetime_mode.map(|safe| {
+            if safe {
+                NvBufferLifetimeMode::PerFrameSafe
+            } else {
+                NvBufferLifetimeMode::ReusablePoolUnsafe
+            }
+        });
         options.enable_pipeline_scheduler = args.nv_enable_pipeline_scheduler;
         options.pipeline_queue_capacity = args.nv_pipeline_queue_capacity;
         config.backend_options = BackendEncoderOptions::Nvidia(options);
@@ -93,9 +99,11 @@ fn main() -> Result<()> {
 
         encoder.submit(EncodeFrame {
             dims,
-            pts_90k: Some(Timestamp90k((i as i64) * 3000)),
+            pts_90k: Some(Timestamp90k(args.fps.pts_90k(i as i64))),
             buffer: RawFrameBuffer::Argb8888(input[start..end].to_vec()),
             force_keyframe: i == 0,
+            dirty_rects: None,
+            repeat_count: 0,
         })?;
 
         while let Some(packet) = encoder.try_reap()? {
@@ -4,7 +4,7 @@ use anyhow::{Context, Result};
 use clap::Parser;
 use video_hw::{
     Backend, BackendEncoderOptions, Codec, Dimensions, EncodeFrame, EncodeSession, EncoderConfig,
-    NvidiaEncoderOptions, RawFrameBuffer, Timestamp90k,
+    FrameRate, NvBufferLifetimeMode, NvidiaEncoderOptions, RawFrameBuffer, Timestamp90k,
 };
 
 #[derive(Parser, Debug)]
@@ -14,8 +14,8 @@ struct Args {
     backend: String,
     #[arg(long, default_value = "h264")]
     codec: String,
-    #[arg(long, default_value_t = 30)]
-    fps: i32,
+    #[arg(long, default_value = "30")]
+    fps: FrameRate,
     #[arg(long, default_value_t = false)]
     require_hardware: bool,
     #[arg(long, default_value_t = 640)]
@@ -177,14 +177,20 @@ fn build_encoder(args: &Args, backend: Backend, codec: Codec) -> EncodeSession {
             options.max_in_flight_outputs = value.clamp(1, 64);
         }
         options.report_metrics = args.nv_report_metrics;
-        options.safe_lifetime_mode = args.nv_safe_lifetime_mode;
+        options.buffer_lifetime_mode = args.nv_safe_lifetime_mode.map(|safe| {
+            if safe {
+                NvBufferLifetimeMode::PerFrameSafe
+            } else {
+                NvBufferLifetimeMode::ReusablePoolUnsafe
+            }
+        });
         options.pipeline_queue_capacity = args.nv_pipeline_queue_capacity;
         config.backend_options = BackendEncoderOptions::Nvidia(options);
     }
     EncodeSession::new(backend, config)
 }
 
-fn make_frame(width: usize, height: usize, index: usize, fps: i32) -> Result<EncodeFrame> {
+fn make_frame(width: usize, height: usize, index: usize, fps: FrameRate) -> Result<EncodeFrame> {
     let dims = dims(width as u32, height as u32)?;
     let frame_size = width.saturating_mul(height).saturating_mul(4);
     let mut argb = vec![0u8; frame_size];
@@ -196,12 +202,13 @@ fn make_frame(width: usize, height: usize, index: usize, fps: i32) -> Result<Enc
         px[3] = 192;
     }
 
-    let pts_step_90k = (90_000 / fps.max(1)) as i64;
     Ok(EncodeFrame {
         dims,
-        pts_90k: Some(Timestamp90k((index as i64).saturating_mul(pts_step_90k))),
+        pts_90k: Some(Timestamp90k(fps.pts_90k(index as i64))),
         buffer: RawFrameBuffer::Argb8888(argb),
         force_keyframe: index == 0,
+        dirty_rects: None,
+        repeat_count: 0,
     })
 }
 
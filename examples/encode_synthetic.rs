@@ -3,8 +3,9 @@ use std::{fs, path::PathBuf};
 use anyhow::{Context, Result};
 use clap::Parser;
 use video_hw::{
-    Backend, BackendEncoderOptions, Codec, Dimensions, EncodeFrame, EncodeSession, EncoderConfig,
-    NvidiaEncoderOptions, RawFrameBuffer, Timestamp90k,
+    Backend, BackendEncoderOptions, Codec, ContentHint, Dimensions, EncodeFrame, EncodeSession,
+    EncoderConfig, FrameRate, NvBufferLifetimeMode, NvidiaEncoderOptions, RawFrameBuffer,
+    Timestamp90k,
 };
 
 #[derive(Parser, Debug)]
@@ -14,8 +15,8 @@ struct Args {
     backend: String,
     #[arg(long, default_value = "h264")]
     codec: String,
-    #[arg(long, default_value_t = 30)]
-    fps: i32,
+    #[arg(long, default_value = "30")]
+    fps: FrameRate,
     #[arg(long, default_value_t = false)]
     require_hardware: bool,
     #[arg(long, default_value_t = 30)]
@@ -37,6 +38,16 @@ struct Args {
     nv_enable_pipeline_scheduler: Option<bool>,
     #[arg(long)]
     nv_pipeline_queue_capacity: Option<usize>,
+    #[arg(long)]
+    nv_content_hint: Option<String>,
+    #[arg(long)]
+    nv_spatial_aq: Option<bool>,
+    #[arg(long)]
+    nv_temporal_aq: Option<bool>,
+    #[arg(long)]
+    nv_aq_strength: Option<u8>,
+    #[arg(long)]
+    nv_lookahead_depth: Option<u32>,
 }
 
 fn main() -> Result<()> {
@@ -53,9 +64,24 @@ fn main() -> Result<()> {
         options.gop_length = args.nv_gop_length;
         options.frame_interval_p = args.nv_frame_interval_p;
         options.report_metrics = args.nv_report_metrics;
-        options.safe_lifetime_mode = args.nv_safe_lifetime_mode;
+        options.buffer_lifetime_mode = args.nv_safe_lifetime_mode.map(|safe| {
+            if safe {
+                NvBufferLifetimeMode::PerFrameSafe
+            } else {
+                NvBufferLifetimeMode::ReusablePoolUnsafe
+            }
+        });
         options.enable_pipeline_scheduler = args.nv_enable_pipeline_scheduler;
         options.pipeline_queue_capacity = args.nv_pipeline_queue_capacity;
+        options.content_hint = args
+            .nv_content_hint
+            .as_deref()
+            .map(parse_content_hint)
+            .transpose()?;
+        options.spatial_aq = args.nv_spatial_aq;
+        options.temporal_aq = args.nv_temporal_aq;
+        options.aq_strength = args.nv_aq_strength;
+        options.lookahead_depth = args.nv_lookahead_depth;
         config.backend_options = BackendEncoderOptions::Nvidia(options);
     }
     let mut encoder = EncodeSession::new(backend, config);
@@ -68,9 +94,11 @@ fn main() -> Result<()> {
         let argb = synthetic_argb(640, 360, i);
         encoder.submit(EncodeFrame {
             dims,
-            pts_90k: Some(Timestamp90k((i as i64) * 3000)),
+            pts_90k: Some(Timestamp90k(args.fps.pts_90k(i as i64))),
             buffer: RawFrameBuffer::Argb8888(argb),
             force_keyframe: i == 0,
+            dirty_rects: None,
+            repeat_count: 0,
         })?;
         while let Some(packet) = encoder.try_reap()? {
             total_packets += 1;
@@ -84,6 +112,9 @@ fn main() -> Result<()> {
     }
 
     fs::write(&args.output, &out)?;
+    if let Some(latency) = encoder.induced_latency() {
+        println!("{latency}");
+    }
 
     println!(
         "packets={}, output_bytes={}, output={}, backend={}, codec={}",
@@ -105,6 +136,15 @@ fn parse_codec(raw: &str) -> Result<Codec> {
     }
 }
 
+fn parse_content_hint(raw: &str) -> Result<ContentHint> {
+    match raw.to_ascii_lowercase().as_str() {
+        "camera" => Ok(ContentHint::Camera),
+        "screen" => Ok(ContentHint::Screen),
+        "animation" => Ok(ContentHint::Animation),
+        other => anyhow::bail!("unsupported content hint: {other}"),
+    }
+}
+
 fn parse_backend(raw: &str) -> Result<Backend> {
     match raw.to_ascii_lowercase().as_str() {
         #[cfg(any(
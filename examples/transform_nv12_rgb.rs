@@ -2,7 +2,10 @@ use std::time::Duration;
 
 use anyhow::{Result, anyhow};
 use clap::Parser;
-use video_hw::{TransformDispatcher, TransformJob, TransformResult, make_argb_to_nv12_dummy};
+use video_hw::{
+    BackendError, Nv12Frame, TransformDispatcher, TransformJob, TransformResult, TransformStage,
+    make_argb_to_nv12_dummy,
+};
 
 #[derive(Debug, Parser)]
 #[command(about = "Run async NV12->RGB transforms on CPU workers")]
@@ -15,42 +18,75 @@ struct Args {
     width: usize,
     #[arg(long, default_value_t = 360)]
     height: usize,
+    // Run a custom luma-inverting stage instead of the built-in RGB conversion.
+    #[arg(long, default_value_t = false)]
+    custom_stage: bool,
+    // Submit every job as one batch and take the results back in order.
+    #[arg(long, default_value_t = false)]
+    batch: bool,
+}
+
+struct InvertLuma;
+
+impl TransformStage for InvertLuma {
+    fn process(&self, mut frame: Nv12Frame) -> Result<TransformResult, BackendError> {
+        let luma = frame.pitch * frame.height;
+        frame.data[..luma].iter_mut().for_each(|v| *v = 255 - *v);
+        Ok(TransformResult::Nv12(frame))
+    }
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
     let dispatcher = TransformDispatcher::new(args.workers, args.jobs.max(1));
+    let stage = args
+        .custom_stage
+        .then(|| dispatcher.register_stage(InvertLuma));
 
-    for _ in 0..args.jobs {
+    let jobs = (0..args.jobs).map(|_| {
         let frame = make_argb_to_nv12_dummy(args.width, args.height);
-        dispatcher
-            .submit(TransformJob::Nv12ToRgb(frame))
-            .map_err(|e| anyhow!("submit transform job failed: {e:?}"))?;
+        match stage {
+            Some(stage) => TransformJob::Custom { stage, frame },
+            None => TransformJob::Nv12ToRgb(frame),
+        }
+    });
+    let mut batched = Vec::new().into_iter();
+    if args.batch {
+        batched = dispatcher
+            .submit_batch(jobs.collect())
+            .map_err(|e| anyhow!("submit transform batch failed: {e:?}"))?
+            .wait_timeout(Duration::from_secs(10))
+            .map_err(|e| anyhow!("waiting transform batch timed out: {e:?}"))?
+            .into_iter();
+    } else {
+        for job in jobs {
+            dispatcher
+                .submit(job)
+                .map_err(|e| anyhow!("submit transform job failed: {e:?}"))?;
+        }
     }
 
     let mut completed = 0usize;
     while completed < args.jobs {
-        let result = dispatcher
-            .recv_timeout(Duration::from_secs(2))
-            .map_err(|e| anyhow!("waiting transform result timed out: {e:?}"))??;
-        match result {
-            TransformResult::Rgb(frame) => {
-                completed += 1;
-                if completed == 1 {
-                    println!(
-                        "first_result: {}x{}, bytes={}",
-                        frame.width,
-                        frame.height,
-                        frame.data.len()
-                    );
-                }
-            }
+        let result = match batched.next() {
+            Some(result) => result?,
+            None => dispatcher
+                .recv_timeout(Duration::from_secs(2))
+                .map_err(|e| anyhow!("waiting transform result timed out: {e:?}"))??,
+        };
+        let (width, height, bytes) = match &result {
+            TransformResult::Rgb(frame) => (frame.width, frame.height, frame.data.len()),
+            TransformResult::Nv12(frame) => (frame.width, frame.height, frame.data.len()),
+        };
+        completed += 1;
+        if completed == 1 {
+            println!("first_result: {width}x{height}, bytes={bytes}");
         }
     }
 
     println!(
-        "transform_completed={}, workers={}, jobs={}",
-        completed, args.workers, args.jobs
+        "transform_completed={}, workers={}, jobs={}, batch={}",
+        completed, args.workers, args.jobs, args.batch
     );
     Ok(())
 }
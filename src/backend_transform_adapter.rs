@@ -6,7 +6,7 @@ use crate::{BackendError, ColorRequest, Frame};
     feature = "backend-nvidia",
     any(target_os = "linux", target_os = "windows")
 ))]
-use crate::{Nv12Frame, RgbFrame, TransformJob};
+use crate::{Nv12Frame, PixelFormat, RgbFrame};
 #[cfg(all(
     feature = "backend-nvidia",
     any(target_os = "linux", target_os = "windows")
@@ -83,12 +83,10 @@ impl BackendTransformAdapter for NvidiaTransformAdapter {
                 feature = "backend-nvidia",
                 any(target_os = "linux", target_os = "windows")
             ))]
-            (DecodedUnit::Nv12Cpu(frame), ColorRequest::Rgb8 | ColorRequest::Rgba8) => {
-                self.dispatcher
-                    .submit(TransformJob::Nv12ToRgb(frame))
-                    .map_err(|e| BackendError::TemporaryBackpressure(format!("{e:?}")))?;
-                Ok(None)
-            }
+            (DecodedUnit::Nv12Cpu(frame), color) => Ok(self
+                .dispatcher
+                .submit_routed(frame, PixelFormat::Nv12, color)?
+                .map(DecodedUnit::Nv12Cpu)),
             (other, _) => Ok(Some(other)),
         }
     }
@@ -101,12 +99,18 @@ impl BackendTransformAdapter for NvidiaTransformAdapter {
                 any(target_os = "linux", target_os = "windows")
             ))]
             Ok(Ok(TransformResult::Rgb(rgb))) => Ok(Some(DecodedUnit::RgbCpu(rgb))),
+            #[cfg(all(
+                test,
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Ok(Ok(TransformResult::Nv12(frame))) => Ok(Some(DecodedUnit::Nv12Cpu(frame))),
             #[cfg(not(all(
                 test,
                 feature = "backend-nvidia",
                 any(target_os = "linux", target_os = "windows")
             )))]
-            Ok(Ok(TransformResult::Rgb(_rgb))) => Ok(None),
+            Ok(Ok(TransformResult::Rgb(_) | TransformResult::Nv12(_))) => Ok(None),
             Ok(Err(err)) => Err(err),
             Err(crate::QueueRecvError::Timeout) | Err(crate::QueueRecvError::Empty) => Ok(None),
             Err(err) => Err(BackendError::Backend(format!(
@@ -179,8 +183,13 @@ mod tests {
             color_primaries: None,
             transfer_function: None,
             ycbcr_matrix: None,
+            planes: None,
             argb: None,
             force_keyframe: false,
+            dirty_rects: None,
+            luma_histogram: None,
+            view: None,
+            stereo_right: None,
         });
         let output = adapter
             .submit(input, ColorRequest::KeepNative, None)
@@ -227,8 +236,13 @@ mod tests {
             color_primaries: None,
             transfer_function: None,
             ycbcr_matrix: None,
+            planes: None,
             argb: None,
             force_keyframe: false,
+            dirty_rects: None,
+            luma_histogram: None,
+            view: None,
+            stereo_right: None,
         });
         let output = adapter
             .submit(input, ColorRequest::KeepNative, None)
@@ -0,0 +1,179 @@
+use std::num::NonZeroU32;
+
+use crate::{Codec, Dimensions, EncoderConfig, FrameRate, Profile};
+
+// H.264 at 1080p30; other sizes and rates scale from here.
+const REFERENCE_BITRATE_BPS: f64 = 5_000_000.0;
+const REFERENCE_PIXELS: f64 = 1920.0 * 1080.0;
+const REFERENCE_FPS: f64 = 30.0;
+// Bits needed grow slower than pixels or frames: detail and motion per pixel drop as either
+// goes up.
+const SCALING_EXPONENT: f64 = 0.75;
+const DEFAULT_HEIGHTS: [u32; 6] = [2160, 1440, 1080, 720, 480, 360];
+
+#[derive(Debug, Clone)]
+pub struct Rendition {
+    pub dims: Dimensions,
+    pub bitrate_bps: u64,
+    pub config: EncoderConfig,
+}
+
+// ABR ladder from one source: each rung keeps the source aspect ratio (even dimensions, never
+// upscaled), gets a bitrate from a bits-per-pixel model, and shares the source frame rate and
+// keyframe_interval so segments line up across renditions.
+#[derive(Debug, Clone)]
+pub struct BitrateLadder {
+    codec: Codec,
+    profile: Profile,
+    source: Dimensions,
+    fps: FrameRate,
+    heights: Vec<u32>,
+    reference_bitrate_bps: f64,
+    gop_seconds: u32,
+}
+
+impl BitrateLadder {
+    pub fn new(codec: Codec, source: Dimensions, fps: impl Into<FrameRate>) -> Self {
+        Self {
+            codec,
+            profile: Profile::ArchiveQuality,
+            source,
+            fps: fps.into(),
+            heights: DEFAULT_HEIGHTS.to_vec(),
+            reference_bitrate_bps: REFERENCE_BITRATE_BPS,
+            gop_seconds: 2,
+        }
+    }
+
+    #[must_use]
+    pub fn with_heights(mut self, heights: impl IntoIterator<Item = u32>) -> Self {
+        self.heights = heights.into_iter().collect();
+        self
+    }
+
+    #[must_use]
+    pub fn with_profile(mut self, profile: Profile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    // The H.264 1080p30 bitrate every rung is scaled from.
+    #[must_use]
+    pub fn with_reference_bitrate(mut self, bitrate_bps: u64) -> Self {
+        self.reference_bitrate_bps = bitrate_bps as f64;
+        self
+    }
+
+    #[must_use]
+    pub fn with_gop_seconds(mut self, seconds: u32) -> Self {
+        self.gop_seconds = seconds.max(1);
+        self
+    }
+
+    // Highest rendition first. Heights above the source collapse into a single source-sized
+    // rung.
+    pub fn renditions(&self) -> Vec<Rendition> {
+        let source_height = self.source.height.get();
+        let mut heights = self
+            .heights
+            .iter()
+            .map(|&height| height.min(source_height))
+            .filter(|&height| height >= 2)
+            .collect::<Vec<_>>();
+        heights.sort_unstable_by(|a, b| b.cmp(a));
+        heights.dedup();
+        let keyframe_interval =
+            NonZeroU32::new(self.fps.rounded().saturating_mul(self.gop_seconds));
+        heights
+            .into_iter()
+            .filter_map(|height| {
+                let dims = self.scaled(height)?;
+                let bitrate_bps = self.bitrate_for(dims);
+                let mut config = self.profile.encoder_config(self.codec, self.fps);
+                config.keyframe_interval = keyframe_interval;
+                config.target_bitrate_bps = Some(bitrate_bps);
+                Some(Rendition {
+                    dims,
+                    bitrate_bps,
+                    config,
+                })
+            })
+            .collect()
+    }
+
+    fn scaled(&self, height: u32) -> Option<Dimensions> {
+        let source_height = self.source.height.get();
+        let width = if height == source_height {
+            self.source.width.get()
+        } else {
+            let width =
+                u64::from(self.source.width.get()) * u64::from(height) / u64::from(source_height);
+            u32::try_from(width).ok()?
+        };
+        Some(Dimensions {
+            width: NonZeroU32::new(width & !1)?,
+            height: NonZeroU32::new(height & !1)?,
+        })
+    }
+
+    fn bitrate_for(&self, dims: Dimensions) -> u64 {
+        let pixels = f64::from(dims.width.get()) * f64::from(dims.height.get());
+        let codec_factor = match self.codec {
+            Codec::H264 => 1.0,
+            Codec::Hevc => 0.65,
+            Codec::Mjpeg => 8.0,
+        };
+        let bitrate = self.reference_bitrate_bps
+            * codec_factor
+            * (pixels / REFERENCE_PIXELS).powf(SCALING_EXPONENT)
+            * (self.fps.as_f64() / REFERENCE_FPS).powf(SCALING_EXPONENT);
+        // Rounded to 10 kbps so ladders print cleanly.
+        ((bitrate / 10_000.0).round() as u64).max(1) * 10_000
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dims(width: u32, height: u32) -> Dimensions {
+        Dimensions {
+            width: NonZeroU32::new(width).unwrap(),
+            height: NonZeroU32::new(height).unwrap(),
+        }
+    }
+
+    #[test]
+    fn ladder_never_upscales_and_shares_the_gop() {
+        let ladder = BitrateLadder::new(Codec::H264, dims(1920, 1080), 30)
+            .with_heights([2160, 1080, 720, 360, 360]);
+        let renditions = ladder.renditions();
+        let sizes = renditions
+            .iter()
+            .map(|rendition| rendition.dims.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, ["1920x1080", "1280x720", "640x360"]);
+        assert_eq!(renditions[0].bitrate_bps, 5_000_000);
+        assert!(
+            renditions
+                .windows(2)
+                .all(|pair| pair[0].bitrate_bps > pair[1].bitrate_bps)
+        );
+        for rendition in &renditions {
+            assert_eq!(rendition.config.keyframe_interval, NonZeroU32::new(60));
+            assert_eq!(
+                rendition.config.target_bitrate_bps,
+                Some(rendition.bitrate_bps)
+            );
+        }
+
+        // Odd scaled widths are rounded down to even; HEVC needs fewer bits for the same rung.
+        let hevc = BitrateLadder::new(Codec::Hevc, dims(1000, 750), 30).with_heights([375]);
+        let rung = &hevc.renditions()[0];
+        assert_eq!(rung.dims, dims(500, 374));
+        assert!(
+            rung.bitrate_bps
+                < BitrateLadder::new(Codec::H264, dims(1000, 750), 30).bitrate_for(rung.dims)
+        );
+    }
+}
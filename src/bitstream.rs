@@ -1,34 +1,169 @@
+use std::collections::BTreeMap;
 use std::mem;
 
-use crate::{BackendError, Codec};
+use std::num::NonZeroU32;
 
-#[derive(Debug, Clone)]
-pub struct AccessUnit {
-    pub nalus: Vec<Vec<u8>>,
-    #[cfg(all(
+use crate::{BackendError, Codec, Dimensions, FrameCrop, find_start_codes, nal_type};
+#[cfg(any(
+    test,
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
         feature = "backend-nvidia",
         any(target_os = "linux", target_os = "windows")
-    ))]
-    pub pts_90k: Option<i64>,
+    )
+))]
+use crate::{EncodedLayout, split_annexb_nal_units, split_length_prefixed_nal_units};
+
+// One picture's NAL units in decode order, without start codes or length prefixes. For MJPEG
+// an access unit holds a single entry: one complete JPEG image from SOI to EOI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessUnit {
+    pub codec: Codec,
+    pub nalus: Vec<Vec<u8>>,
 }
 
+// Parameter sets by id, the way a decoder holds them: a set replaces only the one with the same
+// id (starting a new generation of that id), and slices select theirs through the PPS they
+// reference. Streams that alternate between SPS ids, as spliced broadcast feeds do, keep every
+// set available instead of the most recent one overwriting the rest.
 #[derive(Debug, Clone, Default)]
 pub struct ParameterSetCache {
-    h264_sps: Option<Vec<u8>>,
-    h264_pps: Option<Vec<u8>>,
-    hevc_vps: Option<Vec<u8>>,
-    hevc_sps: Option<Vec<u8>>,
-    hevc_pps: Option<Vec<u8>>,
+    vps: BTreeMap<u32, ParameterSet>,
+    sps: BTreeMap<u32, ParameterSet>,
+    pps: BTreeMap<u32, ParameterSet>,
+    jpeg_sof: Option<Vec<u8>>,
+    // SPS of the most recent slice, else of the most recently received SPS.
+    active_sps: Option<u32>,
+    latest_sps: Option<u32>,
+    // Stored sets changed; decoders built from the stored sets must be rebuilt.
+    content_revision: u64,
+    // Stored sets changed or slices switched to another SPS.
+    revision: u64,
+}
+
+// The slice header fields H.264 7.4.1.2.4 compares to find the first slice of a primary coded
+// picture: two slices belong to the same picture exactly when all of these match. nal_ref_idc
+// only matters as zero or non-zero; the picture order fields are the ones the SPS's
+// pic_order_cnt_type puts in the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct H264PictureId {
+    frame_num: u32,
+    pps_id: u32,
+    field_pic: bool,
+    bottom_field: bool,
+    reference: bool,
+    idr_pic_id: Option<u32>,
+    pic_order_cnt_lsb: Option<u32>,
+    delta_pic_order_cnt: [Option<i32>; 2],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ParameterSet {
+    data: Vec<u8>,
+    generation: u64,
+    // The VPS an HEVC SPS refers to, or the SPS a PPS refers to.
+    parent: Option<u32>,
+}
+
+// Where access units end in a stream of NAL units, kept apart from the NAL units themselves so
+// the assembler and the byte-range scan of bitstream files cut at the same places. Without
+// AUDs, access units end where the next picture's first slice begins: for H.264 where a slice's
+// picture id (H264PictureId) differs from the previous slice's, for HEVC at
+// first_slice_segment_in_pic_flag. Both fields of an interlaced frame are access units of their
+// own, like the specs define them. Parameter sets, SEI and the other NAL units that may open an
+// access unit are held back from `leading` on until the next slice shows which picture they go
+// with.
+#[derive(Debug, Default)]
+pub(crate) struct AccessUnitBoundaries {
+    saw_aud: bool,
+    has_vcl: bool,
+    leading: Option<usize>,
+    last_h264_picture: Option<H264PictureId>,
+}
+
+// Positions are the caller's own: NAL unit indexes, byte offsets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AccessUnitBoundary {
+    // The NAL units before this position are a complete access unit.
+    End(usize),
+    // The NAL units before this position hold no picture and are dropped.
+    Discard(usize),
+}
+
+impl AccessUnitBoundaries {
+    // `parameter_sets` must already have observed `nal`.
+    pub(crate) fn push(
+        &mut self,
+        codec: Codec,
+        nal: &[u8],
+        position: usize,
+        parameter_sets: &ParameterSetCache,
+    ) -> Option<AccessUnitBoundary> {
+        if is_aud(codec, nal) {
+            self.saw_aud = true;
+            self.leading = None;
+            return Some(if mem::take(&mut self.has_vcl) {
+                AccessUnitBoundary::End(position)
+            } else {
+                AccessUnitBoundary::Discard(position)
+            });
+        }
+
+        let nal_is_vcl = is_vcl(codec, nal);
+        let mut boundary = None;
+        if !self.saw_aud && self.has_vcl {
+            if !nal_is_vcl {
+                if self.leading.is_none() && may_open_access_unit(codec, nal) {
+                    self.leading = Some(position);
+                }
+                return None;
+            }
+            let leading = self.leading.take();
+            if self.opens_picture(codec, nal, parameter_sets) {
+                boundary = Some(AccessUnitBoundary::End(leading.unwrap_or(position)));
+            }
+        }
+        if nal_is_vcl {
+            if codec == Codec::H264 {
+                self.last_h264_picture = parameter_sets.h264_picture_id(nal);
+            }
+            self.has_vcl = true;
+        }
+        boundary
+    }
+
+    // End of stream: whether the NAL units since the last boundary are an access unit.
+    pub(crate) fn finish(&mut self) -> bool {
+        self.leading = None;
+        mem::take(&mut self.has_vcl)
+    }
+
+    // Whether this slice opens a new picture rather than continuing the current one. H.264
+    // slices whose header cannot be read (the SPS or PPS is missing) fall back to
+    // first_mb_in_slice == 0; data partitions B and C never open a picture. MV-HEVC slices of
+    // a second layer belong to the base-layer picture before them.
+    fn opens_picture(&self, codec: Codec, nal: &[u8], parameter_sets: &ParameterSetCache) -> bool {
+        match codec {
+            Codec::H264 => match (self.last_h264_picture, parameter_sets.h264_picture_id(nal)) {
+                (Some(last), Some(next)) => last != next,
+                _ => starts_picture(codec, nal),
+            },
+            Codec::Hevc => crate::multiview::hevc_layer_id(nal) == 0 && starts_picture(codec, nal),
+            Codec::Mjpeg => true,
+        }
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct StatefulBitstreamAssembler {
     codec: Option<Codec>,
     pending: Vec<u8>,
-    saw_aud: bool,
     current_nalus: Vec<Vec<u8>>,
-    current_has_vcl: bool,
+    boundaries: AccessUnitBoundaries,
     parameter_sets: ParameterSetCache,
+    max_temporal_id: Option<u8>,
+    // temporal_id from the H.264 prefix NAL unit announcing the next base-layer slice.
+    prefix_temporal_id: Option<u8>,
 }
 
 impl StatefulBitstreamAssembler {
@@ -44,6 +179,25 @@ impl StatefulBitstreamAssembler {
         }
     }
 
+    // Drops pictures above this temporal layer (SVC-T) before they reach the decoder, e.g. to
+    // decode 30 fps out of a 60 fps L1T2 stream. Parameter sets are still tracked.
+    #[must_use]
+    pub fn with_max_temporal_id(mut self, max_temporal_id: Option<u8>) -> Self {
+        self.max_temporal_id = max_temporal_id;
+        self
+    }
+
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    pub fn parameter_sets(&self) -> &ParameterSetCache {
+        &self.parameter_sets
+    }
+
     pub fn push_chunk(
         &mut self,
         chunk: &[u8],
@@ -54,6 +208,10 @@ impl StatefulBitstreamAssembler {
         if !chunk.is_empty() {
             self.pending.extend_from_slice(chunk);
         }
+        if codec == Codec::Mjpeg {
+            let access_units = self.take_complete_jpegs(false);
+            return Ok((access_units, self.parameter_sets.clone()));
+        }
 
         let nalus = self.take_complete_nals(false);
         let access_units = self.process_nals(codec, nalus);
@@ -61,111 +219,140 @@ impl StatefulBitstreamAssembler {
         Ok((access_units, self.parameter_sets.clone()))
     }
 
+    // Slice fast-start: the chunk is taken to end on a NAL boundary, so all of its NAL units are
+    // released at once rather than held for the next start code and the end of the access
+    // unit. Parameter sets are still tracked and the temporal-layer filter still applies.
+    #[cfg(all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    ))]
+    pub(crate) fn push_slices(&mut self, chunk: &[u8], codec: Codec) -> Vec<Vec<u8>> {
+        self.codec = Some(codec);
+        self.pending.extend_from_slice(chunk);
+        self.take_complete_nals(true)
+            .into_iter()
+            .filter(|nal| {
+                self.parameter_sets.observe(codec, nal);
+                !self.above_temporal_layer(codec, nal)
+            })
+            .collect()
+    }
+
     pub fn flush(&mut self) -> Result<(Vec<AccessUnit>, ParameterSetCache), BackendError> {
         let codec = self
             .codec
             .ok_or_else(|| BackendError::InvalidInput("codec is not set".to_string()))?;
+        if codec == Codec::Mjpeg {
+            let access_units = self.take_complete_jpegs(true);
+            return Ok((access_units, self.parameter_sets.clone()));
+        }
         let nalus = self.take_complete_nals(true);
         let mut access_units = self.process_nals(codec, nalus);
-        if self.current_has_vcl && !self.current_nalus.is_empty() {
-            access_units.push(self.finish_current_access_unit(codec));
+        if self.boundaries.finish() && !self.current_nalus.is_empty() {
+            access_units.push(AccessUnit {
+                codec,
+                nalus: mem::take(&mut self.current_nalus),
+            });
         }
 
         Ok((access_units, self.parameter_sets.clone()))
     }
 
+    // AUDs only mark boundaries and are not passed on.
     fn process_nals(&mut self, codec: Codec, nalus: Vec<Vec<u8>>) -> Vec<AccessUnit> {
         let mut out = Vec::new();
 
         for nal in nalus {
             self.parameter_sets.observe(codec, &nal);
-
-            if is_aud(codec, &nal) {
-                self.saw_aud = true;
-                if self.current_has_vcl && !self.current_nalus.is_empty() {
-                    out.push(self.finish_current_access_unit(codec));
-                } else {
-                    self.current_nalus.clear();
-                    self.clear_current_flags();
-                }
+            if self.above_temporal_layer(codec, &nal) {
                 continue;
             }
 
-            if !self.saw_aud
-                && is_vcl(codec, &nal)
-                && self.current_has_vcl
-                && !self.current_nalus.is_empty()
+            let position = self.current_nalus.len();
+            match self
+                .boundaries
+                .push(codec, &nal, position, &self.parameter_sets)
             {
-                out.push(self.finish_current_access_unit(codec));
+                Some(AccessUnitBoundary::End(end)) => {
+                    let next = self.current_nalus.split_off(end);
+                    out.push(AccessUnit {
+                        codec,
+                        nalus: mem::replace(&mut self.current_nalus, next),
+                    });
+                }
+                Some(AccessUnitBoundary::Discard(end)) => {
+                    self.current_nalus.drain(..end);
+                }
+                None => {}
             }
-
-            let nal_is_vcl = is_vcl(codec, &nal);
-            self.current_nalus.push(nal);
-            if nal_is_vcl {
-                self.record_vcl();
+            if !is_aud(codec, &nal) {
+                self.current_nalus.push(nal);
             }
         }
 
         out
     }
 
-    #[cfg(all(
-        feature = "backend-nvidia",
-        any(target_os = "linux", target_os = "windows")
-    ))]
-    fn finish_current_access_unit(&mut self, codec: Codec) -> AccessUnit {
-        let _ = codec;
-        let au = AccessUnit {
-            nalus: mem::take(&mut self.current_nalus),
-            pts_90k: None,
-        };
-        self.clear_current_flags();
-        au
-    }
-
-    #[cfg(not(all(
-        feature = "backend-nvidia",
-        any(target_os = "linux", target_os = "windows")
-    )))]
-    fn finish_current_access_unit(&mut self, codec: Codec) -> AccessUnit {
-        let _ = codec;
-        let au = AccessUnit {
-            nalus: mem::take(&mut self.current_nalus),
+    // Pictures of higher layers are never referenced by lower ones, so their slices can go
+    // without breaking the rest. H.264 takes the layer from the prefix NAL unit (or the SVC/MVC
+    // extension header); a slice without one is layer 0, except that non-reference slices are
+    // dropped too when only the base layer is wanted.
+    fn above_temporal_layer(&mut self, codec: Codec, nal: &[u8]) -> bool {
+        let Some(max) = self.max_temporal_id else {
+            return false;
         };
-        self.clear_current_flags();
-        au
-    }
-
-    #[cfg(all(
-        feature = "backend-nvidia",
-        any(target_os = "linux", target_os = "windows")
-    ))]
-    fn record_vcl(&mut self) {
-        self.current_has_vcl = true;
-    }
-
-    #[cfg(not(all(
-        feature = "backend-nvidia",
-        any(target_os = "linux", target_os = "windows")
-    )))]
-    fn record_vcl(&mut self) {
-        self.current_has_vcl = true;
+        match codec {
+            Codec::H264 => match nal_type(codec, nal) {
+                Some(14) => {
+                    self.prefix_temporal_id = h264_extension_temporal_id(nal);
+                    self.prefix_temporal_id.is_some_and(|tid| tid > max)
+                }
+                Some(20) => h264_extension_temporal_id(nal).is_some_and(|tid| tid > max),
+                Some(1..=5) => match self.prefix_temporal_id.take() {
+                    Some(tid) => tid > max,
+                    None => max == 0 && nal[0] & 0x60 == 0,
+                },
+                _ => false,
+            },
+            Codec::Hevc => is_vcl(codec, nal) && hevc_temporal_id(nal).is_some_and(|tid| tid > max),
+            Codec::Mjpeg => false,
+        }
     }
 
-    #[cfg(all(
-        feature = "backend-nvidia",
-        any(target_os = "linux", target_os = "windows")
-    ))]
-    fn clear_current_flags(&mut self) {
-        self.current_has_vcl = false;
-    }
+    // Bytes before the first SOI are dropped, as are images still incomplete at finalize time.
+    fn take_complete_jpegs(&mut self, finalize: bool) -> Vec<AccessUnit> {
+        let mut images = Vec::new();
+        let mut consumed = 0usize;
+        loop {
+            let rest = &self.pending[consumed..];
+            let Some(start) = find_jpeg_soi(rest) else {
+                // Keep a possible SOI split across chunks.
+                consumed = consumed.max(self.pending.len().saturating_sub(2));
+                break;
+            };
+            consumed += start;
+            let Some(len) = jpeg_image_len(&self.pending[consumed..]) else {
+                break;
+            };
+            images.push(self.pending[consumed..consumed + len].to_vec());
+            consumed += len;
+        }
+        if finalize {
+            self.pending.clear();
+        } else {
+            self.pending.drain(..consumed);
+        }
 
-    #[cfg(not(all(
-        feature = "backend-nvidia",
-        any(target_os = "linux", target_os = "windows")
-    )))]
-    fn clear_current_flags(&mut self) {
-        self.current_has_vcl = false;
+        images
+            .into_iter()
+            .map(|image| {
+                self.parameter_sets.observe(Codec::Mjpeg, &image);
+                AccessUnit {
+                    codec: Codec::Mjpeg,
+                    nalus: vec![image],
+                }
+            })
+            .collect()
     }
 
     fn take_complete_nals(&mut self, finalize: bool) -> Vec<Vec<u8>> {
@@ -217,84 +404,822 @@ impl StatefulBitstreamAssembler {
     }
 }
 
+impl AccessUnit {
+    // Types in NAL unit order; MJPEG images have none.
+    pub fn nal_types(&self) -> impl Iterator<Item = u8> + '_ {
+        self.nalus
+            .iter()
+            .filter_map(|nal| nal_type(self.codec, nal))
+    }
+
+    // Whether decoding can start here: an IDR, or for HEVC any IRAP picture (BLA/IDR/CRA).
+    pub fn is_keyframe(&self) -> bool {
+        self.nalus.iter().any(|nal| is_irap(self.codec, nal))
+    }
+
+    pub fn contains_vps(&self) -> bool {
+        self.codec == Codec::Hevc && self.nal_types().any(|nal_type| nal_type == 32)
+    }
+
+    pub fn contains_sps(&self) -> bool {
+        self.nal_types()
+            .any(|nal_type| matches!((self.codec, nal_type), (Codec::H264, 7) | (Codec::Hevc, 33)))
+    }
+
+    pub fn contains_pps(&self) -> bool {
+        self.nal_types()
+            .any(|nal_type| matches!((self.codec, nal_type), (Codec::H264, 8) | (Codec::Hevc, 34)))
+    }
+
+    // NAL unit bytes without framing; Annex B start codes and AVCC/HVCC length prefixes add
+    // 4 bytes per NAL unit on top.
+    pub fn byte_size(&self) -> usize {
+        self.nalus.iter().map(Vec::len).sum()
+    }
+}
+
 impl ParameterSetCache {
+    // The stored set of NAL type `nal_type` (VPS, SPS or PPS of `codec`) with this id.
+    pub fn get(&self, codec: Codec, nal_type: u8, id: u32) -> Option<&[u8]> {
+        Some(&self.sets(codec, nal_type)?.get(&id)?.data)
+    }
+
+    // Every stored set of NAL type `nal_type`, in id order.
+    pub fn iter_type(&self, codec: Codec, nal_type: u8) -> impl Iterator<Item = (u32, &[u8])> {
+        self.sets(codec, nal_type)
+            .into_iter()
+            .flatten()
+            .map(|(id, set)| (*id, set.data.as_slice()))
+    }
+
+    // The SPS the most recent slice referenced, else the most recently received one.
+    pub fn active_sps(&self) -> Option<&[u8]> {
+        let id = self.active_sps.or(self.latest_sps)?;
+        Some(&self.sps.get(&id)?.data)
+    }
+
+    // The SOF segment of the most recent JPEG image.
+    #[cfg(feature = "bitstream")]
+    pub fn jpeg_frame_header(&self) -> Option<&[u8]> {
+        self.jpeg_sof.as_deref()
+    }
+
+    fn sets(&self, codec: Codec, nal_type: u8) -> Option<&BTreeMap<u32, ParameterSet>> {
+        match (codec, nal_type) {
+            (Codec::Hevc, 32) => Some(&self.vps),
+            (Codec::H264, 7) | (Codec::Hevc, 33) => Some(&self.sps),
+            (Codec::H264, 8) | (Codec::Hevc, 34) => Some(&self.pps),
+            _ => None,
+        }
+    }
+
+    #[cfg(any(
+        test,
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    pub(crate) fn frame_crop(&self, codec: Codec) -> Option<FrameCrop> {
+        let id = self.active_sps.or(self.latest_sps)?;
+        parse_frame_crop(codec, &self.sps.get(&id)?.data)
+    }
+
+    #[cfg(any(
+        test,
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    pub(crate) fn revision(&self) -> u64 {
+        self.revision
+    }
+
     #[cfg(any(test, all(target_os = "macos", feature = "backend-vt")))]
+    pub(crate) fn content_revision(&self) -> u64 {
+        self.content_revision
+    }
+
+    // How many times the set with this id has been replaced by a different one.
+    #[cfg(test)]
+    fn sps_generation(&self, id: u32) -> Option<u64> {
+        self.sps.get(&id).map(|set| set.generation)
+    }
+
+    // Every stored set, VPS then SPS then PPS in id order, so one format description covers
+    // slices referring to any of them. None until each kind the codec needs has been seen.
     pub fn required_for_codec(&self, codec: Codec) -> Option<Vec<Vec<u8>>> {
-        match codec {
-            Codec::H264 => Some(vec![self.h264_sps.clone()?, self.h264_pps.clone()?]),
-            Codec::Hevc => Some(vec![
-                self.hevc_vps.clone()?,
-                self.hevc_sps.clone()?,
-                self.hevc_pps.clone()?,
-            ]),
+        if codec == Codec::Mjpeg {
+            return Some(vec![self.jpeg_sof.clone()?]);
         }
+        if self.sps.is_empty()
+            || self.pps.is_empty()
+            || (codec == Codec::Hevc && self.vps.is_empty())
+        {
+            return None;
+        }
+        Some(
+            self.vps
+                .values()
+                .chain(self.sps.values())
+                .chain(self.pps.values())
+                .map(|set| set.data.clone())
+                .collect(),
+        )
     }
 
-    fn observe(&mut self, codec: Codec, nal: &[u8]) {
+    pub(crate) fn observe(&mut self, codec: Codec, nal: &[u8]) {
         if nal.is_empty() {
             return;
         }
-
-        match codec {
-            Codec::H264 => match nal[0] & 0x1f {
-                7 => self.h264_sps = Some(nal.to_vec()),
-                8 => self.h264_pps = Some(nal.to_vec()),
-                _ => {}
-            },
-            Codec::Hevc => match (nal[0] >> 1) & 0x3f {
-                32 => self.hevc_vps = Some(nal.to_vec()),
-                33 => self.hevc_sps = Some(nal.to_vec()),
-                34 => self.hevc_pps = Some(nal.to_vec()),
-                _ => {}
+        if codec == Codec::Mjpeg {
+            if let Some(sof) = jpeg_frame_header(nal)
+                && self.jpeg_sof.as_deref() != Some(sof)
+            {
+                self.jpeg_sof = Some(sof.to_vec());
+                self.content_revision += 1;
+                self.revision += 1;
+            }
+            return;
+        }
+        if is_vcl(codec, nal) {
+            self.activate(codec, nal);
+            return;
+        }
+        // Ids that cannot be parsed (truncated units) fall back to 0, the id almost every
+        // single-SPS stream uses.
+        let (map, id, parent) = match (codec, nal_type(codec, nal)) {
+            (Codec::H264, Some(7)) => (&mut self.sps, h264_sps_id(nal), None),
+            (Codec::H264, Some(8)) => {
+                let (id, sps) = pps_ids(nal.get(1..).unwrap_or_default());
+                (&mut self.pps, id, sps)
+            }
+            (Codec::Hevc, Some(32)) => (
+                &mut self.vps,
+                nal.get(2).map(|byte| u32::from(byte >> 4)),
+                None,
+            ),
+            (Codec::Hevc, Some(33)) => {
+                let ids = hevc_sps_ids(&mut RbspReader::new(nal.get(2..).unwrap_or_default()));
+                (
+                    &mut self.sps,
+                    ids.map(|(_, sps)| sps),
+                    ids.map(|(vps, _)| vps),
+                )
+            }
+            (Codec::Hevc, Some(34)) => {
+                let (id, sps) = pps_ids(nal.get(2..).unwrap_or_default());
+                (&mut self.pps, id, sps)
+            }
+            _ => return,
+        };
+        let id = id.unwrap_or(0);
+        // Streams repeat identical parameter sets before every IDR; only real changes count.
+        let generation = match map.get(&id) {
+            Some(set) if set.data == nal && set.parent == parent => return,
+            Some(set) => set.generation + 1,
+            None => 0,
+        };
+        map.insert(
+            id,
+            ParameterSet {
+                data: nal.to_vec(),
+                generation,
+                parent,
             },
+        );
+        if matches!(nal_type(codec, nal), Some(7 | 33)) {
+            self.latest_sps = Some(id);
         }
+        self.content_revision += 1;
+        self.revision += 1;
+    }
+
+    fn h264_picture_id(&self, nal: &[u8]) -> Option<H264PictureId> {
+        let nal_type = nal_type(Codec::H264, nal)?;
+        if !matches!(nal_type, 1 | 2 | 5) {
+            return None;
+        }
+        let mut r = RbspReader::new(nal.get(1..)?);
+        r.ue()?; // first_mb_in_slice
+        r.ue()?; // slice_type
+        let pps_id = r.ue()?;
+        let pps = self.pps.get(&pps_id)?;
+        let sps = self.sps.get(&pps.parent.unwrap_or(0))?;
+        let sps = h264_sps(&mut RbspReader::new(sps.data.get(1..)?))?;
+        let bottom_field_pic_order_in_frame_present = {
+            let mut r = RbspReader::new(pps.data.get(1..)?);
+            r.ue()?; // pic_parameter_set_id
+            r.ue()?; // seq_parameter_set_id
+            r.skip(1)?; // entropy_coding_mode_flag
+            r.flag()?
+        };
+        if sps.separate_colour_plane {
+            r.skip(2)?; // colour_plane_id
+        }
+        let frame_num = r.bits(sps.log2_max_frame_num)?;
+        let field_pic = !sps.frame_mbs_only && r.flag()?;
+        let bottom_field = field_pic && r.flag()?;
+        let idr_pic_id = if nal_type == 5 { Some(r.ue()?) } else { None };
+        let mut pic_order_cnt_lsb = None;
+        let mut delta_pic_order_cnt = [None, None];
+        let bottom_present = bottom_field_pic_order_in_frame_present && !field_pic;
+        match sps.pic_order_cnt_type {
+            0 => {
+                pic_order_cnt_lsb = Some(r.bits(sps.log2_max_pic_order_cnt_lsb)?);
+                if bottom_present {
+                    delta_pic_order_cnt[0] = Some(r.se()?);
+                }
+            }
+            1 if !sps.delta_pic_order_always_zero => {
+                delta_pic_order_cnt[0] = Some(r.se()?);
+                if bottom_present {
+                    delta_pic_order_cnt[1] = Some(r.se()?);
+                }
+            }
+            _ => {}
+        }
+        Some(H264PictureId {
+            frame_num,
+            pps_id,
+            field_pic,
+            bottom_field,
+            reference: nal[0] & 0x60 != 0,
+            idr_pic_id,
+            pic_order_cnt_lsb,
+            delta_pic_order_cnt,
+        })
     }
-}
 
-fn find_start_codes(data: &[u8]) -> Vec<(usize, usize)> {
-    let mut out = Vec::new();
-    let mut i = 0usize;
-    while i + 3 <= data.len() {
-        if i + 4 <= data.len()
-            && data[i] == 0
-            && data[i + 1] == 0
-            && data[i + 2] == 0
-            && data[i + 3] == 1
+    // Follows the slice's PPS to its SPS. Switching SPS counts as a revision even when both
+    // were already stored, since the picture size and cropping may differ.
+    fn activate(&mut self, codec: Codec, nal: &[u8]) {
+        let Some(pps_id) = slice_pps_id(codec, nal) else {
+            return;
+        };
+        let Some(sps_id) = self.pps.get(&pps_id).map(|pps| pps.parent.unwrap_or(0)) else {
+            return;
+        };
+        if self
+            .active_sps
+            .replace(sps_id)
+            .is_some_and(|previous| previous != sps_id)
         {
-            out.push((i, 4));
-            i += 4;
-            continue;
+            self.revision += 1;
         }
-        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
-            out.push((i, 3));
-            i += 3;
-            continue;
+    }
+}
+
+// temporal_id of an H.264 prefix (14) or extension slice (20) NAL unit: the 3-byte header
+// extension that follows the NAL header is the SVC layout when its first bit is set, else MVC.
+fn h264_extension_temporal_id(nal: &[u8]) -> Option<u8> {
+    let extension = nal.get(1..4)?;
+    Some(if extension[0] & 0x80 != 0 {
+        extension[2] >> 5
+    } else {
+        (extension[2] >> 3) & 0x07
+    })
+}
+
+fn hevc_temporal_id(nal: &[u8]) -> Option<u8> {
+    (nal.get(1)? & 0x07).checked_sub(1)
+}
+
+fn h264_sps_id(nal: &[u8]) -> Option<u32> {
+    let mut r = RbspReader::new(nal.get(1..)?);
+    r.skip(24)?; // profile_idc, constraint flags, level_idc
+    r.ue()
+}
+
+// (pps_pic_parameter_set_id, pps_seq_parameter_set_id), which open the PPS in both codecs.
+fn pps_ids(payload: &[u8]) -> (Option<u32>, Option<u32>) {
+    let mut r = RbspReader::new(payload);
+    let id = r.ue();
+    (id, id.and_then(|_| r.ue()))
+}
+
+fn slice_pps_id(codec: Codec, nal: &[u8]) -> Option<u32> {
+    match codec {
+        Codec::H264 => {
+            // Data partitions B and C carry no slice header.
+            if !matches!(nal_type(codec, nal)?, 1 | 2 | 5) {
+                return None;
+            }
+            let mut r = RbspReader::new(nal.get(1..)?);
+            r.ue()?; // first_mb_in_slice
+            r.ue()?; // slice_type
+            r.ue()
+        }
+        Codec::Hevc => {
+            let mut r = RbspReader::new(nal.get(2..)?);
+            r.skip(1)?; // first_slice_segment_in_pic_flag
+            if (16..=23).contains(&nal_type(codec, nal)?) {
+                r.skip(1)?; // no_output_of_prior_pics_flag
+            }
+            r.ue()
         }
-        i += 1;
+        Codec::Mjpeg => None,
     }
-    out
 }
 
-fn is_aud(codec: Codec, nal: &[u8]) -> bool {
+pub(crate) fn is_aud(codec: Codec, nal: &[u8]) -> bool {
     if nal.is_empty() {
         return false;
     }
     match codec {
         Codec::H264 => (nal[0] & 0x1f) == 9,
         Codec::Hevc => ((nal[0] >> 1) & 0x3f) == 35,
+        Codec::Mjpeg => false,
+    }
+}
+
+// NAL units that open the next access unit when they follow the last slice of a picture
+// (H.264 7.4.1.2.3, HEVC 7.4.2.4.4): AUD, parameter sets, (prefix) SEI and reserved types.
+fn may_open_access_unit(codec: Codec, nal: &[u8]) -> bool {
+    matches!(
+        (codec, nal_type(codec, nal)),
+        (Codec::H264, Some(6..=9 | 14..=18))
+            | (Codec::Hevc, Some(32..=35 | 39 | 41..=44 | 48..=55))
+    )
+}
+
+// Nothing more of the current picture can follow an AUD, end of sequence or end of stream, so
+// slice fast-start hands a chunk ending in one to NVDEC as a finished picture.
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+pub(crate) fn closes_picture(codec: Codec, nal: &[u8]) -> bool {
+    matches!(
+        (codec, nal_type(codec, nal)),
+        (Codec::H264, Some(9..=11)) | (Codec::Hevc, Some(35..=37))
+    )
+}
+
+pub(crate) fn is_idr(codec: Codec, nal: &[u8]) -> bool {
+    if codec == Codec::Mjpeg {
+        return nal.starts_with(&JPEG_SOI);
     }
+    matches!(
+        (codec, nal_type(codec, nal)),
+        (Codec::H264, Some(5)) | (Codec::Hevc, Some(19 | 20))
+    )
 }
 
-fn is_vcl(codec: Codec, nal: &[u8]) -> bool {
+// Random access point: an IDR slice for H.264; BLA, IDR or CRA for HEVC; every JPEG image.
+pub(crate) fn is_irap(codec: Codec, nal: &[u8]) -> bool {
+    if codec == Codec::Mjpeg {
+        return true;
+    }
+    matches!(
+        (codec, nal_type(codec, nal)),
+        (Codec::H264, Some(5)) | (Codec::Hevc, Some(16..=21))
+    )
+}
+
+// Keyframe tag of one encoded access unit, read from its NAL unit types so the same content is
+// tagged the same way whichever backend produced it. None when no slice could be found (an
+// opaque layout, a truncated sample), in which case the backend's own hint stands.
+#[cfg(any(
+    test,
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+pub(crate) fn detect_keyframe(codec: Codec, layout: EncodedLayout, data: &[u8]) -> Option<bool> {
+    if codec == Codec::Mjpeg {
+        return Some(true);
+    }
+    let nals = match layout {
+        EncodedLayout::AnnexB => split_annexb_nal_units(data),
+        EncodedLayout::Avcc | EncodedLayout::Hvcc => split_length_prefixed_nal_units(data).ok()?,
+        EncodedLayout::Opaque => return None,
+    };
+    let mut slices = nals.into_iter().filter(|nal| is_vcl(codec, nal)).peekable();
+    slices.peek()?;
+    Some(slices.any(|nal| is_irap(codec, nal)))
+}
+
+pub(crate) fn is_parameter_set(codec: Codec, nal: &[u8]) -> bool {
+    matches!(
+        (codec, nal_type(codec, nal)),
+        (Codec::H264, Some(7 | 8)) | (Codec::Hevc, Some(32..=34))
+    )
+}
+
+// Copies `access_unit` with `parameter_sets` in front of its first NAL unit after the AUD.
+// `nals` is the access unit split by split_nals.
+pub(crate) fn insert_parameter_sets(
+    codec: Codec,
+    access_unit: &[u8],
+    nals: &[(usize, &[u8])],
+    parameter_sets: &BTreeMap<u8, Vec<u8>>,
+) -> Vec<u8> {
+    let insert_at = match nals.first() {
+        Some(&(_, nal)) if is_aud(codec, nal) => {
+            nals.get(1).map_or(access_unit.len(), |&(start, _)| start)
+        }
+        _ => 0,
+    };
+    let mut out = Vec::with_capacity(access_unit.len());
+    out.extend_from_slice(&access_unit[..insert_at]);
+    for nal in parameter_sets.values() {
+        out.extend_from_slice(&[0, 0, 0, 1]);
+        out.extend_from_slice(nal);
+    }
+    out.extend_from_slice(&access_unit[insert_at..]);
+    out
+}
+
+pub(crate) fn split_nals(access_unit: &[u8]) -> Vec<(usize, &[u8])> {
+    let start_codes = find_start_codes(access_unit);
+    start_codes
+        .iter()
+        .enumerate()
+        .map(|(index, &(start, start_len))| {
+            let end = start_codes
+                .get(index + 1)
+                .map_or(access_unit.len(), |&(next, _)| next);
+            (start, &access_unit[(start + start_len).min(end)..end])
+        })
+        .collect()
+}
+
+// First slice of a picture: first_mb_in_slice == 0 (a leading 1 bit in its ue(v)) for H.264,
+// first_slice_segment_in_pic_flag for HEVC.
+pub(crate) fn starts_picture(codec: Codec, nal: &[u8]) -> bool {
+    match codec {
+        Codec::H264 => {
+            matches!(nal_type(codec, nal), Some(1 | 2 | 5))
+                && nal.get(1).is_some_and(|byte| byte & 0x80 != 0)
+        }
+        Codec::Hevc => is_vcl(codec, nal) && nal.get(2).is_some_and(|byte| byte & 0x80 != 0),
+        Codec::Mjpeg => true,
+    }
+}
+
+pub(crate) fn is_vcl(codec: Codec, nal: &[u8]) -> bool {
     if nal.is_empty() {
         return false;
     }
     match codec {
         Codec::H264 => matches!(nal[0] & 0x1f, 1 | 2 | 3 | 4 | 5 | 19),
         Codec::Hevc => ((nal[0] >> 1) & 0x3f) <= 31,
+        Codec::Mjpeg => nal.starts_with(&JPEG_SOI),
+    }
+}
+
+const JPEG_SOI: [u8; 2] = [0xFF, 0xD8];
+
+pub(crate) fn find_jpeg_soi(data: &[u8]) -> Option<usize> {
+    data.windows(3)
+        .position(|window| window[..2] == JPEG_SOI && window[2] == 0xFF)
+}
+
+// Walks marker segments rather than searching for FFD9, so EOI markers inside an embedded EXIF
+// thumbnail or in stuffed entropy-coded data do not end the image early. Returns None until the
+// whole image is available.
+pub(crate) fn jpeg_image_len(data: &[u8]) -> Option<usize> {
+    if !data.starts_with(&JPEG_SOI) {
+        return None;
+    }
+    let mut pos = JPEG_SOI.len();
+    loop {
+        // Tolerate junk between segments by resyncing on the next marker prefix.
+        pos += data.get(pos..)?.iter().position(|&byte| byte == 0xFF)?;
+        let marker = *data.get(pos + 1)?;
+        match marker {
+            0xFF => pos += 1,
+            0xD9 => return Some(pos + 2),
+            0x01 | 0xD0..=0xD7 => pos += 2,
+            _ => {
+                let segment_len = usize::from(u16::from_be_bytes([
+                    *data.get(pos + 2)?,
+                    *data.get(pos + 3)?,
+                ]));
+                pos += 2 + segment_len;
+                if marker == 0xDA {
+                    pos = skip_entropy_coded_data(data, pos)?;
+                }
+            }
+        }
+    }
+}
+
+fn skip_entropy_coded_data(data: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        pos += data.get(pos..)?.iter().position(|&byte| byte == 0xFF)?;
+        match *data.get(pos + 1)? {
+            0x00 | 0xD0..=0xD7 => pos += 2,
+            0xFF => pos += 1,
+            _ => return Some(pos),
+        }
     }
 }
 
+// The SOF segment plays the role of the sequence header: dimensions and sampling factors.
+pub(crate) fn jpeg_frame_header(image: &[u8]) -> Option<&[u8]> {
+    let mut pos = JPEG_SOI.len();
+    while pos + 4 <= image.len() && image[pos] == 0xFF {
+        let marker = image[pos + 1];
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        if matches!(marker, 0x01 | 0xD0..=0xD7) {
+            pos += 2;
+            continue;
+        }
+        if marker == 0xD9 || marker == 0xDA {
+            return None;
+        }
+        let segment_len = usize::from(u16::from_be_bytes([image[pos + 2], image[pos + 3]]));
+        let end = (pos + 2 + segment_len).min(image.len());
+        if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            return Some(&image[pos..end]);
+        }
+        pos = end;
+    }
+    None
+}
+
+#[cfg(any(test, all(target_os = "macos", feature = "backend-vt")))]
+pub(crate) fn jpeg_dimensions(frame_header: &[u8]) -> Option<(u16, u16)> {
+    // FFCx, length, precision, then height and width.
+    let height = u16::from_be_bytes([*frame_header.get(5)?, *frame_header.get(6)?]);
+    let width = u16::from_be_bytes([*frame_header.get(7)?, *frame_header.get(8)?]);
+    Some((width, height))
+}
+
+// Reads the coded size and cropping window from an SPS NAL unit (header included).
+pub fn parse_frame_crop(codec: Codec, sps: &[u8]) -> Option<FrameCrop> {
+    let window = match codec {
+        Codec::H264 => h264_sps_window(&mut RbspReader::new(sps.get(1..)?))?,
+        Codec::Hevc => hevc_sps_window(&mut RbspReader::new(sps.get(2..)?))?,
+        Codec::Mjpeg => return None,
+    };
+    let dims = |width: u32, height: u32| {
+        Some(Dimensions {
+            width: NonZeroU32::new(width)?,
+            height: NonZeroU32::new(height)?,
+        })
+    };
+    let offsets = window.left.checked_add(window.right)?;
+    let display_width = window.coded_width.checked_sub(offsets)?;
+    let offsets = window.top.checked_add(window.bottom)?;
+    let display_height = window.coded_height.checked_sub(offsets)?;
+    Some(FrameCrop {
+        coded: dims(window.coded_width, window.coded_height)?,
+        display: dims(display_width, display_height)?,
+        left: window.left,
+        top: window.top,
+    })
+}
+
+// Offsets are in luma samples.
+struct SpsWindow {
+    coded_width: u32,
+    coded_height: u32,
+    left: u32,
+    right: u32,
+    top: u32,
+    bottom: u32,
+}
+
+struct RbspReader {
+    data: Vec<u8>,
+    bit: usize,
+}
+
+impl RbspReader {
+    fn new(payload: &[u8]) -> Self {
+        // Drop emulation prevention bytes (00 00 03 -> 00 00).
+        let mut data = Vec::with_capacity(payload.len());
+        let mut zeros = 0;
+        for &byte in payload {
+            if zeros >= 2 && byte == 0x03 {
+                zeros = 0;
+                continue;
+            }
+            zeros = if byte == 0 { zeros + 1 } else { 0 };
+            data.push(byte);
+        }
+        Self { data, bit: 0 }
+    }
+
+    fn bits(&mut self, count: u32) -> Option<u32> {
+        let mut value = 0u32;
+        for _ in 0..count {
+            let byte = *self.data.get(self.bit / 8)?;
+            value = (value << 1) | u32::from((byte >> (7 - self.bit % 8)) & 1);
+            self.bit += 1;
+        }
+        Some(value)
+    }
+
+    fn skip(&mut self, count: usize) -> Option<()> {
+        self.bit = self.bit.checked_add(count)?;
+        (self.bit <= self.data.len() * 8).then_some(())
+    }
+
+    fn flag(&mut self) -> Option<bool> {
+        Some(self.bits(1)? == 1)
+    }
+
+    fn ue(&mut self) -> Option<u32> {
+        let mut leading_zeros = 0;
+        while !self.flag()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return None;
+            }
+        }
+        Some((1u32 << leading_zeros) - 1 + self.bits(leading_zeros)?)
+    }
+
+    fn se(&mut self) -> Option<i32> {
+        let code = self.ue()?;
+        let magnitude = i32::try_from(code.div_ceil(2)).ok()?;
+        Some(if code % 2 == 1 { magnitude } else { -magnitude })
+    }
+}
+
+// (SubWidthC, SubHeightC) for chroma_format_idc; monochrome and 4:4:4 crop in luma units.
+fn chroma_subsampling(chroma_format_idc: u32) -> (u32, u32) {
+    match chroma_format_idc {
+        1 => (2, 2),
+        2 => (2, 1),
+        _ => (1, 1),
+    }
+}
+
+// The SPS fields slice headers and the cropping window depend on.
+struct H264Sps {
+    chroma_format_idc: u32,
+    separate_colour_plane: bool,
+    log2_max_frame_num: u32,
+    pic_order_cnt_type: u32,
+    log2_max_pic_order_cnt_lsb: u32,
+    delta_pic_order_always_zero: bool,
+    width_in_mbs: u32,
+    height_in_map_units: u32,
+    frame_mbs_only: bool,
+}
+
+// Reads up to and including frame_mbs_only_flag.
+fn h264_sps(r: &mut RbspReader) -> Option<H264Sps> {
+    let profile_idc = r.bits(8)?;
+    r.skip(16)?; // constraint flags, level_idc
+    r.ue()?; // seq_parameter_set_id
+    let mut chroma_format_idc = 1;
+    let mut separate_colour_plane = false;
+    if matches!(
+        profile_idc,
+        100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+    ) {
+        chroma_format_idc = r.ue()?;
+        if chroma_format_idc == 3 {
+            separate_colour_plane = r.flag()?;
+        }
+        r.ue()?; // bit_depth_luma_minus8
+        r.ue()?; // bit_depth_chroma_minus8
+        r.skip(1)?; // qpprime_y_zero_transform_bypass_flag
+        if r.flag()? {
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for index in 0..lists {
+                if r.flag()? {
+                    skip_h264_scaling_list(r, if index < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+    let log2_max_frame_num = r.ue()?.checked_add(4).filter(|&bits| bits <= 16)?;
+    let pic_order_cnt_type = r.ue()?;
+    let mut log2_max_pic_order_cnt_lsb = 0;
+    let mut delta_pic_order_always_zero = false;
+    match pic_order_cnt_type {
+        0 => {
+            log2_max_pic_order_cnt_lsb = r.ue()?.checked_add(4).filter(|&bits| bits <= 16)?;
+        }
+        1 => {
+            delta_pic_order_always_zero = r.flag()?;
+            r.se()?;
+            r.se()?;
+            for _ in 0..r.ue()? {
+                r.se()?;
+            }
+        }
+        _ => {}
+    }
+    r.ue()?; // max_num_ref_frames
+    r.skip(1)?; // gaps_in_frame_num_value_allowed_flag
+    Some(H264Sps {
+        chroma_format_idc,
+        separate_colour_plane,
+        log2_max_frame_num,
+        pic_order_cnt_type,
+        log2_max_pic_order_cnt_lsb,
+        delta_pic_order_always_zero,
+        width_in_mbs: r.ue()?.checked_add(1)?,
+        height_in_map_units: r.ue()?.checked_add(1)?,
+        frame_mbs_only: r.flag()?,
+    })
+}
+
+fn h264_sps_window(r: &mut RbspReader) -> Option<SpsWindow> {
+    let sps = h264_sps(r)?;
+    let frame_mbs_only = u32::from(sps.frame_mbs_only);
+    if frame_mbs_only == 0 {
+        r.skip(1)?; // mb_adaptive_frame_field_flag
+    }
+    r.skip(1)?; // direct_8x8_inference_flag
+    let (left, right, top, bottom) = if r.flag()? {
+        (r.ue()?, r.ue()?, r.ue()?, r.ue()?)
+    } else {
+        (0, 0, 0, 0)
+    };
+    let (crop_x, sub_height) = chroma_subsampling(sps.chroma_format_idc);
+    let crop_y = sub_height * (2 - frame_mbs_only);
+    Some(SpsWindow {
+        coded_width: sps.width_in_mbs.checked_mul(16)?,
+        coded_height: sps
+            .height_in_map_units
+            .checked_mul(16 * (2 - frame_mbs_only))?,
+        left: left.checked_mul(crop_x)?,
+        right: right.checked_mul(crop_x)?,
+        top: top.checked_mul(crop_y)?,
+        bottom: bottom.checked_mul(crop_y)?,
+    })
+}
+
+fn skip_h264_scaling_list(r: &mut RbspReader, size: usize) -> Option<()> {
+    let mut last_scale = 8i32;
+    let mut next_scale = 8i32;
+    for _ in 0..size {
+        if next_scale != 0 {
+            next_scale = (last_scale + r.se()?).rem_euclid(256);
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Some(())
+}
+
+// (sps_video_parameter_set_id, sps_seq_parameter_set_id), reading past profile_tier_level.
+fn hevc_sps_ids(r: &mut RbspReader) -> Option<(u32, u32)> {
+    let vps_id = r.bits(4)?;
+    let max_sub_layers_minus1 = r.bits(3)? as usize;
+    r.skip(1)?; // sps_temporal_id_nesting_flag
+    // profile_tier_level: general profile (88 bits) + general_level_idc.
+    r.skip(96)?;
+    let mut sub_layers = Vec::with_capacity(max_sub_layers_minus1);
+    for _ in 0..max_sub_layers_minus1 {
+        sub_layers.push((r.flag()?, r.flag()?));
+    }
+    if max_sub_layers_minus1 > 0 {
+        r.skip(2 * (8 - max_sub_layers_minus1))?;
+    }
+    for (profile_present, level_present) in sub_layers {
+        if profile_present {
+            r.skip(88)?;
+        }
+        if level_present {
+            r.skip(8)?;
+        }
+    }
+    Some((vps_id, r.ue()?))
+}
+
+fn hevc_sps_window(r: &mut RbspReader) -> Option<SpsWindow> {
+    hevc_sps_ids(r)?;
+    let chroma_format_idc = r.ue()?;
+    if chroma_format_idc == 3 {
+        r.skip(1)?; // separate_colour_plane_flag
+    }
+    let coded_width = r.ue()?;
+    let coded_height = r.ue()?;
+    let (left, right, top, bottom) = if r.flag()? {
+        (r.ue()?, r.ue()?, r.ue()?, r.ue()?)
+    } else {
+        (0, 0, 0, 0)
+    };
+    let (sub_width, sub_height) = chroma_subsampling(chroma_format_idc);
+    Some(SpsWindow {
+        coded_width,
+        coded_height,
+        left: left.checked_mul(sub_width)?,
+        right: right.checked_mul(sub_width)?,
+        top: top.checked_mul(sub_height)?,
+        bottom: bottom.checked_mul(sub_height)?,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -340,6 +1265,200 @@ mod tests {
         }
     }
 
+    #[test]
+    fn temporal_layer_filter_keeps_only_base_layer_pictures() {
+        let annexb = |nals: &[&[u8]]| {
+            nals.iter()
+                .flat_map(|nal| [&[0, 0, 0, 1][..], nal].concat())
+                .collect::<Vec<_>>()
+        };
+        let count_aus = |codec: Codec, data: &[u8], max_temporal_id: Option<u8>| {
+            let mut assembler =
+                StatefulBitstreamAssembler::with_codec(codec).with_max_temporal_id(max_temporal_id);
+            let (mut aus, _) = assembler.push_chunk(data, codec, None).unwrap();
+            aus.extend(assembler.flush().unwrap().0);
+            aus.len()
+        };
+
+        // L1T2 H.264 with SVC prefix NAL units: T0 IDR, T1, T0, T1.
+        let prefix = |tid: u8| [0x6E, 0xC0, 0x80, (tid << 5) | 0x07];
+        let h264 = annexb(&[
+            &[0x09, 0xF0],
+            &prefix(0),
+            &[0x65, 0x88, 0x84],
+            &[0x09, 0xF0],
+            &prefix(1),
+            &[0x01, 0x9A, 0x22],
+            &[0x09, 0xF0],
+            &prefix(0),
+            &[0x41, 0x9A, 0x24],
+            &[0x09, 0xF0],
+            &prefix(1),
+            &[0x01, 0x9A, 0x26],
+        ]);
+        assert_eq!(count_aus(Codec::H264, &h264, None), 4);
+        assert_eq!(count_aus(Codec::H264, &h264, Some(1)), 4);
+        assert_eq!(count_aus(Codec::H264, &h264, Some(0)), 2);
+
+        // HEVC carries the layer in the NAL header: TRAIL_R slices at T0, T1, T0.
+        let hevc = annexb(&[
+            &[0x02, 0x01, 0xAF],
+            &[0x02, 0x02, 0xAF],
+            &[0x02, 0x01, 0xAF],
+        ]);
+        assert_eq!(count_aus(Codec::Hevc, &hevc, Some(0)), 2);
+
+        // MV-HEVC: each layer-1 slice joins the base-layer picture it follows.
+        let stereo = annexb(&[
+            &[0x02, 0x01, 0xAF],
+            &[0x02, 0x09, 0xAF],
+            &[0x02, 0x01, 0xAF],
+            &[0x02, 0x09, 0xAF],
+        ]);
+        assert_eq!(count_aus(Codec::Hevc, &stereo, None), 2);
+    }
+
+    #[test]
+    fn access_units_without_aud_follow_the_slice_headers() {
+        let nal_counts = |codec: Codec, nals: &[Vec<u8>]| {
+            let data = nals
+                .iter()
+                .flat_map(|nal| [&[0, 0, 0, 1][..], nal].concat())
+                .collect::<Vec<_>>();
+            let mut assembler = StatefulBitstreamAssembler::with_codec(codec);
+            let (mut aus, _) = assembler.push_chunk(&data, codec, None).unwrap();
+            aus.extend(assembler.flush().unwrap().0);
+            aus.iter().map(|au| au.nalus.len()).collect::<Vec<_>>()
+        };
+
+        // Interlaced Main profile SPS: 4-bit frame_num, pic_order_cnt_type 0 with a 4-bit lsb.
+        let mut w = BitWriter::default();
+        w.bits(0x67, 8).bits(77, 8).bits(0, 8).bits(30, 8).ue(0);
+        w.ue(0).ue(0).ue(0).ue(1).bits(0, 1).ue(44).ue(17);
+        // frame_mbs_only 0, mb_adaptive_frame_field 0, direct_8x8_inference, no cropping or VUI.
+        let sps = w.bits(0b00100, 5).finish();
+        let pps = BitWriter::default()
+            .bits(0x68, 8)
+            .ue(0)
+            .ue(0)
+            .bits(0, 2)
+            .finish();
+        // field: None for a frame, Some(bottom) for a field.
+        let slice = |header: u8, first_mb: u32, frame_num: u32, field: Option<bool>, lsb: u32| {
+            let mut w = BitWriter::default();
+            w.bits(u32::from(header), 8).ue(first_mb).ue(0).ue(0);
+            w.bits(frame_num, 4).bits(u32::from(field.is_some()), 1);
+            if let Some(bottom) = field {
+                w.bits(u32::from(bottom), 1);
+            }
+            if header & 0x1f == 5 {
+                w.ue(0);
+            }
+            w.bits(lsb, 4).finish()
+        };
+        let h264 = [
+            sps,
+            pps,
+            // A two-slice IDR frame.
+            slice(0x65, 0, 0, None, 0),
+            slice(0x65, 400, 0, None, 0),
+            // The SEI opens the next picture, a field pair with two slices in its top field.
+            vec![0x06, 0x05, 0x80],
+            slice(0x41, 0, 1, Some(false), 4),
+            slice(0x41, 200, 1, Some(false), 4),
+            slice(0x41, 0, 1, Some(true), 5),
+            // Non-reference B frames share frame_num and differ only in picture order.
+            slice(0x01, 0, 2, None, 2),
+            slice(0x01, 0, 2, None, 3),
+            // Arbitrary slice order: the picture opens with a slice that is not at MB 0.
+            slice(0x41, 300, 2, None, 8),
+            slice(0x41, 0, 2, None, 8),
+        ];
+        assert_eq!(nal_counts(Codec::H264, &h264), [4, 3, 1, 1, 1, 2]);
+
+        // HEVC cuts at first_slice_segment_in_pic_flag; a suffix SEI stays with its picture and
+        // a prefix SEI goes with the next one.
+        let hevc = [
+            vec![0x40, 0x01, 0x0C],
+            vec![0x02, 0x01, 0x80],
+            vec![0x02, 0x01, 0x20],
+            vec![0x50, 0x01, 0x05],
+            vec![0x4E, 0x01, 0x05],
+            vec![0x02, 0x01, 0x80],
+        ];
+        assert_eq!(nal_counts(Codec::Hevc, &hevc), [4, 2]);
+    }
+
+    fn jpeg_image(width: u16, height: u16, scan: &[u8]) -> Vec<u8> {
+        let mut out = vec![0xFF, 0xD8];
+        // APP1 carrying a thumbnail-like SOI/EOI pair that must not end the image.
+        out.extend_from_slice(&[0xFF, 0xE1, 0x00, 0x08, 0xFF, 0xD8, 0xAA, 0xFF, 0xD9, 0x00]);
+        out.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x0B, 0x08]);
+        out.extend_from_slice(&height.to_be_bytes());
+        out.extend_from_slice(&width.to_be_bytes());
+        out.extend_from_slice(&[0x01, 0x01, 0x11, 0x00]);
+        out.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00]);
+        out.extend_from_slice(scan);
+        out.extend_from_slice(&[0xFF, 0xD9]);
+        out
+    }
+
+    #[test]
+    fn mjpeg_stream_splits_into_whole_images_across_chunks() {
+        let first = jpeg_image(640, 480, &[0x12, 0xFF, 0x00, 0x34, 0xFF, 0xD0, 0x56]);
+        let second = jpeg_image(640, 480, &[0x78, 0x9A]);
+        let mut data = vec![0x00, 0x13];
+        data.extend_from_slice(&first);
+        data.extend_from_slice(&second);
+
+        let mut assembler = StatefulBitstreamAssembler::with_codec(Codec::Mjpeg);
+        let mut emitted = Vec::new();
+        for chunk in data.chunks(5) {
+            let (aus, _) = assembler.push_chunk(chunk, Codec::Mjpeg, None).unwrap();
+            emitted.extend(aus);
+        }
+        let (flush_aus, cache) = assembler.flush().unwrap();
+        emitted.extend(flush_aus);
+
+        assert_eq!(emitted.len(), 2);
+        assert_eq!(emitted[0].nalus, vec![first.clone()]);
+        assert_eq!(emitted[1].nalus, vec![second]);
+        assert!(is_idr(Codec::Mjpeg, &emitted[0].nalus[0]));
+        let sof = cache.required_for_codec(Codec::Mjpeg).unwrap();
+        assert_eq!(jpeg_dimensions(&sof[0]), Some((640, 480)));
+    }
+
+    #[test]
+    fn incomplete_jpeg_waits_for_more_data() {
+        let image = jpeg_image(320, 240, &[0x01, 0x02]);
+        assert_eq!(jpeg_image_len(&image[..image.len() - 1]), None);
+        assert_eq!(jpeg_image_len(&image), Some(image.len()));
+    }
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn sps_cropping_window_matches_sample_streams() {
+        // SPS units from sample-videos/sample-10s.{h264,h265}. H.264 codes 1080p as 68 macroblock
+        // rows and crops 8 lines; HEVC's 8x8 minimum CU fits 1080 exactly.
+        let h264 = hex("67640028acb403c0113f2e022000007d20001d4c11e30654");
+        let hevc = hex(
+            "420101016000000300900000030000030078a003c08010e596566924caf0169c2000007d20000ea601",
+        );
+        let crop = parse_frame_crop(Codec::H264, &h264).unwrap();
+        assert_eq!(crop.to_string(), "1920x1088 -> 1920x1080+0+0");
+        assert!(crop.is_cropped());
+        let crop = parse_frame_crop(Codec::Hevc, &hevc).unwrap();
+        assert_eq!(crop.to_string(), "1920x1080 -> 1920x1080+0+0");
+        assert!(!crop.is_cropped());
+        assert_eq!(parse_frame_crop(Codec::H264, &[0x67, 0x64]), None);
+    }
+
     #[test]
     fn extracts_required_parameter_sets() {
         let data = h264_sample_annexb();
@@ -350,4 +1469,233 @@ mod tests {
         let params = cache.required_for_codec(Codec::H264).unwrap();
         assert_eq!(params.len(), 2);
     }
+
+    #[test]
+    fn access_units_and_parameter_sets_can_be_inspected() {
+        let mut assembler = StatefulBitstreamAssembler::new();
+        let (mut aus, _) = assembler
+            .push_chunk(&h264_sample_annexb(), Codec::H264, None)
+            .unwrap();
+        aus.extend(assembler.flush().unwrap().0);
+        let summary = aus
+            .iter()
+            .map(|au| {
+                (
+                    au.nal_types().collect::<Vec<_>>(),
+                    au.is_keyframe(),
+                    au.contains_sps() && au.contains_pps(),
+                    au.byte_size(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [(vec![7, 8, 5], true, true, 12), (vec![1], false, false, 4)]
+        );
+        assert!(!aus[0].contains_vps());
+
+        let mut cache = ParameterSetCache::default();
+        let (sps, pps) = (h264_sps(3, 40, 22), h264_pps(1, 3));
+        cache.observe(Codec::H264, &sps);
+        cache.observe(Codec::H264, &pps);
+        assert_eq!(cache.get(Codec::H264, 7, 3), Some(sps.as_slice()));
+        assert_eq!(cache.get(Codec::H264, 7, 0), None);
+        assert_eq!(
+            cache.iter_type(Codec::H264, 8).collect::<Vec<_>>(),
+            [(1, pps.as_slice())]
+        );
+        // A slice type is not a parameter set.
+        assert_eq!(cache.iter_type(Codec::H264, 5).count(), 0);
+        assert_eq!(cache.active_sps(), Some(sps.as_slice()));
+    }
+
+    #[test]
+    fn parameter_set_revision_only_counts_changes() {
+        let mut cache = ParameterSetCache::default();
+        cache.observe(Codec::H264, &[0x67, 0x42, 0x00, 0x1E]);
+        cache.observe(Codec::H264, &[0x68, 0xCE, 0x06, 0xE2]);
+        cache.observe(Codec::H264, &[0x67, 0x42, 0x00, 0x1E]);
+        assert_eq!(cache.revision(), 2);
+        cache.observe(Codec::H264, &[0x67, 0x42, 0x00, 0x28]);
+        assert_eq!(cache.revision(), 3);
+    }
+
+    #[derive(Default)]
+    struct BitWriter {
+        bytes: Vec<u8>,
+        bit: usize,
+    }
+
+    impl BitWriter {
+        fn bits(&mut self, value: u32, count: u32) -> &mut Self {
+            for shift in (0..count).rev() {
+                if self.bit.is_multiple_of(8) {
+                    self.bytes.push(0);
+                }
+                let last = self.bytes.len() - 1;
+                self.bytes[last] |= (((value >> shift) & 1) as u8) << (7 - self.bit % 8);
+                self.bit += 1;
+            }
+            self
+        }
+
+        fn ue(&mut self, value: u32) -> &mut Self {
+            let code = value + 1;
+            let len = 32 - code.leading_zeros();
+            self.bits(0, len - 1).bits(code, len)
+        }
+
+        fn finish(&mut self) -> Vec<u8> {
+            self.bits(1, 1);
+            std::mem::take(&mut self.bytes)
+        }
+    }
+
+    fn h264_sps(id: u32, width_mbs: u32, height_mbs: u32) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.bits(0x67, 8).bits(66, 8).bits(0, 8).bits(30, 8).ue(id);
+        // log2_max_frame_num_minus4, pic_order_cnt_type 2, max_num_ref_frames, gaps flag.
+        w.ue(0).ue(2).ue(1).bits(0, 1);
+        w.ue(width_mbs - 1).ue(height_mbs - 1);
+        // frame_mbs_only, direct_8x8_inference, no cropping, no VUI.
+        w.bits(0b1100, 4).finish()
+    }
+
+    fn h264_pps(id: u32, sps_id: u32) -> Vec<u8> {
+        BitWriter::default()
+            .bits(0x68, 8)
+            .ue(id)
+            .ue(sps_id)
+            .finish()
+    }
+
+    fn h264_idr_slice(pps_id: u32) -> Vec<u8> {
+        // first_mb_in_slice 0, slice_type 7 (I).
+        BitWriter::default()
+            .bits(0x65, 8)
+            .ue(0)
+            .ue(7)
+            .ue(pps_id)
+            .finish()
+    }
+
+    #[test]
+    fn parameter_sets_are_kept_per_id_and_follow_the_active_slice() {
+        let mut cache = ParameterSetCache::default();
+        for nal in [
+            h264_sps(0, 40, 22),
+            h264_pps(0, 0),
+            h264_sps(1, 80, 45),
+            h264_pps(1, 1),
+        ] {
+            cache.observe(Codec::H264, &nal);
+        }
+        assert_eq!(cache.required_for_codec(Codec::H264).unwrap().len(), 4);
+        let display = |cache: &ParameterSetCache| {
+            cache
+                .frame_crop(Codec::H264)
+                .map(|crop| crop.display.to_string())
+        };
+
+        // Splicing back and forth selects the SPS through each slice's PPS.
+        cache.observe(Codec::H264, &h264_idr_slice(0));
+        assert_eq!(display(&cache).as_deref(), Some("640x352"));
+        let revision = cache.revision();
+        cache.observe(Codec::H264, &h264_idr_slice(1));
+        assert_eq!(display(&cache).as_deref(), Some("1280x720"));
+        cache.observe(Codec::H264, &h264_idr_slice(0));
+        assert_eq!(display(&cache).as_deref(), Some("640x352"));
+        assert_eq!(cache.revision(), revision + 2);
+        assert_eq!(cache.content_revision(), 4);
+
+        // Repeating a set is not a change; new content under the same id is a new generation.
+        cache.observe(Codec::H264, &h264_sps(0, 40, 22));
+        assert_eq!(cache.sps_generation(0), Some(0));
+        cache.observe(Codec::H264, &h264_sps(0, 120, 68));
+        assert_eq!(cache.sps_generation(0), Some(1));
+        assert_eq!(cache.sps_generation(1), Some(0));
+        assert_eq!(display(&cache).as_deref(), Some("1920x1088"));
+        assert_eq!(cache.content_revision(), 5);
+    }
+
+    // The same access units as NVENC (Annex B) and VideoToolbox (length-prefixed) emit them
+    // get the same tag.
+    #[test]
+    fn keyframe_detection_matches_across_layouts() {
+        let check = |codec: Codec, nals: &[&[u8]], expected: Option<bool>| {
+            let mut annexb = Vec::new();
+            let mut length_prefixed = Vec::new();
+            for nal in nals {
+                annexb.extend_from_slice(&[0, 0, 0, 1]);
+                annexb.extend_from_slice(nal);
+                length_prefixed.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+                length_prefixed.extend_from_slice(nal);
+            }
+            let nested = match codec {
+                Codec::Hevc => EncodedLayout::Hvcc,
+                _ => EncodedLayout::Avcc,
+            };
+            assert_eq!(
+                detect_keyframe(codec, EncodedLayout::AnnexB, &annexb),
+                expected,
+                "{codec:?} {nals:02X?}"
+            );
+            assert_eq!(
+                detect_keyframe(codec, nested, &length_prefixed),
+                expected,
+                "{codec:?} {nals:02X?}"
+            );
+        };
+        check(
+            Codec::H264,
+            &[&[0x09, 0xF0], &[0x67, 0x42], &[0x68, 0xCE], &[0x65, 0x88]],
+            Some(true),
+        );
+        check(Codec::H264, &[&[0x09, 0xF0], &[0x41, 0x9A]], Some(false));
+        check(Codec::H264, &[&[0x06, 0x05], &[0x01, 0x9E]], Some(false));
+        check(Codec::H264, &[&[0x67, 0x42], &[0x68, 0xCE]], None);
+        // IDR_W_RADL and CRA are both random access points.
+        check(
+            Codec::Hevc,
+            &[&[0x40, 0x01], &[0x26, 0x01, 0xAF]],
+            Some(true),
+        );
+        check(
+            Codec::Hevc,
+            &[&[0x46, 0x01], &[0x2A, 0x01, 0xAF]],
+            Some(true),
+        );
+        check(Codec::Hevc, &[&[0x02, 0x01, 0xD0]], Some(false));
+        check(Codec::Hevc, &[&[0x4E, 0x01, 0x05]], None);
+        check(Codec::Mjpeg, &[&[0xFF, 0xD8, 0xFF, 0xD9]], Some(true));
+        assert_eq!(
+            detect_keyframe(Codec::H264, EncodedLayout::Avcc, &[0, 0, 0, 9, 0x65]),
+            None
+        );
+        assert_eq!(
+            detect_keyframe(Codec::H264, EncodedLayout::Opaque, &[0x65]),
+            None
+        );
+    }
+
+    #[cfg(all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    ))]
+    #[test]
+    fn slice_chunks_ending_in_aud_or_end_of_sequence_close_the_picture() {
+        let closing = |codec: Codec, nal: &[u8]| {
+            let mut assembler = StatefulBitstreamAssembler::with_codec(codec);
+            let chunk = [&[0, 0, 0, 1][..], &[0x41, 0x9A], &[0, 0, 0, 1], nal].concat();
+            let nalus = assembler.push_slices(&chunk, codec);
+            nalus.last().is_some_and(|nal| closes_picture(codec, nal))
+        };
+        assert!(closing(Codec::H264, &[0x09, 0xF0]));
+        assert!(closing(Codec::H264, &[0x0A]));
+        assert!(!closing(Codec::H264, &[0x01, 0x9E]));
+        assert!(!closing(Codec::H264, &[0x06, 0x05]));
+        assert!(closing(Codec::Hevc, &[0x46, 0x01, 0x50]));
+        assert!(closing(Codec::Hevc, &[0x48, 0x01]));
+        assert!(!closing(Codec::Hevc, &[0x02, 0x01, 0xAF]));
+    }
 }
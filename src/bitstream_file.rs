@@ -0,0 +1,434 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::ops::Range;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+#[cfg(feature = "bitstream")]
+use crate::DecodedFrame;
+use crate::bitstream::{
+    AccessUnitBoundaries, AccessUnitBoundary, ParameterSetCache, find_jpeg_soi,
+    insert_parameter_sets, is_idr, is_parameter_set, jpeg_image_len, split_nals,
+};
+use crate::{
+    BackendError, BitstreamInput, Codec, FrameRate, Timestamp90k, find_start_codes, nal_type,
+};
+
+pub struct BitstreamFileReader {
+    codec: Codec,
+    map: Mmap,
+    access_units: Vec<Range<usize>>,
+    cursor: usize,
+}
+
+impl BitstreamFileReader {
+    pub fn open(path: impl AsRef<Path>, codec: Codec) -> Result<Self, BackendError> {
+        let map = map_file(path.as_ref())?;
+        let access_units = scan_access_units(codec, &map);
+        Ok(Self {
+            codec,
+            map,
+            access_units,
+            cursor: 0,
+        })
+    }
+
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    pub fn access_unit_count(&self) -> usize {
+        self.access_units.len()
+    }
+
+    pub fn rewind(&mut self) {
+        self.cursor = 0;
+    }
+
+    #[cfg(any(
+        test,
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    pub(crate) fn access_unit_slices(&self) -> impl Iterator<Item = &[u8]> {
+        self.access_units
+            .iter()
+            .map(|range| &self.map[range.clone()])
+    }
+
+    pub fn next_access_unit(&mut self) -> Option<&[u8]> {
+        let range = self.access_units.get(self.cursor)?.clone();
+        self.cursor += 1;
+        Some(&self.map[range])
+    }
+}
+
+impl Iterator for BitstreamFileReader {
+    type Item = BitstreamInput;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk = self.next_access_unit()?.to_vec();
+        Some(BitstreamInput::AnnexBChunk {
+            chunk,
+            pts_90k: None,
+        })
+    }
+}
+
+// Random access table of an elementary stream file, built by one scan: where every access unit
+// is, which ones start with an IDR, and the pts DecodeSession::seek feeds it with. Elementary
+// streams carry no timestamps, so access unit N in file (decode) order is stamped N frame
+// intervals at `fps`; with B-frames those pts follow decode rather than display order, and a
+// seek lands on the picture of the access unit that holds the target pts.
+pub struct BitstreamIndex {
+    codec: Codec,
+    map: Mmap,
+    entries: Vec<BitstreamIndexEntry>,
+    // Parameter sets last seen before each keyframe that does not repeat them, so a seek can
+    // start a fresh decoder there.
+    parameter_sets: HashMap<usize, BTreeMap<u8, Vec<u8>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitstreamIndexEntry {
+    pub offset: usize,
+    pub len: usize,
+    pub pts_90k: Timestamp90k,
+    pub keyframe: bool,
+}
+
+// The frame DecodeSession::seek was asked for. Decoding continues by submitting
+// index.input(next_access_unit) onwards; frames already decoded past the target stay queued
+// on the session.
+#[cfg(feature = "bitstream")]
+#[derive(Debug)]
+pub struct SeekedFrame {
+    pub frame: DecodedFrame,
+    pub next_access_unit: usize,
+}
+
+impl BitstreamIndex {
+    pub fn open(
+        path: impl AsRef<Path>,
+        codec: Codec,
+        fps: impl Into<FrameRate>,
+    ) -> Result<Self, BackendError> {
+        let map = map_file(path.as_ref())?;
+        let (entries, parameter_sets) = index_access_units(codec, &map, fps.into());
+        Ok(Self {
+            codec,
+            map,
+            entries,
+            parameter_sets,
+        })
+    }
+
+    #[cfg(feature = "bitstream")]
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    pub fn entries(&self) -> &[BitstreamIndexEntry] {
+        &self.entries
+    }
+
+    #[cfg(feature = "bitstream")]
+    pub fn access_unit_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Access unit `index` as decoder input, stamped with its indexed pts.
+    pub fn input(&self, index: usize) -> Option<BitstreamInput> {
+        let entry = self.entries.get(index)?;
+        Some(BitstreamInput::AnnexBChunk {
+            chunk: self.map[entry.offset..entry.offset + entry.len].to_vec(),
+            pts_90k: Some(entry.pts_90k),
+        })
+    }
+
+    // Where a seek to `target` starts and stops: the last keyframe at or before the access
+    // unit holding `target`, i.e. the last one whose pts is not after it.
+    pub(crate) fn seek_range(&self, target: Timestamp90k) -> Result<(usize, usize), BackendError> {
+        let end = self
+            .entries
+            .partition_point(|entry| entry.pts_90k <= target);
+        let Some(target_index) = end.checked_sub(1) else {
+            return Err(BackendError::InvalidInput(format!(
+                "seek target {} is before the first access unit",
+                target.0
+            )));
+        };
+        let keyframe = self.entries[..=target_index]
+            .iter()
+            .rposition(|entry| entry.keyframe)
+            .ok_or_else(|| {
+                BackendError::InvalidInput(format!(
+                    "no keyframe at or before seek target {}",
+                    target.0
+                ))
+            })?;
+        Ok((keyframe, target_index))
+    }
+
+    // input() for the access unit a seek starts decoding from, with the parameter sets it
+    // depends on put in front when it does not carry them itself.
+    pub(crate) fn seek_input(&self, index: usize) -> Option<BitstreamInput> {
+        let input = self.input(index)?;
+        let Some(parameter_sets) = self.parameter_sets.get(&index) else {
+            return Some(input);
+        };
+        let BitstreamInput::AnnexBChunk { chunk, pts_90k } = input else {
+            return Some(input);
+        };
+        let nals = split_nals(&chunk);
+        Some(BitstreamInput::AnnexBChunk {
+            chunk: insert_parameter_sets(self.codec, &chunk, &nals, parameter_sets),
+            pts_90k,
+        })
+    }
+}
+
+type IndexedAccessUnits = (
+    Vec<BitstreamIndexEntry>,
+    HashMap<usize, BTreeMap<u8, Vec<u8>>>,
+);
+
+fn index_access_units(codec: Codec, data: &[u8], fps: FrameRate) -> IndexedAccessUnits {
+    let mut entries = Vec::new();
+    let mut parameter_sets = HashMap::new();
+    let mut latest = BTreeMap::new();
+    for (index, range) in scan_access_units(codec, data).into_iter().enumerate() {
+        let access_unit = &data[range.clone()];
+        let keyframe = if codec == Codec::Mjpeg {
+            true
+        } else {
+            let nals = split_nals(access_unit);
+            let mut in_band = false;
+            for &(_, nal) in &nals {
+                if is_parameter_set(codec, nal)
+                    && let Some(nal_type) = nal_type(codec, nal)
+                {
+                    latest.insert(nal_type, nal.to_vec());
+                    in_band = true;
+                }
+            }
+            let keyframe = nals.iter().any(|&(_, nal)| is_idr(codec, nal));
+            if keyframe && !in_band && !latest.is_empty() {
+                parameter_sets.insert(index, latest.clone());
+            }
+            keyframe
+        };
+        entries.push(BitstreamIndexEntry {
+            offset: range.start,
+            len: range.len(),
+            pts_90k: Timestamp90k(fps.pts_90k(index as i64)),
+            keyframe,
+        });
+    }
+    (entries, parameter_sets)
+}
+
+// Cuts where StatefulBitstreamAssembler does (AccessUnitBoundaries), but over byte ranges so
+// the mapped file is never copied during the scan. Unlike the assembler's output, the ranges
+// keep their AUDs.
+fn scan_access_units(codec: Codec, data: &[u8]) -> Vec<Range<usize>> {
+    if codec == Codec::Mjpeg {
+        return scan_jpeg_images(data);
+    }
+    let start_codes = find_start_codes(data);
+    let mut out = Vec::new();
+    let mut boundaries = AccessUnitBoundaries::default();
+    let mut parameter_sets = ParameterSetCache::default();
+    let mut au_start = start_codes.first().map_or(0, |&(start, _)| start);
+
+    for (index, &(start, start_len)) in start_codes.iter().enumerate() {
+        let end = start_codes
+            .get(index + 1)
+            .map_or(data.len(), |&(next, _)| next);
+        let nal = &data[(start + start_len).min(end)..end];
+        if nal.is_empty() {
+            continue;
+        }
+
+        parameter_sets.observe(codec, nal);
+        match boundaries.push(codec, nal, start, &parameter_sets) {
+            Some(AccessUnitBoundary::End(end)) => {
+                out.push(au_start..end);
+                au_start = end;
+            }
+            Some(AccessUnitBoundary::Discard(end)) => au_start = end,
+            None => {}
+        }
+    }
+
+    if boundaries.finish() {
+        out.push(au_start..data.len());
+    }
+    out
+}
+
+fn map_file(path: &Path) -> Result<Mmap, BackendError> {
+    let file = File::open(path).map_err(|err| {
+        BackendError::InvalidInput(format!("failed to open {}: {err}", path.display()))
+    })?;
+    // The map is read-only and private to its owner; callers must not truncate the file while
+    // it is open.
+    unsafe { Mmap::map(&file) }.map_err(|err| {
+        BackendError::InvalidInput(format!("failed to map {}: {err}", path.display()))
+    })
+}
+
+fn scan_jpeg_images(data: &[u8]) -> Vec<Range<usize>> {
+    let mut out = Vec::new();
+    let mut pos = 0usize;
+    while let Some(start) = find_jpeg_soi(&data[pos..]) {
+        let start = pos + start;
+        let Some(len) = jpeg_image_len(&data[start..]) else {
+            break;
+        };
+        out.push(start..start + len);
+        pos = start + len;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_splits_on_aud_and_keeps_start_codes() {
+        let mut data = Vec::new();
+        for nal in [
+            &[0x09, 0xF0][..],
+            &[0x67, 0x42, 0x00, 0x1E],
+            &[0x68, 0xCE, 0x06, 0xE2],
+            &[0x65, 0x88, 0x84, 0x21],
+            &[0x09, 0xF0],
+            &[0x41, 0x9A, 0x22, 0x11],
+        ] {
+            data.extend_from_slice(&[0, 0, 0, 1]);
+            data.extend_from_slice(nal);
+        }
+
+        let ranges = scan_access_units(Codec::H264, &data);
+        assert_eq!(ranges, vec![0..30, 30..44]);
+        assert_eq!(&data[ranges[1].clone()][..6], &[0, 0, 0, 1, 0x09, 0xF0]);
+    }
+
+    #[test]
+    fn scan_splits_on_vcl_without_aud() {
+        let mut data = Vec::new();
+        for nal in [
+            &[0x67, 0x42][..],
+            &[0x65, 0x88],
+            &[0x41, 0x9A],
+            &[0x41, 0x9B],
+        ] {
+            data.extend_from_slice(&[0, 0, 1]);
+            data.extend_from_slice(nal);
+        }
+
+        let ranges = scan_access_units(Codec::H264, &data);
+        assert_eq!(ranges, vec![0..10, 10..15, 15..20]);
+    }
+
+    #[test]
+    fn scan_keeps_the_slices_of_one_picture_together() {
+        let mut data = Vec::new();
+        for nal in [
+            // VPS, SPS, PPS.
+            &[0x40, 0x01, 0x0C][..],
+            &[0x42, 0x01, 0x01],
+            &[0x44, 0x01, 0xC1],
+            // IDR with two slice segments, then a TRAIL_R picture with two.
+            &[0x26, 0x01, 0xAF],
+            &[0x26, 0x01, 0x20],
+            &[0x02, 0x01, 0xD0],
+            &[0x02, 0x01, 0x40],
+        ] {
+            data.extend_from_slice(&[0, 0, 1]);
+            data.extend_from_slice(nal);
+        }
+
+        let ranges = scan_access_units(Codec::Hevc, &data);
+        assert_eq!(ranges, vec![0..30, 30..42]);
+    }
+
+    #[test]
+    fn index_plans_seeks_from_the_preceding_idr() {
+        let mut data = Vec::new();
+        for nal in [
+            &[0x09, 0xF0][..],
+            &[0x67, 0x42],
+            &[0x68, 0xCE],
+            &[0x65, 0x88],
+            &[0x09, 0xF0],
+            &[0x41, 0x9A],
+            &[0x09, 0xF0],
+            &[0x65, 0x88],
+            &[0x09, 0xF0],
+            &[0x41, 0x9B],
+        ] {
+            data.extend_from_slice(&[0, 0, 0, 1]);
+            data.extend_from_slice(nal);
+        }
+        let path = std::env::temp_dir().join(format!("video-hw-index-{}.h264", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+        let index = BitstreamIndex::open(&path, Codec::H264, 30).expect("index");
+        std::fs::remove_file(&path).unwrap();
+
+        let entries = index.entries();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| (entry.pts_90k.0, entry.keyframe))
+                .collect::<Vec<_>>(),
+            [(0, true), (3000, false), (6000, true), (9000, false)]
+        );
+        assert_eq!(index.seek_range(Timestamp90k(5999)).unwrap(), (0, 1));
+        assert_eq!(index.seek_range(Timestamp90k(9000)).unwrap(), (2, 3));
+        assert!(index.seek_range(Timestamp90k(-1)).is_err());
+
+        // The second IDR repeats no parameter sets; a seek starting there gets the first ones.
+        let Some(BitstreamInput::AnnexBChunk { chunk, pts_90k }) = index.seek_input(2) else {
+            panic!("annex b input");
+        };
+        assert_eq!(pts_90k, Some(Timestamp90k(6000)));
+        assert_eq!(
+            chunk,
+            [
+                0, 0, 0, 1, 0x09, 0xF0, 0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x68, 0xCE, 0, 0, 0, 1,
+                0x65, 0x88
+            ]
+        );
+        assert!(matches!(
+            index.input(2),
+            Some(BitstreamInput::AnnexBChunk { chunk, .. }) if chunk.len() == 12
+        ));
+    }
+
+    #[test]
+    fn reader_yields_one_chunk_per_access_unit() {
+        let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("sample-videos")
+            .join("sample-10s.h264");
+        let mut reader = BitstreamFileReader::open(&path, Codec::H264).expect("open sample");
+        assert_eq!(reader.codec(), Codec::H264);
+        let count = reader.access_unit_count();
+        assert_eq!(count, 303);
+
+        let first = reader
+            .next_access_unit()
+            .expect("first access unit")
+            .to_vec();
+        assert!(first.starts_with(&[0, 0, 1]) || first.starts_with(&[0, 0, 0, 1]));
+        assert_eq!(reader.by_ref().count(), count - 1);
+        reader.rewind();
+        assert_eq!(reader.next_access_unit(), Some(first.as_slice()));
+    }
+}
@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use crate::{BackendError, Dimensions, DirtyRect, EncodeFrame, RawFrameBuffer, Timestamp90k};
+
+pub trait CaptureSource: Send {
+    fn dims(&self) -> Dimensions;
+
+    fn next_frame(&mut self, timeout: Duration) -> Result<Option<CapturedFrame>, BackendError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct CapturedFrame {
+    pub dims: Dimensions,
+    pub pts_90k: Option<Timestamp90k>,
+    pub buffer: RawFrameBuffer,
+    pub dirty_rects: Option<Vec<DirtyRect>>,
+}
+
+impl CapturedFrame {
+    pub fn from_bgra_strided(
+        dims: Dimensions,
+        stride: usize,
+        data: &[u8],
+        pts_90k: Option<Timestamp90k>,
+        dirty_rects: Option<Vec<DirtyRect>>,
+    ) -> Result<Self, BackendError> {
+        let packed = pack_bgra_rows(
+            dims.width.get() as usize,
+            dims.height.get() as usize,
+            stride,
+            data,
+        )?;
+        Ok(Self {
+            dims,
+            pts_90k,
+            buffer: RawFrameBuffer::Argb8888(packed),
+            dirty_rects,
+        })
+    }
+
+    pub fn into_encode_frame(self) -> EncodeFrame {
+        EncodeFrame {
+            dims: self.dims,
+            pts_90k: self.pts_90k,
+            buffer: self.buffer,
+            force_keyframe: false,
+            dirty_rects: self.dirty_rects,
+            repeat_count: 0,
+        }
+    }
+}
+
+// ScreenCaptureKit (kCVPixelFormatType_32BGRA) and DXGI duplication
+// (DXGI_FORMAT_B8G8R8A8_UNORM) both hand out BGRA rows padded to a stride,
+// which is the byte order EncodeSession expects for Argb8888.
+pub fn pack_bgra_rows(
+    width: usize,
+    height: usize,
+    stride: usize,
+    data: &[u8],
+) -> Result<Vec<u8>, BackendError> {
+    let row_bytes = width * 4;
+    if stride < row_bytes {
+        return Err(BackendError::InvalidInput(format!(
+            "capture stride {stride} is smaller than row size {row_bytes}"
+        )));
+    }
+    let required = stride * height.saturating_sub(1) + row_bytes;
+    if data.len() < required {
+        return Err(BackendError::InvalidInput(format!(
+            "capture buffer too small: got {}, expected at least {required}",
+            data.len()
+        )));
+    }
+    if stride == row_bytes {
+        return Ok(data[..row_bytes * height].to_vec());
+    }
+
+    let mut packed = Vec::with_capacity(row_bytes * height);
+    for row in data.chunks(stride).take(height) {
+        packed.extend_from_slice(&row[..row_bytes]);
+    }
+    Ok(packed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_bgra_rows_strips_stride_padding() {
+        let data = [1, 2, 3, 4, 9, 9, 5, 6, 7, 8, 9, 9];
+        let packed = pack_bgra_rows(1, 2, 6, &data).expect("pack");
+        assert_eq!(packed, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn pack_bgra_rows_rejects_short_buffer() {
+        assert!(pack_bgra_rows(2, 2, 8, &[0; 12]).is_err());
+        assert!(pack_bgra_rows(2, 1, 4, &[0; 8]).is_err());
+    }
+}
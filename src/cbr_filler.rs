@@ -0,0 +1,160 @@
+use crate::{BackendError, Codec, EncodedChunk, EncodedLayout, EncoderConfig};
+
+// Start code or length prefix in front of every filler NAL.
+const NAL_PREFIX_LEN: usize = 4;
+// rbsp_trailing_bits after the 0xFF payload.
+const TRAILING_BYTE: u8 = 0x80;
+
+// Tops every chunk up with a filler NAL (H.264 type 12, HEVC FD_NUT) so the stream spends
+// exactly target_bitrate_bps, for transports such as satellite and some broadcast links that
+// need a constant bitrate. Both backends rate-control in VBR mode here, so filler is
+// synthesized after encoding rather than asking NVENC for it. Credit is kept as an exact
+// fraction of a bit per frame; a frame larger than its budget borrows from the following
+// ones, which then get no filler until the debt is repaid.
+#[derive(Debug)]
+pub(crate) struct CbrPadder {
+    codec: Codec,
+    // Each frame earns bitrate * fps.den and each byte costs 8 * fps.num, so credit never
+    // needs rounding.
+    earned_per_frame: i128,
+    cost_per_byte: i128,
+    credit: i128,
+}
+
+impl CbrPadder {
+    // None when the config does not ask for filler or cannot use it; see check_config.
+    pub(crate) fn for_config(config: &EncoderConfig) -> Option<Self> {
+        check_config(config).ok()?;
+        let bitrate = config.target_bitrate_bps.filter(|_| config.cbr_filler)?;
+        let fps = config.fps.normalized();
+        Some(Self {
+            codec: config.codec,
+            earned_per_frame: i128::from(bitrate) * i128::from(fps.den),
+            cost_per_byte: 8 * i128::from(fps.num),
+            credit: 0,
+        })
+    }
+
+    pub(crate) fn pad(&mut self, mut chunk: EncodedChunk) -> Result<EncodedChunk, BackendError> {
+        let header: &[u8] = match self.codec {
+            Codec::H264 => &[0x0C],
+            Codec::Hevc => &[0x4C, 0x01],
+            Codec::Mjpeg => return Ok(chunk),
+        };
+        if chunk.layout == EncodedLayout::Opaque {
+            return Err(BackendError::UnsupportedConfig(
+                "cbr_filler cannot append filler NAL units to opaque chunks".to_string(),
+            ));
+        }
+        let bytes = i128::try_from(chunk.data.len()).unwrap_or(i128::MAX);
+        self.credit += self.earned_per_frame - bytes * self.cost_per_byte;
+        let budget = usize::try_from(self.credit / self.cost_per_byte).unwrap_or(0);
+        let overhead = NAL_PREFIX_LEN + header.len() + 1;
+        if budget < overhead {
+            return Ok(chunk);
+        }
+        let nal_len = budget - NAL_PREFIX_LEN;
+        let prefix = match chunk.layout {
+            EncodedLayout::AnnexB => [0, 0, 0, 1],
+            _ => u32::try_from(nal_len)
+                .map_err(|_| {
+                    BackendError::InvalidInput(format!(
+                        "filler NAL of {nal_len} bytes is too large"
+                    ))
+                })?
+                .to_be_bytes(),
+        };
+        let mut data = Vec::with_capacity(chunk.data.len() + budget);
+        data.extend_from_slice(&chunk.data);
+        data.extend_from_slice(&prefix);
+        data.extend_from_slice(header);
+        data.resize(data.len() + nal_len - header.len() - 1, 0xFF);
+        data.push(TRAILING_BYTE);
+        self.credit -= i128::try_from(budget).unwrap_or(i128::MAX) * self.cost_per_byte;
+        chunk.data = data.into();
+        chunk.filler_bytes += budget;
+        Ok(chunk)
+    }
+}
+
+// Checked on every submit so a misconfigured session fails before any frame is encoded.
+pub(crate) fn check_config(config: &EncoderConfig) -> Result<(), BackendError> {
+    if !config.cbr_filler {
+        return Ok(());
+    }
+    if config.codec == Codec::Mjpeg {
+        return Err(BackendError::UnsupportedConfig(
+            "cbr_filler needs H.264 or HEVC; MJPEG has no filler data".to_string(),
+        ));
+    }
+    if config.target_bitrate_bps.is_none_or(|bitrate| bitrate == 0) {
+        return Err(BackendError::InvalidInput(
+            "cbr_filler needs a non-zero target_bitrate_bps".to_string(),
+        ));
+    }
+    if !config.fps.is_known() {
+        return Err(BackendError::InvalidInput(
+            "cbr_filler needs a known frame rate".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FrameRate;
+
+    fn chunk(layout: EncodedLayout, len: usize) -> EncodedChunk {
+        let prefix = match layout {
+            EncodedLayout::AnnexB => [0, 0, 0, 1],
+            _ => (len as u32 - 4).to_be_bytes(),
+        };
+        EncodedChunk {
+            codec: Codec::H264,
+            layout,
+            data: [&prefix[..], &[0x41], &vec![0x9A; len - 5]].concat().into(),
+            pts_90k: None,
+            is_keyframe: false,
+            filler_bytes: 0,
+            dts_90k: None,
+            display_index: None,
+        }
+    }
+
+    #[test]
+    fn filler_holds_the_exact_bitrate() {
+        let mut config = EncoderConfig::new(Codec::H264, FrameRate::NTSC_29_97, true);
+        config.cbr_filler = true;
+        assert!(matches!(
+            check_config(&config),
+            Err(BackendError::InvalidInput(_))
+        ));
+        assert!(CbrPadder::for_config(&config).is_none());
+
+        // 1000 frames at 30000/1001 fps last 1001/30 s, worth 1_000_000 * 1001 / 30 bytes at
+        // 8 Mbps; only the fraction of a byte still owed at the end is missing.
+        config.target_bitrate_bps = Some(8_000_000);
+        let mut padder = CbrPadder::for_config(&config).unwrap();
+        let mut total = 0;
+        let mut filler = 0;
+        for index in 0..1000 {
+            // A large keyframe every 30 frames overdraws the budget for a few frames.
+            let len = if index % 30 == 0 { 90_000 } else { 20_000 };
+            let padded = padder.pad(chunk(EncodedLayout::AnnexB, len)).unwrap();
+            assert_eq!(padded.data.len(), len + padded.filler_bytes);
+            total += padded.data.len();
+            filler += padded.filler_bytes;
+        }
+        assert_eq!(total, 1_000_000 * 1001 / 30);
+        assert!(filler > 0);
+
+        let mut padder = CbrPadder::for_config(&config).unwrap();
+        let padded = padder.pad(chunk(EncodedLayout::Avcc, 100)).unwrap();
+        let nals = padded.nal_units().unwrap();
+        let filler_nal = nals.last().unwrap();
+        assert_eq!(filler_nal.nal_type, 12);
+        assert_eq!(padded.filler_bytes, filler_nal.data.len() + NAL_PREFIX_LEN);
+        assert_eq!(filler_nal.data.last(), Some(&TRAILING_BYTE));
+    }
+}
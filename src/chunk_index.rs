@@ -0,0 +1,155 @@
+use std::fmt::Write as _;
+
+use crate::{BackendError, EncodedChunk, Timestamp90k};
+
+// Where a chunk landed in the elementary stream written by concatenating every emitted chunk
+// in output order, as the writer sinks do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkIndexEntry {
+    pub pts_90k: Option<Timestamp90k>,
+    pub offset: u64,
+    pub len: u64,
+    pub is_keyframe: bool,
+}
+
+// Seek table for archived streams, built while encoding so the file never has to be
+// re-scanned. Offsets always count every chunk; `keyframes_only` just keeps the table small.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkIndex {
+    entries: Vec<ChunkIndexEntry>,
+    keyframes_only: bool,
+    next_offset: u64,
+}
+
+impl ChunkIndex {
+    pub fn new(keyframes_only: bool) -> Self {
+        Self {
+            keyframes_only,
+            ..Self::default()
+        }
+    }
+
+    pub fn record(&mut self, chunk: &EncodedChunk) {
+        let len = chunk.data.len() as u64;
+        if chunk.is_keyframe || !self.keyframes_only {
+            self.entries.push(ChunkIndexEntry {
+                pts_90k: chunk.pts_90k,
+                offset: self.next_offset,
+                len,
+                is_keyframe: chunk.is_keyframe,
+            });
+        }
+        self.next_offset += len;
+    }
+
+    pub fn entries(&self) -> &[ChunkIndexEntry] {
+        &self.entries
+    }
+
+    // Bytes covered so far, i.e. the size of the indexed stream.
+    pub fn stream_len(&self) -> u64 {
+        self.next_offset
+    }
+
+    // Where to start decoding to show `pts_90k`: the last keyframe presented at or before it.
+    pub fn seek_keyframe(&self, pts_90k: Timestamp90k) -> Option<&ChunkIndexEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.is_keyframe)
+            .filter(|entry| entry.pts_90k.is_some_and(|pts| pts.0 <= pts_90k.0))
+            .max_by_key(|entry| entry.pts_90k.map(|pts| pts.0))
+    }
+
+    // Sidecar format: one `pts<TAB>offset<TAB>len<TAB>key` line per entry, "-" for no pts.
+    pub fn to_tsv(&self) -> String {
+        let mut out = String::new();
+        for entry in &self.entries {
+            let pts = entry
+                .pts_90k
+                .map_or_else(|| "-".to_string(), |pts| pts.0.to_string());
+            let _ = writeln!(
+                out,
+                "{pts}\t{}\t{}\t{}",
+                entry.offset,
+                entry.len,
+                u8::from(entry.is_keyframe)
+            );
+        }
+        out
+    }
+
+    // stream_len of a parsed index only reaches the end of its last entry.
+    pub fn from_tsv(text: &str) -> Result<Self, BackendError> {
+        let mut index = Self::default();
+        for (line_no, line) in text.lines().enumerate() {
+            let invalid = || {
+                BackendError::InvalidInput(format!("chunk index line {}: {line:?}", line_no + 1))
+            };
+            let fields = line.split('\t').collect::<Vec<_>>();
+            let [pts, offset, len, key] = fields[..] else {
+                return Err(invalid());
+            };
+            let entry = ChunkIndexEntry {
+                pts_90k: match pts {
+                    "-" => None,
+                    pts => Some(Timestamp90k(pts.parse().map_err(|_| invalid())?)),
+                },
+                offset: offset.parse().map_err(|_| invalid())?,
+                len: len.parse().map_err(|_| invalid())?,
+                is_keyframe: match key {
+                    "0" => false,
+                    "1" => true,
+                    _ => return Err(invalid()),
+                },
+            };
+            index.next_offset = index.next_offset.max(entry.offset + entry.len);
+            index.entries.push(entry);
+        }
+        index.keyframes_only = index.entries.iter().all(|entry| entry.is_keyframe);
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Codec, EncodedLayout};
+
+    #[test]
+    fn keyframe_index_tracks_offsets_of_every_chunk() {
+        let mut index = ChunkIndex::new(true);
+        for (pts, len, is_keyframe) in [(0, 900, true), (3000, 100, false), (6000, 120, true)] {
+            index.record(&EncodedChunk {
+                codec: Codec::H264,
+                layout: EncodedLayout::AnnexB,
+                data: vec![0; len].into(),
+                pts_90k: Some(Timestamp90k(pts)),
+                is_keyframe,
+                filler_bytes: 0,
+                dts_90k: None,
+                display_index: None,
+            });
+        }
+        let offsets = index
+            .entries()
+            .iter()
+            .map(|entry| entry.offset)
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, [0, 1000]);
+        assert_eq!(index.stream_len(), 1120);
+        assert_eq!(index.seek_keyframe(Timestamp90k(5999)).unwrap().offset, 0);
+        assert_eq!(
+            index.seek_keyframe(Timestamp90k(9000)).unwrap().offset,
+            1000
+        );
+        assert!(index.seek_keyframe(Timestamp90k(-1)).is_none());
+
+        let tsv = index.to_tsv();
+        assert_eq!(tsv, "0\t0\t900\t1\n6000\t1000\t120\t1\n");
+        assert_eq!(
+            ChunkIndex::from_tsv(&tsv).unwrap().entries(),
+            index.entries()
+        );
+        assert!(ChunkIndex::from_tsv("0\t0\t900\n").is_err());
+    }
+}
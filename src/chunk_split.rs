@@ -0,0 +1,168 @@
+use std::time::Duration;
+
+use crate::{EncodedChunk, Timestamp90k};
+
+#[derive(Debug, Clone)]
+pub struct ChunkGroup {
+    pub chunks: Vec<EncodedChunk>,
+    pub start_pts_90k: Option<Timestamp90k>,
+    pub end_pts_90k: Option<Timestamp90k>,
+}
+
+impl ChunkGroup {
+    fn from_chunks(chunks: Vec<EncodedChunk>) -> Self {
+        let mut start: Option<i64> = None;
+        let mut end: Option<i64> = None;
+        for pts in chunks.iter().filter_map(|chunk| chunk.pts_90k) {
+            start = Some(start.map_or(pts.0, |v| v.min(pts.0)));
+            end = Some(end.map_or(pts.0, |v| v.max(pts.0)));
+        }
+        Self {
+            chunks,
+            start_pts_90k: start.map(Timestamp90k),
+            end_pts_90k: end.map(Timestamp90k),
+        }
+    }
+
+    // Chunks emitted before the first keyframe cannot be decoded on their own.
+    pub fn starts_with_keyframe(&self) -> bool {
+        self.chunks.first().is_some_and(|chunk| chunk.is_keyframe)
+    }
+
+    pub fn duration_90k(&self) -> Option<i64> {
+        Some(self.end_pts_90k?.0 - self.start_pts_90k?.0)
+    }
+
+    pub fn byte_len(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.data.len()).sum()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ChunkStreamSplitter {
+    pending: Vec<EncodedChunk>,
+    min_duration_90k: Option<i64>,
+}
+
+impl ChunkStreamSplitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Groups keep accumulating whole GOPs until they span at least `duration`, so every cut
+    // still lands on a keyframe. Streams without pts fall back to one group per GOP.
+    pub fn with_min_duration(duration: Duration) -> Self {
+        let duration_90k = duration.as_nanos().saturating_mul(9) / 100_000;
+        Self {
+            pending: Vec::new(),
+            min_duration_90k: Some(i64::try_from(duration_90k).unwrap_or(i64::MAX)),
+        }
+    }
+
+    pub fn push(&mut self, chunk: EncodedChunk) -> Option<ChunkGroup> {
+        let completed = if chunk.is_keyframe && self.should_cut_before(&chunk) {
+            Some(ChunkGroup::from_chunks(std::mem::take(&mut self.pending)))
+        } else {
+            None
+        };
+        self.pending.push(chunk);
+        completed
+    }
+
+    pub fn finish(&mut self) -> Option<ChunkGroup> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        Some(ChunkGroup::from_chunks(std::mem::take(&mut self.pending)))
+    }
+
+    pub fn split(&mut self, chunks: impl IntoIterator<Item = EncodedChunk>) -> Vec<ChunkGroup> {
+        let mut groups = chunks
+            .into_iter()
+            .filter_map(|chunk| self.push(chunk))
+            .collect::<Vec<_>>();
+        groups.extend(self.finish());
+        groups
+    }
+
+    fn should_cut_before(&self, keyframe: &EncodedChunk) -> bool {
+        let Some(first) = self.pending.first() else {
+            return false;
+        };
+        if !first.is_keyframe {
+            return true;
+        }
+        let Some(min_duration_90k) = self.min_duration_90k else {
+            return true;
+        };
+        let start = self
+            .pending
+            .iter()
+            .filter_map(|chunk| chunk.pts_90k)
+            .map(|pts| pts.0)
+            .min();
+        match (start, keyframe.pts_90k) {
+            (Some(start), Some(pts)) => pts.0.saturating_sub(start) >= min_duration_90k,
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Codec, EncodedLayout};
+
+    fn chunk(pts: i64, is_keyframe: bool) -> EncodedChunk {
+        EncodedChunk {
+            codec: Codec::H264,
+            layout: EncodedLayout::AnnexB,
+            data: vec![0, 0, 0, 1, if is_keyframe { 0x65 } else { 0x41 }].into(),
+            pts_90k: Some(Timestamp90k(pts)),
+            is_keyframe,
+            filler_bytes: 0,
+            dts_90k: None,
+            display_index: None,
+        }
+    }
+
+    fn stream(gop: usize, frames: usize) -> Vec<EncodedChunk> {
+        (0..frames)
+            .map(|i| chunk(i as i64 * 3000, i % gop == 0))
+            .collect()
+    }
+
+    #[test]
+    fn splits_at_every_keyframe_with_pts_ranges() {
+        let groups = ChunkStreamSplitter::new().split(stream(30, 75));
+        assert_eq!(
+            groups.iter().map(|g| g.chunks.len()).collect::<Vec<_>>(),
+            vec![30, 30, 15]
+        );
+        assert!(groups.iter().all(ChunkGroup::starts_with_keyframe));
+        assert_eq!(groups[1].start_pts_90k, Some(Timestamp90k(90_000)));
+        assert_eq!(groups[1].end_pts_90k, Some(Timestamp90k(177_000)));
+        assert_eq!(groups[2].duration_90k(), Some(42_000));
+    }
+
+    #[test]
+    fn leading_non_keyframes_form_their_own_group() {
+        let mut chunks = vec![chunk(-6000, false), chunk(-3000, false)];
+        chunks.extend(stream(30, 30));
+        let groups = ChunkStreamSplitter::new().split(chunks);
+        assert_eq!(groups.len(), 2);
+        assert!(!groups[0].starts_with_keyframe());
+        assert!(groups[1].starts_with_keyframe());
+    }
+
+    #[test]
+    fn min_duration_merges_gops_until_target_is_reached() {
+        // 1 s GOPs at 30 fps, cut every ~2.5 s => groups of three GOPs.
+        let groups = ChunkStreamSplitter::with_min_duration(Duration::from_millis(2500))
+            .split(stream(30, 240));
+        assert_eq!(
+            groups.iter().map(|g| g.chunks.len()).collect::<Vec<_>>(),
+            vec![90, 90, 60]
+        );
+    }
+}
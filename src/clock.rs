@@ -0,0 +1,87 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Time source for sessions. Components that already take `now` as an argument (StreamClock,
+// JitterBuffer) stay clock-agnostic; sessions read the clock they were given, so tests can
+// drive busy time and timeouts without sleeping.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// Stands still until advanced. Clones share the same time, so a test keeps one handle and
+// gives another to the session.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new(Instant::now())
+    }
+}
+
+impl ManualClock {
+    pub fn new(start: Instant) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(start)),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let mut now = self
+            .now
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        *now += by;
+    }
+
+    // Moving backwards is ignored: Instant is monotonic and callers rely on that.
+    pub fn set(&self, instant: Instant) {
+        let mut now = self
+            .now
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        *now = (*now).max(instant);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self
+            .now
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+pub(crate) fn system_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_is_shared_and_monotonic() {
+        let start = Instant::now();
+        let clock = ManualClock::new(start);
+        let session_view: Arc<dyn Clock> = Arc::new(clock.clone());
+        clock.advance(Duration::from_millis(40));
+        assert_eq!(session_view.now(), start + Duration::from_millis(40));
+        clock.set(start);
+        assert_eq!(session_view.now(), start + Duration::from_millis(40));
+        clock.set(start + Duration::from_secs(1));
+        assert_eq!(clock.now() - start, Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{CapabilityReport, Codec, Dimensions, FrameRate};
+
+// Above this many luma samples per frame HEVC wins by enough to justify it wherever it is
+// hardware encoded.
+const HEVC_PREFERRED_ABOVE_PIXELS: u64 = 1920 * 1080;
+// Below this many bits per pixel per frame H.264 starts to block up at sizes where HEVC is
+// still clean (roughly 2 Mbps for 1080p30).
+const HEVC_PREFERRED_BELOW_BITS_PER_PIXEL: f64 = 0.035;
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+// Picks the codec to encode with on `backend`, so every platform makes the same decision for
+// the same content. Asks the backend for its capabilities and defers to choose_codec_from.
+pub fn choose_codec(
+    backend: crate::Backend,
+    dims: Dimensions,
+    fps: impl Into<FrameRate>,
+    target_bitrate_bps: Option<u64>,
+) -> Codec {
+    let fps = fps.into();
+    let session =
+        crate::EncodeSession::new(backend, crate::EncoderConfig::new(Codec::H264, fps, true));
+    let reports = [Codec::Hevc, Codec::H264]
+        .into_iter()
+        .filter_map(|codec| session.query_capability(codec).ok())
+        .collect::<Vec<_>>();
+    choose_codec_from(&reports, dims, fps, target_bitrate_bps)
+}
+
+// Hardware encode beats software, then HEVC is chosen above 1080p or at bitrates too low for
+// H.264 at this size and rate. Without usable reports H.264 is the portable default.
+pub fn choose_codec_from(
+    reports: &[CapabilityReport],
+    dims: Dimensions,
+    fps: impl Into<FrameRate>,
+    target_bitrate_bps: Option<u64>,
+) -> Codec {
+    let fps = fps.into();
+    let rank = |codec: Codec| {
+        reports
+            .iter()
+            .filter(|report| report.codec == codec && report.encode_supported)
+            .map(|report| 1 + u8::from(report.hardware_acceleration))
+            .max()
+            .unwrap_or(0)
+    };
+    let (hevc, h264) = (rank(Codec::Hevc), rank(Codec::H264));
+    if hevc != h264 {
+        return if hevc > h264 {
+            Codec::Hevc
+        } else {
+            Codec::H264
+        };
+    }
+    if hevc == 0 {
+        return Codec::H264;
+    }
+    let pixels = u64::from(dims.width.get()) * u64::from(dims.height.get());
+    let starved = target_bitrate_bps.is_some_and(|bitrate| {
+        let bits_per_pixel = bitrate as f64 / (pixels as f64 * fps.as_f64());
+        bits_per_pixel < HEVC_PREFERRED_BELOW_BITS_PER_PIXEL
+    });
+    if pixels > HEVC_PREFERRED_ABOVE_PIXELS || starved {
+        Codec::Hevc
+    } else {
+        Codec::H264
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+
+    fn dims(width: u32, height: u32) -> Dimensions {
+        Dimensions {
+            width: NonZeroU32::new(width).unwrap(),
+            height: NonZeroU32::new(height).unwrap(),
+        }
+    }
+
+    fn report(codec: Codec, encode_supported: bool, hardware: bool) -> CapabilityReport {
+        CapabilityReport {
+            codec,
+            decode_supported: true,
+            encode_supported,
+            hardware_acceleration: hardware,
+        }
+    }
+
+    #[test]
+    fn hevc_is_preferred_above_1080p_or_when_bitrate_starved() {
+        let both = [
+            report(Codec::Hevc, true, true),
+            report(Codec::H264, true, true),
+        ];
+        assert_eq!(
+            choose_codec_from(&both, dims(1920, 1080), 30, Some(6_000_000)),
+            Codec::H264
+        );
+        assert_eq!(
+            choose_codec_from(&both, dims(3840, 2160), 30, None),
+            Codec::Hevc
+        );
+        assert_eq!(
+            choose_codec_from(&both, dims(1920, 1080), 30, Some(1_500_000)),
+            Codec::Hevc
+        );
+    }
+
+    #[test]
+    fn hardware_support_outranks_content_size() {
+        let software_hevc = [
+            report(Codec::Hevc, true, false),
+            report(Codec::H264, true, true),
+        ];
+        assert_eq!(
+            choose_codec_from(&software_hevc, dims(3840, 2160), 30, None),
+            Codec::H264
+        );
+        let no_h264 = [
+            report(Codec::Hevc, true, true),
+            report(Codec::H264, false, false),
+        ];
+        assert_eq!(
+            choose_codec_from(&no_h264, dims(640, 360), 30, None),
+            Codec::Hevc
+        );
+        assert_eq!(
+            choose_codec_from(&[], dims(3840, 2160), 30, None),
+            Codec::H264
+        );
+    }
+}
@@ -0,0 +1,406 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::pipeline::{BoundedQueueRx, BoundedQueueTx, QueueRecvError, QueueStats, bounded_queue};
+use crate::{BackendError, Nv12Frame};
+
+const INPUT_POLL: Duration = Duration::from_millis(5);
+
+// Second input of a composite, e.g. the presenter camera. `alpha` is an optional
+// width*height coverage plane (255 = opaque) for keyed or shaped overlays.
+#[derive(Debug, Clone)]
+pub struct OverlayFrame {
+    pub frame: Nv12Frame,
+    pub alpha: Option<Vec<u8>>,
+}
+
+impl From<Nv12Frame> for OverlayFrame {
+    fn from(frame: Nv12Frame) -> Self {
+        Self { frame, alpha: None }
+    }
+}
+
+// Where the overlay lands on the base frame, in base luma pixels. The overlay is scaled to
+// `width`x`height`; the rectangle may extend past the base frame and is clipped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompositeLayout {
+    pub x: i32,
+    pub y: i32,
+    pub width: usize,
+    pub height: usize,
+    pub opacity: f32,
+}
+
+impl CompositeLayout {
+    pub fn new(x: i32, y: i32, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+            opacity: 1.0,
+        }
+    }
+
+    // Bottom-right picture-in-picture: the overlay keeps its aspect ratio and takes `fraction`
+    // of the base width, `margin` pixels away from the edges.
+    pub fn picture_in_picture(
+        base: (usize, usize),
+        overlay: (usize, usize),
+        fraction: f32,
+        margin: usize,
+    ) -> Self {
+        let width = ((base.0 as f32 * fraction.clamp(0.0, 1.0)) as usize).max(2) & !1;
+        let height = ((width * overlay.1) / overlay.0.max(1)).max(2) & !1;
+        Self::new(
+            base.0.saturating_sub(width + margin) as i32,
+            base.1.saturating_sub(height + margin) as i32,
+            width,
+            height,
+        )
+    }
+
+    // Visible part of the rectangle as luma x/y ranges of the base frame.
+    fn clip(&self, width: usize, height: usize) -> Option<(usize, usize, usize, usize)> {
+        let x0 = self.x.max(0) as usize;
+        let y0 = self.y.max(0) as usize;
+        let x1 = (i64::from(self.x) + self.width as i64).clamp(0, width as i64) as usize;
+        let y1 = (i64::from(self.y) + self.height as i64).clamp(0, height as i64) as usize;
+        (x0 < x1 && y0 < y1).then_some((x0, x1, y0, y1))
+    }
+}
+
+// Blends one overlay frame onto a base frame. CpuCompositor is the reference; with
+// `transform-cuda` the same operation runs as a CUDA kernel in CudaCompositor.
+pub trait CompositeKernel: Send {
+    fn composite(
+        &self,
+        base: &Nv12Frame,
+        overlay: &OverlayFrame,
+        layout: &CompositeLayout,
+    ) -> Result<Nv12Frame, BackendError>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuCompositor;
+
+impl CompositeKernel for CpuCompositor {
+    fn composite(
+        &self,
+        base: &Nv12Frame,
+        overlay: &OverlayFrame,
+        layout: &CompositeLayout,
+    ) -> Result<Nv12Frame, BackendError> {
+        validate_composite(base, overlay, layout)?;
+        let mut output = base.clone();
+        let Some((x0, x1, y0, y1)) = layout.clip(base.width, base.height) else {
+            return Ok(output);
+        };
+        let pitch = base.pitch.max(base.width);
+        let (luma, chroma) = output.data.split_at_mut(pitch * base.height);
+        let src = &overlay.frame;
+        let src_pitch = src.pitch.max(src.width);
+        let (src_luma, src_chroma) = src.data.split_at(src_pitch * src.height);
+        let (src_w, src_h) = (src.width, src.height);
+        let scale_x = src_w as f32 / layout.width as f32;
+        let scale_y = src_h as f32 / layout.height as f32;
+        let coverage = |sx: f32, sy: f32| {
+            let alpha = overlay.alpha.as_deref().map_or(1.0, |alpha| {
+                bilinear(alpha, src_w, 1, 0, (src_w, src_h), sx, sy) / 255.0
+            });
+            alpha * layout.opacity
+        };
+
+        for y in y0..y1 {
+            let sy = (y as f32 - layout.y as f32 + 0.5) * scale_y - 0.5;
+            for x in x0..x1 {
+                let sx = (x as f32 - layout.x as f32 + 0.5) * scale_x - 0.5;
+                let value = bilinear(src_luma, src_pitch, 1, 0, (src_w, src_h), sx, sy);
+                blend(&mut luma[y * pitch + x], value, coverage(sx, sy));
+            }
+        }
+
+        // One chroma sample per 2x2 block, blended when the block centre lies in the rectangle.
+        let chroma_size = (src_w.div_ceil(2), src_h.div_ceil(2));
+        for cy in y0 / 2..y1.div_ceil(2) {
+            let ly = (cy * 2 + 1) as f32 - layout.y as f32;
+            if ly < 0.0 || ly >= layout.height as f32 {
+                continue;
+            }
+            for cx in x0 / 2..x1.div_ceil(2) {
+                let lx = (cx * 2 + 1) as f32 - layout.x as f32;
+                if lx < 0.0 || lx >= layout.width as f32 {
+                    continue;
+                }
+                let alpha = coverage(lx * scale_x - 0.5, ly * scale_y - 0.5);
+                let (sx, sy) = (lx * scale_x / 2.0 - 0.5, ly * scale_y / 2.0 - 0.5);
+                let uv = cy * pitch + cx * 2;
+                for plane in 0..2 {
+                    let value = bilinear(src_chroma, src_pitch, 2, plane, chroma_size, sx, sy);
+                    blend(&mut chroma[uv + plane], value, alpha);
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
+pub(crate) fn validate_composite(
+    base: &Nv12Frame,
+    overlay: &OverlayFrame,
+    layout: &CompositeLayout,
+) -> Result<(), BackendError> {
+    for (name, frame) in [("base", base), ("overlay", &overlay.frame)] {
+        let pitch = frame.pitch.max(frame.width);
+        if frame.width == 0 || frame.height == 0 {
+            return Err(BackendError::InvalidInput(format!(
+                "{name} nv12 frame dimensions must be positive"
+            )));
+        }
+        if frame.data.len() < pitch * (frame.height + frame.height.div_ceil(2)) {
+            return Err(BackendError::InvalidInput(format!(
+                "{name} nv12 data is smaller than expected"
+            )));
+        }
+    }
+    if let Some(alpha) = &overlay.alpha
+        && alpha.len() < overlay.frame.width * overlay.frame.height
+    {
+        return Err(BackendError::InvalidInput(
+            "overlay alpha plane is smaller than the overlay frame".to_string(),
+        ));
+    }
+    if layout.width == 0 || layout.height == 0 || !(0.0..=1.0).contains(&layout.opacity) {
+        return Err(BackendError::InvalidInput(format!(
+            "invalid composite layout {}x{} opacity={}",
+            layout.width, layout.height, layout.opacity
+        )));
+    }
+    Ok(())
+}
+
+// Samples an interleaved plane (`step` bytes per sample, `offset` selects the component) at
+// fractional sample coordinates, clamping at the edges.
+fn bilinear(
+    plane: &[u8],
+    stride: usize,
+    step: usize,
+    offset: usize,
+    (width, height): (usize, usize),
+    x: f32,
+    y: f32,
+) -> f32 {
+    let x = x.clamp(0.0, (width - 1) as f32);
+    let y = y.clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x as usize, y as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (tx, ty) = (x - x0 as f32, y - y0 as f32);
+    let at = |x: usize, y: usize| f32::from(plane[y * stride + x * step + offset]);
+    let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * tx;
+    let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * tx;
+    top + (bottom - top) * ty
+}
+
+#[inline]
+fn blend(dst: &mut u8, src: f32, alpha: f32) {
+    let value = f32::from(*dst) + (src - f32::from(*dst)) * alpha;
+    *dst = value.round().clamp(0.0, 255.0) as u8;
+}
+
+// Multi-input pipeline stage: the base stream drives the output rate and each base frame is
+// paired with the newest overlay frame whose pts is not later than its own. The last overlay is
+// held while the overlay stream stalls, and base frames pass through until the first overlay
+// arrives. Results go to a bounded queue, so a slow consumer backpressures the base input.
+pub struct CompositeStage {
+    results_rx: BoundedQueueRx<Result<Nv12Frame, BackendError>>,
+    layout: Arc<Mutex<CompositeLayout>>,
+    shutdown: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl CompositeStage {
+    pub fn spawn(
+        kernel: impl CompositeKernel + 'static,
+        base: BoundedQueueRx<Nv12Frame>,
+        overlay: BoundedQueueRx<OverlayFrame>,
+        layout: CompositeLayout,
+        result_queue_capacity: usize,
+    ) -> Self {
+        let (results_tx, results_rx) = bounded_queue(result_queue_capacity.max(1));
+        let layout = Arc::new(Mutex::new(layout));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let worker = {
+            let layout = Arc::clone(&layout);
+            let shutdown = Arc::clone(&shutdown);
+            thread::spawn(move || {
+                run_composite(kernel, base, overlay, results_tx, layout, shutdown)
+            })
+        };
+        Self {
+            results_rx,
+            layout,
+            shutdown,
+            worker: Some(worker),
+        }
+    }
+
+    // Takes effect from the next base frame, e.g. to move or resize the picture-in-picture.
+    pub fn set_layout(&self, layout: CompositeLayout) {
+        match self.layout.lock() {
+            Ok(mut current) => *current = layout,
+            Err(poisoned) => *poisoned.into_inner() = layout,
+        }
+    }
+
+    // Disconnected once the base input has been closed and every result was taken.
+    pub fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<Result<Nv12Frame, BackendError>, QueueRecvError> {
+        self.results_rx.recv_timeout(timeout)
+    }
+
+    pub fn try_recv(&self) -> Result<Result<Nv12Frame, BackendError>, QueueRecvError> {
+        self.results_rx.try_recv()
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        self.results_rx.stats()
+    }
+}
+
+impl Drop for CompositeStage {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            // Keep draining so a worker blocked on a full result queue can observe the flag.
+            while !worker.is_finished() {
+                let _ = self.results_rx.recv_timeout(INPUT_POLL);
+            }
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run_composite(
+    kernel: impl CompositeKernel,
+    base: BoundedQueueRx<Nv12Frame>,
+    overlay: BoundedQueueRx<OverlayFrame>,
+    results: BoundedQueueTx<Result<Nv12Frame, BackendError>>,
+    layout: Arc<Mutex<CompositeLayout>>,
+    shutdown: Arc<AtomicBool>,
+) {
+    let mut current: Option<OverlayFrame> = None;
+    let mut ahead: Option<OverlayFrame> = None;
+    while !shutdown.load(Ordering::Relaxed) {
+        let frame = match base.recv_timeout(INPUT_POLL) {
+            Ok(frame) => frame,
+            Err(QueueRecvError::Timeout | QueueRecvError::Empty) => continue,
+            Err(QueueRecvError::Disconnected) => break,
+        };
+        while let Some(next) = ahead.take().or_else(|| overlay.try_recv().ok()) {
+            let is_ahead = matches!(
+                (next.frame.pts_90k, frame.pts_90k),
+                (Some(overlay_pts), Some(base_pts)) if overlay_pts > base_pts
+            );
+            if is_ahead && current.is_some() {
+                ahead = Some(next);
+                break;
+            }
+            current = Some(next);
+        }
+        let result = match &current {
+            Some(overlay) => {
+                let layout = match layout.lock() {
+                    Ok(layout) => *layout,
+                    Err(poisoned) => *poisoned.into_inner(),
+                };
+                kernel.composite(&frame, overlay, &layout)
+            }
+            None => Ok(frame),
+        };
+        if results.send(result).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: usize, height: usize, luma: u8, chroma: (u8, u8), pts: i64) -> Nv12Frame {
+        let mut data = vec![luma; width * height];
+        for _ in 0..width * height.div_ceil(2) / 2 {
+            data.extend([chroma.0, chroma.1]);
+        }
+        Nv12Frame {
+            width,
+            height,
+            pitch: width,
+            pts_90k: Some(pts),
+            data,
+        }
+    }
+
+    #[test]
+    fn overlay_is_scaled_into_rect_and_alpha_blended() {
+        let base = solid(16, 8, 16, (128, 128), 0);
+        let overlay = OverlayFrame {
+            frame: solid(4, 4, 235, (90, 240), 0),
+            alpha: Some([[255_u8; 4], [255; 4], [0; 4], [0; 4]].concat()),
+        };
+        let mut layout = CompositeLayout::new(8, 0, 8, 8);
+        let out = CpuCompositor.composite(&base, &overlay, &layout).unwrap();
+        // Outside the rectangle untouched, opaque top half replaced, transparent bottom kept.
+        assert_eq!(out.data[0], 16);
+        assert_eq!(out.data[8], 235);
+        assert_eq!(out.data[7 * 16 + 15], 16);
+        let uv = 16 * 8;
+        assert_eq!(&out.data[uv + 8..uv + 10], &[90, 240]);
+        assert_eq!(&out.data[uv..uv + 2], &[128, 128]);
+
+        layout.opacity = 0.5;
+        let out = CpuCompositor.composite(&base, &overlay, &layout).unwrap();
+        assert_eq!(out.data[8], 126);
+        layout.x = 20;
+        let out = CpuCompositor.composite(&base, &overlay, &layout).unwrap();
+        assert_eq!(out.data, base.data);
+    }
+
+    #[test]
+    fn stage_pairs_each_base_frame_with_latest_overlay_not_ahead_of_it() {
+        let (base_tx, base_rx) = bounded_queue(8);
+        let (overlay_tx, overlay_rx) = bounded_queue(8);
+        // Overlay luma encodes its pts so the pairing is visible in the output.
+        for pts in [0, 3000, 9000] {
+            overlay_tx
+                .send(OverlayFrame::from(solid(
+                    4,
+                    4,
+                    100 + (pts / 3000) as u8,
+                    (128, 128),
+                    pts,
+                )))
+                .unwrap();
+        }
+        let stage = CompositeStage::spawn(
+            CpuCompositor,
+            base_rx,
+            overlay_rx,
+            CompositeLayout::new(0, 0, 4, 4),
+            8,
+        );
+        for pts in [0, 3000, 6000, 9000] {
+            base_tx.send(solid(4, 4, 16, (128, 128), pts)).unwrap();
+        }
+        drop(base_tx);
+        let mut lumas = Vec::new();
+        while let Ok(result) = stage.recv_timeout(Duration::from_secs(2)) {
+            lumas.push(result.unwrap().data[0]);
+        }
+        assert_eq!(lumas, vec![100, 101, 101, 103]);
+    }
+}
@@ -0,0 +1,322 @@
+use crate::{
+    BackendEncoderOptions, EncoderConfig, NvidiaEncoderOptions, NvidiaSessionConfig,
+    SessionSwitchMode, SessionSwitchRequest, VtEncoderOptions, VtSessionConfig,
+};
+
+// How EncodeSession::apply_config carries a config change over to the running encoder, from
+// cheapest to most disruptive. A rebuild drains the old session and the new one starts with an
+// IDR; session-level settings (repeat_mode, cbr_filler) never touch the backend. A new
+// target_bitrate_bps or max_qp is hot on both backends, unless it goes back to None.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigApplyPath {
+    Unchanged,
+    HotReconfigure,
+    PropertyUpdate,
+    Rebuild,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigDiff {
+    pub changed: Vec<&'static str>,
+    pub path: ConfigApplyPath,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty()
+    }
+}
+
+// Which live-update mechanism the running backend offers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SwitchTarget {
+    Nvidia,
+    VideoToolbox,
+    None,
+}
+
+// The switch request is set for the hot paths and is what the backend should be handed.
+pub(crate) fn plan_config_change(
+    target: SwitchTarget,
+    current: &EncoderConfig,
+    next: &EncoderConfig,
+) -> (ConfigDiff, Option<SessionSwitchRequest>) {
+    let structural = [
+        ("codec", current.codec != next.codec),
+        ("fps", current.fps != next.fps),
+        (
+            "require_hardware",
+            current.require_hardware != next.require_hardware,
+        ),
+        (
+            "fallback_policy",
+            current.fallback_policy != next.fallback_policy,
+        ),
+        (
+            "keyframe_interval",
+            current.keyframe_interval != next.keyframe_interval,
+        ),
+        ("latency_tune", current.latency_tune != next.latency_tune),
+        // Unsetting a rate target means returning to the preset's own, which only a new
+        // session does.
+        (
+            "target_bitrate_bps",
+            current.target_bitrate_bps != next.target_bitrate_bps
+                && next.target_bitrate_bps.is_none(),
+        ),
+        (
+            "max_qp",
+            current.max_qp != next.max_qp && next.max_qp.is_none(),
+        ),
+    ];
+    let mut changed = structural
+        .iter()
+        .filter(|(_, differs)| *differs)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+    let structural = !changed.is_empty();
+    let rate = [
+        (
+            "target_bitrate_bps",
+            current.target_bitrate_bps != next.target_bitrate_bps
+                && next.target_bitrate_bps.is_some(),
+        ),
+        (
+            "max_qp",
+            current.max_qp != next.max_qp && next.max_qp.is_some(),
+        ),
+    ];
+    let rate_changed = rate.iter().any(|(_, differs)| *differs);
+    changed.extend(
+        rate.iter()
+            .filter(|(_, differs)| *differs)
+            .map(|(name, _)| *name),
+    );
+    if current.repeat_mode != next.repeat_mode {
+        changed.push("repeat_mode");
+    }
+    if current.cbr_filler != next.cbr_filler {
+        changed.push("cbr_filler");
+    }
+
+    let (current_nv, next_nv) = (nvidia_options(current), nvidia_options(next));
+    let nv_hot = [
+        (
+            "nvidia.gop_length",
+            current_nv.gop_length != next_nv.gop_length,
+        ),
+        (
+            "nvidia.frame_interval_p",
+            current_nv.frame_interval_p != next_nv.frame_interval_p,
+        ),
+        (
+            "nvidia.buffer_lifetime_mode",
+            current_nv.buffer_lifetime_mode != next_nv.buffer_lifetime_mode,
+        ),
+    ];
+    let nv_cold = NvidiaEncoderOptions {
+        gop_length: next_nv.gop_length,
+        frame_interval_p: next_nv.frame_interval_p,
+        buffer_lifetime_mode: next_nv.buffer_lifetime_mode,
+        ..current_nv.clone()
+    } != next_nv;
+    let (current_vt, next_vt) = (vt_options(current), vt_options(next));
+    let vt_hot = current_vt.quality != next_vt.quality;
+    let vt_cold = VtEncoderOptions {
+        quality: next_vt.quality,
+        ..current_vt.clone()
+    } != next_vt;
+
+    let backend_fields = nv_hot
+        .into_iter()
+        .filter(|_| target != SwitchTarget::VideoToolbox)
+        .chain((target != SwitchTarget::VideoToolbox).then_some(("nvidia.other", nv_cold)))
+        .chain((target != SwitchTarget::Nvidia).then_some(("videotoolbox.quality", vt_hot)))
+        .chain((target != SwitchTarget::Nvidia).then_some(("videotoolbox.other", vt_cold)))
+        .filter(|(_, differs)| *differs)
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    let backend_changed = !backend_fields.is_empty();
+    changed.extend(backend_fields);
+
+    let (path, request) = if structural {
+        (ConfigApplyPath::Rebuild, None)
+    } else if !backend_changed && !rate_changed {
+        (ConfigApplyPath::Unchanged, None)
+    } else {
+        match target {
+            SwitchTarget::Nvidia if !nv_cold => {
+                // A new GOP structure only takes effect cleanly from an IDR. The vendor-neutral
+                // settings win over the NVENC options, as when the session was built.
+                let config = NvidiaSessionConfig {
+                    gop_length: next
+                        .keyframe_interval
+                        .map(std::num::NonZeroU32::get)
+                        .or(next_nv.gop_length),
+                    frame_interval_p: next_nv.frame_interval_p,
+                    force_idr_on_activate: nv_hot[0].1 || nv_hot[1].1,
+                    buffer_lifetime_mode: next_nv.buffer_lifetime_mode,
+                    average_bitrate_bps: next.target_bitrate_bps.or(next_nv.average_bitrate_bps),
+                    max_qp: next.max_qp.or(next_nv.max_qp),
+                };
+                let mode = SessionSwitchMode::Immediate;
+                (
+                    ConfigApplyPath::HotReconfigure,
+                    Some(SessionSwitchRequest::Nvidia { config, mode }),
+                )
+            }
+            SwitchTarget::VideoToolbox if !vt_cold => {
+                // OnNextKeyframe keeps the compression session and sets the property on it;
+                // rate properties alone go to the running session right away.
+                let config = VtSessionConfig {
+                    force_keyframe_on_activate: false,
+                    quality: next_vt.quality.filter(|_| vt_hot),
+                    average_bitrate_bps: next.target_bitrate_bps.or(next_vt.average_bitrate_bps),
+                    max_qp: next.max_qp.or(next_vt.max_qp),
+                };
+                let mode = if vt_hot {
+                    SessionSwitchMode::OnNextKeyframe
+                } else {
+                    SessionSwitchMode::Immediate
+                };
+                (
+                    ConfigApplyPath::PropertyUpdate,
+                    Some(SessionSwitchRequest::VideoToolbox { config, mode }),
+                )
+            }
+            _ => (ConfigApplyPath::Rebuild, None),
+        }
+    };
+    (ConfigDiff { changed, path }, request)
+}
+
+// Backends treat missing or foreign options as their defaults, so compare those.
+fn nvidia_options(config: &EncoderConfig) -> NvidiaEncoderOptions {
+    match &config.backend_options {
+        BackendEncoderOptions::Nvidia(options) => options.clone(),
+        _ => NvidiaEncoderOptions::default(),
+    }
+}
+
+fn vt_options(config: &EncoderConfig) -> VtEncoderOptions {
+    match &config.backend_options {
+        BackendEncoderOptions::VideoToolbox(options) => options.clone(),
+        _ => VtEncoderOptions::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+
+    fn nv_config(tweak: impl FnOnce(&mut NvidiaEncoderOptions)) -> EncoderConfig {
+        EncoderConfig::new(Codec::H264, 30, true).with_nvidia_options(tweak)
+    }
+
+    #[test]
+    fn plan_picks_the_cheapest_path_per_backend() {
+        let base = EncoderConfig::new(Codec::H264, 30, true);
+
+        let (diff, request) = plan_config_change(SwitchTarget::Nvidia, &base, &nv_config(|_| {}));
+        assert!(diff.is_empty() && request.is_none());
+        assert_eq!(diff.path, ConfigApplyPath::Unchanged);
+
+        let gop = nv_config(|options| options.gop_length = Some(60));
+        let (diff, request) = plan_config_change(SwitchTarget::Nvidia, &base, &gop);
+        assert_eq!(diff.changed, ["nvidia.gop_length"]);
+        assert_eq!(diff.path, ConfigApplyPath::HotReconfigure);
+        let Some(SessionSwitchRequest::Nvidia { config, .. }) = request else {
+            panic!("expected an NVENC reconfigure request");
+        };
+        assert_eq!(config.gop_length, Some(60));
+        assert!(config.force_idr_on_activate);
+
+        let lookahead = nv_config(|options| options.lookahead_depth = Some(8));
+        let (diff, _) = plan_config_change(SwitchTarget::Nvidia, &base, &lookahead);
+        assert_eq!(diff.path, ConfigApplyPath::Rebuild);
+        // VideoToolbox ignores NVENC options altogether.
+        let (diff, _) = plan_config_change(SwitchTarget::VideoToolbox, &base, &lookahead);
+        assert_eq!(diff.path, ConfigApplyPath::Unchanged);
+
+        let mut quality = base.clone();
+        quality.backend_options = BackendEncoderOptions::VideoToolbox(VtEncoderOptions {
+            quality: Some(0.5),
+            ..VtEncoderOptions::default()
+        });
+        let (diff, request) = plan_config_change(SwitchTarget::VideoToolbox, &base, &quality);
+        assert_eq!(diff.path, ConfigApplyPath::PropertyUpdate);
+        assert!(matches!(
+            request,
+            Some(SessionSwitchRequest::VideoToolbox { config, .. }) if config.quality == Some(0.5)
+        ));
+        let (diff, _) = plan_config_change(SwitchTarget::None, &base, &quality);
+        assert_eq!(diff.path, ConfigApplyPath::Rebuild);
+        // Passthrough properties are only applied when the compression session is created.
+        let mut extra = base.clone();
+        extra.backend_options = BackendEncoderOptions::VideoToolbox(VtEncoderOptions {
+            extra_properties: vec![(
+                "PrioritizeEncodingSpeedOverQuality".to_string(),
+                crate::VtPropertyValue::Bool(true),
+            )],
+            ..VtEncoderOptions::default()
+        });
+        let (diff, request) = plan_config_change(SwitchTarget::VideoToolbox, &base, &extra);
+        assert_eq!(diff.changed, ["videotoolbox.other"]);
+        assert_eq!(diff.path, ConfigApplyPath::Rebuild);
+        assert!(request.is_none());
+
+        let mut repeat = base.clone();
+        repeat.repeat_mode = crate::FrameRepeatMode::Hold;
+        let (diff, _) = plan_config_change(SwitchTarget::Nvidia, &base, &repeat);
+        assert_eq!(diff.path, ConfigApplyPath::Unchanged);
+        assert_eq!(diff.changed, ["repeat_mode"]);
+        repeat.cbr_filler = true;
+        let (diff, _) = plan_config_change(SwitchTarget::Nvidia, &base, &repeat);
+        assert_eq!(diff.path, ConfigApplyPath::Unchanged);
+        assert_eq!(diff.changed, ["repeat_mode", "cbr_filler"]);
+
+        let mut cadence = base.clone();
+        cadence.keyframe_interval = std::num::NonZeroU32::new(60);
+        let (diff, _) = plan_config_change(SwitchTarget::VideoToolbox, &base, &cadence);
+        assert_eq!(diff.changed, ["keyframe_interval"]);
+        assert_eq!(diff.path, ConfigApplyPath::Rebuild);
+        let mut zero_latency = base.clone();
+        zero_latency.latency_tune = crate::LatencyTune::ZeroLatency;
+        let (diff, _) = plan_config_change(SwitchTarget::Nvidia, &base, &zero_latency);
+        assert_eq!(diff.changed, ["latency_tune"]);
+        assert_eq!(diff.path, ConfigApplyPath::Rebuild);
+
+        let mut codec = gop.clone();
+        codec.codec = Codec::Hevc;
+        let (diff, request) = plan_config_change(SwitchTarget::Nvidia, &base, &codec);
+        assert_eq!(diff.changed, ["codec", "nvidia.gop_length"]);
+        assert_eq!(diff.path, ConfigApplyPath::Rebuild);
+        assert!(request.is_none());
+
+        let mut rate = cadence.clone();
+        rate.target_bitrate_bps = Some(2_000_000);
+        rate.max_qp = Some(40);
+        let (diff, request) = plan_config_change(SwitchTarget::Nvidia, &cadence, &rate);
+        assert_eq!(diff.changed, ["target_bitrate_bps", "max_qp"]);
+        assert_eq!(diff.path, ConfigApplyPath::HotReconfigure);
+        let Some(SessionSwitchRequest::Nvidia { config, .. }) = request else {
+            panic!("expected an NVENC reconfigure request");
+        };
+        assert_eq!(
+            (config.average_bitrate_bps, config.max_qp, config.gop_length),
+            (Some(2_000_000), Some(40), Some(60))
+        );
+        assert!(!config.force_idr_on_activate);
+        let (diff, request) = plan_config_change(SwitchTarget::VideoToolbox, &cadence, &rate);
+        assert_eq!(diff.path, ConfigApplyPath::PropertyUpdate);
+        assert!(matches!(
+            request,
+            Some(SessionSwitchRequest::VideoToolbox { config, mode: SessionSwitchMode::Immediate })
+                if config.quality.is_none() && config.max_qp == Some(40)
+        ));
+        let (diff, _) = plan_config_change(SwitchTarget::Nvidia, &rate, &cadence);
+        assert_eq!(diff.changed, ["target_bitrate_bps", "max_qp"]);
+        assert_eq!(diff.path, ConfigApplyPath::Rebuild);
+    }
+}
@@ -0,0 +1,770 @@
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+use crate::{
+    Backend, BitstreamInput, DecodeSession, DecoderConfig, Dimensions, EncodeFrame, EncodeSession,
+    EncoderConfig, RawFrameBuffer,
+};
+use crate::{
+    BackendError, Codec, DecodedFrame, SoftwareDecoder, SoftwareDecoderFactory, Timestamp90k,
+};
+
+pub const CONFORMANCE_MANIFEST: &str = "conformance.tsv";
+
+// One bitstream from the manifest. A missing checksum ("-") only checks the frame count, which
+// is how new vectors are added before their first recorded run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConformanceVector {
+    pub name: String,
+    pub path: PathBuf,
+    pub codec: Codec,
+    pub expected_frames: usize,
+    pub expected_checksum: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConformanceVerdict {
+    Pass,
+    FrameCountMismatch { expected: usize, actual: usize },
+    ChecksumMismatch { expected: u64, actual: u64 },
+    DecodeError(String),
+    // The backend cannot run this vector here (no device, codec or profile unsupported).
+    Skipped(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConformanceResult {
+    pub vector: String,
+    pub frames: usize,
+    pub checksum: u64,
+    pub verdict: ConformanceVerdict,
+}
+
+impl ConformanceResult {
+    pub fn passed(&self) -> bool {
+        self.verdict == ConformanceVerdict::Pass
+    }
+
+    pub fn failed(&self) -> bool {
+        !matches!(
+            self.verdict,
+            ConformanceVerdict::Pass | ConformanceVerdict::Skipped(_)
+        )
+    }
+}
+
+impl Display for ConformanceResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match &self.verdict {
+            ConformanceVerdict::Pass => "PASS".to_string(),
+            ConformanceVerdict::FrameCountMismatch { expected, .. } => {
+                format!("FAIL expected_frames={expected}")
+            }
+            ConformanceVerdict::ChecksumMismatch { expected, .. } => {
+                format!("FAIL expected_checksum={expected:016x}")
+            }
+            ConformanceVerdict::DecodeError(err) => format!("FAIL error={err}"),
+            ConformanceVerdict::Skipped(reason) => format!("SKIP reason={reason}"),
+        };
+        write!(
+            f,
+            "[conformance] {} {status} frames={} checksum={:016x}",
+            self.vector, self.frames, self.checksum
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConformanceReport {
+    pub results: Vec<ConformanceResult>,
+}
+
+impl ConformanceReport {
+    pub fn all_passed(&self) -> bool {
+        !self.results.iter().any(ConformanceResult::failed)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &ConformanceResult> {
+        self.results.iter().filter(|result| result.failed())
+    }
+
+    // Manifest lines with the observed values, for recording a new driver baseline.
+    pub fn to_manifest(&self, vectors: &[ConformanceVector]) -> String {
+        vectors
+            .iter()
+            .zip(&self.results)
+            .filter(|(_, result)| !matches!(result.verdict, ConformanceVerdict::Skipped(_)))
+            .map(|(vector, result)| {
+                format!(
+                    "{}\t{}\t{}\t{:016x}\n",
+                    vector.name,
+                    codec_name(vector.codec),
+                    result.frames,
+                    result.checksum
+                )
+            })
+            .collect()
+    }
+}
+
+impl Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            writeln!(f, "{result}")?;
+        }
+        let passed = self.results.iter().filter(|r| r.passed()).count();
+        let failed = self.failures().count();
+        write!(
+            f,
+            "[conformance] passed={passed} failed={failed} skipped={}",
+            self.results.len() - passed - failed
+        )
+    }
+}
+
+// `<dir>/conformance.tsv`: one `file<TAB>codec<TAB>frames<TAB>checksum` line per vector, where
+// codec is h264 or hevc and checksum is 16 hex digits or "-". Blank lines and `#` comments are
+// ignored; files are resolved relative to `dir`.
+pub fn load_conformance_manifest(
+    dir: impl AsRef<Path>,
+) -> Result<Vec<ConformanceVector>, BackendError> {
+    let dir = dir.as_ref();
+    let manifest = dir.join(CONFORMANCE_MANIFEST);
+    let text = fs::read_to_string(&manifest).map_err(|err| {
+        BackendError::InvalidInput(format!("failed to read {}: {err}", manifest.display()))
+    })?;
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_no, line)| {
+            let invalid = |what: &str| {
+                BackendError::InvalidInput(format!(
+                    "{}:{line_no}: invalid {what}",
+                    manifest.display()
+                ))
+            };
+            let fields = line.split('\t').map(str::trim).collect::<Vec<_>>();
+            let [name, codec, frames, checksum] = fields[..] else {
+                return Err(invalid("line, expected 4 tab-separated fields"));
+            };
+            let codec = match codec.to_ascii_lowercase().as_str() {
+                "h264" | "avc" => Codec::H264,
+                "hevc" | "h265" => Codec::Hevc,
+                _ => return Err(invalid("codec")),
+            };
+            let expected_checksum = match checksum {
+                "-" => None,
+                hex => Some(u64::from_str_radix(hex, 16).map_err(|_| invalid("checksum"))?),
+            };
+            Ok(ConformanceVector {
+                name: name.to_string(),
+                path: dir.join(name),
+                codec,
+                expected_frames: frames.parse().map_err(|_| invalid("frame count"))?,
+                expected_checksum,
+            })
+        })
+        .collect()
+}
+
+// FNV-1a over every frame in output order. Frames with pixels hash the visible rows (pitch
+// padding excluded); metadata-only frames hash their dimensions, so the checksum still catches
+// reordering and resolution errors on backends that keep surfaces on the GPU.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameChecksum(u64);
+
+impl Default for FrameChecksum {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl FrameChecksum {
+    pub fn update(&mut self, frame: &DecodedFrame) {
+        match frame {
+            DecodedFrame::Nv12 {
+                dims, pitch, data, ..
+            } => {
+                let width = dims.width.get() as usize;
+                let rows = dims.height.get() as usize + (dims.height.get() as usize).div_ceil(2);
+                for row in data.chunks(*pitch).take(rows) {
+                    self.write(&row[..width.min(row.len())]);
+                }
+            }
+            DecodedFrame::Rgb24 { data, .. } => self.write(data),
+            DecodedFrame::Metadata { dims, .. } => {
+                let (width, height) =
+                    dims.map_or((0, 0), |dims| (dims.width.get(), dims.height.get()));
+                self.write(&width.to_le_bytes());
+                self.write(&height.to_le_bytes());
+            }
+        }
+    }
+
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+fn codec_name(codec: Codec) -> &'static str {
+    match codec {
+        Codec::H264 => "h264",
+        Codec::Hevc => "hevc",
+        Codec::Mjpeg => "mjpeg",
+    }
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+pub fn run_conformance_vector(
+    backend: crate::Backend,
+    vector: &ConformanceVector,
+) -> ConformanceResult {
+    let mut frames = 0usize;
+    let mut checksum = FrameChecksum::default();
+    let decoded = (|| {
+        let data = fs::read(&vector.path).map_err(|err| {
+            BackendError::InvalidInput(format!("failed to read {}: {err}", vector.path.display()))
+        })?;
+        let mut decoder = DecodeSession::new(backend, DecoderConfig::new(vector.codec, 30, true));
+        for chunk in data.chunks(4096) {
+            decoder.submit(BitstreamInput::AnnexBChunk {
+                chunk: chunk.to_vec(),
+                pts_90k: None,
+            })?;
+            while let Some(frame) = decoder.try_reap()? {
+                frames += 1;
+                checksum.update(&frame);
+            }
+        }
+        for frame in decoder.close()? {
+            frames += 1;
+            checksum.update(&frame);
+        }
+        Ok::<(), BackendError>(())
+    })();
+    let verdict = match decoded {
+        Err(BackendError::UnsupportedConfig(reason)) => ConformanceVerdict::Skipped(reason),
+        Err(err) => ConformanceVerdict::DecodeError(err.to_string()),
+        Ok(()) if frames != vector.expected_frames => ConformanceVerdict::FrameCountMismatch {
+            expected: vector.expected_frames,
+            actual: frames,
+        },
+        Ok(()) => match vector.expected_checksum {
+            Some(expected) if expected != checksum.value() => {
+                ConformanceVerdict::ChecksumMismatch {
+                    expected,
+                    actual: checksum.value(),
+                }
+            }
+            _ => ConformanceVerdict::Pass,
+        },
+    };
+    ConformanceResult {
+        vector: vector.name.clone(),
+        frames,
+        checksum: checksum.value(),
+        verdict,
+    }
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+pub fn run_conformance_suite(
+    backend: crate::Backend,
+    vectors: &[ConformanceVector],
+) -> ConformanceReport {
+    ConformanceReport {
+        results: vectors
+            .iter()
+            .map(|vector| run_conformance_vector(backend, vector))
+            .collect(),
+    }
+}
+
+// Contract suite for decoder and encoder implementations, independent of any particular
+// bitstream: new built-in backends run it through DecodeSession/EncodeSession, downstream
+// software decoders through their SoftwareDecoderFactory. Each check is reported separately so
+// one broken rule does not hide the others.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContractCheck {
+    pub name: &'static str,
+    pub failure: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContractReport {
+    pub subject: String,
+    // Set when the implementation cannot run here at all (no device, codec unsupported).
+    pub skipped: Option<String>,
+    pub checks: Vec<ContractCheck>,
+}
+
+impl ContractReport {
+    fn new(subject: impl Into<String>) -> Self {
+        Self {
+            subject: subject.into(),
+            skipped: None,
+            checks: Vec::new(),
+        }
+    }
+
+    fn check(&mut self, name: &'static str, failure: Option<String>) {
+        self.checks.push(ContractCheck { name, failure });
+    }
+
+    pub fn all_passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &ContractCheck> {
+        self.checks.iter().filter(|check| check.failure.is_some())
+    }
+}
+
+impl Display for ContractReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(reason) = &self.skipped {
+            return write!(f, "[contract] {} SKIP reason={reason}", self.subject);
+        }
+        for (index, check) in self.checks.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            match &check.failure {
+                None => write!(f, "[contract] {} {} PASS", self.subject, check.name)?,
+                Some(reason) => write!(
+                    f,
+                    "[contract] {} {} FAIL {reason}",
+                    self.subject, check.name
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// What the decoder checks need from the implementation under test.
+trait ContractDecoder {
+    fn decode(
+        &mut self,
+        access_unit: &[u8],
+        pts_90k: Timestamp90k,
+    ) -> Result<Vec<DecodedFrame>, BackendError>;
+
+    fn flush(&mut self) -> Result<Vec<DecodedFrame>, BackendError>;
+}
+
+impl ContractDecoder for Box<dyn SoftwareDecoder> {
+    fn decode(
+        &mut self,
+        access_unit: &[u8],
+        pts_90k: Timestamp90k,
+    ) -> Result<Vec<DecodedFrame>, BackendError> {
+        self.as_mut().decode(access_unit, Some(pts_90k))
+    }
+
+    fn flush(&mut self) -> Result<Vec<DecodedFrame>, BackendError> {
+        self.as_mut().flush()
+    }
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+impl ContractDecoder for DecodeSession {
+    fn decode(
+        &mut self,
+        access_unit: &[u8],
+        pts_90k: Timestamp90k,
+    ) -> Result<Vec<DecodedFrame>, BackendError> {
+        self.submit(BitstreamInput::AnnexBChunk {
+            chunk: access_unit.to_vec(),
+            pts_90k: Some(pts_90k),
+        })?;
+        let mut frames = Vec::new();
+        while let Some(frame) = self.try_reap()? {
+            frames.push(frame);
+        }
+        Ok(frames)
+    }
+
+    fn flush(&mut self) -> Result<Vec<DecodedFrame>, BackendError> {
+        DecodeSession::flush(self)
+    }
+}
+
+// `samples` are Annex B access units in decode order with their presentation timestamps,
+// starting with a keyframe, each decoding to exactly one frame (progressive, no SEI-only units).
+pub fn check_software_decoder_contract(
+    factory: &SoftwareDecoderFactory,
+    codec: Codec,
+    samples: &[(Vec<u8>, Timestamp90k)],
+) -> ContractReport {
+    check_decoder_contract(
+        format!("software-decoder/{}", codec_name(codec)),
+        || factory.create(codec),
+        samples,
+    )
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+pub fn check_decode_session_contract(
+    backend: Backend,
+    config: DecoderConfig,
+    samples: &[(Vec<u8>, Timestamp90k)],
+) -> ContractReport {
+    check_decoder_contract(
+        format!("decode/{backend:?}/{}", codec_name(config.codec)),
+        || Ok(DecodeSession::new(backend, config.clone())),
+        samples,
+    )
+}
+
+fn check_decoder_contract<D: ContractDecoder>(
+    subject: String,
+    mut factory: impl FnMut() -> Result<D, BackendError>,
+    samples: &[(Vec<u8>, Timestamp90k)],
+) -> ContractReport {
+    let mut report = ContractReport::new(subject);
+    let mut frames = Vec::new();
+    let decoded = factory().and_then(|mut decoder| {
+        for (access_unit, pts) in samples {
+            frames.extend(decoder.decode(access_unit, *pts)?);
+        }
+        frames.extend(decoder.flush()?);
+        Ok(decoder)
+    });
+    let mut decoder = match decoded {
+        Err(err @ (BackendError::UnsupportedCodec(_) | BackendError::UnsupportedConfig(_)))
+            if frames.is_empty() =>
+        {
+            report.skipped = Some(err.to_string());
+            return report;
+        }
+        Err(err) => {
+            report.check("decode", Some(err.to_string()));
+            return report;
+        }
+        Ok(decoder) => decoder,
+    };
+    report.check(
+        "frame_count",
+        (frames.len() != samples.len()).then(|| {
+            format!(
+                "{} access units produced {} frames",
+                samples.len(),
+                frames.len()
+            )
+        }),
+    );
+    // Output is in presentation order, and every frame keeps the pts it was submitted with.
+    let pts = frames.iter().map(DecodedFrame::pts_90k).collect::<Vec<_>>();
+    let unknown = pts
+        .iter()
+        .find(|pts| !pts.is_some_and(|pts| samples.iter().any(|(_, input)| *input == pts)));
+    let out_of_order = pts.windows(2).find(|pair| pair[0] >= pair[1]);
+    report.check(
+        "pts_monotonic",
+        match (unknown, out_of_order) {
+            (Some(pts), _) => Some(format!("frame pts {pts:?} was never submitted")),
+            (None, Some(pair)) => Some(format!("pts {:?} came out before {:?}", pair[0], pair[1])),
+            (None, None) => None,
+        },
+    );
+    report.check(
+        "flush_drains",
+        match decoder.flush() {
+            Ok(extra) if extra.is_empty() => None,
+            Ok(extra) => Some(format!("a second flush returned {} frames", extra.len())),
+            Err(err) => Some(format!("a second flush failed: {err}")),
+        },
+    );
+    // A damaged access unit may be concealed or rejected, but only as a bitstream/input error.
+    let garbage = [0, 0, 0, 1, 0x65, 0xff, 0xff, 0xff, 0xff];
+    let pts = samples.first().map_or(Timestamp90k(0), |(_, pts)| *pts);
+    report.check(
+        "error_mapping",
+        match factory().and_then(|mut decoder| {
+            decoder.decode(&garbage, pts)?;
+            decoder.flush()
+        }) {
+            Ok(_) | Err(BackendError::InvalidBitstream(_) | BackendError::InvalidInput(_)) => None,
+            Err(err) => Some(format!(
+                "a damaged access unit failed with {err}; expected InvalidBitstream or InvalidInput"
+            )),
+        },
+    );
+    report
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+const CONTRACT_FRAMES: u32 = 8;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+const CONTRACT_FORCED_KEYFRAME: u32 = 5;
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+// Encodes a short synthetic ARGB clip of `dims` and checks that every frame comes out once with
+// its pts, forced keyframes are flagged, flush leaves nothing behind and invalid input is
+// rejected as InvalidInput.
+pub fn check_encode_session_contract(
+    backend: Backend,
+    config: EncoderConfig,
+    dims: Dimensions,
+) -> ContractReport {
+    let fps = config.fps;
+    let mut report =
+        ContractReport::new(format!("encode/{backend:?}/{}", codec_name(config.codec)));
+    let mut session = EncodeSession::new(backend, config);
+    let frame = |index: u32, buffer: RawFrameBuffer| EncodeFrame {
+        dims,
+        pts_90k: Some(Timestamp90k(fps.pts_90k(i64::from(index)))),
+        buffer,
+        force_keyframe: index == 0 || index == CONTRACT_FORCED_KEYFRAME,
+        dirty_rects: None,
+        repeat_count: 0,
+    };
+    let pixels = (dims.width.get() * dims.height.get()) as usize;
+
+    let mut chunks = Vec::new();
+    let encoded = (0..CONTRACT_FRAMES)
+        .try_for_each(|index| {
+            let shade = (index * 255 / CONTRACT_FRAMES) as u8;
+            session.submit(frame(
+                index,
+                RawFrameBuffer::Argb8888([0xff, shade, 0x80, 0xff - shade].repeat(pixels)),
+            ))?;
+            while let Some(chunk) = session.try_reap()? {
+                chunks.push(chunk);
+            }
+            Ok(())
+        })
+        .and_then(|()| session.flush());
+    let flushed = match encoded {
+        Err(err @ (BackendError::UnsupportedCodec(_) | BackendError::UnsupportedConfig(_)))
+            if chunks.is_empty() =>
+        {
+            report.skipped = Some(err.to_string());
+            return report;
+        }
+        Err(err) => {
+            report.check("encode", Some(err.to_string()));
+            return report;
+        }
+        Ok(flushed) => flushed,
+    };
+    chunks.extend(flushed);
+
+    let mut pts = chunks
+        .iter()
+        .map(|chunk| chunk.pts_90k.map(|pts| pts.0))
+        .collect::<Vec<_>>();
+    pts.sort_unstable();
+    let expected = (0..CONTRACT_FRAMES)
+        .map(|index| Some(fps.pts_90k(i64::from(index))))
+        .collect::<Vec<_>>();
+    report.check(
+        "pts_coverage",
+        (pts != expected).then(|| format!("chunk pts {pts:?}, expected {expected:?}")),
+    );
+    let forced = Timestamp90k(fps.pts_90k(i64::from(CONTRACT_FORCED_KEYFRAME)));
+    report.check(
+        "keyframe_flags",
+        if !chunks.first().is_some_and(|chunk| chunk.is_keyframe) {
+            Some("the first chunk is not flagged as a keyframe".to_string())
+        } else if !chunks
+            .iter()
+            .any(|chunk| chunk.pts_90k == Some(forced) && chunk.is_keyframe)
+        {
+            Some(format!("forced keyframe at {forced:?} is not flagged"))
+        } else {
+            None
+        },
+    );
+    report.check(
+        "flush_drains",
+        match session.flush() {
+            Ok(extra) if extra.is_empty() => None,
+            Ok(extra) => Some(format!("a second flush returned {} chunks", extra.len())),
+            Err(err) => Some(format!("a second flush failed: {err}")),
+        },
+    );
+    report.check(
+        "error_mapping",
+        match session.submit(frame(CONTRACT_FRAMES, RawFrameBuffer::Argb8888(vec![0; 4]))) {
+            Err(BackendError::InvalidInput(_)) => None,
+            Ok(()) => Some("a frame with a 4 byte buffer was accepted".to_string()),
+            Err(err) => Some(format!(
+                "a frame with a 4 byte buffer failed with {err}; expected InvalidInput"
+            )),
+        },
+    );
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_parses_vectors_and_rejects_bad_lines() {
+        let dir = std::env::temp_dir().join(format!("video-hw-conformance-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(CONFORMANCE_MANIFEST),
+            "# name\tcodec\tframes\tchecksum\n\nBA1_Sony_D.jsv\th264\t17\t00000000deadbeef\nAMP_A_Samsung.bit\thevc\t8\t-\n",
+        )
+        .unwrap();
+        let vectors = load_conformance_manifest(&dir).unwrap();
+        assert_eq!(vectors.len(), 2);
+        assert_eq!(vectors[0].path, dir.join("BA1_Sony_D.jsv"));
+        assert_eq!(vectors[0].expected_checksum, Some(0xdead_beef));
+        assert_eq!(
+            (vectors[1].codec, vectors[1].expected_frames),
+            (Codec::Hevc, 8)
+        );
+        let report = ConformanceReport {
+            results: vec![ConformanceResult {
+                vector: "AMP_A_Samsung.bit".to_string(),
+                frames: 8,
+                checksum: 0xabc,
+                verdict: ConformanceVerdict::Pass,
+            }],
+        };
+        assert_eq!(
+            report.to_manifest(&vectors[1..]),
+            "AMP_A_Samsung.bit\thevc\t8\t0000000000000abc\n"
+        );
+
+        fs::write(dir.join(CONFORMANCE_MANIFEST), "clip.264\tvp9\t1\t-\n").unwrap();
+        let err = load_conformance_manifest(&dir).unwrap_err();
+        assert!(err.to_string().contains(":1: invalid codec"), "{err}");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // One metadata frame per access unit, released in pts order once `delay` more have arrived.
+    struct FakeDecoder {
+        pending: Vec<Timestamp90k>,
+        delay: usize,
+        error: fn(String) -> BackendError,
+    }
+
+    impl SoftwareDecoder for FakeDecoder {
+        fn decode(
+            &mut self,
+            access_unit: &[u8],
+            pts_90k: Option<Timestamp90k>,
+        ) -> Result<Vec<DecodedFrame>, BackendError> {
+            if access_unit.ends_with(&[0xff]) {
+                return Err((self.error)("slice data overrun".to_string()));
+            }
+            self.pending.extend(pts_90k);
+            self.pending.sort_unstable();
+            let ready = self.pending.len().saturating_sub(self.delay);
+            Ok(self.pending.drain(..ready).map(frame).collect())
+        }
+
+        fn flush(&mut self) -> Result<Vec<DecodedFrame>, BackendError> {
+            Ok(self.pending.drain(..).map(frame).collect())
+        }
+    }
+
+    fn frame(pts: Timestamp90k) -> DecodedFrame {
+        DecodedFrame::test_metadata(None, Some(pts), None)
+    }
+
+    #[test]
+    fn contract_suite_reports_each_broken_rule() {
+        // I P B B in decode order; the B frames are presented before the P frame.
+        let samples = [0, 9000, 3000, 6000]
+            .map(|pts| (vec![0, 0, 0, 1, 0x41, 0x9a], Timestamp90k(pts)))
+            .to_vec();
+        let conforming = SoftwareDecoderFactory::new(|_| {
+            Ok(Box::new(FakeDecoder {
+                pending: Vec::new(),
+                delay: 2,
+                error: BackendError::InvalidBitstream,
+            }))
+        });
+        let report = check_software_decoder_contract(&conforming, Codec::H264, &samples);
+        assert!(report.all_passed(), "{report}");
+        assert_eq!(report.checks.len(), 4);
+
+        // Without reorder delay the B frames come out after the P frame, and decode errors are
+        // reported as generic backend failures.
+        let broken = SoftwareDecoderFactory::new(|_| {
+            Ok(Box::new(FakeDecoder {
+                pending: Vec::new(),
+                delay: 0,
+                error: BackendError::Backend,
+            }))
+        });
+        let report = check_software_decoder_contract(&broken, Codec::H264, &samples);
+        let failed = report
+            .failures()
+            .map(|check| check.name)
+            .collect::<Vec<_>>();
+        assert_eq!(failed, ["pts_monotonic", "error_mapping"]);
+        assert!(report.to_string().contains(
+            "[contract] software-decoder/h264 pts_monotonic FAIL pts Some(Timestamp90k(9000)) came out before Some(Timestamp90k(3000))"
+        ), "{report}");
+
+        let unsupported =
+            SoftwareDecoderFactory::new(|codec| Err(BackendError::UnsupportedCodec(codec)));
+        let report = check_software_decoder_contract(&unsupported, Codec::Hevc, &samples);
+        assert!(report.skipped.is_some() && report.all_passed());
+    }
+}
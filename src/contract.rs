@@ -1,11 +1,21 @@
 use std::num::NonZeroU32;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use std::{fmt, fmt::Display};
 
+use crate::host_sessions::describe_holders;
+use crate::reap_cancel::ReadyWaker;
+use crate::{
+    Backend, BackendPixelFormat, DeviceMemory, Diagnostics, HostSessionKind, PixelFormat,
+    SessionHolder, StereoView, UnsupportedConversion,
+};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Codec {
     H264,
     Hevc,
+    Mjpeg,
 }
 
 impl Display for Codec {
@@ -13,6 +23,7 @@ impl Display for Codec {
         match self {
             Self::H264 => f.write_str("h264"),
             Self::Hevc => f.write_str("hevc"),
+            Self::Mjpeg => f.write_str("mjpeg"),
         }
     }
 }
@@ -29,7 +40,77 @@ impl Display for Dimensions {
     }
 }
 
+// The coded picture is what the encoder actually produced (macroblock/CTU aligned); the SPS
+// cropping window selects the displayed region inside it.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameCrop {
+    pub coded: Dimensions,
+    pub display: Dimensions,
+    pub left: u32,
+    pub top: u32,
+}
+
+impl FrameCrop {
+    pub fn is_cropped(&self) -> bool {
+        self.coded != self.display
+    }
+}
+
+impl Display for FrameCrop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {}+{}+{}",
+            self.coded, self.display, self.left, self.top
+        )
+    }
+}
+
+// Stream property changes observed while decoding, reaped separately from the frames so
+// applications do not have to diff every DecodedFrame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamEvent {
+    ParameterSetsUpdated {
+        revision: u64,
+    },
+    ResolutionChanged {
+        from: Option<Dimensions>,
+        to: Dimensions,
+        crop: Option<FrameCrop>,
+    },
+    ColorMetadataChanged {
+        from: Option<ColorMetadata>,
+        to: ColorMetadata,
+    },
+    RecoveredAfterError {
+        pts_90k: Option<Timestamp90k>,
+    },
+}
+
+impl Display for StreamEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ParameterSetsUpdated { revision } => {
+                write!(f, "[stream.parameter_sets] revision={revision}")
+            }
+            Self::ResolutionChanged { from, to, .. } => match from {
+                Some(from) => write!(f, "[stream.resolution] {from} -> {to}"),
+                None => write!(f, "[stream.resolution] {to}"),
+            },
+            Self::ColorMetadataChanged { to, .. } => write!(
+                f,
+                "[stream.color] primaries={:?}, transfer={:?}, matrix={:?}",
+                to.color_primaries, to.transfer_function, to.ycbcr_matrix
+            ),
+            Self::RecoveredAfterError { pts_90k } => match pts_90k {
+                Some(pts) => write!(f, "[stream.recovered] pts_90k={}", pts.0),
+                None => f.write_str("[stream.recovered]"),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Timestamp90k(pub i64);
 
 impl Display for Timestamp90k {
@@ -61,7 +142,122 @@ pub enum RawFrameBuffer {
     Argb8888(Vec<u8>),
     Argb8888Shared(Arc<[u8]>),
     Nv12 { pitch: usize, data: Vec<u8> },
+    Nv12Shared { pitch: usize, data: Arc<[u8]> },
     Rgb24(Vec<u8>),
+    Rgb24Shared(Arc<[u8]>),
+}
+
+impl RawFrameBuffer {
+    pub fn data(&self) -> &[u8] {
+        match self {
+            Self::Argb8888(data) | Self::Nv12 { data, .. } | Self::Rgb24(data) => data,
+            Self::Argb8888Shared(data)
+            | Self::Nv12Shared { data, .. }
+            | Self::Rgb24Shared(data) => data,
+        }
+    }
+
+    // Shared buffers are copied into owned ones first, so other holders of the Arc keep the
+    // original pixels.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        let owned = match self {
+            Self::Argb8888Shared(data) => Some(Self::Argb8888(data.to_vec())),
+            Self::Nv12Shared { pitch, data } => Some(Self::Nv12 {
+                pitch: *pitch,
+                data: data.to_vec(),
+            }),
+            Self::Rgb24Shared(data) => Some(Self::Rgb24(data.to_vec())),
+            Self::Argb8888(_) | Self::Nv12 { .. } | Self::Rgb24(_) => None,
+        };
+        if let Some(owned) = owned {
+            *self = owned;
+        }
+        match self {
+            Self::Argb8888(data) | Self::Nv12 { data, .. } | Self::Rgb24(data) => data,
+            Self::Argb8888Shared(_) | Self::Nv12Shared { .. } | Self::Rgb24Shared(_) => {
+                unreachable!("shared buffers were copied above")
+            }
+        }
+    }
+
+    // Bytes a buffer of this format needs for a `dims` frame: packed rows for ARGB/RGB, and
+    // for NV12 `pitch` bytes per row of the luma plane plus the half-height interleaved chroma.
+    pub fn expected_len(&self, dims: Dimensions) -> usize {
+        self.plane_layout(dims).last().map_or(0, PlaneLayout::end)
+    }
+
+    // Checked on submit so a wrong stride or swapped width/height is reported with the layout
+    // that was expected instead of as a size mismatch inside the backend's flush.
+    pub fn validate(&self, dims: Dimensions) -> Result<(), BackendError> {
+        let (width, height) = (dims.width.get() as usize, dims.height.get() as usize);
+        let (format, plane_names): (_, &[_]) = match self {
+            Self::Argb8888(_) | Self::Argb8888Shared(_) => ("ARGB8888", &["packed"]),
+            Self::Rgb24(_) | Self::Rgb24Shared(_) => ("RGB24", &["packed"]),
+            Self::Nv12 { pitch, .. } | Self::Nv12Shared { pitch, .. } => {
+                if *pitch < width {
+                    return Err(BackendError::InvalidInput(format!(
+                        "NV12 pitch {pitch} is smaller than the frame width {width}"
+                    )));
+                }
+                ("NV12", &["Y", "UV"])
+            }
+        };
+        let len = self.data().len();
+        let expected = self.expected_len(dims);
+        if len == expected {
+            return Ok(());
+        }
+        let planes = self.plane_layout(dims);
+        let layout = planes
+            .iter()
+            .zip(plane_names)
+            .map(|(plane, name)| {
+                format!(
+                    "{name}: {} rows of {} bytes at offset {}",
+                    plane.rows, plane.stride, plane.offset
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut message =
+            format!("{format} buffer for {dims} has {len} bytes, expected {expected} ({layout})");
+        let swapped = Dimensions {
+            width: dims.height,
+            height: dims.width,
+        };
+        let rows = planes.iter().map(|plane| plane.rows).sum::<usize>();
+        if width != height && len == self.expected_len(swapped) {
+            message.push_str(&format!(
+                "; that fits {swapped}, are width and height swapped?"
+            ));
+        } else if len.is_multiple_of(rows) {
+            message.push_str(&format!(
+                "; that is {} bytes per row instead of {}",
+                len / rows,
+                planes[0].stride
+            ));
+        }
+        Err(BackendError::InvalidInput(message))
+    }
+
+    fn plane_layout(&self, dims: Dimensions) -> Vec<PlaneLayout> {
+        let (width, height) = (dims.width.get() as usize, dims.height.get() as usize);
+        match self {
+            Self::Argb8888(_) | Self::Argb8888Shared(_) => PlaneLayout::packed(width * 4, height),
+            Self::Rgb24(_) | Self::Rgb24Shared(_) => PlaneLayout::packed(width * 3, height),
+            Self::Nv12 { pitch, .. } | Self::Nv12Shared { pitch, .. } => {
+                PlaneLayout::nv12(*pitch, height)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
 }
 
 #[derive(Debug, Clone)]
@@ -70,6 +266,24 @@ pub struct EncodeFrame {
     pub pts_90k: Option<Timestamp90k>,
     pub buffer: RawFrameBuffer,
     pub force_keyframe: bool,
+    // Regions that changed since the previous frame, None when unknown. NVENC rewrites only the
+    // rows they touch in its reused input buffers; VideoToolbox takes the whole frame.
+    pub dirty_rects: Option<Vec<DirtyRect>>,
+    // Further frame intervals the content stays on screen (variable frame rate input such as a
+    // static screen share); see FrameRepeatMode.
+    pub repeat_count: u32,
+}
+
+// How EncodeFrame::repeat_count is encoded. Resubmit keeps the output at the configured rate:
+// each repeat is submitted again one interval later, sharing the pixels and with empty dirty
+// rects so NVENC skips the upload. It is still encoded as an ordinary frame; no skip picture is
+// signalled, the encoder only finds nothing to code. Hold encodes the frame once and leaves the
+// gap to the next frame's timestamp, for containers that carry per-sample durations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameRepeatMode {
+    #[default]
+    Resubmit,
+    Hold,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -95,9 +309,99 @@ impl Display for EncodedLayout {
 pub struct EncodedChunk {
     pub codec: Codec,
     pub layout: EncodedLayout,
-    pub data: Vec<u8>,
+    // Shared so fanning a chunk out to several consumers (muxer, network) only bumps a count.
+    pub data: Arc<[u8]>,
     pub pts_90k: Option<Timestamp90k>,
     pub is_keyframe: bool,
+    // Bytes of filler NAL units appended by EncoderConfig::cbr_filler, included in data.
+    pub filler_bytes: usize,
+    // Set by EncodeSession for chunks with a pts: chunks come out in decode order with
+    // strictly increasing DTS, and display_index is the frame's position in submit
+    // (presentation) order, so pts - dts is the composition offset a muxer writes to ctts.
+    pub dts_90k: Option<Timestamp90k>,
+    pub display_index: Option<u64>,
+}
+
+impl EncodedChunk {
+    // Copies the payload out; the data is shared with every clone of this chunk.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.data.to_vec()
+    }
+
+    pub fn composition_offset_90k(&self) -> Option<i64> {
+        Some(self.pts_90k?.0 - self.dts_90k?.0)
+    }
+
+    pub fn nal_units(&self) -> Result<Vec<NalUnit<'_>>, BackendError> {
+        let payloads = match self.layout {
+            EncodedLayout::AnnexB => crate::split_annexb_nal_units(&self.data),
+            EncodedLayout::Avcc | EncodedLayout::Hvcc => {
+                crate::split_length_prefixed_nal_units(&self.data)?
+            }
+            EncodedLayout::Opaque => {
+                return Err(BackendError::UnsupportedConfig(
+                    "opaque encoded chunks cannot be split into NAL units".to_string(),
+                ));
+            }
+        };
+        Ok(payloads
+            .into_iter()
+            .filter_map(|data| {
+                Some(NalUnit {
+                    nal_type: crate::nal_type(self.codec, data)?,
+                    data,
+                })
+            })
+            .collect())
+    }
+
+    // The MV-HEVC views this chunk has slices for, in layer order; empty for other codecs.
+    pub fn stereo_views(&self) -> Result<Vec<StereoView>, BackendError> {
+        let mut views = Vec::new();
+        if self.codec != Codec::Hevc {
+            return Ok(views);
+        }
+        for nal in self.nal_units()? {
+            if nal.nal_type > 31 {
+                continue;
+            }
+            if let Some(view) = StereoView::from_layer_id(crate::multiview::hevc_layer_id(nal.data))
+                && !views.contains(&view)
+            {
+                views.push(view);
+            }
+        }
+        views.sort_by_key(|view| view.layer_id());
+        Ok(views)
+    }
+}
+
+// Feeds an encoder's output straight into a DecodeSession, for round-trip tests and local
+// loopback previews. Length-prefixed layouts become a sample, everything else a chunk (an
+// MJPEG image is passed through as is). VideoToolbox keeps the parameter sets in its format
+// description rather than in the chunks, so its avcc/hvcc output only decodes once SPS/PPS
+// have reached the decoder some other way.
+impl From<EncodedChunk> for BitstreamInput {
+    fn from(chunk: EncodedChunk) -> Self {
+        let pts_90k = chunk.pts_90k;
+        match chunk.layout {
+            EncodedLayout::Avcc | EncodedLayout::Hvcc => Self::LengthPrefixedSample {
+                codec: chunk.codec,
+                sample: chunk.into_vec(),
+                pts_90k,
+            },
+            EncodedLayout::AnnexB | EncodedLayout::Opaque => Self::AnnexBChunk {
+                chunk: chunk.into_vec(),
+                pts_90k,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NalUnit<'a> {
+    pub nal_type: u8,
+    pub data: &'a [u8],
 }
 
 #[derive(Debug, Clone)]
@@ -105,23 +409,157 @@ pub enum DecodedFrame {
     Metadata {
         dims: Option<Dimensions>,
         pts_90k: Option<Timestamp90k>,
-        pixel_format: Option<u32>,
-        decode_info_flags: Option<u32>,
+        pixel_format: Option<BackendPixelFormat>,
+        decode_info_flags: Option<DecodeInfoFlags>,
         color: Option<ColorMetadata>,
+        planes: Option<Vec<PlaneLayout>>,
+        // 256-bin luma histogram computed by the decoder itself (NVDEC with
+        // NvidiaDecoderOptions::histogram on supporting GPUs). Boxed to keep DecodedFrame small.
+        histogram: Option<Box<[u32; 256]>>,
+        // Which eye of an MV-HEVC stream this is, with DecoderConfig::stereo_views.
+        view: Option<StereoView>,
     },
     Nv12 {
         dims: Dimensions,
         pitch: usize,
         pts_90k: Option<Timestamp90k>,
+        planes: Vec<PlaneLayout>,
         data: Vec<u8>,
     },
     Rgb24 {
         dims: Dimensions,
         pts_90k: Option<Timestamp90k>,
+        planes: Vec<PlaneLayout>,
         data: Vec<u8>,
     },
 }
 
+impl DecodedFrame {
+    pub fn pts_90k(&self) -> Option<Timestamp90k> {
+        match self {
+            Self::Metadata { pts_90k, .. }
+            | Self::Nv12 { pts_90k, .. }
+            | Self::Rgb24 { pts_90k, .. } => *pts_90k,
+        }
+    }
+
+    pub fn dims(&self) -> Option<Dimensions> {
+        match self {
+            Self::Metadata { dims, .. } => *dims,
+            Self::Nv12 { dims, .. } | Self::Rgb24 { dims, .. } => Some(*dims),
+        }
+    }
+
+    // Frames carrying pixels imply their format.
+    pub fn pixel_format(&self) -> Option<PixelFormat> {
+        match self {
+            Self::Metadata { pixel_format, .. } => pixel_format.map(BackendPixelFormat::format),
+            Self::Nv12 { .. } => Some(PixelFormat::Nv12),
+            Self::Rgb24 { .. } => Some(PixelFormat::Rgb8),
+        }
+    }
+
+    // The code the decoder reported, only known for metadata frames straight off a backend.
+    pub fn backend_pixel_format(&self) -> Option<BackendPixelFormat> {
+        match self {
+            Self::Metadata { pixel_format, .. } => *pixel_format,
+            Self::Nv12 { .. } | Self::Rgb24 { .. } => None,
+        }
+    }
+
+    pub fn histogram(&self) -> Option<&[u32; 256]> {
+        match self {
+            Self::Metadata { histogram, .. } => histogram.as_deref(),
+            Self::Nv12 { .. } | Self::Rgb24 { .. } => None,
+        }
+    }
+
+    pub fn planes(&self) -> Option<&[PlaneLayout]> {
+        match self {
+            Self::Metadata { planes, .. } => planes.as_deref(),
+            Self::Nv12 { planes, .. } | Self::Rgb24 { planes, .. } => Some(planes),
+        }
+    }
+
+    pub fn view(&self) -> Option<StereoView> {
+        match self {
+            Self::Metadata { view, .. } => *view,
+            Self::Nv12 { .. } | Self::Rgb24 { .. } => None,
+        }
+    }
+}
+
+// Metadata frames for tests of code that only reads the frame's metadata; every other field
+// is None.
+#[cfg(test)]
+impl DecodedFrame {
+    pub(crate) fn test_metadata(
+        dims: Option<Dimensions>,
+        pts_90k: Option<Timestamp90k>,
+        decode_info_flags: Option<DecodeInfoFlags>,
+    ) -> Self {
+        Self::Metadata {
+            dims,
+            pts_90k,
+            pixel_format: None,
+            decode_info_flags,
+            color: None,
+            planes: None,
+            histogram: None,
+            view: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlaneLayout {
+    pub offset: usize,
+    pub stride: usize,
+    pub rows: usize,
+}
+
+impl PlaneLayout {
+    pub fn nv12(pitch: usize, height: usize) -> Vec<PlaneLayout> {
+        vec![
+            PlaneLayout {
+                offset: 0,
+                stride: pitch,
+                rows: height,
+            },
+            PlaneLayout {
+                offset: pitch * height,
+                stride: pitch,
+                rows: height.div_ceil(2),
+            },
+        ]
+    }
+
+    pub fn packed(stride: usize, rows: usize) -> Vec<PlaneLayout> {
+        vec![PlaneLayout {
+            offset: 0,
+            stride,
+            rows,
+        }]
+    }
+
+    pub fn end(&self) -> usize {
+        self.offset + self.stride * self.rows
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct DecodeInfoFlags: u32 {
+        const ASYNCHRONOUS = 1 << 0;
+        const FRAME_DROPPED = 1 << 1;
+        const IMAGE_BUFFER_MODIFIABLE = 1 << 2;
+        const CORRUPTED = 1 << 3;
+        const REPEAT_FIRST_FIELD = 1 << 4;
+        // Not decoded: a stand-in for a lost access unit; hold the previous image.
+        const FREEZE_FRAME = 1 << 5;
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ColorMetadata {
     pub color_primaries: Option<i32>,
@@ -133,12 +571,14 @@ pub struct ColorMetadata {
 pub(crate) struct Frame {
     pub width: usize,
     pub height: usize,
-    pub pixel_format: Option<u32>,
+    pub pixel_format: Option<BackendPixelFormat>,
     pub pts_90k: Option<i64>,
-    pub decode_info_flags: Option<u32>,
+    pub decode_info_flags: Option<DecodeInfoFlags>,
     pub color_primaries: Option<i32>,
     pub transfer_function: Option<i32>,
     pub ycbcr_matrix: Option<i32>,
+    pub planes: Option<Vec<PlaneLayout>>,
+    pub view: Option<StereoView>,
     #[cfg(any(
         all(target_os = "macos", feature = "backend-vt"),
         all(
@@ -146,7 +586,17 @@ pub(crate) struct Frame {
             any(target_os = "linux", target_os = "windows")
         )
     ))]
-    pub argb: Option<Vec<u8>>,
+    pub argb: Option<FramePixels>,
+    // Right-eye pixels of a stereo pair submitted with EncodeSession::submit_stereo; `argb`
+    // holds the left eye.
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    pub stereo_right: Option<FramePixels>,
     #[cfg(any(
         all(target_os = "macos", feature = "backend-vt"),
         all(
@@ -155,24 +605,221 @@ pub(crate) struct Frame {
         )
     ))]
     pub force_keyframe: bool,
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    pub dirty_rects: Option<Vec<DirtyRect>>,
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    pub luma_histogram: Option<Box<[u32; 256]>>,
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+// Pixel payload handed to the encoders. Shared capture buffers are passed through as-is, so
+// neither submission nor fallback replay copies them.
+#[derive(Debug, Clone)]
+pub(crate) enum FramePixels {
+    Owned(Vec<u8>),
+    Shared(Arc<[u8]>),
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+impl std::ops::Deref for FramePixels {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Owned(data) => data,
+            Self::Shared(data) => data,
+        }
+    }
+}
+
+// Controls what a session may switch to when its backend fails on first use. The default keeps
+// the historical behaviour of sticking with the selected hardware backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FallbackPolicy {
+    pub prefer_hardware: bool,
+    pub allow_software: bool,
+    pub allow_cross_vendor: bool,
+}
+
+impl FallbackPolicy {
+    #[must_use]
+    pub fn hardware_only() -> Self {
+        Self {
+            prefer_hardware: true,
+            allow_software: false,
+            allow_cross_vendor: false,
+        }
+    }
+
+    #[must_use]
+    pub fn permissive() -> Self {
+        Self {
+            prefer_hardware: true,
+            allow_software: true,
+            allow_cross_vendor: true,
+        }
+    }
+}
+
+impl Default for FallbackPolicy {
+    fn default() -> Self {
+        Self::hardware_only()
+    }
+}
+
+impl Display for FallbackPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "prefer_hardware={}/allow_software={}/allow_cross_vendor={}",
+            self.prefer_hardware, self.allow_software, self.allow_cross_vendor
+        )
+    }
+}
+
+// Frames per second as num/den so NTSC rates (30000/1001, 24000/1001) are exact. A zero
+// numerator or denominator means "unknown" and falls back to 30 fps wherever a rate is needed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRate {
+    pub num: u32,
+    pub den: u32,
+}
+
+impl FrameRate {
+    pub const NTSC_23_976: Self = Self::new(24_000, 1001);
+    pub const NTSC_29_97: Self = Self::new(30_000, 1001);
+    pub const NTSC_59_94: Self = Self::new(60_000, 1001);
+
+    pub const fn new(num: u32, den: u32) -> Self {
+        Self { num, den }
+    }
+
+    pub fn is_known(&self) -> bool {
+        self.num > 0 && self.den > 0
+    }
+
+    pub fn normalized(self) -> Self {
+        if self.is_known() {
+            self
+        } else {
+            Self::new(30, 1)
+        }
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        let rate = self.normalized();
+        f64::from(rate.num) / f64::from(rate.den)
+    }
+
+    // Nearest whole rate for APIs and heuristics that only take integers.
+    pub fn rounded(&self) -> u32 {
+        let rate = self.normalized();
+        ((rate.num + rate.den / 2) / rate.den).max(1)
+    }
+
+    pub fn frame_duration_ms(&self) -> f64 {
+        1_000.0 / self.as_f64()
+    }
+
+    // Timestamps are derived from the frame index rather than by accumulating a rounded step,
+    // so 29.97 fps stays exact instead of drifting by 0.03 ticks per frame.
+    pub fn pts_90k(&self, frame_index: i64) -> i64 {
+        let rate = self.normalized();
+        let ticks = i128::from(frame_index) * 90_000 * i128::from(rate.den) / i128::from(rate.num);
+        i64::try_from(ticks).unwrap_or(if ticks < 0 { i64::MIN } else { i64::MAX })
+    }
+}
+
+impl From<i32> for FrameRate {
+    fn from(fps: i32) -> Self {
+        Self::new(u32::try_from(fps).unwrap_or(0), 1)
+    }
+}
+
+impl Display for FrameRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+impl FromStr for FrameRate {
+    type Err = BackendError;
+
+    // Accepts "30" or "30000/1001".
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || BackendError::InvalidInput(format!("invalid frame rate: {value}"));
+        let (num, den) = value.split_once('/').unwrap_or((value, "1"));
+        let rate = Self::new(
+            num.trim().parse().map_err(|_| invalid())?,
+            den.trim().parse().map_err(|_| invalid())?,
+        );
+        if rate.is_known() {
+            Ok(rate)
+        } else {
+            Err(invalid())
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct DecoderConfig {
     pub codec: Codec,
-    pub fps: i32,
+    pub fps: FrameRate,
     pub require_hardware: bool,
+    pub force_software: bool,
+    pub fallback_policy: FallbackPolicy,
     pub backend_options: BackendDecoderOptions,
+    // SVC-T receivers: pictures above this temporal_id are dropped before decode.
+    pub max_temporal_id: Option<u8>,
+    // Decoder-side scaling for previews: NVDEC and VideoToolbox write frames at this size (NVDEC
+    // only downscales and rounds to even), so full-resolution surfaces are never produced.
+    pub output_scale: Option<Dimensions>,
+    // MV-HEVC: decode both views and tag every frame with DecodedFrame::view (VideoToolbox on
+    // macOS 14+, see mv_hevc_support). Off, only the base layer (left eye) is decoded.
+    pub stereo_views: bool,
 }
 
 impl DecoderConfig {
     #[must_use]
-    pub fn new(codec: Codec, fps: i32, require_hardware: bool) -> Self {
+    pub fn new(codec: Codec, fps: impl Into<FrameRate>, require_hardware: bool) -> Self {
         Self {
             codec,
-            fps,
+            fps: fps.into(),
             require_hardware,
+            force_software: false,
+            fallback_policy: FallbackPolicy::default(),
             backend_options: BackendDecoderOptions::default(),
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
         }
     }
 }
@@ -181,8 +828,8 @@ impl Display for DecoderConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "DecoderConfig(codec={}, fps={}, require_hardware={})",
-            self.codec, self.fps, self.require_hardware
+            "DecoderConfig(codec={}, fps={}, require_hardware={}, force_software={}, fallback={})",
+            self.codec, self.fps, self.require_hardware, self.force_software, self.fallback_policy
         )
     }
 }
@@ -190,19 +837,55 @@ impl Display for DecoderConfig {
 #[derive(Debug, Clone)]
 pub struct EncoderConfig {
     pub codec: Codec,
-    pub fps: i32,
+    pub fps: FrameRate,
     pub require_hardware: bool,
+    pub fallback_policy: FallbackPolicy,
     pub backend_options: BackendEncoderOptions,
+    pub repeat_mode: FrameRepeatMode,
+    // Every Nth frame counted from the first one submitted is forced to a keyframe by the
+    // session, and N becomes the backend's own maximum GOP length (NVENC gopLength, VT
+    // MaxKeyFrameInterval), so both backends cut GOPs at the same frames.
+    pub keyframe_interval: Option<NonZeroU32>,
+    // Average bitrate for the backend's rate control (NVENC averageBitRate, VT AverageBitRate);
+    // None keeps the preset's own target.
+    pub target_bitrate_bps: Option<u64>,
+    // Highest QP any frame may use (NVENC maxQP, VT MaxAllowedFrameQP); None leaves it to the
+    // rate control.
+    pub max_qp: Option<u32>,
+    // Pad every chunk with filler NAL units so the stream holds exactly target_bitrate_bps
+    // (which must be set); H.264 and HEVC only.
+    pub cbr_filler: bool,
+    pub latency_tune: LatencyTune,
+}
+
+// Throughput lets the session batch frames before handing them to the encoder (see
+// EncodeSession::encode_iter) and keeps the backend options as given. ZeroLatency encodes and
+// reaps every frame inside its submit, so its chunk is ready when submit returns, and
+// overrides the options that hold frames back: NVENC gets one output in flight, no lookahead,
+// no B-frames and no pre-encode pipeline; VideoToolbox gets RealTime,
+// PrioritizeEncodingSpeedOverQuality and no frame reordering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LatencyTune {
+    #[default]
+    Throughput,
+    ZeroLatency,
 }
 
 impl EncoderConfig {
     #[must_use]
-    pub fn new(codec: Codec, fps: i32, require_hardware: bool) -> Self {
+    pub fn new(codec: Codec, fps: impl Into<FrameRate>, require_hardware: bool) -> Self {
         Self {
             codec,
-            fps,
+            fps: fps.into(),
             require_hardware,
+            fallback_policy: FallbackPolicy::default(),
             backend_options: BackendEncoderOptions::default(),
+            repeat_mode: FrameRepeatMode::default(),
+            keyframe_interval: None,
+            target_bitrate_bps: None,
+            max_qp: None,
+            cbr_filler: false,
+            latency_tune: LatencyTune::default(),
         }
     }
 }
@@ -211,12 +894,152 @@ impl Display for EncoderConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "EncoderConfig(codec={}, fps={}, require_hardware={})",
-            self.codec, self.fps, self.require_hardware
+            "EncoderConfig(codec={}, fps={}, require_hardware={}, fallback={})",
+            self.codec, self.fps, self.require_hardware, self.fallback_policy
         )
     }
 }
 
+impl EncoderConfig {
+    // Adjusts the NVENC options in place, starting from defaults when the config carries no
+    // NVENC options yet (options for another backend are replaced).
+    #[must_use]
+    pub fn with_nvidia_options(mut self, tweak: impl FnOnce(&mut NvidiaEncoderOptions)) -> Self {
+        let mut options = match self.backend_options {
+            BackendEncoderOptions::Nvidia(options) => options,
+            _ => NvidiaEncoderOptions::default(),
+        };
+        tweak(&mut options);
+        self.backend_options = BackendEncoderOptions::Nvidia(options);
+        self
+    }
+
+    #[must_use]
+    pub fn with_vt_options(mut self, tweak: impl FnOnce(&mut VtEncoderOptions)) -> Self {
+        let mut options = match self.backend_options {
+            BackendEncoderOptions::VideoToolbox(options) => options,
+            _ => VtEncoderOptions::default(),
+        };
+        tweak(&mut options);
+        self.backend_options = BackendEncoderOptions::VideoToolbox(options);
+        self
+    }
+}
+
+// Named option bundles for the scenarios we keep getting asked about. The name round-trips
+// through Display/FromStr so a profile can be stored in a config file or passed on a CLI, and
+// the expanded config is a plain EncoderConfig that can still be tweaked field by field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    // Interactive streaming: no B-frames, no lookahead, one output in flight, 2 s GOP.
+    LowLatencyStreaming,
+    // Offline recording: B-frames, lookahead and AQ, 5 s GOP, software fallback allowed.
+    ArchiveQuality,
+    // Desktop capture: screen content tuning, no B-frames, long GOP (recover with
+    // force_keyframe instead of periodic IDRs).
+    ScreenShare,
+}
+
+impl Profile {
+    pub const ALL: [Self; 3] = [
+        Self::LowLatencyStreaming,
+        Self::ArchiveQuality,
+        Self::ScreenShare,
+    ];
+
+    // Backend options follow the build target: VideoToolbox on macOS, NVENC elsewhere. Use
+    // nvidia_options/vt_options to pick explicitly.
+    #[must_use]
+    pub fn encoder_config(self, codec: Codec, fps: impl Into<FrameRate>) -> EncoderConfig {
+        let fps = fps.into();
+        let mut config = EncoderConfig::new(codec, fps, true);
+        if self == Self::ArchiveQuality {
+            config.fallback_policy = FallbackPolicy::permissive();
+        }
+        config.backend_options = if cfg!(target_os = "macos") {
+            BackendEncoderOptions::VideoToolbox(self.vt_options())
+        } else {
+            BackendEncoderOptions::Nvidia(self.nvidia_options(fps))
+        };
+        config
+    }
+
+    #[must_use]
+    pub fn nvidia_options(self, fps: FrameRate) -> NvidiaEncoderOptions {
+        let fps = fps.rounded();
+        match self {
+            Self::LowLatencyStreaming => NvidiaEncoderOptions {
+                max_in_flight_outputs: 1,
+                gop_length: Some(fps * 2),
+                frame_interval_p: Some(1),
+                enable_pipeline_scheduler: Some(false),
+                content_hint: Some(ContentHint::Camera),
+                spatial_aq: Some(true),
+                temporal_aq: Some(false),
+                lookahead_depth: Some(0),
+                ..NvidiaEncoderOptions::default()
+            },
+            Self::ArchiveQuality => NvidiaEncoderOptions {
+                max_in_flight_outputs: 8,
+                gop_length: Some(fps * 5),
+                frame_interval_p: Some(3),
+                enable_pipeline_scheduler: Some(true),
+                content_hint: Some(ContentHint::Camera),
+                spatial_aq: Some(true),
+                temporal_aq: Some(true),
+                aq_strength: Some(8),
+                lookahead_depth: Some(16),
+                ..NvidiaEncoderOptions::default()
+            },
+            Self::ScreenShare => NvidiaEncoderOptions {
+                max_in_flight_outputs: 2,
+                gop_length: Some(fps * 10),
+                frame_interval_p: Some(1),
+                enable_pipeline_scheduler: Some(false),
+                content_hint: Some(ContentHint::Screen),
+                spatial_aq: Some(false),
+                temporal_aq: Some(false),
+                lookahead_depth: Some(0),
+                ..NvidiaEncoderOptions::default()
+            },
+        }
+    }
+
+    #[must_use]
+    pub fn vt_options(self) -> VtEncoderOptions {
+        let quality = match self {
+            Self::LowLatencyStreaming => 0.5,
+            Self::ArchiveQuality => 0.9,
+            Self::ScreenShare => 0.75,
+        };
+        VtEncoderOptions {
+            quality: Some(quality),
+            ..VtEncoderOptions::default()
+        }
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LowLatencyStreaming => f.write_str("low-latency-streaming"),
+            Self::ArchiveQuality => f.write_str("archive-quality"),
+            Self::ScreenShare => f.write_str("screen-share"),
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = BackendError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|profile| profile.to_string() == value.trim())
+            .ok_or_else(|| BackendError::InvalidInput(format!("unknown profile: {value}")))
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub enum BackendDecoderOptions {
     #[default]
@@ -229,22 +1052,224 @@ pub enum BackendEncoderOptions {
     #[default]
     Default,
     Nvidia(NvidiaEncoderOptions),
+    VideoToolbox(VtEncoderOptions),
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct NvidiaDecoderOptions {
     pub report_metrics: Option<bool>,
+    pub software_decoder: Option<SoftwareDecoderFactory>,
+    // Raised to the stream's minimum when lower. Unset counts are sized from the coded size,
+    // the reference frames in the SPS and the frame rate; see DecodeSession::surface_pool.
+    pub decode_surfaces: Option<u32>,
+    pub output_surfaces: Option<u32>,
+    // Low latency (the default) hands each picture out as soon as it is decoded; disabling it
+    // lets the parser hold one picture back so NVDEC can overlap decode and display.
+    pub low_latency: Option<bool>,
+    // Has NVDEC compute a luma histogram per picture (DecodedFrame::histogram). GPUs without
+    // histogram support decode normally and report None.
+    pub histogram: Option<bool>,
+    // For streams sent one slice per packet: each submitted chunk must end on a NAL boundary,
+    // and its slices go to the NVDEC parser right away instead of waiting in the access unit
+    // assembler. A chunk whose last NAL unit is an AUD, end of sequence or end of stream
+    // finishes its picture, which is decoded at once rather than when the next picture
+    // arrives; that is where the frame time is saved. H.264/HEVC only; ignored for MJPEG and
+    // software decoding.
+    pub slice_fast_start: Option<bool>,
 }
 
-#[derive(Debug, Clone)]
+pub trait SoftwareDecoder: Send {
+    fn decode(
+        &mut self,
+        access_unit: &[u8],
+        pts_90k: Option<Timestamp90k>,
+    ) -> Result<Vec<DecodedFrame>, BackendError>;
+
+    fn flush(&mut self) -> Result<Vec<DecodedFrame>, BackendError>;
+}
+
+type SoftwareDecoderFn =
+    dyn Fn(Codec) -> Result<Box<dyn SoftwareDecoder>, BackendError> + Send + Sync;
+
+#[derive(Clone)]
+pub struct SoftwareDecoderFactory(Arc<SoftwareDecoderFn>);
+
+impl SoftwareDecoderFactory {
+    pub fn new(
+        factory: impl Fn(Codec) -> Result<Box<dyn SoftwareDecoder>, BackendError>
+        + Send
+        + Sync
+        + 'static,
+    ) -> Self {
+        Self(Arc::new(factory))
+    }
+
+    pub fn create(&self, codec: Codec) -> Result<Box<dyn SoftwareDecoder>, BackendError> {
+        (self.0)(codec)
+    }
+}
+
+impl fmt::Debug for SoftwareDecoderFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SoftwareDecoderFactory(..)")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct NvidiaEncoderOptions {
     pub max_in_flight_outputs: usize,
+    // Input/output buffer pairs allocated with the session, raised to what the GOP and
+    // lookahead hold. Unset sizes the pool from the resolution, frame rate and
+    // max_in_flight_outputs; see EncodeSession::surface_pool.
+    pub surface_pool_size: Option<usize>,
     pub gop_length: Option<u32>,
     pub frame_interval_p: Option<i32>,
     pub report_metrics: Option<bool>,
-    pub safe_lifetime_mode: Option<bool>,
+    pub buffer_lifetime_mode: Option<NvBufferLifetimeMode>,
     pub enable_pipeline_scheduler: Option<bool>,
     pub pipeline_queue_capacity: Option<usize>,
+    pub content_hint: Option<ContentHint>,
+    pub spatial_aq: Option<bool>,
+    pub temporal_aq: Option<bool>,
+    pub aq_strength: Option<u8>,
+    pub lookahead_depth: Option<u32>,
+    pub average_bitrate_bps: Option<u64>,
+    pub max_qp: Option<u32>,
+    // Raw SDK escape hatch, called on every session start and reconfigure after the typed
+    // options have been applied. Whatever it sets is passed to NVENC unchecked.
+    #[cfg(all(
+        feature = "unsafe-options",
+        any(target_os = "linux", target_os = "windows")
+    ))]
+    pub customize_init_params: Option<NvInitParamsHook>,
+}
+
+// Hooks compare by name rather than by fn pointer, whose address is not unique, so a config
+// diff sees a changed hook only when the name changes.
+#[cfg(all(
+    feature = "unsafe-options",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[derive(Clone, Copy)]
+pub struct NvInitParamsHook {
+    pub name: &'static str,
+    pub apply: fn(
+        &mut nvidia_video_codec_sdk::sys::nvEncodeAPI::NV_ENC_INITIALIZE_PARAMS,
+        &mut nvidia_video_codec_sdk::sys::nvEncodeAPI::NV_ENC_CONFIG,
+    ),
+}
+
+#[cfg(all(
+    feature = "unsafe-options",
+    any(target_os = "linux", target_os = "windows")
+))]
+impl PartialEq for NvInitParamsHook {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+#[cfg(all(
+    feature = "unsafe-options",
+    any(target_os = "linux", target_os = "windows")
+))]
+impl fmt::Debug for NvInitParamsHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NvInitParamsHook")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VtEncoderOptions {
+    pub quality: Option<f32>,
+    // Defaults to two seconds of frames.
+    pub max_keyframe_interval: Option<u32>,
+    pub average_bitrate_bps: Option<u64>,
+    pub max_qp: Option<u32>,
+    // RealTime defaults to off (as fast as possible, fine for offline encodes) and frame
+    // reordering to on for the codecs that have B-frames.
+    pub real_time: Option<bool>,
+    pub prioritize_speed: Option<bool>,
+    pub allow_frame_reordering: Option<bool>,
+    // Escape hatch for compression properties the fields above do not model. Keys are the
+    // property names without the kVTCompressionPropertyKey_ prefix (e.g.
+    // "PrioritizeEncodingSpeedOverQuality"); they are set after the typed options at session
+    // creation, so they win on conflicts.
+    pub extra_properties: Vec<(String, VtPropertyValue)>,
+    // Pins the session to one encoder from list_video_toolbox_encoders (its encoder_id), e.g.
+    // an Afterburner card instead of the SoC media engine. Session creation fails if that
+    // encoder cannot take the codec or size.
+    pub encoder_id: Option<String>,
+}
+
+// One entry of VTCopyVideoEncoderList.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VtEncoderInfo {
+    pub encoder_id: String,
+    // None for codec types this crate does not encode; codec_type keeps the FourCC.
+    pub codec: Option<Codec>,
+    pub codec_type: u32,
+    pub name: String,
+    pub display_name: String,
+    pub hardware_accelerated: bool,
+    // IORegistry id of the device behind the encoder (macOS 14+), which tells otherwise
+    // identical encoders on different devices apart.
+    pub gpu_registry_id: Option<u64>,
+}
+
+impl Display for VtEncoderInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}, {})",
+            self.display_name,
+            self.encoder_id,
+            if self.hardware_accelerated {
+                "hardware"
+            } else {
+                "software"
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VtPropertyValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl Display for VtPropertyValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(value) => write!(f, "{value}"),
+            Self::Int(value) => write!(f, "{value}"),
+            Self::Float(value) => write!(f, "{value}"),
+            Self::String(value) => write!(f, "{value:?}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContentHint {
+    #[default]
+    Camera,
+    Screen,
+    Animation,
+}
+
+impl Display for ContentHint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Camera => f.write_str("camera"),
+            Self::Screen => f.write_str("screen"),
+            Self::Animation => f.write_str("animation"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -264,19 +1289,34 @@ impl Display for SessionSwitchMode {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NvBufferLifetimeMode {
+    ReusablePoolUnsafe,
+    PerFrameSafe,
+}
+
 #[derive(Debug, Clone)]
 pub struct NvidiaSessionConfig {
     pub gop_length: Option<u32>,
     pub frame_interval_p: Option<i32>,
     pub force_idr_on_activate: bool,
+    pub buffer_lifetime_mode: Option<NvBufferLifetimeMode>,
+    // Rate control retargeted on the live session; None keeps the current value.
+    pub average_bitrate_bps: Option<u64>,
+    pub max_qp: Option<u32>,
 }
 
 impl Display for NvidiaSessionConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "NvidiaSessionConfig(gop_length={:?}, frame_interval_p={:?}, force_idr_on_activate={})",
-            self.gop_length, self.frame_interval_p, self.force_idr_on_activate
+            "NvidiaSessionConfig(gop_length={:?}, frame_interval_p={:?}, force_idr_on_activate={}, buffer_lifetime_mode={:?}, average_bitrate_bps={:?}, max_qp={:?})",
+            self.gop_length,
+            self.frame_interval_p,
+            self.force_idr_on_activate,
+            self.buffer_lifetime_mode,
+            self.average_bitrate_bps,
+            self.max_qp
         )
     }
 }
@@ -284,14 +1324,20 @@ impl Display for NvidiaSessionConfig {
 #[derive(Debug, Clone)]
 pub struct VtSessionConfig {
     pub force_keyframe_on_activate: bool,
+    // Applied to the live compression session; None keeps the current quality.
+    pub quality: Option<f32>,
+    // Same for the rate control properties. With Immediate and no quality or keyframe
+    // request, these are set on the running session without recreating it.
+    pub average_bitrate_bps: Option<u64>,
+    pub max_qp: Option<u32>,
 }
 
 impl Display for VtSessionConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "VtSessionConfig(force_keyframe_on_activate={})",
-            self.force_keyframe_on_activate
+            "VtSessionConfig(force_keyframe_on_activate={}, quality={:?}, average_bitrate_bps={:?}, max_qp={:?})",
+            self.force_keyframe_on_activate, self.quality, self.average_bitrate_bps, self.max_qp
         )
     }
 }
@@ -306,6 +1352,12 @@ pub enum SessionSwitchRequest {
         config: VtSessionConfig,
         mode: SessionSwitchMode,
     },
+    // Mid-stream resolution change (e.g. ABR downscaling); frames submitted afterwards must
+    // have `dims`. Frames already queued are encoded at the old size first, and the new size
+    // opens with an IDR under a new generation.
+    Resize {
+        dims: Dimensions,
+    },
 }
 
 impl Display for SessionSwitchRequest {
@@ -321,6 +1373,13 @@ impl Display for SessionSwitchRequest {
                     config, mode
                 )
             }
+            Self::Resize { dims } => {
+                write!(
+                    f,
+                    "SessionSwitchRequest::Resize({}x{})",
+                    dims.width, dims.height
+                )
+            }
         }
     }
 }
@@ -329,22 +1388,56 @@ impl Default for NvidiaEncoderOptions {
     fn default() -> Self {
         Self {
             max_in_flight_outputs: 6,
+            surface_pool_size: None,
             gop_length: None,
             frame_interval_p: None,
             report_metrics: None,
-            safe_lifetime_mode: None,
+            buffer_lifetime_mode: None,
             enable_pipeline_scheduler: None,
             pipeline_queue_capacity: None,
+            content_hint: None,
+            spatial_aq: None,
+            temporal_aq: None,
+            aq_strength: None,
+            lookahead_depth: None,
+            average_bitrate_bps: None,
+            max_qp: None,
+            #[cfg(all(
+                feature = "unsafe-options",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            customize_init_params: None,
         }
     }
 }
 
+// Hardware surfaces a session allocated. NVDEC: decode surfaces (the reference frames the SPS
+// asks for plus frames in flight) and output surfaces (frames mapped for the caller). NVENC:
+// input frames, each paired with an output bitstream buffer. `automatic` is false when an
+// option fixed the size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SurfacePoolSizes {
+    pub surfaces: u32,
+    pub output_surfaces: u32,
+    pub automatic: bool,
+}
+
+impl Display for SurfacePoolSizes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SurfacePoolSizes(surfaces={}, output_surfaces={}, automatic={})",
+            self.surfaces, self.output_surfaces, self.automatic
+        )
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DecodeSummary {
     pub decoded_frames: usize,
     pub width: Option<usize>,
     pub height: Option<usize>,
-    pub pixel_format: Option<u32>,
+    pub pixel_format: Option<BackendPixelFormat>,
 }
 
 impl Display for DecodeSummary {
@@ -357,6 +1450,101 @@ impl Display for DecodeSummary {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncodeSummary {
+    pub frames_in: u64,
+    pub packets_out: u64,
+    pub bytes_out: u64,
+    // Part of bytes_out that is CBR filler.
+    pub filler_bytes: u64,
+    pub keyframes: u64,
+    // None until the backend reports a per-frame QP.
+    pub average_qp: Option<f64>,
+    // Size of the most recently submitted frame.
+    pub dims: Option<Dimensions>,
+    pub fps: FrameRate,
+}
+
+impl EncodeSummary {
+    pub(crate) fn new(fps: FrameRate) -> Self {
+        Self {
+            frames_in: 0,
+            packets_out: 0,
+            bytes_out: 0,
+            filler_bytes: 0,
+            keyframes: 0,
+            average_qp: None,
+            dims: None,
+            fps,
+        }
+    }
+
+    pub(crate) fn record_chunk(&mut self, chunk: &EncodedChunk) {
+        self.packets_out += 1;
+        self.bytes_out += chunk.data.len() as u64;
+        self.filler_bytes += chunk.filler_bytes as u64;
+        self.keyframes += u64::from(chunk.is_keyframe);
+    }
+}
+
+impl Display for EncodeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "EncodeSummary(frames_in={}, packets_out={}, bytes_out={}, keyframes={}, average_qp={:?}, dims={:?}, fps={})",
+            self.frames_in,
+            self.packets_out,
+            self.bytes_out,
+            self.keyframes,
+            self.average_qp,
+            self.dims.map(|dims| dims.to_string()),
+            self.fps
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodeSessionInfo {
+    pub backend: Backend,
+    pub buffer_lifetime_mode: Option<NvBufferLifetimeMode>,
+}
+
+impl Display for EncodeSessionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "EncodeSessionInfo(backend={}, buffer_lifetime_mode={:?})",
+            self.backend, self.buffer_lifetime_mode
+        )
+    }
+}
+
+// Frames the encoder holds back before the first packet for a given input can come out:
+// rate-control lookahead plus the B-frame reorder window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EncodeLatency {
+    pub lookahead_frames: u32,
+    pub reorder_frames: u32,
+}
+
+impl EncodeLatency {
+    pub fn frames(&self) -> u32 {
+        self.lookahead_frames.saturating_add(self.reorder_frames)
+    }
+}
+
+impl Display for EncodeLatency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "EncodeLatency(frames={}, lookahead_frames={}, reorder_frames={})",
+            self.frames(),
+            self.lookahead_frames,
+            self.reorder_frames
+        )
+    }
+}
+
 #[derive(Debug, Clone)]
 #[cfg(any(
     all(target_os = "macos", feature = "backend-vt"),
@@ -414,8 +1602,31 @@ pub enum BackendError {
     TemporaryBackpressure(String),
     #[error("device lost: {0}")]
     DeviceLost(String),
+    // Raised by HostSessionArbiter before any hardware session is opened.
+    #[error(
+        "session limit reached: {limit} {kind} sessions on GPU {gpu} held by {}",
+        describe_holders(.holders)
+    )]
+    SessionLimitReached {
+        gpu: u32,
+        kind: HostSessionKind,
+        limit: usize,
+        holders: Vec<SessionHolder>,
+    },
+    // Raised by the decode watchdog (DecodeWatchdogOptions). With `recovered` the session runs
+    // on a rebuilt decoder and takes input again.
+    #[error(
+        "decoder stalled: no output for {waited:?} with {outstanding} access units outstanding (recovered: {recovered})"
+    )]
+    Stalled {
+        waited: Duration,
+        outstanding: u64,
+        recovered: bool,
+    },
     #[error("backend error: {0}")]
     Backend(String),
+    #[error("unsupported conversion: {0}")]
+    UnsupportedConversion(UnsupportedConversion),
 }
 
 pub(crate) trait VideoDecoder {
@@ -429,7 +1640,51 @@ pub(crate) trait VideoDecoder {
 
     fn flush(&mut self) -> Result<Vec<Frame>, BackendError>;
 
+    // Pushes every submitted access unit through while keeping the session usable for more
+    // input. Backends whose flush is already non-destructive can rely on the default.
+    fn drain_available(&mut self) -> Result<Vec<Frame>, BackendError> {
+        self.flush()
+    }
+
+    // Frames the backend finished on its own since the last call, without pushing buffered
+    // input through, so a timed wait can call it as often as it is woken. Backends that only
+    // produce frames inside push and flush can rely on the default.
+    fn poll_ready(&mut self) -> Result<Vec<Frame>, BackendError> {
+        Ok(Vec::new())
+    }
+
+    // To be signalled whenever poll_ready has something new.
+    fn set_ready_waker(&mut self, _waker: ReadyWaker) {}
+
     fn decode_summary(&self) -> DecodeSummary;
+
+    fn set_diagnostics(&mut self, _diagnostics: Diagnostics) {}
+
+    fn frame_crop(&self) -> Option<FrameCrop> {
+        None
+    }
+
+    fn surface_pool(&self) -> Option<SurfacePoolSizes> {
+        None
+    }
+
+    // Bumped whenever the backend sees a parameter set that differs from the cached one.
+    fn parameter_set_revision(&self) -> u64 {
+        0
+    }
+
+    fn device_memory(&self) -> Option<DeviceMemory> {
+        None
+    }
+
+    // Releases what the backend can recreate on demand; see MemoryPressureOptions.
+    fn trim_pools(&mut self) {}
+
+    // Called after the final flush; releases backend resources and reports teardown failures
+    // that Drop would have to swallow.
+    fn close(&mut self) -> Result<(), BackendError> {
+        Ok(())
+    }
 }
 
 pub(crate) trait VideoEncoder {
@@ -447,6 +1702,41 @@ pub(crate) trait VideoEncoder {
             "session switching is not supported by this backend".to_string(),
         ))
     }
+
+    fn invalidate_reference(&mut self, _pts_90k: i64) -> Result<(), BackendError> {
+        Err(BackendError::UnsupportedConfig(
+            "reference invalidation is not supported by this backend".to_string(),
+        ))
+    }
+
+    fn pipeline_depth(&self) -> usize {
+        1
+    }
+
+    fn buffer_lifetime_mode(&self) -> Option<NvBufferLifetimeMode> {
+        None
+    }
+
+    fn induced_latency(&self) -> Option<EncodeLatency> {
+        None
+    }
+
+    fn surface_pool(&self) -> Option<SurfacePoolSizes> {
+        None
+    }
+
+    fn device_memory(&self) -> Option<DeviceMemory> {
+        None
+    }
+
+    // Releases what the backend can recreate on demand; see MemoryPressureOptions.
+    fn trim_pools(&mut self) {}
+
+    fn close(&mut self) -> Result<(), BackendError> {
+        Ok(())
+    }
+
+    fn set_diagnostics(&mut self, _diagnostics: Diagnostics) {}
     #[cfg(any(
         all(target_os = "macos", feature = "backend-vt"),
         all(
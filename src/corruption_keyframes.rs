@@ -0,0 +1,166 @@
+use crate::{DecodeInfoFlags, DecodedFrame, EncodeFrame};
+
+// When a decode-to-encode pipeline forces an IDR after the decoder reported damage (a
+// CORRUPTED or FRAME_DROPPED picture, or a FREEZE_FRAME stand-in for a lost one). OnRecovery
+// waits for the first clean picture, so the IDR carries good content and receivers of the
+// re-encoded stream resync as soon as the source has; OnDamage cuts at the first damaged
+// picture, so the encoder stops predicting from pictures that precede the damage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CorruptionKeyframePolicy {
+    Disabled,
+    OnDamage,
+    #[default]
+    OnRecovery,
+    OnDamageAndRecovery,
+}
+
+// Tracks decoded frames in output order and says which of the frames built from them should
+// be encoded as keyframes. Forced keyframes are at least `min_interval_frames` apart; one that
+// falls inside the interval is deferred (to the next clean frame for a recovery IDR), so a
+// flapping source cannot turn the output into all-intra.
+#[derive(Debug, Clone)]
+pub struct CorruptionKeyframes {
+    policy: CorruptionKeyframePolicy,
+    min_interval_frames: u32,
+    in_damage: bool,
+    pending_damage: bool,
+    pending_recovery: bool,
+    since_forced: Option<u64>,
+    forced: u64,
+}
+
+impl CorruptionKeyframes {
+    pub fn new(policy: CorruptionKeyframePolicy, min_interval_frames: u32) -> Self {
+        Self {
+            policy,
+            min_interval_frames,
+            in_damage: false,
+            pending_damage: false,
+            pending_recovery: false,
+            since_forced: None,
+            forced: 0,
+        }
+    }
+
+    pub fn policy(&self) -> CorruptionKeyframePolicy {
+        self.policy
+    }
+
+    // Takes effect from the next frame; a keyframe already pending is dropped when the new
+    // policy would not have asked for it.
+    pub fn set_policy(&mut self, policy: CorruptionKeyframePolicy) {
+        self.policy = policy;
+        self.pending_damage &= matches!(
+            policy,
+            CorruptionKeyframePolicy::OnDamage | CorruptionKeyframePolicy::OnDamageAndRecovery
+        );
+        self.pending_recovery &= matches!(
+            policy,
+            CorruptionKeyframePolicy::OnRecovery | CorruptionKeyframePolicy::OnDamageAndRecovery
+        );
+    }
+
+    // Keyframes forced so far.
+    pub fn forced(&self) -> u64 {
+        self.forced
+    }
+
+    // Call once per decoded frame, in output order. Returns whether the frame encoded from it
+    // should be a keyframe.
+    pub fn observe(&mut self, frame: &DecodedFrame) -> bool {
+        let damaged = match frame {
+            DecodedFrame::Metadata {
+                decode_info_flags, ..
+            } => decode_info_flags.is_some_and(|flags| {
+                flags.intersects(
+                    DecodeInfoFlags::CORRUPTED
+                        | DecodeInfoFlags::FRAME_DROPPED
+                        | DecodeInfoFlags::FREEZE_FRAME,
+                )
+            }),
+            DecodedFrame::Nv12 { .. } | DecodedFrame::Rgb24 { .. } => false,
+        };
+        let (on_damage, on_recovery) = match self.policy {
+            CorruptionKeyframePolicy::Disabled => (false, false),
+            CorruptionKeyframePolicy::OnDamage => (true, false),
+            CorruptionKeyframePolicy::OnRecovery => (false, true),
+            CorruptionKeyframePolicy::OnDamageAndRecovery => (true, true),
+        };
+        self.pending_damage |= on_damage && damaged && !self.in_damage;
+        self.pending_recovery |= on_recovery && !damaged && self.in_damage;
+        self.in_damage = damaged;
+        self.since_forced = self.since_forced.map(|frames| frames + 1);
+        let spaced = self
+            .since_forced
+            .is_none_or(|frames| frames >= u64::from(self.min_interval_frames));
+        let force = spaced && (self.pending_damage || (self.pending_recovery && !damaged));
+        if force {
+            // One IDR settles both: a recovery still ahead gets its own once the source is
+            // clean again.
+            self.pending_damage = false;
+            self.pending_recovery &= damaged;
+            self.since_forced = Some(0);
+            self.forced += 1;
+        }
+        force
+    }
+
+    // observe() for the frame `encode` was built from, setting force_keyframe when needed.
+    pub fn apply(&mut self, decoded: &DecodedFrame, encode: &mut EncodeFrame) {
+        encode.force_keyframe |= self.observe(decoded);
+    }
+}
+
+impl Default for CorruptionKeyframes {
+    fn default() -> Self {
+        Self::new(CorruptionKeyframePolicy::default(), 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Timestamp90k;
+
+    fn frame(flags: DecodeInfoFlags) -> DecodedFrame {
+        DecodedFrame::test_metadata(None, Some(Timestamp90k(0)), Some(flags))
+    }
+
+    fn run(keyframes: &mut CorruptionKeyframes, pattern: &str) -> String {
+        pattern
+            .chars()
+            .map(|c| {
+                let flags = match c {
+                    'x' => DecodeInfoFlags::CORRUPTED,
+                    'f' => DecodeInfoFlags::FREEZE_FRAME,
+                    _ => DecodeInfoFlags::empty(),
+                };
+                if keyframes.observe(&frame(flags)) {
+                    'K'
+                } else {
+                    c
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn damage_forces_one_spaced_keyframe() {
+        let mut keyframes = CorruptionKeyframes::default();
+        assert_eq!(run(&mut keyframes, "..xx..f..."), "..xxK.fK..");
+
+        let mut keyframes = CorruptionKeyframes::new(CorruptionKeyframePolicy::OnDamage, 0);
+        assert_eq!(run(&mut keyframes, "..xx..x."), "..Kx..K.");
+
+        // Recovery IDRs closer than the interval wait for the next clean frame after it.
+        let mut keyframes = CorruptionKeyframes::new(CorruptionKeyframePolicy::OnRecovery, 4);
+        assert_eq!(run(&mut keyframes, ".x.x.x...."), ".xKx.xK...");
+        assert_eq!(keyframes.forced(), 2);
+
+        let mut keyframes =
+            CorruptionKeyframes::new(CorruptionKeyframePolicy::OnDamageAndRecovery, 0);
+        assert_eq!(run(&mut keyframes, ".xx.."), ".KxK.");
+        keyframes.set_policy(CorruptionKeyframePolicy::Disabled);
+        assert_eq!(run(&mut keyframes, "x.x."), "x.x.");
+    }
+}
@@ -0,0 +1,218 @@
+use std::sync::Arc;
+
+use cudarc::driver::{CudaContext, LaunchConfig, PushKernelArg};
+use cudarc::nvrtc::compile_ptx;
+
+use crate::composite::{CompositeKernel, CompositeLayout, OverlayFrame};
+use crate::{BackendError, Nv12Frame};
+
+// Same sampling and blending as CpuCompositor. One thread per 2x2 block of the base frame, so
+// the block's chroma sample is written by a single thread.
+const COMPOSITE_KERNEL: &str = r#"
+__device__ float bilinear(
+    const unsigned char* plane, unsigned int stride, unsigned int step, unsigned int offset,
+    unsigned int width, unsigned int height, float x, float y
+) {
+    x = fminf(fmaxf(x, 0.0f), (float)(width - 1));
+    y = fminf(fmaxf(y, 0.0f), (float)(height - 1));
+    unsigned int x0 = (unsigned int)x, y0 = (unsigned int)y;
+    unsigned int x1 = min(x0 + 1, width - 1), y1 = min(y0 + 1, height - 1);
+    float tx = x - (float)x0, ty = y - (float)y0;
+    float a = plane[y0 * stride + x0 * step + offset];
+    float b = plane[y0 * stride + x1 * step + offset];
+    float c = plane[y1 * stride + x0 * step + offset];
+    float d = plane[y1 * stride + x1 * step + offset];
+    float top = a + (b - a) * tx;
+    float bottom = c + (d - c) * tx;
+    return top + (bottom - top) * ty;
+}
+
+__device__ void blend(unsigned char* dst, float src, float alpha) {
+    float value = (float)*dst + (src - (float)*dst) * alpha;
+    *dst = (unsigned char)fminf(fmaxf(rintf(value), 0.0f), 255.0f);
+}
+
+extern "C" __global__ void composite_nv12_kernel(
+    unsigned char* base,
+    unsigned int pitch,
+    unsigned int width,
+    unsigned int height,
+    const unsigned char* overlay,
+    unsigned int src_pitch,
+    unsigned int src_width,
+    unsigned int src_height,
+    const unsigned char* alpha,
+    int has_alpha,
+    int rect_x,
+    int rect_y,
+    unsigned int rect_width,
+    unsigned int rect_height,
+    float opacity
+) {
+    unsigned int bx = blockIdx.x * blockDim.x + threadIdx.x;
+    unsigned int by = blockIdx.y * blockDim.y + threadIdx.y;
+    if (bx * 2 >= width || by * 2 >= height) {
+        return;
+    }
+
+    float scale_x = (float)src_width / (float)rect_width;
+    float scale_y = (float)src_height / (float)rect_height;
+    const unsigned char* src_chroma = overlay + src_pitch * src_height;
+
+    for (unsigned int y = by * 2; y < by * 2 + 2 && y < height; ++y) {
+        float ry = (float)y - (float)rect_y;
+        if (ry < 0.0f || ry >= (float)rect_height) {
+            continue;
+        }
+        for (unsigned int x = bx * 2; x < bx * 2 + 2 && x < width; ++x) {
+            float rx = (float)x - (float)rect_x;
+            if (rx < 0.0f || rx >= (float)rect_width) {
+                continue;
+            }
+            float sx = (rx + 0.5f) * scale_x - 0.5f;
+            float sy = (ry + 0.5f) * scale_y - 0.5f;
+            float a = opacity;
+            if (has_alpha) {
+                a *= bilinear(alpha, src_width, 1, 0, src_width, src_height, sx, sy) / 255.0f;
+            }
+            float value = bilinear(overlay, src_pitch, 1, 0, src_width, src_height, sx, sy);
+            blend(&base[y * pitch + x], value, a);
+        }
+    }
+
+    float lx = (float)(bx * 2 + 1) - (float)rect_x;
+    float ly = (float)(by * 2 + 1) - (float)rect_y;
+    if (lx < 0.0f || lx >= (float)rect_width || ly < 0.0f || ly >= (float)rect_height) {
+        return;
+    }
+    float a = opacity;
+    if (has_alpha) {
+        a *= bilinear(alpha, src_width, 1, 0, src_width, src_height,
+            lx * scale_x - 0.5f, ly * scale_y - 0.5f) / 255.0f;
+    }
+    float sx = lx * scale_x / 2.0f - 0.5f;
+    float sy = ly * scale_y / 2.0f - 0.5f;
+    unsigned int chroma_w = (src_width + 1) / 2, chroma_h = (src_height + 1) / 2;
+    unsigned int uv = pitch * height + by * pitch + bx * 2;
+    for (unsigned int plane = 0; plane < 2; ++plane) {
+        float value = bilinear(src_chroma, src_pitch, 2, plane, chroma_w, chroma_h, sx, sy);
+        blend(&base[uv + plane], value, a);
+    }
+}
+"#;
+
+#[derive(Debug, Clone)]
+pub struct CudaCompositor {
+    ctx: Arc<CudaContext>,
+    stream: Arc<cudarc::driver::CudaStream>,
+    kernel: cudarc::driver::CudaFunction,
+}
+
+impl CudaCompositor {
+    pub fn new() -> Result<Self, BackendError> {
+        let ctx = CudaContext::new(0)
+            .map_err(|e| BackendError::UnsupportedConfig(format!("cuda init failed: {e}")))?;
+        let ptx = compile_ptx(COMPOSITE_KERNEL)
+            .map_err(|e| BackendError::UnsupportedConfig(format!("nvrtc compile failed: {e}")))?;
+        let module = ctx
+            .load_module(ptx)
+            .map_err(|e| BackendError::Backend(format!("cuda module load failed: {e}")))?;
+        let kernel = module
+            .load_function("composite_nv12_kernel")
+            .map_err(|e| BackendError::Backend(format!("cuda kernel load failed: {e}")))?;
+        let stream = ctx.default_stream();
+        Ok(Self {
+            ctx,
+            stream,
+            kernel,
+        })
+    }
+}
+
+impl CompositeKernel for CudaCompositor {
+    fn composite(
+        &self,
+        base: &Nv12Frame,
+        overlay: &OverlayFrame,
+        layout: &CompositeLayout,
+    ) -> Result<Nv12Frame, BackendError> {
+        crate::composite::validate_composite(base, overlay, layout)?;
+        let pitch = base.pitch.max(base.width);
+        let src = &overlay.frame;
+        let src_pitch = src.pitch.max(src.width);
+        let base_size = pitch * (base.height + base.height.div_ceil(2));
+        let src_size = src_pitch * (src.height + src.height.div_ceil(2));
+
+        self.ctx
+            .bind_to_thread()
+            .map_err(|e| BackendError::Backend(format!("cuda bind failed: {e}")))?;
+
+        let mut frame = self
+            .stream
+            .clone_htod(&base.data[..base_size])
+            .map_err(|e| BackendError::Backend(format!("cuda htod failed: {e}")))?;
+        let source = self
+            .stream
+            .clone_htod(&src.data[..src_size])
+            .map_err(|e| BackendError::Backend(format!("cuda htod failed: {e}")))?;
+        // A one-byte dummy keeps the argument list fixed when there is no alpha plane.
+        let alpha = self
+            .stream
+            .clone_htod(overlay.alpha.as_deref().unwrap_or(&[255]))
+            .map_err(|e| BackendError::Backend(format!("cuda htod failed: {e}")))?;
+
+        let (pitch_u32, width_u32, height_u32) =
+            (pitch as u32, base.width as u32, base.height as u32);
+        let (src_pitch_u32, src_width_u32, src_height_u32) =
+            (src_pitch as u32, src.width as u32, src.height as u32);
+        let has_alpha = i32::from(overlay.alpha.is_some());
+        let (rect_width, rect_height) = (layout.width as u32, layout.height as u32);
+        let cfg = LaunchConfig {
+            grid_dim: (
+                width_u32.div_ceil(2).div_ceil(16),
+                height_u32.div_ceil(2).div_ceil(16),
+                1,
+            ),
+            block_dim: (16, 16, 1),
+            shared_mem_bytes: 0,
+        };
+
+        unsafe {
+            self.stream
+                .launch_builder(&self.kernel)
+                .arg(&mut frame)
+                .arg(&pitch_u32)
+                .arg(&width_u32)
+                .arg(&height_u32)
+                .arg(&source)
+                .arg(&src_pitch_u32)
+                .arg(&src_width_u32)
+                .arg(&src_height_u32)
+                .arg(&alpha)
+                .arg(&has_alpha)
+                .arg(&layout.x)
+                .arg(&layout.y)
+                .arg(&rect_width)
+                .arg(&rect_height)
+                .arg(&layout.opacity)
+                .launch(cfg)
+        }
+        .map_err(|e| BackendError::Backend(format!("cuda launch failed: {e}")))?;
+
+        self.stream
+            .synchronize()
+            .map_err(|e| BackendError::Backend(format!("cuda sync failed: {e}")))?;
+        let data = self
+            .stream
+            .clone_dtoh(&frame)
+            .map_err(|e| BackendError::Backend(format!("cuda dtoh failed: {e}")))?;
+
+        Ok(Nv12Frame {
+            width: base.width,
+            height: base.height,
+            pitch,
+            pts_90k: base.pts_90k,
+            data,
+        })
+    }
+}
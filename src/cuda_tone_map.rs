@@ -0,0 +1,321 @@
+use std::sync::Arc;
+
+use cudarc::driver::{CudaContext, LaunchConfig, PushKernelArg};
+use cudarc::nvrtc::compile_ptx;
+
+use crate::cuda_transform::MAX_QUEUED_FRAMES;
+use crate::tone_map::{HdrTransfer, ToneMapAlgorithm, ToneMapConfig};
+use crate::{BackendError, Nv12Frame, TransformResult, TransformStage};
+
+// Same math as ToneMapper::map_frame, which is the reference for this kernel. One thread per
+// 2x2 block so the averaged chroma sample is written by a single thread.
+const TONE_MAP_KERNEL: &str = r#"
+__device__ float pq_to_nits(float e) {
+    const float m1 = 0.1593017578125f, m2 = 78.84375f;
+    const float c1 = 0.8359375f, c2 = 18.8515625f, c3 = 18.6875f;
+    float p = powf(fmaxf(e, 0.0f), 1.0f / m2);
+    return 10000.0f * powf(fmaxf(p - c1, 0.0f) / (c2 - c3 * p), 1.0f / m1);
+}
+
+__device__ float nits_to_pq(float nits) {
+    const float m1 = 0.1593017578125f, m2 = 78.84375f;
+    const float c1 = 0.8359375f, c2 = 18.8515625f, c3 = 18.6875f;
+    float y = powf(fminf(fmaxf(nits / 10000.0f, 0.0f), 1.0f), m1);
+    return powf((c1 + c2 * y) / (1.0f + c3 * y), m2);
+}
+
+__device__ float hable(float x) {
+    return ((x * (0.15f * x + 0.05f) + 0.004f) / (x * (0.15f * x + 0.5f) + 0.06f)) - 0.02f / 0.3f;
+}
+
+__device__ float tone_curve(int algorithm, float x, float source_peak) {
+    float y;
+    if (algorithm == 0) {
+        y = x * (1.0f + x / (source_peak * source_peak)) / (1.0f + x);
+    } else if (algorithm == 1) {
+        y = hable(x) / hable(source_peak);
+    } else {
+        float source_pq = nits_to_pq(source_peak * 100.0f);
+        float e1 = nits_to_pq(x * 100.0f) / source_pq;
+        float max_lum = nits_to_pq(100.0f) / source_pq;
+        float knee = 1.5f * max_lum - 0.5f;
+        float e2 = e1;
+        if (e1 >= knee) {
+            float t = (e1 - knee) / (1.0f - knee);
+            float t2 = t * t, t3 = t2 * t;
+            e2 = (2.0f * t3 - 3.0f * t2 + 1.0f) * knee
+                + (t3 - 2.0f * t2 + t) * (1.0f - knee)
+                + (-2.0f * t3 + 3.0f * t2) * max_lum;
+        }
+        y = pq_to_nits(e2 * source_pq) / 100.0f;
+    }
+    return fminf(fmaxf(y, 0.0f), 1.0f);
+}
+
+__device__ float bt709_oetf(float l) {
+    l = fminf(fmaxf(l, 0.0f), 1.0f);
+    return l < 0.018f ? 4.5f * l : 1.099f * powf(l, 0.45f) - 0.099f;
+}
+
+__device__ unsigned char quantize(float v) {
+    return (unsigned char)fminf(fmaxf(rintf(v), 0.0f), 255.0f);
+}
+
+extern "C" __global__ void tone_map_nv12_kernel(
+    const unsigned char* src,
+    unsigned char* dst,
+    unsigned int pitch,
+    unsigned int width,
+    unsigned int height,
+    int transfer,
+    int algorithm,
+    float source_peak_nits,
+    float target_peak_nits
+) {
+    unsigned int bx = blockIdx.x * blockDim.x + threadIdx.x;
+    unsigned int by = blockIdx.y * blockDim.y + threadIdx.y;
+    if (bx * 2 >= width || by * 2 >= height) {
+        return;
+    }
+
+    unsigned int uv = pitch * height + by * pitch + bx * 2;
+    float cb_in = ((float)src[uv] - 128.0f) / 224.0f;
+    float cr_in = ((float)src[uv + 1] - 128.0f) / 224.0f;
+    float source_peak = source_peak_nits / target_peak_nits;
+    float cb_sum = 0.0f, cr_sum = 0.0f, count = 0.0f;
+
+    for (unsigned int y = by * 2; y < by * 2 + 2 && y < height; ++y) {
+        for (unsigned int x = bx * 2; x < bx * 2 + 2 && x < width; ++x) {
+            float luma = ((float)src[y * pitch + x] - 16.0f) / 219.0f;
+            float rgb[3] = {
+                luma + 1.4746f * cr_in,
+                luma - 0.164553f * cb_in - 0.571353f * cr_in,
+                luma + 1.8814f * cb_in,
+            };
+            for (int i = 0; i < 3; ++i) {
+                rgb[i] = fminf(fmaxf(rgb[i], 0.0f), 1.0f);
+            }
+
+            if (transfer == 0) {
+                for (int i = 0; i < 3; ++i) {
+                    rgb[i] = pq_to_nits(rgb[i]) / target_peak_nits;
+                }
+            } else {
+                for (int i = 0; i < 3; ++i) {
+                    float e = rgb[i];
+                    rgb[i] = e <= 0.5f ? e * e / 3.0f
+                        : (expf((e - 0.55991073f) / 0.17883277f) + 0.28466892f) / 12.0f;
+                }
+                float gamma = 1.2f + 0.42f * log10f(source_peak_nits / 1000.0f);
+                float scene = 0.2627f * rgb[0] + 0.6780f * rgb[1] + 0.0593f * rgb[2];
+                float gain = source_peak_nits * powf(fmaxf(scene, 1e-6f), gamma - 1.0f)
+                    / target_peak_nits;
+                for (int i = 0; i < 3; ++i) {
+                    rgb[i] *= gain;
+                }
+            }
+
+            float lum = 0.2627f * rgb[0] + 0.6780f * rgb[1] + 0.0593f * rgb[2];
+            float scale = lum > 0.0f ? tone_curve(algorithm, lum, source_peak) / lum : 0.0f;
+            float r = rgb[0] * scale, g = rgb[1] * scale, b = rgb[2] * scale;
+
+            float r709 = bt709_oetf(1.6605f * r - 0.5876f * g - 0.0728f * b);
+            float g709 = bt709_oetf(-0.1246f * r + 1.1329f * g - 0.0083f * b);
+            float b709 = bt709_oetf(-0.0182f * r - 0.1006f * g + 1.1187f * b);
+
+            float y709 = 0.2126f * r709 + 0.7152f * g709 + 0.0722f * b709;
+            dst[y * pitch + x] = quantize(16.0f + 219.0f * y709);
+            cb_sum += (b709 - y709) / 1.8556f;
+            cr_sum += (r709 - y709) / 1.5748f;
+            count += 1.0f;
+        }
+    }
+
+    dst[uv] = quantize(128.0f + 224.0f * cb_sum / count);
+    dst[uv + 1] = quantize(128.0f + 224.0f * cr_sum / count);
+}
+"#;
+
+#[derive(Debug, Clone)]
+pub struct CudaToneMapper {
+    ctx: Arc<CudaContext>,
+    stream: Arc<cudarc::driver::CudaStream>,
+    kernel: cudarc::driver::CudaFunction,
+    transfer: HdrTransfer,
+    config: ToneMapConfig,
+}
+
+impl CudaToneMapper {
+    pub fn new(transfer: HdrTransfer, config: ToneMapConfig) -> Result<Self, BackendError> {
+        // Reuses the CPU mapper's validation of the peak luminance pair.
+        crate::ToneMapper::new(transfer, config)?;
+        let ctx = CudaContext::new(0)
+            .map_err(|e| BackendError::UnsupportedConfig(format!("cuda init failed: {e}")))?;
+        let ptx = compile_ptx(TONE_MAP_KERNEL)
+            .map_err(|e| BackendError::UnsupportedConfig(format!("nvrtc compile failed: {e}")))?;
+        let module = ctx
+            .load_module(ptx)
+            .map_err(|e| BackendError::Backend(format!("cuda module load failed: {e}")))?;
+        let kernel = module
+            .load_function("tone_map_nv12_kernel")
+            .map_err(|e| BackendError::Backend(format!("cuda kernel load failed: {e}")))?;
+        let stream = ctx.default_stream();
+        Ok(Self {
+            ctx,
+            stream,
+            kernel,
+            transfer,
+            config,
+        })
+    }
+
+    pub fn map_frame(&self, frame: &Nv12Frame) -> Result<Nv12Frame, BackendError> {
+        self.map_frames(std::slice::from_ref(frame))
+            .pop()
+            .expect("one result per frame")
+    }
+
+    // Maps a group the way CudaNv12ToRgb::convert_batch converts one: uploads and launches
+    // for up to MAX_QUEUED_FRAMES frames go on the stream before each synchronize.
+    pub fn map_frames(&self, frames: &[Nv12Frame]) -> Vec<Result<Nv12Frame, BackendError>> {
+        let mut results = Vec::with_capacity(frames.len());
+        for group in frames.chunks(MAX_QUEUED_FRAMES) {
+            let sizes = group.iter().map(nv12_size).collect::<Vec<_>>();
+            match self.run_group(group, &sizes) {
+                Ok(outputs) => {
+                    for ((frame, size), data) in group.iter().zip(sizes).zip(outputs) {
+                        results.push(size.map(|_| Nv12Frame {
+                            width: frame.width,
+                            height: frame.height,
+                            pitch: frame.pitch.max(frame.width),
+                            pts_90k: frame.pts_90k,
+                            data: data.unwrap_or_default(),
+                        }));
+                    }
+                }
+                Err(message) => results.extend(
+                    sizes
+                        .into_iter()
+                        .map(|size| size.and_then(|_| Err(BackendError::Backend(message.clone())))),
+                ),
+            }
+        }
+        results
+    }
+
+    // Mapped data per frame, None where validation failed; the error message otherwise.
+    fn run_group(
+        &self,
+        frames: &[Nv12Frame],
+        sizes: &[Result<usize, BackendError>],
+    ) -> Result<Vec<Option<Vec<u8>>>, String> {
+        self.ctx
+            .bind_to_thread()
+            .map_err(|e| format!("cuda bind failed: {e}"))?;
+
+        let transfer = match self.transfer {
+            HdrTransfer::Pq => 0_i32,
+            HdrTransfer::Hlg => 1,
+        };
+        let algorithm = match self.config.algorithm {
+            ToneMapAlgorithm::Reinhard => 0_i32,
+            ToneMapAlgorithm::Hable => 1,
+            ToneMapAlgorithm::Bt2390 => 2,
+        };
+        // Inputs stay alive until the synchronize below.
+        let mut queued = Vec::with_capacity(frames.len());
+        for (frame, size) in frames.iter().zip(sizes) {
+            let Ok(total_size) = *size else {
+                queued.push(None);
+                continue;
+            };
+            let input = self
+                .stream
+                .clone_htod(&frame.data[..total_size])
+                .map_err(|e| format!("cuda htod failed: {e}"))?;
+            // Starting from a copy keeps the pitch padding intact in the output.
+            let mut output = self
+                .stream
+                .clone_htod(&frame.data[..total_size])
+                .map_err(|e| format!("cuda htod failed: {e}"))?;
+
+            let width_u32 = frame.width as u32;
+            let height_u32 = frame.height as u32;
+            let pitch_u32 = frame.pitch.max(frame.width) as u32;
+            let cfg = LaunchConfig {
+                grid_dim: (
+                    width_u32.div_ceil(2).div_ceil(16),
+                    height_u32.div_ceil(2).div_ceil(16),
+                    1,
+                ),
+                block_dim: (16, 16, 1),
+                shared_mem_bytes: 0,
+            };
+
+            unsafe {
+                self.stream
+                    .launch_builder(&self.kernel)
+                    .arg(&input)
+                    .arg(&mut output)
+                    .arg(&pitch_u32)
+                    .arg(&width_u32)
+                    .arg(&height_u32)
+                    .arg(&transfer)
+                    .arg(&algorithm)
+                    .arg(&self.config.source_peak_nits)
+                    .arg(&self.config.target_peak_nits)
+                    .launch(cfg)
+            }
+            .map_err(|e| format!("cuda launch failed: {e}"))?;
+            queued.push(Some((input, output)));
+        }
+
+        self.stream
+            .synchronize()
+            .map_err(|e| format!("cuda sync failed: {e}"))?;
+        queued
+            .into_iter()
+            .map(|buffers| {
+                buffers
+                    .map(|(_, output)| {
+                        self.stream
+                            .clone_dtoh(&output)
+                            .map_err(|e| format!("cuda dtoh failed: {e}"))
+                    })
+                    .transpose()
+            })
+            .collect()
+    }
+}
+
+// Bytes of the frame the kernel reads.
+fn nv12_size(frame: &Nv12Frame) -> Result<usize, BackendError> {
+    let pitch = frame.pitch.max(frame.width);
+    if frame.width == 0 || frame.height == 0 {
+        return Err(BackendError::InvalidInput(
+            "nv12 frame dimensions must be positive".to_string(),
+        ));
+    }
+    let total_size = pitch
+        .checked_mul(frame.height + frame.height.div_ceil(2))
+        .ok_or_else(|| BackendError::InvalidInput("nv12 total size overflow".to_string()))?;
+    if frame.data.len() < total_size {
+        return Err(BackendError::InvalidInput(
+            "nv12 data is smaller than expected".to_string(),
+        ));
+    }
+    Ok(total_size)
+}
+
+impl TransformStage for CudaToneMapper {
+    fn process(&self, frame: Nv12Frame) -> Result<TransformResult, BackendError> {
+        self.map_frame(&frame).map(TransformResult::Nv12)
+    }
+
+    fn process_batch(&self, frames: Vec<Nv12Frame>) -> Vec<Result<TransformResult, BackendError>> {
+        self.map_frames(&frames)
+            .into_iter()
+            .map(|frame| frame.map(TransformResult::Nv12))
+            .collect()
+    }
+}
@@ -47,6 +47,10 @@ extern "C" __global__ void nv12_to_rgb_kernel(
 }
 "#;
 
+// Frames whose device buffers are alive at once in a batch: enough to amortize the
+// synchronize, few enough that a long 4K batch stays well inside device memory.
+pub(crate) const MAX_QUEUED_FRAMES: usize = 16;
+
 #[derive(Debug, Clone)]
 pub struct CudaNv12ToRgb {
     ctx: Arc<CudaContext>,
@@ -75,78 +79,142 @@ impl CudaNv12ToRgb {
     }
 
     pub fn convert(&self, frame: &Nv12Frame) -> Result<RgbFrame, BackendError> {
-        let width = frame.width;
-        let height = frame.height;
-        let pitch = frame.pitch.max(width);
-        if width == 0 || height == 0 {
-            return Err(BackendError::InvalidInput(
-                "nv12 frame dimensions must be positive".to_string(),
-            ));
-        }
-        if width > pitch {
-            return Err(BackendError::InvalidInput(
-                "nv12 width exceeds pitch".to_string(),
-            ));
-        }
-        let luma_size = pitch
-            .checked_mul(height)
-            .ok_or_else(|| BackendError::InvalidInput("nv12 luma size overflow".to_string()))?;
-        let total_size = luma_size
-            .checked_add(luma_size / 2)
-            .ok_or_else(|| BackendError::InvalidInput("nv12 total size overflow".to_string()))?;
-        if frame.data.len() < total_size {
-            return Err(BackendError::InvalidInput(
-                "nv12 data is smaller than expected".to_string(),
-            ));
+        self.convert_batch(std::slice::from_ref(frame))
+            .pop()
+            .expect("one result per frame")
+    }
+
+    // Converts a group with one pass over the stream per MAX_QUEUED_FRAMES: every upload and
+    // launch is queued before a single synchronize, then the results are copied back in
+    // order. A frame that fails validation gets its own error; a CUDA failure fails every
+    // frame queued with it.
+    pub fn convert_batch(&self, frames: &[Nv12Frame]) -> Vec<Result<RgbFrame, BackendError>> {
+        let mut results = Vec::with_capacity(frames.len());
+        for group in frames.chunks(MAX_QUEUED_FRAMES) {
+            let layouts = group.iter().map(nv12_layout).collect::<Vec<_>>();
+            match self.run_group(group, &layouts) {
+                Ok(outputs) => {
+                    for ((frame, layout), data) in group.iter().zip(layouts).zip(outputs) {
+                        results.push(layout.map(|layout| RgbFrame {
+                            width: layout.width,
+                            height: layout.height,
+                            pts_90k: frame.pts_90k,
+                            data: data.unwrap_or_default(),
+                        }));
+                    }
+                }
+                Err(message) => results.extend(layouts.into_iter().map(|layout| {
+                    layout.and_then(|_| Err(BackendError::Backend(message.clone())))
+                })),
+            }
         }
+        results
+    }
 
+    // RGB data per frame, None where validation failed; the error message otherwise.
+    fn run_group(
+        &self,
+        frames: &[Nv12Frame],
+        layouts: &[Result<Nv12Layout, BackendError>],
+    ) -> Result<Vec<Option<Vec<u8>>>, String> {
         self.ctx
             .bind_to_thread()
-            .map_err(|e| BackendError::Backend(format!("cuda bind failed: {e}")))?;
-
-        let input = self
-            .stream
-            .clone_htod(&frame.data[..total_size])
-            .map_err(|e| BackendError::Backend(format!("cuda htod failed: {e}")))?;
-        let mut output = self
-            .stream
-            .alloc_zeros::<u8>(width.saturating_mul(height).saturating_mul(3))
-            .map_err(|e| BackendError::Backend(format!("cuda alloc failed: {e}")))?;
-
-        let width_u32 = width as u32;
-        let height_u32 = height as u32;
-        let pitch_u32 = pitch as u32;
-        let cfg = LaunchConfig {
-            grid_dim: (width_u32.div_ceil(16), height_u32.div_ceil(16), 1),
-            block_dim: (16, 16, 1),
-            shared_mem_bytes: 0,
-        };
-
-        unsafe {
-            self.stream
-                .launch_builder(&self.kernel)
-                .arg(&input)
-                .arg(&pitch_u32)
-                .arg(&width_u32)
-                .arg(&height_u32)
-                .arg(&mut output)
-                .launch(cfg)
+            .map_err(|e| format!("cuda bind failed: {e}"))?;
+
+        // Inputs stay alive until the synchronize below.
+        let mut queued = Vec::with_capacity(frames.len());
+        for (frame, layout) in frames.iter().zip(layouts) {
+            let Ok(layout) = layout else {
+                queued.push(None);
+                continue;
+            };
+            let input = self
+                .stream
+                .clone_htod(&frame.data[..layout.total_size])
+                .map_err(|e| format!("cuda htod failed: {e}"))?;
+            let mut output = self
+                .stream
+                .alloc_zeros::<u8>(layout.width.saturating_mul(layout.height).saturating_mul(3))
+                .map_err(|e| format!("cuda alloc failed: {e}"))?;
+
+            let width_u32 = layout.width as u32;
+            let height_u32 = layout.height as u32;
+            let pitch_u32 = layout.pitch as u32;
+            let cfg = LaunchConfig {
+                grid_dim: (width_u32.div_ceil(16), height_u32.div_ceil(16), 1),
+                block_dim: (16, 16, 1),
+                shared_mem_bytes: 0,
+            };
+
+            unsafe {
+                self.stream
+                    .launch_builder(&self.kernel)
+                    .arg(&input)
+                    .arg(&pitch_u32)
+                    .arg(&width_u32)
+                    .arg(&height_u32)
+                    .arg(&mut output)
+                    .launch(cfg)
+            }
+            .map_err(|e| format!("cuda launch failed: {e}"))?;
+            queued.push(Some((input, output)));
         }
-        .map_err(|e| BackendError::Backend(format!("cuda launch failed: {e}")))?;
 
         self.stream
             .synchronize()
-            .map_err(|e| BackendError::Backend(format!("cuda sync failed: {e}")))?;
-        let rgb = self
-            .stream
-            .clone_dtoh(&output)
-            .map_err(|e| BackendError::Backend(format!("cuda dtoh failed: {e}")))?;
-
-        Ok(RgbFrame {
-            width,
-            height,
-            pts_90k: frame.pts_90k,
-            data: rgb,
-        })
+            .map_err(|e| format!("cuda sync failed: {e}"))?;
+        queued
+            .into_iter()
+            .map(|buffers| {
+                buffers
+                    .map(|(_, output)| {
+                        self.stream
+                            .clone_dtoh(&output)
+                            .map_err(|e| format!("cuda dtoh failed: {e}"))
+                    })
+                    .transpose()
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Nv12Layout {
+    width: usize,
+    height: usize,
+    pitch: usize,
+    total_size: usize,
+}
+
+fn nv12_layout(frame: &Nv12Frame) -> Result<Nv12Layout, BackendError> {
+    let width = frame.width;
+    let height = frame.height;
+    let pitch = frame.pitch.max(width);
+    if width == 0 || height == 0 {
+        return Err(BackendError::InvalidInput(
+            "nv12 frame dimensions must be positive".to_string(),
+        ));
+    }
+    if width > pitch {
+        return Err(BackendError::InvalidInput(
+            "nv12 width exceeds pitch".to_string(),
+        ));
+    }
+    let luma_size = pitch
+        .checked_mul(height)
+        .ok_or_else(|| BackendError::InvalidInput("nv12 luma size overflow".to_string()))?;
+    let total_size = luma_size
+        .checked_add(luma_size / 2)
+        .ok_or_else(|| BackendError::InvalidInput("nv12 total size overflow".to_string()))?;
+    if frame.data.len() < total_size {
+        return Err(BackendError::InvalidInput(
+            "nv12 data is smaller than expected".to_string(),
+        ));
     }
+    Ok(Nv12Layout {
+        width,
+        height,
+        pitch,
+        total_size,
+    })
 }
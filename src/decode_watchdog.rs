@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::{
+    BackendError, BackendKind, Codec, DecoderConfig, DecoderInner, DiagnosticEvent, Diagnostics,
+    Frame, VideoDecoder, build_decoder_inner, nal_type, reap_cancel::ReadyWaker,
+    split_annexb_nal_units,
+};
+
+// Inputs kept for replay. A stall in a longer GOP is reported without a recovery attempt.
+const MAX_REPLAY: usize = 600;
+
+// A decoder is stalled when more than `held_frames` access units went in without a single
+// frame coming out for `stall_timeout`, as when VideoToolbox callbacks or NVDEC output stop
+// arriving after a driver hang. `held_frames` covers what a decoder keeps back for
+// reordering, so waiting on a stream that ended without a flush is not taken for a stall.
+// With `recover`, the hung decoder is replaced by a new one that is fed the current GOP again
+// from its keyframe, behind the most recent parameter sets; replayed frames up to the last
+// one already output are dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeWatchdogOptions {
+    pub stall_timeout: Duration,
+    pub held_frames: u32,
+    pub recover: bool,
+}
+
+impl Default for DecodeWatchdogOptions {
+    fn default() -> Self {
+        Self {
+            stall_timeout: Duration::from_secs(2),
+            held_frames: 4,
+            recover: true,
+        }
+    }
+}
+
+// Checked from submit and reap_timeout; a blocking flush cannot be interrupted. Each stall is
+// reported as DiagnosticEvent::DecoderStalled and returned as BackendError::Stalled.
+pub(crate) struct DecodeWatchdog {
+    options: Option<DecodeWatchdogOptions>,
+    backend: BackendKind,
+    config: DecoderConfig,
+    diagnostics: Diagnostics,
+    ready_waker: ReadyWaker,
+    outstanding: u64,
+    waiting_since: Option<Instant>,
+    // Latest of each parameter set type, whatever its id.
+    parameter_sets: BTreeMap<u8, Vec<u8>>,
+    // Inputs since the last keyframe; None until one arrives or after an overflow.
+    replay: Option<Vec<(Vec<u8>, Option<i64>)>>,
+    last_output_pts: Option<i64>,
+    stalls: u64,
+}
+
+impl DecodeWatchdog {
+    pub(crate) fn new(
+        backend: BackendKind,
+        config: DecoderConfig,
+        diagnostics: Diagnostics,
+        ready_waker: ReadyWaker,
+    ) -> Self {
+        Self {
+            options: None,
+            backend,
+            config,
+            diagnostics,
+            ready_waker,
+            outstanding: 0,
+            waiting_since: None,
+            parameter_sets: BTreeMap::new(),
+            replay: None,
+            last_output_pts: None,
+            stalls: 0,
+        }
+    }
+
+    pub(crate) fn set_options(&mut self, options: Option<DecodeWatchdogOptions>) {
+        self.options = options;
+        self.outstanding = 0;
+        self.waiting_since = None;
+        self.replay = None;
+    }
+
+    pub(crate) fn options(&self) -> Option<DecodeWatchdogOptions> {
+        self.options
+    }
+
+    pub(crate) fn stalls(&self) -> u64 {
+        self.stalls
+    }
+
+    // Call with every Annex B access unit the decoder accepted.
+    pub(crate) fn observe_input(&mut self, now: Instant, chunk: &[u8], pts_90k: Option<i64>) {
+        let Some(options) = self.options else {
+            return;
+        };
+        self.outstanding += 1;
+        self.waiting_since.get_or_insert(now);
+        if !options.recover {
+            return;
+        }
+        let codec = self.config.codec;
+        let mut keyframe = codec == Codec::Mjpeg;
+        for nal in split_annexb_nal_units(chunk) {
+            match (codec, nal_type(codec, nal)) {
+                (Codec::H264, Some(kind @ (7 | 8))) | (Codec::Hevc, Some(kind @ 32..=34)) => {
+                    self.parameter_sets.insert(kind, nal.to_vec());
+                }
+                (Codec::H264, Some(5)) | (Codec::Hevc, Some(16..=21)) => keyframe = true,
+                _ => {}
+            }
+        }
+        if keyframe {
+            self.replay = Some(Vec::new());
+        }
+        if let Some(replay) = self.replay.as_mut() {
+            replay.push((chunk.to_vec(), pts_90k));
+            if replay.len() > MAX_REPLAY {
+                self.replay = None;
+            }
+        }
+    }
+
+    // Takes the pts of every frame the decoder put out.
+    pub(crate) fn observe_output(&mut self, frames: impl IntoIterator<Item = Option<i64>>) {
+        for pts_90k in frames {
+            self.outstanding = 0;
+            self.waiting_since = None;
+            self.last_output_pts = self.last_output_pts.max(pts_90k);
+        }
+    }
+
+    // After a flush nothing is outstanding, and the next input has to open a GOP anyway.
+    pub(crate) fn flushed(&mut self) {
+        self.outstanding = 0;
+        self.waiting_since = None;
+        self.replay = None;
+        self.last_output_pts = None;
+    }
+
+    // The stall error, with the frames a recovered decoder produced from the replay.
+    pub(crate) fn poll(
+        &mut self,
+        now: Instant,
+        inner: &mut DecoderInner,
+    ) -> Option<(BackendError, Vec<Frame>)> {
+        let options = self.options?;
+        let waited = now.saturating_duration_since(self.waiting_since?);
+        if self.outstanding <= u64::from(options.held_frames) || waited < options.stall_timeout {
+            return None;
+        }
+        let outstanding = self.outstanding;
+        self.stalls += 1;
+        let replayed = match self.replay.take() {
+            Some(replay) if options.recover => self.rebuild(inner, replay).ok(),
+            _ => None,
+        };
+        let recovered = replayed.is_some();
+        self.diagnostics.emit(DiagnosticEvent::DecoderStalled {
+            waited_ms: u64::try_from(waited.as_millis()).unwrap_or(u64::MAX),
+            outstanding,
+            recovered,
+        });
+        // A decoder left hung is reported again after another full timeout.
+        self.outstanding = if recovered { 0 } else { outstanding };
+        self.waiting_since = (!recovered).then_some(now);
+        let err = BackendError::Stalled {
+            waited,
+            outstanding,
+            recovered,
+        };
+        Some((err, replayed.unwrap_or_default()))
+    }
+
+    // The hung decoder is dropped without a flush, which would wait on the same output.
+    fn rebuild(
+        &mut self,
+        inner: &mut DecoderInner,
+        replay: Vec<(Vec<u8>, Option<i64>)>,
+    ) -> Result<Vec<Frame>, BackendError> {
+        *inner = build_decoder_inner(self.backend, self.config.clone());
+        inner.set_diagnostics(self.diagnostics.clone());
+        inner.set_ready_waker(self.ready_waker.clone());
+        let mut frames = Vec::new();
+        for (index, (chunk, pts_90k)) in replay.iter().enumerate() {
+            let chunk = if index == 0 {
+                let mut leading = Vec::new();
+                for nal in self.parameter_sets.values() {
+                    leading.extend_from_slice(&[0, 0, 0, 1]);
+                    leading.extend_from_slice(nal);
+                }
+                leading.extend_from_slice(chunk);
+                leading
+            } else {
+                chunk.clone()
+            };
+            frames.extend(inner.push_bitstream_chunk(&chunk, *pts_90k)?);
+        }
+        self.replay = Some(replay);
+        frames.retain(|frame| {
+            self.last_output_pts
+                .is_none_or(|last| frame.pts_90k.is_none_or(|pts| pts > last))
+        });
+        self.observe_output(frames.iter().map(|frame| frame.pts_90k));
+        Ok(frames)
+    }
+}
+
+#[cfg(all(
+    test,
+    any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    )
+))]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::UnsupportedDecoderAdapter;
+    use crate::diagnostics::RecordingDiagnostics;
+
+    #[test]
+    fn stalls_need_outstanding_input_past_the_held_frames() {
+        let sink = Arc::new(RecordingDiagnostics::default());
+        let mut watchdog = DecodeWatchdog::new(
+            BackendKind::os_default(),
+            DecoderConfig::new(Codec::H264, 30, true),
+            Diagnostics::from_arc(sink.clone()),
+            crate::reap_cancel::ReapWaiter::new().ready_waker(),
+        );
+        let mut inner =
+            DecoderInner::Unsupported(UnsupportedDecoderAdapter::new("hung".to_string()));
+        let p_slice = [0, 0, 0, 1, 0x41, 0x9A];
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+
+        // Off by default.
+        watchdog.observe_input(start, &p_slice, Some(0));
+        assert!(watchdog.poll(at(60), &mut inner).is_none());
+
+        watchdog.set_options(Some(DecodeWatchdogOptions {
+            held_frames: 1,
+            ..DecodeWatchdogOptions::default()
+        }));
+        watchdog.observe_input(at(60), &p_slice, Some(0));
+        // One access unit may be held back for reordering.
+        assert!(watchdog.poll(at(62), &mut inner).is_none());
+        // The wait counts from the oldest input without output.
+        watchdog.observe_input(at(61), &p_slice, Some(3000));
+        assert!(watchdog.poll(at(61), &mut inner).is_none());
+        watchdog.observe_output([Some(0)]);
+        assert!(watchdog.poll(at(63), &mut inner).is_none());
+
+        watchdog.observe_input(at(63), &p_slice, Some(6000));
+        watchdog.observe_input(at(63), &p_slice, Some(9000));
+        assert!(watchdog.poll(at(64), &mut inner).is_none());
+        // No keyframe was seen, so there is nothing to replay into a new decoder.
+        let (err, replayed) = watchdog.poll(at(65), &mut inner).unwrap();
+        assert!(replayed.is_empty());
+        assert_eq!(
+            err.to_string(),
+            "decoder stalled: no output for 2s with 2 access units outstanding (recovered: false)"
+        );
+        // Reported again only after another full timeout.
+        assert!(watchdog.poll(at(66), &mut inner).is_none());
+        assert!(watchdog.poll(at(67), &mut inner).is_some());
+        assert_eq!(watchdog.stalls(), 2);
+        assert_eq!(
+            sink.events()[0].to_string(),
+            "[decode.stalled] waited_ms=2000, outstanding=2, recovered=false"
+        );
+
+        watchdog.flushed();
+        assert!(watchdog.poll(at(120), &mut inner).is_none());
+    }
+}
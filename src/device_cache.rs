@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+use std::sync::OnceLock;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+use cudarc::driver::CudaContext;
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+use crate::BackendError;
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Counters of a process-wide device cache. `in_use` entries are held by at least one live
+// session; the rest are idle and go away once they stay idle for the timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeviceCacheStats {
+    pub cached: usize,
+    pub in_use: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+// Shares handles that are slow to create between sessions, keyed by device. Sessions hold
+// plain Arc clones, so an entry counts as idle once the cache holds the only reference. A
+// sweeper thread runs while anything is cached and drops entries idle for `idle_timeout`.
+pub(crate) struct DeviceCache<K, V> {
+    // None turns caching off: every lookup creates a fresh handle.
+    idle_timeout: Option<Duration>,
+    state: Mutex<CacheState<K, V>>,
+}
+
+struct CacheState<K, V> {
+    entries: HashMap<K, CacheEntry<V>>,
+    sweeper_running: bool,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+struct CacheEntry<V> {
+    value: Arc<V>,
+    idle_since: Option<Instant>,
+}
+
+impl<K, V> DeviceCache<K, V>
+where
+    K: Eq + Hash + Copy + Send + 'static,
+    V: Send + Sync + 'static,
+{
+    pub(crate) fn new(idle_timeout: Option<Duration>) -> Self {
+        Self {
+            idle_timeout,
+            state: Mutex::new(CacheState {
+                entries: HashMap::new(),
+                sweeper_running: false,
+                hits: 0,
+                misses: 0,
+                evictions: 0,
+            }),
+        }
+    }
+
+    // Creation runs under the lock so sessions starting together share one handle rather than
+    // each paying for their own.
+    pub(crate) fn get_or_create<E>(
+        &'static self,
+        key: K,
+        create: impl FnOnce() -> Result<Arc<V>, E>,
+    ) -> Result<Arc<V>, E> {
+        let Some(idle_timeout) = self.idle_timeout else {
+            return create();
+        };
+        let mut state = self.lock();
+        if let Some(entry) = state.entries.get_mut(&key) {
+            entry.idle_since = None;
+            let value = Arc::clone(&entry.value);
+            state.hits += 1;
+            return Ok(value);
+        }
+        let value = create()?;
+        state.misses += 1;
+        state.entries.insert(
+            key,
+            CacheEntry {
+                value: Arc::clone(&value),
+                idle_since: None,
+            },
+        );
+        if !state.sweeper_running {
+            let period = (idle_timeout / 4).max(Duration::from_millis(10));
+            state.sweeper_running = thread::Builder::new()
+                .name("video-hw-device-cache".to_string())
+                .spawn(move || {
+                    loop {
+                        thread::sleep(period);
+                        if !self.sweep(Instant::now()) {
+                            break;
+                        }
+                    }
+                })
+                .is_ok();
+        }
+        Ok(value)
+    }
+
+    // Evicts entries nobody has held for the idle timeout, counted from the first sweep that
+    // found them idle. Returns whether anything is still cached.
+    pub(crate) fn sweep(&self, now: Instant) -> bool {
+        let timeout = self.idle_timeout.unwrap_or_default();
+        let mut evicted = Vec::new();
+        let mut state = self.lock();
+        state.entries.retain(|_, entry| {
+            if Arc::strong_count(&entry.value) > 1 {
+                entry.idle_since = None;
+                return true;
+            }
+            let since = *entry.idle_since.get_or_insert(now);
+            if now.saturating_duration_since(since) < timeout {
+                return true;
+            }
+            evicted.push(Arc::clone(&entry.value));
+            false
+        });
+        state.evictions += evicted.len() as u64;
+        let remaining = !state.entries.is_empty();
+        state.sweeper_running &= remaining;
+        drop(state);
+        // Device teardown can take a while; not under the lock.
+        drop(evicted);
+        remaining
+    }
+
+    // Evicts every entry no session holds right now, regardless of the idle timeout, e.g. to
+    // give device memory back under pressure. Returns how many went.
+    pub(crate) fn evict_idle(&self) -> usize {
+        let mut evicted = Vec::new();
+        let mut state = self.lock();
+        state.entries.retain(|_, entry| {
+            if Arc::strong_count(&entry.value) > 1 {
+                return true;
+            }
+            evicted.push(Arc::clone(&entry.value));
+            false
+        });
+        state.evictions += evicted.len() as u64;
+        drop(state);
+        evicted.len()
+    }
+
+    pub(crate) fn stats(&self) -> DeviceCacheStats {
+        let state = self.lock();
+        DeviceCacheStats {
+            cached: state.entries.len(),
+            in_use: state
+                .entries
+                .values()
+                .filter(|entry| Arc::strong_count(&entry.value) > 1)
+                .count(),
+            hits: state.hits,
+            misses: state.misses,
+            evictions: state.evictions,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CacheState<K, V>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// VIDEO_HW_DEVICE_CACHE_IDLE_MS=N keeps idle handles for N ms (default 30 s); 0 disables the
+// cache, so every session creates and releases its own.
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+fn idle_timeout_from_env() -> Option<Duration> {
+    match std::env::var("VIDEO_HW_DEVICE_CACHE_IDLE_MS")
+        .ok()
+        .and_then(|ms| ms.parse::<u64>().ok())
+    {
+        Some(0) => None,
+        Some(ms) => Some(Duration::from_millis(ms)),
+        None => Some(DEFAULT_IDLE_TIMEOUT),
+    }
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+fn cuda_contexts() -> &'static DeviceCache<usize, CudaContext> {
+    static CONTEXTS: OnceLock<DeviceCache<usize, CudaContext>> = OnceLock::new();
+    CONTEXTS.get_or_init(|| DeviceCache::new(idle_timeout_from_env()))
+}
+
+// The CUDA primary context of device `ordinal`. Retaining it on a cold device costs hundreds
+// of ms, and releasing the last reference tears it down again, so sessions created back to
+// back get it from the cache instead.
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+pub(crate) fn cuda_context(ordinal: usize) -> Result<Arc<CudaContext>, BackendError> {
+    cuda_contexts().get_or_create(ordinal, || {
+        CudaContext::new(ordinal).map_err(|err| {
+            BackendError::UnsupportedConfig(format!("failed to initialize CUDA context: {err}"))
+        })
+    })
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+pub(crate) fn release_idle_cuda_contexts() -> usize {
+    cuda_contexts().evict_idle()
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+pub fn cuda_context_cache_stats() -> DeviceCacheStats {
+    cuda_contexts().stats()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_entries_are_evicted_after_the_timeout() {
+        let cache: &'static DeviceCache<u32, String> =
+            Box::leak(Box::new(DeviceCache::new(Some(Duration::from_secs(3600)))));
+        let first = cache
+            .get_or_create(0, || Ok::<_, ()>(Arc::new("ctx0".to_string())))
+            .unwrap();
+        let second = cache
+            .get_or_create(0, || -> Result<_, ()> { panic!("cached entry is reused") })
+            .unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(
+            cache.get_or_create(1, || Err::<Arc<String>, _>("no device")),
+            Err("no device")
+        );
+        assert_eq!(
+            cache.stats(),
+            DeviceCacheStats {
+                cached: 1,
+                in_use: 1,
+                hits: 1,
+                misses: 1,
+                evictions: 0,
+            }
+        );
+
+        let start = Instant::now();
+        assert!(cache.sweep(start));
+        drop((first, second));
+        assert!(cache.sweep(start + Duration::from_secs(10)));
+        assert_eq!(cache.stats().in_use, 0);
+        // Leasing again restarts the idle clock.
+        drop(cache.get_or_create(0, || -> Result<_, ()> { unreachable!() }));
+        assert!(cache.sweep(start + Duration::from_secs(3000)));
+        assert!(!cache.sweep(start + Duration::from_secs(6601)));
+        assert_eq!(cache.stats().evictions, 1);
+
+        let held = cache
+            .get_or_create(2, || Ok::<_, ()>(Arc::new("ctx2".to_string())))
+            .unwrap();
+        drop(cache.get_or_create(3, || Ok::<_, ()>(Arc::new("ctx3".to_string()))));
+        assert_eq!(cache.evict_idle(), 1);
+        assert_eq!(cache.stats().cached, 1);
+        drop(held);
+
+        let uncached: &'static DeviceCache<u32, String> =
+            Box::leak(Box::new(DeviceCache::new(None)));
+        let a = uncached
+            .get_or_create(0, || Ok::<_, ()>(Arc::new(String::new())))
+            .unwrap();
+        let b = uncached
+            .get_or_create(0, || Ok::<_, ()>(Arc::new(String::new())))
+            .unwrap();
+        assert!(!Arc::ptr_eq(&a, &b));
+        assert_eq!(uncached.stats().cached, 0);
+    }
+}
@@ -0,0 +1,436 @@
+use std::fmt::{self, Write as _};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::{Codec, MemoryPressureLevel, Timestamp90k};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagnosticEvent {
+    SessionCreated {
+        backend: String,
+        codec: Codec,
+    },
+    Reconfigured {
+        generation: u64,
+        force_idr: bool,
+    },
+    SoftwareFallback {
+        reason: String,
+    },
+    BackendFallback {
+        from: String,
+        to: String,
+        reason: String,
+    },
+    BufferPoolExhausted {
+        in_flight: usize,
+        capacity: usize,
+    },
+    // Free device memory is None when the backend cannot query it.
+    MemoryPressure {
+        level: MemoryPressureLevel,
+        free_bytes: Option<u64>,
+        total_bytes: Option<u64>,
+        allocation_failures: u64,
+    },
+    DecoderStalled {
+        waited_ms: u64,
+        outstanding: u64,
+        recovered: bool,
+    },
+    // A re-decoded encoder chunk that did not match what was submitted; see
+    // IntegrityCheckOptions.
+    IntegrityMismatch {
+        pts_90k: Option<Timestamp90k>,
+        reason: String,
+    },
+    Metrics {
+        scope: &'static str,
+        detail: String,
+    },
+}
+
+impl fmt::Display for DiagnosticEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SessionCreated { backend, codec } => {
+                write!(f, "[session.created] backend={backend}, codec={codec}")
+            }
+            Self::Reconfigured {
+                generation,
+                force_idr,
+            } => write!(
+                f,
+                "[session.reconfigured] generation={generation}, force_idr={force_idr}"
+            ),
+            Self::SoftwareFallback { reason } => {
+                write!(f, "[session.software_fallback] reason={reason}")
+            }
+            Self::BackendFallback { from, to, reason } => write!(
+                f,
+                "[session.backend_fallback] from={from}, to={to}, reason={reason}"
+            ),
+            Self::BufferPoolExhausted {
+                in_flight,
+                capacity,
+            } => write!(
+                f,
+                "[buffer_pool.exhausted] in_flight={in_flight}, capacity={capacity}"
+            ),
+            Self::MemoryPressure { .. }
+            | Self::DecoderStalled { .. }
+            | Self::IntegrityMismatch { .. } => {
+                let (name, fields) = self.json_fields();
+                write!(f, "[{name}]")?;
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    let separator = if index == 0 { " " } else { ", " };
+                    write!(f, "{separator}{key}={value}")?;
+                }
+                Ok(())
+            }
+            Self::Metrics { scope, detail } => write!(f, "[{scope}] {detail}"),
+        }
+    }
+}
+
+impl DiagnosticEvent {
+    // The `key=value` pairs of a Metrics detail, in order; other events have none.
+    pub fn metric_fields(&self) -> Vec<(&str, &str)> {
+        match self {
+            Self::Metrics { detail, .. } => detail
+                .split(", ")
+                .filter_map(|field| field.split_once('='))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    // One flat JSON object per event, `{"event":"<scope or kind>",...}`. Numeric and boolean
+    // values keep their type, so metric lines can be loaded without parsing the text form.
+    pub fn to_json(&self) -> String {
+        let (name, fields) = self.json_fields();
+        let mut out = String::from("{\"event\":");
+        write_json_string(&mut out, name);
+        for (key, value) in fields {
+            out.push(',');
+            write_json_string(&mut out, key);
+            out.push(':');
+            if value == "true" || value == "false" || is_json_number(&value) {
+                out.push_str(&value);
+            } else {
+                write_json_string(&mut out, &value);
+            }
+        }
+        out.push('}');
+        out
+    }
+
+    fn json_fields(&self) -> (&str, Vec<(&str, String)>) {
+        match self {
+            Self::SessionCreated { backend, codec } => (
+                "session.created",
+                vec![("backend", backend.clone()), ("codec", codec.to_string())],
+            ),
+            Self::Reconfigured {
+                generation,
+                force_idr,
+            } => (
+                "session.reconfigured",
+                vec![
+                    ("generation", generation.to_string()),
+                    ("force_idr", force_idr.to_string()),
+                ],
+            ),
+            Self::SoftwareFallback { reason } => (
+                "session.software_fallback",
+                vec![("reason", reason.clone())],
+            ),
+            Self::BackendFallback { from, to, reason } => (
+                "session.backend_fallback",
+                vec![
+                    ("from", from.clone()),
+                    ("to", to.clone()),
+                    ("reason", reason.clone()),
+                ],
+            ),
+            Self::BufferPoolExhausted {
+                in_flight,
+                capacity,
+            } => (
+                "buffer_pool.exhausted",
+                vec![
+                    ("in_flight", in_flight.to_string()),
+                    ("capacity", capacity.to_string()),
+                ],
+            ),
+            Self::MemoryPressure {
+                level,
+                free_bytes,
+                total_bytes,
+                allocation_failures,
+            } => {
+                let mut fields = vec![("level", level.to_string())];
+                fields.extend(free_bytes.map(|bytes| ("free_bytes", bytes.to_string())));
+                fields.extend(total_bytes.map(|bytes| ("total_bytes", bytes.to_string())));
+                fields.push(("allocation_failures", allocation_failures.to_string()));
+                ("memory.pressure", fields)
+            }
+            Self::DecoderStalled {
+                waited_ms,
+                outstanding,
+                recovered,
+            } => (
+                "decode.stalled",
+                vec![
+                    ("waited_ms", waited_ms.to_string()),
+                    ("outstanding", outstanding.to_string()),
+                    ("recovered", recovered.to_string()),
+                ],
+            ),
+            Self::IntegrityMismatch { pts_90k, reason } => {
+                let mut fields = Vec::new();
+                fields.extend(pts_90k.map(|pts| ("pts_90k", pts.0.to_string())));
+                fields.push(("reason", reason.clone()));
+                ("encode.integrity_mismatch", fields)
+            }
+            Self::Metrics { scope, .. } => (
+                scope,
+                self.metric_fields()
+                    .into_iter()
+                    .map(|(key, value)| (key, value.to_string()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+fn is_json_number(value: &str) -> bool {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    digits.starts_with(|c: char| c.is_ascii_digit())
+        && value.parse::<f64>().is_ok_and(f64::is_finite)
+}
+
+fn write_json_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// Crate-wide knobs for what reaches stderr, read once: VIDEO_HW_METRICS_FORMAT=json prints
+// DiagnosticEvent::to_json instead of the text form, and VIDEO_HW_METRICS_INTERVAL_MS=N prints
+// at most one Metrics event per scope every N ms across all sessions (the rest are dropped).
+#[derive(Debug)]
+struct StderrConfig {
+    json: bool,
+    metrics_interval: Option<Duration>,
+}
+
+fn stderr_config() -> &'static StderrConfig {
+    static CONFIG: OnceLock<StderrConfig> = OnceLock::new();
+    CONFIG.get_or_init(|| StderrConfig {
+        json: std::env::var("VIDEO_HW_METRICS_FORMAT")
+            .is_ok_and(|format| format.eq_ignore_ascii_case("json")),
+        metrics_interval: std::env::var("VIDEO_HW_METRICS_INTERVAL_MS")
+            .ok()
+            .and_then(|ms| ms.parse().ok())
+            .filter(|&ms| ms > 0)
+            .map(Duration::from_millis),
+    })
+}
+
+#[derive(Debug, Default)]
+struct MetricsRateLimiter {
+    last_printed: Vec<(&'static str, Instant)>,
+}
+
+impl MetricsRateLimiter {
+    fn allow(&mut self, scope: &'static str, interval: Duration, now: Instant) -> bool {
+        match self
+            .last_printed
+            .iter_mut()
+            .find(|(name, _)| *name == scope)
+        {
+            Some((_, last)) if now.saturating_duration_since(*last) < interval => false,
+            Some((_, last)) => {
+                *last = now;
+                true
+            }
+            None => {
+                self.last_printed.push((scope, now));
+                true
+            }
+        }
+    }
+}
+
+fn print_to_stderr(event: &DiagnosticEvent) {
+    static LIMITER: Mutex<MetricsRateLimiter> = Mutex::new(MetricsRateLimiter {
+        last_printed: Vec::new(),
+    });
+    let config = stderr_config();
+    if let (DiagnosticEvent::Metrics { scope, .. }, Some(interval)) =
+        (event, config.metrics_interval)
+        && !LIMITER
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .allow(scope, interval, Instant::now())
+    {
+        return;
+    }
+    if config.json {
+        eprintln!("{}", event.to_json());
+    } else {
+        eprintln!("{event}");
+    }
+}
+
+pub trait DiagnosticsSink: Send + Sync {
+    fn on_event(&self, event: &DiagnosticEvent);
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StderrDiagnostics;
+
+impl DiagnosticsSink for StderrDiagnostics {
+    fn on_event(&self, event: &DiagnosticEvent) {
+        print_to_stderr(event);
+    }
+}
+
+// Sessions built without an explicit sink keep the historical behaviour: metrics lines go to
+// stderr and lifecycle events are dropped.
+#[derive(Debug, Clone, Copy, Default)]
+struct MetricsToStderr;
+
+impl DiagnosticsSink for MetricsToStderr {
+    fn on_event(&self, event: &DiagnosticEvent) {
+        if matches!(event, DiagnosticEvent::Metrics { .. }) {
+            print_to_stderr(event);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Diagnostics(Arc<dyn DiagnosticsSink>);
+
+impl Diagnostics {
+    pub fn new(sink: impl DiagnosticsSink + 'static) -> Self {
+        Self(Arc::new(sink))
+    }
+
+    pub fn from_arc(sink: Arc<dyn DiagnosticsSink>) -> Self {
+        Self(sink)
+    }
+
+    pub fn emit(&self, event: DiagnosticEvent) {
+        self.0.on_event(&event);
+    }
+}
+
+impl Default for Diagnostics {
+    fn default() -> Self {
+        Self::new(MetricsToStderr)
+    }
+}
+
+impl fmt::Debug for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Diagnostics(..)")
+    }
+}
+
+// Keeps every event for tests to inspect; shared by the modules that report diagnostics.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct RecordingDiagnostics(Mutex<Vec<DiagnosticEvent>>);
+
+#[cfg(test)]
+impl RecordingDiagnostics {
+    pub(crate) fn events(&self) -> Vec<DiagnosticEvent> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl DiagnosticsSink for RecordingDiagnostics {
+    fn on_event(&self, event: &DiagnosticEvent) {
+        self.0.lock().unwrap().push(event.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emit_forwards_events_to_sink() {
+        let sink = Arc::new(RecordingDiagnostics::default());
+        let diagnostics = Diagnostics::from_arc(sink.clone());
+        diagnostics.emit(DiagnosticEvent::BufferPoolExhausted {
+            in_flight: 6,
+            capacity: 6,
+        });
+        assert_eq!(
+            sink.events(),
+            vec![DiagnosticEvent::BufferPoolExhausted {
+                in_flight: 6,
+                capacity: 6
+            }]
+        );
+    }
+
+    #[test]
+    fn metrics_display_keeps_scope_prefix() {
+        let event = DiagnosticEvent::Metrics {
+            scope: "nv.decode",
+            detail: "frames=3".to_string(),
+        };
+        assert_eq!(event.to_string(), "[nv.decode] frames=3");
+    }
+
+    #[test]
+    fn json_form_keeps_value_types() {
+        let event = DiagnosticEvent::Metrics {
+            scope: "nv.encode",
+            detail: "frames=12, encode_ms=3.250, flush=true, mode=\"safe\", bad".to_string(),
+        };
+        assert_eq!(
+            event.metric_fields(),
+            [
+                ("frames", "12"),
+                ("encode_ms", "3.250"),
+                ("flush", "true"),
+                ("mode", "\"safe\"")
+            ]
+        );
+        assert_eq!(
+            event.to_json(),
+            r#"{"event":"nv.encode","frames":12,"encode_ms":3.250,"flush":true,"mode":"\"safe\""}"#
+        );
+        let fallback = DiagnosticEvent::SoftwareFallback {
+            reason: "nan".to_string(),
+        };
+        assert_eq!(
+            fallback.to_json(),
+            r#"{"event":"session.software_fallback","reason":"nan"}"#
+        );
+
+        let mut limiter = MetricsRateLimiter::default();
+        let start = Instant::now();
+        let interval = Duration::from_millis(100);
+        assert!(limiter.allow("nv.encode", interval, start));
+        assert!(!limiter.allow("nv.encode", interval, start + Duration::from_millis(50)));
+        assert!(limiter.allow("nv.decode", interval, start + Duration::from_millis(50)));
+        assert!(limiter.allow("nv.encode", interval, start + interval));
+    }
+}
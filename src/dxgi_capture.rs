@@ -0,0 +1,352 @@
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+use windows::Win32::Foundation::{HMODULE, RECT};
+use windows::Win32::Graphics::Direct3D::D3D_DRIVER_TYPE_UNKNOWN;
+use windows::Win32::Graphics::Direct3D11::{
+    D3D11_CPU_ACCESS_READ, D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_MAP_READ,
+    D3D11_MAPPED_SUBRESOURCE, D3D11_SDK_VERSION, D3D11_TEXTURE2D_DESC, D3D11_USAGE_STAGING,
+    D3D11CreateDevice, ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D,
+};
+use windows::Win32::Graphics::Dxgi::Common::{DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_SAMPLE_DESC};
+use windows::Win32::Graphics::Dxgi::{
+    CreateDXGIFactory1, DXGI_ERROR_ACCESS_LOST, DXGI_ERROR_NOT_FOUND, DXGI_ERROR_WAIT_TIMEOUT,
+    DXGI_OUTDUPL_FRAME_INFO, DXGI_OUTDUPL_MOVE_RECT, IDXGIAdapter1, IDXGIFactory1, IDXGIOutput1,
+    IDXGIOutputDuplication, IDXGIResource,
+};
+use windows::Win32::System::Performance::QueryPerformanceFrequency;
+use windows::core::Interface;
+
+use crate::{BackendError, CaptureSource, CapturedFrame, Dimensions, DirtyRect, Timestamp90k};
+
+// Desktop Duplication of one display output. Frames are copied through a
+// staging texture into CPU memory; rotated displays come out in the
+// unrotated scan-out orientation.
+pub struct DxgiCaptureSource {
+    device: ID3D11Device,
+    context: ID3D11DeviceContext,
+    output: IDXGIOutput1,
+    // None after DXGI_ERROR_ACCESS_LOST (mode change, secure desktop); the
+    // next call duplicates the output again.
+    duplication: Option<IDXGIOutputDuplication>,
+    staging: Option<ID3D11Texture2D>,
+    dims: Dimensions,
+    qpc_frequency: i64,
+    first_present: Option<i64>,
+}
+
+impl DxgiCaptureSource {
+    // Outputs are counted across adapters in DXGI enumeration order, so 0 is
+    // the primary display.
+    pub fn new(output_index: usize) -> Result<Self, BackendError> {
+        let factory: IDXGIFactory1 =
+            unsafe { CreateDXGIFactory1() }.map_err(|err| dxgi_error("CreateDXGIFactory1", err))?;
+        let mut remaining = output_index;
+        for adapter_index in 0.. {
+            let adapter = match unsafe { factory.EnumAdapters1(adapter_index) } {
+                Ok(adapter) => adapter,
+                Err(err) if err.code() == DXGI_ERROR_NOT_FOUND => break,
+                Err(err) => return Err(dxgi_error("EnumAdapters1", err)),
+            };
+            for index in 0.. {
+                let output = match unsafe { adapter.EnumOutputs(index) } {
+                    Ok(output) => output,
+                    Err(err) if err.code() == DXGI_ERROR_NOT_FOUND => break,
+                    Err(err) => return Err(dxgi_error("EnumOutputs", err)),
+                };
+                if remaining > 0 {
+                    remaining -= 1;
+                    continue;
+                }
+                let output = output
+                    .cast::<IDXGIOutput1>()
+                    .map_err(|err| dxgi_error("IDXGIOutput1", err))?;
+                return Self::open(&adapter, output);
+            }
+        }
+        Err(BackendError::InvalidInput(format!(
+            "display output {output_index} not found"
+        )))
+    }
+
+    fn open(adapter: &IDXGIAdapter1, output: IDXGIOutput1) -> Result<Self, BackendError> {
+        let mut device = None;
+        let mut context = None;
+        unsafe {
+            D3D11CreateDevice(
+                adapter,
+                D3D_DRIVER_TYPE_UNKNOWN,
+                HMODULE::default(),
+                D3D11_CREATE_DEVICE_BGRA_SUPPORT,
+                None,
+                D3D11_SDK_VERSION,
+                Some(&mut device),
+                None,
+                Some(&mut context),
+            )
+        }
+        .map_err(|err| dxgi_error("D3D11CreateDevice", err))?;
+        let (Some(device), Some(context)) = (device, context) else {
+            return Err(BackendError::Backend(
+                "D3D11CreateDevice returned no device".to_string(),
+            ));
+        };
+
+        let mut qpc_frequency = 0;
+        unsafe { QueryPerformanceFrequency(&mut qpc_frequency) }
+            .map_err(|err| dxgi_error("QueryPerformanceFrequency", err))?;
+
+        let (duplication, dims) = duplicate(&output, &device)?;
+        Ok(Self {
+            device,
+            context,
+            output,
+            duplication: Some(duplication),
+            staging: None,
+            dims,
+            qpc_frequency,
+            first_present: None,
+        })
+    }
+
+    fn read_frame(
+        &mut self,
+        duplication: &IDXGIOutputDuplication,
+        info: &DXGI_OUTDUPL_FRAME_INFO,
+        resource: Option<IDXGIResource>,
+    ) -> Result<Option<CapturedFrame>, BackendError> {
+        // Pointer-only updates leave the desktop image untouched.
+        if info.LastPresentTime == 0 {
+            return Ok(None);
+        }
+        let texture = resource
+            .ok_or_else(|| {
+                BackendError::Backend("AcquireNextFrame returned no surface".to_string())
+            })?
+            .cast::<ID3D11Texture2D>()
+            .map_err(|err| dxgi_error("ID3D11Texture2D", err))?;
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        unsafe { texture.GetDesc(&mut desc) };
+        if desc.Format != DXGI_FORMAT_B8G8R8A8_UNORM {
+            return Err(BackendError::UnsupportedConfig(format!(
+                "desktop surface format {} is not BGRA",
+                desc.Format.0
+            )));
+        }
+        let dims = dimensions(desc.Width, desc.Height)?;
+        let dirty_rects = frame_dirty_rects(duplication, info, dims)?;
+
+        let first_present = *self.first_present.get_or_insert(info.LastPresentTime);
+        let ticks = i128::from(info.LastPresentTime - first_present);
+        let pts = ticks * 90_000 / i128::from(self.qpc_frequency.max(1));
+        let pts_90k = Some(Timestamp90k(pts as i64));
+
+        let staging = self.staging_for(&desc)?;
+        unsafe { self.context.CopyResource(&staging, &texture) };
+        let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+        unsafe {
+            self.context
+                .Map(&staging, 0, D3D11_MAP_READ, 0, Some(&mut mapped))
+        }
+        .map_err(|err| dxgi_error("Map", err))?;
+        let stride = mapped.RowPitch as usize;
+        let data = unsafe {
+            std::slice::from_raw_parts(mapped.pData.cast::<u8>(), stride * desc.Height as usize)
+        };
+        let frame = CapturedFrame::from_bgra_strided(dims, stride, data, pts_90k, dirty_rects);
+        unsafe { self.context.Unmap(&staging, 0) };
+        frame.map(Some)
+    }
+
+    fn staging_for(
+        &mut self,
+        source: &D3D11_TEXTURE2D_DESC,
+    ) -> Result<ID3D11Texture2D, BackendError> {
+        if let Some(staging) = &self.staging {
+            let mut desc = D3D11_TEXTURE2D_DESC::default();
+            unsafe { staging.GetDesc(&mut desc) };
+            if desc.Width == source.Width && desc.Height == source.Height {
+                return Ok(staging.clone());
+            }
+        }
+        let desc = D3D11_TEXTURE2D_DESC {
+            Width: source.Width,
+            Height: source.Height,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: source.Format,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Usage: D3D11_USAGE_STAGING,
+            BindFlags: 0,
+            CPUAccessFlags: D3D11_CPU_ACCESS_READ.0 as u32,
+            MiscFlags: 0,
+        };
+        let mut staging = None;
+        unsafe { self.device.CreateTexture2D(&desc, None, Some(&mut staging)) }
+            .map_err(|err| dxgi_error("CreateTexture2D", err))?;
+        let staging = staging.ok_or_else(|| {
+            BackendError::Backend("CreateTexture2D returned no texture".to_string())
+        })?;
+        self.staging = Some(staging.clone());
+        Ok(staging)
+    }
+}
+
+impl CaptureSource for DxgiCaptureSource {
+    fn dims(&self) -> Dimensions {
+        self.dims
+    }
+
+    fn next_frame(&mut self, timeout: Duration) -> Result<Option<CapturedFrame>, BackendError> {
+        let duplication = match &self.duplication {
+            Some(duplication) => duplication.clone(),
+            None => {
+                let (duplication, dims) = duplicate(&self.output, &self.device)?;
+                self.duplication = Some(duplication.clone());
+                self.dims = dims;
+                duplication
+            }
+        };
+
+        let timeout_ms = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+        let mut info = DXGI_OUTDUPL_FRAME_INFO::default();
+        let mut resource = None;
+        match unsafe { duplication.AcquireNextFrame(timeout_ms, &mut info, &mut resource) } {
+            Ok(()) => {}
+            Err(err) if err.code() == DXGI_ERROR_WAIT_TIMEOUT => return Ok(None),
+            Err(err) if err.code() == DXGI_ERROR_ACCESS_LOST => {
+                self.duplication = None;
+                self.staging = None;
+                return Ok(None);
+            }
+            Err(err) => return Err(dxgi_error("AcquireNextFrame", err)),
+        }
+
+        let frame = self.read_frame(&duplication, &info, resource);
+        match unsafe { duplication.ReleaseFrame() } {
+            Ok(()) => {}
+            Err(err) if err.code() == DXGI_ERROR_ACCESS_LOST => self.duplication = None,
+            Err(err) => return Err(dxgi_error("ReleaseFrame", err)),
+        }
+        frame
+    }
+}
+
+fn duplicate(
+    output: &IDXGIOutput1,
+    device: &ID3D11Device,
+) -> Result<(IDXGIOutputDuplication, Dimensions), BackendError> {
+    let duplication = unsafe { output.DuplicateOutput(device) }
+        .map_err(|err| dxgi_error("DuplicateOutput", err))?;
+    let desc = unsafe { duplication.GetDesc() };
+    let dims = dimensions(desc.ModeDesc.Width, desc.ModeDesc.Height)?;
+    Ok((duplication, dims))
+}
+
+// Move destinations count as changed too: the encoder only sees the final
+// image, not the blit.
+fn frame_dirty_rects(
+    duplication: &IDXGIOutputDuplication,
+    info: &DXGI_OUTDUPL_FRAME_INFO,
+    dims: Dimensions,
+) -> Result<Option<Vec<DirtyRect>>, BackendError> {
+    let capacity = info.TotalMetadataBufferSize as usize;
+    if capacity == 0 {
+        return Ok(None);
+    }
+
+    let move_size = size_of::<DXGI_OUTDUPL_MOVE_RECT>();
+    let mut moves = vec![DXGI_OUTDUPL_MOVE_RECT::default(); capacity / move_size + 1];
+    let mut required = 0;
+    unsafe {
+        duplication.GetFrameMoveRects(
+            (moves.len() * move_size) as u32,
+            moves.as_mut_ptr(),
+            &mut required,
+        )
+    }
+    .map_err(|err| dxgi_error("GetFrameMoveRects", err))?;
+    moves.truncate(required as usize / move_size);
+
+    let rect_size = size_of::<RECT>();
+    let mut dirty = vec![RECT::default(); capacity / rect_size + 1];
+    let mut required = 0;
+    unsafe {
+        duplication.GetFrameDirtyRects(
+            (dirty.len() * rect_size) as u32,
+            dirty.as_mut_ptr(),
+            &mut required,
+        )
+    }
+    .map_err(|err| dxgi_error("GetFrameDirtyRects", err))?;
+    dirty.truncate(required as usize / rect_size);
+
+    Ok(Some(
+        moves
+            .iter()
+            .map(|entry| entry.DestinationRect)
+            .chain(dirty)
+            .filter_map(|rect| clip_rect(rect, dims))
+            .collect(),
+    ))
+}
+
+fn clip_rect(rect: RECT, dims: Dimensions) -> Option<DirtyRect> {
+    let clamp = |value: i32, limit: NonZeroU32| value.clamp(0, limit.get() as i32) as u32;
+    let left = clamp(rect.left, dims.width);
+    let top = clamp(rect.top, dims.height);
+    let right = clamp(rect.right, dims.width);
+    let bottom = clamp(rect.bottom, dims.height);
+    (right > left && bottom > top).then(|| DirtyRect {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+    })
+}
+
+fn dimensions(width: u32, height: u32) -> Result<Dimensions, BackendError> {
+    match (NonZeroU32::new(width), NonZeroU32::new(height)) {
+        (Some(width), Some(height)) => Ok(Dimensions { width, height }),
+        _ => Err(BackendError::Backend(format!(
+            "desktop duplication reported empty output {width}x{height}"
+        ))),
+    }
+}
+
+fn dxgi_error(call: &str, err: windows::core::Error) -> BackendError {
+    BackendError::Backend(format!("{call} failed: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(left: i32, top: i32, right: i32, bottom: i32) -> RECT {
+        RECT {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    #[test]
+    fn clip_rect_keeps_rects_inside_the_output() {
+        let dims = dimensions(64, 32).expect("dims");
+        assert_eq!(
+            clip_rect(rect(-8, 4, 16, 40), dims),
+            Some(DirtyRect {
+                x: 0,
+                y: 4,
+                width: 16,
+                height: 28,
+            })
+        );
+        assert_eq!(clip_rect(rect(70, 0, 80, 8), dims), None);
+        assert_eq!(clip_rect(rect(8, 8, 8, 16), dims), None);
+    }
+}
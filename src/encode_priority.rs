@@ -0,0 +1,181 @@
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EncodePriority {
+    // Batch transcodes that can absorb delay.
+    Background,
+    #[default]
+    Normal,
+    // Live sources (camera, screen) whose frames must not wait behind a batch.
+    Realtime,
+}
+
+impl Display for EncodePriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Background => "background",
+            Self::Normal => "normal",
+            Self::Realtime => "realtime",
+        })
+    }
+}
+
+impl FromStr for EncodePriority {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "background" => Ok(Self::Background),
+            "normal" => Ok(Self::Normal),
+            "realtime" => Ok(Self::Realtime),
+            other => Err(format!("unknown encode priority: {other}")),
+        }
+    }
+}
+
+// Cooperative admission to a shared encode engine. Sessions split with the same arbiter take a
+// permit for every submit and flush; when the engine is at its concurrency limit the next
+// permit goes to the highest-priority waiter, FIFO within a priority. A background batch is
+// therefore overtaken at the next frame boundary rather than finishing its queue first.
+#[derive(Clone)]
+pub struct EncodeArbiter {
+    inner: Arc<ArbiterInner>,
+}
+
+struct ArbiterInner {
+    concurrency: usize,
+    state: Mutex<ArbiterState>,
+    released: Condvar,
+}
+
+#[derive(Default)]
+struct ArbiterState {
+    active: usize,
+    next_ticket: u64,
+    waiting: BTreeSet<(Reverse<EncodePriority>, u64)>,
+}
+
+impl EncodeArbiter {
+    // `concurrency` is how many sessions may be inside the engine at once (at least 1). NVENC
+    // runs a few sessions in parallel efficiently; 1 gives strict priority order.
+    pub fn new(concurrency: usize) -> Self {
+        Self {
+            inner: Arc::new(ArbiterInner {
+                concurrency: concurrency.max(1),
+                state: Mutex::new(ArbiterState::default()),
+                released: Condvar::new(),
+            }),
+        }
+    }
+
+    // Blocks until this caller is the highest-priority waiter and a slot is free.
+    pub fn acquire(&self, priority: EncodePriority) -> EncodePermit {
+        let mut state = self.lock();
+        let key = (Reverse(priority), state.next_ticket);
+        state.next_ticket += 1;
+        state.waiting.insert(key);
+        while state.active >= self.inner.concurrency || state.waiting.first() != Some(&key) {
+            state = self
+                .inner
+                .released
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        state.waiting.remove(&key);
+        state.active += 1;
+        // The next waiter may also fit when concurrency > 1.
+        self.inner.released.notify_all();
+        EncodePermit {
+            arbiter: self.clone(),
+        }
+    }
+
+    pub fn waiting(&self) -> usize {
+        self.lock().waiting.len()
+    }
+
+    pub fn active(&self) -> usize {
+        self.lock().active
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ArbiterState> {
+        self.inner
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl fmt::Debug for EncodeArbiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncodeArbiter")
+            .field("concurrency", &self.inner.concurrency)
+            .field("active", &self.active())
+            .field("waiting", &self.waiting())
+            .finish()
+    }
+}
+
+pub struct EncodePermit {
+    arbiter: EncodeArbiter,
+}
+
+impl Drop for EncodePermit {
+    fn drop(&mut self) {
+        self.arbiter.lock().active -= 1;
+        self.arbiter.inner.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn wait_for_waiters(arbiter: &EncodeArbiter, count: usize) {
+        while arbiter.waiting() < count {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn realtime_waiter_overtakes_earlier_background_waiter() {
+        let arbiter = EncodeArbiter::new(1);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let held = arbiter.acquire(EncodePriority::Normal);
+        let spawn = |priority: EncodePriority| {
+            let arbiter = arbiter.clone();
+            let order = Arc::clone(&order);
+            thread::spawn(move || {
+                let _permit = arbiter.acquire(priority);
+                order.lock().unwrap().push(priority);
+            })
+        };
+        let background = spawn(EncodePriority::Background);
+        wait_for_waiters(&arbiter, 1);
+        let second_background = spawn(EncodePriority::Background);
+        wait_for_waiters(&arbiter, 2);
+        let realtime = spawn(EncodePriority::Realtime);
+        wait_for_waiters(&arbiter, 3);
+        drop(held);
+        for worker in [background, second_background, realtime] {
+            worker.join().unwrap();
+        }
+        assert_eq!(
+            *order.lock().unwrap(),
+            [
+                EncodePriority::Realtime,
+                EncodePriority::Background,
+                EncodePriority::Background
+            ]
+        );
+        assert_eq!((arbiter.active(), arbiter.waiting()), (0, 0));
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::{BackendError, Codec, EncodedChunk};
+
+// Receives chunks straight from the encode session as they are reaped, in output order. A
+// write error is returned from the submit/flush call that produced the chunk.
+pub trait EncodedSink {
+    fn write_chunk(&mut self, chunk: &EncodedChunk) -> Result<(), BackendError>;
+
+    fn flush(&mut self) -> Result<(), BackendError> {
+        Ok(())
+    }
+}
+
+// Rewrites each chunk right after encoding, before it is counted, indexed, queued or handed to
+// a sink, so protection such as SRTP-style payload encryption or CENC subsample encryption
+// happens inside the session. Transforms run in the order they were added; an error fails the
+// submit/flush that produced the chunk.
+pub trait ChunkTransform {
+    fn transform(&mut self, chunk: EncodedChunk) -> Result<EncodedChunk, BackendError>;
+}
+
+impl<F> ChunkTransform for F
+where
+    F: FnMut(EncodedChunk) -> Result<EncodedChunk, BackendError>,
+{
+    fn transform(&mut self, chunk: EncodedChunk) -> Result<EncodedChunk, BackendError> {
+        self(chunk)
+    }
+}
+
+pub(crate) fn apply_chunk_transforms(
+    transforms: &mut [Box<dyn ChunkTransform>],
+    chunk: EncodedChunk,
+) -> Result<EncodedChunk, BackendError> {
+    transforms
+        .iter_mut()
+        .try_fold(chunk, |chunk, transform| transform.transform(chunk))
+}
+
+fn write_error(target: &str, err: std::io::Error) -> BackendError {
+    BackendError::Backend(format!("failed to write encoded output to {target}: {err}"))
+}
+
+// Writes chunk payloads back to back in the layout the backend produced.
+pub struct WriterSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> WriterSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> EncodedSink for WriterSink<W> {
+    fn write_chunk(&mut self, chunk: &EncodedChunk) -> Result<(), BackendError> {
+        self.writer
+            .write_all(&chunk.data)
+            .map_err(|err| write_error("writer", err))
+    }
+
+    fn flush(&mut self) -> Result<(), BackendError> {
+        self.writer
+            .flush()
+            .map_err(|err| write_error("writer", err))
+    }
+}
+
+// Rolls over to `<prefix>-00001.<ext>`, `<prefix>-00002.<ext>`, ... once a file has reached
+// `max_bytes`. Files are only cut in front of a keyframe so each one decodes on its own.
+pub struct ChunkedFileSink {
+    directory: PathBuf,
+    prefix: String,
+    max_bytes: u64,
+    current: Option<(BufWriter<File>, PathBuf)>,
+    current_bytes: u64,
+    next_index: u32,
+    written: Vec<PathBuf>,
+}
+
+impl ChunkedFileSink {
+    pub fn new(directory: impl AsRef<Path>, prefix: impl Into<String>, max_bytes: u64) -> Self {
+        Self {
+            directory: directory.as_ref().to_path_buf(),
+            prefix: prefix.into(),
+            max_bytes: max_bytes.max(1),
+            current: None,
+            current_bytes: 0,
+            next_index: 1,
+            written: Vec::new(),
+        }
+    }
+
+    // Every file opened so far, including the one still being written.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.written
+    }
+
+    fn roll(&mut self, codec: Codec) -> Result<(), BackendError> {
+        self.finish_current()?;
+        let extension = match codec {
+            Codec::H264 => "h264",
+            Codec::Hevc => "h265",
+            Codec::Mjpeg => "mjpeg",
+        };
+        let path = self.directory.join(format!(
+            "{}-{:05}.{extension}",
+            self.prefix, self.next_index
+        ));
+        let file = File::create(&path).map_err(|err| {
+            BackendError::Backend(format!("failed to create {}: {err}", path.display()))
+        })?;
+        self.next_index += 1;
+        self.current_bytes = 0;
+        self.written.push(path.clone());
+        self.current = Some((BufWriter::new(file), path));
+        Ok(())
+    }
+
+    fn finish_current(&mut self) -> Result<(), BackendError> {
+        match self.current.as_mut() {
+            Some((writer, path)) => writer
+                .flush()
+                .map_err(|err| write_error(&path.display().to_string(), err)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl EncodedSink for ChunkedFileSink {
+    fn write_chunk(&mut self, chunk: &EncodedChunk) -> Result<(), BackendError> {
+        if self.current.is_none() || (chunk.is_keyframe && self.current_bytes >= self.max_bytes) {
+            self.roll(chunk.codec)?;
+        }
+        let Some((writer, path)) = self.current.as_mut() else {
+            return Ok(());
+        };
+        writer
+            .write_all(&chunk.data)
+            .map_err(|err| write_error(&path.display().to_string(), err))?;
+        self.current_bytes = self.current_bytes.saturating_add(chunk.data.len() as u64);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), BackendError> {
+        self.finish_current()
+    }
+}
+
+impl Drop for ChunkedFileSink {
+    fn drop(&mut self) {
+        let _ = self.finish_current();
+    }
+}
+
+// Keeps the most recent chunks within a byte budget, e.g. for "save the last N seconds" clips.
+// The sink is moved into the session; readers use a RingBufferHandle.
+pub struct RingBufferSink {
+    shared: Arc<Mutex<RingBuffer>>,
+}
+
+#[derive(Clone)]
+pub struct RingBufferHandle {
+    shared: Arc<Mutex<RingBuffer>>,
+}
+
+struct RingBuffer {
+    chunks: VecDeque<EncodedChunk>,
+    bytes: usize,
+    capacity_bytes: usize,
+}
+
+impl RingBufferSink {
+    pub fn new(capacity_bytes: usize) -> Self {
+        Self {
+            shared: Arc::new(Mutex::new(RingBuffer {
+                chunks: VecDeque::new(),
+                bytes: 0,
+                capacity_bytes,
+            })),
+        }
+    }
+
+    pub fn handle(&self) -> RingBufferHandle {
+        RingBufferHandle {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl EncodedSink for RingBufferSink {
+    fn write_chunk(&mut self, chunk: &EncodedChunk) -> Result<(), BackendError> {
+        let mut ring = self
+            .shared
+            .lock()
+            .map_err(|_| BackendError::Backend("ring buffer lock poisoned".to_string()))?;
+        ring.bytes = ring.bytes.saturating_add(chunk.data.len());
+        ring.chunks.push_back(chunk.clone());
+        // The newest chunk always stays, even when it alone exceeds the budget.
+        while ring.bytes > ring.capacity_bytes && ring.chunks.len() > 1 {
+            if let Some(evicted) = ring.chunks.pop_front() {
+                ring.bytes -= evicted.data.len();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RingBufferHandle {
+    pub fn byte_len(&self) -> usize {
+        self.shared.lock().map_or(0, |ring| ring.bytes)
+    }
+
+    // Buffered chunks starting at the oldest keyframe, so the result is decodable on its own.
+    pub fn snapshot(&self) -> Vec<EncodedChunk> {
+        let Ok(ring) = self.shared.lock() else {
+            return Vec::new();
+        };
+        ring.chunks
+            .iter()
+            .skip_while(|chunk| !chunk.is_keyframe)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EncodedLayout, Timestamp90k};
+
+    fn chunk(index: i64, is_keyframe: bool) -> EncodedChunk {
+        let header = if is_keyframe { 0x65 } else { 0x41 };
+        EncodedChunk {
+            codec: Codec::H264,
+            layout: EncodedLayout::AnnexB,
+            data: vec![0, 0, 0, 1, header, index as u8].into(),
+            pts_90k: Some(Timestamp90k(index * 3000)),
+            is_keyframe,
+            filler_bytes: 0,
+            dts_90k: None,
+            display_index: None,
+        }
+    }
+
+    #[test]
+    fn chunk_transforms_run_in_order() {
+        struct Tag(u8);
+        impl ChunkTransform for Tag {
+            fn transform(&mut self, mut chunk: EncodedChunk) -> Result<EncodedChunk, BackendError> {
+                chunk.data = [&chunk.data[..], &[self.0]].concat().into();
+                Ok(chunk)
+            }
+        }
+        let xor = |mut chunk: EncodedChunk| {
+            chunk.data = chunk.data.iter().map(|byte| byte ^ 0xFF).collect();
+            Ok(chunk)
+        };
+        let mut transforms: Vec<Box<dyn ChunkTransform>> = vec![Box::new(xor), Box::new(Tag(7))];
+        let out = apply_chunk_transforms(&mut transforms, chunk(1, true)).unwrap();
+        assert_eq!(&out.data[..], &[0xFF, 0xFF, 0xFF, 0xFE, 0x9A, 0xFE, 7]);
+        assert!(out.is_keyframe);
+
+        let mut failing: Vec<Box<dyn ChunkTransform>> = vec![Box::new(|_| {
+            Err(BackendError::Backend("key unavailable".to_string()))
+        })];
+        assert!(apply_chunk_transforms(&mut failing, chunk(2, false)).is_err());
+    }
+
+    #[test]
+    fn ring_buffer_evicts_oldest_and_snapshots_from_keyframe() {
+        let sink_bytes = 6 * 5;
+        let mut sink = RingBufferSink::new(sink_bytes);
+        let handle = sink.handle();
+        for index in 0..8 {
+            sink.write_chunk(&chunk(index, index % 4 == 0)).unwrap();
+        }
+        assert_eq!(handle.byte_len(), sink_bytes);
+        // Chunks 3..8 remain; the snapshot starts at keyframe 4.
+        assert_eq!(
+            handle
+                .snapshot()
+                .iter()
+                .map(|chunk| chunk.pts_90k.unwrap().0 / 3000)
+                .collect::<Vec<_>>(),
+            vec![4, 5, 6, 7]
+        );
+    }
+
+    #[test]
+    fn chunked_file_sink_only_cuts_before_keyframes() {
+        let directory = std::env::temp_dir().join(format!("video-hw-sink-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut sink = ChunkedFileSink::new(&directory, "clip", 12);
+        for index in 0..6 {
+            sink.write_chunk(&chunk(index, index % 3 == 0)).unwrap();
+        }
+        sink.flush().unwrap();
+        let files = sink.files().to_vec();
+        assert_eq!(
+            files
+                .iter()
+                .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect::<Vec<_>>(),
+            vec!["clip-00001.h264", "clip-00002.h264"]
+        );
+        assert_eq!(std::fs::metadata(&files[0]).unwrap().len(), 18);
+        drop(sink);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn writer_sink_concatenates_payloads() {
+        let mut sink = WriterSink::new(Vec::new());
+        sink.write_chunk(&chunk(0, true)).unwrap();
+        sink.write_chunk(&chunk(1, false)).unwrap();
+        assert_eq!(sink.into_inner().len(), 12);
+    }
+}
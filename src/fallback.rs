@@ -0,0 +1,272 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::{
+    BackendError, BackendKind, DecoderConfig, DecoderInner, DiagnosticEvent, Diagnostics,
+    EncodedPacket, EncoderConfig, EncoderInner, FallbackPolicy, Frame, VideoDecoder, VideoEncoder,
+    build_decoder_inner, build_encoder_inner, reap_cancel::ReadyWaker,
+};
+
+// Backends open their device session lazily on the first push, so a missing driver or an
+// exhausted NVENC session limit only shows up there. Until the first output proves the backend
+// works, the session keeps every input so the next candidate can be fed the same stream.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct BackendCandidate {
+    pub(crate) kind: BackendKind,
+    pub(crate) software: bool,
+}
+
+impl fmt::Display for BackendCandidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.software {
+            write!(f, "{}(software)", self.kind)
+        } else {
+            write!(f, "{}", self.kind)
+        }
+    }
+}
+
+pub(crate) fn candidate_chain(
+    primary: BackendKind,
+    policy: FallbackPolicy,
+    software_capable: bool,
+) -> Vec<BackendCandidate> {
+    let mut kinds = vec![primary];
+    if policy.allow_cross_vendor {
+        for kind in crate::preferred_backend_order() {
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+    }
+    let hardware = kinds.iter().map(|&kind| BackendCandidate {
+        kind,
+        software: false,
+    });
+    let software = kinds
+        .iter()
+        .filter(|_| policy.allow_software && software_capable)
+        .map(|&kind| BackendCandidate {
+            kind,
+            software: true,
+        });
+    if policy.prefer_hardware {
+        hardware.chain(software).collect()
+    } else {
+        software.chain(hardware).collect()
+    }
+}
+
+fn is_session_creation_error(err: &BackendError) -> bool {
+    matches!(
+        err,
+        BackendError::UnsupportedCodec(_)
+            | BackendError::UnsupportedConfig(_)
+            | BackendError::DeviceLost(_)
+    )
+}
+
+struct FallbackChain {
+    diagnostics: Diagnostics,
+    current: BackendCandidate,
+    remaining: VecDeque<BackendCandidate>,
+}
+
+impl FallbackChain {
+    fn start(
+        primary: BackendKind,
+        policy: FallbackPolicy,
+        software_capable: bool,
+        diagnostics: &Diagnostics,
+    ) -> Self {
+        let mut remaining = VecDeque::from(candidate_chain(primary, policy, software_capable));
+        let current = remaining.pop_front().unwrap_or(BackendCandidate {
+            kind: primary,
+            software: false,
+        });
+        Self {
+            diagnostics: diagnostics.clone(),
+            current,
+            remaining,
+        }
+    }
+
+    fn advance(&mut self, err: BackendError) -> Result<BackendCandidate, BackendError> {
+        if !is_session_creation_error(&err) {
+            return Err(err);
+        }
+        let Some(next) = self.remaining.pop_front() else {
+            return Err(err);
+        };
+        self.diagnostics.emit(DiagnosticEvent::BackendFallback {
+            from: self.current.to_string(),
+            to: next.to_string(),
+            reason: err.to_string(),
+        });
+        self.current = next;
+        Ok(next)
+    }
+}
+
+pub(crate) struct DecodeFallback {
+    chain: FallbackChain,
+    config: DecoderConfig,
+    ready_waker: ReadyWaker,
+    replay: Vec<(Vec<u8>, Option<i64>)>,
+}
+
+impl DecodeFallback {
+    pub(crate) fn start(
+        primary: BackendKind,
+        config: DecoderConfig,
+        diagnostics: &Diagnostics,
+        ready_waker: ReadyWaker,
+    ) -> (DecoderInner, Option<Self>) {
+        // Software decode is only a candidate when the caller left both knobs open.
+        let software_capable = !config.require_hardware && !config.force_software;
+        let chain = FallbackChain::start(
+            primary,
+            config.fallback_policy,
+            software_capable,
+            diagnostics,
+        );
+        let mut inner =
+            build_decoder_inner(chain.current.kind, candidate_config(&config, chain.current));
+        inner.set_diagnostics(diagnostics.clone());
+        inner.set_ready_waker(ready_waker.clone());
+        let fallback = (!chain.remaining.is_empty()).then(|| Self {
+            chain,
+            config,
+            ready_waker,
+            replay: Vec::new(),
+        });
+        (inner, fallback)
+    }
+
+    pub(crate) fn record(&mut self, chunk: &[u8], pts_90k: Option<i64>) {
+        self.replay.push((chunk.to_vec(), pts_90k));
+    }
+
+    pub(crate) fn recover(
+        &mut self,
+        inner: &mut DecoderInner,
+        mut err: BackendError,
+    ) -> Result<Vec<Frame>, BackendError> {
+        loop {
+            let next = self.chain.advance(err)?;
+            *inner = build_decoder_inner(next.kind, candidate_config(&self.config, next));
+            inner.set_diagnostics(self.chain.diagnostics.clone());
+            inner.set_ready_waker(self.ready_waker.clone());
+            let mut frames = Vec::new();
+            let replayed = self.replay.iter().try_for_each(|(chunk, pts_90k)| {
+                frames.extend(inner.push_bitstream_chunk(chunk, *pts_90k)?);
+                Ok(())
+            });
+            match replayed {
+                Ok(()) => return Ok(frames),
+                Err(replay_err) => err = replay_err,
+            }
+        }
+    }
+}
+
+fn candidate_config(config: &DecoderConfig, candidate: BackendCandidate) -> DecoderConfig {
+    let mut config = config.clone();
+    config.force_software |= candidate.software;
+    config
+}
+
+pub(crate) struct EncodeFallback {
+    chain: FallbackChain,
+    config: EncoderConfig,
+    replay: Vec<Frame>,
+}
+
+impl EncodeFallback {
+    pub(crate) fn start(
+        primary: BackendKind,
+        config: EncoderConfig,
+        diagnostics: &Diagnostics,
+    ) -> (EncoderInner, Option<Self>) {
+        // There is no software encoder path, so the chain only walks hardware backends.
+        let chain = FallbackChain::start(primary, config.fallback_policy, false, diagnostics);
+        let mut inner = build_encoder_inner(chain.current.kind, config.clone());
+        inner.set_diagnostics(diagnostics.clone());
+        let fallback = (!chain.remaining.is_empty()).then(|| Self {
+            chain,
+            config,
+            replay: Vec::new(),
+        });
+        (inner, fallback)
+    }
+
+    pub(crate) fn backend_kind(&self) -> BackendKind {
+        self.chain.current.kind
+    }
+
+    pub(crate) fn record(&mut self, frame: &Frame) {
+        self.replay.push(frame.clone());
+    }
+
+    pub(crate) fn recover(
+        &mut self,
+        inner: &mut EncoderInner,
+        mut err: BackendError,
+    ) -> Result<Vec<EncodedPacket>, BackendError> {
+        loop {
+            let next = self.chain.advance(err)?;
+            *inner = build_encoder_inner(next.kind, self.config.clone());
+            inner.set_diagnostics(self.chain.diagnostics.clone());
+            let mut packets = Vec::new();
+            let replayed = self.replay.iter().try_for_each(|frame| {
+                packets.extend(inner.push_frame(frame.clone())?);
+                Ok(())
+            });
+            match replayed {
+                Ok(()) => return Ok(packets),
+                Err(replay_err) => err = replay_err,
+            }
+        }
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_policy_has_no_fallback_candidates() {
+        let chain = candidate_chain(BackendKind::Nvidia, FallbackPolicy::default(), true);
+        assert_eq!(
+            chain,
+            vec![BackendCandidate {
+                kind: BackendKind::Nvidia,
+                software: false
+            }]
+        );
+    }
+
+    #[test]
+    fn prefer_hardware_orders_software_last() {
+        let policy = FallbackPolicy {
+            prefer_hardware: false,
+            allow_software: true,
+            allow_cross_vendor: false,
+        };
+        let chain = candidate_chain(BackendKind::Nvidia, policy, true);
+        assert_eq!(
+            chain.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec!["nvidia(software)", "nvidia"]
+        );
+        let chain = candidate_chain(BackendKind::Nvidia, FallbackPolicy::permissive(), true);
+        assert_eq!(
+            chain.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec!["nvidia", "nvidia(software)"]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::VecDeque;
+
+use crate::{DecodedFrame, Timestamp90k};
+
+// Reports kept until take_frame_analyses; the oldest go first.
+const MAX_PENDING: usize = 1024;
+// Frames whose pixels are already in CPU memory are sampled on this grid, which keeps the
+// cost well below a copy of the frame.
+const CPU_SAMPLE_STEP: usize = 4;
+
+// Thresholds on the 8-bit luma scale. A pixel is dark at or below `black_luma_max` (video
+// black is 16), and a frame is black once `black_ratio` of its pixels are dark. A frame is
+// frozen when at most `freeze_difference` of its pixels changed histogram bin against the
+// previous frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameAnalysisOptions {
+    pub black_luma_max: u8,
+    pub black_ratio: f32,
+    pub freeze_difference: f32,
+}
+
+impl Default for FrameAnalysisOptions {
+    fn default() -> Self {
+        Self {
+            black_luma_max: 32,
+            black_ratio: 0.98,
+            freeze_difference: 0.001,
+        }
+    }
+}
+
+// Per-frame luma statistics for feed monitoring. `black_run` and `frozen_run` count the
+// consecutive analyzed frames, this one included, that were black or frozen, so a dead feed
+// shows up as a run passing some alerting threshold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameAnalysis {
+    pub pts_90k: Option<Timestamp90k>,
+    pub luma_mean: f32,
+    pub luma_variance: f32,
+    pub black: bool,
+    pub frozen: bool,
+    pub black_run: u32,
+    pub frozen_run: u32,
+}
+
+// Works from a 256-bin luma histogram: the one NVDEC computes on the GPU while
+// post-processing each picture (NvidiaDecoderOptions::histogram), or a sampled one for
+// frames that were copied to the CPU anyway. Frames with neither (GPU surfaces without a
+// histogram) are not analyzed.
+#[derive(Debug, Default)]
+pub(crate) struct FrameAnalyzer {
+    options: Option<FrameAnalysisOptions>,
+    previous: Option<Box<[u32; 256]>>,
+    black_run: u32,
+    frozen_run: u32,
+    pending: VecDeque<FrameAnalysis>,
+}
+
+impl FrameAnalyzer {
+    pub(crate) fn set_options(&mut self, options: Option<FrameAnalysisOptions>) {
+        self.options = options;
+        self.previous = None;
+        self.black_run = 0;
+        self.frozen_run = 0;
+    }
+
+    pub(crate) fn options(&self) -> Option<FrameAnalysisOptions> {
+        self.options
+    }
+
+    pub(crate) fn observe(&mut self, frame: &DecodedFrame) {
+        let Some(options) = self.options else {
+            return;
+        };
+        let Some(histogram) = luma_histogram(frame) else {
+            return;
+        };
+        let total = histogram.iter().map(|&count| u64::from(count)).sum::<u64>();
+        if total == 0 {
+            return;
+        }
+        let (sum, square_sum) = histogram.iter().zip(0_u64..).fold(
+            (0_u64, 0_u64),
+            |(sum, square_sum), (&count, luma)| {
+                let count = u64::from(count);
+                (sum + count * luma, square_sum + count * luma * luma)
+            },
+        );
+        let mean = sum as f64 / total as f64;
+        let variance = (square_sum as f64 / total as f64 - mean * mean).max(0.0);
+        let dark = histogram[..=usize::from(options.black_luma_max)]
+            .iter()
+            .map(|&count| u64::from(count))
+            .sum::<u64>();
+        let black = dark as f64 >= f64::from(options.black_ratio) * total as f64;
+        let frozen = self
+            .previous
+            .as_ref()
+            .is_some_and(|previous| moved_share(previous, &histogram) <= options.freeze_difference);
+        self.black_run = if black { self.black_run + 1 } else { 0 };
+        self.frozen_run = if frozen { self.frozen_run + 1 } else { 0 };
+        self.previous = Some(histogram);
+        if self.pending.len() == MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(FrameAnalysis {
+            pts_90k: frame.pts_90k(),
+            luma_mean: mean as f32,
+            luma_variance: variance as f32,
+            black,
+            frozen,
+            black_run: self.black_run,
+            frozen_run: self.frozen_run,
+        });
+    }
+
+    pub(crate) fn take(&mut self) -> Vec<FrameAnalysis> {
+        self.pending.drain(..).collect()
+    }
+}
+
+// Share of pixels that would have to change bin to turn one histogram into the other, with
+// both scaled to the same pixel count.
+fn moved_share(previous: &[u32; 256], current: &[u32; 256]) -> f32 {
+    let previous_total = previous.iter().map(|&count| f64::from(count)).sum::<f64>();
+    let current_total = current.iter().map(|&count| f64::from(count)).sum::<f64>();
+    if previous_total == 0.0 {
+        return 1.0;
+    }
+    let distance = previous
+        .iter()
+        .zip(current)
+        .map(|(&before, &after)| {
+            (f64::from(before) / previous_total - f64::from(after) / current_total).abs()
+        })
+        .sum::<f64>();
+    (distance / 2.0) as f32
+}
+
+fn luma_histogram(frame: &DecodedFrame) -> Option<Box<[u32; 256]>> {
+    let mut histogram = Box::new([0_u32; 256]);
+    match frame {
+        DecodedFrame::Metadata { histogram, .. } => return histogram.clone(),
+        DecodedFrame::Nv12 {
+            dims, planes, data, ..
+        } => {
+            let luma = planes.first()?;
+            let width = dims.width.get() as usize;
+            for row in (0..luma.rows).step_by(CPU_SAMPLE_STEP) {
+                let start = luma.offset + row * luma.stride;
+                let line = data.get(start..start + width)?;
+                for &value in line.iter().step_by(CPU_SAMPLE_STEP) {
+                    histogram[usize::from(value)] += 1;
+                }
+            }
+        }
+        DecodedFrame::Rgb24 {
+            dims, planes, data, ..
+        } => {
+            let plane = planes.first()?;
+            let width = dims.width.get() as usize;
+            for row in (0..plane.rows).step_by(CPU_SAMPLE_STEP) {
+                let start = plane.offset + row * plane.stride;
+                let line = data.get(start..start + width * 3)?;
+                for pixel in line.chunks_exact(3).step_by(CPU_SAMPLE_STEP) {
+                    // BT.601 luma in 8-bit fixed point.
+                    let luma = (77 * u32::from(pixel[0])
+                        + 150 * u32::from(pixel[1])
+                        + 29 * u32::from(pixel[2]))
+                        >> 8;
+                    histogram[luma as usize] += 1;
+                }
+            }
+        }
+    }
+    Some(histogram)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+    use crate::{Dimensions, PlaneLayout};
+
+    fn metadata(pts: i64, histogram: [u32; 256]) -> DecodedFrame {
+        DecodedFrame::Metadata {
+            dims: None,
+            pts_90k: Some(Timestamp90k(pts)),
+            pixel_format: None,
+            decode_info_flags: None,
+            color: None,
+            planes: None,
+            histogram: Some(Box::new(histogram)),
+            view: None,
+        }
+    }
+
+    #[test]
+    fn black_and_frozen_runs_follow_the_luma_histogram() {
+        let mut analyzer = FrameAnalyzer::default();
+        let mut flat = [0_u32; 256];
+        flat[100] = 500;
+        flat[200] = 500;
+        analyzer.observe(&metadata(0, flat));
+        assert!(analyzer.take().is_empty());
+
+        analyzer.set_options(Some(FrameAnalysisOptions::default()));
+        let mut black = [0_u32; 256];
+        black[16] = 995;
+        black[180] = 5;
+        for pts in [0, 3000, 6000] {
+            analyzer.observe(&metadata(pts, flat));
+        }
+        analyzer.observe(&metadata(9000, black));
+        analyzer.observe(&metadata(12000, black));
+        let reports = analyzer.take();
+        assert_eq!(reports.len(), 5);
+        assert_eq!(reports[0].luma_mean, 150.0);
+        assert_eq!(reports[0].luma_variance, 2500.0);
+        assert!(!reports[0].frozen);
+        assert_eq!(
+            reports.iter().map(|r| r.frozen_run).collect::<Vec<_>>(),
+            [0, 1, 2, 0, 1]
+        );
+        assert_eq!(
+            reports.iter().map(|r| r.black_run).collect::<Vec<_>>(),
+            [0, 0, 0, 1, 2]
+        );
+        assert_eq!(reports[4].pts_90k, Some(Timestamp90k(12000)));
+
+        // CPU frames are sampled from the luma plane; a video-black NV12 picture extends the run.
+        let (width, height) = (64, 32);
+        let mut data = vec![128_u8; width * height * 3 / 2];
+        data[..width * height].fill(16);
+        analyzer.observe(&DecodedFrame::Nv12 {
+            dims: Dimensions {
+                width: NonZeroU32::new(width as u32).unwrap(),
+                height: NonZeroU32::new(height as u32).unwrap(),
+            },
+            pitch: width,
+            pts_90k: None,
+            planes: PlaneLayout::nv12(width, height),
+            data,
+        });
+        let [report] = analyzer.take()[..] else {
+            panic!("one report per analyzed frame");
+        };
+        assert_eq!((report.luma_mean, report.black_run), (16.0, 3));
+    }
+}
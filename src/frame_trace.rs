@@ -0,0 +1,240 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+use crate::Timestamp90k;
+
+// Traces still inside the session; older entries belong to frames the backend dropped.
+const MAX_IN_FLIGHT: usize = 256;
+// Reaped traces kept until take_frame_traces; the oldest go first.
+const MAX_COMPLETED: usize = 1024;
+
+// Points a frame passes on its way through a session. Decode: Submitted (submit called),
+// Assembled (input repacked as Annex B), HardwareIn (handed to the backend, whose own
+// access unit assembly counts towards the hardware), HardwareOut (returned by the backend),
+// Reaped. Encode: Submitted, Transformed (pre-encode hooks done), HardwareIn, HardwareOut
+// (chunk returned by the encoder, before filler and chunk transforms), Reaped (returned to
+// the caller or written to the sink).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TraceStage {
+    Submitted,
+    Assembled,
+    Transformed,
+    HardwareIn,
+    HardwareOut,
+    Reaped,
+}
+
+impl TraceStage {
+    const ALL: [Self; 6] = [
+        Self::Submitted,
+        Self::Assembled,
+        Self::Transformed,
+        Self::HardwareIn,
+        Self::HardwareOut,
+        Self::Reaped,
+    ];
+}
+
+impl Display for TraceStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Submitted => "submitted",
+            Self::Assembled => "assembled",
+            Self::Transformed => "transformed",
+            Self::HardwareIn => "hardware_in",
+            Self::HardwareOut => "hardware_out",
+            Self::Reaped => "reaped",
+        })
+    }
+}
+
+// When one frame passed each stage, matched from input to output by pts (untimed frames are
+// not traced). Stages a session does not pass are None.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameTrace {
+    pub pts_90k: Timestamp90k,
+    stamps: [Option<Instant>; 6],
+}
+
+impl FrameTrace {
+    fn new(pts_90k: Timestamp90k) -> Self {
+        Self {
+            pts_90k,
+            stamps: [None; 6],
+        }
+    }
+
+    pub fn at(&self, stage: TraceStage) -> Option<Instant> {
+        self.stamps[stage as usize]
+    }
+
+    // Time spent getting from the previous recorded stage to `stage`.
+    pub fn stage_latency(&self, stage: TraceStage) -> Option<Duration> {
+        let at = self.at(stage)?;
+        let previous = self.stamps[..stage as usize]
+            .iter()
+            .rev()
+            .find_map(|s| *s)?;
+        Some(at.saturating_duration_since(previous))
+    }
+
+    // Submitted to Reaped.
+    pub fn total(&self) -> Option<Duration> {
+        Some(
+            self.at(TraceStage::Reaped)?
+                .saturating_duration_since(self.at(TraceStage::Submitted)?),
+        )
+    }
+
+    // The stage a latency spike belongs to.
+    pub fn slowest_stage(&self) -> Option<(TraceStage, Duration)> {
+        TraceStage::ALL
+            .into_iter()
+            .filter_map(|stage| Some((stage, self.stage_latency(stage)?)))
+            .max_by_key(|(_, latency)| *latency)
+    }
+
+    fn stamp(&mut self, stage: TraceStage, now: Instant) {
+        self.stamps[stage as usize].get_or_insert(now);
+    }
+}
+
+impl Display for FrameTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FrameTrace(pts_90k={}", self.pts_90k.0)?;
+        for stage in TraceStage::ALL {
+            if let Some(latency) = self.stage_latency(stage) {
+                write!(f, ", {stage}_ms={:.3}", latency.as_secs_f64() * 1_000.0)?;
+            }
+        }
+        f.write_str(")")
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct FrameTracer {
+    enabled: bool,
+    in_flight: VecDeque<FrameTrace>,
+    completed: VecDeque<FrameTrace>,
+}
+
+impl FrameTracer {
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.in_flight.clear();
+        }
+    }
+
+    pub(crate) fn submitted(&mut self, pts_90k: Option<Timestamp90k>, now: Instant) {
+        let Some(pts) = pts_90k.filter(|_| self.enabled) else {
+            return;
+        };
+        if self.in_flight.len() == MAX_IN_FLIGHT {
+            self.in_flight.pop_front();
+        }
+        let mut trace = FrameTrace::new(pts);
+        trace.stamp(TraceStage::Submitted, now);
+        self.in_flight.push_back(trace);
+    }
+
+    // The oldest in-flight frame with this pts that has not passed `stage` yet.
+    pub(crate) fn stamp(&mut self, pts_90k: Option<Timestamp90k>, stage: TraceStage, now: Instant) {
+        if let Some(trace) = self.find(pts_90k, stage) {
+            trace.stamp(stage, now);
+        }
+    }
+
+    pub(crate) fn reaped(&mut self, pts_90k: Option<Timestamp90k>, now: Instant) {
+        let Some(pts) = pts_90k.filter(|_| self.enabled) else {
+            return;
+        };
+        let Some(mut trace) = self
+            .in_flight
+            .iter()
+            .position(|trace| trace.pts_90k == pts)
+            .and_then(|position| self.in_flight.remove(position))
+        else {
+            return;
+        };
+        trace.stamp(TraceStage::Reaped, now);
+        if self.completed.len() == MAX_COMPLETED {
+            self.completed.pop_front();
+        }
+        self.completed.push_back(trace);
+    }
+
+    // The frame never reached the backend.
+    pub(crate) fn discard(&mut self, pts_90k: Option<Timestamp90k>) {
+        if let Some(pts) = pts_90k {
+            self.in_flight.retain(|trace| trace.pts_90k != pts);
+        }
+    }
+
+    pub(crate) fn take_completed(&mut self) -> Vec<FrameTrace> {
+        self.completed.drain(..).collect()
+    }
+
+    fn find(
+        &mut self,
+        pts_90k: Option<Timestamp90k>,
+        stage: TraceStage,
+    ) -> Option<&mut FrameTrace> {
+        let pts = pts_90k.filter(|_| self.enabled)?;
+        self.in_flight
+            .iter_mut()
+            .find(|trace| trace.pts_90k == pts && trace.at(stage).is_none())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traces_attribute_latency_to_stages() {
+        let start = Instant::now();
+        let ms = |n: u64| start + Duration::from_millis(n);
+        let mut tracer = FrameTracer::default();
+        tracer.submitted(Some(Timestamp90k(0)), ms(0));
+        assert!(tracer.in_flight.is_empty());
+
+        tracer.set_enabled(true);
+        for pts in [0, 3000] {
+            tracer.submitted(Some(Timestamp90k(pts)), ms(pts as u64 / 300));
+            tracer.stamp(
+                Some(Timestamp90k(pts)),
+                TraceStage::HardwareIn,
+                ms(pts as u64 / 300 + 1),
+            );
+        }
+        tracer.submitted(None, ms(20));
+        tracer.stamp(Some(Timestamp90k(3000)), TraceStage::HardwareOut, ms(14));
+        tracer.reaped(Some(Timestamp90k(3000)), ms(15));
+        tracer.stamp(Some(Timestamp90k(0)), TraceStage::HardwareOut, ms(40));
+        tracer.reaped(Some(Timestamp90k(0)), ms(41));
+        tracer.reaped(Some(Timestamp90k(0)), ms(42));
+
+        let traces = tracer.take_completed();
+        assert_eq!(
+            traces
+                .iter()
+                .map(|trace| trace.pts_90k.0)
+                .collect::<Vec<_>>(),
+            [3000, 0]
+        );
+        let slow = &traces[1];
+        assert_eq!(slow.total(), Some(Duration::from_millis(41)));
+        assert_eq!(
+            slow.slowest_stage(),
+            Some((TraceStage::HardwareOut, Duration::from_millis(39)))
+        );
+        assert_eq!(slow.stage_latency(TraceStage::Assembled), None);
+        assert_eq!(
+            slow.to_string(),
+            "FrameTrace(pts_90k=0, hardware_in_ms=1.000, hardware_out_ms=39.000, reaped_ms=1.000)"
+        );
+        assert!(tracer.take_completed().is_empty());
+    }
+}
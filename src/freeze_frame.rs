@@ -0,0 +1,153 @@
+use crate::{
+    BackendPixelFormat, ColorMetadata, DecodeInfoFlags, DecodedFrame, Dimensions, PlaneLayout,
+    Timestamp90k,
+};
+
+#[derive(Debug, Clone)]
+struct LastGood {
+    dims: Option<Dimensions>,
+    pixel_format: Option<BackendPixelFormat>,
+    color: Option<ColorMetadata>,
+    planes: Option<Vec<PlaneLayout>>,
+}
+
+// Error concealment for real-time receivers: when an access unit is lost mid-GOP the session
+// can queue a metadata-only stand-in for it, shaped like the last good frame and flagged
+// FREEZE_FRAME, so the renderer deliberately holds the previous image at that pts instead of
+// seeing nothing until the next keyframe arrives.
+#[derive(Debug, Default)]
+pub(crate) struct FreezeFrameConcealer {
+    enabled: bool,
+    last_good: Option<LastGood>,
+    injected: u64,
+}
+
+impl FreezeFrameConcealer {
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub(crate) fn injected(&self) -> u64 {
+        self.injected
+    }
+
+    pub(crate) fn observe(&mut self, frame: &DecodedFrame) {
+        let last_good = match frame {
+            DecodedFrame::Metadata {
+                dims,
+                pixel_format,
+                decode_info_flags,
+                color,
+                planes,
+                ..
+            } => {
+                let flags = decode_info_flags.unwrap_or_default();
+                if flags.intersects(
+                    DecodeInfoFlags::CORRUPTED
+                        | DecodeInfoFlags::FRAME_DROPPED
+                        | DecodeInfoFlags::FREEZE_FRAME,
+                ) {
+                    return;
+                }
+                LastGood {
+                    dims: *dims,
+                    pixel_format: *pixel_format,
+                    color: *color,
+                    planes: planes.clone(),
+                }
+            }
+            DecodedFrame::Nv12 { dims, planes, .. } | DecodedFrame::Rgb24 { dims, planes, .. } => {
+                LastGood {
+                    dims: Some(*dims),
+                    pixel_format: None,
+                    color: None,
+                    planes: Some(planes.clone()),
+                }
+            }
+        };
+        self.last_good = Some(last_good);
+    }
+
+    // None while disabled or before the first good frame, when there is nothing to hold.
+    pub(crate) fn conceal(&mut self, pts_90k: Option<Timestamp90k>) -> Option<DecodedFrame> {
+        if !self.enabled {
+            return None;
+        }
+        let last_good = self.last_good.clone()?;
+        self.injected += 1;
+        Some(DecodedFrame::Metadata {
+            dims: last_good.dims,
+            pts_90k,
+            pixel_format: last_good.pixel_format,
+            decode_info_flags: Some(DecodeInfoFlags::FREEZE_FRAME),
+            color: last_good.color,
+            planes: last_good.planes,
+            histogram: None,
+            view: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+
+    fn frame(pts: i64, flags: DecodeInfoFlags) -> DecodedFrame {
+        let dims = Dimensions {
+            width: NonZeroU32::new(640).unwrap(),
+            height: NonZeroU32::new(360).unwrap(),
+        };
+        let mut frame =
+            DecodedFrame::test_metadata(Some(dims), Some(Timestamp90k(pts)), Some(flags));
+        if let DecodedFrame::Metadata {
+            pixel_format,
+            planes,
+            histogram,
+            ..
+        } = &mut frame
+        {
+            *pixel_format = Some(BackendPixelFormat::from_nvdec_surface_format(0));
+            *planes = Some(PlaneLayout::nv12(640, 360));
+            *histogram = Some(Box::new([0; 256]));
+        }
+        frame
+    }
+
+    #[test]
+    fn freeze_frame_repeats_the_last_good_frame() {
+        let mut concealer = FreezeFrameConcealer::default();
+        concealer.observe(&frame(0, DecodeInfoFlags::empty()));
+        assert!(concealer.conceal(Some(Timestamp90k(3000))).is_none());
+
+        concealer.set_enabled(true);
+        concealer.observe(&frame(3000, DecodeInfoFlags::CORRUPTED));
+        let Some(DecodedFrame::Metadata {
+            dims,
+            pts_90k,
+            pixel_format,
+            decode_info_flags,
+            planes,
+            histogram,
+            ..
+        }) = concealer.conceal(Some(Timestamp90k(6000)))
+        else {
+            panic!("expected a freeze frame");
+        };
+        assert_eq!(dims.map(|dims| dims.width.get()), Some(640));
+        assert_eq!(pts_90k, Some(Timestamp90k(6000)));
+        assert_eq!(
+            pixel_format,
+            Some(BackendPixelFormat::from_nvdec_surface_format(0))
+        );
+        assert_eq!(decode_info_flags, Some(DecodeInfoFlags::FREEZE_FRAME));
+        assert_eq!(planes.as_deref(), Some(&PlaneLayout::nv12(640, 360)[..]));
+        assert!(histogram.is_none());
+        assert_eq!(concealer.injected(), 1);
+
+        let mut fresh = FreezeFrameConcealer::default();
+        fresh.set_enabled(true);
+        assert!(fresh.conceal(None).is_none());
+    }
+}
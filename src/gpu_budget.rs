@@ -0,0 +1,162 @@
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use crate::EncodePriority;
+
+// A cap on hardware operations in flight on one GPU, shared by every DecodeSession and
+// EncodeSession registered with it through set_gpu_budget. Each backend submit and flush holds
+// a slot for the duration of the call. Waiters are served highest priority first (FIFO within
+// a priority, the same order as EncodeArbiter), and the realtime reserve keeps some slots free
+// for Realtime work only, so a decode batch at Background priority can never occupy the whole
+// device while a live encode is waiting.
+#[derive(Clone)]
+pub struct GpuBudget {
+    inner: Arc<BudgetInner>,
+}
+
+struct BudgetInner {
+    max_in_flight: usize,
+    realtime_reserve: usize,
+    state: Mutex<BudgetState>,
+    released: Condvar,
+}
+
+#[derive(Default)]
+struct BudgetState {
+    in_flight: usize,
+    next_ticket: u64,
+    waiting: BTreeSet<(Reverse<EncodePriority>, u64)>,
+}
+
+impl GpuBudget {
+    // `max_in_flight` operations at once across all registered sessions (at least 1).
+    pub fn new(max_in_flight: usize) -> Self {
+        Self::with_realtime_reserve(max_in_flight, 0)
+    }
+
+    // Like new, but the last `reserve` slots are only handed to Realtime work. At least one
+    // slot always stays open to the other priorities.
+    pub fn with_realtime_reserve(max_in_flight: usize, reserve: usize) -> Self {
+        let max_in_flight = max_in_flight.max(1);
+        Self {
+            inner: Arc::new(BudgetInner {
+                max_in_flight,
+                realtime_reserve: reserve.min(max_in_flight - 1),
+                state: Mutex::new(BudgetState::default()),
+                released: Condvar::new(),
+            }),
+        }
+    }
+
+    // Blocks until this caller is the highest-priority waiter and a slot it may use is free.
+    pub fn acquire(&self, priority: EncodePriority) -> GpuBudgetPermit {
+        let limit = match priority {
+            EncodePriority::Realtime => self.inner.max_in_flight,
+            _ => self.inner.max_in_flight - self.inner.realtime_reserve,
+        };
+        let mut state = self.lock();
+        let key = (Reverse(priority), state.next_ticket);
+        state.next_ticket += 1;
+        state.waiting.insert(key);
+        while state.in_flight >= limit || state.waiting.first() != Some(&key) {
+            state = self
+                .inner
+                .released
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        state.waiting.remove(&key);
+        state.in_flight += 1;
+        self.inner.released.notify_all();
+        GpuBudgetPermit {
+            budget: self.clone(),
+        }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.lock().in_flight
+    }
+
+    pub fn waiting(&self) -> usize {
+        self.lock().waiting.len()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BudgetState> {
+        self.inner
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl fmt::Debug for GpuBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GpuBudget")
+            .field("max_in_flight", &self.inner.max_in_flight)
+            .field("realtime_reserve", &self.inner.realtime_reserve)
+            .field("in_flight", &self.in_flight())
+            .field("waiting", &self.waiting())
+            .finish()
+    }
+}
+
+pub struct GpuBudgetPermit {
+    budget: GpuBudget,
+}
+
+impl Drop for GpuBudgetPermit {
+    fn drop(&mut self) {
+        self.budget.lock().in_flight -= 1;
+        self.budget.inner.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn wait_for_waiters(budget: &GpuBudget, count: usize) {
+        while budget.waiting() < count {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn reserve_lets_realtime_past_a_saturating_batch() {
+        let budget = GpuBudget::with_realtime_reserve(2, 1);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let held = budget.acquire(EncodePriority::Background);
+        let spawn = |priority: EncodePriority| {
+            let budget = budget.clone();
+            let order = Arc::clone(&order);
+            thread::spawn(move || {
+                let _permit = budget.acquire(priority);
+                order.lock().unwrap().push(priority);
+            })
+        };
+        let background = spawn(EncodePriority::Background);
+        wait_for_waiters(&budget, 1);
+        // The free slot is reserved, so the batch waits while the live encode goes straight in.
+        spawn(EncodePriority::Realtime).join().unwrap();
+        assert_eq!(*order.lock().unwrap(), [EncodePriority::Realtime]);
+        assert_eq!((budget.in_flight(), budget.waiting()), (1, 1));
+
+        drop(held);
+        background.join().unwrap();
+        assert_eq!(
+            *order.lock().unwrap(),
+            [EncodePriority::Realtime, EncodePriority::Background]
+        );
+        assert_eq!((budget.in_flight(), budget.waiting()), (0, 0));
+
+        // Every priority can still make progress with a reserve covering the whole budget.
+        let single = GpuBudget::with_realtime_reserve(1, 4);
+        drop(single.acquire(EncodePriority::Background));
+        assert_eq!(single.in_flight(), 0);
+    }
+}
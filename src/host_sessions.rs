@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::fs::{self, File, TryLockError};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::BackendError;
+
+const LOCK_FILE: &str = "registry.lock";
+const CLAIM_EXTENSION: &str = "session";
+
+static NEXT_CLAIM: AtomicU64 = AtomicU64::new(0);
+
+// Sessions claim slots on the device NVIDIA sessions open, CUDA device 0.
+pub(crate) const SESSION_GPU: u32 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HostSessionKind {
+    Decode,
+    Encode,
+}
+
+impl Display for HostSessionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode => f.write_str("decode"),
+            Self::Encode => f.write_str("encode"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SessionHolder {
+    pub pid: u32,
+    pub gpu: u32,
+    pub kind: HostSessionKind,
+}
+
+// A registry of hardware sessions shared by every process on the host that points an arbiter
+// at the same directory, so they can stay under the driver's session limits (NVENC on
+// consumer GPUs) together instead of finding out from a failed session open. Each session
+// is a file named after its GPU, kind and process, locked by its owner for as long as the
+// slot is held; the OS drops the lock when a process dies, and the next claim clears its
+// files. Claims are serialized through a lock file in the same directory.
+#[derive(Debug, Clone)]
+pub struct HostSessionArbiter {
+    dir: PathBuf,
+    max_decode: Option<usize>,
+    max_encode: Option<usize>,
+}
+
+impl HostSessionArbiter {
+    // Tracks sessions without limiting them until with_max_sessions sets a limit.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_decode: None,
+            max_encode: None,
+        }
+    }
+
+    // Sessions of `kind` allowed at once on each GPU, across all processes.
+    #[must_use]
+    pub fn with_max_sessions(mut self, kind: HostSessionKind, max: usize) -> Self {
+        match kind {
+            HostSessionKind::Decode => self.max_decode = Some(max),
+            HostSessionKind::Encode => self.max_encode = Some(max),
+        }
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn max_sessions(&self, kind: HostSessionKind) -> Option<usize> {
+        match kind {
+            HostSessionKind::Decode => self.max_decode,
+            HostSessionKind::Encode => self.max_encode,
+        }
+    }
+
+    // Live sessions on `gpu`, this process's included.
+    pub fn holders(&self, gpu: u32) -> Result<Vec<SessionHolder>, BackendError> {
+        let _lock = self.lock_registry()?;
+        self.live_holders(gpu)
+    }
+
+    // Takes a slot, or fails right away with SessionLimitReached when `gpu` already has the
+    // limit of sessions of `kind`. The slot is released when the claim is dropped.
+    pub fn claim(&self, gpu: u32, kind: HostSessionKind) -> Result<HostSessionClaim, BackendError> {
+        let _lock = self.lock_registry()?;
+        let holders = self.live_holders(gpu)?;
+        if let Some(limit) = self.max_sessions(kind) {
+            let holders = holders
+                .into_iter()
+                .filter(|holder| holder.kind == kind)
+                .collect::<Vec<_>>();
+            if holders.len() >= limit {
+                return Err(BackendError::SessionLimitReached {
+                    gpu,
+                    kind,
+                    limit,
+                    holders,
+                });
+            }
+        }
+        let holder = SessionHolder {
+            pid: std::process::id(),
+            gpu,
+            kind,
+        };
+        let path = self.dir.join(format!(
+            "gpu{gpu}-{kind}-{}-{}.{CLAIM_EXTENSION}",
+            holder.pid,
+            NEXT_CLAIM.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = File::create_new(&path)
+            .and_then(|file| file.lock().map(|()| file))
+            .map_err(|err| registry_error(&path, err))?;
+        Ok(HostSessionClaim {
+            holder,
+            path,
+            file: Some(file),
+        })
+    }
+
+    fn lock_registry(&self) -> Result<File, BackendError> {
+        fs::create_dir_all(&self.dir).map_err(|err| registry_error(&self.dir, err))?;
+        let path = self.dir.join(LOCK_FILE);
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(|err| registry_error(&path, err))?;
+        file.lock().map_err(|err| registry_error(&path, err))?;
+        Ok(file)
+    }
+
+    // Files nobody holds a lock on belong to processes that exited without releasing them.
+    fn live_holders(&self, gpu: u32) -> Result<Vec<SessionHolder>, BackendError> {
+        let entries = fs::read_dir(&self.dir).map_err(|err| registry_error(&self.dir, err))?;
+        let mut holders = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(holder) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(parse_claim_name)
+            else {
+                continue;
+            };
+            if holder.gpu != gpu {
+                continue;
+            }
+            let Ok(file) = File::open(&path) else {
+                continue;
+            };
+            match file.try_lock() {
+                Ok(()) => {
+                    drop(file);
+                    let _ = fs::remove_file(&path);
+                }
+                Err(TryLockError::WouldBlock | TryLockError::Error(_)) => holders.push(holder),
+            }
+        }
+        holders.sort_unstable();
+        Ok(holders)
+    }
+}
+
+impl Default for HostSessionArbiter {
+    // A directory every process of the same user agrees on without configuration.
+    fn default() -> Self {
+        Self::new(std::env::temp_dir().join("video-hw-sessions"))
+    }
+}
+
+#[derive(Debug)]
+pub struct HostSessionClaim {
+    holder: SessionHolder,
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl HostSessionClaim {
+    pub fn holder(&self) -> SessionHolder {
+        self.holder
+    }
+}
+
+impl Drop for HostSessionClaim {
+    fn drop(&mut self) {
+        // Unlock before removing: Windows does not delete files that are still open.
+        self.file.take();
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// "gpu{gpu}-{kind}-{pid}-{n}.session"
+fn parse_claim_name(name: &str) -> Option<SessionHolder> {
+    let stem = name.strip_suffix(CLAIM_EXTENSION)?.strip_suffix('.')?;
+    let mut parts = stem.split('-');
+    let gpu = parts.next()?.strip_prefix("gpu")?.parse().ok()?;
+    let kind = match parts.next()? {
+        "decode" => HostSessionKind::Decode,
+        "encode" => HostSessionKind::Encode,
+        _ => return None,
+    };
+    let pid = parts.next()?.parse().ok()?;
+    parts.next()?.parse::<u64>().ok()?;
+    Some(SessionHolder { pid, gpu, kind })
+}
+
+fn registry_error(path: &Path, err: std::io::Error) -> BackendError {
+    BackendError::Backend(format!("host session registry {}: {err}", path.display()))
+}
+
+// "pid 4242 (2 sessions), pid 4250" for SessionLimitReached.
+pub(crate) fn describe_holders(holders: &[SessionHolder]) -> String {
+    if holders.is_empty() {
+        return "no live process".to_string();
+    }
+    let mut per_pid = BTreeMap::<u32, usize>::new();
+    for holder in holders {
+        *per_pid.entry(holder.pid).or_default() += 1;
+    }
+    per_pid
+        .into_iter()
+        .map(|(pid, count)| match count {
+            1 => format!("pid {pid}"),
+            _ => format!("pid {pid} ({count} sessions)"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claims_stop_at_the_limit_and_stale_files_are_cleared() {
+        let dir = std::env::temp_dir().join(format!("video-hw-sessions-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let arbiter = HostSessionArbiter::new(&dir).with_max_sessions(HostSessionKind::Encode, 2);
+        let pid = std::process::id();
+
+        // A process that died holding a slot left its file behind unlocked.
+        fs::create_dir_all(&dir).unwrap();
+        File::create(dir.join("gpu0-encode-1-0.session")).unwrap();
+
+        let first = arbiter.claim(0, HostSessionKind::Encode).unwrap();
+        let second = arbiter.claim(0, HostSessionKind::Encode).unwrap();
+        assert!(!dir.join("gpu0-encode-1-0.session").exists());
+        let err = arbiter.claim(0, HostSessionKind::Encode).unwrap_err();
+        assert!(matches!(
+            &err,
+            BackendError::SessionLimitReached { gpu: 0, limit: 2, holders, .. } if holders.len() == 2
+        ));
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "session limit reached: 2 encode sessions on GPU 0 held by pid {pid} (2 sessions)"
+            )
+        );
+
+        // Other GPUs and decode sessions have their own counts.
+        let other_gpu = arbiter.claim(1, HostSessionKind::Encode).unwrap();
+        let decode = arbiter.claim(0, HostSessionKind::Decode).unwrap();
+        assert_eq!(arbiter.holders(0).unwrap().len(), 3);
+
+        drop(first);
+        let third = arbiter.claim(0, HostSessionKind::Encode).unwrap();
+        assert_eq!(
+            third.holder(),
+            SessionHolder {
+                pid,
+                gpu: 0,
+                kind: HostSessionKind::Encode
+            }
+        );
+        drop((second, third, other_gpu, decode));
+        assert!(arbiter.holders(0).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
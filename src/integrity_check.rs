@@ -0,0 +1,318 @@
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+
+use crate::bitstream::{detect_keyframe, is_parameter_set};
+use crate::{
+    Backend, Codec, DecodeInfoFlags, DecodeSession, DecodedFrame, DecoderConfig, DiagnosticEvent,
+    Diagnostics, Dimensions, EncodedChunk, EncoderConfig, FrameRate, Timestamp90k,
+};
+
+// Expected sizes are kept for this many submitted frames; the encoder never holds more.
+const MAX_EXPECTED: usize = 256;
+
+// Opt-in smoke test of the encoder's own output. Once `every_chunks` chunks went by, the next
+// keyframe that carries its parameter sets opens a window: it and up to `window_chunks - 1`
+// chunks after it (fewer when another keyframe comes first) are decoded on a separate decode
+// session on `backend`, the encoder's when None. Every chunk in a window must decode without
+// damage flags, keep the keyframe tag its slices imply and come back at the size its frame
+// was submitted at. Length-prefixed VideoToolbox output keeps parameter sets out of band, so
+// its windows are skipped and counted as such.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntegrityCheckOptions {
+    pub every_chunks: NonZeroU32,
+    pub window_chunks: NonZeroU32,
+    pub backend: Option<Backend>,
+}
+
+impl Default for IntegrityCheckOptions {
+    fn default() -> Self {
+        Self {
+            every_chunks: NonZeroU32::new(300).expect("non-zero"),
+            window_chunks: NonZeroU32::new(30).expect("non-zero"),
+            backend: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IntegrityCheckStats {
+    pub windows: u64,
+    pub windows_skipped: u64,
+    pub chunks_checked: u64,
+    pub frames_decoded: u64,
+    pub mismatches: u64,
+}
+
+#[derive(Debug, Default)]
+struct Window {
+    fed: u64,
+    decoded: u64,
+}
+
+// Every mismatch is reported as DiagnosticEvent::IntegrityMismatch.
+pub(crate) struct IntegrityChecker {
+    options: IntegrityCheckOptions,
+    backend: Backend,
+    codec: Codec,
+    fps: FrameRate,
+    diagnostics: Diagnostics,
+    decoder: Option<DecodeSession>,
+    expected: BTreeMap<Timestamp90k, Dimensions>,
+    last_dims: Option<Dimensions>,
+    since_window: u64,
+    window: Option<Window>,
+    stats: IntegrityCheckStats,
+}
+
+impl IntegrityChecker {
+    pub(crate) fn new(
+        options: IntegrityCheckOptions,
+        encoder_backend: Backend,
+        config: &EncoderConfig,
+        diagnostics: Diagnostics,
+    ) -> Self {
+        Self {
+            options,
+            backend: options.backend.unwrap_or(encoder_backend),
+            codec: config.codec,
+            fps: config.fps,
+            diagnostics,
+            decoder: None,
+            expected: BTreeMap::new(),
+            last_dims: None,
+            // The first keyframe opens a window.
+            since_window: u64::from(options.every_chunks.get()),
+            window: None,
+            stats: IntegrityCheckStats::default(),
+        }
+    }
+
+    // After the encoder was rebuilt for a new config; the caller flushed the old one first.
+    pub(crate) fn restart(&mut self, encoder_backend: Backend, config: &EncoderConfig) {
+        self.close_window();
+        self.backend = self.options.backend.unwrap_or(encoder_backend);
+        self.codec = config.codec;
+        self.fps = config.fps;
+        self.decoder = None;
+    }
+
+    pub(crate) fn options(&self) -> IntegrityCheckOptions {
+        self.options
+    }
+
+    pub(crate) fn stats(&self) -> IntegrityCheckStats {
+        self.stats
+    }
+
+    // Call with every frame handed to the encoder, after pre-encode hooks.
+    pub(crate) fn expect(&mut self, pts_90k: Option<Timestamp90k>, dims: Dimensions) {
+        self.last_dims = Some(dims);
+        if let Some(pts_90k) = pts_90k {
+            if self.expected.len() >= MAX_EXPECTED {
+                self.expected.pop_first();
+            }
+            self.expected.insert(pts_90k, dims);
+        }
+    }
+
+    // Call with every chunk the session emits, in decode order.
+    pub(crate) fn check(&mut self, chunk: &EncodedChunk) {
+        self.since_window += 1;
+        let detected = detect_keyframe(chunk.codec, chunk.layout, &chunk.data);
+        if detected.unwrap_or(chunk.is_keyframe) {
+            self.close_window();
+            if self.since_window >= u64::from(self.options.every_chunks.get()) {
+                self.since_window = 0;
+                if self_contained(chunk) {
+                    self.window = Some(Window::default());
+                    self.stats.windows += 1;
+                } else {
+                    self.stats.windows_skipped += 1;
+                }
+            }
+        }
+        if self.window.is_none() {
+            return;
+        }
+        self.stats.chunks_checked += 1;
+        if let Some(detected) = detected
+            && detected != chunk.is_keyframe
+        {
+            self.mismatch(
+                chunk.pts_90k,
+                format!(
+                    "chunk tagged is_keyframe={} but its slices say {detected}",
+                    chunk.is_keyframe
+                ),
+            );
+        }
+        let decoder = self.decoder.get_or_insert_with(|| {
+            DecodeSession::new(self.backend, DecoderConfig::new(self.codec, self.fps, true))
+        });
+        let decoded = decoder.submit(chunk.clone().into()).and_then(|()| {
+            let mut frames = Vec::new();
+            while let Some(frame) = decoder.try_reap()? {
+                frames.push(frame);
+            }
+            Ok(frames)
+        });
+        if let Some(window) = self.window.as_mut() {
+            window.fed += 1;
+        }
+        match decoded {
+            Ok(frames) => frames.iter().for_each(|frame| self.inspect(frame)),
+            Err(err) => self.decode_failed(chunk.pts_90k, &err.to_string()),
+        }
+        if self
+            .window
+            .as_ref()
+            .is_some_and(|window| window.fed >= u64::from(self.options.window_chunks.get()))
+        {
+            self.close_window();
+        }
+    }
+
+    // Flushes the verification decoder and checks that every chunk fed came back as a frame.
+    pub(crate) fn close_window(&mut self) {
+        if self.window.is_none() {
+            return;
+        }
+        let flushed = self
+            .decoder
+            .as_mut()
+            .map_or(Ok(Vec::new()), DecodeSession::flush);
+        match flushed {
+            Ok(frames) => frames.iter().for_each(|frame| self.inspect(frame)),
+            Err(err) => self.decode_failed(None, &err.to_string()),
+        }
+        // None when the flush failed, which was reported already.
+        let Some(window) = self.window.take() else {
+            return;
+        };
+        if window.decoded < window.fed {
+            self.mismatch(
+                None,
+                format!(
+                    "{} of {} chunks did not decode to a frame",
+                    window.fed - window.decoded,
+                    window.fed
+                ),
+            );
+        }
+    }
+
+    fn inspect(&mut self, frame: &DecodedFrame) {
+        self.stats.frames_decoded += 1;
+        if let Some(window) = self.window.as_mut() {
+            window.decoded += 1;
+        }
+        let pts_90k = frame.pts_90k();
+        if let DecodedFrame::Metadata {
+            decode_info_flags: Some(flags),
+            ..
+        } = frame
+            && flags.intersects(DecodeInfoFlags::CORRUPTED | DecodeInfoFlags::FRAME_DROPPED)
+        {
+            self.mismatch(pts_90k, format!("decoder flagged the frame {flags:?}"));
+        }
+        let expected = match pts_90k {
+            Some(pts_90k) => self.expected.get(&pts_90k).copied(),
+            None => self.last_dims,
+        };
+        if let (Some(expected), Some(dims)) = (expected, frame.dims())
+            && expected != dims
+        {
+            self.mismatch(
+                pts_90k,
+                format!("decoded a {dims} frame from a {expected} one"),
+            );
+        }
+    }
+
+    // The decoder is rebuilt for the next window rather than trusted after an error.
+    fn decode_failed(&mut self, pts_90k: Option<Timestamp90k>, err: &str) {
+        self.mismatch(pts_90k, format!("re-decode failed: {err}"));
+        self.window = None;
+        self.decoder = None;
+    }
+
+    fn mismatch(&mut self, pts_90k: Option<Timestamp90k>, reason: String) {
+        self.stats.mismatches += 1;
+        self.diagnostics
+            .emit(DiagnosticEvent::IntegrityMismatch { pts_90k, reason });
+    }
+}
+
+// A keyframe the verification decoder can start from without anything sent before it.
+fn self_contained(chunk: &EncodedChunk) -> bool {
+    chunk.codec == Codec::Mjpeg
+        || chunk.nal_units().is_ok_and(|nals| {
+            nals.iter()
+                .any(|nal| is_parameter_set(chunk.codec, nal.data))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::EncodedLayout;
+    use crate::diagnostics::RecordingDiagnostics;
+
+    fn avcc(nal: &[u8], pts: i64) -> EncodedChunk {
+        let mut data = (nal.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(nal);
+        EncodedChunk {
+            codec: Codec::H264,
+            layout: EncodedLayout::Avcc,
+            data: data.into(),
+            pts_90k: Some(Timestamp90k(pts)),
+            is_keyframe: nal[0] & 0x1F == 5,
+            filler_bytes: 0,
+            dts_90k: None,
+            display_index: None,
+        }
+    }
+
+    #[test]
+    fn windows_without_parameter_sets_are_skipped_on_schedule() {
+        let sink = Arc::new(RecordingDiagnostics::default());
+        let options = IntegrityCheckOptions {
+            every_chunks: NonZeroU32::new(4).unwrap(),
+            ..IntegrityCheckOptions::default()
+        };
+        let mut checker = IntegrityChecker::new(
+            options,
+            Backend::os_default(),
+            &EncoderConfig::new(Codec::H264, 30, true),
+            Diagnostics::from_arc(sink.clone()),
+        );
+        // An IDR every other chunk; only those at least four chunks apart open a window.
+        for index in 0..10 {
+            let nal: &[u8] = if index % 2 == 0 {
+                &[0x65, 0x88]
+            } else {
+                &[0x41, 0x9A]
+            };
+            checker.check(&avcc(nal, index * 3000));
+        }
+        assert_eq!(
+            checker.stats(),
+            IntegrityCheckStats {
+                windows_skipped: 3,
+                ..IntegrityCheckStats::default()
+            }
+        );
+        assert!(sink.events().is_empty());
+
+        let event = DiagnosticEvent::IntegrityMismatch {
+            pts_90k: Some(Timestamp90k(3000)),
+            reason: "decoded a 320x180 frame from a 640x360 one".to_string(),
+        };
+        assert_eq!(
+            event.to_string(),
+            "[encode.integrity_mismatch] pts_90k=3000, reason=decoded a 320x180 frame from a 640x360 one"
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+use crate::{BackendError, BitstreamInput, Codec, DecodeSession, Timestamp90k};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JitterEvent {
+    // Sequences `expected..received` never arrived within the target delay.
+    Gap { expected: u64, received: u64 },
+    // Inter frames are being dropped until the next keyframe; forward as a PLI/FIR upstream.
+    KeyframeNeeded,
+}
+
+impl Display for JitterEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Gap { expected, received } => {
+                write!(f, "[jitter.gap] expected={expected} received={received}")
+            }
+            Self::KeyframeNeeded => f.write_str("[jitter.keyframe_needed]"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JitterBufferStats {
+    pub received: u64,
+    pub delivered: u64,
+    pub reordered: u64,
+    pub duplicates: u64,
+    // Arrived after a later access unit had already been delivered.
+    pub late: u64,
+    pub lost: u64,
+    pub dropped_until_keyframe: u64,
+}
+
+#[derive(Debug)]
+struct Packet {
+    sequence: Option<u64>,
+    input: BitstreamInput,
+    arrived: Instant,
+    is_keyframe: bool,
+}
+
+// Holds incoming access units for `target_delay` so late packets can slot back into order
+// before they reach the decoder. Transports with sequence numbers get gap detection; without
+// them access units are ordered by pts (or arrival when that is missing too). Decoding only
+// (re)starts on a keyframe, so after a gap inter frames are dropped and KeyframeNeeded is raised.
+#[derive(Debug)]
+pub struct JitterBuffer {
+    codec: Codec,
+    target_delay: Duration,
+    max_packets: usize,
+    pending: BTreeMap<i128, Packet>,
+    retry: Option<BitstreamInput>,
+    last_delivered: Option<i128>,
+    expected_sequence: Option<u64>,
+    arrival_key: i128,
+    waiting_for_keyframe: bool,
+    keyframe_requested: bool,
+    events: VecDeque<JitterEvent>,
+    // pts of access units dropped while waiting for a keyframe, for freeze-frame concealment.
+    dropped: VecDeque<Option<Timestamp90k>>,
+    stats: JitterBufferStats,
+}
+
+impl JitterBuffer {
+    pub fn new(codec: Codec, target_delay: Duration) -> Self {
+        Self {
+            codec,
+            target_delay,
+            max_packets: 256,
+            pending: BTreeMap::new(),
+            retry: None,
+            last_delivered: None,
+            expected_sequence: None,
+            arrival_key: 0,
+            waiting_for_keyframe: true,
+            keyframe_requested: false,
+            events: VecDeque::new(),
+            dropped: VecDeque::new(),
+            stats: JitterBufferStats::default(),
+        }
+    }
+
+    // Once more access units than this are held, the oldest is released without waiting.
+    pub fn with_max_packets(mut self, max_packets: usize) -> Self {
+        self.max_packets = max_packets.max(1);
+        self
+    }
+
+    pub fn push(&mut self, sequence: Option<u64>, input: BitstreamInput, now: Instant) {
+        let key = match (sequence, input_pts(&input)) {
+            (Some(sequence), _) => i128::from(sequence),
+            (None, Some(pts)) => i128::from(pts),
+            (None, None) => {
+                self.arrival_key = self
+                    .arrival_key
+                    .max(self.last_delivered.unwrap_or(0))
+                    .max(self.pending.last_key_value().map_or(0, |(key, _)| *key))
+                    + 1;
+                self.arrival_key
+            }
+        };
+        if self.last_delivered.is_some_and(|last| key <= last) {
+            self.stats.late += 1;
+            return;
+        }
+        if self.pending.contains_key(&key) {
+            self.stats.duplicates += 1;
+            return;
+        }
+        if self.pending.range(key + 1..).next().is_some() {
+            self.stats.reordered += 1;
+        }
+        self.stats.received += 1;
+        let is_keyframe = input_is_keyframe(self.codec, &input);
+        self.pending.insert(
+            key,
+            Packet {
+                sequence,
+                input,
+                arrived: now,
+                is_keyframe,
+            },
+        );
+    }
+
+    // When the oldest held access unit becomes deliverable, for sleeping until the next pop.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        if self.retry.is_some() || self.pending.len() > self.max_packets {
+            return Some(Instant::now());
+        }
+        let (_, head) = self.pending.first_key_value()?;
+        Some(head.arrived + self.target_delay)
+    }
+
+    pub fn pop_ready(&mut self, now: Instant) -> Option<BitstreamInput> {
+        if let Some(input) = self.retry.take() {
+            return Some(input);
+        }
+        loop {
+            let (_, head) = self.pending.first_key_value()?;
+            if head.arrived + self.target_delay > now && self.pending.len() <= self.max_packets {
+                return None;
+            }
+            if let Some(input) = self.release_head() {
+                return Some(input);
+            }
+        }
+    }
+
+    // End of stream: everything still held is released in order, gaps included.
+    pub fn flush(&mut self) -> Vec<BitstreamInput> {
+        let mut out = self.retry.take().into_iter().collect::<Vec<_>>();
+        while !self.pending.is_empty() {
+            out.extend(self.release_head());
+        }
+        out
+    }
+
+    // Submits every deliverable access unit. On TemporaryBackpressure the unit is kept for the
+    // next call and the number submitted so far is returned.
+    pub fn drain_into(
+        &mut self,
+        session: &mut DecodeSession,
+        now: Instant,
+    ) -> Result<usize, BackendError> {
+        let mut submitted = 0;
+        while let Some(input) = self.pop_ready(now) {
+            for pts_90k in self.dropped.drain(..) {
+                session.conceal_lost_frame(pts_90k);
+            }
+            match session.submit(input.clone()) {
+                Ok(()) => submitted += 1,
+                Err(BackendError::TemporaryBackpressure(_)) => {
+                    self.retry = Some(input);
+                    break;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        for pts_90k in self.dropped.drain(..) {
+            session.conceal_lost_frame(pts_90k);
+        }
+        Ok(submitted)
+    }
+
+    // For callers that submit via pop_ready themselves: the dropped access units, in order,
+    // to hand to DecodeSession::conceal_lost_frame. Only the newest max_packets are kept.
+    pub fn take_dropped(&mut self) -> Vec<Option<Timestamp90k>> {
+        self.dropped.drain(..).collect()
+    }
+
+    pub fn pop_event(&mut self) -> Option<JitterEvent> {
+        self.events.pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len() + usize::from(self.retry.is_some())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stats(&self) -> JitterBufferStats {
+        self.stats
+    }
+
+    fn release_head(&mut self) -> Option<BitstreamInput> {
+        let (key, packet) = self.pending.pop_first()?;
+        self.last_delivered = Some(key);
+        if let (Some(expected), Some(received)) = (self.expected_sequence, packet.sequence)
+            && received > expected
+        {
+            self.stats.lost += received - expected;
+            self.events
+                .push_back(JitterEvent::Gap { expected, received });
+            self.waiting_for_keyframe = true;
+            self.keyframe_requested = false;
+        }
+        if let Some(sequence) = packet.sequence {
+            self.expected_sequence = Some(sequence + 1);
+        }
+        if self.waiting_for_keyframe {
+            if !packet.is_keyframe {
+                self.stats.dropped_until_keyframe += 1;
+                if self.dropped.len() >= self.max_packets {
+                    self.dropped.pop_front();
+                }
+                self.dropped
+                    .push_back(input_pts(&packet.input).map(Timestamp90k));
+                if !self.keyframe_requested {
+                    self.keyframe_requested = true;
+                    self.events.push_back(JitterEvent::KeyframeNeeded);
+                }
+                return None;
+            }
+            self.waiting_for_keyframe = false;
+        }
+        self.stats.delivered += 1;
+        Some(packet.input)
+    }
+}
+
+fn input_pts(input: &BitstreamInput) -> Option<i64> {
+    match input {
+        BitstreamInput::AnnexBChunk { pts_90k, .. }
+        | BitstreamInput::AccessUnitRawNal { pts_90k, .. }
+        | BitstreamInput::LengthPrefixedSample { pts_90k, .. } => pts_90k.map(|pts| pts.0),
+    }
+}
+
+fn input_is_keyframe(codec: Codec, input: &BitstreamInput) -> bool {
+    let is_irap = |nal: &[u8]| match codec {
+        Codec::H264 => crate::nal_type(codec, nal) == Some(5),
+        Codec::Hevc => matches!(crate::nal_type(codec, nal), Some(16..=21)),
+        Codec::Mjpeg => true,
+    };
+    match input {
+        BitstreamInput::AnnexBChunk { chunk, .. } => crate::split_annexb_nal_units(chunk)
+            .into_iter()
+            .any(is_irap),
+        BitstreamInput::AccessUnitRawNal { nalus, .. } => nalus.iter().any(|nal| is_irap(nal)),
+        BitstreamInput::LengthPrefixedSample { sample, .. } => {
+            crate::split_length_prefixed_nal_units(sample)
+                .is_ok_and(|nals| nals.into_iter().any(is_irap))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Timestamp90k;
+
+    fn au(pts: i64, keyframe: bool) -> BitstreamInput {
+        BitstreamInput::AnnexBChunk {
+            chunk: vec![0, 0, 0, 1, if keyframe { 0x65 } else { 0x41 }, 0x88],
+            pts_90k: Some(Timestamp90k(pts)),
+        }
+    }
+
+    fn pts_of(inputs: &[BitstreamInput]) -> Vec<i64> {
+        inputs.iter().filter_map(input_pts).collect()
+    }
+
+    #[test]
+    fn reorders_within_target_delay() {
+        let start = Instant::now();
+        let delay = Duration::from_millis(50);
+        let mut buffer = JitterBuffer::new(Codec::H264, delay);
+        for (sequence, keyframe) in [(0, true), (2, false), (1, false), (2, false)] {
+            buffer.push(Some(sequence), au(sequence as i64 * 3000, keyframe), start);
+        }
+        assert!(
+            buffer
+                .pop_ready(start + Duration::from_millis(10))
+                .is_none()
+        );
+        assert_eq!(buffer.next_deadline(), Some(start + delay));
+        let mut out = Vec::new();
+        while let Some(input) = buffer.pop_ready(start + delay) {
+            out.push(input);
+        }
+        assert_eq!(pts_of(&out), vec![0, 3000, 6000]);
+        buffer.push(Some(1), au(3000, false), start + delay);
+        let stats = buffer.stats();
+        assert_eq!(
+            (
+                stats.reordered,
+                stats.duplicates,
+                stats.late,
+                stats.delivered
+            ),
+            (1, 1, 1, 3)
+        );
+        assert_eq!(buffer.pop_event(), None);
+    }
+
+    #[test]
+    fn gap_drops_inter_frames_until_keyframe() {
+        let start = Instant::now();
+        let mut buffer = JitterBuffer::new(Codec::H264, Duration::ZERO);
+        for (sequence, keyframe) in [(0, true), (1, false), (4, false), (5, false), (6, true)] {
+            buffer.push(Some(sequence), au(sequence as i64 * 3000, keyframe), start);
+        }
+        assert_eq!(pts_of(&buffer.flush()), vec![0, 3000, 18_000]);
+        assert_eq!(
+            buffer.pop_event(),
+            Some(JitterEvent::Gap {
+                expected: 2,
+                received: 4
+            })
+        );
+        assert_eq!(buffer.pop_event(), Some(JitterEvent::KeyframeNeeded));
+        assert_eq!(buffer.pop_event(), None);
+        let stats = buffer.stats();
+        assert_eq!((stats.lost, stats.dropped_until_keyframe), (2, 2));
+        assert_eq!(
+            buffer.take_dropped(),
+            [Some(Timestamp90k(12_000)), Some(Timestamp90k(15_000))]
+        );
+        assert!(buffer.take_dropped().is_empty());
+    }
+}
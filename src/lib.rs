@@ -1,6 +1,7 @@
 use std::collections::VecDeque;
 use std::fmt;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[cfg(any(
     all(target_os = "macos", feature = "backend-vt"),
@@ -10,16 +11,79 @@ use std::time::Duration;
     )
 ))]
 mod backend_transform_adapter;
+mod bitrate_ladder;
+#[cfg(any(test, feature = "bitstream"))]
+mod bitstream;
+#[cfg(any(test, feature = "bitstream"))]
+mod bitstream_file;
+#[cfg(feature = "capture")]
+mod capture;
+mod cbr_filler;
+mod chunk_index;
+mod chunk_split;
+mod clock;
+mod codec_choice;
+#[cfg(feature = "transform-cpu")]
+mod composite;
+mod config_diff;
+#[cfg(feature = "conformance")]
+mod conformance;
+mod contract;
+mod corruption_keyframes;
+#[cfg(all(
+    feature = "transform-cuda",
+    any(target_os = "linux", target_os = "windows")
+))]
+mod cuda_composite;
+#[cfg(all(
+    feature = "transform-cuda",
+    any(target_os = "linux", target_os = "windows")
+))]
+mod cuda_tone_map;
+#[cfg(all(
+    feature = "transform-cuda",
+    any(target_os = "linux", target_os = "windows")
+))]
+mod cuda_transform;
+mod decode_watchdog;
 #[cfg(any(
     test,
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+mod device_cache;
+mod diagnostics;
+#[cfg(all(feature = "capture", target_os = "windows"))]
+mod dxgi_capture;
+mod encode_priority;
+mod encoded_sink;
+#[cfg(any(
     all(target_os = "macos", feature = "backend-vt"),
     all(
         feature = "backend-nvidia",
         any(target_os = "linux", target_os = "windows")
     )
 ))]
-mod bitstream;
-mod contract;
+mod fallback;
+mod frame_analysis;
+mod frame_trace;
+mod freeze_frame;
+mod gpu_budget;
+mod host_sessions;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+mod integrity_check;
+mod jitter_buffer;
+mod master_clock;
+mod memory_pressure;
+mod multiview;
 #[cfg(all(
     feature = "backend-nvidia",
     any(target_os = "linux", target_os = "windows")
@@ -30,6 +94,27 @@ mod nv_backend;
     any(target_os = "linux", target_os = "windows")
 ))]
 mod nv_meta_decoder;
+#[cfg(feature = "nvml")]
+mod nvml;
+mod output_filter;
+#[cfg(any(
+    test,
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+mod parallel_decode;
+#[cfg(any(
+    test,
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+mod parallel_encode;
 mod pipeline;
 #[cfg(any(
     all(target_os = "macos", feature = "backend-vt"),
@@ -39,27 +124,257 @@ mod pipeline;
     )
 ))]
 mod pipeline_scheduler;
+mod pixel_format;
+mod pre_encode;
+mod preflight;
+pub mod prelude;
+mod rate_control;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+mod ready_notify;
+mod reap_cancel;
+mod reorder_info;
+#[cfg(all(feature = "capture", target_os = "macos"))]
+mod sck_capture;
+#[cfg(any(
+    test,
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+mod self_test;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+mod session_handle;
+mod stream_clock;
+mod stream_events;
+#[cfg(any(
+    test,
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+mod surface_pool;
+#[cfg(feature = "transform-cpu")]
+mod tone_map;
 mod transform;
+mod utilization;
 
 #[cfg(all(target_os = "macos", feature = "backend-vt"))]
 mod vt_backend;
-
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+mod vt_multiview;
+
+pub use bitrate_ladder::{BitrateLadder, Rendition};
+#[cfg(feature = "bitstream")]
+pub use bitstream::{AccessUnit, ParameterSetCache, StatefulBitstreamAssembler, parse_frame_crop};
+#[cfg(feature = "bitstream")]
+pub use bitstream_file::{BitstreamFileReader, BitstreamIndex, BitstreamIndexEntry, SeekedFrame};
+#[cfg(feature = "capture")]
+pub use capture::{CaptureSource, CapturedFrame, pack_bgra_rows};
+pub use chunk_index::{ChunkIndex, ChunkIndexEntry};
+pub use chunk_split::{ChunkGroup, ChunkStreamSplitter};
+pub use clock::{Clock, ManualClock, SystemClock};
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+pub use codec_choice::choose_codec;
+pub use codec_choice::choose_codec_from;
+#[cfg(feature = "transform-cpu")]
+pub use composite::{
+    CompositeKernel, CompositeLayout, CompositeStage, CpuCompositor, OverlayFrame,
+};
+pub use config_diff::{ConfigApplyPath, ConfigDiff};
+#[cfg(feature = "conformance")]
+pub use conformance::{
+    CONFORMANCE_MANIFEST, ConformanceReport, ConformanceResult, ConformanceVector,
+    ConformanceVerdict, ContractCheck, ContractReport, FrameChecksum,
+    check_software_decoder_contract, load_conformance_manifest,
+};
+#[cfg(all(
+    feature = "conformance",
+    any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    )
+))]
+pub use conformance::{
+    check_decode_session_contract, check_encode_session_contract, run_conformance_suite,
+    run_conformance_vector,
+};
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+pub(crate) use contract::FramePixels;
+#[cfg(all(
+    feature = "unsafe-options",
+    any(target_os = "linux", target_os = "windows")
+))]
+pub use contract::NvInitParamsHook;
 pub use contract::{
     BackendDecoderOptions, BackendEncoderOptions, BackendError, BitstreamInput, CapabilityReport,
-    Codec, ColorMetadata, DecodeSummary, DecodedFrame, DecoderConfig, Dimensions, EncodeFrame,
-    EncodedChunk, EncodedLayout, EncoderConfig, NvidiaDecoderOptions, NvidiaEncoderOptions,
-    NvidiaSessionConfig, RawFrameBuffer, SessionSwitchMode, SessionSwitchRequest, Timestamp90k,
+    Codec, ColorMetadata, ContentHint, DecodeInfoFlags, DecodeSummary, DecodedFrame, DecoderConfig,
+    Dimensions, DirtyRect, EncodeFrame, EncodeLatency, EncodeSessionInfo, EncodeSummary,
+    EncodedChunk, EncodedLayout, EncoderConfig, FallbackPolicy, FrameCrop, FrameRate,
+    FrameRepeatMode, LatencyTune, NalUnit, NvBufferLifetimeMode, NvidiaDecoderOptions,
+    NvidiaEncoderOptions, NvidiaSessionConfig, PlaneLayout, Profile, RawFrameBuffer,
+    SessionSwitchMode, SessionSwitchRequest, SoftwareDecoder, SoftwareDecoderFactory, StreamEvent,
+    SurfacePoolSizes, Timestamp90k, VtEncoderInfo, VtEncoderOptions, VtPropertyValue,
     VtSessionConfig,
 };
 pub(crate) use contract::{EncodedPacket, Frame, VideoDecoder, VideoEncoder};
+pub use corruption_keyframes::{CorruptionKeyframePolicy, CorruptionKeyframes};
+#[cfg(all(
+    feature = "transform-cuda",
+    any(target_os = "linux", target_os = "windows")
+))]
+pub use cuda_composite::CudaCompositor;
+#[cfg(all(
+    feature = "transform-cuda",
+    any(target_os = "linux", target_os = "windows")
+))]
+pub use cuda_tone_map::CudaToneMapper;
+#[cfg(all(
+    feature = "transform-cuda",
+    any(target_os = "linux", target_os = "windows")
+))]
+pub use cuda_transform::CudaNv12ToRgb;
+pub use decode_watchdog::DecodeWatchdogOptions;
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+pub use device_cache::{DeviceCacheStats, cuda_context_cache_stats};
+pub use diagnostics::{DiagnosticEvent, Diagnostics, DiagnosticsSink, StderrDiagnostics};
+#[cfg(all(feature = "capture", target_os = "windows"))]
+pub use dxgi_capture::DxgiCaptureSource;
+pub use encode_priority::{EncodeArbiter, EncodePermit, EncodePriority};
+pub use encoded_sink::{
+    ChunkTransform, ChunkedFileSink, EncodedSink, RingBufferHandle, RingBufferSink, WriterSink,
+};
+pub use frame_analysis::{FrameAnalysis, FrameAnalysisOptions};
+pub use frame_trace::{FrameTrace, TraceStage};
+pub use gpu_budget::{GpuBudget, GpuBudgetPermit};
+pub use host_sessions::{HostSessionArbiter, HostSessionClaim, HostSessionKind, SessionHolder};
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+pub use integrity_check::{IntegrityCheckOptions, IntegrityCheckStats};
+pub use jitter_buffer::{JitterBuffer, JitterBufferStats, JitterEvent};
+pub use master_clock::{MasterClock, MasterClockOptions, MasterClockStats};
+pub use memory_pressure::{DeviceMemory, MemoryPressureLevel, MemoryPressureOptions};
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+pub use multiview::mv_hevc_support;
+pub use multiview::{MultiViewSupport, StereoView};
+#[cfg(feature = "nvml")]
+pub use nvml::{
+    GpuHealth, GpuHealthEvent, GpuMonitor, GpuWatch, NVIDIA_SESSION_DEVICE_INDEX, ThrottleReasons,
+};
+pub use output_filter::DecodeOutputFilter;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+pub use parallel_decode::ParallelGopDecoder;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+pub use parallel_encode::ParallelGopEncoder;
 pub use pipeline::{
     BoundedQueueRx, BoundedQueueTx, InFlightCredits, QueueRecvError, QueueSendError, QueueStats,
     bounded_queue,
 };
+pub use pixel_format::{BackendPixelFormat, PixelFormat, PixelFormatNamespace};
+pub use pre_encode::PreEncodeHook;
+pub use preflight::BackendPreflight;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+pub use preflight::preflight;
+pub use rate_control::{RateControlUpdate, RateControlWindow, RateController};
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+pub use ready_notify::ReadyNotifier;
+pub use reap_cancel::ReapCanceller;
+#[cfg(all(feature = "capture", target_os = "macos"))]
+pub use sck_capture::ScreenCaptureKitSource;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+pub use self_test::{SelfTestReport, SelfTestStage, self_test};
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+pub use session_handle::{DecodeReaper, DecodeSubmitter, EncodeReaper, EncodeSubmitter};
+pub use stream_clock::{Playout, StreamClock, StreamClockStats};
+#[cfg(feature = "transform-cpu")]
+pub use tone_map::{HdrTransfer, ToneMapAlgorithm, ToneMapConfig, ToneMapper};
 pub use transform::{
-    ColorRequest, Nv12Frame, RgbFrame, TransformDispatcher, TransformJob, TransformResult,
-    make_argb_to_nv12_dummy, nv12_to_rgb24, should_enqueue_transform,
+    ColorRequest, Nv12Frame, RgbFrame, TransformBatch, TransformDispatcher, TransformJob,
+    TransformResult, TransformRoute, TransformStage, TransformStageId, UnsupportedConversion,
+    make_argb_to_nv12_dummy, nv12_to_rgb24, route_transform, should_enqueue_transform,
 };
+pub use utilization::EngineUtilization;
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+pub use vt_backend::list_video_toolbox_encoders;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(
@@ -224,6 +539,45 @@ impl VideoDecoder for DecoderInner {
         }
     }
 
+    fn drain_available(&mut self) -> Result<Vec<Frame>, BackendError> {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.drain_available(),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.drain_available(),
+            Self::Unsupported(inner) => inner.drain_available(),
+        }
+    }
+
+    fn poll_ready(&mut self) -> Result<Vec<Frame>, BackendError> {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.poll_ready(),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.poll_ready(),
+            Self::Unsupported(inner) => inner.poll_ready(),
+        }
+    }
+
+    fn set_ready_waker(&mut self, waker: reap_cancel::ReadyWaker) {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.set_ready_waker(waker),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.set_ready_waker(waker),
+            Self::Unsupported(inner) => inner.set_ready_waker(waker),
+        }
+    }
+
     fn decode_summary(&self) -> DecodeSummary {
         match self {
             #[cfg(all(target_os = "macos", feature = "backend-vt"))]
@@ -236,47 +590,138 @@ impl VideoDecoder for DecoderInner {
             Self::Unsupported(inner) => inner.decode_summary(),
         }
     }
-}
 
-#[cfg(not(any(
-    all(target_os = "macos", feature = "backend-vt"),
-    all(
-        feature = "backend-nvidia",
-        any(target_os = "linux", target_os = "windows")
-    )
-)))]
-impl VideoDecoder for DecoderInner {
-    fn query_capability(&self, _codec: Codec) -> Result<CapabilityReport, BackendError> {
-        Ok(CapabilityReport {
-            codec: _codec,
-            decode_supported: false,
-            encode_supported: false,
-            hardware_acceleration: false,
-        })
+    fn surface_pool(&self) -> Option<SurfacePoolSizes> {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.surface_pool(),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.surface_pool(),
+            Self::Unsupported(inner) => inner.surface_pool(),
+        }
     }
 
-    fn push_bitstream_chunk(
-        &mut self,
-        _chunk: &[u8],
-        _pts_90k: Option<i64>,
-    ) -> Result<Vec<Frame>, BackendError> {
-        Err(BackendError::UnsupportedConfig(
-            "no backend feature enabled".to_string(),
-        ))
+    fn frame_crop(&self) -> Option<FrameCrop> {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.frame_crop(),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.frame_crop(),
+            Self::Unsupported(inner) => inner.frame_crop(),
+        }
     }
 
-    fn flush(&mut self) -> Result<Vec<Frame>, BackendError> {
-        Err(BackendError::UnsupportedConfig(
-            "no backend feature enabled".to_string(),
-        ))
+    fn device_memory(&self) -> Option<DeviceMemory> {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.device_memory(),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.device_memory(),
+            Self::Unsupported(inner) => inner.device_memory(),
+        }
     }
 
-    fn decode_summary(&self) -> DecodeSummary {
-        DecodeSummary {
-            decoded_frames: 0,
-            width: None,
-            height: None,
-            pixel_format: None,
+    fn trim_pools(&mut self) {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.trim_pools(),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.trim_pools(),
+            Self::Unsupported(inner) => inner.trim_pools(),
+        }
+    }
+
+    fn parameter_set_revision(&self) -> u64 {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.parameter_set_revision(),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.parameter_set_revision(),
+            Self::Unsupported(inner) => inner.parameter_set_revision(),
+        }
+    }
+
+    fn close(&mut self) -> Result<(), BackendError> {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.close(),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.close(),
+            Self::Unsupported(inner) => inner.close(),
+        }
+    }
+
+    fn set_diagnostics(&mut self, diagnostics: Diagnostics) {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.set_diagnostics(diagnostics),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.set_diagnostics(diagnostics),
+            Self::Unsupported(inner) => inner.set_diagnostics(diagnostics),
+        }
+    }
+}
+
+#[cfg(not(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+)))]
+impl VideoDecoder for DecoderInner {
+    fn query_capability(&self, _codec: Codec) -> Result<CapabilityReport, BackendError> {
+        Ok(CapabilityReport {
+            codec: _codec,
+            decode_supported: false,
+            encode_supported: false,
+            hardware_acceleration: false,
+        })
+    }
+
+    fn push_bitstream_chunk(
+        &mut self,
+        _chunk: &[u8],
+        _pts_90k: Option<i64>,
+    ) -> Result<Vec<Frame>, BackendError> {
+        Err(BackendError::UnsupportedConfig(
+            "no backend feature enabled".to_string(),
+        ))
+    }
+
+    fn flush(&mut self) -> Result<Vec<Frame>, BackendError> {
+        Err(BackendError::UnsupportedConfig(
+            "no backend feature enabled".to_string(),
+        ))
+    }
+
+    fn decode_summary(&self) -> DecodeSummary {
+        DecodeSummary {
+            decoded_frames: 0,
+            width: None,
+            height: None,
+            pixel_format: None,
         }
     }
 }
@@ -372,6 +817,123 @@ impl VideoEncoder for EncoderInner {
             Self::Unsupported(inner) => inner.request_session_switch(request),
         }
     }
+
+    fn invalidate_reference(&mut self, pts_90k: i64) -> Result<(), BackendError> {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.invalidate_reference(pts_90k),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.invalidate_reference(pts_90k),
+            Self::Unsupported(inner) => inner.invalidate_reference(pts_90k),
+        }
+    }
+
+    fn pipeline_depth(&self) -> usize {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.pipeline_depth(),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.pipeline_depth(),
+            Self::Unsupported(inner) => inner.pipeline_depth(),
+        }
+    }
+
+    fn buffer_lifetime_mode(&self) -> Option<NvBufferLifetimeMode> {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.buffer_lifetime_mode(),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.buffer_lifetime_mode(),
+            Self::Unsupported(inner) => inner.buffer_lifetime_mode(),
+        }
+    }
+
+    fn induced_latency(&self) -> Option<EncodeLatency> {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.induced_latency(),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.induced_latency(),
+            Self::Unsupported(inner) => inner.induced_latency(),
+        }
+    }
+
+    fn surface_pool(&self) -> Option<SurfacePoolSizes> {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.surface_pool(),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.surface_pool(),
+            Self::Unsupported(inner) => inner.surface_pool(),
+        }
+    }
+
+    fn device_memory(&self) -> Option<DeviceMemory> {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.device_memory(),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.device_memory(),
+            Self::Unsupported(inner) => inner.device_memory(),
+        }
+    }
+
+    fn trim_pools(&mut self) {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.trim_pools(),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.trim_pools(),
+            Self::Unsupported(inner) => inner.trim_pools(),
+        }
+    }
+
+    fn close(&mut self) -> Result<(), BackendError> {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.close(),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.close(),
+            Self::Unsupported(inner) => inner.close(),
+        }
+    }
+
+    fn set_diagnostics(&mut self, diagnostics: Diagnostics) {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(inner) => inner.set_diagnostics(diagnostics),
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(inner) => inner.set_diagnostics(diagnostics),
+            Self::Unsupported(inner) => inner.set_diagnostics(diagnostics),
+        }
+    }
 }
 
 #[cfg(not(any(
@@ -413,6 +975,21 @@ impl VideoEncoder for EncoderInner {
     }
 }
 
+impl EncoderInner {
+    fn switch_target(&self) -> config_diff::SwitchTarget {
+        match self {
+            #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+            Self::VideoToolbox(_) => config_diff::SwitchTarget::VideoToolbox,
+            #[cfg(all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            ))]
+            Self::Nvidia(_) => config_diff::SwitchTarget::Nvidia,
+            _ => config_diff::SwitchTarget::None,
+        }
+    }
+}
+
 #[cfg(any(
     all(target_os = "macos", feature = "backend-vt"),
     all(
@@ -440,10 +1017,44 @@ impl BackendKind {
 pub struct DecodeSession {
     decoder_inner: DecoderInner,
     ready: VecDeque<DecodedFrame>,
+    ready_peak: usize,
+    ready_capacity: Option<usize>,
+    events: stream_events::StreamEventTracker,
+    concealer: freeze_frame::FreezeFrameConcealer,
+    output_filter: output_filter::OutputFilterState,
+    analyzer: frame_analysis::FrameAnalyzer,
+    memory_pressure: memory_pressure::MemoryPressureMonitor,
+    watchdog: decode_watchdog::DecodeWatchdog,
+    tracer: frame_trace::FrameTracer,
+    gpu_budget: Option<(GpuBudget, EncodePriority)>,
+    host_claim: Option<HostSessionClaim>,
+    utilization: utilization::UtilizationTracker,
+    clock: Arc<dyn Clock>,
+    reap_waiter: reap_cancel::ReapWaiter,
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    fallback: Option<fallback::DecodeFallback>,
 }
 
 impl DecodeSession {
     pub fn new(backend: Backend, config: DecoderConfig) -> Self {
+        Self::with_diagnostics(backend, config, Diagnostics::default())
+    }
+
+    pub fn with_diagnostics(
+        backend: Backend,
+        config: DecoderConfig,
+        diagnostics: Diagnostics,
+    ) -> Self {
+        let codec = config.codec;
+        let clock = clock::system_clock();
+        let watchdog_config = config.clone();
+        let reap_waiter = reap_cancel::ReapWaiter::new();
         #[cfg(any(
             all(target_os = "macos", feature = "backend-vt"),
             all(
@@ -451,10 +1062,24 @@ impl DecodeSession {
                 any(target_os = "linux", target_os = "windows")
             )
         ))]
-        let decoder_inner: DecoderInner = match resolve_decoder_backend(backend, &config) {
-            Ok(selected) => build_decoder_inner(selected, config),
-            Err(err) => DecoderInner::Unsupported(UnsupportedDecoderAdapter::new(err.to_string())),
-        };
+        let (backend_kind, decoder_inner, fallback, created) =
+            match resolve_decoder_backend(backend, &config) {
+                Ok(selected) => {
+                    let (inner, fallback) = fallback::DecodeFallback::start(
+                        selected,
+                        config,
+                        &diagnostics,
+                        reap_waiter.ready_waker(),
+                    );
+                    (selected, inner, fallback, true)
+                }
+                Err(err) => {
+                    let mut inner =
+                        DecoderInner::Unsupported(UnsupportedDecoderAdapter::new(err.to_string()));
+                    inner.set_diagnostics(diagnostics.clone());
+                    (backend, inner, None, false)
+                }
+            };
         #[cfg(not(any(
             all(target_os = "macos", feature = "backend-vt"),
             all(
@@ -462,14 +1087,63 @@ impl DecodeSession {
                 any(target_os = "linux", target_os = "windows")
             )
         )))]
-        let decoder_inner = build_decoder_inner(backend, config);
+        let (backend_kind, decoder_inner, created) = {
+            let mut inner = build_decoder_inner(backend, config);
+            inner.set_diagnostics(diagnostics.clone());
+            (backend, inner, false)
+        };
+        if created {
+            diagnostics.emit(DiagnosticEvent::SessionCreated {
+                backend: backend_kind.to_string(),
+                codec,
+            });
+        }
+        let watchdog = decode_watchdog::DecodeWatchdog::new(
+            backend_kind,
+            watchdog_config,
+            diagnostics.clone(),
+            reap_waiter.ready_waker(),
+        );
         Self {
             decoder_inner,
             ready: VecDeque::new(),
+            ready_peak: 0,
+            ready_capacity: None,
+            events: stream_events::StreamEventTracker::default(),
+            concealer: freeze_frame::FreezeFrameConcealer::default(),
+            output_filter: output_filter::OutputFilterState::new(codec),
+            analyzer: frame_analysis::FrameAnalyzer::default(),
+            memory_pressure: memory_pressure::MemoryPressureMonitor::new(diagnostics),
+            watchdog,
+            tracer: frame_trace::FrameTracer::default(),
+            gpu_budget: None,
+            host_claim: None,
+            utilization: utilization::UtilizationTracker::new(clock.now()),
+            clock,
+            reap_waiter,
+            #[cfg(any(
+                all(target_os = "macos", feature = "backend-vt"),
+                all(
+                    feature = "backend-nvidia",
+                    any(target_os = "linux", target_os = "windows")
+                )
+            ))]
+            fallback,
         }
     }
 
     pub fn submit(&mut self, input: BitstreamInput) -> Result<(), BackendError> {
+        // Checked before the backend sees the input, so a rejected submit can simply be retried
+        // once the consumer has reaped some frames.
+        if let Some(capacity) = self.ready_capacity
+            && self.ready.len() >= capacity
+        {
+            return Err(BackendError::TemporaryBackpressure(format!(
+                "decode ready queue is full ({} frames, capacity {capacity})",
+                self.ready.len()
+            )));
+        }
+        let submitted_at = self.clock.now();
         let (annexb, pts_90k) = match input {
             BitstreamInput::AnnexBChunk { chunk, pts_90k } => (chunk, pts_90k.map(|v| v.0)),
             BitstreamInput::AccessUnitRawNal {
@@ -489,134 +1163,1523 @@ impl DecodeSession {
                 pts_90k.map(|v| v.0),
             ),
         };
-        let outputs = self
-            .decoder_inner
-            .push_bitstream_chunk(&annexb, pts_90k)?
-            .into_iter()
-            .map(legacy_to_decoded_frame)
-            .collect::<Vec<_>>();
+        if self
+            .memory_pressure
+            .sample(submitted_at, || self.decoder_inner.device_memory())
+        {
+            self.trim_pools();
+        }
+        let traced_pts = pts_90k.map(Timestamp90k);
+        self.tracer.submitted(traced_pts, submitted_at);
+        let now = self.clock.now();
+        self.tracer.stamp(traced_pts, TraceStage::Assembled, now);
+        self.output_filter.observe_input(&annexb, pts_90k);
+        self.tracer.stamp(traced_pts, TraceStage::HardwareIn, now);
+        let permit = self.acquire_gpu_budget();
+        self.utilization.begin(self.clock.now());
+        let pushed = self.push_to_backend(&annexb, pts_90k);
+        self.utilization.end(1, self.clock.now());
+        drop(permit);
+        self.events.observe_parameter_sets(
+            self.decoder_inner.parameter_set_revision(),
+            self.decoder_inner.frame_crop(),
+        );
+        if pushed
+            .as_ref()
+            .is_err_and(|err| self.memory_pressure.observe_error(err))
+        {
+            self.trim_pools();
+        }
+        let pushed = pushed.inspect_err(|_| {
+            self.events.observe_error();
+            self.tracer.discard(traced_pts);
+        })?;
+        self.watchdog.observe_input(now, &annexb, pts_90k);
+        let outputs = self.accept_frames(pushed);
         self.ready.extend(outputs);
-        Ok(())
+        self.ready_peak = self.ready_peak.max(self.ready.len());
+        self.check_stall()
+    }
+
+    pub fn ready_stats(&self) -> QueueStats {
+        QueueStats {
+            depth: self.ready.len(),
+            peak_depth: self.ready_peak,
+        }
+    }
+
+    pub fn set_ready_capacity(&mut self, capacity: Option<usize>) {
+        self.ready_capacity = capacity;
     }
 
     pub fn try_reap(&mut self) -> Result<Option<DecodedFrame>, BackendError> {
-        Ok(self.ready.pop_front())
+        let frame = self.ready.pop_front();
+        self.mark_reaped(frame.iter());
+        Ok(frame)
     }
 
-    pub fn reap_timeout(
+    // Per-frame stage timestamps for frames reaped from now on, collected with
+    // take_frame_traces; see TraceStage. Off by default.
+    pub fn set_frame_tracing(&mut self, enabled: bool) {
+        self.tracer.set_enabled(enabled);
+    }
+
+    // Traces of frames reaped since the last call, in reap order; match them to the frames by
+    // pts.
+    pub fn take_frame_traces(&mut self) -> Vec<FrameTrace> {
+        self.tracer.take_completed()
+    }
+
+    // Shares one GPU's hardware slots with the other sessions registered on `budget`; see
+    // GpuBudget. Every backend call waits for a slot at `priority`.
+    pub fn set_gpu_budget(&mut self, budget: &GpuBudget, priority: EncodePriority) {
+        self.gpu_budget = Some((budget.clone(), priority));
+    }
+
+    pub fn clear_gpu_budget(&mut self) {
+        self.gpu_budget = None;
+    }
+
+    // Takes a slot for this session in a registry shared with other processes (see
+    // HostSessionArbiter), held until the session is dropped or the arbiter cleared. Fails
+    // with SessionLimitReached, naming the processes that hold the slots, when the GPU is
+    // already at the arbiter's limit; call it before the first submit so no hardware session
+    // is opened past the limit. A slot already held is only given up once the new claim
+    // succeeds, so a failed call leaves the session registered as before.
+    pub fn set_host_session_arbiter(
         &mut self,
-        _timeout: Duration,
-    ) -> Result<Option<DecodedFrame>, BackendError> {
-        self.try_reap()
+        arbiter: &HostSessionArbiter,
+    ) -> Result<(), BackendError> {
+        let claim = arbiter.claim(host_sessions::SESSION_GPU, HostSessionKind::Decode)?;
+        self.host_claim = Some(claim);
+        Ok(())
     }
 
-    pub fn flush(&mut self) -> Result<Vec<DecodedFrame>, BackendError> {
-        let mut out = std::mem::take(&mut self.ready)
-            .into_iter()
-            .collect::<Vec<_>>();
-        out.extend(
-            self.decoder_inner
-                .flush()?
-                .into_iter()
-                .map(legacy_to_decoded_frame)
-                .collect::<Vec<_>>(),
-        );
-        Ok(out)
+    pub fn clear_host_session_arbiter(&mut self) {
+        self.host_claim = None;
     }
 
-    pub fn summary(&self) -> DecodeSummary {
-        self.decoder_inner.decode_summary()
+    fn acquire_gpu_budget(&self) -> Option<GpuBudgetPermit> {
+        self.gpu_budget
+            .as_ref()
+            .map(|(budget, priority)| budget.acquire(*priority))
     }
 
-    pub fn query_capability(&self, codec: Codec) -> Result<CapabilityReport, BackendError> {
-        self.decoder_inner.query_capability(codec)
+    fn mark_reaped<'a>(&mut self, frames: impl IntoIterator<Item = &'a DecodedFrame>) {
+        let now = self.clock.now();
+        for frame in frames {
+            self.tracer.reaped(frame.pts_90k(), now);
+        }
     }
-}
 
-pub struct EncodeSession {
-    backend_kind: BackendKind,
-    encoder_inner: EncoderInner,
-    ready: VecDeque<EncodedChunk>,
-}
+    // Opt-in error concealment: conceal_lost_frame then queues FREEZE_FRAME stand-ins.
+    pub fn set_freeze_frame_concealment(&mut self, enabled: bool) {
+        self.concealer.set_enabled(enabled);
+    }
 
-impl EncodeSession {
-    pub fn new(backend: Backend, config: EncoderConfig) -> Self {
-        #[cfg(any(
-            all(target_os = "macos", feature = "backend-vt"),
-            all(
-                feature = "backend-nvidia",
-                any(target_os = "linux", target_os = "windows")
-            )
-        ))]
-        let (backend_kind, encoder_inner): (BackendKind, EncoderInner) =
-            match resolve_encoder_backend(backend, &config) {
-                Ok(selected) => (selected, build_encoder_inner(selected, config)),
-                Err(err) => (
-                    fallback_backend_kind(backend),
-                    EncoderInner::Unsupported(UnsupportedEncoderAdapter::new(err.to_string())),
-                ),
-            };
-        #[cfg(not(any(
-            all(target_os = "macos", feature = "backend-vt"),
-            all(
-                feature = "backend-nvidia",
-                any(target_os = "linux", target_os = "windows")
-            )
-        )))]
-        let (backend_kind, encoder_inner) = (backend, build_encoder_inner(backend, config));
-        Self {
+    // Reports an access unit that will never be decoded (e.g. dropped by a JitterBuffer while
+    // waiting for the requested keyframe). Returns whether a freeze frame was queued; it is
+    // ordered after everything decoded so far.
+    pub fn conceal_lost_frame(&mut self, pts_90k: Option<Timestamp90k>) -> bool {
+        let Some(frame) = self.concealer.conceal(pts_90k) else {
+            return false;
+        };
+        if !self.output_filter.admit(&frame) {
+            return false;
+        }
+        self.ready.push_back(frame);
+        self.ready_peak = self.ready_peak.max(self.ready.len());
+        true
+    }
+
+    pub fn concealed_frames(&self) -> u64 {
+        self.concealer.injected()
+    }
+
+    // Luma statistics and black/freeze detection for every decoded frame from now on,
+    // collected with take_frame_analyses; None turns it off (the default). On NVIDIA this
+    // reads the GPU-computed histogram, so it needs NvidiaDecoderOptions::histogram; frames
+    // without pixels or a histogram are not analyzed. Filtered-out frames are analyzed too.
+    pub fn set_frame_analysis(&mut self, options: Option<FrameAnalysisOptions>) {
+        self.analyzer.set_options(options);
+    }
+
+    pub fn frame_analysis(&self) -> Option<FrameAnalysisOptions> {
+        self.analyzer.options()
+    }
+
+    // Reports of frames decoded since the last call, in output order; match them to the
+    // frames by pts.
+    pub fn take_frame_analyses(&mut self) -> Vec<FrameAnalysis> {
+        self.analyzer.take()
+    }
+
+    // Watches device memory for long-running servers, reporting DiagnosticEvent::MemoryPressure
+    // to this session's diagnostics sink; see MemoryPressureOptions. None turns it off (the
+    // default).
+    pub fn set_memory_pressure_monitoring(&mut self, options: Option<MemoryPressureOptions>) {
+        self.memory_pressure.set_options(options);
+    }
+
+    pub fn memory_pressure_monitoring(&self) -> Option<MemoryPressureOptions> {
+        self.memory_pressure.options()
+    }
+
+    pub fn memory_pressure(&self) -> MemoryPressureLevel {
+        self.memory_pressure.level()
+    }
+
+    // Turns a hung decoder into BackendError::Stalled from submit or reap_timeout, reported as
+    // DiagnosticEvent::DecoderStalled, instead of reap_timeout returning None forever; see
+    // DecodeWatchdogOptions. None turns it off (the default).
+    pub fn set_decode_watchdog(&mut self, options: Option<DecodeWatchdogOptions>) {
+        self.watchdog.set_options(options);
+    }
+
+    pub fn decode_watchdog(&self) -> Option<DecodeWatchdogOptions> {
+        self.watchdog.options()
+    }
+
+    // Stalls detected so far, recovered or not.
+    pub fn stalls(&self) -> u64 {
+        self.watchdog.stalls()
+    }
+
+    fn trim_pools(&mut self) {
+        self.decoder_inner.trim_pools();
+        self.ready.shrink_to_fit();
+    }
+
+    // Takes effect for frames decoded from now on; frames already queued stay. Keyframes are
+    // recognised from input submitted after keyframes_only was turned on.
+    pub fn set_output_filter(&mut self, filter: DecodeOutputFilter) {
+        self.output_filter.set(filter);
+    }
+
+    pub fn output_filter(&self) -> &DecodeOutputFilter {
+        self.output_filter.filter()
+    }
+
+    // Decoded frames the output filter has dropped so far.
+    pub fn filtered_frames(&self) -> u64 {
+        self.output_filter.dropped()
+    }
+
+    // Replaces the real clock, e.g. with a ManualClock in tests. Time-based statistics restart
+    // from the new clock's current time.
+    #[must_use]
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self.utilization = utilization::UtilizationTracker::new(self.clock.now());
+        self
+    }
+
+    // Decode engine busy share over the last second, estimated from time spent in the backend.
+    pub fn utilization(&self) -> EngineUtilization {
+        self.utilization.snapshot(self.clock.now())
+    }
+
+    #[cfg(all(
+        feature = "nvml",
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    ))]
+    // Health monitor for the GPU this session decodes on, for migrating off ailing devices.
+    pub fn gpu_monitor(&self) -> Result<GpuMonitor, BackendError> {
+        if !matches!(self.decoder_inner, DecoderInner::Nvidia(_)) {
+            return Err(BackendError::UnsupportedConfig(
+                "GPU health monitoring requires an NVIDIA session".to_string(),
+            ));
+        }
+        GpuMonitor::open(NVIDIA_SESSION_DEVICE_INDEX)
+    }
+
+    // Stream property changes are queued as they are detected, independently of frame reaping.
+    pub fn try_reap_event(&mut self) -> Option<StreamEvent> {
+        self.events.pop()
+    }
+
+    pub fn drain_events(&mut self) -> Vec<StreamEvent> {
+        self.events.drain()
+    }
+
+    // Blocks until a frame is ready, for at most `timeout`. The wait sleeps until a backend
+    // callback reports finished work and only then polls for it, without pushing buffered
+    // input through. Returns Ok(None) on timeout or when woken by a ReapCanceller.
+    pub fn reap_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<DecodedFrame>, BackendError> {
+        let deadline = Instant::now().checked_add(timeout);
+        loop {
+            if self.ready.is_empty() {
+                self.queue_ready()?;
+                self.check_stall()?;
+            }
+            if let Some(frame) = self.try_reap()? {
+                return Ok(Some(frame));
+            }
+            if !self.reap_waiter.wait(deadline) {
+                self.check_stall()?;
+                return Ok(None);
+            }
+        }
+    }
+
+    pub fn reap_canceller(&self) -> ReapCanceller {
+        self.reap_waiter.canceller()
+    }
+
+    // Frames presented before `pts_90k`, in output order. Later frames stay queued, so a
+    // segmenter can cut a time window without flushing the decoder.
+    pub fn reap_until(&mut self, pts_90k: Timestamp90k) -> Result<Vec<DecodedFrame>, BackendError> {
+        self.queue_ready()?;
+        let reaped = take_before(&mut self.ready, pts_90k, DecodedFrame::pts_90k);
+        self.mark_reaped(&reaped);
+        Ok(reaped)
+    }
+
+    pub fn flush(&mut self) -> Result<Vec<DecodedFrame>, BackendError> {
+        let mut out = std::mem::take(&mut self.ready)
+            .into_iter()
+            .collect::<Vec<_>>();
+        let permit = self.acquire_gpu_budget();
+        self.utilization.begin(self.clock.now());
+        let flushed = self.flush_backend();
+        self.utilization.idle(self.clock.now());
+        drop(permit);
+        out.extend(self.accept_frames(flushed?));
+        self.mark_reaped(&out);
+        Ok(out)
+    }
+
+    // Flushes, then tears the backend down in a fixed order (decoder, then its device
+    // context). Resources are released even when the flush fails; the first error wins.
+    pub fn close(mut self) -> Result<Vec<DecodedFrame>, BackendError> {
+        let drained = self.flush();
+        let closed = self.decoder_inner.close();
+        let drained = drained?;
+        closed?;
+        Ok(drained)
+    }
+
+    // Frame-accurate random access: drops everything decoded so far, decodes from the last
+    // keyframe at or before `target` and discards frames until the one of the access unit
+    // holding `target`, which is returned. The decoder starts over at that keyframe, so the
+    // stream must not depend on pictures before it (closed GOPs, as IDRs guarantee).
+    #[cfg(feature = "bitstream")]
+    pub fn seek(
+        &mut self,
+        index: &BitstreamIndex,
+        target: Timestamp90k,
+    ) -> Result<SeekedFrame, BackendError> {
+        let (start, target_index) = index.seek_range(target)?;
+        let target_pts = index.entries()[target_index].pts_90k;
+        let flushed = self.flush_backend();
+        self.discard_ready(self.ready.len());
+        flushed?;
+        for position in start..index.access_unit_count() {
+            let input = if position == start {
+                index.seek_input(position)
+            } else {
+                index.input(position)
+            };
+            if let Some(input) = input {
+                self.submit(input)?;
+            }
+            if let Some(frame) = self.take_seek_target(target_pts) {
+                return Ok(SeekedFrame {
+                    frame,
+                    next_access_unit: position + 1,
+                });
+            }
+        }
+        let flushed = self.flush_backend()?;
+        let outputs = self.accept_frames(flushed);
+        self.ready.extend(outputs);
+        self.take_seek_target(target_pts)
+            .map(|frame| SeekedFrame {
+                frame,
+                next_access_unit: index.access_unit_count(),
+            })
+            .ok_or_else(|| {
+                BackendError::Backend(format!(
+                    "decoder produced no frame with pts {} while seeking",
+                    target_pts.0
+                ))
+            })
+    }
+
+    // Everything queued up to the target frame precedes it in output order and is dropped.
+    #[cfg(feature = "bitstream")]
+    fn take_seek_target(&mut self, pts_90k: Timestamp90k) -> Option<DecodedFrame> {
+        let Some(position) = self
+            .ready
+            .iter()
+            .position(|frame| frame.pts_90k() == Some(pts_90k))
+        else {
+            self.discard_ready(self.ready.len());
+            return None;
+        };
+        self.discard_ready(position);
+        let frame = self.ready.pop_front();
+        self.mark_reaped(frame.iter());
+        frame
+    }
+
+    #[cfg(feature = "bitstream")]
+    fn discard_ready(&mut self, count: usize) {
+        for frame in self.ready.drain(..count) {
+            self.tracer.discard(frame.pts_90k());
+        }
+    }
+
+    pub fn drain_available(&mut self) -> Result<Vec<DecodedFrame>, BackendError> {
+        let mut out = std::mem::take(&mut self.ready)
+            .into_iter()
+            .collect::<Vec<_>>();
+        let drained = self.decoder_inner.drain_available()?;
+        out.extend(self.accept_frames(drained));
+        self.mark_reaped(&out);
+        Ok(out)
+    }
+
+    pub fn summary(&self) -> DecodeSummary {
+        self.decoder_inner.decode_summary()
+    }
+
+    // Coded size and SPS cropping window of the active stream; decoded frames already carry
+    // the cropped (display) size.
+    pub fn frame_crop(&self) -> Option<FrameCrop> {
+        self.decoder_inner.frame_crop()
+    }
+
+    // Surfaces the backend allocated for this stream, known once the first sequence header
+    // has been decoded (NVDEC only).
+    pub fn surface_pool(&self) -> Option<SurfacePoolSizes> {
+        self.decoder_inner.surface_pool()
+    }
+
+    pub fn query_capability(&self, codec: Codec) -> Result<CapabilityReport, BackendError> {
+        self.decoder_inner.query_capability(codec)
+    }
+
+    // Frames a rebuilt decoder replayed are queued before the error is returned.
+    fn check_stall(&mut self) -> Result<(), BackendError> {
+        let Some((err, replayed)) = self
+            .watchdog
+            .poll(self.clock.now(), &mut self.decoder_inner)
+        else {
+            return Ok(());
+        };
+        let outputs = self.accept_frames(replayed);
+        self.ready.extend(outputs);
+        Err(err)
+    }
+
+    fn queue_ready(&mut self) -> Result<(), BackendError> {
+        let ready = self.decoder_inner.poll_ready()?;
+        let outputs = self.accept_frames(ready);
+        self.ready.extend(outputs);
+        self.ready_peak = self.ready_peak.max(self.ready.len());
+        Ok(())
+    }
+
+    fn accept_frames(&mut self, frames: Vec<Frame>) -> Vec<DecodedFrame> {
+        self.watchdog
+            .observe_output(frames.iter().map(|frame| frame.pts_90k));
+        let now = self.clock.now();
+        frames
+            .into_iter()
+            .map(legacy_to_decoded_frame)
+            .inspect(|frame| {
+                self.events.observe_frame(frame);
+                self.concealer.observe(frame);
+                self.analyzer.observe(frame);
+                self.tracer
+                    .stamp(frame.pts_90k(), TraceStage::HardwareOut, now);
+            })
+            .filter(|frame| self.output_filter.admit(frame))
+            .collect()
+    }
+
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    fn push_to_backend(
+        &mut self,
+        chunk: &[u8],
+        pts_90k: Option<i64>,
+    ) -> Result<Vec<Frame>, BackendError> {
+        if let Some(fallback) = self.fallback.as_mut() {
+            fallback.record(chunk, pts_90k);
+        }
+        let frames = match self.decoder_inner.push_bitstream_chunk(chunk, pts_90k) {
+            Ok(frames) => frames,
+            Err(err) => self.recover(err)?,
+        };
+        if !frames.is_empty() {
+            self.fallback = None;
+        }
+        Ok(frames)
+    }
+
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    fn flush_backend(&mut self) -> Result<Vec<Frame>, BackendError> {
+        self.watchdog.flushed();
+        let mut frames = Vec::new();
+        loop {
+            match self.decoder_inner.flush() {
+                Ok(flushed) => {
+                    frames.extend(flushed);
+                    self.fallback = None;
+                    return Ok(frames);
+                }
+                Err(err) => frames.extend(self.recover(err)?),
+            }
+        }
+    }
+
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    fn recover(&mut self, err: BackendError) -> Result<Vec<Frame>, BackendError> {
+        match self.fallback.as_mut() {
+            Some(fallback) => fallback.recover(&mut self.decoder_inner, err),
+            None => Err(err),
+        }
+    }
+
+    #[cfg(not(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    )))]
+    fn push_to_backend(
+        &mut self,
+        chunk: &[u8],
+        pts_90k: Option<i64>,
+    ) -> Result<Vec<Frame>, BackendError> {
+        self.decoder_inner.push_bitstream_chunk(chunk, pts_90k)
+    }
+
+    #[cfg(not(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    )))]
+    fn flush_backend(&mut self) -> Result<Vec<Frame>, BackendError> {
+        self.watchdog.flushed();
+        self.decoder_inner.flush()
+    }
+}
+
+pub struct EncodeSession {
+    backend_kind: BackendKind,
+    encoder_inner: EncoderInner,
+    // What the session was asked for, kept so apply_config can diff and rebuild.
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    requested_backend: Backend,
+    config: EncoderConfig,
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    diagnostics: Diagnostics,
+    ready: VecDeque<EncodedChunk>,
+    reap_waiter: reap_cancel::ReapWaiter,
+    sink: Option<Box<dyn EncodedSink>>,
+    chunk_transforms: Vec<Box<dyn ChunkTransform>>,
+    pre_encode_hooks: Vec<Box<dyn PreEncodeHook>>,
+    rate_control: Option<rate_control::RateControlState>,
+    memory_pressure: memory_pressure::MemoryPressureMonitor,
+    cbr_padder: Option<cbr_filler::CbrPadder>,
+    reorder: reorder_info::ReorderTracker,
+    tracer: frame_trace::FrameTracer,
+    gpu_budget: Option<(GpuBudget, EncodePriority)>,
+    host_claim: Option<HostSessionClaim>,
+    master_clock: Option<master_clock::MasterClockStamper>,
+    summary: EncodeSummary,
+    repeat_mode: FrameRepeatMode,
+    // Set by a Resize switch request; later frames must have this size.
+    resized_dims: Option<Dimensions>,
+    // Frames since the first one (or the last rebuild/resize), for keyframe_interval.
+    keyframe_phase: u64,
+    chunk_index: Option<ChunkIndex>,
+    utilization: utilization::UtilizationTracker,
+    clock: Arc<dyn Clock>,
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    fallback: Option<fallback::EncodeFallback>,
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    integrity: Option<integrity_check::IntegrityChecker>,
+}
+
+impl EncodeSession {
+    pub fn new(backend: Backend, config: EncoderConfig) -> Self {
+        Self::with_diagnostics(backend, config, Diagnostics::default())
+    }
+
+    pub fn with_diagnostics(
+        backend: Backend,
+        config: EncoderConfig,
+        diagnostics: Diagnostics,
+    ) -> Self {
+        let codec = config.codec;
+        let clock = clock::system_clock();
+        let summary = EncodeSummary::new(config.fps);
+        let repeat_mode = config.repeat_mode;
+        let cbr_padder = cbr_filler::CbrPadder::for_config(&config);
+        let requested_config = config.clone();
+        #[cfg(any(
+            all(target_os = "macos", feature = "backend-vt"),
+            all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            )
+        ))]
+        let (backend_kind, encoder_inner, fallback, created) =
+            match resolve_encoder_backend(backend, &config) {
+                Ok(selected) => {
+                    let (inner, fallback) =
+                        fallback::EncodeFallback::start(selected, config, &diagnostics);
+                    (selected, inner, fallback, true)
+                }
+                Err(err) => {
+                    let mut inner =
+                        EncoderInner::Unsupported(UnsupportedEncoderAdapter::new(err.to_string()));
+                    inner.set_diagnostics(diagnostics.clone());
+                    (fallback_backend_kind(backend), inner, None, false)
+                }
+            };
+        #[cfg(not(any(
+            all(target_os = "macos", feature = "backend-vt"),
+            all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            )
+        )))]
+        let (backend_kind, encoder_inner, created) = {
+            let mut inner = build_encoder_inner(backend, config);
+            inner.set_diagnostics(diagnostics.clone());
+            (backend, inner, false)
+        };
+        if created {
+            diagnostics.emit(DiagnosticEvent::SessionCreated {
+                backend: backend_kind.to_string(),
+                codec,
+            });
+        }
+        let memory_pressure = memory_pressure::MemoryPressureMonitor::new(diagnostics.clone());
+        Self {
             backend_kind,
             encoder_inner,
+            #[cfg(any(
+                all(target_os = "macos", feature = "backend-vt"),
+                all(
+                    feature = "backend-nvidia",
+                    any(target_os = "linux", target_os = "windows")
+                )
+            ))]
+            requested_backend: backend,
+            config: requested_config,
+            #[cfg(any(
+                all(target_os = "macos", feature = "backend-vt"),
+                all(
+                    feature = "backend-nvidia",
+                    any(target_os = "linux", target_os = "windows")
+                )
+            ))]
+            diagnostics,
             ready: VecDeque::new(),
+            reap_waiter: reap_cancel::ReapWaiter::new(),
+            sink: None,
+            chunk_transforms: Vec::new(),
+            pre_encode_hooks: Vec::new(),
+            rate_control: None,
+            memory_pressure,
+            cbr_padder,
+            reorder: reorder_info::ReorderTracker::new(),
+            tracer: frame_trace::FrameTracer::default(),
+            gpu_budget: None,
+            host_claim: None,
+            master_clock: None,
+            summary,
+            repeat_mode,
+            resized_dims: None,
+            keyframe_phase: 0,
+            chunk_index: None,
+            utilization: utilization::UtilizationTracker::new(clock.now()),
+            clock,
+            #[cfg(any(
+                all(target_os = "macos", feature = "backend-vt"),
+                all(
+                    feature = "backend-nvidia",
+                    any(target_os = "linux", target_os = "windows")
+                )
+            ))]
+            fallback,
+            #[cfg(any(
+                all(target_os = "macos", feature = "backend-vt"),
+                all(
+                    feature = "backend-nvidia",
+                    any(target_os = "linux", target_os = "windows")
+                )
+            ))]
+            integrity: None,
+        }
+    }
+
+    pub fn submit(&mut self, frame: EncodeFrame) -> Result<(), BackendError> {
+        let repeats = match self.repeat_mode {
+            FrameRepeatMode::Resubmit => frame.repeat_count,
+            FrameRepeatMode::Hold => 0,
+        };
+        if repeats == 0 {
+            return self.submit_one(frame);
+        }
+        for frame in repeat_frames(frame, repeats, self.summary.fps) {
+            self.submit_one(frame)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    // One MV-HEVC stereo pair: `left` goes to the base layer and `right` to the second layer of
+    // the same access unit. Needs an HEVC session on a backend that mv_hevc_support says can
+    // encode, and both views at the same size and timestamp. Pre-encode hooks see each view;
+    // repeat_count is not expanded for pairs.
+    pub fn submit_stereo(
+        &mut self,
+        left: EncodeFrame,
+        mut right: EncodeFrame,
+    ) -> Result<(), BackendError> {
+        if self.config.codec != Codec::Hevc {
+            return Err(BackendError::UnsupportedConfig(format!(
+                "stereo pairs need an hevc session, not {}",
+                self.config.codec
+            )));
+        }
+        if !multiview::mv_hevc_support(self.backend_kind).encode {
+            return Err(BackendError::UnsupportedConfig(format!(
+                "{} cannot encode MV-HEVC on this machine",
+                self.backend_kind
+            )));
+        }
+        if left.dims != right.dims || left.pts_90k != right.pts_90k {
+            return Err(BackendError::InvalidInput(
+                "stereo views must share dimensions and timestamp".to_string(),
+            ));
+        }
+        pre_encode::apply_pre_encode_hooks(&mut self.pre_encode_hooks, &mut right)?;
+        let right = encode_frame_to_legacy(right)?.argb;
+        self.submit_with(left, move |frame| {
+            let mut legacy = encode_frame_to_legacy(frame)?;
+            legacy.stereo_right = right;
+            Ok(legacy)
+        })
+    }
+
+    fn submit_one(&mut self, frame: EncodeFrame) -> Result<(), BackendError> {
+        self.submit_with(frame, encode_frame_to_legacy)
+    }
+
+    // `to_backend` turns the frame, once the pre-encode hooks ran, into what the backend takes.
+    fn submit_with(
+        &mut self,
+        mut frame: EncodeFrame,
+        to_backend: impl FnOnce(EncodeFrame) -> Result<Frame, BackendError>,
+    ) -> Result<(), BackendError> {
+        if let Some(stamper) = self.master_clock.as_mut() {
+            frame.pts_90k = Some(stamper.stamp(self.config.fps));
+        }
+        let dims = frame.dims;
+        let pts_90k = frame.pts_90k;
+        if let Some(resized) = self.resized_dims
+            && resized != dims
+        {
+            return Err(BackendError::InvalidInput(format!(
+                "frame is {}x{} but the session was resized to {}x{}",
+                dims.width, dims.height, resized.width, resized.height
+            )));
+        }
+        if let Some(state) = self.rate_control.as_mut() {
+            let (next, keyframe) = state.poll(&self.config);
+            if let Some(next) = next {
+                self.apply_config(next)?;
+            }
+            frame.force_keyframe |= keyframe;
+        }
+        cbr_filler::check_config(&self.config)?;
+        if let Some(interval) = self.config.keyframe_interval {
+            frame.force_keyframe |= self
+                .keyframe_phase
+                .is_multiple_of(u64::from(interval.get()));
+        }
+        let now = self.clock.now();
+        if self
+            .memory_pressure
+            .sample(now, || self.encoder_inner.device_memory())
+        {
+            self.trim_pools();
+        }
+        self.tracer.submitted(pts_90k, now);
+        self.utilization.begin(now);
+        let pushed = pre_encode::apply_pre_encode_hooks(&mut self.pre_encode_hooks, &mut frame)
+            .inspect(|()| {
+                self.tracer
+                    .stamp(pts_90k, TraceStage::Transformed, self.clock.now());
+            })
+            .and_then(|()| to_backend(frame))
+            .and_then(|legacy| {
+                let _permit = self.acquire_gpu_budget();
+                self.tracer
+                    .stamp(pts_90k, TraceStage::HardwareIn, self.clock.now());
+                let mut packets = self.push_to_backend(legacy)?;
+                if self.config.latency_tune == LatencyTune::ZeroLatency {
+                    packets.extend(self.flush_backend()?);
+                }
+                Ok(packets)
+            })
+            .inspect_err(|_| self.tracer.discard(pts_90k));
+        // A failed submit produces no output later, so it must not stay in flight.
+        let completed = pushed.as_ref().map_or(1, Vec::len);
+        self.utilization.end(completed, self.clock.now());
+        if pushed
+            .as_ref()
+            .is_err_and(|err| self.memory_pressure.observe_error(err))
+        {
+            self.trim_pools();
+        }
+        let outputs = pushed?;
+        self.reorder.observe_submit(pts_90k);
+        if let Some(state) = self.rate_control.as_mut() {
+            state.observe_frame();
+        }
+        self.keyframe_phase += 1;
+        self.summary.frames_in += 1;
+        self.summary.dims = Some(dims);
+        let outputs = self.finish_chunks(outputs)?;
+        self.deliver(outputs)
+    }
+
+    fn finish_chunks(
+        &mut self,
+        packets: Vec<EncodedPacket>,
+    ) -> Result<Vec<EncodedChunk>, BackendError> {
+        packets
+            .into_iter()
+            .map(|packet| {
+                let mut chunk = legacy_packet_to_encoded_chunk(self.backend_kind, packet);
+                self.tracer
+                    .stamp(chunk.pts_90k, TraceStage::HardwareOut, self.clock.now());
+                let reorder_frames = self
+                    .encoder_inner
+                    .induced_latency()
+                    .map_or(0, |latency| latency.reorder_frames);
+                self.reorder
+                    .annotate(&mut chunk, reorder_frames, self.config.fps);
+                if let Some(padder) = self.cbr_padder.as_mut() {
+                    chunk = padder.pad(chunk)?;
+                }
+                let chunk =
+                    encoded_sink::apply_chunk_transforms(&mut self.chunk_transforms, chunk)?;
+                self.record_chunk(&chunk);
+                Ok(chunk)
+            })
+            .collect()
+    }
+
+    pub fn add_chunk_transform(&mut self, transform: impl ChunkTransform + 'static) {
+        self.chunk_transforms.push(Box::new(transform));
+    }
+
+    pub fn clear_chunk_transforms(&mut self) {
+        self.chunk_transforms.clear();
+    }
+
+    // Hooks run in the order they were added; see PreEncodeHook.
+    pub fn add_pre_encode_hook(&mut self, hook: impl PreEncodeHook + 'static) {
+        self.pre_encode_hooks.push(Box::new(hook));
+    }
+
+    pub fn clear_pre_encode_hooks(&mut self) {
+        self.pre_encode_hooks.clear();
+    }
+
+    // Hands rate decisions to `controller`, which runs before every `every_frames`th frame
+    // with the statistics since its last run; see RateController. Replaces any previous one.
+    pub fn set_rate_controller(
+        &mut self,
+        controller: impl RateController + 'static,
+        every_frames: std::num::NonZeroU32,
+    ) {
+        self.rate_control = Some(rate_control::RateControlState::new(
+            Box::new(controller),
+            every_frames,
+        ));
+    }
+
+    pub fn clear_rate_controller(&mut self) {
+        self.rate_control = None;
+    }
+
+    // Watches device memory for long-running servers, reporting DiagnosticEvent::MemoryPressure
+    // to this session's diagnostics sink; see MemoryPressureOptions. None turns it off (the
+    // default).
+    pub fn set_memory_pressure_monitoring(&mut self, options: Option<MemoryPressureOptions>) {
+        self.memory_pressure.set_options(options);
+    }
+
+    pub fn memory_pressure_monitoring(&self) -> Option<MemoryPressureOptions> {
+        self.memory_pressure.options()
+    }
+
+    pub fn memory_pressure(&self) -> MemoryPressureLevel {
+        self.memory_pressure.level()
+    }
+
+    // Re-decodes a window of this session's output every so often and reports mismatches as
+    // DiagnosticEvent::IntegrityMismatch; see IntegrityCheckOptions. None turns it off (the
+    // default). New options close the current window and start the stats over.
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    pub fn set_integrity_check(&mut self, options: Option<IntegrityCheckOptions>) {
+        if let Some(checker) = self.integrity.as_mut() {
+            checker.close_window();
+        }
+        self.integrity = options.map(|options| {
+            integrity_check::IntegrityChecker::new(
+                options,
+                self.backend_kind,
+                &self.config,
+                self.diagnostics.clone(),
+            )
+        });
+    }
+
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    pub fn integrity_check(&self) -> Option<IntegrityCheckOptions> {
+        self.integrity
+            .as_ref()
+            .map(integrity_check::IntegrityChecker::options)
+    }
+
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    pub fn integrity_check_stats(&self) -> Option<IntegrityCheckStats> {
+        self.integrity
+            .as_ref()
+            .map(integrity_check::IntegrityChecker::stats)
+    }
+
+    fn trim_pools(&mut self) {
+        self.encoder_inner.trim_pools();
+        self.ready.shrink_to_fit();
+    }
+
+    // While a sink is attached, chunks bypass the ready queue: try_reap/flush return nothing
+    // new and the sink sees every chunk in output order. Chunks still queued are forwarded on
+    // attach.
+    pub fn attach_sink(&mut self, sink: impl EncodedSink + 'static) -> Result<(), BackendError> {
+        self.sink = Some(Box::new(sink));
+        let queued = std::mem::take(&mut self.ready);
+        self.deliver(queued.into())
+    }
+
+    pub fn detach_sink(&mut self) -> Option<Box<dyn EncodedSink>> {
+        self.sink.take()
+    }
+
+    fn deliver(&mut self, chunks: Vec<EncodedChunk>) -> Result<(), BackendError> {
+        match self.sink.as_mut() {
+            Some(sink) => {
+                chunks
+                    .iter()
+                    .try_for_each(|chunk| sink.write_chunk(chunk))?;
+                self.mark_reaped(&chunks);
+                Ok(())
+            }
+            None => {
+                self.ready.extend(chunks);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn try_reap(&mut self) -> Result<Option<EncodedChunk>, BackendError> {
+        Ok(self.pop_ready())
+    }
+
+    // Blocks until a chunk is queued, for at most `timeout`. Encoders hand their output back
+    // from submit and flush, so with nothing queued this sleeps out `timeout` unless a
+    // ReapCanceller wakes it; either way it returns Ok(None).
+    pub fn reap_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<EncodedChunk>, BackendError> {
+        let deadline = Instant::now().checked_add(timeout);
+        loop {
+            if let Some(chunk) = self.pop_ready() {
+                return Ok(Some(chunk));
+            }
+            if !self.reap_waiter.wait(deadline) {
+                return Ok(None);
+            }
+        }
+    }
+
+    pub fn reap_canceller(&self) -> ReapCanceller {
+        self.reap_waiter.canceller()
+    }
+
+    fn pop_ready(&mut self) -> Option<EncodedChunk> {
+        let chunk = self.ready.pop_front();
+        self.mark_reaped(chunk.iter());
+        chunk
+    }
+
+    // Per-frame stage timestamps for frames whose chunks are reaped (or written to the sink)
+    // from now on, collected with take_frame_traces; see TraceStage. Off by default.
+    pub fn set_frame_tracing(&mut self, enabled: bool) {
+        self.tracer.set_enabled(enabled);
+    }
+
+    // Traces of chunks reaped since the last call, in reap order; match them to the chunks by
+    // pts.
+    pub fn take_frame_traces(&mut self) -> Vec<FrameTrace> {
+        self.tracer.take_completed()
+    }
+
+    // Shares one GPU's hardware slots with the other sessions registered on `budget`; see
+    // GpuBudget. Every backend call waits for a slot at `priority`.
+    pub fn set_gpu_budget(&mut self, budget: &GpuBudget, priority: EncodePriority) {
+        self.gpu_budget = Some((budget.clone(), priority));
+    }
+
+    pub fn clear_gpu_budget(&mut self) {
+        self.gpu_budget = None;
+    }
+
+    // Takes a slot for this session in a registry shared with other processes (see
+    // HostSessionArbiter), held until the session is dropped or the arbiter cleared. Fails
+    // with SessionLimitReached, naming the processes that hold the slots, when the GPU is
+    // already at the arbiter's limit; call it before the first submit so no hardware session
+    // is opened past the limit. A slot already held is only given up once the new claim
+    // succeeds, so a failed call leaves the session registered as before.
+    pub fn set_host_session_arbiter(
+        &mut self,
+        arbiter: &HostSessionArbiter,
+    ) -> Result<(), BackendError> {
+        let claim = arbiter.claim(host_sessions::SESSION_GPU, HostSessionKind::Encode)?;
+        self.host_claim = Some(claim);
+        Ok(())
+    }
+
+    pub fn clear_host_session_arbiter(&mut self) {
+        self.host_claim = None;
+    }
+
+    fn acquire_gpu_budget(&self) -> Option<GpuBudgetPermit> {
+        self.gpu_budget
+            .as_ref()
+            .map(|(budget, priority)| budget.acquire(*priority))
+    }
+
+    fn mark_reaped<'a>(&mut self, chunks: impl IntoIterator<Item = &'a EncodedChunk>) {
+        let now = self.clock.now();
+        for chunk in chunks {
+            self.tracer.reaped(chunk.pts_90k, now);
+        }
+    }
+
+    // Queued chunks up to the first one presented at or after `pts_90k`. Chunks come out in
+    // decode order, so this stops there rather than skipping ahead and breaking the stream.
+    pub fn drain_until(&mut self, pts_90k: Timestamp90k) -> Vec<EncodedChunk> {
+        let drained = take_before(&mut self.ready, pts_90k, |chunk| chunk.pts_90k);
+        self.mark_reaped(&drained);
+        drained
+    }
+
+    pub fn flush(&mut self) -> Result<Vec<EncodedChunk>, BackendError> {
+        let mut out = std::mem::take(&mut self.ready)
+            .into_iter()
+            .collect::<Vec<_>>();
+        let permit = self.acquire_gpu_budget();
+        self.utilization.begin(self.clock.now());
+        let flushed = self.flush_backend();
+        self.utilization.idle(self.clock.now());
+        drop(permit);
+        let flushed = flushed?;
+        let flushed = self.finish_chunks(flushed)?;
+        // Everything submitted is out, so the open window can be decoded to the end.
+        #[cfg(any(
+            all(target_os = "macos", feature = "backend-vt"),
+            all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            )
+        ))]
+        if let Some(checker) = self.integrity.as_mut() {
+            checker.close_window();
+        }
+        match self.sink.as_mut() {
+            Some(sink) => {
+                flushed
+                    .iter()
+                    .try_for_each(|chunk| sink.write_chunk(chunk))?;
+                sink.flush()?;
+                self.mark_reaped(&flushed);
+            }
+            None => out.extend(flushed),
+        }
+        self.mark_reaped(&out);
+        Ok(out)
+    }
+
+    // Flushes, then releases pooled buffers, the encoder session and finally the device
+    // context. Resources are released even when the flush fails; the first error wins.
+    pub fn close(mut self) -> Result<Vec<EncodedChunk>, BackendError> {
+        let drained = self.flush();
+        let closed = self.encoder_inner.close();
+        let drained = drained?;
+        closed?;
+        Ok(drained)
+    }
+
+    // Indexes every chunk emitted from now on, including those handed to a sink. Offsets are
+    // relative to the first indexed chunk, so enable it before the first submit to index a
+    // whole file.
+    pub fn enable_chunk_index(&mut self, keyframes_only: bool) {
+        self.chunk_index = Some(ChunkIndex::new(keyframes_only));
+    }
+
+    pub fn chunk_index(&self) -> Option<&ChunkIndex> {
+        self.chunk_index.as_ref()
+    }
+
+    // close() that also hands back the index covering the flushed tail.
+    pub fn finish(mut self) -> Result<(Vec<EncodedChunk>, Option<ChunkIndex>), BackendError> {
+        let drained = self.flush();
+        let index = self.chunk_index.take();
+        let closed = self.encoder_inner.close();
+        let drained = drained?;
+        closed?;
+        Ok((drained, index))
+    }
+
+    fn record_chunk(&mut self, chunk: &EncodedChunk) {
+        self.summary.record_chunk(chunk);
+        if let Some(state) = self.rate_control.as_mut() {
+            state.observe_chunk(chunk);
+        }
+        if let Some(index) = self.chunk_index.as_mut() {
+            index.record(chunk);
+        }
+        #[cfg(any(
+            all(target_os = "macos", feature = "backend-vt"),
+            all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            )
+        ))]
+        if let Some(checker) = self.integrity.as_mut() {
+            checker.check(chunk);
+        }
+    }
+
+    pub fn query_capability(&self, codec: Codec) -> Result<CapabilityReport, BackendError> {
+        self.encoder_inner.query_capability(codec)
+    }
+
+    pub fn config(&self) -> &EncoderConfig {
+        &self.config
+    }
+
+    pub fn diff_config(&self, config: &EncoderConfig) -> ConfigDiff {
+        config_diff::plan_config_change(self.encoder_inner.switch_target(), &self.config, config).0
+    }
+
+    // Moves the session to `config` the cheapest way the backend allows: an NVENC reconfigure,
+    // a VideoToolbox property update, or draining the encoder and rebuilding it, in which case
+    // the new session opens with an IDR. Drained chunks are queued or written to the sink as
+    // usual. Returns which fields changed and the path that was taken.
+    pub fn apply_config(&mut self, config: EncoderConfig) -> Result<ConfigDiff, BackendError> {
+        let (diff, request) = config_diff::plan_config_change(
+            self.encoder_inner.switch_target(),
+            &self.config,
+            &config,
+        );
+        match (diff.path, request) {
+            (ConfigApplyPath::Rebuild, _) => self.rebuild_encoder(config.clone())?,
+            (_, Some(request)) => self.encoder_inner.request_session_switch(request)?,
+            (_, None) => {}
+        }
+        self.repeat_mode = config.repeat_mode;
+        if diff.changed.contains(&"cbr_filler")
+            || diff.changed.contains(&"target_bitrate_bps")
+            || diff.path == ConfigApplyPath::Rebuild
+        {
+            self.cbr_padder = cbr_filler::CbrPadder::for_config(&config);
+        }
+        self.summary.fps = config.fps;
+        self.config = config;
+        Ok(diff)
+    }
+
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    fn rebuild_encoder(&mut self, config: EncoderConfig) -> Result<(), BackendError> {
+        let drained = self.flush()?;
+        self.ready.extend(drained);
+        self.encoder_inner.close()?;
+        let rebuilt =
+            Self::with_diagnostics(self.requested_backend, config, self.diagnostics.clone());
+        self.backend_kind = rebuilt.backend_kind;
+        self.encoder_inner = rebuilt.encoder_inner;
+        self.fallback = rebuilt.fallback;
+        if let Some(checker) = self.integrity.as_mut() {
+            checker.restart(self.backend_kind, &rebuilt.config);
+        }
+        // The new session opens with an IDR, so the cadence restarts from it.
+        self.keyframe_phase = 0;
+        self.reorder.restart();
+        Ok(())
+    }
+
+    // Without a backend BackendKind is uninhabited, so no session exists to rebuild.
+    #[cfg(not(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    )))]
+    fn rebuild_encoder(&mut self, _config: EncoderConfig) -> Result<(), BackendError> {
+        match self.backend_kind {}
+    }
+
+    // A Resize first drains the frames already submitted so they are encoded at the old size.
+    // Backends that cannot resize the live session are rebuilt from the current config, which
+    // also starts over with an IDR at the new size.
+    pub fn request_session_switch(
+        &mut self,
+        request: SessionSwitchRequest,
+    ) -> Result<(), BackendError> {
+        let SessionSwitchRequest::Resize { dims } = request else {
+            return self.encoder_inner.request_session_switch(request);
+        };
+        let drained = self.flush()?;
+        self.ready.extend(drained);
+        match self.encoder_inner.request_session_switch(request) {
+            Err(BackendError::UnsupportedConfig(_)) => self.rebuild_encoder(self.config.clone())?,
+            resized => resized?,
+        }
+        self.resized_dims = Some(dims);
+        self.keyframe_phase = 0;
+        Ok(())
+    }
+
+    pub fn invalidate_reference(&mut self, pts_90k: Timestamp90k) -> Result<(), BackendError> {
+        self.encoder_inner.invalidate_reference(pts_90k.0)
+    }
+
+    // Counterpart of DecodeSession::summary: totals since the session was created, counting
+    // chunks when the backend emits them (whether reaped or handed to a sink).
+    pub fn summary(&self) -> EncodeSummary {
+        self.summary
+    }
+
+    pub fn session_info(&self) -> EncodeSessionInfo {
+        EncodeSessionInfo {
+            backend: self.backend_kind,
+            buffer_lifetime_mode: self.encoder_inner.buffer_lifetime_mode(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self.utilization = utilization::UtilizationTracker::new(self.clock.now());
+        self
+    }
+
+    // Stamps every submitted frame (repeats included) from `clock` at the session frame rate,
+    // ignoring EncodeFrame::pts_90k, so chunk pts and dts follow the house clock; see
+    // MasterClockOptions for how drift is corrected.
+    pub fn set_master_clock(
+        &mut self,
+        clock: impl MasterClock + 'static,
+        options: MasterClockOptions,
+    ) {
+        self.master_clock = Some(master_clock::MasterClockStamper::new(
+            Arc::new(clock),
+            options,
+        ));
+    }
+
+    // Back to the pts_90k of each frame.
+    pub fn clear_master_clock(&mut self) {
+        self.master_clock = None;
+    }
+
+    pub fn master_clock_stats(&self) -> Option<MasterClockStats> {
+        self.master_clock
+            .as_ref()
+            .map(master_clock::MasterClockStamper::stats)
+    }
+
+    // Encode engine busy share over the last second: from each submit until its chunk comes
+    // back, so a pipelined NVENC session reads close to 1.0 once it is saturated.
+    pub fn utilization(&self) -> EngineUtilization {
+        self.utilization.snapshot(self.clock.now())
+    }
+
+    #[cfg(all(
+        feature = "nvml",
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    ))]
+    pub fn gpu_monitor(&self) -> Result<GpuMonitor, BackendError> {
+        if self.backend_kind != BackendKind::Nvidia {
+            return Err(BackendError::UnsupportedConfig(
+                "GPU health monitoring requires an NVIDIA session".to_string(),
+            ));
+        }
+        GpuMonitor::open(NVIDIA_SESSION_DEVICE_INDEX)
+    }
+
+    // Known once the backend session exists, i.e. after the first submitted frame.
+    pub fn induced_latency(&self) -> Option<EncodeLatency> {
+        self.encoder_inner.induced_latency()
+    }
+
+    // Buffers the backend allocated with the current session, known after the first submitted
+    // frame (NVENC with the reusable pool only).
+    pub fn surface_pool(&self) -> Option<SurfacePoolSizes> {
+        self.encoder_inner.surface_pool()
+    }
+
+    pub fn encode_iter<I>(
+        &mut self,
+        frames: I,
+    ) -> impl Iterator<Item = Result<EncodedChunk, BackendError>>
+    where
+        I: IntoIterator<Item = EncodeFrame>,
+    {
+        let depth = match self.config.latency_tune {
+            LatencyTune::Throughput => self.encoder_inner.pipeline_depth().max(1),
+            LatencyTune::ZeroLatency => 1,
+        };
+        EncodeIter {
+            session: self,
+            frames: frames.into_iter(),
+            submitted: 0,
+            depth,
+            done: false,
         }
     }
 
-    pub fn submit(&mut self, frame: EncodeFrame) -> Result<(), BackendError> {
-        let legacy = encode_frame_to_legacy(frame)?;
-        let outputs = self
-            .encoder_inner
-            .push_frame(legacy)?
-            .into_iter()
-            .map(|packet| legacy_packet_to_encoded_chunk(self.backend_kind, packet))
-            .collect::<Vec<_>>();
-        self.ready.extend(outputs);
-        Ok(())
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    fn push_to_backend(&mut self, frame: Frame) -> Result<Vec<EncodedPacket>, BackendError> {
+        if let Some(fallback) = self.fallback.as_mut() {
+            fallback.record(&frame);
+        }
+        if let Some(checker) = self.integrity.as_mut()
+            && let Some(dims) = dimensions_from_legacy(frame.width, frame.height)
+        {
+            checker.expect(frame.pts_90k.map(Timestamp90k), dims);
+        }
+        let packets = match self.encoder_inner.push_frame(frame) {
+            Ok(packets) => packets,
+            Err(err) => self.recover(err)?,
+        };
+        if !packets.is_empty() {
+            self.fallback = None;
+        }
+        Ok(packets)
     }
 
-    pub fn try_reap(&mut self) -> Result<Option<EncodedChunk>, BackendError> {
-        Ok(self.ready.pop_front())
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    fn flush_backend(&mut self) -> Result<Vec<EncodedPacket>, BackendError> {
+        let mut packets = Vec::new();
+        loop {
+            match self.encoder_inner.flush() {
+                Ok(flushed) => {
+                    packets.extend(flushed);
+                    self.fallback = None;
+                    return Ok(packets);
+                }
+                Err(err) => packets.extend(self.recover(err)?),
+            }
+        }
     }
 
-    pub fn reap_timeout(
-        &mut self,
-        _timeout: Duration,
-    ) -> Result<Option<EncodedChunk>, BackendError> {
-        self.try_reap()
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    fn recover(&mut self, err: BackendError) -> Result<Vec<EncodedPacket>, BackendError> {
+        let Some(fallback) = self.fallback.as_mut() else {
+            return Err(err);
+        };
+        let packets = fallback.recover(&mut self.encoder_inner, err)?;
+        self.backend_kind = fallback.backend_kind();
+        Ok(packets)
     }
 
-    pub fn flush(&mut self) -> Result<Vec<EncodedChunk>, BackendError> {
-        let mut out = std::mem::take(&mut self.ready)
-            .into_iter()
-            .collect::<Vec<_>>();
-        out.extend(
-            self.encoder_inner
-                .flush()?
-                .into_iter()
-                .map(|packet| legacy_packet_to_encoded_chunk(self.backend_kind, packet))
-                .collect::<Vec<_>>(),
-        );
-        Ok(out)
+    #[cfg(not(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    )))]
+    fn push_to_backend(&mut self, frame: Frame) -> Result<Vec<EncodedPacket>, BackendError> {
+        self.encoder_inner.push_frame(frame)
     }
 
-    pub fn query_capability(&self, codec: Codec) -> Result<CapabilityReport, BackendError> {
-        self.encoder_inner.query_capability(codec)
+    #[cfg(not(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    )))]
+    fn flush_backend(&mut self) -> Result<Vec<EncodedPacket>, BackendError> {
+        self.encoder_inner.flush()
+    }
+}
+
+// Backends only hand packets back on flush, so the adapter flushes every `depth` frames: deep
+// enough to keep the backend's in-flight queue full, shallow enough to stream output.
+struct EncodeIter<'a, I> {
+    session: &'a mut EncodeSession,
+    frames: I,
+    submitted: usize,
+    depth: usize,
+    done: bool,
+}
+
+impl<I> EncodeIter<'_, I> {
+    fn flush_into_ready(&mut self) -> Result<(), BackendError> {
+        self.submitted = 0;
+        let chunks = self.session.flush()?;
+        self.session.ready.extend(chunks);
+        Ok(())
     }
+}
 
-    pub fn request_session_switch(
-        &mut self,
-        request: SessionSwitchRequest,
-    ) -> Result<(), BackendError> {
-        self.encoder_inner.request_session_switch(request)
+impl<I: Iterator<Item = EncodeFrame>> Iterator for EncodeIter<'_, I> {
+    type Item = Result<EncodedChunk, BackendError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(chunk) = self.session.pop_ready() {
+                return Some(Ok(chunk));
+            }
+            if self.done {
+                return None;
+            }
+            let step = match self.frames.next() {
+                Some(frame) => self.session.submit(frame).and_then(|()| {
+                    self.submitted += 1;
+                    if self.submitted >= self.depth {
+                        self.flush_into_ready()
+                    } else {
+                        Ok(())
+                    }
+                }),
+                None => {
+                    self.done = true;
+                    self.flush_into_ready()
+                }
+            };
+            if let Err(err) = step {
+                self.done = true;
+                return Some(Err(err));
+            }
+        }
     }
 }
 
@@ -784,6 +2847,10 @@ fn resolve_decoder_backend(
     }
     let mut diagnostics = Vec::new();
     for candidate in preferred_backend_order() {
+        if let Some(blocker) = preflight::preflight(candidate).decode_blocker {
+            diagnostics.push(format!("{candidate:?}: preflight: {blocker}"));
+            continue;
+        }
         let probe = build_decoder_inner(candidate, config.clone());
         match probe.query_capability(config.codec) {
             Ok(capability) => {
@@ -827,6 +2894,10 @@ fn resolve_encoder_backend(
     }
     let mut diagnostics = Vec::new();
     for candidate in preferred_backend_order() {
+        if let Some(blocker) = preflight::preflight(candidate).encode_blocker {
+            diagnostics.push(format!("{candidate:?}: preflight: {blocker}"));
+            continue;
+        }
         let probe = build_encoder_inner(candidate, config.clone());
         match probe.query_capability(config.codec) {
             Ok(capability) => {
@@ -902,10 +2973,30 @@ fn build_encoder_inner(kind: BackendKind, config: EncoderConfig) -> EncoderInner
         BackendKind::Auto => build_encoder_inner(BackendKind::os_default(), config),
         #[cfg(all(target_os = "macos", feature = "backend-vt"))]
         BackendKind::VideoToolbox => {
+            // The vendor-neutral settings win over the backend options they map to.
+            let (interval, bitrate, max_qp, latency_tune) = (
+                config.keyframe_interval,
+                config.target_bitrate_bps,
+                config.max_qp,
+                config.latency_tune,
+            );
+            let config = config.with_vt_options(|options| {
+                options.max_keyframe_interval = interval
+                    .map(std::num::NonZeroU32::get)
+                    .or(options.max_keyframe_interval);
+                options.average_bitrate_bps = bitrate.or(options.average_bitrate_bps);
+                options.max_qp = max_qp.or(options.max_qp);
+                if latency_tune == LatencyTune::ZeroLatency {
+                    options.real_time = Some(true);
+                    options.prioritize_speed = Some(true);
+                    options.allow_frame_reordering = Some(false);
+                }
+            });
             EncoderInner::VideoToolbox(vt_backend::VtEncoderAdapter::with_config(
                 config.codec,
                 config.fps,
                 config.require_hardware,
+                config.backend_options,
             ))
         }
         #[cfg(all(
@@ -913,6 +3004,25 @@ fn build_encoder_inner(kind: BackendKind, config: EncoderConfig) -> EncoderInner
             any(target_os = "linux", target_os = "windows")
         ))]
         BackendKind::Nvidia => {
+            let (interval, bitrate, max_qp, latency_tune) = (
+                config.keyframe_interval,
+                config.target_bitrate_bps,
+                config.max_qp,
+                config.latency_tune,
+            );
+            let config = config.with_nvidia_options(|options| {
+                options.gop_length = interval
+                    .map(std::num::NonZeroU32::get)
+                    .or(options.gop_length);
+                options.average_bitrate_bps = bitrate.or(options.average_bitrate_bps);
+                options.max_qp = max_qp.or(options.max_qp);
+                if latency_tune == LatencyTune::ZeroLatency {
+                    options.max_in_flight_outputs = 1;
+                    options.lookahead_depth = Some(0);
+                    options.frame_interval_p = Some(1);
+                    options.enable_pipeline_scheduler = Some(false);
+                }
+            });
             EncoderInner::Nvidia(Box::new(nv_backend::NvEncoderAdapter::with_config(
                 config.codec,
                 config.fps,
@@ -945,6 +3055,15 @@ fn pack_access_unit_nalus_to_annexb(nalus: &[Vec<u8>]) -> Vec<u8> {
 }
 
 fn unpack_length_prefixed_sample_to_annexb(sample: &[u8]) -> Result<Vec<u8>, BackendError> {
+    let mut out = Vec::new();
+    for nal in split_length_prefixed_nal_units(sample)? {
+        out.extend_from_slice(&[0, 0, 0, 1]);
+        out.extend_from_slice(nal);
+    }
+    Ok(out)
+}
+
+fn split_length_prefixed_nal_units(sample: &[u8]) -> Result<Vec<&[u8]>, BackendError> {
     let mut out = Vec::new();
     let mut payload = sample;
     while payload.len() >= 4 {
@@ -955,8 +3074,7 @@ fn unpack_length_prefixed_sample_to_annexb(sample: &[u8]) -> Result<Vec<u8>, Bac
                 "invalid length-prefixed sample payload".to_string(),
             ));
         }
-        out.extend_from_slice(&[0, 0, 0, 1]);
-        out.extend_from_slice(&payload[..nal_len]);
+        out.push(&payload[..nal_len]);
         payload = &payload[nal_len..];
     }
     if !payload.is_empty() {
@@ -967,6 +3085,60 @@ fn unpack_length_prefixed_sample_to_annexb(sample: &[u8]) -> Result<Vec<u8>, Bac
     Ok(out)
 }
 
+fn split_annexb_nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let start_codes = find_start_codes(data);
+    let mut out = Vec::with_capacity(start_codes.len());
+    for (idx, (start, prefix_len)) in start_codes.iter().copied().enumerate() {
+        let end = start_codes
+            .get(idx + 1)
+            .map(|(next, _)| *next)
+            .unwrap_or(data.len());
+        // A NAL unit never ends in a zero byte, so trailing zeros are padding before the next
+        // start code.
+        let mut nal = &data[start + prefix_len..end];
+        while let [rest @ .., 0] = nal {
+            nal = rest;
+        }
+        if !nal.is_empty() {
+            out.push(nal);
+        }
+    }
+    out
+}
+
+pub(crate) fn find_start_codes(data: &[u8]) -> Vec<(usize, usize)> {
+    let mut out = Vec::new();
+    let mut i = 0usize;
+    while i + 3 <= data.len() {
+        if i + 4 <= data.len()
+            && data[i] == 0
+            && data[i + 1] == 0
+            && data[i + 2] == 0
+            && data[i + 3] == 1
+        {
+            out.push((i, 4));
+            i += 4;
+            continue;
+        }
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            out.push((i, 3));
+            i += 3;
+            continue;
+        }
+        i += 1;
+    }
+    out
+}
+
+pub(crate) fn nal_type(codec: Codec, nal: &[u8]) -> Option<u8> {
+    let header = *nal.first()?;
+    match codec {
+        Codec::H264 => Some(header & 0x1f),
+        Codec::Hevc => Some((header >> 1) & 0x3f),
+        Codec::Mjpeg => None,
+    }
+}
+
 fn legacy_to_decoded_frame(frame: Frame) -> DecodedFrame {
     let dims = dimensions_from_legacy(frame.width, frame.height);
     let color = if frame.color_primaries.is_some()
@@ -987,6 +3159,24 @@ fn legacy_to_decoded_frame(frame: Frame) -> DecodedFrame {
         pixel_format: frame.pixel_format,
         decode_info_flags: frame.decode_info_flags,
         color,
+        planes: frame.planes,
+        #[cfg(any(
+            all(target_os = "macos", feature = "backend-vt"),
+            all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            )
+        ))]
+        histogram: frame.luma_histogram,
+        #[cfg(not(any(
+            all(target_os = "macos", feature = "backend-vt"),
+            all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            )
+        )))]
+        histogram: None,
+        view: frame.view,
     }
 }
 
@@ -996,9 +3186,23 @@ fn encode_frame_to_legacy(frame: EncodeFrame) -> Result<Frame, BackendError> {
         pts_90k,
         buffer,
         force_keyframe,
+        dirty_rects,
+        repeat_count: _,
     } = frame;
+    buffer.validate(dims)?;
     let width = dims.width.get() as usize;
     let height = dims.height.get() as usize;
+    for rect in dirty_rects.iter().flatten() {
+        if rect.width == 0
+            || rect.height == 0
+            || rect.x.saturating_add(rect.width) > dims.width.get()
+            || rect.y.saturating_add(rect.height) > dims.height.get()
+        {
+            return Err(BackendError::InvalidInput(format!(
+                "dirty rect {rect:?} is empty or outside frame {dims}"
+            )));
+        }
+    }
     #[cfg(any(
         all(target_os = "macos", feature = "backend-vt"),
         all(
@@ -1007,15 +3211,15 @@ fn encode_frame_to_legacy(frame: EncodeFrame) -> Result<Frame, BackendError> {
         )
     ))]
     let argb = match buffer {
-        RawFrameBuffer::Argb8888(data) => Some(data),
-        RawFrameBuffer::Argb8888Shared(data) => Some(data.to_vec()),
-        RawFrameBuffer::Nv12 { .. } => {
+        RawFrameBuffer::Argb8888(data) => Some(FramePixels::Owned(data)),
+        RawFrameBuffer::Argb8888Shared(data) => Some(FramePixels::Shared(data)),
+        RawFrameBuffer::Nv12 { .. } | RawFrameBuffer::Nv12Shared { .. } => {
             return Err(BackendError::InvalidInput(
                 "RawFrameBuffer::Nv12 is not supported by Encoder::push_encode_frame yet"
                     .to_string(),
             ));
         }
-        RawFrameBuffer::Rgb24(_) => {
+        RawFrameBuffer::Rgb24(_) | RawFrameBuffer::Rgb24Shared(_) => {
             return Err(BackendError::InvalidInput(
                 "RawFrameBuffer::Rgb24 is not supported by Encoder::push_encode_frame yet"
                     .to_string(),
@@ -1030,13 +3234,13 @@ fn encode_frame_to_legacy(frame: EncodeFrame) -> Result<Frame, BackendError> {
         )
     )))]
     match buffer {
-        RawFrameBuffer::Nv12 { .. } => {
+        RawFrameBuffer::Nv12 { .. } | RawFrameBuffer::Nv12Shared { .. } => {
             return Err(BackendError::InvalidInput(
                 "RawFrameBuffer::Nv12 is not supported by Encoder::push_encode_frame yet"
                     .to_string(),
             ));
         }
-        RawFrameBuffer::Rgb24(_) => {
+        RawFrameBuffer::Rgb24(_) | RawFrameBuffer::Rgb24Shared(_) => {
             return Err(BackendError::InvalidInput(
                 "RawFrameBuffer::Rgb24 is not supported by Encoder::push_encode_frame yet"
                     .to_string(),
@@ -1044,6 +3248,20 @@ fn encode_frame_to_legacy(frame: EncodeFrame) -> Result<Frame, BackendError> {
         }
         RawFrameBuffer::Argb8888(_) | RawFrameBuffer::Argb8888Shared(_) => {}
     }
+    #[cfg(any(
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    let (argb, width, height) = match argb {
+        Some(data) if width % 2 == 1 || height % 2 == 1 => {
+            let (data, width, height) = pad_argb_to_even(&data, width, height)?;
+            (Some(FramePixels::Owned(data)), width, height)
+        }
+        argb => (argb, width, height),
+    };
     #[cfg(not(any(
         all(target_os = "macos", feature = "backend-vt"),
         all(
@@ -1051,7 +3269,7 @@ fn encode_frame_to_legacy(frame: EncodeFrame) -> Result<Frame, BackendError> {
             any(target_os = "linux", target_os = "windows")
         )
     )))]
-    let _ = force_keyframe;
+    let _ = (force_keyframe, dirty_rects);
     Ok(Frame {
         width,
         height,
@@ -1061,6 +3279,8 @@ fn encode_frame_to_legacy(frame: EncodeFrame) -> Result<Frame, BackendError> {
         color_primaries: None,
         transfer_function: None,
         ycbcr_matrix: None,
+        planes: None,
+        view: None,
         #[cfg(any(
             all(target_os = "macos", feature = "backend-vt"),
             all(
@@ -1077,9 +3297,74 @@ fn encode_frame_to_legacy(frame: EncodeFrame) -> Result<Frame, BackendError> {
             )
         ))]
         force_keyframe,
+        #[cfg(any(
+            all(target_os = "macos", feature = "backend-vt"),
+            all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            )
+        ))]
+        dirty_rects,
+        #[cfg(any(
+            all(target_os = "macos", feature = "backend-vt"),
+            all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            )
+        ))]
+        luma_histogram: None,
+        #[cfg(any(
+            all(target_os = "macos", feature = "backend-vt"),
+            all(
+                feature = "backend-nvidia",
+                any(target_os = "linux", target_os = "windows")
+            )
+        ))]
+        stereo_right: None,
     })
 }
 
+// 4:2:0 encoders need even dimensions and the SPS cropping window can only trim whole chroma
+// samples, so odd sizes are rounded up by repeating the last column/row instead of leaving
+// uninitialized edges. Macroblock/CTU alignment is left to the encoder, which signals it as
+// SPS cropping.
+#[cfg(any(
+    test,
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+fn pad_argb_to_even(
+    argb: &[u8],
+    width: usize,
+    height: usize,
+) -> Result<(Vec<u8>, usize, usize), BackendError> {
+    let row_bytes = width * 4;
+    if argb.len() != row_bytes * height {
+        return Err(BackendError::InvalidInput(format!(
+            "ARGB buffer has {} bytes, expected {} for {width}x{height}",
+            argb.len(),
+            row_bytes * height
+        )));
+    }
+    let padded_width = width.next_multiple_of(2);
+    let padded_height = height.next_multiple_of(2);
+    let mut out = Vec::with_capacity(padded_width * padded_height * 4);
+    for row in argb.chunks_exact(row_bytes) {
+        out.extend_from_slice(row);
+        if padded_width > width {
+            out.extend_from_slice(&row[row_bytes - 4..]);
+        }
+    }
+    if padded_height > height {
+        let last_row = out.len() - padded_width * 4;
+        out.extend_from_within(last_row..);
+    }
+    Ok((out, padded_width, padded_height))
+}
+
 #[cfg(any(
     all(target_os = "macos", feature = "backend-vt"),
     all(
@@ -1089,6 +3374,9 @@ fn encode_frame_to_legacy(frame: EncodeFrame) -> Result<Frame, BackendError> {
 ))]
 fn legacy_packet_to_encoded_chunk(kind: BackendKind, packet: EncodedPacket) -> EncodedChunk {
     let layout = match (kind, packet.codec) {
+        // Encoders reject MJPEG before producing anything; JPEG images carry no NAL framing.
+        #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+        (_, Codec::Mjpeg) => EncodedLayout::AnnexB,
         #[cfg(all(target_os = "macos", feature = "backend-vt"))]
         (BackendKind::Auto, Codec::H264) => EncodedLayout::Avcc,
         #[cfg(all(target_os = "macos", feature = "backend-vt"))]
@@ -1108,12 +3396,19 @@ fn legacy_packet_to_encoded_chunk(kind: BackendKind, packet: EncodedPacket) -> E
         ))]
         (BackendKind::Auto, _) => EncodedLayout::AnnexB,
     };
+    // Tagged from the payload so VideoToolbox and NVENC agree on the same content; the
+    // backend's hint only covers output without a slice in it.
+    let is_keyframe = bitstream::detect_keyframe(packet.codec, layout, &packet.data)
+        .unwrap_or(packet.is_keyframe);
     EncodedChunk {
         codec: packet.codec,
         layout,
-        data: packet.data,
+        data: packet.data.into(),
         pts_90k: packet.pts_90k.map(Timestamp90k),
-        is_keyframe: packet.is_keyframe,
+        is_keyframe,
+        filler_bytes: 0,
+        dts_90k: None,
+        display_index: None,
     }
 }
 
@@ -1138,6 +3433,49 @@ fn dimensions_from_legacy(width: usize, height: usize) -> Option<Dimensions> {
     Some(Dimensions { width, height })
 }
 
+// The frame followed by `repeats` unchanged copies one frame interval apart. An owned buffer
+// is moved into a shared one first so the copies do not duplicate the pixels.
+fn repeat_frames(mut frame: EncodeFrame, repeats: u32, fps: FrameRate) -> Vec<EncodeFrame> {
+    frame.buffer = match frame.buffer {
+        RawFrameBuffer::Argb8888(data) => RawFrameBuffer::Argb8888Shared(data.into()),
+        RawFrameBuffer::Nv12 { pitch, data } => RawFrameBuffer::Nv12Shared {
+            pitch,
+            data: data.into(),
+        },
+        RawFrameBuffer::Rgb24(data) => RawFrameBuffer::Rgb24Shared(data.into()),
+        shared => shared,
+    };
+    frame.repeat_count = 0;
+    let repeat = EncodeFrame {
+        force_keyframe: false,
+        dirty_rects: Some(Vec::new()),
+        ..frame.clone()
+    };
+    let mut frames = vec![frame];
+    frames.extend((1..=i64::from(repeats)).map(|index| {
+        EncodeFrame {
+            pts_90k: repeat
+                .pts_90k
+                .map(|pts| Timestamp90k(pts.0.saturating_add(fps.pts_90k(index)))),
+            ..repeat.clone()
+        }
+    }));
+    frames
+}
+
+// Entries without a timestamp go out with the ones before them so they cannot hold the queue.
+fn take_before<T>(
+    queue: &mut VecDeque<T>,
+    pts_90k: Timestamp90k,
+    pts_of: impl Fn(&T) -> Option<Timestamp90k>,
+) -> Vec<T> {
+    let split = queue
+        .iter()
+        .position(|item| pts_of(item).is_some_and(|pts| pts.0 >= pts_90k.0))
+        .unwrap_or(queue.len());
+    queue.drain(..split).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1154,6 +3492,128 @@ mod tests {
         assert_eq!(BackendKind::default(), BackendKind::Auto);
     }
 
+    #[test]
+    fn encode_summary_counts_chunks_and_keyframes() {
+        let mut summary = EncodeSummary::new(FrameRate::NTSC_29_97);
+        for (len, is_keyframe) in [(1200, true), (300, false), (250, false)] {
+            summary.record_chunk(&EncodedChunk {
+                codec: Codec::H264,
+                layout: EncodedLayout::AnnexB,
+                data: vec![0; len].into(),
+                pts_90k: None,
+                is_keyframe,
+                filler_bytes: 0,
+                dts_90k: None,
+                display_index: None,
+            });
+        }
+        assert_eq!(
+            (summary.packets_out, summary.bytes_out, summary.keyframes),
+            (3, 1750, 1)
+        );
+        assert_eq!(
+            summary.to_string(),
+            "EncodeSummary(frames_in=0, packets_out=3, bytes_out=1750, keyframes=1, average_qp=None, dims=None, fps=30000/1001)"
+        );
+    }
+
+    #[test]
+    fn take_before_stops_at_first_chunk_at_or_after_pts() {
+        let chunk = |pts: Option<i64>| EncodedChunk {
+            codec: Codec::H264,
+            layout: EncodedLayout::AnnexB,
+            data: Arc::from([]),
+            pts_90k: pts.map(Timestamp90k),
+            is_keyframe: false,
+            filler_bytes: 0,
+            dts_90k: None,
+            display_index: None,
+        };
+        // Decode order with a B-frame: I0 P6000 B3000, then an untimed chunk and P9000.
+        let mut ready = [Some(0), Some(6000), Some(3000), None, Some(9000)]
+            .into_iter()
+            .map(chunk)
+            .collect::<VecDeque<_>>();
+        let pts = |chunks: &[EncodedChunk]| {
+            chunks
+                .iter()
+                .map(|chunk| chunk.pts_90k.map(|pts| pts.0))
+                .collect::<Vec<_>>()
+        };
+        let first = take_before(&mut ready, Timestamp90k(3000), |chunk| chunk.pts_90k);
+        assert_eq!(pts(&first), [Some(0)]);
+        let second = take_before(&mut ready, Timestamp90k(9000), |chunk| chunk.pts_90k);
+        assert_eq!(pts(&second), [Some(6000), Some(3000), None]);
+        assert_eq!(ready.len(), 1);
+    }
+
+    #[test]
+    fn repeated_frames_advance_pts_and_share_pixels() {
+        let dims = Dimensions {
+            width: std::num::NonZeroU32::new(2).unwrap(),
+            height: std::num::NonZeroU32::new(2).unwrap(),
+        };
+        let frames = repeat_frames(
+            EncodeFrame {
+                dims,
+                pts_90k: Some(Timestamp90k(9000)),
+                buffer: RawFrameBuffer::Argb8888(vec![7; 16]),
+                force_keyframe: true,
+                dirty_rects: None,
+                repeat_count: 2,
+            },
+            2,
+            FrameRate::NTSC_29_97,
+        );
+        let pts = frames
+            .iter()
+            .map(|frame| frame.pts_90k.map(|pts| pts.0))
+            .collect::<Vec<_>>();
+        assert_eq!(pts, [Some(9000), Some(12003), Some(15006)]);
+        assert!(frames[0].force_keyframe && !frames[1].force_keyframe);
+        assert_eq!(frames[2].dirty_rects.as_deref(), Some(&[][..]));
+        let (RawFrameBuffer::Argb8888Shared(first), RawFrameBuffer::Argb8888Shared(last)) =
+            (&frames[0].buffer, &frames[2].buffer)
+        else {
+            panic!("repeats should share the frame buffer");
+        };
+        assert!(Arc::ptr_eq(first, last));
+    }
+
+    #[test]
+    fn profile_expands_and_can_be_tweaked() {
+        let profile: Profile = "screen-share".parse().unwrap();
+        assert_eq!(profile, Profile::ScreenShare);
+        let options = profile.nvidia_options(FrameRate::NTSC_29_97);
+        assert_eq!(options.content_hint, Some(ContentHint::Screen));
+        assert_eq!(options.gop_length, Some(300));
+        let config = EncoderConfig::new(Codec::H264, 60, true)
+            .with_nvidia_options(|options| {
+                *options = Profile::LowLatencyStreaming.nvidia_options(60.into());
+            })
+            .with_nvidia_options(|options| options.gop_length = Some(30));
+        let BackendEncoderOptions::Nvidia(options) = config.backend_options else {
+            panic!("expected nvidia options");
+        };
+        assert_eq!(
+            (options.gop_length, options.frame_interval_p),
+            (Some(30), Some(1))
+        );
+        assert!("broadcast".parse::<Profile>().is_err());
+    }
+
+    #[test]
+    fn ntsc_frame_rate_pts_does_not_drift() {
+        let rate: FrameRate = "30000/1001".parse().unwrap();
+        assert_eq!(rate, FrameRate::NTSC_29_97);
+        assert_eq!(rate.to_string(), "30000/1001");
+        assert_eq!(rate.rounded(), 30);
+        // One hour of 29.97 fps lands exactly on 3003 ticks per frame.
+        assert_eq!(rate.pts_90k(107_892), 107_892 * 3003);
+        assert_eq!(FrameRate::from(0).pts_90k(2), 6000);
+        assert!("30/0".parse::<FrameRate>().is_err());
+    }
+
     #[test]
     fn unpack_length_prefixed_sample_to_annexb_converts_nals() {
         let sample = [
@@ -1170,6 +3630,89 @@ mod tests {
         );
     }
 
+    #[test]
+    fn encoded_chunk_nal_units_splits_annexb_and_length_prefixed() {
+        let annexb = EncodedChunk {
+            codec: Codec::H264,
+            layout: EncodedLayout::AnnexB,
+            data: vec![
+                0, 0, 0, 1, 0x67, 0x64, 0, //
+                0, 0, 1, 0x68, 0xEE, 0x3C, //
+                0, 0, 0, 1, 0x65, 0x88,
+            ]
+            .into(),
+            pts_90k: None,
+            is_keyframe: true,
+            filler_bytes: 0,
+            dts_90k: None,
+            display_index: None,
+        };
+        let nals = annexb.nal_units().unwrap();
+        assert_eq!(
+            nals.iter().map(|nal| nal.nal_type).collect::<Vec<_>>(),
+            vec![7, 8, 5]
+        );
+        assert_eq!(nals[0].data, &[0x67, 0x64]);
+        assert_eq!(nals[2].data, &[0x65, 0x88]);
+
+        let hvcc = EncodedChunk {
+            codec: Codec::Hevc,
+            layout: EncodedLayout::Hvcc,
+            data: vec![0, 0, 0, 3, 0x26, 0x01, 0xAF].into(),
+            pts_90k: None,
+            is_keyframe: true,
+            filler_bytes: 0,
+            dts_90k: None,
+            display_index: None,
+        };
+        let nals = hvcc.nal_units().unwrap();
+        assert_eq!(nals.len(), 1);
+        assert_eq!(nals[0].nal_type, 19);
+        assert_eq!(nals[0].data, &[0x26, 0x01, 0xAF]);
+    }
+
+    #[test]
+    fn encoded_chunk_converts_to_bitstream_input() {
+        let hvcc = EncodedChunk {
+            codec: Codec::Hevc,
+            layout: EncodedLayout::Hvcc,
+            data: vec![0, 0, 0, 3, 0x26, 0x01, 0xAF].into(),
+            pts_90k: Some(Timestamp90k(3000)),
+            is_keyframe: true,
+            filler_bytes: 0,
+            dts_90k: None,
+            display_index: None,
+        };
+        let BitstreamInput::LengthPrefixedSample {
+            codec,
+            sample,
+            pts_90k,
+        } = BitstreamInput::from(hvcc)
+        else {
+            panic!("hvcc chunk should become a length-prefixed sample");
+        };
+        assert_eq!((codec, pts_90k), (Codec::Hevc, Some(Timestamp90k(3000))));
+        assert_eq!(
+            unpack_length_prefixed_sample_to_annexb(&sample).unwrap(),
+            [0, 0, 0, 1, 0x26, 0x01, 0xAF]
+        );
+
+        let annexb = EncodedChunk {
+            codec: Codec::H264,
+            layout: EncodedLayout::AnnexB,
+            data: vec![0, 0, 0, 1, 0x65, 0x88].into(),
+            pts_90k: None,
+            is_keyframe: true,
+            filler_bytes: 0,
+            dts_90k: None,
+            display_index: None,
+        };
+        assert!(matches!(
+            annexb.into(),
+            BitstreamInput::AnnexBChunk { chunk, pts_90k: None } if chunk == [0, 0, 0, 1, 0x65, 0x88]
+        ));
+    }
+
     #[test]
     fn encoded_layout_is_inferred_from_backend_and_codec() {
         #[cfg(all(target_os = "macos", feature = "backend-vt"))]
@@ -1226,6 +3769,75 @@ mod tests {
             pts_90k: Some(Timestamp90k(0)),
             buffer: RawFrameBuffer::Rgb24(vec![0; 640 * 360 * 3]),
             force_keyframe: false,
+            dirty_rects: None,
+            repeat_count: 0,
+        });
+        assert!(matches!(result, Err(BackendError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn raw_frame_buffer_validate_explains_layout_mistakes() {
+        let dims = |width, height| Dimensions {
+            width: std::num::NonZeroU32::new(width).unwrap(),
+            height: std::num::NonZeroU32::new(height).unwrap(),
+        };
+        let nv12 = RawFrameBuffer::Nv12 {
+            pitch: 64,
+            data: vec![0; 64 * 48],
+        };
+        assert_eq!(nv12.expected_len(dims(60, 32)), 64 * 48);
+        assert!(nv12.validate(dims(60, 32)).is_ok());
+        let error = |buffer: &RawFrameBuffer, dims| match buffer.validate(dims) {
+            Err(BackendError::InvalidInput(message)) => message,
+            other => panic!("expected InvalidInput, got {other:?}"),
+        };
+        assert_eq!(
+            error(&nv12, dims(64, 30)),
+            "NV12 buffer for 64x30 has 3072 bytes, expected 2880 \
+             (Y: 30 rows of 64 bytes at offset 0, UV: 15 rows of 64 bytes at offset 1920)"
+        );
+        assert!(error(&nv12, dims(80, 32)).contains("pitch 64 is smaller"));
+
+        assert!(error(&nv12, dims(32, 64)).ends_with("are width and height swapped?"));
+        let padded = RawFrameBuffer::Argb8888(vec![0; 64 * 4 * 30]);
+        assert!(error(&padded, dims(60, 30)).ends_with("256 bytes per row instead of 240"));
+    }
+
+    #[test]
+    fn odd_argb_frames_are_padded_by_edge_replication() {
+        let argb = (0u8..3 * 3 * 4).collect::<Vec<_>>();
+        let (padded, width, height) = pad_argb_to_even(&argb, 3, 3).unwrap();
+        assert_eq!((width, height), (4, 4));
+        let pixel = |data: &[u8], stride: usize, x: usize, y: usize| {
+            data[(y * stride + x) * 4..][..4].to_vec()
+        };
+        assert_eq!(pixel(&padded, 4, 3, 1), pixel(&argb, 3, 2, 1));
+        assert_eq!(pixel(&padded, 4, 1, 3), pixel(&argb, 3, 1, 2));
+        assert_eq!(pixel(&padded, 4, 3, 3), pixel(&argb, 3, 2, 2));
+        assert!(matches!(
+            pad_argb_to_even(&[0; 7], 3, 3),
+            Err(BackendError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn encode_frame_to_legacy_rejects_out_of_bounds_dirty_rects() {
+        let dims = Dimensions {
+            width: std::num::NonZeroU32::new(64).unwrap(),
+            height: std::num::NonZeroU32::new(32).unwrap(),
+        };
+        let result = encode_frame_to_legacy(EncodeFrame {
+            dims,
+            pts_90k: None,
+            buffer: RawFrameBuffer::Argb8888(vec![0; 64 * 32 * 4]),
+            force_keyframe: false,
+            dirty_rects: Some(vec![DirtyRect {
+                x: 48,
+                y: 0,
+                width: 32,
+                height: 8,
+            }]),
+            repeat_count: 0,
         });
         assert!(matches!(result, Err(BackendError::InvalidInput(_))));
     }
@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use crate::{FrameRate, Timestamp90k};
+
+// Share of the measured drift corrected per frame, so submit-time jitter averages out instead
+// of showing up in the timestamps.
+const DRIFT_SMOOTHING: i64 = 8;
+
+// House time an EncodeSession locks its timestamps to: a PTP clock, a GStreamer pipeline
+// clock, a genlock counter. Closures returning the current time work too.
+pub trait MasterClock: Send + Sync {
+    fn now_90k(&self) -> Timestamp90k;
+}
+
+impl<F> MasterClock for F
+where
+    F: Fn() -> Timestamp90k + Send + Sync,
+{
+    fn now_90k(&self) -> Timestamp90k {
+        self()
+    }
+}
+
+// `max_slew_90k` caps the correction applied to one frame's timestamp (default 1 ms). Drift
+// beyond `resync_threshold_90k` (default 1 s, e.g. the source stalled or the house clock
+// jumped) re-anchors on the clock at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MasterClockOptions {
+    pub max_slew_90k: i64,
+    pub resync_threshold_90k: i64,
+}
+
+impl Default for MasterClockOptions {
+    fn default() -> Self {
+        Self {
+            max_slew_90k: 90,
+            resync_threshold_90k: 90_000,
+        }
+    }
+}
+
+// `drift_90k` is the house clock minus the frame-rate timeline at the last frame (positive:
+// the stream was running slow), `corrected_90k` the slew applied in total.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MasterClockStats {
+    pub frames: u64,
+    pub drift_90k: i64,
+    pub max_drift_90k: i64,
+    pub corrected_90k: i64,
+    pub resyncs: u64,
+}
+
+// Stamps frames at the session frame rate starting from the house clock, and slews that
+// timeline towards the clock a little per frame so it stays locked without jumping. Without a
+// known frame rate every frame gets the clock reading itself. Timestamps strictly increase.
+pub(crate) struct MasterClockStamper {
+    clock: Arc<dyn MasterClock>,
+    options: MasterClockOptions,
+    // Timeline origin, the frame rate it runs at and the frames stamped on it.
+    anchor: Option<(i64, FrameRate, i64)>,
+    last: Option<i64>,
+    stats: MasterClockStats,
+}
+
+impl MasterClockStamper {
+    pub(crate) fn new(clock: Arc<dyn MasterClock>, options: MasterClockOptions) -> Self {
+        Self {
+            clock,
+            options,
+            anchor: None,
+            last: None,
+            stats: MasterClockStats::default(),
+        }
+    }
+
+    pub(crate) fn stamp(&mut self, fps: FrameRate) -> Timestamp90k {
+        let now = self.clock.now_90k().0;
+        let pts = match self.anchor {
+            Some((origin, rate, index)) if rate == fps && fps.is_known() => {
+                let nominal = origin + fps.pts_90k(index);
+                let drift = now - nominal;
+                self.stats.drift_90k = drift;
+                self.stats.max_drift_90k = self.stats.max_drift_90k.max(drift.abs());
+                if drift.abs() > self.options.resync_threshold_90k {
+                    self.stats.resyncs += 1;
+                    self.anchor = Some((now, fps, 1));
+                    now
+                } else {
+                    let slew_limit = self.options.max_slew_90k.max(0);
+                    let correction = (drift / DRIFT_SMOOTHING).clamp(-slew_limit, slew_limit);
+                    self.stats.corrected_90k += correction;
+                    self.anchor = Some((origin + correction, fps, index + 1));
+                    nominal + correction
+                }
+            }
+            _ => {
+                self.anchor = Some((now, fps, 1));
+                now
+            }
+        };
+        let pts = match self.last {
+            Some(last) if pts <= last => last + 1,
+            _ => pts,
+        };
+        self.last = Some(pts);
+        self.stats.frames += 1;
+        Timestamp90k(pts)
+    }
+
+    pub(crate) fn stats(&self) -> MasterClockStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn timeline_slews_towards_a_drifting_house_clock() {
+        let house = Arc::new(AtomicI64::new(900_000));
+        let clock = {
+            let house = Arc::clone(&house);
+            move || Timestamp90k(house.load(Ordering::Relaxed))
+        };
+        let mut stamper = MasterClockStamper::new(Arc::new(clock), MasterClockOptions::default());
+        let fps = FrameRate::new(30, 1);
+        // The source delivers 3000-tick frames with ±200 ticks of jitter, but the house clock
+        // runs 0.1% fast, so the stream would fall behind it without correction.
+        let mut stamps = Vec::new();
+        for frame in 0..300_i64 {
+            let jitter = if frame % 2 == 0 { 200 } else { -200 };
+            house.store(900_000 + frame * 3003 + jitter, Ordering::Relaxed);
+            stamps.push(stamper.stamp(fps).0);
+        }
+        assert_eq!(stamps[0], 900_200);
+        // Frame spacing never moves by more than the slew limit.
+        assert!(
+            stamps
+                .windows(2)
+                .all(|pair| (pair[1] - pair[0] - 3000).abs() <= 90)
+        );
+        // After settling the stream tracks the clock to within the jitter.
+        let last_house = 900_000 + 299 * 3003;
+        assert!((stamps[299] - last_house).abs() <= 250, "{}", stamps[299]);
+        let stats = stamper.stats();
+        assert_eq!((stats.frames, stats.resyncs), (300, 0));
+        assert!(stats.corrected_90k > 0);
+
+        // A jump in the house clock re-anchors, and a clock going backwards cannot make the
+        // timestamps do the same.
+        house.store(10_000_000, Ordering::Relaxed);
+        assert_eq!(stamper.stamp(fps), Timestamp90k(10_000_000));
+        assert_eq!(stamper.stats().resyncs, 1);
+        house.store(0, Ordering::Relaxed);
+        stamper.stamp(FrameRate::new(0, 0));
+        assert_eq!(
+            stamper.stamp(FrameRate::new(0, 0)),
+            Timestamp90k(10_000_002)
+        );
+    }
+}
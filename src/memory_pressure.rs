@@ -0,0 +1,229 @@
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+use crate::{BackendError, DiagnosticEvent, Diagnostics};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum MemoryPressureLevel {
+    #[default]
+    Normal,
+    Elevated,
+    Critical,
+}
+
+impl Display for MemoryPressureLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Normal => f.write_str("normal"),
+            Self::Elevated => f.write_str("elevated"),
+            Self::Critical => f.write_str("critical"),
+        }
+    }
+}
+
+// Device memory as the driver reports it (cuMemGetInfo on the session's CUDA context).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceMemory {
+    pub free_bytes: u64,
+    pub total_bytes: u64,
+}
+
+// Free device memory is sampled from submit at most once per `sample_interval`; the level is
+// Elevated once the free share drops to `elevated_free_ratio` and Critical at
+// `critical_free_ratio` or after an allocation failure. With `shrink_pools`, every rise in the
+// level makes the session release what it can rebuild on demand: an idle VideoToolbox pixel
+// buffer pool, CUDA contexts no session holds and spare ready-queue capacity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryPressureOptions {
+    pub sample_interval: Duration,
+    pub elevated_free_ratio: f32,
+    pub critical_free_ratio: f32,
+    pub shrink_pools: bool,
+}
+
+impl Default for MemoryPressureOptions {
+    fn default() -> Self {
+        Self {
+            sample_interval: Duration::from_secs(1),
+            elevated_free_ratio: 0.15,
+            critical_free_ratio: 0.05,
+            shrink_pools: false,
+        }
+    }
+}
+
+// Reports level changes, and every allocation failure, as DiagnosticEvent::MemoryPressure.
+// Backends without a free-memory query (VideoToolbox shares system memory) only report
+// allocation failures.
+#[derive(Debug)]
+pub(crate) struct MemoryPressureMonitor {
+    diagnostics: Diagnostics,
+    options: Option<MemoryPressureOptions>,
+    level: MemoryPressureLevel,
+    memory: Option<DeviceMemory>,
+    last_sample: Option<Instant>,
+    allocation_failures: u64,
+}
+
+impl MemoryPressureMonitor {
+    pub(crate) fn new(diagnostics: Diagnostics) -> Self {
+        Self {
+            diagnostics,
+            options: None,
+            level: MemoryPressureLevel::Normal,
+            memory: None,
+            last_sample: None,
+            allocation_failures: 0,
+        }
+    }
+
+    pub(crate) fn set_options(&mut self, options: Option<MemoryPressureOptions>) {
+        self.options = options;
+        self.level = MemoryPressureLevel::Normal;
+        self.memory = None;
+        self.last_sample = None;
+    }
+
+    pub(crate) fn options(&self) -> Option<MemoryPressureOptions> {
+        self.options
+    }
+
+    pub(crate) fn level(&self) -> MemoryPressureLevel {
+        self.level
+    }
+
+    // Returns whether the session should shrink its pools now.
+    pub(crate) fn sample(
+        &mut self,
+        now: Instant,
+        probe: impl FnOnce() -> Option<DeviceMemory>,
+    ) -> bool {
+        let Some(options) = self.options else {
+            return false;
+        };
+        if self
+            .last_sample
+            .is_some_and(|last| now.saturating_duration_since(last) < options.sample_interval)
+        {
+            return false;
+        }
+        self.last_sample = Some(now);
+        let Some(memory) = probe().filter(|memory| memory.total_bytes > 0) else {
+            return false;
+        };
+        self.memory = Some(memory);
+        let free = memory.free_bytes as f64 / memory.total_bytes as f64;
+        let level = if free <= f64::from(options.critical_free_ratio) {
+            MemoryPressureLevel::Critical
+        } else if free <= f64::from(options.elevated_free_ratio) {
+            MemoryPressureLevel::Elevated
+        } else {
+            MemoryPressureLevel::Normal
+        };
+        if level == self.level {
+            return false;
+        }
+        let rising = level > self.level;
+        self.level = level;
+        self.report();
+        rising && options.shrink_pools
+    }
+
+    // Returns whether the session should shrink its pools now.
+    pub(crate) fn observe_error(&mut self, err: &BackendError) -> bool {
+        let Some(options) = self.options else {
+            return false;
+        };
+        if !is_allocation_failure(err) {
+            return false;
+        }
+        self.allocation_failures += 1;
+        self.level = MemoryPressureLevel::Critical;
+        self.report();
+        options.shrink_pools
+    }
+
+    fn report(&self) {
+        self.diagnostics.emit(DiagnosticEvent::MemoryPressure {
+            level: self.level,
+            free_bytes: self.memory.map(|memory| memory.free_bytes),
+            total_bytes: self.memory.map(|memory| memory.total_bytes),
+            allocation_failures: self.allocation_failures,
+        });
+    }
+}
+
+// CUDA_ERROR_OUT_OF_MEMORY, NV_ENC_ERR_OUT_OF_MEMORY and the VideoToolbox allocation errors
+// all reach the session as backend error text.
+fn is_allocation_failure(err: &BackendError) -> bool {
+    let message = err.to_string().to_ascii_lowercase();
+    [
+        "out_of_memory",
+        "out of memory",
+        "allocation failed",
+        "-12904",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::diagnostics::RecordingDiagnostics;
+
+    #[test]
+    fn level_changes_and_allocation_failures_are_reported() {
+        let sink = Arc::new(RecordingDiagnostics::default());
+        let mut monitor = MemoryPressureMonitor::new(Diagnostics::from_arc(sink.clone()));
+        let gib = 1 << 30;
+        let free = |free_bytes| {
+            move || {
+                Some(DeviceMemory {
+                    free_bytes,
+                    total_bytes: 10 * gib,
+                })
+            }
+        };
+        let start = Instant::now();
+        assert!(!monitor.sample(start, free(0)));
+        monitor.set_options(Some(MemoryPressureOptions {
+            shrink_pools: true,
+            ..MemoryPressureOptions::default()
+        }));
+
+        assert!(!monitor.sample(start, free(5 * gib)));
+        // Within the sample interval the probe is not consulted.
+        assert!(!monitor.sample(start + Duration::from_millis(500), || unreachable!()));
+        assert!(monitor.sample(start + Duration::from_secs(1), free(gib)));
+        assert_eq!(monitor.level(), MemoryPressureLevel::Elevated);
+        assert!(!monitor.sample(start + Duration::from_secs(2), free(gib)));
+        assert!(!monitor.sample(start + Duration::from_secs(3), free(4 * gib)));
+        assert!(!monitor.observe_error(&BackendError::Backend("bad argument".to_string())));
+        assert!(monitor.observe_error(&BackendError::Backend(
+            "nvEncLockBitstream failed: NV_ENC_ERR_OUT_OF_MEMORY".to_string()
+        )));
+        assert_eq!(monitor.level(), MemoryPressureLevel::Critical);
+
+        let events = sink.events();
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[2],
+            DiagnosticEvent::MemoryPressure {
+                level: MemoryPressureLevel::Critical,
+                free_bytes: Some(4 * gib),
+                total_bytes: Some(10 * gib),
+                allocation_failures: 1,
+            }
+        );
+        assert_eq!(
+            events[0].to_string(),
+            format!(
+                "[memory.pressure] level=elevated, free_bytes={gib}, total_bytes={}, allocation_failures=0",
+                10 * gib
+            )
+        );
+    }
+}
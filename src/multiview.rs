@@ -0,0 +1,133 @@
+use std::fmt::{self, Display};
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+use crate::Backend;
+
+// The two views of stereo MV-HEVC as Apple spatial video lays them out: the base layer
+// (nuh_layer_id 0) carries the left eye, the second layer the right eye.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StereoView {
+    Left,
+    Right,
+}
+
+impl StereoView {
+    pub fn layer_id(self) -> u8 {
+        match self {
+            Self::Left => 0,
+            Self::Right => 1,
+        }
+    }
+
+    pub fn from_layer_id(layer_id: u8) -> Option<Self> {
+        match layer_id {
+            0 => Some(Self::Left),
+            1 => Some(Self::Right),
+            _ => None,
+        }
+    }
+}
+
+impl Display for StereoView {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Left => "left",
+            Self::Right => "right",
+        })
+    }
+}
+
+// Whether a backend can decode both views of, and encode, two-layer MV-HEVC on this machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MultiViewSupport {
+    pub decode: bool,
+    pub encode: bool,
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+// VideoToolbox answers on macOS 14 and later, when the media engine handles MV-HEVC; NVDEC and
+// NVENC have no multi-layer HEVC, so NVIDIA supports neither direction.
+pub fn mv_hevc_support(backend: Backend) -> MultiViewSupport {
+    match backend {
+        Backend::Auto => mv_hevc_support(Backend::os_default()),
+        #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+        Backend::VideoToolbox => crate::vt_multiview::support(),
+        #[cfg(all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        ))]
+        Backend::Nvidia => MultiViewSupport::default(),
+    }
+}
+
+// nuh_layer_id from the two-byte HEVC NAL unit header.
+pub(crate) fn hevc_layer_id(nal: &[u8]) -> u8 {
+    match nal {
+        [first, second, ..] => ((first & 0x01) << 5) | (second >> 3),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{Codec, EncodedChunk, EncodedLayout};
+
+    #[test]
+    fn views_follow_the_hevc_layer_id() {
+        for view in [StereoView::Left, StereoView::Right] {
+            assert_eq!(StereoView::from_layer_id(view.layer_id()), Some(view));
+        }
+        assert_eq!(StereoView::from_layer_id(2), None);
+        assert_eq!(StereoView::Right.to_string(), "right");
+
+        // IDR_W_RADL in the base layer, TRAIL_R in layer 1, and an SPS whose layer id uses the
+        // high bit kept in the first header byte.
+        assert_eq!(hevc_layer_id(&[0x26, 0x01]), 0);
+        assert_eq!(hevc_layer_id(&[0x02, 0x09]), 1);
+        assert_eq!(hevc_layer_id(&[0x43, 0x09]), 33);
+        assert_eq!(hevc_layer_id(&[0x02]), 0);
+
+        let chunk = EncodedChunk {
+            codec: Codec::Hevc,
+            layout: EncodedLayout::AnnexB,
+            data: Arc::from(
+                [
+                    &[0, 0, 0, 1, 0x42, 0x09, 0x01][..],
+                    &[0, 0, 0, 1, 0x26, 0x01, 0xaf][..],
+                    &[0, 0, 0, 1, 0x02, 0x09, 0xaf][..],
+                ]
+                .concat(),
+            ),
+            pts_90k: None,
+            is_keyframe: true,
+            filler_bytes: 0,
+            dts_90k: None,
+            display_index: None,
+        };
+        assert_eq!(
+            chunk.stereo_views().unwrap(),
+            vec![StereoView::Left, StereoView::Right]
+        );
+        let h264 = EncodedChunk {
+            codec: Codec::H264,
+            data: Arc::from(&[0, 0, 0, 1, 0x65, 0x88][..]),
+            ..chunk
+        };
+        assert!(h264.stereo_views().unwrap().is_empty());
+    }
+}
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::collections::VecDeque;
 use std::mem;
 use std::pin::Pin;
@@ -5,18 +6,21 @@ use std::sync::{Arc, mpsc};
 use std::time::{Duration, Instant};
 
 use cudarc::driver::CudaContext;
-use nvidia_video_codec_sdk::{
-    DecodeCodec, Encoder, EncoderInitParams, ErrorKind, ReconfigureParams,
-};
+use nvidia_video_codec_sdk::sys::cuviddec::cudaVideoCodec;
+use nvidia_video_codec_sdk::{Encoder, EncoderInitParams, ErrorKind, ReconfigureParams};
 
 use crate::backend_transform_adapter::{DecodedUnit, NvidiaTransformAdapter};
-use crate::bitstream::{AccessUnit, StatefulBitstreamAssembler};
-use crate::nv_meta_decoder::NvMetaDecoder;
+use crate::bitstream::{AccessUnit, StatefulBitstreamAssembler, closes_picture, starts_picture};
+use crate::device_cache;
+use crate::nv_meta_decoder::{NvDecodeTuning, NvMetaDecoder};
 use crate::pipeline_scheduler::PipelineScheduler;
 use crate::{
     BackendDecoderOptions, BackendEncoderOptions, BackendError, CapabilityReport, Codec,
-    ColorRequest, DecodeSummary, DecoderConfig, EncodedPacket, Frame, NvidiaSessionConfig,
-    SessionSwitchMode, SessionSwitchRequest, VideoDecoder, VideoEncoder,
+    ColorRequest, ContentHint, DecodeSummary, DecodedFrame, DecoderConfig, DeviceMemory,
+    DiagnosticEvent, Diagnostics, DirtyRect, EncodeLatency, EncodedPacket, Frame, FrameCrop,
+    FrameRate, NvBufferLifetimeMode, NvidiaSessionConfig, SessionSwitchMode, SessionSwitchRequest,
+    SoftwareDecoder, SoftwareDecoderFactory, SurfacePoolSizes, Timestamp90k, VideoDecoder,
+    VideoEncoder, surface_pool,
 };
 
 #[derive(Debug, Default)]
@@ -123,12 +127,6 @@ fn env_usize(name: &str) -> Option<usize> {
     std::env::var(name).ok()?.parse::<usize>().ok()
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum NvBufferLifetimeMode {
-    ReusablePoolUnsafe,
-    PerFrameSafe,
-}
-
 #[derive(Debug, Default, Clone, Copy)]
 struct CopyStats {
     input_upload_bytes: u64,
@@ -143,44 +141,111 @@ pub struct NvDecoderAdapter {
     assembler: StatefulBitstreamAssembler,
     packer: AnnexBPacker,
     decoder: Option<NvMetaDecoder>,
-    next_pts_90k: i64,
+    tuning: NvDecodeTuning,
+    slice_fast_start: bool,
+    // pts of the picture whose slices are arriving, for untimed slice chunks.
+    slice_pts_90k: i64,
+    surfaces_reported: bool,
+    software_factory: Option<SoftwareDecoderFactory>,
+    software: Option<Box<dyn SoftwareDecoder>>,
+    next_frame_index: i64,
     last_summary: DecodeSummary,
+    diagnostics: Diagnostics,
 }
 
 impl NvDecoderAdapter {
     pub fn new(config: DecoderConfig) -> Self {
-        let report_metrics = match &config.backend_options {
-            BackendDecoderOptions::Nvidia(options) => options
-                .report_metrics
-                .or_else(|| env_bool("VIDEO_HW_NV_METRICS"))
-                .unwrap_or(false),
-            BackendDecoderOptions::Default => env_bool("VIDEO_HW_NV_METRICS").unwrap_or(false),
+        let (report_metrics, software_factory, tuning) = match &config.backend_options {
+            BackendDecoderOptions::Nvidia(options) => {
+                let defaults = NvDecodeTuning::default();
+                (
+                    options
+                        .report_metrics
+                        .or_else(|| env_bool("VIDEO_HW_NV_METRICS"))
+                        .unwrap_or(false),
+                    options.software_decoder.clone(),
+                    NvDecodeTuning {
+                        decode_surfaces: options.decode_surfaces,
+                        output_surfaces: options.output_surfaces,
+                        low_latency: options.low_latency.unwrap_or(defaults.low_latency),
+                        histogram: options.histogram.unwrap_or(defaults.histogram),
+                        ..defaults
+                    },
+                )
+            }
+            BackendDecoderOptions::Default => (
+                env_bool("VIDEO_HW_NV_METRICS").unwrap_or(false),
+                None,
+                NvDecodeTuning::default(),
+            ),
+        };
+        let slice_fast_start = matches!(
+            &config.backend_options,
+            BackendDecoderOptions::Nvidia(options) if options.slice_fast_start == Some(true)
+        );
+        let tuning = NvDecodeTuning {
+            in_flight: surface_pool::fps_headroom(config.fps) + u32::from(!tuning.low_latency),
+            output_scale: config
+                .output_scale
+                .map(|dims| (dims.width.get(), dims.height.get())),
+            ..tuning
         };
         Self {
-            assembler: StatefulBitstreamAssembler::with_codec(config.codec),
+            assembler: StatefulBitstreamAssembler::with_codec(config.codec)
+                .with_max_temporal_id(config.max_temporal_id),
             packer: AnnexBPacker::default(),
             config,
             report_metrics,
             decoder: None,
-            next_pts_90k: 0,
+            tuning,
+            slice_fast_start,
+            slice_pts_90k: 0,
+            surfaces_reported: false,
+            software_factory,
+            software: None,
+            next_frame_index: 0,
             last_summary: DecodeSummary {
                 decoded_frames: 0,
                 width: None,
                 height: None,
                 pixel_format: None,
             },
+            diagnostics: Diagnostics::default(),
         }
     }
 
     fn ensure_decoder(&mut self) -> Result<(), BackendError> {
-        if self.decoder.is_some() {
+        if self.decoder.is_some() || self.software.is_some() {
             return Ok(());
         }
+        if self.config.stereo_views {
+            return Err(BackendError::UnsupportedConfig(
+                "NVDEC cannot decode MV-HEVC enhancement layers (stereo_views)".to_string(),
+            ));
+        }
 
-        let cuda_ctx = CudaContext::new(0).map_err(|err| {
-            BackendError::UnsupportedConfig(format!("failed to initialize CUDA context: {err}"))
-        })?;
-        let decoder = NvMetaDecoder::new(cuda_ctx, to_decode_codec(self.config.codec))?;
+        if self.config.force_software {
+            if self.config.require_hardware {
+                return Err(BackendError::UnsupportedConfig(
+                    "require_hardware and force_software cannot both be set".to_string(),
+                ));
+            }
+            let factory = self.software_factory.as_ref().ok_or_else(|| {
+                BackendError::UnsupportedConfig(
+                    "force_software on nvidia needs NvidiaDecoderOptions::software_decoder"
+                        .to_string(),
+                )
+            })?;
+            self.software = Some(factory.create(self.config.codec)?);
+            self.diagnostics.emit(DiagnosticEvent::SoftwareFallback {
+                reason: "force_software requested".to_string(),
+            });
+            return Ok(());
+        }
+
+        let cuda_ctx = device_cache::cuda_context(0)?;
+        let decoder =
+            NvMetaDecoder::new(cuda_ctx, to_decode_codec(self.config.codec), self.tuning)?;
 
         self.decoder = Some(decoder);
         Ok(())
@@ -199,11 +264,7 @@ impl NvDecoderAdapter {
         let mut timing = StageTiming::default();
         let mut pack_samples = SampleStats::default();
         let mut sdk_samples = SampleStats::default();
-        let expected_frame_ms = if self.config.fps > 0 {
-            1_000.0 / self.config.fps as f64
-        } else {
-            33.333
-        };
+        let expected_frame_ms = self.config.fps.frame_duration_ms();
         let mut frames = Vec::new();
         let mut map_samples = SampleStats::default();
         let mut queue_depth_samples = SampleStats::default();
@@ -211,23 +272,51 @@ impl NvDecoderAdapter {
         let mut last_pts_90k = None;
 
         for au in access_units {
-            let pts_90k = if let Some(pts) = au.pts_90k.or(fallback_pts_90k) {
+            let pts_90k = if let Some(pts) = fallback_pts_90k {
                 pts
             } else {
                 self.bump_pts_90k()
             };
             let pack_start = Instant::now();
-            let packed = self.packer.pack(au);
+            // NVDEC's JPEG parser takes each image as-is; start codes would corrupt the SOI.
+            let packed = if self.config.codec == Codec::Mjpeg {
+                au.nalus.first().cloned().unwrap_or_default()
+            } else {
+                self.packer.pack(au)
+            };
             let pack_elapsed = pack_start.elapsed();
             timing.pack += pack_elapsed;
             pack_samples.push_duration_ms(pack_elapsed);
 
             let decode_start = Instant::now();
-            let decoded = {
+            let decoded = if let Some(software) = self.software.as_mut() {
+                software
+                    .decode(packed, Some(Timestamp90k(pts_90k)))?
+                    .into_iter()
+                    .map(software_frame_to_legacy)
+                    .collect()
+            } else {
                 let decoder = self.decoder.as_mut().ok_or_else(|| {
                     BackendError::Backend("decoder should be initialized".to_string())
                 })?;
-                decoder.push_access_unit(packed, pts_90k)?
+                let decoded = decoder.push_access_unit(packed, pts_90k)?;
+                if self.report_metrics
+                    && !self.surfaces_reported
+                    && let Some(surfaces) = decoder.surface_counts()
+                {
+                    self.surfaces_reported = true;
+                    self.diagnostics.emit(DiagnosticEvent::Metrics {
+                        scope: "nv.decode.config",
+                        detail: format!(
+                            "decode_surfaces={}, output_surfaces={}, automatic={}, low_latency={}",
+                            surfaces.surfaces,
+                            surfaces.output_surfaces,
+                            surfaces.automatic,
+                            self.tuning.low_latency
+                        ),
+                    });
+                }
+                decoded
             };
             let sdk_elapsed = decode_start.elapsed();
             timing.sdk += sdk_elapsed;
@@ -255,40 +344,64 @@ impl NvDecoderAdapter {
         };
 
         if self.report_metrics {
-            eprintln!(
-                "[nv.decode] access_units={}, frames={}, pack_ms={:.3}, sdk_ms={:.3}, map_ms={:.3}, pack_p95_ms={:.3}, pack_p99_ms={:.3}, sdk_p95_ms={:.3}, sdk_p99_ms={:.3}, map_p95_ms={:.3}, map_p99_ms={:.3}, queue_depth_peak={:.0}, queue_depth_p95={:.3}, queue_depth_p99={:.3}, jitter_ms_mean={:.3}, jitter_ms_p95={:.3}, jitter_ms_p99={:.3}",
-                access_units.len(),
-                reap_summary.frames.len(),
-                timing.pack.as_secs_f64() * 1_000.0,
-                timing.sdk.as_secs_f64() * 1_000.0,
-                reap_summary.map_samples.samples.iter().sum::<f64>(),
-                pack_samples.p95(),
-                pack_samples.p99(),
-                sdk_samples.p95(),
-                sdk_samples.p99(),
-                reap_summary.map_samples.p95(),
-                reap_summary.map_samples.p99(),
-                reap_summary.queue_depth_samples.peak(),
-                reap_summary.queue_depth_samples.p95(),
-                reap_summary.queue_depth_samples.p99(),
-                reap_summary.jitter_samples.mean(),
-                reap_summary.jitter_samples.p95(),
-                reap_summary.jitter_samples.p99()
-            );
+            self.diagnostics.emit(DiagnosticEvent::Metrics {
+                scope: "nv.decode",
+                detail: format!(
+                    "access_units={}, frames={}, pack_ms={:.3}, sdk_ms={:.3}, map_ms={:.3}, pack_p95_ms={:.3}, pack_p99_ms={:.3}, sdk_p95_ms={:.3}, sdk_p99_ms={:.3}, map_p95_ms={:.3}, map_p99_ms={:.3}, queue_depth_peak={:.0}, queue_depth_p95={:.3}, queue_depth_p99={:.3}, jitter_ms_mean={:.3}, jitter_ms_p95={:.3}, jitter_ms_p99={:.3}",
+                    access_units.len(),
+                    reap_summary.frames.len(),
+                    timing.pack.as_secs_f64() * 1_000.0,
+                    timing.sdk.as_secs_f64() * 1_000.0,
+                    reap_summary.map_samples.samples.iter().sum::<f64>(),
+                    pack_samples.p95(),
+                    pack_samples.p99(),
+                    sdk_samples.p95(),
+                    sdk_samples.p99(),
+                    reap_summary.map_samples.p95(),
+                    reap_summary.map_samples.p99(),
+                    reap_summary.queue_depth_samples.peak(),
+                    reap_summary.queue_depth_samples.p95(),
+                    reap_summary.queue_depth_samples.p99(),
+                    reap_summary.jitter_samples.mean(),
+                    reap_summary.jitter_samples.p95(),
+                    reap_summary.jitter_samples.p99()
+                ),
+            });
         }
 
         Ok(reap_summary.frames)
     }
 
-    fn bump_pts_90k(&mut self) -> i64 {
-        let current = self.next_pts_90k;
-        let step = if self.config.fps > 0 {
-            (90_000 / i64::from(self.config.fps)).max(1)
-        } else {
-            3_000
+    fn decode_slices(
+        &mut self,
+        chunk: &[u8],
+        pts_90k: Option<i64>,
+    ) -> Result<Vec<Frame>, BackendError> {
+        let nalus = self.assembler.push_slices(chunk, self.config.codec);
+        if nalus.is_empty() {
+            return Ok(Vec::new());
+        }
+        let codec = self.config.codec;
+        self.slice_pts_90k = match pts_90k {
+            Some(pts) => pts,
+            None if nalus.iter().any(|nal| starts_picture(codec, nal)) => self.bump_pts_90k(),
+            None => self.slice_pts_90k,
         };
-        self.next_pts_90k = self.next_pts_90k.saturating_add(step);
-        current
+        let end_of_picture = nalus.last().is_some_and(|nal| closes_picture(codec, nal));
+        let packed = self.packer.pack(&AccessUnit { codec, nalus });
+        let decoder = self
+            .decoder
+            .as_mut()
+            .ok_or_else(|| BackendError::Backend("decoder should be initialized".to_string()))?;
+        let decoded = decoder.push_slices(packed, self.slice_pts_90k, end_of_picture)?;
+        self.apply_decoded_summary(&decoded);
+        Ok(decoded)
+    }
+
+    fn bump_pts_90k(&mut self) -> i64 {
+        let index = self.next_frame_index;
+        self.next_frame_index = self.next_frame_index.saturating_add(1);
+        self.config.fps.pts_90k(index)
     }
 
     fn apply_decoded_summary(&mut self, decoded: &[Frame]) {
@@ -300,6 +413,7 @@ impl NvDecoderAdapter {
         if let Some(last) = decoded.last() {
             self.last_summary.width = Some(last.width);
             self.last_summary.height = Some(last.height);
+            self.last_summary.pixel_format = last.pixel_format;
         }
     }
 }
@@ -308,7 +422,7 @@ impl VideoDecoder for NvDecoderAdapter {
     fn query_capability(&self, codec: Codec) -> Result<CapabilityReport, BackendError> {
         Ok(CapabilityReport {
             codec,
-            decode_supported: matches!(codec, Codec::H264 | Codec::Hevc),
+            decode_supported: matches!(codec, Codec::H264 | Codec::Hevc | Codec::Mjpeg),
             encode_supported: matches!(codec, Codec::H264 | Codec::Hevc),
             hardware_acceleration: true,
         })
@@ -319,6 +433,12 @@ impl VideoDecoder for NvDecoderAdapter {
         chunk: &[u8],
         pts_90k: Option<i64>,
     ) -> Result<Vec<Frame>, BackendError> {
+        if self.slice_fast_start && self.config.codec != Codec::Mjpeg {
+            self.ensure_decoder()?;
+            if self.software.is_none() {
+                return self.decode_slices(chunk, pts_90k);
+            }
+        }
         let (access_units, _cache) =
             self.assembler
                 .push_chunk(chunk, self.config.codec, pts_90k)?;
@@ -329,7 +449,15 @@ impl VideoDecoder for NvDecoderAdapter {
         let (access_units, _cache) = self.assembler.flush()?;
         let mut frames = self.decode_access_units(&access_units, None)?;
 
-        if let Some(decoder) = self.decoder.as_mut() {
+        if let Some(software) = self.software.as_mut() {
+            let drained: Vec<Frame> = software
+                .flush()?
+                .into_iter()
+                .map(software_frame_to_legacy)
+                .collect();
+            self.apply_decoded_summary(&drained);
+            frames.extend(drained);
+        } else if let Some(decoder) = self.decoder.as_mut() {
             let drained = decoder.flush()?;
             self.apply_decoded_summary(&drained);
             frames.extend(drained);
@@ -338,18 +466,143 @@ impl VideoDecoder for NvDecoderAdapter {
         Ok(frames)
     }
 
+    fn drain_available(&mut self) -> Result<Vec<Frame>, BackendError> {
+        let (access_units, _cache) = self.assembler.flush()?;
+        let mut frames = self.decode_access_units(&access_units, None)?;
+
+        if let Some(software) = self.software.as_mut() {
+            let drained: Vec<Frame> = software
+                .flush()?
+                .into_iter()
+                .map(software_frame_to_legacy)
+                .collect();
+            self.apply_decoded_summary(&drained);
+            frames.extend(drained);
+        } else if let Some(decoder) = self.decoder.as_mut() {
+            let drained = decoder.drain()?;
+            self.apply_decoded_summary(&drained);
+            frames.extend(drained);
+        }
+
+        Ok(frames)
+    }
+
     fn decode_summary(&self) -> DecodeSummary {
         self.last_summary.clone()
     }
+
+    fn surface_pool(&self) -> Option<SurfacePoolSizes> {
+        self.decoder.as_ref()?.surface_counts()
+    }
+
+    fn device_memory(&self) -> Option<DeviceMemory> {
+        cuda_device_memory(self.decoder.as_ref()?.context())
+    }
+
+    // Decode surfaces are fixed by the stream; only idle cached contexts can go.
+    fn trim_pools(&mut self) {
+        device_cache::release_idle_cuda_contexts();
+    }
+
+    fn frame_crop(&self) -> Option<FrameCrop> {
+        self.assembler
+            .parameter_sets()
+            .frame_crop(self.config.codec)
+    }
+
+    fn parameter_set_revision(&self) -> u64 {
+        self.assembler.parameter_sets().revision()
+    }
+
+    fn set_diagnostics(&mut self, diagnostics: Diagnostics) {
+        self.diagnostics = diagnostics;
+    }
+
+    fn close(&mut self) -> Result<(), BackendError> {
+        self.software = None;
+        // The decoder holds its own reference to the CUDA context (the rest belong to the
+        // device cache), so the context outlives the parser and decoder it is destroyed with.
+        match self.decoder.take() {
+            Some(mut decoder) => decoder.close(),
+            None => Ok(()),
+        }
+    }
+}
+
+// The NVDEC path reports frame metadata only, so software frames are reduced to the same shape
+// to keep A/B runs comparable.
+fn software_frame_to_legacy(frame: DecodedFrame) -> Frame {
+    let (dims, pts_90k, pixel_format, decode_info_flags, color, planes, histogram, view) =
+        match frame {
+            DecodedFrame::Metadata {
+                dims,
+                pts_90k,
+                pixel_format,
+                decode_info_flags,
+                color,
+                planes,
+                histogram,
+                view,
+            } => (
+                dims,
+                pts_90k,
+                pixel_format,
+                decode_info_flags,
+                color,
+                planes,
+                histogram,
+                view,
+            ),
+            DecodedFrame::Nv12 {
+                dims,
+                pts_90k,
+                planes,
+                ..
+            }
+            | DecodedFrame::Rgb24 {
+                dims,
+                pts_90k,
+                planes,
+                ..
+            } => (
+                Some(dims),
+                pts_90k,
+                None,
+                None,
+                None,
+                Some(planes),
+                None,
+                None,
+            ),
+        };
+    Frame {
+        width: dims.map_or(0, |dims| dims.width.get() as usize),
+        height: dims.map_or(0, |dims| dims.height.get() as usize),
+        pixel_format,
+        pts_90k: pts_90k.map(|pts| pts.0),
+        decode_info_flags,
+        color_primaries: color.and_then(|color| color.color_primaries),
+        transfer_function: color.and_then(|color| color.transfer_function),
+        ycbcr_matrix: color.and_then(|color| color.ycbcr_matrix),
+        planes,
+        argb: None,
+        force_keyframe: false,
+        dirty_rects: None,
+        luma_histogram: histogram,
+        view,
+        stereo_right: None,
+    }
 }
 
 pub struct NvEncoderAdapter {
     codec: Codec,
-    fps: i32,
+    fps: FrameRate,
     require_hardware: bool,
     max_in_flight_outputs: usize,
+    surface_pool_size: Option<usize>,
     gop_length: Option<u32>,
     frame_interval_p: Option<i32>,
+    tuning: NvEncodeTuning,
     cuda_ctx: Option<Arc<CudaContext>>,
     active_session: Option<NvEncodeSession>,
     session_reconfigure_pending: bool,
@@ -364,30 +617,57 @@ pub struct NvEncoderAdapter {
     report_metrics: bool,
     buffer_lifetime_mode: NvBufferLifetimeMode,
     pipeline_scheduler: Option<PipelineScheduler>,
+    diagnostics: Diagnostics,
 }
 
 impl NvEncoderAdapter {
     pub fn with_config(
         codec: Codec,
-        fps: i32,
+        fps: FrameRate,
         require_hardware: bool,
         backend_options: BackendEncoderOptions,
     ) -> Self {
         let options = match backend_options {
             BackendEncoderOptions::Nvidia(options) => options,
-            BackendEncoderOptions::Default => crate::NvidiaEncoderOptions::default(),
+            BackendEncoderOptions::Default | BackendEncoderOptions::VideoToolbox(_) => {
+                crate::NvidiaEncoderOptions::default()
+            }
         };
         let max_in_flight_outputs = options.max_in_flight_outputs.clamp(1, 64);
+        let surface_pool_size = options.surface_pool_size;
         let gop_length = options.gop_length;
         let frame_interval_p = options.frame_interval_p;
+        let tuning = NvEncodeTuning {
+            content_hint: options.content_hint.unwrap_or_default(),
+            spatial_aq: options.spatial_aq,
+            temporal_aq: options.temporal_aq,
+            aq_strength: options.aq_strength.map(|v| v.min(15)),
+            lookahead_depth: options
+                .lookahead_depth
+                .map(|v| v.min(NV_MAX_LOOKAHEAD_DEPTH) as u16),
+            average_bitrate: options
+                .average_bitrate_bps
+                .map(|v| v.clamp(1, u64::from(u32::MAX)) as u32),
+            max_qp: options.max_qp,
+            #[cfg(feature = "unsafe-options")]
+            customize_init_params: options.customize_init_params,
+        };
         let report_metrics = options
             .report_metrics
             .or_else(|| env_bool("VIDEO_HW_NV_METRICS"))
             .unwrap_or(false);
-        let safe_lifetime_mode = options
-            .safe_lifetime_mode
-            .or_else(|| env_bool("VIDEO_HW_NV_SAFE_LIFETIME"))
-            .unwrap_or(false);
+        let buffer_lifetime_mode = options
+            .buffer_lifetime_mode
+            .or_else(|| {
+                env_bool("VIDEO_HW_NV_SAFE_LIFETIME").map(|safe| {
+                    if safe {
+                        NvBufferLifetimeMode::PerFrameSafe
+                    } else {
+                        NvBufferLifetimeMode::ReusablePoolUnsafe
+                    }
+                })
+            })
+            .unwrap_or(NvBufferLifetimeMode::ReusablePoolUnsafe);
         let enable_pipeline_scheduler = options
             .enable_pipeline_scheduler
             .or_else(|| env_bool("VIDEO_HW_NV_PIPELINE"))
@@ -402,8 +682,10 @@ impl NvEncoderAdapter {
             fps,
             require_hardware,
             max_in_flight_outputs,
+            surface_pool_size,
             gop_length,
             frame_interval_p,
+            tuning,
             cuda_ctx: None,
             active_session: None,
             session_reconfigure_pending: false,
@@ -416,11 +698,7 @@ impl NvEncoderAdapter {
             width: None,
             height: None,
             report_metrics,
-            buffer_lifetime_mode: if safe_lifetime_mode {
-                NvBufferLifetimeMode::PerFrameSafe
-            } else {
-                NvBufferLifetimeMode::ReusablePoolUnsafe
-            },
+            buffer_lifetime_mode,
             pipeline_scheduler: if enable_pipeline_scheduler {
                 Some(PipelineScheduler::new(
                     NvidiaTransformAdapter::new(1, pipeline_queue_capacity),
@@ -429,6 +707,7 @@ impl NvEncoderAdapter {
             } else {
                 None
             },
+            diagnostics: Diagnostics::default(),
         }
     }
 
@@ -492,9 +771,7 @@ impl NvEncoderAdapter {
         if let Some(ctx) = &self.cuda_ctx {
             return Ok(Arc::clone(ctx));
         }
-        let ctx = CudaContext::new(0).map_err(|err| {
-            BackendError::UnsupportedConfig(format!("failed to initialize CUDA context: {err}"))
-        })?;
+        let ctx = device_cache::cuda_context(0)?;
         self.cuda_ctx = Some(Arc::clone(&ctx));
         Ok(ctx)
     }
@@ -510,7 +787,7 @@ impl NvEncoderAdapter {
         let cuda_ctx = self.ensure_cuda_ctx()?;
 
         let encoder = Encoder::initialize_with_cuda(cuda_ctx).map_err(map_encode_error)?;
-        let encode_guid = to_encode_guid(self.codec);
+        let encode_guid = to_encode_guid(self.codec)?;
 
         let encode_guids = encoder.get_encode_guids().map_err(map_encode_error)?;
         if !encode_guids.contains(&encode_guid) {
@@ -531,21 +808,29 @@ impl NvEncoderAdapter {
         if let Some(frame_interval_p) = self.frame_interval_p {
             preset_config.presetCfg.frameIntervalP = frame_interval_p;
         }
-        let frame_interval_p = usize::try_from(preset_config.presetCfg.frameIntervalP).unwrap_or(1);
-        let lookahead_depth = usize::from(preset_config.presetCfg.rcParams.lookaheadDepth);
-        let pool_size = frame_interval_p
-            .saturating_add(lookahead_depth)
-            .saturating_add(1)
-            .max(3);
+        self.tuning.apply(&mut preset_config.presetCfg);
 
         let mut init_params = EncoderInitParams::new(encode_guid, width as u32, height as u32);
         init_params
             .preset_guid(preset_guid)
             .tuning_info(tuning_info)
             .display_aspect_ratio(16, 9)
-            .framerate(self.fps.max(1) as u32, 1)
+            .framerate(self.fps.normalized().num, self.fps.normalized().den)
             .enable_picture_type_decision()
             .encode_config(&mut preset_config.presetCfg);
+        let config = self.tuning.customize(&mut init_params);
+        let latency = structural_latency(&config);
+        let frame_interval_p = usize::try_from(config.frameIntervalP).unwrap_or(1);
+        let lookahead_depth = usize::from(config.rcParams.lookaheadDepth);
+        let pool = surface_pool::nvenc_pool(
+            width,
+            height,
+            frame_interval_p
+                .saturating_add(lookahead_depth)
+                .saturating_add(1),
+            self.max_in_flight_outputs + surface_pool::fps_headroom(self.fps) as usize,
+            self.surface_pool_size,
+        );
 
         let session = encoder
             .start_session(
@@ -561,7 +846,8 @@ impl NvEncoderAdapter {
             generation,
             self.buffer_lifetime_mode,
             input_layout,
-            pool_size.max(self.max_in_flight_outputs),
+            pool,
+            latency,
         )
     }
 
@@ -578,7 +864,9 @@ impl NvEncoderAdapter {
             self.fps,
             self.gop_length,
             self.frame_interval_p,
+            &self.tuning,
             force_idr,
+            None,
         )?;
         session.generation = target_generation;
         self.active_generation = target_generation;
@@ -587,6 +875,10 @@ impl NvEncoderAdapter {
             .next_generation
             .max(target_generation.saturating_add(1));
         self.session_reconfigure_pending = false;
+        self.diagnostics.emit(DiagnosticEvent::Reconfigured {
+            generation: target_generation,
+            force_idr,
+        });
         Ok(true)
     }
 
@@ -605,11 +897,18 @@ impl NvEncoderAdapter {
         };
 
         if needs_recreate {
+            let rebuilt = self.active_session.is_some();
             let generation = self.config_generation.max(1);
             self.active_session = Some(self.build_session(width, height, generation)?);
             self.active_generation = generation;
             self.next_generation = self.next_generation.max(generation.saturating_add(1));
             self.session_reconfigure_pending = false;
+            if rebuilt {
+                self.diagnostics.emit(DiagnosticEvent::Reconfigured {
+                    generation,
+                    force_idr: true,
+                });
+            }
         }
         self.active_session
             .as_mut()
@@ -621,7 +920,7 @@ impl VideoEncoder for NvEncoderAdapter {
     fn query_capability(&self, codec: Codec) -> Result<CapabilityReport, BackendError> {
         Ok(CapabilityReport {
             codec,
-            decode_supported: matches!(codec, Codec::H264 | Codec::Hevc),
+            decode_supported: matches!(codec, Codec::H264 | Codec::Hevc | Codec::Mjpeg),
             encode_supported: matches!(codec, Codec::H264 | Codec::Hevc),
             hardware_acceleration: true,
         })
@@ -629,6 +928,11 @@ impl VideoEncoder for NvEncoderAdapter {
 
     fn push_frame(&mut self, frame: Frame) -> Result<Vec<EncodedPacket>, BackendError> {
         let mut frame = frame;
+        if frame.stereo_right.is_some() {
+            return Err(BackendError::UnsupportedConfig(
+                "NVENC cannot encode MV-HEVC stereo pairs".to_string(),
+            ));
+        }
         if self.pending_switch.is_some() && frame.force_keyframe {
             self.apply_pending_switch_if_needed()?;
         }
@@ -685,6 +989,7 @@ impl VideoEncoder for NvEncoderAdapter {
             codec: self.codec,
             max_in_flight,
             report_metrics: self.report_metrics,
+            diagnostics: self.diagnostics.clone(),
         };
         let session = self.ensure_session(width, height)?;
         if session.buffer_lifetime_mode == NvBufferLifetimeMode::PerFrameSafe {
@@ -693,6 +998,7 @@ impl VideoEncoder for NvEncoderAdapter {
         let fps = safe_flush_options.fps;
         let codec = safe_flush_options.codec;
         let report_metrics = safe_flush_options.report_metrics;
+        let diagnostics = safe_flush_options.diagnostics;
         let input_layout = session.input_layout;
         let mut pending_outputs = VecDeque::<PendingOutput>::new();
         let mut packets = Vec::new();
@@ -701,11 +1007,7 @@ impl VideoEncoder for NvEncoderAdapter {
         let mut output_depth_peak = 0usize;
         let mut queue_depth_samples = SampleStats::default();
         let mut output_jitter_samples = SampleStats::default();
-        let expected_frame_ms = if fps > 0 {
-            1_000.0 / fps as f64
-        } else {
-            33.333
-        };
+        let expected_frame_ms = fps.frame_duration_ms();
         let mut last_output_pts_90k = None;
         let (ready_tx, ready_rx) = mpsc::channel::<PendingOutput>();
         let (reaped_tx, reaped_rx) = mpsc::channel::<Result<ReapedOutput, BackendError>>();
@@ -729,6 +1031,10 @@ impl VideoEncoder for NvEncoderAdapter {
 
             for (index, frame) in pending_frames.iter().enumerate() {
                 while session.available_pairs() == 0 {
+                    diagnostics.emit(DiagnosticEvent::BufferPoolExhausted {
+                        in_flight: pending_outputs.len(),
+                        capacity: max_in_flight,
+                    });
                     let pending = pending_outputs.pop_front().ok_or_else(|| {
                         BackendError::Backend(
                             "buffer pool exhausted without pending output to reap".to_string(),
@@ -764,10 +1070,10 @@ impl VideoEncoder for NvEncoderAdapter {
                 let mut pair = session.checkout_pair()?;
                 let synth_start = Instant::now();
                 let _ = input_layout;
-                let argb = frame
-                    .argb
-                    .clone()
-                    .unwrap_or_else(|| make_synthetic_argb(width, height, index));
+                let argb = match frame.argb.as_deref() {
+                    Some(argb) => Cow::Borrowed(argb),
+                    None => Cow::Owned(make_synthetic_argb(width, height, index)),
+                };
                 if argb.len() != width.saturating_mul(height).saturating_mul(4) {
                     return Err(BackendError::InvalidInput(format!(
                         "argb payload size mismatch: expected {}, got {}",
@@ -776,18 +1082,27 @@ impl VideoEncoder for NvEncoderAdapter {
                     )));
                 }
                 timing.synth += synth_start.elapsed();
-                copy_stats.input_upload_bytes = copy_stats
-                    .input_upload_bytes
-                    .saturating_add(argb.len() as u64);
-                copy_stats.input_upload_frames = copy_stats.input_upload_frames.saturating_add(1);
-                {
+                let (input_frame, rows) = session.input_rows.next(
+                    pair.input_frame.take(),
+                    frame.argb.as_ref().and(frame.dirty_rects.as_deref()),
+                    width,
+                    height,
+                );
+                let upload = &argb[..rows * width * 4];
+                if !upload.is_empty() {
+                    copy_stats.input_upload_bytes = copy_stats
+                        .input_upload_bytes
+                        .saturating_add(upload.len() as u64);
+                    copy_stats.input_upload_frames =
+                        copy_stats.input_upload_frames.saturating_add(1);
                     let upload_start = Instant::now();
                     let mut lock = pair.input.lock().map_err(map_encode_error)?;
                     unsafe {
-                        lock.write(&argb);
+                        lock.write(upload);
                     }
                     timing.upload += upload_start.elapsed();
                 }
+                pair.input_frame = Some(input_frame);
                 let input_timestamp = frame
                     .pts_90k
                     .unwrap_or_else(|| (index as i64).saturating_mul(3_000))
@@ -817,7 +1132,7 @@ impl VideoEncoder for NvEncoderAdapter {
                 pending_outputs.push_back(PendingOutput {
                     pair,
                     pts_90k: frame.pts_90k,
-                    is_keyframe: index == 0,
+                    is_keyframe: index == 0 || frame.force_keyframe,
                 });
                 output_depth_peak = output_depth_peak.max(pending_outputs.len());
                 queue_depth_samples.push_value(pending_outputs.len() as f64);
@@ -897,28 +1212,31 @@ impl VideoEncoder for NvEncoderAdapter {
         })?;
 
         if report_metrics {
-            eprintln!(
-                "[nv.encode] frames={}, packets={}, queue_peak={}, max_in_flight={}, synth_ms={:.3}, upload_ms={:.3}, submit_ms={:.3}, reap_ms={:.3}, encode_ms={:.3}, lock_ms={:.3}, queue_p95={:.3}, queue_p99={:.3}, jitter_ms_mean={:.3}, jitter_ms_p95={:.3}, jitter_ms_p99={:.3}, input_copy_bytes={}, input_copy_frames={}, output_copy_bytes={}, output_copy_packets={}",
-                pending_frames.len(),
-                packets.len(),
-                output_depth_peak,
-                max_in_flight,
-                timing.synth.as_secs_f64() * 1_000.0,
-                timing.upload.as_secs_f64() * 1_000.0,
-                timing.sdk.as_secs_f64() * 1_000.0,
-                timing.reap.as_secs_f64() * 1_000.0,
-                timing.sdk.as_secs_f64() * 1_000.0,
-                timing.output_lock.as_secs_f64() * 1_000.0,
-                queue_depth_samples.p95(),
-                queue_depth_samples.p99(),
-                output_jitter_samples.mean(),
-                output_jitter_samples.p95(),
-                output_jitter_samples.p99(),
-                copy_stats.input_upload_bytes,
-                copy_stats.input_upload_frames,
-                copy_stats.output_copy_bytes,
-                copy_stats.output_copy_packets
-            );
+            diagnostics.emit(DiagnosticEvent::Metrics {
+                scope: "nv.encode",
+                detail: format!(
+                    "frames={}, packets={}, queue_peak={}, max_in_flight={}, synth_ms={:.3}, upload_ms={:.3}, submit_ms={:.3}, reap_ms={:.3}, encode_ms={:.3}, lock_ms={:.3}, queue_p95={:.3}, queue_p99={:.3}, jitter_ms_mean={:.3}, jitter_ms_p95={:.3}, jitter_ms_p99={:.3}, input_copy_bytes={}, input_copy_frames={}, output_copy_bytes={}, output_copy_packets={}",
+                    pending_frames.len(),
+                    packets.len(),
+                    output_depth_peak,
+                    max_in_flight,
+                    timing.synth.as_secs_f64() * 1_000.0,
+                    timing.upload.as_secs_f64() * 1_000.0,
+                    timing.sdk.as_secs_f64() * 1_000.0,
+                    timing.reap.as_secs_f64() * 1_000.0,
+                    timing.sdk.as_secs_f64() * 1_000.0,
+                    timing.output_lock.as_secs_f64() * 1_000.0,
+                    queue_depth_samples.p95(),
+                    queue_depth_samples.p99(),
+                    output_jitter_samples.mean(),
+                    output_jitter_samples.p95(),
+                    output_jitter_samples.p99(),
+                    copy_stats.input_upload_bytes,
+                    copy_stats.input_upload_frames,
+                    copy_stats.output_copy_bytes,
+                    copy_stats.output_copy_packets
+                ),
+            });
         }
 
         Ok(packets)
@@ -936,7 +1254,96 @@ impl VideoEncoder for NvEncoderAdapter {
                 "VideoToolbox session switch request is not supported by NVIDIA backend"
                     .to_string(),
             )),
+            SessionSwitchRequest::Resize { dims } => {
+                self.apply_resize(dims.width.get() as usize, dims.height.get() as usize)
+            }
+        }
+    }
+
+    fn invalidate_reference(&mut self, pts_90k: i64) -> Result<(), BackendError> {
+        if self
+            .pending_frames
+            .iter()
+            .any(|frame| frame.pts_90k == Some(pts_90k))
+        {
+            return Err(BackendError::InvalidInput(format!(
+                "frame pts={pts_90k} has not been encoded yet"
+            )));
         }
+        let session = self.active_session.as_mut().ok_or_else(|| {
+            BackendError::InvalidInput(
+                "no active encode session to invalidate references in".to_string(),
+            )
+        })?;
+        session.invalidate_reference(pts_90k)
+    }
+
+    // Each flush ends with an NVENC drain, so batches span several in-flight windows to keep the
+    // output pool busy for most of the batch.
+    fn pipeline_depth(&self) -> usize {
+        self.max_in_flight_outputs.saturating_mul(4)
+    }
+
+    fn buffer_lifetime_mode(&self) -> Option<NvBufferLifetimeMode> {
+        Some(
+            self.active_session
+                .as_ref()
+                .map_or(self.buffer_lifetime_mode, |session| {
+                    session.buffer_lifetime_mode
+                }),
+        )
+    }
+
+    fn induced_latency(&self) -> Option<EncodeLatency> {
+        self.active_session.as_ref().map(|session| session.latency)
+    }
+
+    // Per-frame safe sessions allocate their buffers per flush instead of keeping a pool.
+    fn surface_pool(&self) -> Option<SurfacePoolSizes> {
+        self.active_session
+            .as_ref()
+            .filter(|session| {
+                session.buffer_lifetime_mode == NvBufferLifetimeMode::ReusablePoolUnsafe
+            })
+            .map(|session| session.pool)
+    }
+
+    fn device_memory(&self) -> Option<DeviceMemory> {
+        cuda_device_memory(self.cuda_ctx.as_deref()?)
+    }
+
+    // The NVENC pool stays: it is sized for the GOP structure, which a hot reconfigure may
+    // deepen without reallocating.
+    fn trim_pools(&mut self) {
+        device_cache::release_idle_cuda_contexts();
+    }
+
+    // Teardown order matters: pooled input/output buffers are registered with the NVENC
+    // session, and the session lives inside the CUDA context. Field drop order would release
+    // the context first, so everything is dismantled explicitly here (and from Drop).
+    fn close(&mut self) -> Result<(), BackendError> {
+        self.pipeline_scheduler = None;
+        self.pending_frames.clear();
+        let synced = match &self.cuda_ctx {
+            Some(ctx) => ctx
+                .bind_to_thread()
+                .and_then(|()| ctx.synchronize())
+                .map_err(|err| {
+                    BackendError::DeviceLost(format!("failed to synchronize CUDA context: {err}"))
+                }),
+            None => Ok(()),
+        };
+        if let Some(mut session) = self.active_session.take() {
+            session.reusable_inputs.clear();
+            session.reusable_outputs.clear();
+            drop(session);
+        }
+        self.cuda_ctx = None;
+        synced
+    }
+
+    fn set_diagnostics(&mut self, diagnostics: Diagnostics) {
+        self.diagnostics = diagnostics;
     }
 
     fn pipeline_generation_hint(&self) -> Option<u64> {
@@ -963,17 +1370,14 @@ impl NvEncoderAdapter {
             codec,
             max_in_flight,
             report_metrics,
+            diagnostics,
         } = options;
         let mut packets = Vec::with_capacity(pending_frames.len());
         let mut timing = StageTiming::default();
         let mut copy_stats = CopyStats::default();
         let mut queue_depth_samples = SampleStats::default();
         let mut output_jitter_samples = SampleStats::default();
-        let expected_frame_ms = if fps > 0 {
-            1_000.0 / fps as f64
-        } else {
-            33.333
-        };
+        let expected_frame_ms = fps.frame_duration_ms();
         let mut last_output_pts_90k = None;
         let pool_size = max_in_flight.clamp(1, 64).max(3);
         let mut free_pairs = VecDeque::with_capacity(pool_size);
@@ -990,8 +1394,13 @@ impl NvEncoderAdapter {
                 .get_ref()
                 .create_output_bitstream()
                 .map_err(map_encode_error)?;
-            free_pairs.push_back(SafeBufferPair { input, output });
+            free_pairs.push_back(SafeBufferPair {
+                input,
+                input_frame: None,
+                output,
+            });
         }
+        let mut input_rows = InputRowTracker::default();
         let mut pending_outputs = VecDeque::new();
 
         for (index, frame) in pending_frames.iter().enumerate() {
@@ -1021,10 +1430,10 @@ impl NvEncoderAdapter {
             })?;
 
             let synth_start = Instant::now();
-            let argb = frame
-                .argb
-                .clone()
-                .unwrap_or_else(|| make_synthetic_argb(width, height, index));
+            let argb = match frame.argb.as_deref() {
+                Some(argb) => Cow::Borrowed(argb),
+                None => Cow::Owned(make_synthetic_argb(width, height, index)),
+            };
             if argb.len() != width.saturating_mul(height).saturating_mul(4) {
                 return Err(BackendError::InvalidInput(format!(
                     "argb payload size mismatch: expected {}, got {}",
@@ -1033,19 +1442,28 @@ impl NvEncoderAdapter {
                 )));
             }
             timing.synth += synth_start.elapsed();
-            copy_stats.input_upload_bytes = copy_stats
-                .input_upload_bytes
-                .saturating_add(argb.len() as u64);
-            copy_stats.input_upload_frames = copy_stats.input_upload_frames.saturating_add(1);
-
-            let upload_start = Instant::now();
-            {
-                let mut lock = pair.input.lock().map_err(map_encode_error)?;
-                unsafe {
-                    lock.write(&argb);
+            let (input_frame, rows) = input_rows.next(
+                pair.input_frame.take(),
+                frame.argb.as_ref().and(frame.dirty_rects.as_deref()),
+                width,
+                height,
+            );
+            let upload = &argb[..rows * width * 4];
+            if !upload.is_empty() {
+                copy_stats.input_upload_bytes = copy_stats
+                    .input_upload_bytes
+                    .saturating_add(upload.len() as u64);
+                copy_stats.input_upload_frames = copy_stats.input_upload_frames.saturating_add(1);
+                let upload_start = Instant::now();
+                {
+                    let mut lock = pair.input.lock().map_err(map_encode_error)?;
+                    unsafe {
+                        lock.write(upload);
+                    }
                 }
+                timing.upload += upload_start.elapsed();
             }
-            timing.upload += upload_start.elapsed();
+            pair.input_frame = Some(input_frame);
 
             let input_timestamp = frame
                 .pts_90k
@@ -1103,25 +1521,28 @@ impl NvEncoderAdapter {
         }
 
         if report_metrics {
-            eprintln!(
-                "[nv.encode.safe] frames={}, packets={}, synth_ms={:.3}, upload_ms={:.3}, submit_ms={:.3}, reap_ms={:.3}, lock_ms={:.3}, queue_p95={:.3}, queue_p99={:.3}, jitter_ms_mean={:.3}, jitter_ms_p95={:.3}, jitter_ms_p99={:.3}, input_copy_bytes={}, input_copy_frames={}, output_copy_bytes={}, output_copy_packets={}",
-                pending_frames.len(),
-                packets.len(),
-                timing.synth.as_secs_f64() * 1_000.0,
-                timing.upload.as_secs_f64() * 1_000.0,
-                timing.sdk.as_secs_f64() * 1_000.0,
-                timing.reap.as_secs_f64() * 1_000.0,
-                timing.output_lock.as_secs_f64() * 1_000.0,
-                queue_depth_samples.p95(),
-                queue_depth_samples.p99(),
-                output_jitter_samples.mean(),
-                output_jitter_samples.p95(),
-                output_jitter_samples.p99(),
-                copy_stats.input_upload_bytes,
-                copy_stats.input_upload_frames,
-                copy_stats.output_copy_bytes,
-                copy_stats.output_copy_packets
-            );
+            diagnostics.emit(DiagnosticEvent::Metrics {
+                scope: "nv.encode.safe",
+                detail: format!(
+                    "frames={}, packets={}, synth_ms={:.3}, upload_ms={:.3}, submit_ms={:.3}, reap_ms={:.3}, lock_ms={:.3}, queue_p95={:.3}, queue_p99={:.3}, jitter_ms_mean={:.3}, jitter_ms_p95={:.3}, jitter_ms_p99={:.3}, input_copy_bytes={}, input_copy_frames={}, output_copy_bytes={}, output_copy_packets={}",
+                    pending_frames.len(),
+                    packets.len(),
+                    timing.synth.as_secs_f64() * 1_000.0,
+                    timing.upload.as_secs_f64() * 1_000.0,
+                    timing.sdk.as_secs_f64() * 1_000.0,
+                    timing.reap.as_secs_f64() * 1_000.0,
+                    timing.output_lock.as_secs_f64() * 1_000.0,
+                    queue_depth_samples.p95(),
+                    queue_depth_samples.p99(),
+                    output_jitter_samples.mean(),
+                    output_jitter_samples.p95(),
+                    output_jitter_samples.p99(),
+                    copy_stats.input_upload_bytes,
+                    copy_stats.input_upload_frames,
+                    copy_stats.output_copy_bytes,
+                    copy_stats.output_copy_packets
+                ),
+            });
         }
 
         Ok(packets)
@@ -1165,12 +1586,69 @@ impl NvEncoderAdapter {
         }
     }
 
+    // NVENC resizes in place (encoder reset plus IDR) down to the size the session was created
+    // with. A larger size, or a reconfigure the driver rejects, recreates the session on the
+    // next flush instead; ensure_session sees the size mismatch.
+    fn apply_resize(&mut self, width: usize, height: usize) -> Result<(), BackendError> {
+        if !self.pending_frames.is_empty() {
+            return Err(BackendError::InvalidInput(
+                "frames of the previous size must be flushed before a resize".to_string(),
+            ));
+        }
+        let target_generation = self.next_generation;
+        self.next_generation = self.next_generation.saturating_add(1);
+        self.config_generation = target_generation;
+        self.force_next_keyframe = true;
+        let Some(session) = self.active_session.as_mut() else {
+            return Ok(());
+        };
+        let in_place = width <= session.max_width
+            && height <= session.max_height
+            && session
+                .reconfigure(
+                    self.codec,
+                    self.fps,
+                    self.gop_length,
+                    self.frame_interval_p,
+                    &self.tuning,
+                    true,
+                    Some((width, height)),
+                )
+                .is_ok();
+        if !in_place {
+            self.session_reconfigure_pending = true;
+            return Ok(());
+        }
+        session.generation = target_generation;
+        self.active_generation = target_generation;
+        self.diagnostics.emit(DiagnosticEvent::Reconfigured {
+            generation: target_generation,
+            force_idr: true,
+        });
+        Ok(())
+    }
+
     fn apply_pending_switch_if_needed(&mut self) -> Result<(), BackendError> {
         let Some(pending) = self.pending_switch.take() else {
             return Ok(());
         };
         self.gop_length = pending.config.gop_length;
         self.frame_interval_p = pending.config.frame_interval_p;
+        // Rate targets reach NVENC through the reconfigure below, without an IDR of their own.
+        if let Some(bitrate) = pending.config.average_bitrate_bps {
+            self.tuning.average_bitrate = Some(bitrate.clamp(1, u64::from(u32::MAX)) as u32);
+        }
+        if let Some(qp) = pending.config.max_qp {
+            self.tuning.max_qp = Some(qp);
+        }
+        // Buffer pools are allocated with the session, so a lifetime change always rebuilds it.
+        let lifetime_changed = pending
+            .config
+            .buffer_lifetime_mode
+            .is_some_and(|mode| mode != self.buffer_lifetime_mode);
+        if let Some(mode) = pending.config.buffer_lifetime_mode {
+            self.buffer_lifetime_mode = mode;
+        }
         self.config_generation = pending.target_generation;
         self.session_reconfigure_pending = true;
         if pending.config.force_idr_on_activate
@@ -1181,9 +1659,10 @@ impl NvEncoderAdapter {
 
         let force_idr = pending.config.force_idr_on_activate
             || matches!(pending.mode, SessionSwitchMode::OnNextKeyframe);
-        if self
-            .try_reconfigure_active_session(force_idr, pending.target_generation)
-            .is_err()
+        if lifetime_changed
+            || self
+                .try_reconfigure_active_session(force_idr, pending.target_generation)
+                .is_err()
         {
             self.session_reconfigure_pending = true;
             if matches!(pending.mode, SessionSwitchMode::DrainThenSwap)
@@ -1203,14 +1682,15 @@ impl NvEncoderAdapter {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 struct SafeFlushOptions {
     width: usize,
     height: usize,
-    fps: i32,
+    fps: FrameRate,
     codec: Codec,
     max_in_flight: usize,
     report_metrics: bool,
+    diagnostics: Diagnostics,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -1222,11 +1702,17 @@ struct NvEncodeSession {
     session: Pin<Box<nvidia_video_codec_sdk::Session>>,
     width: usize,
     height: usize,
+    // Initialization size, the upper bound for resizing in place.
+    max_width: usize,
+    max_height: usize,
     generation: u64,
     buffer_lifetime_mode: NvBufferLifetimeMode,
     input_layout: NvInputLayout,
-    reusable_inputs: VecDeque<nvidia_video_codec_sdk::Buffer<'static>>,
+    pool: SurfacePoolSizes,
+    latency: EncodeLatency,
+    reusable_inputs: VecDeque<(nvidia_video_codec_sdk::Buffer<'static>, Option<u64>)>,
     reusable_outputs: VecDeque<nvidia_video_codec_sdk::Bitstream<'static>>,
+    input_rows: InputRowTracker,
 }
 
 impl NvEncodeSession {
@@ -1237,14 +1723,16 @@ impl NvEncodeSession {
         generation: u64,
         buffer_lifetime_mode: NvBufferLifetimeMode,
         input_layout: NvInputLayout,
-        pool_size: usize,
+        pool: SurfacePoolSizes,
+        latency: EncodeLatency,
     ) -> Result<Self, BackendError> {
         let session = Box::pin(session);
-        let mut reusable_inputs = VecDeque::with_capacity(pool_size.max(3));
-        let mut reusable_outputs = VecDeque::with_capacity(pool_size.max(3));
+        let pool_size = pool.surfaces as usize;
+        let mut reusable_inputs = VecDeque::with_capacity(pool_size);
+        let mut reusable_outputs = VecDeque::with_capacity(pool_size);
         if buffer_lifetime_mode == NvBufferLifetimeMode::ReusablePoolUnsafe {
             let session_ref: &nvidia_video_codec_sdk::Session = Pin::as_ref(&session).get_ref();
-            for _ in 0..pool_size.max(3) {
+            for _ in 0..pool_size {
                 let input = session_ref
                     .create_input_buffer()
                     .map_err(map_encode_error)?;
@@ -1253,12 +1741,15 @@ impl NvEncodeSession {
                     .map_err(map_encode_error)?;
                 // Safety: session is pinned and outlives these buffers. Drop explicitly clears
                 // buffers before session is dropped.
-                reusable_inputs.push_back(unsafe {
-                    mem::transmute::<
-                        nvidia_video_codec_sdk::Buffer<'_>,
-                        nvidia_video_codec_sdk::Buffer<'static>,
-                    >(input)
-                });
+                reusable_inputs.push_back((
+                    unsafe {
+                        mem::transmute::<
+                            nvidia_video_codec_sdk::Buffer<'_>,
+                            nvidia_video_codec_sdk::Buffer<'static>,
+                        >(input)
+                    },
+                    None,
+                ));
                 reusable_outputs.push_back(unsafe {
                     mem::transmute::<
                         nvidia_video_codec_sdk::Bitstream<'_>,
@@ -1271,26 +1762,36 @@ impl NvEncodeSession {
             session,
             width,
             height,
+            max_width: width,
+            max_height: height,
             generation,
             buffer_lifetime_mode,
             input_layout,
+            pool,
+            latency,
             reusable_inputs,
             reusable_outputs,
+            input_rows: InputRowTracker::default(),
         })
     }
 
     fn checkout_pair(&mut self) -> Result<BufferPair, BackendError> {
-        let input = self.reusable_inputs.pop_front().ok_or_else(|| {
+        let (input, input_frame) = self.reusable_inputs.pop_front().ok_or_else(|| {
             BackendError::TemporaryBackpressure("no reusable NVENC input buffer".to_string())
         })?;
         let output = self.reusable_outputs.pop_front().ok_or_else(|| {
             BackendError::TemporaryBackpressure("no reusable NVENC output bitstream".to_string())
         })?;
-        Ok(BufferPair { input, output })
+        Ok(BufferPair {
+            input,
+            input_frame,
+            output,
+        })
     }
 
     fn checkin_pair(&mut self, pair: BufferPair) {
-        self.reusable_inputs.push_back(pair.input);
+        self.reusable_inputs
+            .push_back((pair.input, pair.input_frame));
         self.reusable_outputs.push_back(pair.output);
     }
 
@@ -1301,12 +1802,15 @@ impl NvEncodeSession {
     fn reconfigure(
         &mut self,
         codec: Codec,
-        fps: i32,
+        fps: FrameRate,
         gop_length: Option<u32>,
         frame_interval_p: Option<i32>,
+        tuning: &NvEncodeTuning,
         force_idr: bool,
+        resize: Option<(usize, usize)>,
     ) -> Result<(), BackendError> {
-        let encode_guid = to_encode_guid(codec);
+        let (width, height) = resize.unwrap_or((self.width, self.height));
+        let encode_guid = to_encode_guid(codec)?;
         let preset_guid = nvidia_video_codec_sdk::sys::nvEncodeAPI::NV_ENC_PRESET_P1_GUID;
         let tuning_info =
             nvidia_video_codec_sdk::sys::nvEncodeAPI::NV_ENC_TUNING_INFO::NV_ENC_TUNING_INFO_ULTRA_LOW_LATENCY;
@@ -1321,28 +1825,48 @@ impl NvEncodeSession {
         if let Some(frame_interval_p) = frame_interval_p {
             preset_config.presetCfg.frameIntervalP = frame_interval_p;
         }
+        tuning.apply(&mut preset_config.presetCfg);
 
-        let mut init_params =
-            EncoderInitParams::new(encode_guid, self.width as u32, self.height as u32);
+        let mut init_params = EncoderInitParams::new(encode_guid, width as u32, height as u32);
         init_params
             .preset_guid(preset_guid)
             .tuning_info(tuning_info)
             .display_aspect_ratio(16, 9)
-            .framerate(fps.max(1) as u32, 1)
+            .framerate(fps.normalized().num, fps.normalized().den)
             .enable_picture_type_decision()
             .encode_config(&mut preset_config.presetCfg);
+        let latency = structural_latency(&tuning.customize(&mut init_params));
 
         self.session
             .as_mut()
             .get_mut()
             .reconfigure(
                 ReconfigureParams::new(init_params)
-                    .reset_encoder(false)
+                    .reset_encoder(resize.is_some())
                     .force_idr(force_idr),
             )
             .map_err(map_encode_error)?;
+        self.latency = latency;
+        self.width = width;
+        self.height = height;
         Ok(())
     }
+
+    // NVENC identifies references by the input timestamp given to encode_picture, which is the
+    // frame's pts clamped to zero.
+    fn invalidate_reference(&mut self, pts_90k: i64) -> Result<(), BackendError> {
+        self.session
+            .as_mut()
+            .get_mut()
+            .invalidate_ref_frames(pts_90k.max(0) as u64)
+            .map_err(map_encode_error)
+    }
+}
+
+impl Drop for NvEncoderAdapter {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
 }
 
 impl Drop for NvEncodeSession {
@@ -1354,9 +1878,133 @@ impl Drop for NvEncodeSession {
 
 struct BufferPair {
     input: nvidia_video_codec_sdk::Buffer<'static>,
+    // Frame whose pixels the input buffer holds, see InputRowTracker.
+    input_frame: Option<u64>,
     output: nvidia_video_codec_sdk::Bitstream<'static>,
 }
 
+#[derive(Debug, Clone, Copy)]
+struct NvEncodeTuning {
+    #[cfg(feature = "unsafe-options")]
+    customize_init_params: Option<crate::NvInitParamsHook>,
+    content_hint: ContentHint,
+    spatial_aq: Option<bool>,
+    temporal_aq: Option<bool>,
+    aq_strength: Option<u8>,
+    lookahead_depth: Option<u16>,
+    average_bitrate: Option<u32>,
+    max_qp: Option<u32>,
+}
+
+impl NvEncodeTuning {
+    // The content hint only fills in options the caller left unset. Text and UI edges want
+    // spatial AQ without temporal AQ; flat shaded animation bands without temporal AQ.
+    // NVENC has no HEVC SCC tools (palette, intra block copy), so HEVC gets the same mapping.
+    fn hinted_aq(&self) -> (Option<bool>, Option<bool>) {
+        match self.content_hint {
+            ContentHint::Camera => (self.spatial_aq, self.temporal_aq),
+            ContentHint::Screen => (
+                self.spatial_aq.or(Some(true)),
+                self.temporal_aq.or(Some(false)),
+            ),
+            ContentHint::Animation => (
+                self.spatial_aq.or(Some(false)),
+                self.temporal_aq.or(Some(true)),
+            ),
+        }
+    }
+
+    // Constant-quality VBR keeps static screen frames almost free while sharp edges keep their
+    // QP. A caller bitrate means the caller owns rate control, so the preset mode stays.
+    fn constant_quality(&self) -> Option<u8> {
+        (self.content_hint == ContentHint::Screen && self.average_bitrate.is_none()).then_some(24)
+    }
+
+    fn apply(&self, config: &mut nvidia_video_codec_sdk::sys::nvEncodeAPI::NV_ENC_CONFIG) {
+        let rc = &mut config.rcParams;
+        if let Some(quality) = self.constant_quality() {
+            rc.rateControlMode =
+                nvidia_video_codec_sdk::sys::nvEncodeAPI::NV_ENC_PARAMS_RC_MODE::NV_ENC_PARAMS_RC_VBR;
+            rc.targetQuality = quality;
+            rc.targetQualityLSB = 0;
+        }
+        let (spatial_aq, temporal_aq) = self.hinted_aq();
+        if let Some(enabled) = spatial_aq {
+            rc.set_enableAQ(u32::from(enabled));
+        }
+        if let Some(enabled) = temporal_aq {
+            rc.set_enableTemporalAQ(u32::from(enabled));
+            if enabled {
+                rc.set_enableLookahead(1);
+                rc.lookaheadDepth = rc.lookaheadDepth.max(8);
+            }
+        }
+        if let Some(strength) = self.aq_strength {
+            // 0 lets the driver pick; 1..=15 is the explicit spatial AQ strength.
+            rc.set_aqStrength(u32::from(strength));
+        }
+        // An explicit depth wins over whatever the preset or content hint picked. Temporal AQ
+        // cannot run without lookahead, so depth 0 turns it off as well.
+        match self.lookahead_depth {
+            Some(0) => {
+                rc.set_enableLookahead(0);
+                rc.lookaheadDepth = 0;
+                rc.set_enableTemporalAQ(0);
+            }
+            Some(depth) => {
+                rc.set_enableLookahead(1);
+                rc.lookaheadDepth = depth;
+            }
+            None => {}
+        }
+        if let Some(bitrate) = self.average_bitrate {
+            rc.averageBitRate = bitrate;
+            rc.maxBitRate = bitrate;
+            rc.targetQuality = 0;
+        }
+        if let Some(qp) = self.max_qp {
+            rc.set_enableMaxQP(1);
+            rc.maxQP = nvidia_video_codec_sdk::sys::nvEncodeAPI::NV_ENC_QP {
+                qpInterP: qp,
+                qpInterB: qp,
+                qpIntra: qp,
+            };
+        }
+    }
+
+    // Hands the finished parameters to the raw hook, if any, and returns the encode config the
+    // session will actually run with, so latency and pool sizing see the hook's changes too.
+    fn customize(
+        &self,
+        init_params: &mut EncoderInitParams<'_>,
+    ) -> nvidia_video_codec_sdk::sys::nvEncodeAPI::NV_ENC_CONFIG {
+        let params = init_params.as_raw_mut();
+        // encode_config() pointed this at the preset config, which outlives init_params.
+        let config = unsafe { &mut *params.encodeConfig };
+        #[cfg(feature = "unsafe-options")]
+        if let Some(hook) = self.customize_init_params {
+            (hook.apply)(params, config);
+        }
+        *config
+    }
+}
+
+const NV_MAX_LOOKAHEAD_DEPTH: u32 = 32;
+
+fn structural_latency(
+    config: &nvidia_video_codec_sdk::sys::nvEncodeAPI::NV_ENC_CONFIG,
+) -> EncodeLatency {
+    let lookahead_frames = if config.rcParams.enableLookahead() != 0 {
+        u32::from(config.rcParams.lookaheadDepth)
+    } else {
+        0
+    };
+    EncodeLatency {
+        lookahead_frames,
+        reorder_frames: u32::try_from(config.frameIntervalP.saturating_sub(1)).unwrap_or(0),
+    }
+}
+
 #[derive(Debug, Clone)]
 struct PendingSessionSwitch {
     config: NvidiaSessionConfig,
@@ -1372,6 +2020,7 @@ struct PendingOutput {
 
 struct SafeBufferPair<'a> {
     input: nvidia_video_codec_sdk::Buffer<'a>,
+    input_frame: Option<u64>,
     output: nvidia_video_codec_sdk::Bitstream<'a>,
 }
 
@@ -1430,17 +2079,21 @@ fn lock_safe_output_packet(
     ))
 }
 
-fn to_decode_codec(codec: Codec) -> DecodeCodec {
+fn to_decode_codec(codec: Codec) -> cudaVideoCodec {
     match codec {
-        Codec::H264 => DecodeCodec::H264,
-        Codec::Hevc => DecodeCodec::H265,
+        Codec::H264 => cudaVideoCodec::cudaVideoCodec_H264,
+        Codec::Hevc => cudaVideoCodec::cudaVideoCodec_HEVC,
+        Codec::Mjpeg => cudaVideoCodec::cudaVideoCodec_JPEG,
     }
 }
 
-fn to_encode_guid(codec: Codec) -> nvidia_video_codec_sdk::sys::nvEncodeAPI::GUID {
+fn to_encode_guid(
+    codec: Codec,
+) -> Result<nvidia_video_codec_sdk::sys::nvEncodeAPI::GUID, BackendError> {
     match codec {
-        Codec::H264 => nvidia_video_codec_sdk::sys::nvEncodeAPI::NV_ENC_CODEC_H264_GUID,
-        Codec::Hevc => nvidia_video_codec_sdk::sys::nvEncodeAPI::NV_ENC_CODEC_HEVC_GUID,
+        Codec::H264 => Ok(nvidia_video_codec_sdk::sys::nvEncodeAPI::NV_ENC_CODEC_H264_GUID),
+        Codec::Hevc => Ok(nvidia_video_codec_sdk::sys::nvEncodeAPI::NV_ENC_CODEC_HEVC_GUID),
+        Codec::Mjpeg => Err(BackendError::UnsupportedCodec(codec)),
     }
 }
 
@@ -1457,10 +2110,22 @@ fn map_encode_error(error: nvidia_video_codec_sdk::EncodeError) -> BackendError
         ErrorKind::InvalidParam | ErrorKind::InvalidCall => {
             BackendError::InvalidInput(error.to_string())
         }
+        // Spelled out so memory pressure monitoring recognises it.
+        ErrorKind::OutOfMemory => BackendError::Backend(format!("out of memory: {error}")),
         _ => BackendError::Backend(error.to_string()),
     }
 }
 
+// cuMemGetInfo for the device behind `ctx`.
+fn cuda_device_memory(ctx: &CudaContext) -> Option<DeviceMemory> {
+    ctx.bind_to_thread().ok()?;
+    let (free, total) = cudarc::driver::result::mem_get_info().ok()?;
+    Some(DeviceMemory {
+        free_bytes: free as u64,
+        total_bytes: total as u64,
+    })
+}
+
 fn update_jitter_samples(
     jitter_samples: &mut SampleStats,
     last_pts_90k: &mut Option<i64>,
@@ -1477,6 +2142,71 @@ fn update_jitter_samples(
     *last_pts_90k = Some(current);
 }
 
+// Rows of the reused NVENC input buffers that are stale for the next frame. Buffers rotate, so
+// the one handed out for a frame last held one a few frames back and only the rows some frame
+// since then marked dirty need rewriting. The binding's lock writes from the top of the surface,
+// so an upload covers every row down to the lowest stale one.
+#[derive(Debug, Default)]
+struct InputRowTracker {
+    dims: (usize, usize),
+    next_frame: u64,
+    // Buffers holding an earlier frame were written at other dimensions.
+    valid_from: u64,
+    // (frame, rows from the top it changed), oldest first.
+    changed: VecDeque<(u64, usize)>,
+}
+
+impl InputRowTracker {
+    const HISTORY: usize = 64;
+
+    // Numbers the next frame and returns the number with the rows to write into a buffer that
+    // holds frame `held`; None dirty rects mean the whole frame changed.
+    fn next(
+        &mut self,
+        held: Option<u64>,
+        dirty_rects: Option<&[DirtyRect]>,
+        width: usize,
+        height: usize,
+    ) -> (u64, usize) {
+        let frame = self.next_frame;
+        self.next_frame += 1;
+        if self.dims != (width, height) {
+            self.dims = (width, height);
+            self.valid_from = frame;
+            self.changed.clear();
+        }
+        let rows = dirty_rects.map_or(height, |rects| {
+            rects
+                .iter()
+                .map(|rect| (rect.y as usize + rect.height as usize).min(height))
+                .max()
+                .unwrap_or(0)
+        });
+        if self.changed.len() == Self::HISTORY {
+            self.changed.pop_front();
+        }
+        self.changed.push_back((frame, rows));
+        let upload = match held {
+            Some(held)
+                if held >= self.valid_from
+                    && self
+                        .changed
+                        .front()
+                        .is_some_and(|&(oldest, _)| oldest <= held + 1) =>
+            {
+                self.changed
+                    .iter()
+                    .filter(|&&(changed, _)| changed > held)
+                    .map(|&(_, rows)| rows)
+                    .max()
+                    .unwrap_or(0)
+            }
+            _ => height,
+        };
+        (frame, upload)
+    }
+}
+
 fn make_synthetic_argb(width: usize, height: usize, frame_index: usize) -> Vec<u8> {
     let mut buffer = vec![0_u8; width.saturating_mul(height).saturating_mul(4)];
     for y in 0..height {
@@ -1499,8 +2229,12 @@ mod tests {
 
     #[test]
     fn switch_on_next_keyframe_stays_pending_when_frames_are_buffered() {
-        let mut adapter =
-            NvEncoderAdapter::with_config(Codec::H264, 30, true, BackendEncoderOptions::Default);
+        let mut adapter = NvEncoderAdapter::with_config(
+            Codec::H264,
+            30.into(),
+            true,
+            BackendEncoderOptions::Default,
+        );
         adapter.pending_frames.push(Frame {
             width: 640,
             height: 360,
@@ -1510,8 +2244,13 @@ mod tests {
             color_primaries: None,
             transfer_function: None,
             ycbcr_matrix: None,
+            planes: None,
             argb: None,
             force_keyframe: false,
+            dirty_rects: None,
+            luma_histogram: None,
+            view: None,
+            stereo_right: None,
         });
 
         adapter
@@ -1520,6 +2259,9 @@ mod tests {
                     gop_length: Some(60),
                     frame_interval_p: Some(1),
                     force_idr_on_activate: false,
+                    buffer_lifetime_mode: None,
+                    average_bitrate_bps: None,
+                    max_qp: None,
                 },
                 SessionSwitchMode::OnNextKeyframe,
             )
@@ -1529,16 +2271,84 @@ mod tests {
         assert!(adapter.force_next_keyframe);
     }
 
+    #[test]
+    fn screen_hint_leaves_explicit_options_alone() {
+        let screen = |options: crate::NvidiaEncoderOptions| {
+            NvEncoderAdapter::with_config(
+                Codec::Hevc,
+                30.into(),
+                true,
+                BackendEncoderOptions::Nvidia(crate::NvidiaEncoderOptions {
+                    content_hint: Some(ContentHint::Screen),
+                    ..options
+                }),
+            )
+            .tuning
+        };
+
+        let defaults = screen(crate::NvidiaEncoderOptions::default());
+        assert_eq!(defaults.hinted_aq(), (Some(true), Some(false)));
+        assert_eq!(defaults.constant_quality(), Some(24));
+
+        let explicit = screen(crate::NvidiaEncoderOptions {
+            spatial_aq: Some(false),
+            average_bitrate_bps: Some(4_000_000),
+            ..crate::NvidiaEncoderOptions::default()
+        });
+        assert_eq!(explicit.hinted_aq(), (Some(false), Some(false)));
+        assert_eq!(explicit.constant_quality(), None);
+    }
+
+    #[test]
+    fn switch_can_change_buffer_lifetime_mode() {
+        let mut adapter = NvEncoderAdapter::with_config(
+            Codec::H264,
+            30.into(),
+            true,
+            BackendEncoderOptions::Default,
+        );
+        assert_eq!(
+            adapter.buffer_lifetime_mode(),
+            Some(NvBufferLifetimeMode::ReusablePoolUnsafe)
+        );
+        adapter
+            .apply_nvidia_session_switch(
+                NvidiaSessionConfig {
+                    gop_length: None,
+                    frame_interval_p: None,
+                    force_idr_on_activate: false,
+                    buffer_lifetime_mode: Some(NvBufferLifetimeMode::PerFrameSafe),
+                    average_bitrate_bps: None,
+                    max_qp: None,
+                },
+                SessionSwitchMode::Immediate,
+            )
+            .unwrap();
+
+        assert_eq!(
+            adapter.buffer_lifetime_mode(),
+            Some(NvBufferLifetimeMode::PerFrameSafe)
+        );
+        assert!(adapter.session_reconfigure_pending);
+    }
+
     #[test]
     fn switch_immediate_updates_config_even_without_active_session() {
-        let mut adapter =
-            NvEncoderAdapter::with_config(Codec::H264, 30, true, BackendEncoderOptions::Default);
+        let mut adapter = NvEncoderAdapter::with_config(
+            Codec::H264,
+            30.into(),
+            true,
+            BackendEncoderOptions::Default,
+        );
         adapter
             .apply_nvidia_session_switch(
                 NvidiaSessionConfig {
                     gop_length: Some(48),
                     frame_interval_p: Some(1),
                     force_idr_on_activate: true,
+                    buffer_lifetime_mode: None,
+                    average_bitrate_bps: None,
+                    max_qp: None,
                 },
                 SessionSwitchMode::Immediate,
             )
@@ -1552,14 +2362,21 @@ mod tests {
     #[test]
     fn pending_switch_generation_syncs_to_pipeline_scheduler() {
         let scheduler = PipelineScheduler::new(NvidiaTransformAdapter::new(1, 4), 4);
-        let mut adapter =
-            NvEncoderAdapter::with_config(Codec::H264, 30, true, BackendEncoderOptions::Default);
+        let mut adapter = NvEncoderAdapter::with_config(
+            Codec::H264,
+            30.into(),
+            true,
+            BackendEncoderOptions::Default,
+        );
         adapter
             .apply_nvidia_session_switch(
                 NvidiaSessionConfig {
                     gop_length: Some(48),
                     frame_interval_p: Some(1),
                     force_idr_on_activate: false,
+                    buffer_lifetime_mode: None,
+                    average_bitrate_bps: None,
+                    max_qp: None,
                 },
                 SessionSwitchMode::OnNextKeyframe,
             )
@@ -1570,8 +2387,12 @@ mod tests {
 
     #[test]
     fn push_frame_succeeds_with_integrated_pipeline_scheduler() {
-        let mut adapter =
-            NvEncoderAdapter::with_config(Codec::H264, 30, true, BackendEncoderOptions::Default);
+        let mut adapter = NvEncoderAdapter::with_config(
+            Codec::H264,
+            30.into(),
+            true,
+            BackendEncoderOptions::Default,
+        );
         let scheduler = PipelineScheduler::new(NvidiaTransformAdapter::new(1, 8), 8);
         scheduler.set_generation(999);
         adapter.pipeline_scheduler = Some(scheduler);
@@ -1586,8 +2407,13 @@ mod tests {
                 color_primaries: None,
                 transfer_function: None,
                 ycbcr_matrix: None,
+                planes: None,
                 argb: None,
                 force_keyframe: false,
+                dirty_rects: None,
+                luma_histogram: None,
+                view: None,
+                stereo_right: None,
             })
             .unwrap();
 
@@ -1600,4 +2426,117 @@ mod tests {
             Some(adapter.configured_generation())
         );
     }
+
+    struct CountingSoftwareDecoder;
+
+    impl SoftwareDecoder for CountingSoftwareDecoder {
+        fn decode(
+            &mut self,
+            _access_unit: &[u8],
+            pts_90k: Option<Timestamp90k>,
+        ) -> Result<Vec<DecodedFrame>, BackendError> {
+            Ok(vec![DecodedFrame::Metadata {
+                dims: None,
+                pts_90k,
+                pixel_format: None,
+                decode_info_flags: None,
+                color: None,
+                planes: None,
+                histogram: None,
+                view: None,
+            }])
+        }
+
+        fn flush(&mut self) -> Result<Vec<DecodedFrame>, BackendError> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn decode_tuning_follows_nvidia_options() {
+        let adapter = NvDecoderAdapter::new(DecoderConfig::new(Codec::H264, 30, false));
+        assert_eq!(adapter.tuning, NvDecodeTuning::default());
+        assert!(!adapter.slice_fast_start);
+
+        let mut config = DecoderConfig::new(Codec::H264, 30, false);
+        config.backend_options = BackendDecoderOptions::Nvidia(crate::NvidiaDecoderOptions {
+            decode_surfaces: Some(12),
+            output_surfaces: Some(4),
+            low_latency: Some(false),
+            histogram: Some(true),
+            slice_fast_start: Some(true),
+            ..Default::default()
+        });
+        let adapter = NvDecoderAdapter::new(config);
+        assert!(adapter.slice_fast_start);
+        assert_eq!(
+            adapter.tuning,
+            NvDecodeTuning {
+                decode_surfaces: Some(12),
+                output_surfaces: Some(4),
+                in_flight: 1,
+                low_latency: false,
+                histogram: true,
+                output_scale: None,
+            }
+        );
+    }
+
+    #[test]
+    fn force_software_requires_a_software_decoder() {
+        let mut config = DecoderConfig::new(Codec::H264, 30, false);
+        config.force_software = true;
+        let mut adapter = NvDecoderAdapter::new(config.clone());
+        assert!(matches!(
+            adapter.ensure_decoder(),
+            Err(BackendError::UnsupportedConfig(_))
+        ));
+
+        config.backend_options = BackendDecoderOptions::Nvidia(crate::NvidiaDecoderOptions {
+            report_metrics: Some(false),
+            software_decoder: Some(SoftwareDecoderFactory::new(|_| {
+                Ok(Box::new(CountingSoftwareDecoder))
+            })),
+            ..Default::default()
+        });
+        let mut adapter = NvDecoderAdapter::new(config);
+        adapter
+            .push_bitstream_chunk(
+                &[
+                    0, 0, 0, 1, 0x67, 0x42, 0, 0x1e, //
+                    0, 0, 0, 1, 0x68, 0xce, 0x38, 0x80, //
+                    0, 0, 0, 1, 0x65, 0x88, 0x84,
+                ],
+                Some(0),
+            )
+            .unwrap();
+        let frames = adapter.flush().unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].pts_90k, Some(0));
+        assert!(adapter.decoder.is_none());
+    }
+
+    #[test]
+    fn input_rows_cover_every_frame_since_the_buffer_was_written() {
+        let rect = |y, height| DirtyRect {
+            x: 0,
+            y,
+            width: 4,
+            height,
+        };
+        let mut rows = InputRowTracker::default();
+        assert_eq!(rows.next(None, Some(&[rect(0, 2)]), 16, 32), (0, 32));
+        assert_eq!(rows.next(None, Some(&[]), 16, 32), (1, 32));
+        // Buffer 0 held frame 0; frames 1 and 2 changed nothing below row 6.
+        assert_eq!(
+            rows.next(Some(0), Some(&[rect(2, 4), rect(1, 1)]), 16, 32),
+            (2, 6)
+        );
+        assert_eq!(rows.next(Some(1), Some(&[rect(30, 8)]), 16, 32), (3, 32));
+        assert_eq!(rows.next(Some(3), Some(&[]), 16, 32), (4, 0));
+        assert_eq!(rows.next(Some(4), None, 16, 32), (5, 32));
+        // A new size invalidates what the buffers hold.
+        assert_eq!(rows.next(Some(5), Some(&[]), 16, 16), (6, 16));
+        assert_eq!(rows.next(Some(6), Some(&[]), 16, 16), (7, 0));
+    }
 }
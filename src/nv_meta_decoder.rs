@@ -1,16 +1,16 @@
 use std::collections::VecDeque;
-use std::ffi::{c_int, c_longlong, c_ulong, c_void};
+use std::ffi::{c_int, c_longlong, c_uint, c_ulong, c_ulonglong, c_void};
 use std::ptr;
 use std::sync::{Arc, Mutex};
 
 use cudarc::driver::CudaContext;
-use cudarc::driver::sys::CUresult;
-use nvidia_video_codec_sdk::DecodeCodec;
+use cudarc::driver::sys::{CUresult, cuMemcpyDtoH_v2};
 use nvidia_video_codec_sdk::sys::cuviddec::{
-    CUVIDDECODECAPS, CUVIDDECODECREATEINFO, CUVIDPICPARAMS, CUVIDRECONFIGUREDECODERINFO,
-    CUvideodecoder, cudaVideoChromaFormat, cudaVideoCodec, cudaVideoCreateFlags,
-    cudaVideoDeinterlaceMode, cudaVideoSurfaceFormat, cuvidCreateDecoder, cuvidDecodePicture,
-    cuvidDestroyDecoder, cuvidGetDecoderCaps, cuvidReconfigureDecoder,
+    CUVIDDECODECAPS, CUVIDDECODECREATEINFO, CUVIDGETDECODESTATUS, CUVIDPICPARAMS, CUVIDPROCPARAMS,
+    CUVIDRECONFIGUREDECODERINFO, CUvideodecoder, cudaVideoChromaFormat, cudaVideoCodec,
+    cudaVideoCreateFlags, cudaVideoDeinterlaceMode, cudaVideoSurfaceFormat, cuvidCreateDecoder,
+    cuvidDecodePicture, cuvidDecodeStatus, cuvidDestroyDecoder, cuvidGetDecodeStatus,
+    cuvidGetDecoderCaps, cuvidMapVideoFrame64, cuvidReconfigureDecoder, cuvidUnmapVideoFrame64,
 };
 use nvidia_video_codec_sdk::sys::nvcuvid::{
     CUVIDEOFORMAT, CUVIDPARSERDISPINFO, CUVIDPARSERPARAMS, CUVIDSOURCEDATAPACKET,
@@ -18,7 +18,37 @@ use nvidia_video_codec_sdk::sys::nvcuvid::{
     cuvidParseVideoData,
 };
 
-use crate::{BackendError, Frame};
+use crate::{
+    BackendError, BackendPixelFormat, DecodeInfoFlags, Frame, SurfacePoolSizes, surface_pool,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NvDecodeTuning {
+    // Unset counts are sized automatically once the sequence header gives the stream's size
+    // and reference frames; see surface_pool::nvdec_pool.
+    pub decode_surfaces: Option<u32>,
+    pub output_surfaces: Option<u32>,
+    // Pictures between parse and display beyond the DPB.
+    pub in_flight: u32,
+    pub low_latency: bool,
+    pub histogram: bool,
+    pub output_scale: Option<(u32, u32)>,
+}
+
+impl Default for NvDecodeTuning {
+    fn default() -> Self {
+        Self {
+            decode_surfaces: None,
+            output_surfaces: None,
+            in_flight: 0,
+            low_latency: true,
+            histogram: false,
+            output_scale: None,
+        }
+    }
+}
+
+const HISTOGRAM_BINS: usize = 256;
 
 #[derive(Debug)]
 pub struct NvMetaDecoder {
@@ -28,22 +58,30 @@ pub struct NvMetaDecoder {
 }
 
 impl NvMetaDecoder {
-    pub fn new(ctx: Arc<CudaContext>, codec: DecodeCodec) -> Result<Self, BackendError> {
+    pub fn new(
+        ctx: Arc<CudaContext>,
+        codec: cudaVideoCodec,
+        tuning: NvDecodeTuning,
+    ) -> Result<Self, BackendError> {
         ctx.bind_to_thread().map_err(map_cuda_error)?;
-        check_decoder_caps(codec)?;
+        let histogram_supported = check_decoder_caps(codec)?;
 
         let mut bridge = Box::new(MetaCallbackBridge {
             codec,
+            tuning: NvDecodeTuning {
+                histogram: tuning.histogram && histogram_supported,
+                ..tuning
+            },
             state: Mutex::new(MetaDecoderState::default()),
         });
         let bridge_ptr = ptr::from_mut(bridge.as_mut()).cast::<c_void>();
 
         let mut parser_params = CUVIDPARSERPARAMS {
-            CodecType: to_cuda_codec(codec),
+            CodecType: codec,
             ulMaxNumDecodeSurfaces: 1,
             ulClockRate: 90_000,
             ulErrorThreshold: 0,
-            ulMaxDisplayDelay: 0,
+            ulMaxDisplayDelay: if tuning.low_latency { 0 } else { 1 },
             pUserData: bridge_ptr,
             pfnSequenceCallback: Some(sequence_callback),
             pfnDecodePicture: Some(decode_callback),
@@ -68,6 +106,26 @@ impl NvMetaDecoder {
         &mut self,
         access_unit: &[u8],
         timestamp_90k: i64,
+    ) -> Result<Vec<Frame>, BackendError> {
+        self.push_packet(access_unit, timestamp_90k, self.bridge.tuning.low_latency)
+    }
+
+    // Part of a picture. With `end_of_picture` the parser decodes the picture right away;
+    // otherwise it waits for the next picture to start before decoding this one.
+    pub fn push_slices(
+        &mut self,
+        slices: &[u8],
+        timestamp_90k: i64,
+        end_of_picture: bool,
+    ) -> Result<Vec<Frame>, BackendError> {
+        self.push_packet(slices, timestamp_90k, end_of_picture)
+    }
+
+    fn push_packet(
+        &mut self,
+        access_unit: &[u8],
+        timestamp_90k: i64,
+        end_of_picture: bool,
     ) -> Result<Vec<Frame>, BackendError> {
         if access_unit.is_empty() {
             return Err(BackendError::InvalidInput(
@@ -80,8 +138,10 @@ impl NvMetaDecoder {
         let payload_size = c_ulong::try_from(access_unit.len()).map_err(|_| {
             BackendError::InvalidInput("access unit size does not fit into c_ulong".to_string())
         })?;
-        let flags = (CUvideopacketflags::CUVID_PKT_TIMESTAMP as c_ulong)
-            | (CUvideopacketflags::CUVID_PKT_ENDOFPICTURE as c_ulong);
+        let mut flags = CUvideopacketflags::CUVID_PKT_TIMESTAMP as c_ulong;
+        if end_of_picture {
+            flags |= CUvideopacketflags::CUVID_PKT_ENDOFPICTURE as c_ulong;
+        }
         let mut packet = CUVIDSOURCEDATAPACKET {
             flags,
             payload_size,
@@ -98,11 +158,23 @@ impl NvMetaDecoder {
     }
 
     pub fn flush(&mut self) -> Result<Vec<Frame>, BackendError> {
+        self.send_end_of_stream(true)
+    }
+
+    // ENDOFSTREAM without NOTIFY_EOS empties the parser's reorder queue but leaves the parser and
+    // decoder alive; the next segment resumes from its own parameter sets.
+    pub fn drain(&mut self) -> Result<Vec<Frame>, BackendError> {
+        self.send_end_of_stream(false)
+    }
+
+    fn send_end_of_stream(&mut self, notify_eos: bool) -> Result<Vec<Frame>, BackendError> {
         self.ctx.bind_to_thread().map_err(map_cuda_error)?;
         self.ensure_no_callback_error()?;
 
-        let flags = (CUvideopacketflags::CUVID_PKT_ENDOFSTREAM as c_ulong)
-            | (CUvideopacketflags::CUVID_PKT_NOTIFY_EOS as c_ulong);
+        let mut flags = CUvideopacketflags::CUVID_PKT_ENDOFSTREAM as c_ulong;
+        if notify_eos {
+            flags |= CUvideopacketflags::CUVID_PKT_NOTIFY_EOS as c_ulong;
+        }
         let mut packet = CUVIDSOURCEDATAPACKET {
             flags,
             payload_size: 0,
@@ -118,6 +190,15 @@ impl NvMetaDecoder {
         self.drain_display_queue()
     }
 
+    pub fn context(&self) -> &CudaContext {
+        &self.ctx
+    }
+
+    // None until the first sequence header has configured the decoder.
+    pub fn surface_counts(&self) -> Option<SurfacePoolSizes> {
+        lock_state(&self.bridge.state).surfaces
+    }
+
     fn ensure_no_callback_error(&self) -> Result<(), BackendError> {
         let state = lock_state(&self.bridge.state);
         match &state.sticky_error {
@@ -145,14 +226,21 @@ impl NvMetaDecoder {
             out.push(Frame {
                 width: width as usize,
                 height: height as usize,
-                pixel_format: None,
+                pixel_format: Some(BackendPixelFormat::from_nvdec_surface_format(
+                    cudaVideoSurfaceFormat::cudaVideoSurfaceFormat_NV12 as u32,
+                )),
                 pts_90k: Some(entry.timestamp),
-                decode_info_flags: None,
+                decode_info_flags: Some(entry.flags),
                 color_primaries: None,
                 transfer_function: None,
                 ycbcr_matrix: None,
+                planes: None,
                 argb: None,
                 force_keyframe: false,
+                dirty_rects: None,
+                luma_histogram: entry.histogram,
+                view: None,
+                stereo_right: None,
             });
         }
         self.ensure_no_callback_error()?;
@@ -160,12 +248,15 @@ impl NvMetaDecoder {
     }
 }
 
-impl Drop for NvMetaDecoder {
-    fn drop(&mut self) {
-        let _ = self.ctx.bind_to_thread();
+impl NvMetaDecoder {
+    // Destroys the parser and then the decoder while the context is still bound. Every step
+    // runs even if an earlier one fails; the first failure is returned.
+    pub fn close(&mut self) -> Result<(), BackendError> {
+        let mut result = self.ctx.bind_to_thread().map_err(map_cuda_error);
         if !self.parser.is_null() {
-            let _ = unsafe { cuvidDestroyVideoParser(self.parser) };
+            let status = unsafe { cuvidDestroyVideoParser(self.parser) };
             self.parser = ptr::null_mut();
+            result = result.and(check_nvdec(status, "cuvidDestroyVideoParser"));
         }
 
         let decoder = {
@@ -173,20 +264,31 @@ impl Drop for NvMetaDecoder {
             state.decoder.take()
         };
         if let Some(decoder) = decoder {
-            let _ = unsafe { cuvidDestroyDecoder(decoder) };
+            let status = unsafe { cuvidDestroyDecoder(decoder) };
+            result = result.and(check_nvdec(status, "cuvidDestroyDecoder"));
         }
+        result
+    }
+}
+
+impl Drop for NvMetaDecoder {
+    fn drop(&mut self) {
+        let _ = self.close();
     }
 }
 
 #[derive(Debug)]
 struct MetaCallbackBridge {
-    codec: DecodeCodec,
+    codec: cudaVideoCodec,
+    tuning: NvDecodeTuning,
     state: Mutex<MetaDecoderState>,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Default)]
 struct DisplayQueueEntry {
     timestamp: i64,
+    flags: DecodeInfoFlags,
+    histogram: Option<Box<[u32; HISTOGRAM_BINS]>>,
 }
 
 #[derive(Debug, Default)]
@@ -196,6 +298,7 @@ struct MetaDecoderState {
     display_queue: VecDeque<DisplayQueueEntry>,
     width: u32,
     height: u32,
+    surfaces: Option<SurfacePoolSizes>,
 }
 
 impl MetaDecoderState {
@@ -207,7 +310,8 @@ impl MetaDecoderState {
 
     fn configure_decoder(
         &mut self,
-        codec: DecodeCodec,
+        codec: cudaVideoCodec,
+        tuning: NvDecodeTuning,
         format: &CUVIDEOFORMAT,
     ) -> Result<c_int, String> {
         if format.bit_depth_luma_minus8 != 0 || format.bit_depth_chroma_minus8 != 0 {
@@ -220,10 +324,18 @@ impl MetaDecoderState {
             return Err("decoder reported zero dimensions".to_string());
         }
 
-        let num_surfaces = u32::from(format.min_num_decode_surfaces.max(1));
+        let pool = surface_pool::nvdec_pool(
+            format.coded_width,
+            format.coded_height,
+            u32::from(format.min_num_decode_surfaces),
+            tuning.in_flight,
+            (tuning.decode_surfaces, tuning.output_surfaces),
+        );
+        let (num_surfaces, output_surfaces) = (pool.surfaces, pool.output_surfaces);
         let rect = resolve_target_rect(format);
-        let target_width = rect.2.saturating_sub(rect.0) as u32;
-        let target_height = rect.3.saturating_sub(rect.1) as u32;
+        let (target_width, target_height) = resolve_target_size(rect, tuning.output_scale);
+        // The display area is scaled into the whole target surface.
+        let target_rect = (0, 0, target_width as i32, target_height as i32);
 
         if let Some(decoder) = self.decoder {
             let mut reconfigure = CUVIDRECONFIGUREDECODERINFO {
@@ -233,7 +345,7 @@ impl MetaDecoderState {
                 ulTargetHeight: target_height,
                 ulNumDecodeSurfaces: num_surfaces,
                 display_area: to_reconfigure_rect(rect),
-                target_rect: to_reconfigure_target_rect(rect),
+                target_rect: to_reconfigure_target_rect(target_rect),
                 ..Default::default()
             };
             check_nvdec(
@@ -246,7 +358,7 @@ impl MetaDecoderState {
                 ulWidth: format.coded_width as c_ulong,
                 ulHeight: format.coded_height as c_ulong,
                 ulNumDecodeSurfaces: num_surfaces as c_ulong,
-                CodecType: to_cuda_codec(codec),
+                CodecType: codec,
                 ChromaFormat: format.chroma_format,
                 ulCreationFlags: cudaVideoCreateFlags::cudaVideoCreate_PreferCUVID as c_ulong,
                 bitDepthMinus8: format.bit_depth_luma_minus8 as c_ulong,
@@ -258,10 +370,10 @@ impl MetaDecoderState {
                 DeinterlaceMode: cudaVideoDeinterlaceMode::cudaVideoDeinterlaceMode_Weave,
                 ulTargetWidth: target_width as c_ulong,
                 ulTargetHeight: target_height as c_ulong,
-                ulNumOutputSurfaces: 2,
+                ulNumOutputSurfaces: output_surfaces as c_ulong,
                 vidLock: ptr::null_mut(),
-                target_rect: to_create_target_rect(rect),
-                enableHistogram: 0,
+                target_rect: to_create_target_rect(target_rect),
+                enableHistogram: if tuning.histogram { 1 } else { 0 },
                 ..Default::default()
             };
             let mut decoder = ptr::null_mut();
@@ -275,6 +387,7 @@ impl MetaDecoderState {
 
         self.width = target_width;
         self.height = target_height;
+        self.surfaces = Some(pool);
         Ok(num_surfaces as c_int)
     }
 }
@@ -293,7 +406,7 @@ unsafe extern "C" fn sequence_callback(
     }
 
     let mut state = lock_state(&bridge.state);
-    let result = state.configure_decoder(bridge.codec, unsafe { &*format });
+    let result = state.configure_decoder(bridge.codec, bridge.tuning, unsafe { &*format });
     match result {
         Ok(surfaces) => surfaces,
         Err(message) => {
@@ -346,15 +459,103 @@ unsafe extern "C" fn display_callback(
     }
     let info = unsafe { &*display_info };
     let mut state = lock_state(&bridge.state);
+    let mut flags = DecodeInfoFlags::empty();
+    flags.set(
+        DecodeInfoFlags::REPEAT_FIRST_FIELD,
+        info.repeat_first_field != 0,
+    );
+    if let Some(decoder) = state.decoder {
+        let mut status = CUVIDGETDECODESTATUS::default();
+        let queried = unsafe { cuvidGetDecodeStatus(decoder, info.picture_index, &mut status) };
+        if queried.result().is_ok() {
+            flags.set(
+                DecodeInfoFlags::CORRUPTED,
+                matches!(
+                    status.decodeStatus,
+                    cuvidDecodeStatus::cuvidDecodeStatus_Error
+                        | cuvidDecodeStatus::cuvidDecodeStatus_Error_Concealed
+                ),
+            );
+        }
+    }
+    let histogram = match state.decoder {
+        Some(decoder) if bridge.tuning.histogram => match read_histogram(decoder, info) {
+            Ok(histogram) => Some(histogram),
+            Err(err) => {
+                state.set_error_once(err.to_string());
+                return 0;
+            }
+        },
+        _ => None,
+    };
     state.display_queue.push_back(DisplayQueueEntry {
         timestamp: info.timestamp,
+        flags,
+        histogram,
     });
     1
 }
 
-fn check_decoder_caps(codec: DecodeCodec) -> Result<(), BackendError> {
+// NVDEC fills the histogram while post-processing a picture for output, so the picture is
+// mapped (which waits for its decode) and unmapped again right after the counters are copied.
+// The frame data itself is never touched.
+fn read_histogram(
+    decoder: CUvideodecoder,
+    info: &CUVIDPARSERDISPINFO,
+) -> Result<Box<[u32; HISTOGRAM_BINS]>, BackendError> {
+    let mut histogram_dptr: c_ulonglong = 0;
+    let mut params = CUVIDPROCPARAMS {
+        progressive_frame: info.progressive_frame,
+        second_field: info.repeat_first_field + 1,
+        top_field_first: info.top_field_first,
+        unpaired_field: c_int::from(info.repeat_first_field < 0),
+        histogram_dptr: &mut histogram_dptr,
+        ..Default::default()
+    };
+    let mut frame_dptr: c_ulonglong = 0;
+    let mut pitch: c_uint = 0;
+    check_nvdec(
+        unsafe {
+            cuvidMapVideoFrame64(
+                decoder,
+                info.picture_index,
+                &mut frame_dptr,
+                &mut pitch,
+                &mut params,
+            )
+        },
+        "cuvidMapVideoFrame64",
+    )?;
+    let mut histogram = Box::new([0_u32; HISTOGRAM_BINS]);
+    let copied = if histogram_dptr == 0 {
+        Err(BackendError::Backend(
+            "NVDEC returned no histogram buffer".to_string(),
+        ))
+    } else {
+        check_nvdec(
+            unsafe {
+                cuMemcpyDtoH_v2(
+                    histogram.as_mut_ptr().cast(),
+                    histogram_dptr,
+                    size_of::<[u32; HISTOGRAM_BINS]>(),
+                )
+            },
+            "cuMemcpyDtoH(histogram)",
+        )
+    };
+    let unmapped = check_nvdec(
+        unsafe { cuvidUnmapVideoFrame64(decoder, frame_dptr) },
+        "cuvidUnmapVideoFrame64",
+    );
+    copied?;
+    unmapped?;
+    Ok(histogram)
+}
+
+// Returns whether the decoder can produce 256 32-bit histogram counters for 8-bit luma.
+fn check_decoder_caps(codec: cudaVideoCodec) -> Result<bool, BackendError> {
     let mut caps = CUVIDDECODECAPS {
-        eCodecType: to_cuda_codec(codec),
+        eCodecType: codec,
         eChromaFormat: cudaVideoChromaFormat::cudaVideoChromaFormat_420,
         nBitDepthMinus8: 0,
         ..Default::default()
@@ -374,7 +575,9 @@ fn check_decoder_caps(codec: DecodeCodec) -> Result<(), BackendError> {
             "NV12 output is not supported by NVDEC".to_string(),
         ));
     }
-    Ok(())
+    Ok(caps.bIsHistogramSupported != 0
+        && caps.nCounterBitDepth == 32
+        && usize::from(caps.nMaxHistogramBins) >= HISTOGRAM_BINS)
 }
 
 fn check_nvdec(status: CUresult, operation: &'static str) -> Result<(), BackendError> {
@@ -387,14 +590,6 @@ fn map_cuda_error(err: cudarc::driver::DriverError) -> BackendError {
     BackendError::UnsupportedConfig(format!("failed to bind CUDA context: {err}"))
 }
 
-fn to_cuda_codec(codec: DecodeCodec) -> cudaVideoCodec {
-    match codec {
-        DecodeCodec::H264 => cudaVideoCodec::cudaVideoCodec_H264,
-        DecodeCodec::H265 => cudaVideoCodec::cudaVideoCodec_HEVC,
-        DecodeCodec::Av1 => cudaVideoCodec::cudaVideoCodec_AV1,
-    }
-}
-
 fn resolve_target_rect(format: &CUVIDEOFORMAT) -> (i32, i32, i32, i32) {
     let left = format.display_area.left.max(0);
     let top = format.display_area.top.max(0);
@@ -412,6 +607,23 @@ fn resolve_target_rect(format: &CUVIDEOFORMAT) -> (i32, i32, i32, i32) {
     (left, top, right, bottom)
 }
 
+// NVDEC's scaler only shrinks and NV12 needs even sizes; without a scale the target is the
+// display area itself.
+fn resolve_target_size(
+    (left, top, right, bottom): (i32, i32, i32, i32),
+    output_scale: Option<(u32, u32)>,
+) -> (u32, u32) {
+    let width = right.saturating_sub(left) as u32;
+    let height = bottom.saturating_sub(top) as u32;
+    match output_scale {
+        Some((scaled_width, scaled_height)) => (
+            (scaled_width.min(width) & !1).max(2),
+            (scaled_height.min(height) & !1).max(2),
+        ),
+        None => (width, height),
+    }
+}
+
 fn to_create_rect(
     (left, top, right, bottom): (i32, i32, i32, i32),
 ) -> nvidia_video_codec_sdk::sys::cuviddec::_CUVIDDECODECREATEINFO__bindgen_ty_1 {
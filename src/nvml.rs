@@ -0,0 +1,485 @@
+use std::ffi::{CStr, CString, c_char, c_int, c_uint, c_ulonglong, c_void};
+use std::fmt::{self, Display};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use libloading::Library;
+
+use crate::BackendError;
+
+// NVIDIA sessions open CUDA device 0. CUDA enumerates fastest-first by default while NVML uses
+// PCI order, so on mixed-GPU hosts set CUDA_DEVICE_ORDER=PCI_BUS_ID to make them the same GPU.
+pub const NVIDIA_SESSION_DEVICE_INDEX: u32 = 0;
+
+#[cfg(target_os = "windows")]
+const NVML_LIBRARY: &str = "nvml.dll";
+#[cfg(not(target_os = "windows"))]
+const NVML_LIBRARY: &str = "libnvidia-ml.so.1";
+
+type NvmlReturn = c_int;
+type NvmlDevice = *mut c_void;
+type NvmlEventSet = *mut c_void;
+
+const NVML_SUCCESS: NvmlReturn = 0;
+const NVML_ERROR_NOT_SUPPORTED: NvmlReturn = 3;
+const NVML_ERROR_TIMEOUT: NvmlReturn = 10;
+const NVML_TEMPERATURE_GPU: c_int = 0;
+const NVML_CLOCK_VIDEO: c_int = 3;
+const NVML_MEMORY_ERROR_TYPE_UNCORRECTED: c_int = 1;
+const NVML_VOLATILE_ECC: c_int = 0;
+const NVML_EVENT_TYPE_DOUBLE_BIT_ECC_ERROR: c_ulonglong = 0x2;
+const NVML_EVENT_TYPE_XID_CRITICAL_ERROR: c_ulonglong = 0x8;
+
+#[repr(C)]
+struct NvmlEventData {
+    _device: NvmlDevice,
+    event_type: c_ulonglong,
+    event_data: c_ulonglong,
+    _gpu_instance_id: c_uint,
+    _compute_instance_id: c_uint,
+}
+
+bitflags::bitflags! {
+    // nvmlClocksThrottleReasons, as reported for the current clocks.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct ThrottleReasons: u64 {
+        const GPU_IDLE = 0x1;
+        const APPLICATIONS_CLOCKS_SETTING = 0x2;
+        const SW_POWER_CAP = 0x4;
+        const HW_SLOWDOWN = 0x8;
+        const SYNC_BOOST = 0x10;
+        const SW_THERMAL_SLOWDOWN = 0x20;
+        const HW_THERMAL_SLOWDOWN = 0x40;
+        const HW_POWER_BRAKE_SLOWDOWN = 0x80;
+        const DISPLAY_CLOCK_SETTING = 0x100;
+    }
+}
+
+impl ThrottleReasons {
+    // Idle and user clock settings lower clocks on purpose; only these indicate a device that
+    // cannot sustain the work it has.
+    pub fn is_throttling(self) -> bool {
+        self.intersects(
+            Self::SW_POWER_CAP
+                | Self::HW_SLOWDOWN
+                | Self::SW_THERMAL_SLOWDOWN
+                | Self::HW_THERMAL_SLOWDOWN
+                | Self::HW_POWER_BRAKE_SLOWDOWN,
+        )
+    }
+}
+
+// Fields the device or driver does not report (e.g. ECC on consumer boards) are None.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GpuHealth {
+    pub temperature_c: Option<u32>,
+    pub video_clock_mhz: Option<u32>,
+    pub max_video_clock_mhz: Option<u32>,
+    pub encoder_utilization_percent: Option<u32>,
+    pub decoder_utilization_percent: Option<u32>,
+    pub throttle_reasons: ThrottleReasons,
+    pub uncorrected_ecc_errors: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GpuHealthEvent {
+    Throttling { reasons: ThrottleReasons },
+    ThrottlingCleared,
+    // Volatile uncorrected ECC error count grew since the previous sample.
+    EccErrors { uncorrected: u64 },
+    Xid { code: u64 },
+    // Sampling failed; the watcher keeps running and reports again on the next failure.
+    MonitorError(String),
+}
+
+impl Display for GpuHealthEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Throttling { reasons } => {
+                write!(f, "[gpu.throttling] reasons={:#x}", reasons.bits())
+            }
+            Self::ThrottlingCleared => f.write_str("[gpu.throttling_cleared]"),
+            Self::EccErrors { uncorrected } => {
+                write!(f, "[gpu.ecc_errors] uncorrected={uncorrected}")
+            }
+            Self::Xid { code } => write!(f, "[gpu.xid] code={code}"),
+            Self::MonitorError(err) => write!(f, "[gpu.monitor_error] {err}"),
+        }
+    }
+}
+
+struct NvmlApi {
+    // Keeps the function pointers below valid.
+    _library: Library,
+    shutdown: unsafe extern "C" fn() -> NvmlReturn,
+    error_string: unsafe extern "C" fn(NvmlReturn) -> *const c_char,
+    handle_by_index: unsafe extern "C" fn(c_uint, *mut NvmlDevice) -> NvmlReturn,
+    handle_by_pci_bus_id: unsafe extern "C" fn(*const c_char, *mut NvmlDevice) -> NvmlReturn,
+    temperature: unsafe extern "C" fn(NvmlDevice, c_int, *mut c_uint) -> NvmlReturn,
+    clock_info: unsafe extern "C" fn(NvmlDevice, c_int, *mut c_uint) -> NvmlReturn,
+    max_clock_info: unsafe extern "C" fn(NvmlDevice, c_int, *mut c_uint) -> NvmlReturn,
+    encoder_utilization: unsafe extern "C" fn(NvmlDevice, *mut c_uint, *mut c_uint) -> NvmlReturn,
+    decoder_utilization: unsafe extern "C" fn(NvmlDevice, *mut c_uint, *mut c_uint) -> NvmlReturn,
+    throttle_reasons: unsafe extern "C" fn(NvmlDevice, *mut c_ulonglong) -> NvmlReturn,
+    total_ecc_errors:
+        unsafe extern "C" fn(NvmlDevice, c_int, c_int, *mut c_ulonglong) -> NvmlReturn,
+    event_set_create: unsafe extern "C" fn(*mut NvmlEventSet) -> NvmlReturn,
+    register_events: unsafe extern "C" fn(NvmlDevice, c_ulonglong, NvmlEventSet) -> NvmlReturn,
+    event_set_wait: unsafe extern "C" fn(NvmlEventSet, *mut NvmlEventData, c_uint) -> NvmlReturn,
+    event_set_free: unsafe extern "C" fn(NvmlEventSet) -> NvmlReturn,
+}
+
+// NVML is documented as thread-safe; device and event set handles are opaque driver pointers.
+unsafe impl Send for NvmlApi {}
+unsafe impl Sync for NvmlApi {}
+
+impl NvmlApi {
+    fn load() -> Result<Self, BackendError> {
+        let library = unsafe { Library::new(NVML_LIBRARY) }.map_err(|err| {
+            BackendError::UnsupportedConfig(format!("failed to load {NVML_LIBRARY}: {err}"))
+        })?;
+        fn symbol<T: Copy>(library: &Library, name: &str) -> Result<T, BackendError> {
+            unsafe { library.get::<T>(name.as_bytes()) }
+                .map(|symbol| *symbol)
+                .map_err(|err| {
+                    BackendError::UnsupportedConfig(format!("NVML symbol {name} missing: {err}"))
+                })
+        }
+        let init: unsafe extern "C" fn() -> NvmlReturn = symbol(&library, "nvmlInit_v2")?;
+        let api = Self {
+            shutdown: symbol(&library, "nvmlShutdown")?,
+            error_string: symbol(&library, "nvmlErrorString")?,
+            handle_by_index: symbol(&library, "nvmlDeviceGetHandleByIndex_v2")?,
+            handle_by_pci_bus_id: symbol(&library, "nvmlDeviceGetHandleByPciBusId_v2")?,
+            temperature: symbol(&library, "nvmlDeviceGetTemperature")?,
+            clock_info: symbol(&library, "nvmlDeviceGetClockInfo")?,
+            max_clock_info: symbol(&library, "nvmlDeviceGetMaxClockInfo")?,
+            encoder_utilization: symbol(&library, "nvmlDeviceGetEncoderUtilization")?,
+            decoder_utilization: symbol(&library, "nvmlDeviceGetDecoderUtilization")?,
+            throttle_reasons: symbol(&library, "nvmlDeviceGetCurrentClocksThrottleReasons")?,
+            total_ecc_errors: symbol(&library, "nvmlDeviceGetTotalEccErrors")?,
+            event_set_create: symbol(&library, "nvmlEventSetCreate")?,
+            register_events: symbol(&library, "nvmlDeviceRegisterEvents")?,
+            event_set_wait: symbol(&library, "nvmlEventSetWait_v2")?,
+            event_set_free: symbol(&library, "nvmlEventSetFree")?,
+            _library: library,
+        };
+        let ret = unsafe { init() };
+        if ret != NVML_SUCCESS {
+            let err = api.error("nvmlInit_v2", ret);
+            // Nothing to shut down; unload the library without running Drop.
+            let api = std::mem::ManuallyDrop::new(api);
+            drop(unsafe { std::ptr::read(&api._library) });
+            return Err(err);
+        }
+        Ok(api)
+    }
+
+    fn error(&self, call: &str, ret: NvmlReturn) -> BackendError {
+        let message = unsafe { (self.error_string)(ret) };
+        let message = if message.is_null() {
+            format!("error {ret}")
+        } else {
+            unsafe { CStr::from_ptr(message) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        BackendError::Backend(format!("{call} failed: {message}"))
+    }
+
+    fn check(&self, call: &str, ret: NvmlReturn) -> Result<(), BackendError> {
+        if ret == NVML_SUCCESS {
+            Ok(())
+        } else {
+            Err(self.error(call, ret))
+        }
+    }
+
+    // Optional queries: unsupported on this device is None, anything else is an error.
+    fn optional<T>(
+        &self,
+        call: &str,
+        ret: NvmlReturn,
+        value: T,
+    ) -> Result<Option<T>, BackendError> {
+        match ret {
+            NVML_SUCCESS => Ok(Some(value)),
+            NVML_ERROR_NOT_SUPPORTED => Ok(None),
+            _ => Err(self.error(call, ret)),
+        }
+    }
+}
+
+impl Drop for NvmlApi {
+    fn drop(&mut self) {
+        unsafe { (self.shutdown)() };
+    }
+}
+
+pub struct GpuMonitor {
+    api: Arc<NvmlApi>,
+    device: DeviceHandle,
+}
+
+#[derive(Clone, Copy)]
+struct DeviceHandle(NvmlDevice);
+
+unsafe impl Send for DeviceHandle {}
+unsafe impl Sync for DeviceHandle {}
+
+impl GpuMonitor {
+    pub fn open(device_index: u32) -> Result<Self, BackendError> {
+        let api = Arc::new(NvmlApi::load()?);
+        let mut device = std::ptr::null_mut();
+        api.check("nvmlDeviceGetHandleByIndex_v2", unsafe {
+            (api.handle_by_index)(device_index, &mut device)
+        })?;
+        Ok(Self {
+            api,
+            device: DeviceHandle(device),
+        })
+    }
+
+    // `bus_id` as printed by nvidia-smi, e.g. "00000000:01:00.0".
+    pub fn open_pci_bus_id(bus_id: &str) -> Result<Self, BackendError> {
+        let bus_id = CString::new(bus_id)
+            .map_err(|_| BackendError::InvalidInput("PCI bus id contains NUL".to_string()))?;
+        let api = Arc::new(NvmlApi::load()?);
+        let mut device = std::ptr::null_mut();
+        api.check("nvmlDeviceGetHandleByPciBusId_v2", unsafe {
+            (api.handle_by_pci_bus_id)(bus_id.as_ptr(), &mut device)
+        })?;
+        Ok(Self {
+            api,
+            device: DeviceHandle(device),
+        })
+    }
+
+    pub fn sample(&self) -> Result<GpuHealth, BackendError> {
+        sample(&self.api, self.device)
+    }
+
+    // Samples every `interval` on a background thread and reports throttling transitions, new
+    // uncorrected ECC errors and Xid critical errors. Dropping the returned handle stops it.
+    pub fn watch<F>(&self, interval: Duration, callback: F) -> GpuWatch
+    where
+        F: FnMut(GpuHealthEvent) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let api = Arc::clone(&self.api);
+        let device = self.device;
+        let thread_stop = Arc::clone(&stop);
+        let thread =
+            thread::spawn(move || watch_loop(&api, device, interval, &thread_stop, callback));
+        GpuWatch {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+pub struct GpuWatch {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for GpuWatch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+fn sample(api: &NvmlApi, device: DeviceHandle) -> Result<GpuHealth, BackendError> {
+    let device = device.0;
+    let (mut temperature_c, mut clock, mut max_clock) = (0, 0, 0);
+    let (mut encoder, mut decoder, mut period) = (0, 0, 0);
+    let (mut reasons, mut ecc) = (0, 0);
+    unsafe {
+        let temperature_c = api.optional(
+            "nvmlDeviceGetTemperature",
+            (api.temperature)(device, NVML_TEMPERATURE_GPU, &mut temperature_c),
+            temperature_c,
+        )?;
+        let video_clock_mhz = api.optional(
+            "nvmlDeviceGetClockInfo",
+            (api.clock_info)(device, NVML_CLOCK_VIDEO, &mut clock),
+            clock,
+        )?;
+        let max_video_clock_mhz = api.optional(
+            "nvmlDeviceGetMaxClockInfo",
+            (api.max_clock_info)(device, NVML_CLOCK_VIDEO, &mut max_clock),
+            max_clock,
+        )?;
+        let encoder_utilization_percent = api.optional(
+            "nvmlDeviceGetEncoderUtilization",
+            (api.encoder_utilization)(device, &mut encoder, &mut period),
+            encoder,
+        )?;
+        let decoder_utilization_percent = api.optional(
+            "nvmlDeviceGetDecoderUtilization",
+            (api.decoder_utilization)(device, &mut decoder, &mut period),
+            decoder,
+        )?;
+        let throttle_reasons = api
+            .optional(
+                "nvmlDeviceGetCurrentClocksThrottleReasons",
+                (api.throttle_reasons)(device, &mut reasons),
+                reasons,
+            )?
+            .map_or_else(ThrottleReasons::empty, ThrottleReasons::from_bits_retain);
+        let uncorrected_ecc_errors = api.optional(
+            "nvmlDeviceGetTotalEccErrors",
+            (api.total_ecc_errors)(
+                device,
+                NVML_MEMORY_ERROR_TYPE_UNCORRECTED,
+                NVML_VOLATILE_ECC,
+                &mut ecc,
+            ),
+            ecc,
+        )?;
+        Ok(GpuHealth {
+            temperature_c,
+            video_clock_mhz,
+            max_video_clock_mhz,
+            encoder_utilization_percent,
+            decoder_utilization_percent,
+            throttle_reasons,
+            uncorrected_ecc_errors,
+        })
+    }
+}
+
+fn watch_loop<F>(
+    api: &NvmlApi,
+    device: DeviceHandle,
+    interval: Duration,
+    stop: &AtomicBool,
+    mut callback: F,
+) where
+    F: FnMut(GpuHealthEvent),
+{
+    // Xid and double-bit ECC events arrive through an event set, which then also paces the
+    // loop; devices without event support (e.g. under some virtualization) are only polled.
+    let events = register_events(api, device);
+    let wait_ms = c_uint::try_from(interval.as_millis()).unwrap_or(c_uint::MAX);
+    let mut tracker = HealthTracker::default();
+    while !stop.load(Ordering::Acquire) {
+        match events {
+            Some(set) => {
+                let mut data = NvmlEventData {
+                    _device: std::ptr::null_mut(),
+                    event_type: 0,
+                    event_data: 0,
+                    _gpu_instance_id: 0,
+                    _compute_instance_id: 0,
+                };
+                let ret = unsafe { (api.event_set_wait)(set, &mut data, wait_ms) };
+                if ret == NVML_SUCCESS && data.event_type & NVML_EVENT_TYPE_XID_CRITICAL_ERROR != 0
+                {
+                    callback(GpuHealthEvent::Xid {
+                        code: data.event_data,
+                    });
+                } else if ret != NVML_SUCCESS && ret != NVML_ERROR_TIMEOUT {
+                    callback(GpuHealthEvent::MonitorError(
+                        api.error("nvmlEventSetWait_v2", ret).to_string(),
+                    ));
+                    thread::park_timeout(interval);
+                }
+            }
+            None => thread::park_timeout(interval),
+        }
+        if stop.load(Ordering::Acquire) {
+            break;
+        }
+        match sample(api, device) {
+            Ok(health) => tracker.observe(&health).into_iter().for_each(&mut callback),
+            Err(err) => callback(GpuHealthEvent::MonitorError(err.to_string())),
+        }
+    }
+    if let Some(set) = events {
+        unsafe { (api.event_set_free)(set) };
+    }
+}
+
+fn register_events(api: &NvmlApi, device: DeviceHandle) -> Option<NvmlEventSet> {
+    let mut set = std::ptr::null_mut();
+    if unsafe { (api.event_set_create)(&mut set) } != NVML_SUCCESS {
+        return None;
+    }
+    let types = NVML_EVENT_TYPE_XID_CRITICAL_ERROR | NVML_EVENT_TYPE_DOUBLE_BIT_ECC_ERROR;
+    if unsafe { (api.register_events)(device.0, types, set) } != NVML_SUCCESS {
+        unsafe { (api.event_set_free)(set) };
+        return None;
+    }
+    Some(set)
+}
+
+// Turns consecutive samples into edge-triggered events, so a throttling device is reported
+// once when it starts and once when it recovers rather than on every sample.
+#[derive(Debug, Default)]
+struct HealthTracker {
+    throttling: Option<ThrottleReasons>,
+    ecc_errors: Option<u64>,
+}
+
+impl HealthTracker {
+    fn observe(&mut self, health: &GpuHealth) -> Vec<GpuHealthEvent> {
+        let mut events = Vec::new();
+        let reasons = health.throttle_reasons;
+        if reasons.is_throttling() {
+            if self.throttling != Some(reasons) {
+                events.push(GpuHealthEvent::Throttling { reasons });
+            }
+            self.throttling = Some(reasons);
+        } else if self.throttling.take().is_some() {
+            events.push(GpuHealthEvent::ThrottlingCleared);
+        }
+        if let Some(count) = health.uncorrected_ecc_errors {
+            if self.ecc_errors.is_some_and(|previous| count > previous) {
+                events.push(GpuHealthEvent::EccErrors { uncorrected: count });
+            }
+            self.ecc_errors = Some(count);
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracker_reports_throttling_edges_and_new_ecc_errors() {
+        let mut tracker = HealthTracker::default();
+        let mut health = GpuHealth {
+            throttle_reasons: ThrottleReasons::GPU_IDLE,
+            uncorrected_ecc_errors: Some(2),
+            ..GpuHealth::default()
+        };
+        // Idle clocks are not throttling, and errors from before the watch are not reported.
+        assert!(tracker.observe(&health).is_empty());
+
+        health.throttle_reasons = ThrottleReasons::HW_THERMAL_SLOWDOWN;
+        let thermal = GpuHealthEvent::Throttling {
+            reasons: ThrottleReasons::HW_THERMAL_SLOWDOWN,
+        };
+        assert_eq!(tracker.observe(&health), vec![thermal]);
+        assert!(tracker.observe(&health).is_empty());
+
+        health.throttle_reasons = ThrottleReasons::empty();
+        health.uncorrected_ecc_errors = Some(3);
+        assert_eq!(
+            tracker.observe(&health),
+            vec![
+                GpuHealthEvent::ThrottlingCleared,
+                GpuHealthEvent::EccErrors { uncorrected: 3 }
+            ]
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+use std::num::NonZeroU32;
+use std::ops::Range;
+
+use crate::{Codec, DecodedFrame, Timestamp90k, nal_type, split_annexb_nal_units};
+
+// Keyframe pts remembered while waiting for their frames; older entries belong to pictures the
+// decoder never returned.
+const MAX_PENDING_KEYFRAMES: usize = 64;
+
+// Which decoded frames a DecodeSession queues, for consumers such as preview windows that only
+// want a fraction of the stream. Conditions combine: a frame must be inside pts_range, be a
+// keyframe if keyframes_only is set, and then decimate keeps every Nth of the frames left.
+// Filtered frames never reach the ready queue (or a flush/drain result) but still count for
+// stream events and freeze-frame concealment. Frames without a pts fail keyframes_only and
+// pts_range.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecodeOutputFilter {
+    pub keyframes_only: bool,
+    pub decimate: Option<NonZeroU32>,
+    pub pts_range: Option<Range<Timestamp90k>>,
+}
+
+#[derive(Debug)]
+pub(crate) struct OutputFilterState {
+    codec: Codec,
+    filter: DecodeOutputFilter,
+    keyframe_pts: VecDeque<i64>,
+    // Frames that passed the other conditions since the filter was set, for decimation.
+    candidates: u64,
+    dropped: u64,
+}
+
+impl OutputFilterState {
+    pub(crate) fn new(codec: Codec) -> Self {
+        Self {
+            codec,
+            filter: DecodeOutputFilter::default(),
+            keyframe_pts: VecDeque::new(),
+            candidates: 0,
+            dropped: 0,
+        }
+    }
+
+    pub(crate) fn set(&mut self, filter: DecodeOutputFilter) {
+        self.filter = filter;
+        self.candidates = 0;
+    }
+
+    pub(crate) fn filter(&self) -> &DecodeOutputFilter {
+        &self.filter
+    }
+
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    // Decoded frames carry no picture type, so keyframes are recognised on the way in (any
+    // IRAP picture, which a decoder can start from) and matched by pts on the way out.
+    pub(crate) fn observe_input(&mut self, annexb: &[u8], pts_90k: Option<i64>) {
+        if !self.filter.keyframes_only {
+            return;
+        }
+        let Some(pts) = pts_90k else {
+            return;
+        };
+        let keyframe = self.codec == Codec::Mjpeg
+            || split_annexb_nal_units(annexb).into_iter().any(|nal| {
+                matches!(
+                    (self.codec, nal_type(self.codec, nal)),
+                    (Codec::H264, Some(5)) | (Codec::Hevc, Some(16..=21))
+                )
+            });
+        if keyframe {
+            if self.keyframe_pts.len() == MAX_PENDING_KEYFRAMES {
+                self.keyframe_pts.pop_front();
+            }
+            self.keyframe_pts.push_back(pts);
+        }
+    }
+
+    pub(crate) fn admit(&mut self, frame: &DecodedFrame) -> bool {
+        let admitted = self.matches(frame);
+        self.dropped += u64::from(!admitted);
+        admitted
+    }
+
+    fn matches(&mut self, frame: &DecodedFrame) -> bool {
+        let pts = frame.pts_90k();
+        if let Some(range) = &self.filter.pts_range
+            && !pts.is_some_and(|pts| range.contains(&pts))
+        {
+            return false;
+        }
+        if self.filter.keyframes_only {
+            let position = pts.and_then(|pts| {
+                self.keyframe_pts
+                    .iter()
+                    .position(|&keyframe| keyframe == pts.0)
+            });
+            let Some(position) = position else {
+                return false;
+            };
+            self.keyframe_pts.remove(position);
+        }
+        let candidate = self.candidates;
+        self.candidates += 1;
+        self.filter
+            .decimate
+            .is_none_or(|n| candidate.is_multiple_of(u64::from(n.get())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(pts: i64) -> DecodedFrame {
+        DecodedFrame::test_metadata(None, Some(Timestamp90k(pts)), None)
+    }
+
+    #[test]
+    fn filters_combine_range_keyframes_and_decimation() {
+        let mut state = OutputFilterState::new(Codec::H264);
+        assert!((0..4).all(|pts| state.admit(&frame(pts))));
+
+        state.set(DecodeOutputFilter {
+            decimate: NonZeroU32::new(3),
+            pts_range: Some(Timestamp90k(10)..Timestamp90k(20)),
+            ..DecodeOutputFilter::default()
+        });
+        let kept = (0..30)
+            .filter(|&pts| state.admit(&frame(pts)))
+            .collect::<Vec<_>>();
+        assert_eq!(kept, [10, 13, 16, 19]);
+        assert_eq!(state.dropped(), 26);
+
+        state.set(DecodeOutputFilter {
+            keyframes_only: true,
+            ..DecodeOutputFilter::default()
+        });
+        let idr = [0, 0, 0, 1, 0x65, 0x88];
+        let non_idr = [0, 0, 0, 1, 0x41, 0x9a];
+        state.observe_input(&[&[0, 0, 0, 1, 0x67, 0x42][..], &idr].concat(), Some(3000));
+        state.observe_input(&non_idr, Some(6000));
+        state.observe_input(&idr, None);
+        // Frames come back in presentation order, which need not be submit order.
+        assert!(!state.admit(&frame(6000)));
+        assert!(state.admit(&frame(3000)));
+        assert!(!state.admit(&frame(3000)));
+        assert!(state.keyframe_pts.is_empty());
+    }
+}
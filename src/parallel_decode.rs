@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+use std::path::Path;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+use std::sync::Mutex;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::bitstream::{insert_parameter_sets, is_idr, is_parameter_set, split_nals};
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+use crate::{
+    Backend, BackendError, BitstreamFileReader, BitstreamInput, DecodeSession, DecodedFrame,
+    DecoderConfig, Timestamp90k,
+};
+use crate::{Codec, nal_type};
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+#[derive(Debug, Clone)]
+pub struct ParallelGopDecoder {
+    backend: Backend,
+    config: DecoderConfig,
+    max_sessions: usize,
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+impl ParallelGopDecoder {
+    pub fn new(backend: Backend, config: DecoderConfig, max_sessions: usize) -> Self {
+        Self {
+            backend,
+            config,
+            max_sessions: max_sessions.max(1),
+        }
+    }
+
+    // Output order matches a single-session decode of the same file: GOPs are independent, so
+    // concatenating per-GOP output in file order is already display order.
+    pub fn decode_file(&self, path: impl AsRef<Path>) -> Result<Vec<DecodedFrame>, BackendError> {
+        let reader = BitstreamFileReader::open(path, self.config.codec)?;
+        let segments = split_gops(self.config.codec, reader.access_unit_slices());
+        drop(reader);
+
+        let next_segment = AtomicUsize::new(0);
+        let results = Mutex::new(vec![None; segments.len()]);
+        let workers = self.max_sessions.min(segments.len()).max(1);
+
+        std::thread::scope(|scope| -> Result<(), BackendError> {
+            let handles = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| -> Result<(), BackendError> {
+                        let mut session = DecodeSession::new(self.backend, self.config.clone());
+                        loop {
+                            let index = next_segment.fetch_add(1, Ordering::Relaxed);
+                            let Some(segment) = segments.get(index) else {
+                                return Ok(());
+                            };
+                            let mut frames = Vec::new();
+                            for (offset, access_unit) in segment.access_units.iter().enumerate() {
+                                let au_index = (segment.first_access_unit + offset) as i64;
+                                session.submit(BitstreamInput::AnnexBChunk {
+                                    chunk: access_unit.clone(),
+                                    pts_90k: Some(Timestamp90k(self.config.fps.pts_90k(au_index))),
+                                })?;
+                                while let Some(frame) = session.try_reap()? {
+                                    frames.push(frame);
+                                }
+                            }
+                            frames.extend(session.drain_available()?);
+                            results.lock().map_err(|_| {
+                                BackendError::Backend(
+                                    "parallel decode result lock poisoned".to_string(),
+                                )
+                            })?[index] = Some(frames);
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            for handle in handles {
+                handle.join().map_err(|_| {
+                    BackendError::Backend("parallel decode worker panicked".to_string())
+                })??;
+            }
+            Ok(())
+        })?;
+
+        let results = results.into_inner().map_err(|_| {
+            BackendError::Backend("parallel decode result lock poisoned".to_string())
+        })?;
+        Ok(results.into_iter().flatten().flatten().collect())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct GopSegment {
+    first_access_unit: usize,
+    access_units: Vec<Vec<u8>>,
+}
+
+// A new segment starts at every access unit carrying an IDR slice. When that access unit does
+// not repeat the parameter sets, the latest ones are spliced in (after the AUD, if any) so each
+// segment can be decoded by a fresh session.
+fn split_gops<'a>(codec: Codec, access_units: impl Iterator<Item = &'a [u8]>) -> Vec<GopSegment> {
+    let mut segments: Vec<GopSegment> = Vec::new();
+    let mut parameter_sets = BTreeMap::<u8, Vec<u8>>::new();
+
+    for (index, access_unit) in access_units.enumerate() {
+        // Every JPEG image is self-contained, tables included, so each one starts a segment.
+        let standalone = codec == Codec::Mjpeg;
+        let nals = if standalone {
+            Vec::new()
+        } else {
+            split_nals(access_unit)
+        };
+        let has_idr = standalone || nals.iter().any(|&(_, nal)| is_idr(codec, nal));
+        let has_parameter_sets =
+            standalone || nals.iter().any(|&(_, nal)| is_parameter_set(codec, nal));
+
+        match segments.last_mut() {
+            Some(segment) if !has_idr => segment.access_units.push(access_unit.to_vec()),
+            _ => {
+                let first = if has_parameter_sets || parameter_sets.is_empty() {
+                    access_unit.to_vec()
+                } else {
+                    insert_parameter_sets(codec, access_unit, &nals, &parameter_sets)
+                };
+                segments.push(GopSegment {
+                    first_access_unit: index,
+                    access_units: vec![first],
+                });
+            }
+        }
+
+        for &(_, nal) in &nals {
+            if is_parameter_set(codec, nal)
+                && let Some(kind) = nal_type(codec, nal)
+            {
+                parameter_sets.insert(kind, nal.to_vec());
+            }
+        }
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annexb(nals: &[&[u8]]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for nal in nals {
+            out.extend_from_slice(&[0, 0, 0, 1]);
+            out.extend_from_slice(nal);
+        }
+        out
+    }
+
+    #[test]
+    fn split_gops_starts_segments_at_idr_and_repeats_parameter_sets() {
+        let access_units = [
+            annexb(&[&[0x09, 0xF0], &[0x67, 0x42], &[0x68, 0xCE], &[0x65, 0x88]]),
+            annexb(&[&[0x09, 0xF0], &[0x41, 0x9A]]),
+            annexb(&[&[0x09, 0xF0], &[0x65, 0x88]]),
+            annexb(&[&[0x09, 0xF0], &[0x41, 0x9B]]),
+        ];
+        let segments = split_gops(Codec::H264, access_units.iter().map(Vec::as_slice));
+
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].access_units.len(), 2);
+        assert_eq!(segments[1].first_access_unit, 2);
+        assert_eq!(
+            segments[1].access_units[0],
+            annexb(&[&[0x09, 0xF0], &[0x67, 0x42], &[0x68, 0xCE], &[0x65, 0x88]])
+        );
+    }
+
+    #[test]
+    fn split_gops_covers_every_access_unit_of_sample() {
+        let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("sample-videos")
+            .join("sample-10s.h264");
+        let reader =
+            crate::bitstream_file::BitstreamFileReader::open(path, Codec::H264).expect("open");
+        let segments = split_gops(Codec::H264, reader.access_unit_slices());
+
+        assert!(!segments.is_empty());
+        assert_eq!(
+            segments.iter().map(|s| s.access_units.len()).sum::<usize>(),
+            reader.access_unit_count()
+        );
+    }
+}
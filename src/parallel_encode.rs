@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+use std::sync::Mutex;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::bitstream::{insert_parameter_sets, is_parameter_set, split_nals};
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+use crate::{Backend, EncodeFrame, EncodeSession, EncoderConfig};
+use crate::{BackendError, Codec, EncodedChunk, EncodedLayout, Timestamp90k, nal_type};
+
+// Segment length when the config sets no keyframe interval: two seconds of frames.
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+const DEFAULT_SEGMENT_SECONDS: u32 = 2;
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+#[derive(Debug, Clone)]
+pub struct ParallelGopEncoder {
+    backend: Backend,
+    config: EncoderConfig,
+    max_sessions: usize,
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+impl ParallelGopEncoder {
+    pub fn new(backend: Backend, config: EncoderConfig, max_sessions: usize) -> Self {
+        Self {
+            backend,
+            config,
+            max_sessions: max_sessions.max(1),
+        }
+    }
+
+    // Frames per segment: EncoderConfig::keyframe_interval when set, so segments end where a
+    // single session would have started a GOP anyway; two seconds of frames otherwise.
+    pub fn segment_frames(&self) -> usize {
+        match self.config.keyframe_interval {
+            Some(interval) => interval.get() as usize,
+            None => (self.config.fps.rounded().max(1) * DEFAULT_SEGMENT_SECONDS) as usize,
+        }
+    }
+
+    // Offline encode of a whole clip: the frames are cut into segments that each open with a
+    // forced keyframe, up to `max_sessions` sessions encode segments concurrently, and the
+    // output is spliced back in segment order (see splice_segments). Frames without a pts are
+    // stamped from their position at the config frame rate, so every segment shares one
+    // timeline; repeat_count must be 0.
+    pub fn encode(&self, frames: Vec<EncodeFrame>) -> Result<Vec<EncodedChunk>, BackendError> {
+        if frames.iter().any(|frame| frame.repeat_count > 0) {
+            return Err(BackendError::InvalidInput(
+                "parallel encode takes one EncodeFrame per output frame (repeat_count 0)"
+                    .to_string(),
+            ));
+        }
+        let segment_frames = self.segment_frames().max(1);
+        let mut segments = Vec::new();
+        let mut current = Vec::with_capacity(segment_frames);
+        for (index, mut frame) in frames.into_iter().enumerate() {
+            frame.pts_90k = frame
+                .pts_90k
+                .or_else(|| Some(Timestamp90k(self.config.fps.pts_90k(index as i64))));
+            frame.force_keyframe |= current.is_empty();
+            current.push(frame);
+            if current.len() == segment_frames {
+                let first_frame = index + 1 - segment_frames;
+                segments.push(Some((first_frame, std::mem::take(&mut current))));
+            }
+        }
+        if !current.is_empty() {
+            let first_frame = segments.len() * segment_frames;
+            segments.push(Some((first_frame, current)));
+        }
+
+        let count = segments.len();
+        let pending = Mutex::new(segments);
+        let next_segment = AtomicUsize::new(0);
+        let results = Mutex::new(vec![Vec::new(); count]);
+        let workers = self.max_sessions.min(count).max(1);
+        let poisoned =
+            || BackendError::Backend("parallel encode segment lock poisoned".to_string());
+
+        std::thread::scope(|scope| -> Result<(), BackendError> {
+            let handles = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| -> Result<(), BackendError> {
+                        // One session per worker: a forced keyframe after a flush closes the
+                        // GOP just like a fresh session would, without paying for another
+                        // hardware session per segment.
+                        let mut session = EncodeSession::new(self.backend, self.config.clone());
+                        let mut submitted = 0_u64;
+                        loop {
+                            let index = next_segment.fetch_add(1, Ordering::Relaxed);
+                            if index >= count {
+                                return session.close().map(drop);
+                            }
+                            let Some((first_frame, frames)) =
+                                pending.lock().map_err(|_| poisoned())?[index].take()
+                            else {
+                                continue;
+                            };
+                            let frame_count = frames.len() as u64;
+                            let mut chunks = Vec::new();
+                            for frame in frames {
+                                session.submit(frame)?;
+                                while let Some(chunk) = session.try_reap()? {
+                                    chunks.push(chunk);
+                                }
+                            }
+                            chunks.extend(session.flush()?);
+                            // The session counts display order over everything it encoded.
+                            for chunk in &mut chunks {
+                                chunk.display_index = chunk.display_index.map(|display_index| {
+                                    display_index.saturating_sub(submitted) + first_frame as u64
+                                });
+                            }
+                            submitted += frame_count;
+                            results.lock().map_err(|_| poisoned())?[index] = chunks;
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            for handle in handles {
+                handle.join().map_err(|_| {
+                    BackendError::Backend("parallel encode worker panicked".to_string())
+                })??;
+            }
+            Ok(())
+        })?;
+
+        let results = results.into_inner().map_err(|_| poisoned())?;
+        splice_segments(self.config.codec, results)
+    }
+}
+
+// Joins per-segment output into one stream. Each segment has to open with a keyframe so no
+// picture references across a splice. Annex B segments also have to agree on parameter sets:
+// one that does not repeat them gets the first segment's put in front of its keyframe, one
+// with different ones fails the encode. DTS is kept strictly increasing across splices.
+fn splice_segments(
+    codec: Codec,
+    segments: Vec<Vec<EncodedChunk>>,
+) -> Result<Vec<EncodedChunk>, BackendError> {
+    let mut reference: Option<BTreeMap<u8, Vec<u8>>> = None;
+    let mut last_dts = None;
+    let mut out = Vec::with_capacity(segments.iter().map(Vec::len).sum());
+    for (index, segment) in segments.into_iter().enumerate() {
+        let mut chunks = segment.into_iter();
+        let Some(mut first) = chunks.next() else {
+            continue;
+        };
+        if !first.is_keyframe {
+            return Err(BackendError::Backend(format!(
+                "parallel encode segment {index} does not open with a keyframe"
+            )));
+        }
+        if first.layout == EncodedLayout::AnnexB && codec != Codec::Mjpeg {
+            let nals = split_nals(&first.data);
+            let found = nals
+                .iter()
+                .filter(|&&(_, nal)| is_parameter_set(codec, nal))
+                .filter_map(|&(_, nal)| Some((nal_type(codec, nal)?, nal.to_vec())))
+                .collect::<BTreeMap<_, _>>();
+            match &reference {
+                None => reference = Some(found),
+                Some(expected) if found.is_empty() && !expected.is_empty() => {
+                    first.data = insert_parameter_sets(codec, &first.data, &nals, expected).into();
+                }
+                Some(expected) if found != *expected => {
+                    return Err(BackendError::Backend(format!(
+                        "parallel encode segment {index} has parameter sets that differ from the first segment's"
+                    )));
+                }
+                Some(_) => {}
+            }
+        }
+        for mut chunk in std::iter::once(first).chain(chunks) {
+            if let Some(dts) = chunk.dts_90k {
+                let dts = match last_dts {
+                    Some(last) if dts.0 <= last => last + 1,
+                    _ => dts.0,
+                };
+                last_dts = Some(dts);
+                chunk.dts_90k = Some(Timestamp90k(dts));
+            }
+            out.push(chunk);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annexb(nals: &[&[u8]]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for nal in nals {
+            out.extend_from_slice(&[0, 0, 0, 1]);
+            out.extend_from_slice(nal);
+        }
+        out
+    }
+
+    fn chunk(data: Vec<u8>, pts: i64, dts: i64, is_keyframe: bool) -> EncodedChunk {
+        EncodedChunk {
+            codec: Codec::H264,
+            layout: EncodedLayout::AnnexB,
+            data: data.into(),
+            pts_90k: Some(Timestamp90k(pts)),
+            is_keyframe,
+            filler_bytes: 0,
+            dts_90k: Some(Timestamp90k(dts)),
+            display_index: None,
+        }
+    }
+
+    #[test]
+    fn segments_splice_with_shared_parameter_sets() {
+        let sps: &[u8] = &[0x67, 0x42];
+        let pps: &[u8] = &[0x68, 0xCE];
+        let first = vec![
+            chunk(
+                annexb(&[&[0x09, 0xF0], sps, pps, &[0x65, 0x88]]),
+                0,
+                -3000,
+                true,
+            ),
+            chunk(annexb(&[&[0x41, 0x9A]]), 3000, 0, false),
+        ];
+        // The second segment's session repeated no parameter sets, and its DTS restarts below
+        // the first segment's last one.
+        let second = vec![
+            chunk(annexb(&[&[0x09, 0xF0], &[0x65, 0x88]]), 6000, -1, true),
+            chunk(annexb(&[&[0x41, 0x9B]]), 9000, 6000, false),
+        ];
+        let spliced = splice_segments(Codec::H264, vec![first.clone(), second]).unwrap();
+        assert_eq!(spliced.len(), 4);
+        assert_eq!(
+            &spliced[2].data[..],
+            &annexb(&[&[0x09, 0xF0], sps, pps, &[0x65, 0x88]])[..]
+        );
+        let dts = spliced
+            .iter()
+            .map(|chunk| chunk.dts_90k.unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(dts, [-3000, 0, 1, 6000]);
+
+        let open_gop = vec![chunk(annexb(&[&[0x41, 0x9A]]), 6000, 3000, false)];
+        assert!(splice_segments(Codec::H264, vec![first.clone(), open_gop]).is_err());
+        let other_sps = vec![chunk(
+            annexb(&[&[0x67, 0x64], pps, &[0x65, 0x88]]),
+            6000,
+            3000,
+            true,
+        )];
+        assert!(splice_segments(Codec::H264, vec![first, other_sps]).is_err());
+    }
+}
@@ -15,7 +15,8 @@ use crate::{BackendError, ColorRequest};
 enum SchedulerTask {
     Frame {
         generation: u64,
-        input: DecodedUnit,
+        // Boxed: frames are much larger than the shutdown marker.
+        input: Box<DecodedUnit>,
         color: ColorRequest,
         resize: Option<(u32, u32)>,
     },
@@ -73,7 +74,7 @@ impl PipelineScheduler {
         self.in_tx
             .try_send(SchedulerTask::Frame {
                 generation,
-                input,
+                input: Box::new(input),
                 color,
                 resize,
             })
@@ -151,7 +152,7 @@ fn run_scheduler<A>(
                     ))));
                     continue;
                 }
-                let submit_result = adapter.submit(input, color, resize);
+                let submit_result = adapter.submit(*input, color, resize);
                 match submit_result {
                     Ok(Some(output)) => {
                         let latest_generation = generation.load(Ordering::Relaxed);
@@ -231,8 +232,13 @@ mod tests {
                     color_primaries: None,
                     transfer_function: None,
                     ycbcr_matrix: None,
+                    planes: None,
                     argb: None,
                     force_keyframe: false,
+                    dirty_rects: None,
+                    luma_histogram: None,
+                    view: None,
+                    stereo_right: None,
                 }),
                 ColorRequest::KeepNative,
                 None,
@@ -285,8 +291,13 @@ mod tests {
                     color_primaries: None,
                     transfer_function: None,
                     ycbcr_matrix: None,
+                    planes: None,
                     argb: None,
                     force_keyframe: false,
+                    dirty_rects: None,
+                    luma_histogram: None,
+                    view: None,
+                    stereo_right: None,
                 }),
                 ColorRequest::KeepNative,
                 None,
@@ -0,0 +1,153 @@
+use std::fmt::{self, Display};
+
+// Vendor-neutral pixel layout of decoded surfaces and transform buffers. Video and full range
+// variants of a layout are the same variant; the code the backend reported is kept next to it
+// in BackendPixelFormat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PixelFormat {
+    Nv12,
+    P010,
+    Bgra8,
+    Rgba8,
+    Rgb8,
+    Yuv420p,
+    Yuv444p,
+    Other,
+}
+
+impl Display for PixelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Nv12 => f.write_str("nv12"),
+            Self::P010 => f.write_str("p010"),
+            Self::Bgra8 => f.write_str("bgra8"),
+            Self::Rgba8 => f.write_str("rgba8"),
+            Self::Rgb8 => f.write_str("rgb8"),
+            Self::Yuv420p => f.write_str("yuv420p"),
+            Self::Yuv444p => f.write_str("yuv444p"),
+            Self::Other => f.write_str("other"),
+        }
+    }
+}
+
+// Which code space a raw pixel format code belongs to: CoreVideo OSTypes on VideoToolbox,
+// cudaVideoSurfaceFormat values on NVDEC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PixelFormatNamespace {
+    CoreVideo,
+    NvdecSurface,
+}
+
+impl Display for PixelFormatNamespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CoreVideo => f.write_str("core-video"),
+            Self::NvdecSurface => f.write_str("nvdec-surface"),
+        }
+    }
+}
+
+// A backend-reported format: the normalized layout plus the untouched code, so 420f and 420v
+// both read as Nv12 but raw() still tells them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BackendPixelFormat {
+    format: PixelFormat,
+    namespace: PixelFormatNamespace,
+    code: u32,
+}
+
+const fn fourcc(code: &[u8; 4]) -> u32 {
+    u32::from_be_bytes(*code)
+}
+
+// kCVPixelFormatType_24RGB is not a FourCC but the plain bit depth.
+const CV_24RGB: u32 = 0x18;
+
+impl BackendPixelFormat {
+    pub fn from_core_video(code: u32) -> Self {
+        let format = match code {
+            c if c == fourcc(b"420v") || c == fourcc(b"420f") => PixelFormat::Nv12,
+            c if c == fourcc(b"x420") || c == fourcc(b"xf20") => PixelFormat::P010,
+            c if c == fourcc(b"BGRA") => PixelFormat::Bgra8,
+            c if c == fourcc(b"RGBA") => PixelFormat::Rgba8,
+            c if c == fourcc(b"y420") || c == fourcc(b"f420") => PixelFormat::Yuv420p,
+            c if c == fourcc(b"y444") => PixelFormat::Yuv444p,
+            CV_24RGB => PixelFormat::Rgb8,
+            _ => PixelFormat::Other,
+        };
+        Self {
+            format,
+            namespace: PixelFormatNamespace::CoreVideo,
+            code,
+        }
+    }
+
+    // P016 is how NVDEC outputs 10-bit streams, in the P010 layout.
+    pub fn from_nvdec_surface_format(code: u32) -> Self {
+        let format = match code {
+            0 => PixelFormat::Nv12,
+            1 => PixelFormat::P010,
+            2 => PixelFormat::Yuv444p,
+            _ => PixelFormat::Other,
+        };
+        Self {
+            format,
+            namespace: PixelFormatNamespace::NvdecSurface,
+            code,
+        }
+    }
+
+    pub fn format(self) -> PixelFormat {
+        self.format
+    }
+
+    pub fn namespace(self) -> PixelFormatNamespace {
+        self.namespace
+    }
+
+    pub fn raw(self) -> u32 {
+        self.code
+    }
+}
+
+impl Display for BackendPixelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({} {:#010x})",
+            self.format, self.namespace, self.code
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backend_codes_map_to_shared_variants_and_keep_the_raw_code() {
+        let video = BackendPixelFormat::from_core_video(875_704_438);
+        let full = BackendPixelFormat::from_core_video(875_704_422);
+        assert_eq!(
+            (video.format(), full.format()),
+            (PixelFormat::Nv12, PixelFormat::Nv12)
+        );
+        assert_eq!((video.raw(), full.raw()), (875_704_438, 875_704_422));
+        assert_ne!(video, full);
+        assert_eq!(
+            BackendPixelFormat::from_core_video(1_111_970_369).format(),
+            PixelFormat::Bgra8
+        );
+
+        let nvdec = BackendPixelFormat::from_nvdec_surface_format(1);
+        assert_eq!(nvdec.format(), PixelFormat::P010);
+        assert_eq!(nvdec.namespace(), PixelFormatNamespace::NvdecSurface);
+        assert_eq!(nvdec.raw(), 1);
+        assert_ne!(BackendPixelFormat::from_core_video(1), nvdec);
+
+        let unknown = BackendPixelFormat::from_core_video(0x1234);
+        assert_eq!(unknown.format(), PixelFormat::Other);
+        assert_eq!(unknown.raw(), 0x1234);
+        assert_eq!(unknown.to_string(), "other (core-video 0x00001234)");
+    }
+}
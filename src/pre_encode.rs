@@ -0,0 +1,103 @@
+use crate::{BackendError, EncodeFrame};
+
+// Runs on every frame right before it is handed to the encoder (after repeat expansion and
+// keyframe scheduling) with mutable access to its pixels, so forensic watermarking can be
+// integrated without forking the pipeline. Shared buffers are copied on first write through
+// RawFrameBuffer::data_mut; a GPU implementation uploads, runs its kernel and reads back
+// inside process(). Time spent here counts as encoder busy time in EngineUtilization, and an
+// error (TemporaryBackpressure included) rejects the frame before it reaches the backend, so
+// the caller can retry it as with a full encoder queue.
+pub trait PreEncodeHook {
+    fn process(&mut self, frame: &mut EncodeFrame) -> Result<(), BackendError>;
+}
+
+impl<F> PreEncodeHook for F
+where
+    F: FnMut(&mut EncodeFrame) -> Result<(), BackendError>,
+{
+    fn process(&mut self, frame: &mut EncodeFrame) -> Result<(), BackendError> {
+        self(frame)
+    }
+}
+
+// Hooks only ever see a buffer that matches the frame size, and must leave the size alone.
+pub(crate) fn apply_pre_encode_hooks(
+    hooks: &mut [Box<dyn PreEncodeHook>],
+    frame: &mut EncodeFrame,
+) -> Result<(), BackendError> {
+    if hooks.is_empty() {
+        return Ok(());
+    }
+    let dims = frame.dims;
+    frame.buffer.validate(dims)?;
+    for hook in hooks.iter_mut() {
+        hook.process(frame)?;
+    }
+    if frame.dims != dims {
+        return Err(BackendError::InvalidInput(format!(
+            "pre-encode hook changed the frame size from {dims} to {}",
+            frame.dims
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{Dimensions, RawFrameBuffer};
+
+    fn frame(buffer: RawFrameBuffer) -> EncodeFrame {
+        EncodeFrame {
+            dims: Dimensions {
+                width: NonZeroU32::new(2).unwrap(),
+                height: NonZeroU32::new(2).unwrap(),
+            },
+            pts_90k: None,
+            buffer,
+            force_keyframe: false,
+            dirty_rects: None,
+            repeat_count: 0,
+        }
+    }
+
+    #[test]
+    fn hooks_mark_a_private_copy_of_shared_pixels() {
+        let shared: Arc<[u8]> = Arc::from(vec![0u8; 16]);
+        let mut input = frame(RawFrameBuffer::Argb8888Shared(Arc::clone(&shared)));
+        let mut hooks: Vec<Box<dyn PreEncodeHook>> = vec![
+            Box::new(|frame: &mut EncodeFrame| {
+                frame.buffer.data_mut()[0] = 0xAB;
+                Ok(())
+            }),
+            Box::new(|frame: &mut EncodeFrame| {
+                frame.buffer.data_mut()[15] ^= 0xFF;
+                Ok(())
+            }),
+        ];
+        apply_pre_encode_hooks(&mut hooks, &mut input).unwrap();
+        assert!(matches!(input.buffer, RawFrameBuffer::Argb8888(_)));
+        assert_eq!(
+            (input.buffer.data()[0], input.buffer.data()[15]),
+            (0xAB, 0xFF)
+        );
+        assert!(shared.iter().all(|&byte| byte == 0));
+
+        let mut resize: Vec<Box<dyn PreEncodeHook>> = vec![Box::new(|frame: &mut EncodeFrame| {
+            frame.dims.width = NonZeroU32::new(4).unwrap();
+            Ok(())
+        })];
+        let mut input = frame(RawFrameBuffer::Argb8888(vec![0; 16]));
+        assert!(matches!(
+            apply_pre_encode_hooks(&mut resize, &mut input),
+            Err(BackendError::InvalidInput(_))
+        ));
+        // A wrongly sized buffer is reported before any hook writes into it.
+        let mut short = frame(RawFrameBuffer::Argb8888(vec![0; 4]));
+        assert!(apply_pre_encode_hooks(&mut hooks, &mut short).is_err());
+        assert_eq!(short.buffer.data(), [0; 4]);
+    }
+}
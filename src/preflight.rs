@@ -0,0 +1,253 @@
+use std::fmt::{self, Display};
+use std::time::Duration;
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+use std::time::Instant;
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+use std::{ffi::c_int, sync::OnceLock};
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+use libloading::Library;
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+use crate::Backend;
+
+#[cfg(all(feature = "backend-nvidia", target_os = "windows"))]
+const CUDA_LIBRARY: &str = "nvcuda.dll";
+#[cfg(all(feature = "backend-nvidia", target_os = "linux"))]
+const CUDA_LIBRARY: &str = "libcuda.so.1";
+#[cfg(all(feature = "backend-nvidia", target_os = "windows"))]
+const NVCUVID_LIBRARY: &str = "nvcuvid.dll";
+#[cfg(all(feature = "backend-nvidia", target_os = "linux"))]
+const NVCUVID_LIBRARY: &str = "libnvcuvid.so.1";
+#[cfg(all(feature = "backend-nvidia", target_os = "windows"))]
+const NVENC_LIBRARY: &str = "nvEncodeAPI64.dll";
+#[cfg(all(feature = "backend-nvidia", target_os = "linux"))]
+const NVENC_LIBRARY: &str = "libnvidia-encode.so.1";
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+const CUDA_SUCCESS: c_int = 0;
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+const CUDA_ERROR_NO_DEVICE: c_int = 100;
+
+// What a backend's preflight found: the driver API version it could query and, per direction,
+// why a session cannot work on this machine (None when the checks passed). The checks only load
+// the driver libraries and ask for versions and device counts, so a machine without a GPU or
+// driver is rejected in milliseconds instead of failing inside session creation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendPreflight {
+    pub backend: String,
+    pub driver_version: Option<String>,
+    pub decode_blocker: Option<String>,
+    pub encode_blocker: Option<String>,
+    pub elapsed: Duration,
+}
+
+impl BackendPreflight {
+    pub fn decode_ready(&self) -> bool {
+        self.decode_blocker.is_none()
+    }
+
+    pub fn encode_ready(&self) -> bool {
+        self.encode_blocker.is_none()
+    }
+
+    #[cfg(all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    ))]
+    fn blocked(backend: &str, blocker: String, elapsed: Duration) -> Self {
+        Self {
+            backend: backend.to_string(),
+            driver_version: None,
+            decode_blocker: Some(blocker.clone()),
+            encode_blocker: Some(blocker),
+            elapsed,
+        }
+    }
+}
+
+impl Display for BackendPreflight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[preflight] {}", self.backend)?;
+        if let Some(version) = &self.driver_version {
+            write!(f, " driver={version}")?;
+        }
+        for (direction, blocker) in [
+            ("decode", &self.decode_blocker),
+            ("encode", &self.encode_blocker),
+        ] {
+            match blocker {
+                None => write!(f, " {direction}=ok")?,
+                Some(reason) => write!(f, " {direction}=blocked({reason})")?,
+            }
+        }
+        write!(f, " elapsed_ms={:.3}", self.elapsed.as_secs_f64() * 1_000.0)
+    }
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+// Runs once per backend and process; later calls return the cached result (with the elapsed
+// time of the first run), since driver libraries do not appear or vanish while running.
+pub fn preflight(backend: Backend) -> BackendPreflight {
+    match backend {
+        Backend::Auto => preflight(Backend::os_default()),
+        // VideoToolbox ships with the OS; whether a codec runs in hardware is only known once a
+        // session asks for it.
+        #[cfg(all(target_os = "macos", feature = "backend-vt"))]
+        Backend::VideoToolbox => BackendPreflight {
+            backend: backend.to_string(),
+            driver_version: None,
+            decode_blocker: None,
+            encode_blocker: None,
+            elapsed: Duration::ZERO,
+        },
+        #[cfg(all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        ))]
+        Backend::Nvidia => nvidia_preflight().report.clone(),
+    }
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+struct NvidiaPreflight {
+    report: BackendPreflight,
+    // Kept loaded: unloading libcuda after cuInit would pull it out from under later sessions.
+    _libraries: Vec<Library>,
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+fn nvidia_preflight() -> &'static NvidiaPreflight {
+    static PREFLIGHT: OnceLock<NvidiaPreflight> = OnceLock::new();
+    PREFLIGHT.get_or_init(|| {
+        let started = Instant::now();
+        let backend = Backend::Nvidia.to_string();
+        let (cuda, driver_version) = match probe_cuda() {
+            Ok(found) => found,
+            Err(blocker) => {
+                return NvidiaPreflight {
+                    report: BackendPreflight::blocked(&backend, blocker, started.elapsed()),
+                    _libraries: Vec::new(),
+                };
+            }
+        };
+        let mut libraries = vec![cuda];
+        let mut missing = |name: &str| match unsafe { Library::new(name) } {
+            Ok(library) => {
+                libraries.push(library);
+                None
+            }
+            Err(err) => Some(format!("{name} not loadable: {err}")),
+        };
+        let decode_blocker = missing(NVCUVID_LIBRARY);
+        let encode_blocker = missing(NVENC_LIBRARY);
+        NvidiaPreflight {
+            report: BackendPreflight {
+                backend,
+                driver_version: Some(driver_version),
+                decode_blocker,
+                encode_blocker,
+                elapsed: started.elapsed(),
+            },
+            _libraries: libraries,
+        }
+    })
+}
+
+// Loads the CUDA driver, reads its version (valid before cuInit) and checks a device shows up.
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+fn probe_cuda() -> Result<(Library, String), String> {
+    type CuDriverGetVersion = unsafe extern "C" fn(*mut c_int) -> c_int;
+    type CuInit = unsafe extern "C" fn(u32) -> c_int;
+    type CuDeviceGetCount = unsafe extern "C" fn(*mut c_int) -> c_int;
+
+    let cuda = unsafe { Library::new(CUDA_LIBRARY) }
+        .map_err(|err| format!("CUDA driver {CUDA_LIBRARY} not loadable: {err}"))?;
+    let symbol_error = |err: libloading::Error| format!("{CUDA_LIBRARY}: {err}");
+    let mut version: c_int = 0;
+    let mut count: c_int = 0;
+    unsafe {
+        let get_version = cuda
+            .get::<CuDriverGetVersion>(b"cuDriverGetVersion\0")
+            .map_err(symbol_error)?;
+        let init = cuda.get::<CuInit>(b"cuInit\0").map_err(symbol_error)?;
+        let get_count = cuda
+            .get::<CuDeviceGetCount>(b"cuDeviceGetCount\0")
+            .map_err(symbol_error)?;
+        if get_version(&mut version) != CUDA_SUCCESS {
+            return Err("cuDriverGetVersion failed".to_string());
+        }
+        match init(0) {
+            CUDA_SUCCESS => {}
+            CUDA_ERROR_NO_DEVICE => return Err("no CUDA-capable device".to_string()),
+            code => return Err(format!("cuInit failed with CUresult {code}")),
+        }
+        if get_count(&mut count) != CUDA_SUCCESS || count == 0 {
+            return Err("no CUDA-capable device".to_string());
+        }
+    }
+    // 12040 -> "12.4".
+    let version = format!("{}.{}", version / 1000, version % 1000 / 10);
+    Ok((cuda, version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_names_the_blocker() {
+        let mut report = BackendPreflight {
+            backend: "nvidia".to_string(),
+            driver_version: None,
+            decode_blocker: Some("CUDA driver libcuda.so.1 not loadable".to_string()),
+            encode_blocker: Some("CUDA driver libcuda.so.1 not loadable".to_string()),
+            elapsed: Duration::from_micros(1500),
+        };
+        assert!(!report.decode_ready() && !report.encode_ready());
+        report.driver_version = Some("12.4".to_string());
+        report.decode_blocker = None;
+        report.encode_blocker = Some("libnvidia-encode.so.1 not loadable".to_string());
+        assert!(report.decode_ready() && !report.encode_ready());
+        assert_eq!(
+            report.to_string(),
+            "[preflight] nvidia driver=12.4 decode=ok encode=blocked(libnvidia-encode.so.1 not loadable) elapsed_ms=1.500"
+        );
+    }
+}
@@ -0,0 +1,10 @@
+// `use video_hw::prelude::*;` brings in what a typical decode/encode loop names: sessions,
+// their configs, the frame and chunk types passing through them and the error enum. This set
+// is kept stable; everything else stays reachable from the crate root but may move between
+// releases.
+pub use crate::{
+    Backend, BackendError, BackendKind, BitstreamInput, CapabilityReport, Codec, ColorMetadata,
+    DecodeSession, DecodedFrame, DecoderConfig, Dimensions, EncodeFrame, EncodeSession,
+    EncodedChunk, EncodedLayout, EncoderConfig, FrameRate, PixelFormat, PlaneLayout, Profile,
+    RawFrameBuffer, StreamEvent, Timestamp90k,
+};
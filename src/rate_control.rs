@@ -0,0 +1,183 @@
+use std::num::NonZeroU32;
+
+use crate::{EncodedChunk, EncoderConfig, FrameRate};
+
+// What the session measured since the controller last ran. `bytes` is everything the encoder
+// emitted, filler included, i.e. what goes on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateControlWindow {
+    pub frames: u64,
+    pub chunks: u64,
+    pub bytes: u64,
+    pub keyframes: u64,
+    pub fps: FrameRate,
+    pub target_bitrate_bps: Option<u64>,
+    pub max_qp: Option<u32>,
+}
+
+impl RateControlWindow {
+    // Output rate over the window at the session frame rate; None before any frame or at an
+    // unknown frame rate.
+    pub fn bitrate_bps(&self) -> Option<u64> {
+        if self.frames == 0 || !self.fps.is_known() {
+            return None;
+        }
+        let bits = u128::from(self.bytes) * 8 * u128::from(self.fps.num);
+        let ticks = u128::from(self.frames) * u128::from(self.fps.den);
+        u64::try_from(bits / ticks).ok()
+    }
+}
+
+// Fields left at None keep the current setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RateControlUpdate {
+    pub target_bitrate_bps: Option<u64>,
+    pub max_qp: Option<u32>,
+    pub force_keyframe: bool,
+}
+
+// Plug-in point for an external congestion controller, e.g. GCC or transport-cc estimates
+// from a WebRTC stack. The session calls update() right before every Nth frame; new targets
+// go through apply_config, which retargets a running NVENC or VideoToolbox session without a
+// keyframe, and force_keyframe makes that frame an IDR.
+pub trait RateController {
+    fn update(&mut self, window: &RateControlWindow) -> RateControlUpdate;
+}
+
+impl<F> RateController for F
+where
+    F: FnMut(&RateControlWindow) -> RateControlUpdate,
+{
+    fn update(&mut self, window: &RateControlWindow) -> RateControlUpdate {
+        self(window)
+    }
+}
+
+pub(crate) struct RateControlState {
+    controller: Box<dyn RateController>,
+    every_frames: NonZeroU32,
+    frames: u64,
+    chunks: u64,
+    bytes: u64,
+    keyframes: u64,
+}
+
+impl RateControlState {
+    pub(crate) fn new(controller: Box<dyn RateController>, every_frames: NonZeroU32) -> Self {
+        Self {
+            controller,
+            every_frames,
+            frames: 0,
+            chunks: 0,
+            bytes: 0,
+            keyframes: 0,
+        }
+    }
+
+    pub(crate) fn observe_frame(&mut self) {
+        self.frames += 1;
+    }
+
+    pub(crate) fn observe_chunk(&mut self, chunk: &EncodedChunk) {
+        self.chunks += 1;
+        self.bytes += chunk.data.len() as u64;
+        self.keyframes += u64::from(chunk.is_keyframe);
+    }
+
+    // Runs the controller once a full window has been submitted. Returns the config to switch
+    // to, if the update changes it, and whether the next frame has to be a keyframe.
+    pub(crate) fn poll(&mut self, config: &EncoderConfig) -> (Option<EncoderConfig>, bool) {
+        if self.frames < u64::from(self.every_frames.get()) {
+            return (None, false);
+        }
+        let window = RateControlWindow {
+            frames: self.frames,
+            chunks: self.chunks,
+            bytes: self.bytes,
+            keyframes: self.keyframes,
+            fps: config.fps,
+            target_bitrate_bps: config.target_bitrate_bps,
+            max_qp: config.max_qp,
+        };
+        (self.frames, self.chunks, self.bytes, self.keyframes) = (0, 0, 0, 0);
+        let update = self.controller.update(&window);
+        let mut next = config.clone();
+        next.target_bitrate_bps = update.target_bitrate_bps.or(next.target_bitrate_bps);
+        next.max_qp = update.max_qp.or(next.max_qp);
+        let changed =
+            next.target_bitrate_bps != config.target_bitrate_bps || next.max_qp != config.max_qp;
+        (changed.then_some(next), update.force_keyframe)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{Codec, EncodedLayout};
+
+    #[test]
+    fn controller_runs_once_per_window() {
+        let mut config = EncoderConfig::new(Codec::H264, 30, true);
+        config.target_bitrate_bps = Some(4_000_000);
+        let mut windows = Vec::new();
+        let controller = move |window: &RateControlWindow| {
+            windows.push(*window);
+            // Halve the rate on the first window, then hold it and ask for an IDR.
+            match windows.len() {
+                1 => RateControlUpdate {
+                    target_bitrate_bps: window.target_bitrate_bps.map(|bitrate| bitrate / 2),
+                    max_qp: Some(42),
+                    force_keyframe: false,
+                },
+                _ => RateControlUpdate {
+                    force_keyframe: true,
+                    ..RateControlUpdate::default()
+                },
+            }
+        };
+        let mut state = RateControlState::new(Box::new(controller), NonZeroU32::new(3).unwrap());
+        let chunk = EncodedChunk {
+            codec: Codec::H264,
+            layout: EncodedLayout::AnnexB,
+            data: Arc::from(vec![0_u8; 2500]),
+            pts_90k: None,
+            is_keyframe: false,
+            filler_bytes: 0,
+            dts_90k: None,
+            display_index: None,
+        };
+        for _ in 0..2 {
+            state.observe_frame();
+            state.observe_chunk(&chunk);
+            assert!(matches!(state.poll(&config), (None, false)));
+        }
+        state.observe_frame();
+        state.observe_chunk(&chunk);
+        let (next, keyframe) = state.poll(&config);
+        let next = next.expect("the first window retargets");
+        assert_eq!(
+            (next.target_bitrate_bps, next.max_qp, keyframe),
+            (Some(2_000_000), Some(42), false)
+        );
+        assert!(matches!(state.poll(&next), (None, false)));
+
+        for _ in 0..3 {
+            state.observe_frame();
+        }
+        let (unchanged, keyframe) = state.poll(&next);
+        assert!(unchanged.is_none() && keyframe);
+
+        let window = RateControlWindow {
+            frames: 3,
+            chunks: 3,
+            bytes: 7500,
+            keyframes: 0,
+            fps: FrameRate::new(30, 1),
+            target_bitrate_bps: None,
+            max_qp: None,
+        };
+        assert_eq!(window.bitrate_bps(), Some(600_000));
+    }
+}
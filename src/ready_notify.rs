@@ -0,0 +1,244 @@
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// OS-level readiness signal for hosts that multiplex many sessions on their own event loop
+// (epoll/kqueue/WaitForMultipleObjects or an async runtime's reactor). It is level-style: the
+// handle becomes readable/signalled when output is queued and is reset by the try_reap call
+// that finds the queue empty, so the host loop is "wait on the handle, then try_reap until
+// None". Spurious wake-ups are possible; missed ones are not.
+//
+// Linux uses an eventfd, other Unix systems a non-blocking pipe (register the read end for
+// EVFILT_READ with kqueue), Windows a manual-reset event.
+#[derive(Debug, Clone)]
+pub struct ReadyNotifier {
+    inner: Arc<NotifierInner>,
+}
+
+#[derive(Debug)]
+struct NotifierInner {
+    signalled: AtomicBool,
+    os: sys::OsEvent,
+}
+
+impl ReadyNotifier {
+    pub(crate) fn new() -> io::Result<Self> {
+        Ok(Self {
+            inner: Arc::new(NotifierInner {
+                signalled: AtomicBool::new(false),
+                os: sys::OsEvent::new()?,
+            }),
+        })
+    }
+
+    pub fn is_signalled(&self) -> bool {
+        self.inner.signalled.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn notify(&self) {
+        if !self.inner.signalled.swap(true, Ordering::AcqRel) {
+            self.inner.os.set();
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        if self.inner.signalled.swap(false, Ordering::AcqRel) {
+            self.inner.os.reset();
+        }
+    }
+}
+
+#[cfg(unix)]
+impl std::os::fd::AsRawFd for ReadyNotifier {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        self.inner.os.fd()
+    }
+}
+
+#[cfg(unix)]
+impl std::os::fd::AsFd for ReadyNotifier {
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        // The descriptor lives as long as the shared inner state, which outlives the borrow.
+        unsafe { std::os::fd::BorrowedFd::borrow_raw(self.inner.os.fd()) }
+    }
+}
+
+#[cfg(windows)]
+impl std::os::windows::io::AsRawHandle for ReadyNotifier {
+    fn as_raw_handle(&self) -> std::os::windows::io::RawHandle {
+        self.inner.os.handle()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+    #[derive(Debug)]
+    pub(super) struct OsEvent {
+        fd: OwnedFd,
+    }
+
+    impl OsEvent {
+        pub(super) fn new() -> io::Result<Self> {
+            let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self {
+                fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            })
+        }
+
+        pub(super) fn fd(&self) -> RawFd {
+            self.fd.as_raw_fd()
+        }
+
+        pub(super) fn set(&self) {
+            let value = 1u64;
+            unsafe {
+                libc::write(self.fd(), (&raw const value).cast(), size_of::<u64>());
+            }
+        }
+
+        pub(super) fn reset(&self) {
+            // Reading an eventfd returns and zeroes the counter; EAGAIN means it already was 0.
+            let mut value = 0u64;
+            unsafe {
+                libc::read(self.fd(), (&raw mut value).cast(), size_of::<u64>());
+            }
+        }
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+mod sys {
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+    #[derive(Debug)]
+    pub(super) struct OsEvent {
+        read: OwnedFd,
+        write: OwnedFd,
+    }
+
+    impl OsEvent {
+        pub(super) fn new() -> io::Result<Self> {
+            let mut fds = [0; 2];
+            if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let (read, write) =
+                unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+            for fd in [&read, &write] {
+                let fd = fd.as_raw_fd();
+                unsafe {
+                    let flags = libc::fcntl(fd, libc::F_GETFL);
+                    if flags < 0
+                        || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0
+                        || libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) < 0
+                    {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+            }
+            Ok(Self { read, write })
+        }
+
+        pub(super) fn fd(&self) -> RawFd {
+            self.read.as_raw_fd()
+        }
+
+        pub(super) fn set(&self) {
+            let byte = 1u8;
+            unsafe {
+                libc::write(self.write.as_raw_fd(), (&raw const byte).cast(), 1);
+            }
+        }
+
+        pub(super) fn reset(&self) {
+            let mut buf = [0u8; 64];
+            while unsafe { libc::read(self.fd(), buf.as_mut_ptr().cast(), buf.len()) } > 0 {}
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::io;
+
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::System::Threading::{CreateEventW, ResetEvent, SetEvent};
+
+    #[derive(Debug)]
+    pub(super) struct OsEvent {
+        handle: HANDLE,
+    }
+
+    // Event handles may be signalled and waited on from any thread.
+    unsafe impl Send for OsEvent {}
+    unsafe impl Sync for OsEvent {}
+
+    impl OsEvent {
+        pub(super) fn new() -> io::Result<Self> {
+            // Manual reset, initially non-signalled, unnamed.
+            let handle = unsafe { CreateEventW(std::ptr::null(), 1, 0, std::ptr::null()) };
+            if handle.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { handle })
+        }
+
+        pub(super) fn handle(&self) -> std::os::windows::io::RawHandle {
+            self.handle
+        }
+
+        pub(super) fn set(&self) {
+            unsafe {
+                SetEvent(self.handle);
+            }
+        }
+
+        pub(super) fn reset(&self) {
+            unsafe {
+                ResetEvent(self.handle);
+            }
+        }
+    }
+
+    impl Drop for OsEvent {
+        fn drop(&mut self) {
+            unsafe {
+                CloseHandle(self.handle);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::fd::AsRawFd;
+
+    use super::*;
+
+    fn readable(notifier: &ReadyNotifier) -> bool {
+        let mut pollfd = libc::pollfd {
+            fd: notifier.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(&mut pollfd, 1, 0) == 1 }
+    }
+
+    #[test]
+    fn notifier_fd_is_readable_until_cleared() {
+        let notifier = ReadyNotifier::new().unwrap();
+        assert!(!readable(&notifier));
+        notifier.notify();
+        notifier.clone().notify();
+        assert!(readable(&notifier) && notifier.is_signalled());
+        notifier.clear();
+        assert!(!readable(&notifier) && !notifier.is_signalled());
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use crate::pipeline::{BoundedQueueRx, BoundedQueueTx, bounded_queue};
+
+// Wakes a session blocked in reap_timeout from another thread, e.g. on shutdown. The wait
+// returns Ok(None) and queued outputs stay where they are, so cancelling never drops a frame.
+// A cancel issued while nobody waits ends the next wait that finds nothing ready.
+#[derive(Debug, Clone)]
+pub struct ReapCanceller {
+    waker: ReadyWaker,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ReapCanceller {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+        self.waker.wake();
+    }
+}
+
+// Handed to backends that finish work on threads of their own (VideoToolbox callbacks), which
+// signal it whenever VideoDecoder::poll_ready has something new.
+#[derive(Debug, Clone)]
+pub(crate) struct ReadyWaker(BoundedQueueTx<()>);
+
+impl ReadyWaker {
+    pub(crate) fn wake(&self) {
+        // A full queue already holds a pending wake-up.
+        let _ = self.0.try_send(());
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ReapWaiter {
+    wake_tx: BoundedQueueTx<()>,
+    wake_rx: BoundedQueueRx<()>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ReapWaiter {
+    pub(crate) fn new() -> Self {
+        let (wake_tx, wake_rx) = bounded_queue(1);
+        Self {
+            wake_tx,
+            wake_rx,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub(crate) fn canceller(&self) -> ReapCanceller {
+        ReapCanceller {
+            waker: self.ready_waker(),
+            cancelled: Arc::clone(&self.cancelled),
+        }
+    }
+
+    pub(crate) fn ready_waker(&self) -> ReadyWaker {
+        ReadyWaker(self.wake_tx.clone())
+    }
+
+    // Blocks until a backend or canceller wakes the waiter or `deadline` passes (None waits
+    // without one). False once the wait is over: cancelled or past the deadline. Deadlines use
+    // the real clock since this blocks the thread, whatever clock the session has.
+    pub(crate) fn wait(&self, deadline: Option<Instant>) -> bool {
+        if self.cancelled.swap(false, Ordering::AcqRel) {
+            return false;
+        }
+        let woken = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(left) => self.wake_rx.recv_timeout(left).is_ok(),
+                None => false,
+            },
+            None => self.wake_rx.recv().is_ok(),
+        };
+        woken && !self.cancelled.swap(false, Ordering::AcqRel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn waits_end_on_ready_output_cancel_or_deadline() {
+        let waiter = ReapWaiter::new();
+        let canceller = waiter.canceller();
+        let cancel = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            canceller.cancel();
+        });
+        let started = Instant::now();
+        assert!(!waiter.wait(None));
+        cancel.join().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        let waker = waiter.ready_waker();
+        let wake = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            waker.wake();
+        });
+        assert!(waiter.wait(Instant::now().checked_add(Duration::from_secs(30))));
+        wake.join().unwrap();
+
+        assert!(!waiter.wait(Some(Instant::now())));
+    }
+}
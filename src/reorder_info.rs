@@ -0,0 +1,153 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+use crate::{EncodedChunk, FrameRate, Timestamp90k};
+
+// Submitted frames remembered until their chunk comes out; older entries belong to frames the
+// encoder dropped.
+const MAX_PENDING_FRAMES: usize = 256;
+
+// Fills in EncodedChunk::dts_90k and display_index so muxers can write ctts without parsing
+// slice headers. Chunks leave the encoder in decode order, and the Nth of them is decoded
+// when the Nth smallest submitted pts is due, less the reorder window (B-frames the encoder
+// holds back) so a reordered frame never gets a DTS after its own PTS. DTS is kept strictly
+// increasing across rebuilds.
+#[derive(Debug)]
+pub(crate) struct ReorderTracker {
+    pending_pts: BinaryHeap<Reverse<i64>>,
+    display_order: VecDeque<(i64, u64)>,
+    submitted: u64,
+    // Fixed by the first chunk after a (re)start, once the backend knows its reorder window.
+    shift_90k: Option<i64>,
+    last_dts: Option<i64>,
+}
+
+impl ReorderTracker {
+    pub(crate) fn new() -> Self {
+        Self {
+            pending_pts: BinaryHeap::new(),
+            display_order: VecDeque::new(),
+            submitted: 0,
+            shift_90k: None,
+            last_dts: None,
+        }
+    }
+
+    // Frames are submitted in presentation order; untimed frames still take a display index.
+    pub(crate) fn observe_submit(&mut self, pts_90k: Option<Timestamp90k>) {
+        let index = self.submitted;
+        self.submitted += 1;
+        let Some(pts) = pts_90k else {
+            return;
+        };
+        if self.display_order.len() == MAX_PENDING_FRAMES {
+            self.display_order.pop_front();
+        }
+        self.display_order.push_back((pts.0, index));
+        self.pending_pts.push(Reverse(pts.0));
+    }
+
+    // A rebuilt session may reorder differently; call once the old one is drained.
+    #[cfg(any(
+        test,
+        all(target_os = "macos", feature = "backend-vt"),
+        all(
+            feature = "backend-nvidia",
+            any(target_os = "linux", target_os = "windows")
+        )
+    ))]
+    pub(crate) fn restart(&mut self) {
+        self.pending_pts.clear();
+        self.display_order.clear();
+        self.shift_90k = None;
+    }
+
+    pub(crate) fn annotate(
+        &mut self,
+        chunk: &mut EncodedChunk,
+        reorder_frames: u32,
+        fps: FrameRate,
+    ) {
+        let Some(pts) = chunk.pts_90k else {
+            return;
+        };
+        if let Some(position) = self
+            .display_order
+            .iter()
+            .position(|&(submitted, _)| submitted == pts.0)
+        {
+            chunk.display_index = self.display_order.remove(position).map(|(_, index)| index);
+        }
+        let shift = *self
+            .shift_90k
+            .get_or_insert_with(|| i64::from(reorder_frames) * frame_duration_90k(fps));
+        let base = self.pending_pts.pop().map_or(pts.0, |Reverse(base)| base);
+        let dts = match self.last_dts {
+            Some(last) if base - shift <= last => last + 1,
+            _ => base - shift,
+        };
+        self.last_dts = Some(dts);
+        chunk.dts_90k = Some(Timestamp90k(dts));
+    }
+}
+
+fn frame_duration_90k(fps: FrameRate) -> i64 {
+    if !fps.is_known() {
+        return 0;
+    }
+    let fps = fps.normalized();
+    (90_000 * i64::from(fps.den) + i64::from(fps.num) / 2) / i64::from(fps.num)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Codec, EncodedLayout};
+
+    fn chunk(pts: i64) -> EncodedChunk {
+        EncodedChunk {
+            codec: Codec::H264,
+            layout: EncodedLayout::AnnexB,
+            data: Vec::new().into(),
+            pts_90k: Some(Timestamp90k(pts)),
+            is_keyframe: false,
+            filler_bytes: 0,
+            dts_90k: None,
+            display_index: None,
+        }
+    }
+
+    #[test]
+    fn b_frames_get_monotonic_dts_no_later_than_pts() {
+        let mut tracker = ReorderTracker::new();
+        for index in 0..7 {
+            tracker.observe_submit(Some(Timestamp90k(index * 3000)));
+        }
+        // IBBP with two B-frames: I0 P3 B1 B2 P6 B4 B5.
+        let annotated = [0, 3, 1, 2, 6, 4, 5].map(|frame| {
+            let mut chunk = chunk(frame * 3000);
+            tracker.annotate(&mut chunk, 2, FrameRate::new(30, 1));
+            chunk
+        });
+        let dts = annotated
+            .iter()
+            .map(|chunk| chunk.dts_90k.unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(dts, [-6000, -3000, 0, 3000, 6000, 9000, 12000]);
+        assert!(annotated.iter().all(|chunk| chunk.dts_90k <= chunk.pts_90k));
+        let order = annotated
+            .iter()
+            .map(|chunk| chunk.display_index.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(order, [0, 3, 1, 2, 6, 4, 5]);
+        assert_eq!(annotated[1].composition_offset_90k(), Some(12000));
+
+        // After a rebuild without B-frames DTS keeps increasing even though pts restart.
+        tracker.restart();
+        tracker.observe_submit(Some(Timestamp90k(0)));
+        let mut restarted = chunk(0);
+        tracker.annotate(&mut restarted, 0, FrameRate::new(30, 1));
+        assert_eq!(restarted.dts_90k, Some(Timestamp90k(12001)));
+        assert_eq!(restarted.display_index, Some(7));
+    }
+}
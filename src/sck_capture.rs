@@ -0,0 +1,456 @@
+use std::ffi::{CStr, c_char, c_void};
+use std::num::NonZeroU32;
+use std::ptr;
+use std::sync::OnceLock;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use block2::RcBlock;
+use core_foundation::array::{CFArrayGetCount, CFArrayGetValueAtIndex, CFArrayRef};
+use core_foundation::base::TCFType;
+use core_foundation::dictionary::{CFDictionaryGetValue, CFDictionaryRef};
+use core_foundation::number::{CFNumber, CFNumberRef};
+use core_foundation::string::CFStringRef;
+use core_media::sample_buffer::CMSampleBufferRef;
+use core_media::time::CMTime;
+use core_video::image_buffer::CVImageBufferRef;
+use core_video::pixel_buffer::{CVPixelBuffer, kCVPixelFormatType_32BGRA};
+use objc2::rc::{Allocated, Retained};
+use objc2::runtime::{AnyClass, AnyObject, Bool, NSObject, NSObjectProtocol};
+use objc2::{AllocAnyThread, DefinedClass, define_class, msg_send};
+
+use crate::pipeline::{BoundedQueueRx, BoundedQueueTx, QueueRecvError, bounded_queue};
+use crate::{BackendError, CaptureSource, CapturedFrame, Dimensions, DirtyRect, Timestamp90k};
+
+// SCStreamOutputTypeScreen, SCFrameStatusComplete and kCVPixelBufferLock_ReadOnly.
+const OUTPUT_TYPE_SCREEN: isize = 0;
+const FRAME_STATUS_COMPLETE: i64 = 0;
+const LOCK_READ_ONLY: u64 = 1;
+// Frames the stream may deliver ahead of next_frame; older ones are dropped beyond that.
+const FRAME_QUEUE_DEPTH: usize = 4;
+const COMPLETION_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct CGRect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+#[link(name = "ScreenCaptureKit", kind = "framework")]
+unsafe extern "C" {
+    static SCStreamFrameInfoStatus: CFStringRef;
+    static SCStreamFrameInfoDirtyRects: CFStringRef;
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+unsafe extern "C" {
+    fn CGRectMakeWithDictionaryRepresentation(dict: CFDictionaryRef, rect: *mut CGRect) -> bool;
+    fn CGDisplayCopyDisplayMode(display: u32) -> *mut c_void;
+    fn CGDisplayModeGetPixelWidth(mode: *mut c_void) -> usize;
+    fn CGDisplayModeGetPixelHeight(mode: *mut c_void) -> usize;
+    fn CGDisplayModeRelease(mode: *mut c_void);
+}
+
+#[link(name = "CoreMedia", kind = "framework")]
+unsafe extern "C" {
+    fn CMSampleBufferGetImageBuffer(sbuf: CMSampleBufferRef) -> CVImageBufferRef;
+    fn CMSampleBufferGetSampleAttachmentsArray(sbuf: CMSampleBufferRef, create: u8) -> CFArrayRef;
+    fn CMSampleBufferGetPresentationTimeStamp(sbuf: CMSampleBufferRef) -> CMTime;
+}
+
+unsafe extern "C" {
+    fn dispatch_queue_create(label: *const c_char, attr: *mut c_void) -> *mut AnyObject;
+}
+
+struct OutputState {
+    frames: BoundedQueueTx<CapturedFrame>,
+    first_pts: OnceLock<i64>,
+}
+
+define_class!(
+    // SCStreamOutput receiver; runs on the stream's serial sample handler queue.
+    #[unsafe(super(NSObject))]
+    #[name = "VideoHwScreenCaptureOutput"]
+    #[ivars = OutputState]
+    struct CaptureOutput;
+
+    unsafe impl NSObjectProtocol for CaptureOutput {}
+
+    impl CaptureOutput {
+        #[unsafe(method(stream:didOutputSampleBuffer:ofType:))]
+        fn did_output_sample_buffer(
+            &self,
+            _stream: *mut AnyObject,
+            sample: *mut c_void,
+            output_type: isize,
+        ) {
+            if output_type != OUTPUT_TYPE_SCREEN || sample.is_null() {
+                return;
+            }
+            let state = self.ivars();
+            let frame = unsafe { read_sample(sample as CMSampleBufferRef, &state.first_pts) };
+            if let Some(frame) = frame {
+                // A full queue means the caller is behind; the frame is dropped like a missed
+                // refresh rather than stalling the capture queue.
+                let _ = state.frames.try_send(frame);
+            }
+        }
+    }
+);
+
+impl CaptureOutput {
+    fn new(state: OutputState) -> Retained<Self> {
+        let this = Self::alloc().set_ivars(state);
+        unsafe { msg_send![super(this), init] }
+    }
+}
+
+// ScreenCaptureKit capture of one display. Frames arrive as BGRA at the display's pixel size
+// with the dirty rects ScreenCaptureKit reports; idle frames (nothing changed) are not
+// delivered. Needs the screen recording permission, otherwise new() fails.
+pub struct ScreenCaptureKitSource {
+    stream: Retained<AnyObject>,
+    // Dropped after Drop has stopped the stream, so no sample arrives without a receiver.
+    _output: Retained<CaptureOutput>,
+    _queue: Retained<AnyObject>,
+    frames: BoundedQueueRx<CapturedFrame>,
+    dims: Dimensions,
+}
+
+// SCStream may be driven from any thread; samples are handed over through the queue.
+unsafe impl Send for ScreenCaptureKitSource {}
+
+impl ScreenCaptureKitSource {
+    // Displays are counted in SCShareableContent order, where 0 is the main display.
+    pub fn new(display_index: usize) -> Result<Self, BackendError> {
+        let content = shareable_content()?;
+        unsafe {
+            let displays: *mut AnyObject = msg_send![&*content, displays];
+            let count: usize = msg_send![displays, count];
+            if display_index >= count {
+                return Err(BackendError::InvalidInput(format!(
+                    "display {display_index} not found, {count} available"
+                )));
+            }
+            let display: *mut AnyObject = msg_send![displays, objectAtIndex: display_index];
+            let dims = display_pixel_size(display)?;
+
+            let windows: Retained<AnyObject> = msg_send![class(c"NSArray")?, array];
+            let filter: Allocated<AnyObject> = msg_send![class(c"SCContentFilter")?, alloc];
+            let filter: Option<Retained<AnyObject>> =
+                msg_send![filter, initWithDisplay: display, excludingWindows: &*windows];
+            let filter = filter.ok_or_else(|| sck_error("SCContentFilter init", None))?;
+
+            let config: Retained<AnyObject> = msg_send![class(c"SCStreamConfiguration")?, new];
+            let _: () = msg_send![&*config, setWidth: dims.width.get() as usize];
+            let _: () = msg_send![&*config, setHeight: dims.height.get() as usize];
+            let _: () = msg_send![&*config, setPixelFormat: kCVPixelFormatType_32BGRA];
+            let _: () = msg_send![&*config, setShowsCursor: Bool::YES];
+
+            let stream: Allocated<AnyObject> = msg_send![class(c"SCStream")?, alloc];
+            let stream: Option<Retained<AnyObject>> = msg_send![
+                stream,
+                initWithFilter: &*filter,
+                configuration: &*config,
+                delegate: ptr::null_mut::<AnyObject>()
+            ];
+            let stream = stream.ok_or_else(|| sck_error("SCStream init", None))?;
+
+            let (frames_tx, frames) = bounded_queue(FRAME_QUEUE_DEPTH);
+            let output = CaptureOutput::new(OutputState {
+                frames: frames_tx,
+                first_pts: OnceLock::new(),
+            });
+            let queue = Retained::from_raw(dispatch_queue_create(
+                c"video-hw.screen-capture".as_ptr(),
+                ptr::null_mut(),
+            ))
+            .ok_or_else(|| sck_error("dispatch_queue_create", None))?;
+            let mut error: *mut AnyObject = ptr::null_mut();
+            let added: Bool = msg_send![
+                &*stream,
+                addStreamOutput: &*output,
+                type: OUTPUT_TYPE_SCREEN,
+                sampleHandlerQueue: &*queue,
+                error: &mut error
+            ];
+            if !added.as_bool() {
+                return Err(sck_error("addStreamOutput", Some(error)));
+            }
+
+            let (done_tx, done_rx) = mpsc::sync_channel(1);
+            let handler = RcBlock::new(move |error: *mut AnyObject| {
+                let _ = done_tx.send((!error.is_null()).then(|| ns_error_message(error)));
+            });
+            let _: () = msg_send![&*stream, startCaptureWithCompletionHandler: &*handler];
+            match done_rx.recv_timeout(COMPLETION_TIMEOUT) {
+                Ok(None) => {}
+                Ok(Some(message)) => {
+                    return Err(BackendError::Backend(format!(
+                        "startCapture failed: {message}"
+                    )));
+                }
+                Err(_) => {
+                    return Err(BackendError::Backend(
+                        "startCapture did not complete".to_string(),
+                    ));
+                }
+            }
+
+            Ok(Self {
+                stream,
+                _output: output,
+                _queue: queue,
+                frames,
+                dims,
+            })
+        }
+    }
+}
+
+impl CaptureSource for ScreenCaptureKitSource {
+    fn dims(&self) -> Dimensions {
+        self.dims
+    }
+
+    fn next_frame(&mut self, timeout: Duration) -> Result<Option<CapturedFrame>, BackendError> {
+        match self.frames.recv_timeout(timeout) {
+            Ok(frame) => {
+                self.dims = frame.dims;
+                Ok(Some(frame))
+            }
+            Err(QueueRecvError::Timeout) => Ok(None),
+            Err(QueueRecvError::Disconnected) => Err(BackendError::Backend(
+                "screen capture stream stopped".to_string(),
+            )),
+        }
+    }
+}
+
+impl Drop for ScreenCaptureKitSource {
+    fn drop(&mut self) {
+        let (done_tx, done_rx) = mpsc::sync_channel(1);
+        let handler = RcBlock::new(move |_error: *mut AnyObject| {
+            let _ = done_tx.send(());
+        });
+        unsafe {
+            let _: () = msg_send![&*self.stream, stopCaptureWithCompletionHandler: &*handler];
+        }
+        let _ = done_rx.recv_timeout(COMPLETION_TIMEOUT);
+    }
+}
+
+struct SendObject(Retained<AnyObject>);
+
+// Only moved from the completion handler's thread to the waiting caller.
+unsafe impl Send for SendObject {}
+
+fn shareable_content() -> Result<Retained<AnyObject>, BackendError> {
+    let (done_tx, done_rx) = mpsc::sync_channel(1);
+    let handler = RcBlock::new(move |content: *mut AnyObject, error: *mut AnyObject| {
+        let result = match unsafe { Retained::retain(content) } {
+            Some(content) => Ok(SendObject(content)),
+            None => Err(ns_error_message(error)),
+        };
+        let _ = done_tx.send(result);
+    });
+    unsafe {
+        let _: () = msg_send![
+            class(c"SCShareableContent")?,
+            getShareableContentWithCompletionHandler: &*handler
+        ];
+    }
+    match done_rx.recv_timeout(COMPLETION_TIMEOUT) {
+        Ok(Ok(SendObject(content))) => Ok(content),
+        Ok(Err(message)) => Err(BackendError::Backend(format!(
+            "SCShareableContent unavailable (screen recording permission?): {message}"
+        ))),
+        Err(_) => Err(BackendError::Backend(
+            "SCShareableContent did not complete".to_string(),
+        )),
+    }
+}
+
+// SCDisplay reports points; the stream is configured in pixels of the current display mode.
+unsafe fn display_pixel_size(display: *mut AnyObject) -> Result<Dimensions, BackendError> {
+    unsafe {
+        let display_id: u32 = msg_send![display, displayID];
+        let mode = CGDisplayCopyDisplayMode(display_id);
+        if !mode.is_null() {
+            let width = CGDisplayModeGetPixelWidth(mode);
+            let height = CGDisplayModeGetPixelHeight(mode);
+            CGDisplayModeRelease(mode);
+            return dimensions(width, height);
+        }
+        let width: isize = msg_send![display, width];
+        let height: isize = msg_send![display, height];
+        dimensions(width.max(0) as usize, height.max(0) as usize)
+    }
+}
+
+// Frames that fail to map are dropped; the caller sees a gap, as with a missed refresh.
+unsafe fn read_sample(
+    sample: CMSampleBufferRef,
+    first_pts: &OnceLock<i64>,
+) -> Option<CapturedFrame> {
+    unsafe {
+        let info = frame_info(sample)?;
+        let status = CFDictionaryGetValue(info, SCStreamFrameInfoStatus.cast()) as CFNumberRef;
+        if status.is_null()
+            || CFNumber::wrap_under_get_rule(status).to_i64() != Some(FRAME_STATUS_COMPLETE)
+        {
+            return None;
+        }
+        let image = CMSampleBufferGetImageBuffer(sample);
+        if image.is_null() {
+            return None;
+        }
+        let pixel_buffer = CVPixelBuffer::wrap_under_get_rule(image);
+        let dims = dimensions(pixel_buffer.get_width(), pixel_buffer.get_height()).ok()?;
+        let dirty_rects = frame_dirty_rects(info, dims);
+        let pts_90k = cm_time_to_90k(CMSampleBufferGetPresentationTimeStamp(sample))
+            .map(|pts| Timestamp90k(pts - *first_pts.get_or_init(|| pts)));
+
+        if pixel_buffer.lock_base_address(LOCK_READ_ONLY) != 0 {
+            return None;
+        }
+        let stride = pixel_buffer.get_bytes_per_row();
+        let base = pixel_buffer.get_base_address() as *const u8;
+        let frame = (!base.is_null())
+            .then(|| {
+                let data = std::slice::from_raw_parts(base, stride * dims.height.get() as usize);
+                CapturedFrame::from_bgra_strided(dims, stride, data, pts_90k, dirty_rects).ok()
+            })
+            .flatten();
+        pixel_buffer.unlock_base_address(LOCK_READ_ONLY);
+        frame
+    }
+}
+
+unsafe fn frame_info(sample: CMSampleBufferRef) -> Option<CFDictionaryRef> {
+    unsafe {
+        let attachments = CMSampleBufferGetSampleAttachmentsArray(sample, 0);
+        if attachments.is_null() || CFArrayGetCount(attachments) < 1 {
+            return None;
+        }
+        let info = CFArrayGetValueAtIndex(attachments, 0) as CFDictionaryRef;
+        (!info.is_null()).then_some(info)
+    }
+}
+
+unsafe fn frame_dirty_rects(info: CFDictionaryRef, dims: Dimensions) -> Option<Vec<DirtyRect>> {
+    unsafe {
+        let rects = CFDictionaryGetValue(info, SCStreamFrameInfoDirtyRects.cast()) as CFArrayRef;
+        if rects.is_null() {
+            return None;
+        }
+        Some(
+            (0..CFArrayGetCount(rects))
+                .filter_map(|index| {
+                    let mut rect = CGRect::default();
+                    let entry = CFArrayGetValueAtIndex(rects, index) as CFDictionaryRef;
+                    CGRectMakeWithDictionaryRepresentation(entry, &mut rect)
+                        .then(|| clip_rect(rect, dims))
+                        .flatten()
+                })
+                .collect(),
+        )
+    }
+}
+
+fn clip_rect(rect: CGRect, dims: Dimensions) -> Option<DirtyRect> {
+    let clamp = |value: f64, limit: NonZeroU32| value.clamp(0.0, f64::from(limit.get())) as u32;
+    let left = clamp(rect.x.floor(), dims.width);
+    let top = clamp(rect.y.floor(), dims.height);
+    let right = clamp((rect.x + rect.width).ceil(), dims.width);
+    let bottom = clamp((rect.y + rect.height).ceil(), dims.height);
+    (right > left && bottom > top).then(|| DirtyRect {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+    })
+}
+
+fn cm_time_to_90k(time: CMTime) -> Option<i64> {
+    if time.timescale <= 0 {
+        return None;
+    }
+    let scaled = i128::from(time.value) * 90_000 / i128::from(time.timescale);
+    i64::try_from(scaled).ok()
+}
+
+fn dimensions(width: usize, height: usize) -> Result<Dimensions, BackendError> {
+    match (
+        u32::try_from(width).ok().and_then(NonZeroU32::new),
+        u32::try_from(height).ok().and_then(NonZeroU32::new),
+    ) {
+        (Some(width), Some(height)) => Ok(Dimensions { width, height }),
+        _ => Err(BackendError::Backend(format!(
+            "screen capture reported empty display {width}x{height}"
+        ))),
+    }
+}
+
+fn class(name: &CStr) -> Result<&'static AnyClass, BackendError> {
+    AnyClass::get(name).ok_or_else(|| {
+        BackendError::UnsupportedConfig(format!(
+            "{} is unavailable; ScreenCaptureKit needs macOS 12.3 or newer",
+            name.to_string_lossy()
+        ))
+    })
+}
+
+fn ns_error_message(error: *mut AnyObject) -> String {
+    if error.is_null() {
+        return "unknown error".to_string();
+    }
+    unsafe {
+        let description: *mut AnyObject = msg_send![error, localizedDescription];
+        if description.is_null() {
+            return "unknown error".to_string();
+        }
+        let utf8: *const c_char = msg_send![description, UTF8String];
+        if utf8.is_null() {
+            return "unknown error".to_string();
+        }
+        CStr::from_ptr(utf8).to_string_lossy().into_owned()
+    }
+}
+
+fn sck_error(call: &str, error: Option<*mut AnyObject>) -> BackendError {
+    match error {
+        Some(error) => BackendError::Backend(format!("{call} failed: {}", ns_error_message(error))),
+        None => BackendError::Backend(format!("{call} failed")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clip_rect_rounds_outwards_and_keeps_rects_inside_the_display() {
+        let dims = dimensions(64, 32).expect("dims");
+        let rect = |x, y, width, height| CGRect {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(
+            clip_rect(rect(-8.0, 4.5, 24.0, 40.0), dims),
+            Some(DirtyRect {
+                x: 0,
+                y: 4,
+                width: 16,
+                height: 28,
+            })
+        );
+        assert_eq!(clip_rect(rect(70.0, 0.0, 10.0, 8.0), dims), None);
+        assert_eq!(clip_rect(rect(8.0, 8.0, 0.0, 8.0), dims), None);
+    }
+}
@@ -0,0 +1,309 @@
+use std::fmt::{self, Display};
+use std::num::NonZeroU32;
+use std::time::Duration;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+use std::time::Instant;
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+use crate::{
+    Backend, BackendError, DecodeSession, DecoderConfig, EncodeFrame, EncodeSession, EncodedChunk,
+    EncoderConfig, FrameRate, RawFrameBuffer, Timestamp90k,
+};
+use crate::{Codec, DecodedFrame, Dimensions};
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+const SELF_TEST_FRAMES: u32 = 8;
+// Small enough to finish in well under a second, above every backend's minimum encode size.
+const SELF_TEST_WIDTH: u32 = 320;
+const SELF_TEST_HEIGHT: u32 = 240;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTestStage {
+    Encode,
+    Decode,
+    Verify,
+}
+
+impl Display for SelfTestStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Encode => "encode",
+            Self::Decode => "decode",
+            Self::Verify => "verify",
+        })
+    }
+}
+
+// Outcome of self_test. Stage times cover submit through flush of the whole clip, session
+// creation included, so a slow driver initialisation shows up here too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestReport {
+    pub backend: String,
+    pub codec: Codec,
+    pub dims: Dimensions,
+    pub frames_submitted: u32,
+    pub chunks_encoded: usize,
+    pub frames_decoded: usize,
+    pub encode_time: Option<Duration>,
+    pub decode_time: Option<Duration>,
+    // Why the decode stage did not run although encoding worked: VideoToolbox keeps SPS/PPS in
+    // its format description, so its chunks cannot be decoded on their own.
+    pub decode_skipped: Option<String>,
+    pub failure: Option<(SelfTestStage, String)>,
+}
+
+impl SelfTestReport {
+    fn new(backend: String, codec: Codec) -> Self {
+        Self {
+            backend,
+            codec,
+            dims: self_test_dims(),
+            frames_submitted: 0,
+            chunks_encoded: 0,
+            frames_decoded: 0,
+            encode_time: None,
+            decode_time: None,
+            decode_skipped: None,
+            failure: None,
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    // Every frame came back once at the submitted size; frames without known dims only count.
+    fn verify(&mut self, decoded: &[DecodedFrame]) {
+        let expected = self.frames_submitted as usize;
+        let failure = if self.chunks_encoded != expected {
+            Some(format!(
+                "encoded {} chunks for {expected} frames",
+                self.chunks_encoded
+            ))
+        } else if self.decode_skipped.is_none() && decoded.len() != expected {
+            Some(format!("decoded {} of {expected} frames", decoded.len()))
+        } else {
+            decoded
+                .iter()
+                .filter_map(DecodedFrame::dims)
+                .find(|dims| *dims != self.dims)
+                .map(|dims| format!("decoded a {dims} frame, expected {}", self.dims))
+        };
+        self.failure = failure.map(|reason| (SelfTestStage::Verify, reason));
+    }
+}
+
+impl Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[self_test] {} {} {}",
+            self.backend, self.codec, self.dims
+        )?;
+        match &self.failure {
+            None => f.write_str(" PASS")?,
+            Some((stage, reason)) => write!(f, " FAIL stage={stage} error={reason}")?,
+        }
+        write!(
+            f,
+            " frames={} chunks={} decoded={}",
+            self.frames_submitted, self.chunks_encoded, self.frames_decoded
+        )?;
+        for (stage, time) in [("encode", self.encode_time), ("decode", self.decode_time)] {
+            if let Some(time) = time {
+                write!(f, " {stage}_ms={:.3}", time.as_secs_f64() * 1_000.0)?;
+            }
+        }
+        if let Some(reason) = &self.decode_skipped {
+            write!(f, " decode=skip reason={reason}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+// One-call install check: encodes a short synthetic H.264 clip on `backend` with hardware
+// required, decodes it back on the same backend and checks frame counts and sizes. Never
+// panics; a missing driver or device comes back as an encode failure.
+pub fn self_test(backend: Backend) -> SelfTestReport {
+    let mut report = SelfTestReport::new(backend.to_string(), Codec::H264);
+    let fps = FrameRate::new(30, 1);
+    let started = Instant::now();
+    let chunks = match encode_clip(backend, report.dims, fps, &mut report.frames_submitted) {
+        Ok(chunks) => chunks,
+        Err(err) => {
+            report.failure = Some((SelfTestStage::Encode, err.to_string()));
+            return report;
+        }
+    };
+    report.encode_time = Some(started.elapsed());
+    report.chunks_encoded = chunks.len();
+
+    let self_contained = chunks.iter().any(|chunk| {
+        chunk
+            .nal_units()
+            .is_ok_and(|nals| nals.iter().any(|nal| nal.nal_type == 7))
+    });
+    let mut decoded = Vec::new();
+    if self_contained {
+        let started = Instant::now();
+        if let Err(err) = decode_clip(backend, fps, chunks, &mut decoded) {
+            report.frames_decoded = decoded.len();
+            report.failure = Some((SelfTestStage::Decode, err.to_string()));
+            return report;
+        }
+        report.decode_time = Some(started.elapsed());
+        report.frames_decoded = decoded.len();
+    } else {
+        report.decode_skipped = Some("encoded chunks carry no SPS/PPS".to_string());
+    }
+    report.verify(&decoded);
+    report
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+fn encode_clip(
+    backend: Backend,
+    dims: Dimensions,
+    fps: FrameRate,
+    submitted: &mut u32,
+) -> Result<Vec<EncodedChunk>, BackendError> {
+    let mut session = EncodeSession::new(backend, EncoderConfig::new(Codec::H264, fps, true));
+    let pixels = (dims.width.get() * dims.height.get()) as usize;
+    let mut chunks = Vec::new();
+    for index in 0..SELF_TEST_FRAMES {
+        let shade = (index * 255 / SELF_TEST_FRAMES) as u8;
+        session.submit(EncodeFrame {
+            dims,
+            pts_90k: Some(Timestamp90k(fps.pts_90k(i64::from(index)))),
+            buffer: RawFrameBuffer::Argb8888([0xff, shade, 0x80, 0xff - shade].repeat(pixels)),
+            force_keyframe: index == 0,
+            dirty_rects: None,
+            repeat_count: 0,
+        })?;
+        *submitted += 1;
+        while let Some(chunk) = session.try_reap()? {
+            chunks.push(chunk);
+        }
+    }
+    chunks.extend(session.close()?);
+    Ok(chunks)
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+fn decode_clip(
+    backend: Backend,
+    fps: FrameRate,
+    chunks: Vec<EncodedChunk>,
+    decoded: &mut Vec<DecodedFrame>,
+) -> Result<(), BackendError> {
+    let mut session = DecodeSession::new(backend, DecoderConfig::new(Codec::H264, fps, true));
+    for chunk in chunks {
+        session.submit(chunk.into())?;
+        while let Some(frame) = session.try_reap()? {
+            decoded.push(frame);
+        }
+    }
+    decoded.extend(session.close()?);
+    Ok(())
+}
+
+fn self_test_dims() -> Dimensions {
+    Dimensions {
+        width: NonZeroU32::new(SELF_TEST_WIDTH).expect("self-test width is non-zero"),
+        height: NonZeroU32::new(SELF_TEST_HEIGHT).expect("self-test height is non-zero"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(dims: Option<Dimensions>) -> DecodedFrame {
+        DecodedFrame::test_metadata(dims, None, None)
+    }
+
+    #[test]
+    fn verify_checks_counts_and_sizes() {
+        let mut report = SelfTestReport::new("nvidia".to_string(), Codec::H264);
+        report.frames_submitted = 2;
+        report.chunks_encoded = 2;
+        report.frames_decoded = 2;
+        report.encode_time = Some(Duration::from_millis(12));
+        report.decode_time = Some(Duration::from_micros(4500));
+        report.verify(&[frame(Some(self_test_dims())), frame(None)]);
+        assert!(report.passed());
+        assert_eq!(
+            report.to_string(),
+            "[self_test] nvidia h264 320x240 PASS frames=2 chunks=2 decoded=2 encode_ms=12.000 decode_ms=4.500"
+        );
+
+        let small = Dimensions {
+            width: NonZeroU32::new(160).unwrap(),
+            height: NonZeroU32::new(120).unwrap(),
+        };
+        report.verify(&[frame(Some(self_test_dims())), frame(Some(small))]);
+        assert_eq!(
+            report.failure,
+            Some((
+                SelfTestStage::Verify,
+                "decoded a 160x120 frame, expected 320x240".to_string()
+            ))
+        );
+
+        report.verify(&[frame(None)]);
+        assert!(!report.passed());
+        // Without a decode stage only the encoder output is checked.
+        report.decode_skipped = Some("encoded chunks carry no SPS/PPS".to_string());
+        report.verify(&[]);
+        assert!(report.passed());
+
+        for (stage, name) in [
+            (SelfTestStage::Encode, "encode"),
+            (SelfTestStage::Decode, "decode"),
+        ] {
+            report.failure = Some((stage, "session closed".to_string()));
+            assert!(
+                report
+                    .to_string()
+                    .contains(&format!(" FAIL stage={name} error=session closed "))
+            );
+        }
+    }
+}
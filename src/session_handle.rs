@@ -0,0 +1,438 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::{
+    Backend, BackendError, BitstreamInput, DecodeSession, DecodeSummary, DecodedFrame,
+    DecoderConfig, Diagnostics, EncodeArbiter, EncodeFrame, EncodePriority, EncodeSession,
+    EncodedChunk, EncoderConfig, ReadyNotifier, SessionSwitchRequest, Timestamp90k,
+};
+
+// Backend sessions are not guaranteed to be Send (VideoToolbox sessions are CF objects bound to
+// their creating thread in practice), so the session lives on a dedicated worker thread and the
+// two handles only own channel endpoints. Errors raised by the worker are delivered in order on
+// the reaper side, right where the output of the failing command would have appeared.
+
+// Created by the first ready_notifier() call; until then the worker has nothing to signal.
+type ReadySignal = Arc<OnceLock<ReadyNotifier>>;
+
+enum DecodeCommand {
+    Submit(BitstreamInput),
+    Drain,
+    Flush,
+}
+
+enum EncodeCommand {
+    Submit(EncodeFrame),
+    Flush,
+    SessionSwitch(SessionSwitchRequest),
+    InvalidateReference(Timestamp90k),
+}
+
+pub struct DecodeSubmitter {
+    commands: Option<Sender<DecodeCommand>>,
+    summary: Arc<Mutex<DecodeSummary>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+pub struct DecodeReaper {
+    outputs: Receiver<Result<DecodedFrame, BackendError>>,
+    ready: ReadySignal,
+}
+
+pub struct EncodeSubmitter {
+    commands: Option<Sender<EncodeCommand>>,
+    priority: Arc<Mutex<EncodePriority>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+pub struct EncodeReaper {
+    outputs: Receiver<Result<EncodedChunk, BackendError>>,
+    ready: ReadySignal,
+}
+
+impl DecodeSession {
+    pub fn split(backend: Backend, config: DecoderConfig) -> (DecodeSubmitter, DecodeReaper) {
+        Self::split_with_diagnostics(backend, config, Diagnostics::default())
+    }
+
+    pub fn split_with_diagnostics(
+        backend: Backend,
+        config: DecoderConfig,
+        diagnostics: Diagnostics,
+    ) -> (DecodeSubmitter, DecodeReaper) {
+        let (command_tx, command_rx) = mpsc::channel::<DecodeCommand>();
+        let (output_tx, output_rx) = mpsc::channel();
+        let summary = Arc::new(Mutex::new(DecodeSummary {
+            decoded_frames: 0,
+            width: None,
+            height: None,
+            pixel_format: None,
+        }));
+        let worker_summary = Arc::clone(&summary);
+        let ready = ReadySignal::default();
+        let worker_ready = Arc::clone(&ready);
+        let worker = std::thread::spawn(move || {
+            let mut session = DecodeSession::with_diagnostics(backend, config, diagnostics);
+            for command in command_rx {
+                let result = match command {
+                    DecodeCommand::Submit(input) => {
+                        session.submit(input).and_then(|()| session.drain_ready())
+                    }
+                    DecodeCommand::Drain => session.drain_available(),
+                    DecodeCommand::Flush => session.flush(),
+                };
+                if let Ok(mut summary) = worker_summary.lock() {
+                    *summary = session.summary();
+                }
+                if !forward_outputs(&output_tx, &worker_ready, result) {
+                    break;
+                }
+            }
+        });
+        (
+            DecodeSubmitter {
+                commands: Some(command_tx),
+                summary,
+                worker: Some(worker),
+            },
+            DecodeReaper {
+                outputs: output_rx,
+                ready,
+            },
+        )
+    }
+
+    fn drain_ready(&mut self) -> Result<Vec<DecodedFrame>, BackendError> {
+        let mut out = Vec::new();
+        while let Some(frame) = self.try_reap()? {
+            out.push(frame);
+        }
+        Ok(out)
+    }
+}
+
+impl EncodeSession {
+    pub fn split(backend: Backend, config: EncoderConfig) -> (EncodeSubmitter, EncodeReaper) {
+        Self::split_with_diagnostics(backend, config, Diagnostics::default())
+    }
+
+    pub fn split_with_diagnostics(
+        backend: Backend,
+        config: EncoderConfig,
+        diagnostics: Diagnostics,
+    ) -> (EncodeSubmitter, EncodeReaper) {
+        spawn_encode_worker(
+            backend,
+            config,
+            diagnostics,
+            None,
+            EncodePriority::default(),
+        )
+    }
+
+    // Like split_with_diagnostics, but every submit and flush first takes a permit from
+    // `arbiter`, which sessions sharing one GPU should have in common. The priority can be
+    // changed later through EncodeSubmitter::set_priority.
+    pub fn split_with_arbiter(
+        backend: Backend,
+        config: EncoderConfig,
+        diagnostics: Diagnostics,
+        arbiter: &EncodeArbiter,
+        priority: EncodePriority,
+    ) -> (EncodeSubmitter, EncodeReaper) {
+        spawn_encode_worker(
+            backend,
+            config,
+            diagnostics,
+            Some(arbiter.clone()),
+            priority,
+        )
+    }
+
+    fn drain_ready(&mut self) -> Result<Vec<EncodedChunk>, BackendError> {
+        let mut out = Vec::new();
+        while let Some(chunk) = self.try_reap()? {
+            out.push(chunk);
+        }
+        Ok(out)
+    }
+}
+
+fn spawn_encode_worker(
+    backend: Backend,
+    config: EncoderConfig,
+    diagnostics: Diagnostics,
+    arbiter: Option<EncodeArbiter>,
+    priority: EncodePriority,
+) -> (EncodeSubmitter, EncodeReaper) {
+    let (command_tx, command_rx) = mpsc::channel::<EncodeCommand>();
+    let (output_tx, output_rx) = mpsc::channel();
+    let priority = Arc::new(Mutex::new(priority));
+    let worker_priority = Arc::clone(&priority);
+    let ready = ReadySignal::default();
+    let worker_ready = Arc::clone(&ready);
+    let worker = std::thread::spawn(move || {
+        let mut session = EncodeSession::with_diagnostics(backend, config, diagnostics);
+        let admit = || {
+            arbiter.as_ref().map(|arbiter| {
+                let priority = *worker_priority
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                arbiter.acquire(priority)
+            })
+        };
+        for command in command_rx {
+            let result = match command {
+                EncodeCommand::Submit(frame) => {
+                    let _permit = admit();
+                    session.submit(frame).and_then(|()| session.drain_ready())
+                }
+                EncodeCommand::Flush => {
+                    let _permit = admit();
+                    session.flush()
+                }
+                EncodeCommand::SessionSwitch(request) => session
+                    .request_session_switch(request)
+                    .and_then(|()| session.drain_ready()),
+                EncodeCommand::InvalidateReference(pts_90k) => {
+                    session.invalidate_reference(pts_90k).map(|()| Vec::new())
+                }
+            };
+            if !forward_outputs(&output_tx, &worker_ready, result) {
+                break;
+            }
+        }
+    });
+    (
+        EncodeSubmitter {
+            commands: Some(command_tx),
+            priority,
+            worker: Some(worker),
+        },
+        EncodeReaper {
+            outputs: output_rx,
+            ready,
+        },
+    )
+}
+
+fn forward_outputs<T>(
+    outputs: &Sender<Result<T, BackendError>>,
+    ready: &ReadySignal,
+    result: Result<Vec<T>, BackendError>,
+) -> bool {
+    let sent_any = !matches!(&result, Ok(items) if items.is_empty());
+    let connected = match result {
+        Ok(items) => items.into_iter().all(|item| outputs.send(Ok(item)).is_ok()),
+        Err(err) => outputs.send(Err(err)).is_ok(),
+    };
+    if sent_any && let Some(notifier) = ready.get() {
+        notifier.notify();
+    }
+    connected
+}
+
+fn ready_notifier(ready: &ReadySignal) -> Result<ReadyNotifier, BackendError> {
+    if let Some(notifier) = ready.get() {
+        return Ok(notifier.clone());
+    }
+    let created = ReadyNotifier::new()
+        .map_err(|err| BackendError::Backend(format!("failed to create ready notifier: {err}")))?;
+    // Output may already be queued, so start signalled and let the first drain reset it.
+    created.notify();
+    Ok(ready.get_or_init(|| created).clone())
+}
+
+fn send_command<T>(commands: &Option<Sender<T>>, command: T) -> Result<(), BackendError> {
+    commands
+        .as_ref()
+        .and_then(|commands| commands.send(command).ok())
+        .ok_or_else(|| BackendError::Backend("session worker has stopped".to_string()))
+}
+
+fn join_worker(worker: &mut Option<JoinHandle<()>>) -> Result<(), BackendError> {
+    match worker.take() {
+        Some(worker) => worker
+            .join()
+            .map_err(|_| BackendError::Backend("session worker panicked".to_string())),
+        None => Ok(()),
+    }
+}
+
+fn recv_output<T>(
+    outputs: &Receiver<Result<T, BackendError>>,
+    timeout: Duration,
+) -> Result<Option<T>, BackendError> {
+    match outputs.recv_timeout(timeout) {
+        Ok(item) => item.map(Some),
+        Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => Ok(None),
+    }
+}
+
+fn try_recv_output<T>(
+    outputs: &Receiver<Result<T, BackendError>>,
+) -> Result<Option<T>, BackendError> {
+    match outputs.try_recv() {
+        Ok(item) => item.map(Some),
+        Err(TryRecvError::Empty | TryRecvError::Disconnected) => Ok(None),
+    }
+}
+
+// Resets the notifier once the queue is seen empty. The worker signals after sending, so an
+// output that slips in before the reset is caught by the second receive and re-signalled.
+fn try_recv_notified<T>(
+    outputs: &Receiver<Result<T, BackendError>>,
+    ready: &ReadySignal,
+) -> Result<Option<T>, BackendError> {
+    match (outputs.try_recv(), ready.get()) {
+        (Ok(item), _) => item.map(Some),
+        (Err(TryRecvError::Empty | TryRecvError::Disconnected), None) => Ok(None),
+        (Err(TryRecvError::Empty | TryRecvError::Disconnected), Some(notifier)) => {
+            notifier.clear();
+            let item = try_recv_output(outputs);
+            if !matches!(item, Ok(None)) {
+                notifier.notify();
+            }
+            item
+        }
+    }
+}
+
+impl DecodeSubmitter {
+    pub fn submit(&mut self, input: BitstreamInput) -> Result<(), BackendError> {
+        send_command(&self.commands, DecodeCommand::Submit(input))
+    }
+
+    pub fn drain_available(&mut self) -> Result<(), BackendError> {
+        send_command(&self.commands, DecodeCommand::Drain)
+    }
+
+    pub fn flush(&mut self) -> Result<(), BackendError> {
+        send_command(&self.commands, DecodeCommand::Flush)
+    }
+
+    // Flushes and stops the worker; the reaper ends once the remaining frames are taken.
+    pub fn finish(mut self) -> Result<DecodeSummary, BackendError> {
+        self.flush()?;
+        self.commands = None;
+        join_worker(&mut self.worker)?;
+        Ok(self.summary())
+    }
+
+    pub fn summary(&self) -> DecodeSummary {
+        self.summary
+            .lock()
+            .map(|summary| summary.clone())
+            .unwrap_or(DecodeSummary {
+                decoded_frames: 0,
+                width: None,
+                height: None,
+                pixel_format: None,
+            })
+    }
+}
+
+impl Drop for DecodeSubmitter {
+    fn drop(&mut self) {
+        self.commands = None;
+        let _ = join_worker(&mut self.worker);
+    }
+}
+
+impl DecodeReaper {
+    pub fn try_reap(&self) -> Result<Option<DecodedFrame>, BackendError> {
+        try_recv_notified(&self.outputs, &self.ready)
+    }
+
+    pub fn reap_timeout(&self, timeout: Duration) -> Result<Option<DecodedFrame>, BackendError> {
+        recv_output(&self.outputs, timeout)
+    }
+
+    // Pollable handle signalled while frames are waiting; see ReadyNotifier.
+    pub fn ready_notifier(&self) -> Result<ReadyNotifier, BackendError> {
+        ready_notifier(&self.ready)
+    }
+}
+
+impl Iterator for DecodeReaper {
+    type Item = Result<DecodedFrame, BackendError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.outputs.recv().ok()
+    }
+}
+
+impl EncodeSubmitter {
+    pub fn submit(&mut self, frame: EncodeFrame) -> Result<(), BackendError> {
+        send_command(&self.commands, EncodeCommand::Submit(frame))
+    }
+
+    pub fn flush(&mut self) -> Result<(), BackendError> {
+        send_command(&self.commands, EncodeCommand::Flush)
+    }
+
+    pub fn request_session_switch(
+        &mut self,
+        request: SessionSwitchRequest,
+    ) -> Result<(), BackendError> {
+        send_command(&self.commands, EncodeCommand::SessionSwitch(request))
+    }
+
+    pub fn invalidate_reference(&mut self, pts_90k: Timestamp90k) -> Result<(), BackendError> {
+        send_command(&self.commands, EncodeCommand::InvalidateReference(pts_90k))
+    }
+
+    // Applies from the next command the worker admits; only matters with an arbiter.
+    pub fn set_priority(&self, priority: EncodePriority) {
+        *self
+            .priority
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = priority;
+    }
+
+    pub fn priority(&self) -> EncodePriority {
+        *self
+            .priority
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Flushes and stops the worker; the reaper ends once the remaining chunks are taken.
+    pub fn finish(mut self) -> Result<(), BackendError> {
+        self.flush()?;
+        self.commands = None;
+        join_worker(&mut self.worker)
+    }
+}
+
+impl Drop for EncodeSubmitter {
+    fn drop(&mut self) {
+        self.commands = None;
+        let _ = join_worker(&mut self.worker);
+    }
+}
+
+impl EncodeReaper {
+    pub fn try_reap(&self) -> Result<Option<EncodedChunk>, BackendError> {
+        try_recv_notified(&self.outputs, &self.ready)
+    }
+
+    pub fn reap_timeout(&self, timeout: Duration) -> Result<Option<EncodedChunk>, BackendError> {
+        recv_output(&self.outputs, timeout)
+    }
+
+    // Pollable handle signalled while chunks are waiting; see ReadyNotifier.
+    pub fn ready_notifier(&self) -> Result<ReadyNotifier, BackendError> {
+        ready_notifier(&self.ready)
+    }
+}
+
+impl Iterator for EncodeReaper {
+    type Item = Result<EncodedChunk, BackendError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.outputs.recv().ok()
+    }
+}
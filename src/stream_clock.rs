@@ -0,0 +1,237 @@
+use std::time::{Duration, Instant};
+
+use crate::Timestamp90k;
+
+// Where a frame stands relative to its playout deadline when it was observed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Playout {
+    // Present after waiting `wait`.
+    Early { wait: Duration },
+    // Past the deadline but within the late tolerance; present immediately.
+    OnTime { late_by: Duration },
+    // Beyond the late tolerance; players usually drop it.
+    Late { late_by: Duration },
+    // The pts jumped further than the discontinuity threshold (seek, splice, wrap), so the
+    // clock re-anchored on this frame.
+    Discontinuity,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamClockStats {
+    pub observed: u64,
+    pub late: u64,
+    pub discontinuities: u64,
+    pub max_late_by: Duration,
+}
+
+// Maps pts_90k onto a monotonic wall clock for one session. The first observed frame anchors
+// the mapping; pausing shifts the anchor so playout resumes where it stopped. Every method takes
+// `now` explicitly so callers decide which instant a frame is judged at.
+#[derive(Debug, Clone)]
+pub struct StreamClock {
+    anchor: Option<(Timestamp90k, Instant)>,
+    paused_at: Option<Instant>,
+    late_tolerance: Duration,
+    discontinuity_threshold: Duration,
+    stats: StreamClockStats,
+}
+
+impl Default for StreamClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamClock {
+    pub fn new() -> Self {
+        Self {
+            anchor: None,
+            paused_at: None,
+            late_tolerance: Duration::from_millis(40),
+            discontinuity_threshold: Duration::from_secs(10),
+            stats: StreamClockStats::default(),
+        }
+    }
+
+    pub fn with_late_tolerance(mut self, tolerance: Duration) -> Self {
+        self.late_tolerance = tolerance;
+        self
+    }
+
+    pub fn with_discontinuity_threshold(mut self, threshold: Duration) -> Self {
+        self.discontinuity_threshold = threshold;
+        self
+    }
+
+    // Re-anchoring while paused keeps the clock paused at `pts`.
+    pub fn anchor(&mut self, pts: Timestamp90k, now: Instant) {
+        self.anchor = Some((pts, now));
+        if self.paused_at.is_some() {
+            self.paused_at = Some(now);
+        }
+    }
+
+    pub fn is_anchored(&self) -> bool {
+        self.anchor.is_some()
+    }
+
+    // Forget the mapping, e.g. after a seek; the next observed frame anchors again.
+    pub fn reset(&mut self) {
+        self.anchor = None;
+        self.paused_at = None;
+    }
+
+    pub fn pause(&mut self, now: Instant) {
+        if self.paused_at.is_none() {
+            self.paused_at = Some(now);
+        }
+    }
+
+    pub fn resume(&mut self, now: Instant) {
+        if let (Some(paused_at), Some((pts, at))) = (self.paused_at.take(), self.anchor) {
+            self.anchor = Some((pts, at + now.saturating_duration_since(paused_at)));
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    // Wall-clock instant at which `pts` should be presented; None before anchoring or for a pts
+    // that would fall before the anchor instant can represent.
+    pub fn deadline(&self, pts: Timestamp90k) -> Option<Instant> {
+        let (anchor_pts, anchor_at) = self.anchor?;
+        let ticks = pts.0.checked_sub(anchor_pts.0)?;
+        if ticks >= 0 {
+            anchor_at.checked_add(ticks_to_duration(ticks.unsigned_abs()))
+        } else {
+            anchor_at.checked_sub(ticks_to_duration(ticks.unsigned_abs()))
+        }
+    }
+
+    // Stream position at `now`, frozen while paused.
+    pub fn position(&self, now: Instant) -> Option<Timestamp90k> {
+        let (anchor_pts, anchor_at) = self.anchor?;
+        let now = self.paused_at.unwrap_or(now);
+        let elapsed = if now >= anchor_at {
+            duration_to_ticks(now - anchor_at)
+        } else {
+            -duration_to_ticks(anchor_at - now)
+        };
+        Some(Timestamp90k(anchor_pts.0.saturating_add(elapsed)))
+    }
+
+    // Classifies a frame leaving the decoder and updates the late-frame statistics.
+    pub fn observe(&mut self, pts: Timestamp90k, now: Instant) -> Playout {
+        self.stats.observed += 1;
+        let Some(deadline) = self.deadline(pts) else {
+            let discontinuity = self.anchor.is_some();
+            self.anchor(pts, now);
+            if discontinuity {
+                self.stats.discontinuities += 1;
+                return Playout::Discontinuity;
+            }
+            return Playout::Early {
+                wait: Duration::ZERO,
+            };
+        };
+        // While paused nothing is late; the frame simply waits for resume.
+        let now = self.paused_at.unwrap_or(now);
+        let offset = if deadline >= now {
+            deadline - now
+        } else {
+            now - deadline
+        };
+        if offset > self.discontinuity_threshold {
+            self.anchor(pts, now);
+            self.stats.discontinuities += 1;
+            return Playout::Discontinuity;
+        }
+        if deadline >= now {
+            return Playout::Early { wait: offset };
+        }
+        if offset <= self.late_tolerance {
+            return Playout::OnTime { late_by: offset };
+        }
+        self.stats.late += 1;
+        self.stats.max_late_by = self.stats.max_late_by.max(offset);
+        Playout::Late { late_by: offset }
+    }
+
+    pub fn stats(&self) -> StreamClockStats {
+        self.stats
+    }
+}
+
+fn ticks_to_duration(ticks: u64) -> Duration {
+    let nanos = u128::from(ticks) * 100_000 / 9;
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
+
+fn duration_to_ticks(duration: Duration) -> i64 {
+    i64::try_from(duration.as_nanos() * 9 / 100_000).unwrap_or(i64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_classified_against_anchored_deadlines() {
+        let start = Instant::now();
+        let mut clock = StreamClock::new().with_late_tolerance(Duration::from_millis(20));
+        assert_eq!(
+            clock.observe(Timestamp90k(9000), start),
+            Playout::Early {
+                wait: Duration::ZERO
+            }
+        );
+        // 1/30 s later in stream time, observed immediately.
+        assert_eq!(
+            clock.observe(Timestamp90k(12_000), start),
+            Playout::Early {
+                wait: Duration::from_nanos(33_333_333)
+            }
+        );
+        assert!(matches!(
+            clock.observe(Timestamp90k(12_000), start + Duration::from_millis(40)),
+            Playout::OnTime { .. }
+        ));
+        assert_eq!(
+            clock.observe(Timestamp90k(15_000), start + Duration::from_millis(166)),
+            Playout::Late {
+                late_by: Duration::from_nanos(99_333_334)
+            }
+        );
+        assert_eq!(
+            clock.observe(Timestamp90k(9000 + 90_000 * 60), start),
+            Playout::Discontinuity
+        );
+        let stats = clock.stats();
+        assert_eq!(
+            (stats.observed, stats.late, stats.discontinuities),
+            (5, 1, 1)
+        );
+    }
+
+    #[test]
+    fn pause_freezes_position_and_shifts_deadlines() {
+        let start = Instant::now();
+        let mut clock = StreamClock::new();
+        clock.anchor(Timestamp90k(0), start);
+        clock.pause(start + Duration::from_secs(1));
+        assert_eq!(
+            clock.position(start + Duration::from_secs(5)),
+            Some(Timestamp90k(90_000))
+        );
+        clock.resume(start + Duration::from_secs(3));
+        assert_eq!(
+            clock.deadline(Timestamp90k(90_000)),
+            Some(start + Duration::from_secs(3))
+        );
+        assert_eq!(
+            clock.position(start + Duration::from_secs(4)),
+            Some(Timestamp90k(180_000))
+        );
+    }
+}
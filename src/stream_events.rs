@@ -0,0 +1,183 @@
+use std::collections::VecDeque;
+
+use crate::{ColorMetadata, DecodeInfoFlags, DecodedFrame, Dimensions, FrameCrop, StreamEvent};
+
+// Derived on the session side from what every backend already reports: the parameter set
+// revision and SPS cropping after each submit, and the decoded frames themselves. Once an SPS
+// has been parsed it is the only source of resolution changes; frame dims lag behind it by the
+// reorder depth and would otherwise report the old size a second time.
+#[derive(Debug, Default)]
+pub(crate) struct StreamEventTracker {
+    pending: VecDeque<StreamEvent>,
+    parameter_set_revision: u64,
+    dims: Option<Dimensions>,
+    dims_from_sps: bool,
+    color: Option<ColorMetadata>,
+    impaired: bool,
+}
+
+impl StreamEventTracker {
+    pub(crate) fn observe_parameter_sets(&mut self, revision: u64, crop: Option<FrameCrop>) {
+        if revision == self.parameter_set_revision {
+            return;
+        }
+        self.parameter_set_revision = revision;
+        self.pending
+            .push_back(StreamEvent::ParameterSetsUpdated { revision });
+        if let Some(crop) = crop {
+            self.dims_from_sps = true;
+            self.resize(crop.display, Some(crop));
+        }
+    }
+
+    pub(crate) fn observe_frame(&mut self, frame: &DecodedFrame) {
+        let (dims, pts_90k, color, flags) = match frame {
+            DecodedFrame::Metadata {
+                dims,
+                pts_90k,
+                color,
+                decode_info_flags,
+                ..
+            } => (*dims, *pts_90k, *color, *decode_info_flags),
+            DecodedFrame::Nv12 { dims, pts_90k, .. }
+            | DecodedFrame::Rgb24 { dims, pts_90k, .. } => (Some(*dims), *pts_90k, None, None),
+        };
+        if !self.dims_from_sps
+            && let Some(dims) = dims
+        {
+            self.resize(dims, None);
+        }
+        if let Some(color) = color
+            && self.color != Some(color)
+        {
+            self.pending.push_back(StreamEvent::ColorMetadataChanged {
+                from: self.color.replace(color),
+                to: color,
+            });
+        }
+        let damaged = flags.is_some_and(|flags| {
+            flags.intersects(DecodeInfoFlags::CORRUPTED | DecodeInfoFlags::FRAME_DROPPED)
+        });
+        if damaged {
+            self.impaired = true;
+        } else if self.impaired {
+            self.impaired = false;
+            self.pending
+                .push_back(StreamEvent::RecoveredAfterError { pts_90k });
+        }
+    }
+
+    // A submit the backend rejected; the next clean frame reports the recovery.
+    pub(crate) fn observe_error(&mut self) {
+        self.impaired = true;
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<StreamEvent> {
+        self.pending.pop_front()
+    }
+
+    pub(crate) fn drain(&mut self) -> Vec<StreamEvent> {
+        self.pending.drain(..).collect()
+    }
+
+    fn resize(&mut self, to: Dimensions, crop: Option<FrameCrop>) {
+        if self.dims == Some(to) {
+            return;
+        }
+        self.pending.push_back(StreamEvent::ResolutionChanged {
+            from: self.dims.replace(to),
+            to,
+            crop,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+    use crate::Timestamp90k;
+
+    fn dims(width: u32, height: u32) -> Dimensions {
+        Dimensions {
+            width: NonZeroU32::new(width).unwrap(),
+            height: NonZeroU32::new(height).unwrap(),
+        }
+    }
+
+    fn frame(width: u32, height: u32, pts: i64, flags: DecodeInfoFlags) -> DecodedFrame {
+        DecodedFrame::Metadata {
+            dims: Some(dims(width, height)),
+            pts_90k: Some(Timestamp90k(pts)),
+            pixel_format: None,
+            decode_info_flags: Some(flags),
+            color: Some(ColorMetadata {
+                color_primaries: Some(1),
+                transfer_function: Some(1),
+                ycbcr_matrix: Some(1),
+            }),
+            planes: None,
+            histogram: None,
+            view: None,
+        }
+    }
+
+    #[test]
+    fn sps_resolution_change_is_reported_once() {
+        let mut tracker = StreamEventTracker::default();
+        let crop = FrameCrop {
+            coded: dims(1920, 1088),
+            display: dims(1920, 1080),
+            left: 0,
+            top: 0,
+        };
+        tracker.observe_parameter_sets(2, Some(crop));
+        tracker.observe_parameter_sets(2, Some(crop));
+        tracker.observe_frame(&frame(1920, 1080, 0, DecodeInfoFlags::empty()));
+        let resized = FrameCrop {
+            coded: dims(1280, 720),
+            display: dims(1280, 720),
+            ..crop
+        };
+        tracker.observe_parameter_sets(3, Some(resized));
+        // A reordered frame of the previous size must not flip the resolution back.
+        tracker.observe_frame(&frame(1920, 1080, 3000, DecodeInfoFlags::empty()));
+        assert_eq!(
+            tracker
+                .drain()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "[stream.parameter_sets] revision=2",
+                "[stream.resolution] 1920x1080",
+                "[stream.color] primaries=Some(1), transfer=Some(1), matrix=Some(1)",
+                "[stream.parameter_sets] revision=3",
+                "[stream.resolution] 1920x1080 -> 1280x720",
+            ]
+        );
+    }
+
+    #[test]
+    fn clean_frame_after_corruption_reports_recovery() {
+        let mut tracker = StreamEventTracker::default();
+        tracker.observe_frame(&frame(640, 360, 0, DecodeInfoFlags::empty()));
+        tracker.drain();
+        tracker.observe_frame(&frame(640, 360, 3000, DecodeInfoFlags::CORRUPTED));
+        assert_eq!(tracker.pop(), None);
+        tracker.observe_frame(&frame(640, 360, 6000, DecodeInfoFlags::empty()));
+        assert_eq!(
+            tracker.pop(),
+            Some(StreamEvent::RecoveredAfterError {
+                pts_90k: Some(Timestamp90k(6000))
+            })
+        );
+        tracker.observe_error();
+        tracker.observe_frame(&frame(640, 360, 9000, DecodeInfoFlags::empty()));
+        assert!(matches!(
+            tracker.pop(),
+            Some(StreamEvent::RecoveredAfterError { .. })
+        ));
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{FrameRate, SurfacePoolSizes};
+
+// Surface memory one session's automatic pool aims to stay under. Only headroom is trimmed to
+// fit; the surfaces a stream needs to decode or encode at all are always allocated.
+const POOL_BUDGET_BYTES: u64 = 384 << 20;
+// cuvidCreateDecoder rejects more decode surfaces than this.
+const NVDEC_MAX_DECODE_SURFACES: u32 = 32;
+const NVDEC_DEFAULT_OUTPUT_SURFACES: u32 = 2;
+const NVENC_MIN_POOL: usize = 3;
+const NVENC_MAX_POOL: usize = 64;
+
+// One extra frame in flight per 60 fps beyond the first, so a 240 fps stream does not run the
+// pool dry between two reaps.
+pub(crate) fn fps_headroom(fps: FrameRate) -> u32 {
+    if !fps.is_known() {
+        return 0;
+    }
+    (fps.as_f64() / 60.0).ceil().max(1.0) as u32 - 1
+}
+
+// `reference_surfaces` is the parser's min_num_decode_surfaces, the DPB size it read from the
+// SPS; `in_flight` the pictures between parse and display on top of it. Requested counts win
+// over the automatic ones, but decode surfaces never drop below the DPB.
+pub(crate) fn nvdec_pool(
+    coded_width: u32,
+    coded_height: u32,
+    reference_surfaces: u32,
+    in_flight: u32,
+    requested: (Option<u32>, Option<u32>),
+) -> SurfacePoolSizes {
+    let minimum = reference_surfaces.max(1);
+    let nv12_bytes = u64::from(coded_width) * u64::from(coded_height) * 3 / 2;
+    let affordable = u32::try_from(POOL_BUDGET_BYTES / nv12_bytes.max(1)).unwrap_or(u32::MAX);
+    let output_surfaces = requested.1.unwrap_or_else(|| {
+        NVDEC_DEFAULT_OUTPUT_SURFACES
+            .saturating_add(in_flight)
+            .min(affordable.saturating_sub(minimum))
+            .max(1)
+    });
+    let surfaces = requested.0.map_or_else(
+        || {
+            minimum
+                .saturating_add(in_flight)
+                .min(affordable.saturating_sub(output_surfaces))
+                .min(NVDEC_MAX_DECODE_SURFACES)
+        },
+        |requested| requested.min(NVDEC_MAX_DECODE_SURFACES),
+    );
+    SurfacePoolSizes {
+        surfaces: surfaces.max(minimum),
+        output_surfaces: output_surfaces.max(1),
+        automatic: requested == (None, None),
+    }
+}
+
+// `structural` is what the GOP and lookahead keep inside the encoder (frameIntervalP +
+// lookaheadDepth + 1), `in_flight` the outputs allowed to queue before reaping.
+pub(crate) fn nvenc_pool(
+    width: usize,
+    height: usize,
+    structural: usize,
+    in_flight: usize,
+    requested: Option<usize>,
+) -> SurfacePoolSizes {
+    let minimum = structural.max(NVENC_MIN_POOL);
+    let argb_bytes = (width as u64) * (height as u64) * 4;
+    let affordable = usize::try_from(POOL_BUDGET_BYTES / argb_bytes.max(1)).unwrap_or(usize::MAX);
+    let size = requested
+        .unwrap_or_else(|| structural.max(in_flight).min(affordable))
+        .min(NVENC_MAX_POOL)
+        .max(minimum);
+    let size = u32::try_from(size).unwrap_or(u32::MAX);
+    SurfacePoolSizes {
+        surfaces: size,
+        output_surfaces: size,
+        automatic: requested.is_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pools_follow_resolution_references_and_rate() {
+        assert_eq!(fps_headroom(FrameRate::new(30, 1)), 0);
+        assert_eq!(fps_headroom(FrameRate::new(120, 1)), 1);
+        assert_eq!(fps_headroom(FrameRate::new(240, 1)), 3);
+
+        // 1080p with 5 references and 240 fps headroom fits comfortably.
+        let hd = nvdec_pool(1920, 1088, 5, 3, (None, None));
+        assert_eq!((hd.surfaces, hd.output_surfaces), (8, 5));
+        // 8K cannot afford the headroom, but still gets the full DPB.
+        let uhd = nvdec_pool(7680, 4320, 17, 3, (None, None));
+        assert_eq!((uhd.surfaces, uhd.output_surfaces), (17, 1));
+        assert!(uhd.automatic);
+        let fixed = nvdec_pool(1920, 1088, 5, 3, (Some(2), Some(4)));
+        assert_eq!((fixed.surfaces, fixed.output_surfaces), (5, 4));
+        assert!(!fixed.automatic);
+
+        // 4K ARGB buffers are ~33 MB, so deep in-flight queues are trimmed to the budget...
+        assert_eq!(nvenc_pool(3840, 2160, 4, 16, None).surfaces, 12);
+        // ...but never below what lookahead and B-frames hold inside the encoder.
+        assert_eq!(nvenc_pool(3840, 2160, 20, 6, None).surfaces, 20);
+        assert_eq!(nvenc_pool(1280, 720, 2, 6, None).surfaces, 6);
+        let fixed = nvenc_pool(1280, 720, 4, 6, Some(2));
+        assert_eq!((fixed.surfaces, fixed.automatic), (4, false));
+    }
+}
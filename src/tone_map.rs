@@ -0,0 +1,367 @@
+use crate::{BackendError, ColorMetadata, Nv12Frame, TransformResult, TransformStage};
+
+// H.273 code points, as reported by both NVDEC and VideoToolbox in ColorMetadata.
+const TRANSFER_PQ: i32 = 16;
+const TRANSFER_HLG: i32 = 18;
+const BT709: i32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HdrTransfer {
+    Pq,
+    Hlg,
+}
+
+impl HdrTransfer {
+    // None for SDR content, so callers can pass frames through untouched.
+    pub fn from_color(color: &ColorMetadata) -> Option<Self> {
+        match color.transfer_function? {
+            TRANSFER_PQ => Some(Self::Pq),
+            TRANSFER_HLG => Some(Self::Hlg),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToneMapAlgorithm {
+    // Extended Reinhard on luminance; cheap and never clips below the source peak.
+    Reinhard,
+    // Filmic curve with a stronger shoulder; keeps more midtone contrast.
+    Hable,
+    // ITU-R BT.2390 EETF: identity below the knee, Hermite roll-off in the PQ domain above it.
+    #[default]
+    Bt2390,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapConfig {
+    pub algorithm: ToneMapAlgorithm,
+    // Mastering peak of the source; HLG also uses it as the nominal display peak for its OOTF.
+    pub source_peak_nits: f32,
+    // SDR reference white.
+    pub target_peak_nits: f32,
+}
+
+impl Default for ToneMapConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: ToneMapAlgorithm::default(),
+            source_peak_nits: 1000.0,
+            target_peak_nits: 100.0,
+        }
+    }
+}
+
+// Converts BT.2020 PQ/HLG NV12 into BT.709 SDR NV12 on the CPU. It doubles as the reference
+// for the CUDA kernel and can be registered as a TransformStage on a TransformDispatcher.
+#[derive(Debug, Clone, Copy)]
+pub struct ToneMapper {
+    transfer: HdrTransfer,
+    config: ToneMapConfig,
+}
+
+impl ToneMapper {
+    pub fn new(transfer: HdrTransfer, config: ToneMapConfig) -> Result<Self, BackendError> {
+        if !(config.target_peak_nits > 0.0 && config.source_peak_nits >= config.target_peak_nits) {
+            return Err(BackendError::InvalidInput(format!(
+                "tone mapping needs 0 < target peak <= source peak, got {} -> {} nits",
+                config.source_peak_nits, config.target_peak_nits
+            )));
+        }
+        Ok(Self { transfer, config })
+    }
+
+    // Uses the color metadata from the decode path; None when the stream is not HDR.
+    pub fn for_color(
+        color: &ColorMetadata,
+        config: ToneMapConfig,
+    ) -> Option<Result<Self, BackendError>> {
+        HdrTransfer::from_color(color).map(|transfer| Self::new(transfer, config))
+    }
+
+    pub fn transfer(&self) -> HdrTransfer {
+        self.transfer
+    }
+
+    pub fn config(&self) -> ToneMapConfig {
+        self.config
+    }
+
+    // What the output frames should be tagged with when they are re-encoded.
+    pub fn output_color() -> ColorMetadata {
+        ColorMetadata {
+            color_primaries: Some(BT709),
+            transfer_function: Some(BT709),
+            ycbcr_matrix: Some(BT709),
+        }
+    }
+
+    pub fn map_frame(&self, frame: &Nv12Frame) -> Result<Nv12Frame, BackendError> {
+        let (width, height, pitch) = (frame.width, frame.height, frame.pitch.max(frame.width));
+        if width == 0 || height == 0 {
+            return Err(BackendError::InvalidInput(
+                "nv12 frame dimensions must be positive".to_string(),
+            ));
+        }
+        let luma_size = pitch * height;
+        let chroma_rows = height.div_ceil(2);
+        if frame.data.len() < luma_size + chroma_rows * pitch {
+            return Err(BackendError::InvalidInput(
+                "nv12 data is smaller than expected".to_string(),
+            ));
+        }
+
+        let mut data = frame.data.clone();
+        let (luma_out, chroma_out) = data.split_at_mut(luma_size);
+        let (luma_in, chroma_in) = frame.data.split_at(luma_size);
+        // One 2x2 block shares a chroma sample: map each pixel, write its luma, and average
+        // the mapped chroma back into the block.
+        for by in 0..chroma_rows {
+            for bx in 0..width.div_ceil(2) {
+                let uv = by * pitch + bx * 2;
+                let (u, v) = (chroma_in[uv], chroma_in[uv + 1]);
+                let (mut cb_sum, mut cr_sum, mut count) = (0.0, 0.0, 0.0);
+                for y in (by * 2)..(by * 2 + 2).min(height) {
+                    for x in (bx * 2)..(bx * 2 + 2).min(width) {
+                        let (luma, cb, cr) = self.map_pixel(luma_in[y * pitch + x], u, v);
+                        luma_out[y * pitch + x] = luma;
+                        cb_sum += cb;
+                        cr_sum += cr;
+                        count += 1.0;
+                    }
+                }
+                chroma_out[uv] = quantize_chroma(cb_sum / count);
+                chroma_out[uv + 1] = quantize_chroma(cr_sum / count);
+            }
+        }
+        Ok(Nv12Frame {
+            width,
+            height,
+            pitch,
+            pts_90k: frame.pts_90k,
+            data,
+        })
+    }
+
+    fn map_pixel(&self, y: u8, u: u8, v: u8) -> (u8, f32, f32) {
+        let rgb = bt2020_ycbcr_to_rgb(y, u, v);
+        let linear = match self.transfer {
+            HdrTransfer::Pq => rgb.map(|e| pq_to_nits(e) / self.config.target_peak_nits),
+            HdrTransfer::Hlg => hlg_to_display(rgb, self.config.source_peak_nits)
+                .map(|nits| nits / self.config.target_peak_nits),
+        };
+        let source_peak = self.config.source_peak_nits / self.config.target_peak_nits;
+        let mapped = scale_luminance(linear, |l| {
+            tone_curve(self.config.algorithm, l, source_peak)
+        });
+        let sdr = bt2020_to_bt709(mapped).map(|c| bt709_oetf(c.clamp(0.0, 1.0)));
+        bt709_rgb_to_ycbcr(sdr)
+    }
+}
+
+impl TransformStage for ToneMapper {
+    fn process(&self, frame: Nv12Frame) -> Result<TransformResult, BackendError> {
+        self.map_frame(&frame).map(TransformResult::Nv12)
+    }
+}
+
+fn bt2020_ycbcr_to_rgb(y: u8, u: u8, v: u8) -> [f32; 3] {
+    let y = (f32::from(y) - 16.0) / 219.0;
+    let cb = (f32::from(u) - 128.0) / 224.0;
+    let cr = (f32::from(v) - 128.0) / 224.0;
+    [
+        y + 1.4746 * cr,
+        y - 0.164_553 * cb - 0.571_353 * cr,
+        y + 1.8814 * cb,
+    ]
+    .map(|c| c.clamp(0.0, 1.0))
+}
+
+// SMPTE ST 2084 EOTF.
+pub(crate) fn pq_to_nits(encoded: f32) -> f32 {
+    const M1: f32 = 0.159_301_76;
+    const M2: f32 = 78.843_75;
+    const C1: f32 = 0.835_937_5;
+    const C2: f32 = 18.851_563;
+    const C3: f32 = 18.6875;
+    let p = encoded.max(0.0).powf(1.0 / M2);
+    10_000.0 * ((p - C1).max(0.0) / (C2 - C3 * p)).powf(1.0 / M1)
+}
+
+// Inverse EOTF, used by the BT.2390 roll-off which works on PQ-encoded values.
+pub(crate) fn nits_to_pq(nits: f32) -> f32 {
+    const M1: f32 = 0.159_301_76;
+    const M2: f32 = 78.843_75;
+    const C1: f32 = 0.835_937_5;
+    const C2: f32 = 18.851_563;
+    const C3: f32 = 18.6875;
+    let y = (nits / 10_000.0).clamp(0.0, 1.0).powf(M1);
+    ((C1 + C2 * y) / (1.0 + C3 * y)).powf(M2)
+}
+
+// BT.2100 HLG inverse OETF followed by the OOTF for a display of `peak_nits`.
+fn hlg_to_display(rgb: [f32; 3], peak_nits: f32) -> [f32; 3] {
+    const A: f32 = 0.178_832_77;
+    const B: f32 = 0.284_668_92;
+    const C: f32 = 0.559_910_7;
+    let scene = rgb.map(|e| {
+        if e <= 0.5 {
+            e * e / 3.0
+        } else {
+            (((e - C) / A).exp() + B) / 12.0
+        }
+    });
+    let gamma = 1.2 + 0.42 * (peak_nits / 1000.0).log10();
+    let luminance = 0.2627 * scene[0] + 0.6780 * scene[1] + 0.0593 * scene[2];
+    let gain = peak_nits * luminance.max(1e-6).powf(gamma - 1.0);
+    scene.map(|c| c * gain)
+}
+
+// Tone curves act on luminance and rescale RGB with it, which keeps hue stable.
+fn scale_luminance(rgb: [f32; 3], curve: impl Fn(f32) -> f32) -> [f32; 3] {
+    let luminance = 0.2627 * rgb[0] + 0.6780 * rgb[1] + 0.0593 * rgb[2];
+    if luminance <= 0.0 {
+        return [0.0; 3];
+    }
+    let scale = curve(luminance) / luminance;
+    rgb.map(|c| c * scale)
+}
+
+// `x` and `source_peak` are in units of the SDR target peak; the result is in [0, 1].
+pub(crate) fn tone_curve(algorithm: ToneMapAlgorithm, x: f32, source_peak: f32) -> f32 {
+    match algorithm {
+        ToneMapAlgorithm::Reinhard => x * (1.0 + x / (source_peak * source_peak)) / (1.0 + x),
+        ToneMapAlgorithm::Hable => hable(x) / hable(source_peak),
+        ToneMapAlgorithm::Bt2390 => {
+            let source_pq = nits_to_pq(source_peak * 100.0);
+            let e1 = nits_to_pq(x * 100.0) / source_pq;
+            let max_lum = nits_to_pq(100.0) / source_pq;
+            let knee = 1.5 * max_lum - 0.5;
+            let e2 = if e1 < knee {
+                e1
+            } else {
+                let t = (e1 - knee) / (1.0 - knee);
+                let (t2, t3) = (t * t, t * t * t);
+                (2.0 * t3 - 3.0 * t2 + 1.0) * knee
+                    + (t3 - 2.0 * t2 + t) * (1.0 - knee)
+                    + (-2.0 * t3 + 3.0 * t2) * max_lum
+            };
+            pq_to_nits(e2 * source_pq) / 100.0
+        }
+    }
+    .clamp(0.0, 1.0)
+}
+
+fn hable(x: f32) -> f32 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+fn bt2020_to_bt709(rgb: [f32; 3]) -> [f32; 3] {
+    [
+        1.6605 * rgb[0] - 0.5876 * rgb[1] - 0.0728 * rgb[2],
+        -0.1246 * rgb[0] + 1.1329 * rgb[1] - 0.0083 * rgb[2],
+        -0.0182 * rgb[0] - 0.1006 * rgb[1] + 1.1187 * rgb[2],
+    ]
+}
+
+fn bt709_oetf(linear: f32) -> f32 {
+    if linear < 0.018 {
+        4.5 * linear
+    } else {
+        1.099 * linear.powf(0.45) - 0.099
+    }
+}
+
+fn bt709_rgb_to_ycbcr(rgb: [f32; 3]) -> (u8, f32, f32) {
+    let y = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+    let cb = (rgb[2] - y) / 1.8556;
+    let cr = (rgb[0] - y) / 1.5748;
+    ((16.0 + 219.0 * y).round().clamp(0.0, 255.0) as u8, cb, cr)
+}
+
+fn quantize_chroma(value: f32) -> u8 {
+    (128.0 + 224.0 * value).round().clamp(0.0, 255.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pq_frame(y: u8) -> Nv12Frame {
+        let (width, height) = (4, 2);
+        let mut data = vec![y; width * height];
+        data.extend(std::iter::repeat_n(128, width * height / 2));
+        Nv12Frame {
+            width,
+            height,
+            pitch: width,
+            pts_90k: Some(9000),
+            data,
+        }
+    }
+
+    #[test]
+    fn pq_transfer_round_trips_and_is_detected_from_metadata() {
+        for nits in [0.1_f32, 100.0, 1000.0, 4000.0] {
+            assert!((pq_to_nits(nits_to_pq(nits)) - nits).abs() / nits < 1e-3);
+        }
+        let color = ColorMetadata {
+            color_primaries: Some(9),
+            transfer_function: Some(TRANSFER_PQ),
+            ycbcr_matrix: Some(9),
+        };
+        assert_eq!(HdrTransfer::from_color(&color), Some(HdrTransfer::Pq));
+        assert!(
+            ToneMapper::for_color(&ToneMapper::output_color(), ToneMapConfig::default()).is_none()
+        );
+    }
+
+    #[test]
+    fn tone_curves_are_monotonic_and_reach_sdr_white_at_source_peak() {
+        for algorithm in [
+            ToneMapAlgorithm::Reinhard,
+            ToneMapAlgorithm::Hable,
+            ToneMapAlgorithm::Bt2390,
+        ] {
+            let mut last = 0.0;
+            for step in 1..=100 {
+                let value = tone_curve(algorithm, step as f32 * 0.1, 10.0);
+                assert!(value >= last, "{algorithm:?} not monotonic at step {step}");
+                last = value;
+            }
+            assert!((last - 1.0).abs() < 0.02, "{algorithm:?} peaks at {last}");
+        }
+    }
+
+    #[test]
+    fn pq_white_maps_into_sdr_range_and_keeps_neutral_chroma() {
+        let mapper = ToneMapper::new(HdrTransfer::Pq, ToneMapConfig::default()).unwrap();
+        // Y=16 is black; ~Y=145 is ~203 nits (HDR reference white) in limited-range PQ.
+        let black = mapper.map_frame(&pq_frame(16)).unwrap();
+        let white = mapper.map_frame(&pq_frame(145)).unwrap();
+        assert_eq!(black.data[0], 16);
+        assert!(
+            (200..=235).contains(&white.data[0]),
+            "got {}",
+            white.data[0]
+        );
+        assert!(white.data[8..].iter().all(|&c| c.abs_diff(128) <= 1));
+        assert_eq!(white.pts_90k, Some(9000));
+        assert!(
+            ToneMapper::new(
+                HdrTransfer::Hlg,
+                ToneMapConfig {
+                    source_peak_nits: 50.0,
+                    ..ToneMapConfig::default()
+                }
+            )
+            .is_err()
+        );
+    }
+}
@@ -1,10 +1,15 @@
+use std::fmt;
+use std::num::NonZeroU32;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::mpsc;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
-use crate::BackendError;
 use crate::pipeline::{BoundedQueueRx, QueueRecvError, QueueSendError, bounded_queue};
+use crate::{
+    BackendError, Dimensions, EncodeFrame, PixelFormat, PlaneLayout, RawFrameBuffer, Timestamp90k,
+};
 
 #[derive(Debug, Clone)]
 pub struct Nv12Frame {
@@ -15,6 +20,46 @@ pub struct Nv12Frame {
     pub data: Vec<u8>,
 }
 
+impl Nv12Frame {
+    pub fn planes(&self) -> Vec<PlaneLayout> {
+        PlaneLayout::nv12(self.pitch, self.height)
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        PixelFormat::Nv12
+    }
+
+    // Hands a transformed or composited frame to EncodeSession::submit as RawFrameBuffer::Nv12.
+    pub fn into_encode_frame(self) -> Result<EncodeFrame, BackendError> {
+        let dims = u32::try_from(self.width)
+            .ok()
+            .zip(u32::try_from(self.height).ok())
+            .and_then(|(width, height)| {
+                Some(Dimensions {
+                    width: NonZeroU32::new(width)?,
+                    height: NonZeroU32::new(height)?,
+                })
+            })
+            .ok_or_else(|| {
+                BackendError::InvalidInput(format!(
+                    "nv12 frame dimensions {}x{} cannot be encoded",
+                    self.width, self.height
+                ))
+            })?;
+        Ok(EncodeFrame {
+            dims,
+            pts_90k: self.pts_90k.map(Timestamp90k),
+            buffer: RawFrameBuffer::Nv12 {
+                pitch: self.pitch.max(self.width),
+                data: self.data,
+            },
+            force_keyframe: false,
+            dirty_rects: None,
+            repeat_count: 0,
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RgbFrame {
     pub width: usize,
@@ -23,6 +68,16 @@ pub struct RgbFrame {
     pub data: Vec<u8>,
 }
 
+impl RgbFrame {
+    pub fn planes(&self) -> Vec<PlaneLayout> {
+        PlaneLayout::packed(self.width * 3, self.height)
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        PixelFormat::Rgb8
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ColorRequest {
     KeepNative,
@@ -36,33 +91,185 @@ impl ColorRequest {
     }
 }
 
+impl fmt::Display for ColorRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::KeepNative => "keep_native",
+            Self::Rgb8 => "rgb8",
+            Self::Rgba8 => "rgba8",
+        })
+    }
+}
+
+// (source, request) pairs the built-in kernels serve besides KeepNative, which passes any
+// format through.
+const SUPPORTED_CONVERSIONS: [(PixelFormat, ColorRequest); 3] = [
+    (PixelFormat::Nv12, ColorRequest::Rgb8),
+    (PixelFormat::Rgb8, ColorRequest::Rgb8),
+    (PixelFormat::Rgba8, ColorRequest::Rgba8),
+];
+
+// What the dispatcher does with a frame of a given format for a ColorRequest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransformRoute {
+    // Already in the requested format.
+    Passthrough,
+    Nv12ToRgb,
+}
+
+impl TransformRoute {
+    pub fn job(self, frame: Nv12Frame) -> Option<TransformJob> {
+        match self {
+            Self::Passthrough => None,
+            Self::Nv12ToRgb => Some(TransformJob::Nv12ToRgb(frame)),
+        }
+    }
+}
+
+// A source format / request pair no kernel handles, with the pairs that would work.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedConversion {
+    pub source: PixelFormat,
+    pub requested: ColorRequest,
+    pub supported: Vec<(PixelFormat, ColorRequest)>,
+}
+
+impl fmt::Display for UnsupportedConversion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {} (supported:", self.source, self.requested)?;
+        for (source, requested) in &self.supported {
+            write!(f, " {source}->{requested}")?;
+        }
+        write!(f, " any->{})", ColorRequest::KeepNative)
+    }
+}
+
+// Picks the kernel for a `source` frame before anything is queued, so a format the kernels
+// cannot read fails here with the supported pairs instead of producing garbage or failing
+// inside a worker.
+pub fn route_transform(
+    source: PixelFormat,
+    color: ColorRequest,
+) -> Result<TransformRoute, BackendError> {
+    match (source, color) {
+        (_, ColorRequest::KeepNative)
+        | (PixelFormat::Rgb8, ColorRequest::Rgb8)
+        | (PixelFormat::Rgba8, ColorRequest::Rgba8) => Ok(TransformRoute::Passthrough),
+        (PixelFormat::Nv12, ColorRequest::Rgb8) => Ok(TransformRoute::Nv12ToRgb),
+        _ => Err(BackendError::UnsupportedConversion(UnsupportedConversion {
+            source,
+            requested: color,
+            supported: SUPPORTED_CONVERSIONS.to_vec(),
+        })),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum TransformJob {
     Nv12ToRgb(Nv12Frame),
+    Custom {
+        stage: TransformStageId,
+        frame: Nv12Frame,
+    },
 }
 
 #[derive(Debug, Clone)]
 pub enum TransformResult {
     Rgb(RgbFrame),
+    Nv12(Nv12Frame),
+}
+
+// Application-provided kernel (tone mapping, overlays, ...). It runs on the dispatcher's
+// workers, so its results share the bounded result queue and backpressure with built-in jobs.
+pub trait TransformStage: Send + Sync {
+    fn process(&self, frame: Nv12Frame) -> Result<TransformResult, BackendError>;
+
+    // Consecutive jobs for this stage in a submit_batch, one result per frame in order. A GPU
+    // stage overrides this to queue the whole run on its stream before synchronizing.
+    fn process_batch(&self, frames: Vec<Nv12Frame>) -> Vec<Result<TransformResult, BackendError>> {
+        frames
+            .into_iter()
+            .map(|frame| self.process(frame))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TransformStageId(usize);
+
+#[derive(Clone, Default)]
+struct StageRegistry(Arc<RwLock<Vec<Arc<dyn TransformStage>>>>);
+
+impl StageRegistry {
+    fn get(&self, id: TransformStageId) -> Option<Arc<dyn TransformStage>> {
+        self.0.read().ok()?.get(id.0).cloned()
+    }
+}
+
+impl fmt::Debug for StageRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self.0.read().map_or(0, |stages| stages.len());
+        write!(f, "StageRegistry({count} stages)")
+    }
+}
+
+type BatchResults = Vec<Result<TransformResult, BackendError>>;
+
+enum Work {
+    Job(TransformJob),
+    Batch(Vec<TransformJob>, mpsc::SyncSender<BatchResults>),
+}
+
+// Results of one submit_batch, in job order, delivered together once the whole group ran.
+#[derive(Debug)]
+pub struct TransformBatch {
+    len: usize,
+    results: mpsc::Receiver<BatchResults>,
+}
+
+impl TransformBatch {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn wait(self) -> Result<BatchResults, QueueRecvError> {
+        self.results
+            .recv()
+            .map_err(|_| QueueRecvError::Disconnected)
+    }
+
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<BatchResults, QueueRecvError> {
+        self.results.recv_timeout(timeout).map_err(|err| match err {
+            mpsc::RecvTimeoutError::Timeout => QueueRecvError::Timeout,
+            mpsc::RecvTimeoutError::Disconnected => QueueRecvError::Disconnected,
+        })
+    }
 }
 
 #[derive(Debug)]
 pub struct TransformDispatcher {
-    jobs_tx: Option<mpsc::Sender<TransformJob>>,
+    jobs_tx: Option<mpsc::Sender<Work>>,
     results_rx: BoundedQueueRx<Result<TransformResult, BackendError>>,
     workers: Vec<JoinHandle<()>>,
+    stages: StageRegistry,
 }
 
 impl TransformDispatcher {
     pub fn new(worker_count: usize, result_queue_capacity: usize) -> Self {
-        let (jobs_tx, jobs_rx) = mpsc::channel::<TransformJob>();
+        let (jobs_tx, jobs_rx) = mpsc::channel::<Work>();
         let jobs_rx = Arc::new(Mutex::new(jobs_rx));
         let (results_tx, results_rx) = bounded_queue(result_queue_capacity.max(1));
+        let stages = StageRegistry::default();
 
         let mut workers = Vec::new();
         for _ in 0..worker_count.max(1) {
             let jobs = Arc::clone(&jobs_rx);
             let results = results_tx.clone();
+            let stages = stages.clone();
             workers.push(thread::spawn(move || {
                 loop {
                     let job = {
@@ -72,11 +279,15 @@ impl TransformDispatcher {
                         };
                         receiver.recv()
                     };
-                    let Ok(job) = job else {
-                        break;
-                    };
-                    let result = run_job(job);
-                    let _ = results.send(result);
+                    match job {
+                        Ok(Work::Job(job)) => {
+                            let _ = results.send(run_job(job, &stages));
+                        }
+                        Ok(Work::Batch(jobs, batch)) => {
+                            let _ = batch.send(run_batch(jobs, &stages));
+                        }
+                        Err(_) => break,
+                    }
                 }
             }));
         }
@@ -85,14 +296,62 @@ impl TransformDispatcher {
             jobs_tx: Some(jobs_tx),
             results_rx,
             workers,
+            stages,
         }
     }
 
+    // Stages can be registered at any time; jobs naming an unknown id fail with InvalidInput.
+    pub fn register_stage(&self, stage: impl TransformStage + 'static) -> TransformStageId {
+        let mut stages = match self.stages.0.write() {
+            Ok(stages) => stages,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        stages.push(Arc::new(stage));
+        TransformStageId(stages.len() - 1)
+    }
+
     pub fn submit(&self, job: TransformJob) -> Result<(), QueueSendError> {
         let Some(tx) = &self.jobs_tx else {
             return Err(QueueSendError::Disconnected);
         };
-        tx.send(job).map_err(|_| QueueSendError::Disconnected)
+        tx.send(Work::Job(job))
+            .map_err(|_| QueueSendError::Disconnected)
+    }
+
+    // Queues a group of jobs, such as a whole offline clip, that one worker runs start to finish
+    // instead of paying the queue round trip per job. Consecutive jobs for the same custom stage
+    // go to TransformStage::process_batch together. The results come back through the returned
+    // TransformBatch in job order, not through recv, so they bypass the result queue's
+    // backpressure: size groups by the memory their output takes.
+    pub fn submit_batch(&self, jobs: Vec<TransformJob>) -> Result<TransformBatch, QueueSendError> {
+        let Some(tx) = &self.jobs_tx else {
+            return Err(QueueSendError::Disconnected);
+        };
+        let (batch_tx, results) = mpsc::sync_channel(1);
+        let len = jobs.len();
+        tx.send(Work::Batch(jobs, batch_tx))
+            .map_err(|_| QueueSendError::Disconnected)?;
+        Ok(TransformBatch { len, results })
+    }
+
+    // Routes `frame`, whose data is in `source` format, with route_transform and queues the
+    // chosen kernel. The frame comes back untouched when it already has the requested format.
+    pub fn submit_routed(
+        &self,
+        frame: Nv12Frame,
+        source: PixelFormat,
+        color: ColorRequest,
+    ) -> Result<Option<Nv12Frame>, BackendError> {
+        let route = route_transform(source, color)?;
+        if route == TransformRoute::Passthrough {
+            return Ok(Some(frame));
+        }
+        if let Some(job) = route.job(frame) {
+            self.submit(job).map_err(|err| {
+                BackendError::Backend(format!("transform dispatcher rejected job: {err:?}"))
+            })?;
+        }
+        Ok(None)
     }
 
     pub fn recv(&self) -> Result<Result<TransformResult, BackendError>, QueueRecvError> {
@@ -120,15 +379,63 @@ impl Drop for TransformDispatcher {
     }
 }
 
-fn run_job(job: TransformJob) -> Result<TransformResult, BackendError> {
+fn run_job(job: TransformJob, stages: &StageRegistry) -> Result<TransformResult, BackendError> {
     match job {
         TransformJob::Nv12ToRgb(frame) => {
             let rgb = nv12_to_rgb24(&frame)?;
             Ok(TransformResult::Rgb(rgb))
         }
+        TransformJob::Custom { stage, frame } => {
+            let kernel = stages.get(stage).ok_or_else(|| unknown_stage(stage))?;
+            // A panicking user kernel becomes an error result instead of taking a worker down.
+            panic::catch_unwind(AssertUnwindSafe(|| kernel.process(frame)))
+                .unwrap_or_else(|_| Err(stage_panicked(stage)))
+        }
     }
 }
 
+fn run_batch(jobs: Vec<TransformJob>, stages: &StageRegistry) -> BatchResults {
+    let mut results = Vec::with_capacity(jobs.len());
+    let mut jobs = jobs.into_iter().peekable();
+    while let Some(job) = jobs.next() {
+        let TransformJob::Custom { stage, frame } = job else {
+            results.push(run_job(job, stages));
+            continue;
+        };
+        let mut frames = vec![frame];
+        while let Some(TransformJob::Custom { frame, .. }) = jobs.next_if(
+            |next| matches!(next, TransformJob::Custom { stage: next, .. } if *next == stage),
+        ) {
+            frames.push(frame);
+        }
+        let count = frames.len();
+        let Some(kernel) = stages.get(stage) else {
+            results.extend((0..count).map(|_| Err(unknown_stage(stage))));
+            continue;
+        };
+        match panic::catch_unwind(AssertUnwindSafe(|| kernel.process_batch(frames))) {
+            Ok(run) if run.len() == count => results.extend(run),
+            Ok(run) => results.extend((0..count).map(|_| {
+                Err(BackendError::Backend(format!(
+                    "transform stage {} returned {} results for {count} frames",
+                    stage.0,
+                    run.len()
+                )))
+            })),
+            Err(_) => results.extend((0..count).map(|_| Err(stage_panicked(stage)))),
+        }
+    }
+    results
+}
+
+fn unknown_stage(stage: TransformStageId) -> BackendError {
+    BackendError::InvalidInput(format!("unknown transform stage {}", stage.0))
+}
+
+fn stage_panicked(stage: TransformStageId) -> BackendError {
+    BackendError::Backend(format!("transform stage {} panicked", stage.0))
+}
+
 pub fn nv12_to_rgb24(frame: &Nv12Frame) -> Result<RgbFrame, BackendError> {
     let width = frame.width;
     let height = frame.height;
@@ -155,31 +462,30 @@ pub fn nv12_to_rgb24(frame: &Nv12Frame) -> Result<RgbFrame, BackendError> {
         ));
     }
 
-    let uv_base = luma_size;
-    let mut rgb = vec![0_u8; width.saturating_mul(height).saturating_mul(3)];
-    for y in 0..height {
-        let y_row = y * pitch;
-        let uv_row = uv_base + (y / 2) * pitch;
-        let dst_row = y * width * 3;
-        for x in 0..width {
-            let y_value = i32::from(frame.data[y_row + x]);
-            let uv_index = uv_row + (x & !1);
-            let u_value = i32::from(frame.data[uv_index]);
-            let v_value = i32::from(frame.data[uv_index + 1]);
-
-            let c = (y_value - 16).max(0);
-            let d = u_value - 128;
-            let e = v_value - 128;
-            let r = clip_to_u8((298 * c + 409 * e + 128) >> 8);
-            let g = clip_to_u8((298 * c - 100 * d - 208 * e + 128) >> 8);
-            let b = clip_to_u8((298 * c + 516 * d + 128) >> 8);
-
-            let dst = dst_row + x * 3;
-            rgb[dst] = r;
-            rgb[dst + 1] = g;
-            rgb[dst + 2] = b;
+    let (luma, chroma) = frame.data.split_at(luma_size);
+    let row_bytes = width * 3;
+    let mut rgb = vec![0_u8; row_bytes.saturating_mul(height)];
+    // A band is the two output rows that share one chroma row, so bands are independent and can
+    // be converted on any thread.
+    let convert_band = |(band, dst): (usize, &mut [u8])| {
+        let uv = &chroma[band * pitch..];
+        for (row, dst_row) in dst.chunks_exact_mut(row_bytes).enumerate() {
+            let y_row = (band * 2 + row) * pitch;
+            convert_row(&luma[y_row..y_row + width], uv, dst_row);
         }
+    };
+    #[cfg(feature = "transform-rayon")]
+    {
+        use rayon::prelude::*;
+        rgb.par_chunks_mut(row_bytes * 2)
+            .enumerate()
+            .with_min_len(RAYON_MIN_BANDS)
+            .for_each(convert_band);
     }
+    #[cfg(not(feature = "transform-rayon"))]
+    rgb.chunks_mut(row_bytes * 2)
+        .enumerate()
+        .for_each(convert_band);
 
     Ok(RgbFrame {
         width,
@@ -189,6 +495,34 @@ pub fn nv12_to_rgb24(frame: &Nv12Frame) -> Result<RgbFrame, BackendError> {
     })
 }
 
+// Enough rows per task that scheduling stays well below the conversion cost even for small
+// frames; 1080p still splits into ~34 tasks.
+#[cfg(feature = "transform-rayon")]
+const RAYON_MIN_BANDS: usize = 16;
+
+// BT.601 limited range. The chroma terms are computed once per horizontal pixel pair and the
+// inner loop is branch-free fixed-point math over fixed-size chunks, which LLVM vectorizes.
+#[inline]
+fn convert_row(luma: &[u8], uv: &[u8], dst: &mut [u8]) {
+    for ((dst_pair, y_pair), uv_pair) in dst
+        .chunks_mut(6)
+        .zip(luma.chunks(2))
+        .zip(uv.chunks_exact(2))
+    {
+        let d = i32::from(uv_pair[0]) - 128;
+        let e = i32::from(uv_pair[1]) - 128;
+        let r_offset = 409 * e + 128;
+        let g_offset = -100 * d - 208 * e + 128;
+        let b_offset = 516 * d + 128;
+        for (px, &y) in dst_pair.chunks_exact_mut(3).zip(y_pair) {
+            let c = 298 * (i32::from(y) - 16).max(0);
+            px[0] = clip_to_u8((c + r_offset) >> 8);
+            px[1] = clip_to_u8((c + g_offset) >> 8);
+            px[2] = clip_to_u8((c + b_offset) >> 8);
+        }
+    }
+}
+
 #[inline]
 fn clip_to_u8(value: i32) -> u8 {
     value.clamp(0, 255) as u8
@@ -233,6 +567,51 @@ mod tests {
         assert_eq!(rgb.data.len(), 64 * 36 * 3);
     }
 
+    #[test]
+    fn nv12_to_rgb_matches_per_pixel_reference() {
+        // Odd sizes and padded pitch exercise the band and pixel-pair edges.
+        let (width, height, pitch) = (37_usize, 23_usize, 40_usize);
+        let chroma_rows = height.div_ceil(2);
+        let mut data = vec![0_u8; pitch * (height + chroma_rows)];
+        for (i, value) in data.iter_mut().enumerate() {
+            *value = (i.wrapping_mul(7919) % 251) as u8;
+        }
+        let frame = Nv12Frame {
+            width,
+            height,
+            pitch,
+            pts_90k: Some(3000),
+            data,
+        };
+        let rgb = nv12_to_rgb24(&frame).unwrap();
+        for y in 0..height {
+            for x in 0..width {
+                let uv = pitch * height + (y / 2) * pitch + (x & !1);
+                let c = (i32::from(frame.data[y * pitch + x]) - 16).max(0);
+                let d = i32::from(frame.data[uv]) - 128;
+                let e = i32::from(frame.data[uv + 1]) - 128;
+                let expected = [
+                    clip_to_u8((298 * c + 409 * e + 128) >> 8),
+                    clip_to_u8((298 * c - 100 * d - 208 * e + 128) >> 8),
+                    clip_to_u8((298 * c + 516 * d + 128) >> 8),
+                ];
+                let dst = (y * width + x) * 3;
+                assert_eq!(rgb.data[dst..dst + 3], expected, "pixel ({x}, {y})");
+            }
+        }
+        assert_eq!(rgb.pts_90k, Some(3000));
+    }
+
+    #[test]
+    fn nv12_plane_layout_covers_frame_data() {
+        let frame = make_argb_to_nv12_dummy(64, 36);
+        let planes = frame.planes();
+        assert_eq!(planes.len(), 2);
+        assert_eq!(planes[1].offset, 64 * 36);
+        assert_eq!(planes[1].rows, 18);
+        assert_eq!(planes[1].end(), frame.data.len());
+    }
+
     #[test]
     fn dispatcher_runs_transform_job() {
         let dispatcher = TransformDispatcher::new(2, 8);
@@ -247,9 +626,162 @@ mod tests {
                 assert_eq!(rgb.width, 32);
                 assert_eq!(rgb.height, 18);
             }
+            other => panic!("expected RGB output, got {other:?}"),
         }
     }
 
+    struct Invert;
+
+    impl TransformStage for Invert {
+        fn process(&self, mut frame: Nv12Frame) -> Result<TransformResult, BackendError> {
+            if frame.pts_90k == Some(-1) {
+                panic!("bad frame");
+            }
+            let luma = frame.pitch * frame.height;
+            frame.data[..luma].iter_mut().for_each(|v| *v = 255 - *v);
+            Ok(TransformResult::Nv12(frame))
+        }
+    }
+
+    #[test]
+    fn dispatcher_runs_registered_custom_stage() {
+        let dispatcher = TransformDispatcher::new(1, 4);
+        let stage = dispatcher.register_stage(Invert);
+        let frame = make_argb_to_nv12_dummy(8, 4);
+        let expected = 255 - frame.data[5];
+        dispatcher
+            .submit(TransformJob::Custom { stage, frame })
+            .unwrap();
+        let recv = || dispatcher.recv_timeout(Duration::from_secs(1)).unwrap();
+        match recv() {
+            Ok(TransformResult::Nv12(frame)) => assert_eq!(frame.data[5], expected),
+            other => panic!("expected NV12 output, got {other:?}"),
+        }
+
+        let mut bad = make_argb_to_nv12_dummy(8, 4);
+        bad.pts_90k = Some(-1);
+        dispatcher
+            .submit(TransformJob::Custom { stage, frame: bad })
+            .unwrap();
+        assert!(matches!(recv(), Err(BackendError::Backend(_))));
+        // The worker survived the panic and keeps serving jobs.
+        dispatcher
+            .submit(TransformJob::Custom {
+                stage: TransformStageId(7),
+                frame: make_argb_to_nv12_dummy(8, 4),
+            })
+            .unwrap();
+        assert!(matches!(recv(), Err(BackendError::InvalidInput(_))));
+    }
+
+    struct RecordRuns(Arc<Mutex<Vec<usize>>>);
+
+    impl TransformStage for RecordRuns {
+        fn process(&self, frame: Nv12Frame) -> Result<TransformResult, BackendError> {
+            Ok(TransformResult::Nv12(frame))
+        }
+
+        fn process_batch(
+            &self,
+            frames: Vec<Nv12Frame>,
+        ) -> Vec<Result<TransformResult, BackendError>> {
+            self.0.lock().unwrap().push(frames.len());
+            frames
+                .into_iter()
+                .map(|frame| self.process(frame))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn batches_come_back_in_order_with_stage_runs_grouped() {
+        let dispatcher = TransformDispatcher::new(3, 4);
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let record = dispatcher.register_stage(RecordRuns(runs.clone()));
+        let invert = dispatcher.register_stage(Invert);
+        let frame = |pts| Nv12Frame {
+            pts_90k: Some(pts),
+            ..make_argb_to_nv12_dummy(8, 4)
+        };
+        let custom = |stage, pts| TransformJob::Custom {
+            stage,
+            frame: frame(pts),
+        };
+        let jobs = vec![
+            TransformJob::Nv12ToRgb(frame(0)),
+            custom(record, 1),
+            custom(record, 2),
+            custom(record, 3),
+            custom(invert, 4),
+            custom(record, 5),
+            custom(TransformStageId(9), 6),
+        ];
+        let batch = dispatcher.submit_batch(jobs).unwrap();
+        assert_eq!(batch.len(), 7);
+        let results = batch.wait_timeout(Duration::from_secs(1)).unwrap();
+        let pts = results
+            .iter()
+            .map(|result| match result {
+                Ok(TransformResult::Rgb(frame)) => frame.pts_90k,
+                Ok(TransformResult::Nv12(frame)) => frame.pts_90k,
+                Err(_) => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pts,
+            [Some(0), Some(1), Some(2), Some(3), Some(4), Some(5), None]
+        );
+        assert!(matches!(results[0], Ok(TransformResult::Rgb(_))));
+        assert!(matches!(results[6], Err(BackendError::InvalidInput(_))));
+        assert_eq!(*runs.lock().unwrap(), [3, 1]);
+        // Batch results never reach the shared result queue.
+        assert!(matches!(dispatcher.try_recv(), Err(QueueRecvError::Empty)));
+    }
+
+    #[test]
+    fn routing_rejects_formats_the_kernels_cannot_read() {
+        assert_eq!(
+            route_transform(PixelFormat::P010, ColorRequest::KeepNative).unwrap(),
+            TransformRoute::Passthrough
+        );
+        assert_eq!(
+            route_transform(PixelFormat::Nv12, ColorRequest::Rgb8).unwrap(),
+            TransformRoute::Nv12ToRgb
+        );
+        let err = route_transform(PixelFormat::P010, ColorRequest::Rgb8).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unsupported conversion: p010 -> rgb8 (supported: nv12->rgb8 rgb8->rgb8 rgba8->rgba8 any->keep_native)"
+        );
+
+        let dispatcher = TransformDispatcher::new(1, 4);
+        let frame = make_argb_to_nv12_dummy(8, 4);
+        assert!(matches!(
+            dispatcher.submit_routed(frame.clone(), PixelFormat::Nv12, ColorRequest::Rgba8),
+            Err(BackendError::UnsupportedConversion(UnsupportedConversion {
+                source: PixelFormat::Nv12,
+                requested: ColorRequest::Rgba8,
+                ..
+            }))
+        ));
+        assert!(
+            dispatcher
+                .submit_routed(frame.clone(), PixelFormat::Nv12, ColorRequest::KeepNative)
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            dispatcher
+                .submit_routed(frame, PixelFormat::Nv12, ColorRequest::Rgb8)
+                .unwrap()
+                .is_none()
+        );
+        assert!(matches!(
+            dispatcher.recv_timeout(Duration::from_secs(1)).unwrap(),
+            Ok(TransformResult::Rgb(_))
+        ));
+    }
+
     #[test]
     fn keep_native_fast_path_bypasses_transform() {
         assert!(!should_enqueue_transform(ColorRequest::KeepNative, None));
@@ -0,0 +1,148 @@
+use std::time::{Duration, Instant};
+
+pub(crate) const UTILIZATION_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EngineUtilization {
+    // Share of `window` during which the session had work in the hardware engine, 0.0..=1.0.
+    // Several sessions on one GPU add up; near 1.0 the engine is saturated for this session.
+    pub busy_ratio: f64,
+    pub window: Duration,
+    // Submitted frames whose output has not come back yet.
+    pub in_flight: usize,
+}
+
+// Busy-time estimate from the session's point of view: the engine counts as busy from a submit
+// until every piece of work submitted so far has produced its output. Backend calls themselves
+// count as one unit of work, so synchronous backends report the time spent inside them.
+// Ratios are reported for the last complete window, or the current one before the first
+// window has elapsed.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct UtilizationTracker {
+    window: Duration,
+    window_start: Instant,
+    busy: Duration,
+    busy_since: Option<Instant>,
+    active: usize,
+    last_window_busy: Option<Duration>,
+}
+
+impl UtilizationTracker {
+    pub(crate) fn new(now: Instant) -> Self {
+        Self {
+            window: UTILIZATION_WINDOW,
+            window_start: now,
+            busy: Duration::ZERO,
+            busy_since: None,
+            active: 0,
+            last_window_busy: None,
+        }
+    }
+
+    pub(crate) fn begin(&mut self, now: Instant) {
+        self.roll(now);
+        if self.active == 0 {
+            self.busy_since = Some(now);
+        }
+        self.active += 1;
+    }
+
+    pub(crate) fn end(&mut self, completed: usize, now: Instant) {
+        self.roll(now);
+        self.active = self.active.saturating_sub(completed);
+        if self.active == 0
+            && let Some(since) = self.busy_since.take()
+        {
+            self.busy += now.saturating_duration_since(since);
+        }
+    }
+
+    // Everything submitted has completed, e.g. after a flush.
+    pub(crate) fn idle(&mut self, now: Instant) {
+        self.end(self.active, now);
+    }
+
+    pub(crate) fn snapshot(&self, now: Instant) -> EngineUtilization {
+        let mut view = *self;
+        view.roll(now);
+        let (busy, window) = match view.last_window_busy {
+            Some(busy) => (busy, view.window),
+            None => {
+                let open = view
+                    .busy_since
+                    .map_or(Duration::ZERO, |since| now.saturating_duration_since(since));
+                (
+                    view.busy + open,
+                    now.saturating_duration_since(view.window_start),
+                )
+            }
+        };
+        let busy_ratio = if window.is_zero() {
+            0.0
+        } else {
+            (busy.as_secs_f64() / window.as_secs_f64()).min(1.0)
+        };
+        EngineUtilization {
+            busy_ratio,
+            window,
+            in_flight: self.active,
+        }
+    }
+
+    fn roll(&mut self, now: Instant) {
+        let end = self.window_start + self.window;
+        if now < end {
+            return;
+        }
+        let open = self
+            .busy_since
+            .map_or(Duration::ZERO, |since| end.saturating_duration_since(since));
+        self.last_window_busy = Some((self.busy + open).min(self.window));
+        self.busy = Duration::ZERO;
+        self.window_start = end;
+        // Whole windows without any call were either entirely busy or entirely idle.
+        let skipped = (now - self.window_start).as_nanos() / self.window.as_nanos();
+        if skipped > 0 {
+            self.window_start += self.window * u32::try_from(skipped).unwrap_or(u32::MAX);
+            self.last_window_busy = Some(if self.busy_since.is_some() {
+                self.window
+            } else {
+                Duration::ZERO
+            });
+        }
+        if self.busy_since.is_some() {
+            self.busy_since = Some(self.window_start);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busy_ratio_counts_overlapping_work_once() {
+        let start = Instant::now();
+        let ms = |value: u64| start + Duration::from_millis(value);
+        let mut tracker = UtilizationTracker::new(start);
+        // Two frames in flight from 0..300 ms, then a synchronous call 500..600 ms.
+        tracker.begin(ms(0));
+        tracker.begin(ms(100));
+        tracker.end(1, ms(200));
+        tracker.end(1, ms(300));
+        tracker.begin(ms(500));
+        tracker.end(1, ms(600));
+        let partial = tracker.snapshot(ms(800));
+        assert!((partial.busy_ratio - 0.5).abs() < 1e-9);
+        assert_eq!(partial.in_flight, 0);
+
+        // A frame still in flight across the window boundary keeps the next window busy.
+        tracker.begin(ms(900));
+        let report = tracker.snapshot(ms(1500));
+        assert!((report.busy_ratio - 0.5).abs() < 1e-9);
+        assert_eq!((report.window, report.in_flight), (UTILIZATION_WINDOW, 1));
+        assert!((tracker.snapshot(ms(2100)).busy_ratio - 1.0).abs() < 1e-9);
+        tracker.idle(ms(2100));
+        assert_eq!(tracker.snapshot(ms(4500)).busy_ratio, 0.0);
+    }
+}
@@ -1,25 +1,31 @@
 use std::{
-    collections::VecDeque,
+    collections::{HashSet, VecDeque},
     ffi::c_void,
+    mem,
     sync::{
         Arc, Mutex,
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicU64, AtomicUsize, Ordering},
     },
     time::{Duration, Instant},
 };
 
 use crate::backend_transform_adapter::{DecodedUnit, VtTransformAdapter};
 use crate::bitstream::{AccessUnit, ParameterSetCache, StatefulBitstreamAssembler};
+use crate::pipeline::{BoundedQueueTx, bounded_queue};
 use crate::pipeline_scheduler::PipelineScheduler;
+use crate::reap_cancel::ReadyWaker;
+use crate::vt_multiview::{self, StereoCompressionSession};
 use crate::{
-    BackendError, CapabilityReport, Codec, ColorRequest, DecodeSummary, DecoderConfig,
-    EncodedPacket, Frame, SessionSwitchMode, SessionSwitchRequest, VideoDecoder, VideoEncoder,
-    VtSessionConfig,
+    BackendEncoderOptions, BackendError, BackendPixelFormat, CapabilityReport, Codec, ColorRequest,
+    DecodeInfoFlags, DecodeSummary, DecoderConfig, DiagnosticEvent, Diagnostics, EncodedPacket,
+    Frame, FrameCrop, FrameRate, PlaneLayout, SessionSwitchMode, SessionSwitchRequest, StereoView,
+    VideoDecoder, VideoEncoder, VtEncoderInfo, VtPropertyValue, VtSessionConfig,
 };
 use core_foundation::{
+    array::{CFArray, CFArrayRef},
     base::{CFAllocator, CFType, TCFType, kCFAllocatorSystemDefault},
     boolean::CFBoolean,
-    dictionary::{CFDictionary, CFMutableDictionary},
+    dictionary::{CFDictionary, CFDictionaryRef, CFMutableDictionary},
     number::CFNumber,
     string::CFString,
 };
@@ -27,7 +33,7 @@ use core_media::{
     block_buffer::CMBlockBuffer,
     format_description::{
         CMFormatDescription, CMVideoCodecType, CMVideoFormatDescription, kCMVideoCodecType_H264,
-        kCMVideoCodecType_HEVC,
+        kCMVideoCodecType_HEVC, kCMVideoCodecType_JPEG,
     },
     sample_buffer::{CMSampleBuffer, CMSampleTimingInfo},
     time::{CMTime, kCMTimeInvalid},
@@ -38,7 +44,8 @@ use core_video::{
         CVColorPrimariesGetIntegerCodePointForString, CVImageBuffer, CVImageBufferKeys,
         CVTransferFunctionGetIntegerCodePointForString, CVYCbCrMatrixGetIntegerCodePointForString,
     },
-    pixel_buffer::{CVPixelBuffer, kCVPixelFormatType_32BGRA},
+    pixel_buffer::{CVPixelBuffer, CVPixelBufferKeys, kCVPixelFormatType_32BGRA},
+    pixel_buffer_pool::{CVPixelBufferPool, CVPixelBufferPoolKeys},
 };
 use video_toolbox::{
     compression_properties::{
@@ -81,33 +88,60 @@ impl SamplePacker for AvccHvccPacker {
     }
 }
 
+// JPEG samples are the image bytes verbatim, without any length prefix.
+#[derive(Debug, Default)]
+pub struct JpegPacker;
+
+impl SamplePacker for JpegPacker {
+    fn pack(&mut self, access_unit: &AccessUnit) -> Result<PackedSample, BackendError> {
+        Ok(PackedSample {
+            data: access_unit.nalus.concat(),
+        })
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 struct DecodeOutputState {
     decoded_frames: usize,
     width: Option<usize>,
     height: Option<usize>,
-    pixel_format: Option<u32>,
+    pixel_format: Option<BackendPixelFormat>,
     pending_frames: VecDeque<Frame>,
+    // Signalled from the callback so a session blocked in reap_timeout picks the frame up.
+    ready_waker: Option<ReadyWaker>,
 }
 
 struct VtDecoderSession {
     session: VTDecompressionSession,
+    codec: Codec,
     format_description: CMVideoFormatDescription,
     decode_state: Box<Mutex<DecodeOutputState>>,
     next_pts: Mutex<i64>,
 }
 
 impl VtDecoderSession {
-    fn new(config: &DecoderConfig, parameter_sets: &[Vec<u8>]) -> Result<Self, BackendError> {
-        let codec_type = to_cm_codec_type(config.codec);
-        if config.require_hardware
-            && !VTDecompressionSession::is_hardware_decode_supported(codec_type)
-        {
+    fn new(
+        config: &DecoderConfig,
+        parameter_sets: &[Vec<u8>],
+        ready_waker: Option<ReadyWaker>,
+    ) -> Result<Self, BackendError> {
+        if config.require_hardware && config.force_software {
+            return Err(BackendError::UnsupportedConfig(
+                "require_hardware and force_software cannot both be set".to_string(),
+            ));
+        }
+        if config.require_hardware && !hardware_decode_supported(config.codec) {
             return Err(BackendError::UnsupportedConfig(format!(
                 "{} hardware decode is not supported on this machine",
                 codec_label(config.codec)
             )));
         }
+        if config.stereo_views && (config.codec != Codec::Hevc || !vt_multiview::support().decode) {
+            return Err(BackendError::UnsupportedConfig(format!(
+                "stereo_views needs MV-HEVC decode support, not available for {} here",
+                codec_label(config.codec)
+            )));
+        }
 
         let format_description = create_format_description(config.codec, parameter_sets)?;
 
@@ -118,11 +152,21 @@ impl VtDecoderSession {
                 &CFBoolean::true_value().as_CFType(),
             );
             Some(spec.to_immutable())
+        } else if config.force_software {
+            let mut spec = CFMutableDictionary::<CFString, CFType>::new();
+            spec.add(
+                &VideoDecoderSpecification::EnableHardwareAcceleratedVideoDecoder.into(),
+                &CFBoolean::false_value().as_CFType(),
+            );
+            Some(spec.to_immutable())
         } else {
             None
         };
 
-        let mut decode_state = Box::new(Mutex::new(DecodeOutputState::default()));
+        let mut decode_state = Box::new(Mutex::new(DecodeOutputState {
+            ready_waker,
+            ..DecodeOutputState::default()
+        }));
         let decode_state_ptr =
             (&mut *decode_state as *mut Mutex<DecodeOutputState>).cast::<c_void>();
         let callback = VTDecompressionOutputCallbackRecord {
@@ -130,18 +174,42 @@ impl VtDecoderSession {
             decompressionOutputRefCon: decode_state_ptr,
         };
 
+        // VideoToolbox scales into destination buffers of the requested size.
+        let destination_attributes = config.output_scale.map(|dims| {
+            let mut attributes = CFMutableDictionary::<CFString, CFType>::new();
+            attributes.add(
+                &CVPixelBufferKeys::Width.into(),
+                &CFNumber::from(i64::from(dims.width.get())).as_CFType(),
+            );
+            attributes.add(
+                &CVPixelBufferKeys::Height.into(),
+                &CFNumber::from(i64::from(dims.height.get())).as_CFType(),
+            );
+            attributes.to_immutable()
+        });
+
         let session = unsafe {
             VTDecompressionSession::new_with_callback(
                 format_description.clone(),
                 decoder_specification,
-                None,
+                destination_attributes,
                 Some(&callback as *const VTDecompressionOutputCallbackRecord),
             )
         }
         .map_err(|status| vt_error("VTDecompressionSession::new_with_callback", status))?;
+        if config.stereo_views {
+            unsafe {
+                vt_multiview::enable_stereo_decode(
+                    session.as_concrete_TypeRef() as *mut c_void,
+                    vt_decode_multi_image_callback,
+                    decode_state_ptr,
+                )?;
+            }
+        }
 
         Ok(Self {
             session,
+            codec: config.codec,
             format_description,
             decode_state,
             next_pts: Mutex::new(0),
@@ -151,9 +219,12 @@ impl VtDecoderSession {
     fn decode_access_units(
         &self,
         access_units: &[AccessUnit],
-        fps: i32,
+        fps: FrameRate,
     ) -> Result<(), BackendError> {
-        let mut packer = AvccHvccPacker;
+        let mut packer: Box<dyn SamplePacker> = match self.codec {
+            Codec::Mjpeg => Box::new(JpegPacker),
+            Codec::H264 | Codec::Hevc => Box::new(AvccHvccPacker),
+        };
         for access_unit in access_units {
             let packed = packer.pack(access_unit)?;
 
@@ -180,8 +251,8 @@ impl VtDecoderSession {
                 )
             };
             let timing = CMSampleTimingInfo {
-                duration: CMTime::make(1, fps),
-                presentationTimeStamp: CMTime::make(self.next_pts(), fps),
+                duration: cm_frame_time(fps, 1),
+                presentationTimeStamp: cm_frame_time(fps, self.next_pts()),
                 decodeTimeStamp: unsafe { kCMTimeInvalid },
             };
             let sample_buffer = CMSampleBuffer::new_ready(
@@ -263,17 +334,27 @@ pub struct VtDecoderAdapter {
     config: DecoderConfig,
     assembler: StatefulBitstreamAssembler,
     decoder: Option<VtDecoderSession>,
+    decoder_revision: u64,
+    // Output and frame count of sessions retired by a parameter set change, not yet reported.
+    retired_frames: Vec<Frame>,
+    retired_decoded_frames: usize,
     last_summary: DecodeSummary,
     last_output_pts_90k: Option<i64>,
     pipeline_scheduler: Option<PipelineScheduler>,
+    ready_waker: Option<ReadyWaker>,
+    diagnostics: Diagnostics,
 }
 
 impl VtDecoderAdapter {
     pub fn new(config: DecoderConfig) -> Self {
         Self {
-            assembler: StatefulBitstreamAssembler::with_codec(config.codec),
+            assembler: StatefulBitstreamAssembler::with_codec(config.codec)
+                .with_max_temporal_id(config.max_temporal_id),
             config,
             decoder: None,
+            decoder_revision: 0,
+            retired_frames: Vec::new(),
+            retired_decoded_frames: 0,
             last_summary: DecodeSummary {
                 decoded_frames: 0,
                 width: None,
@@ -290,15 +371,51 @@ impl VtDecoderAdapter {
             } else {
                 None
             },
+            ready_waker: None,
+            diagnostics: Diagnostics::default(),
         }
     }
 
     fn ensure_decoder(&mut self, cache: &ParameterSetCache) -> Result<(), BackendError> {
-        if self.decoder.is_some() {
-            return Ok(());
-        }
+        let rebuild = match self.decoder.take() {
+            Some(decoder) if self.decoder_revision == cache.content_revision() => {
+                self.decoder = Some(decoder);
+                return Ok(());
+            }
+            // A new SPS id or a new generation of a known one: the format description only
+            // holds the sets it was created with, so finish the old session and start again
+            // with every stored set.
+            Some(decoder) => {
+                decoder.wait_for_completion()?;
+                self.retired_frames.extend(decoder.drain_output_frames());
+                self.retired_decoded_frames += decoder.snapshot_summary().decoded_frames;
+                true
+            }
+            None => false,
+        };
         if let Some(parameter_sets) = cache.required_for_codec(self.config.codec) {
-            self.decoder = Some(VtDecoderSession::new(&self.config, &parameter_sets)?);
+            self.decoder = Some(VtDecoderSession::new(
+                &self.config,
+                &parameter_sets,
+                self.ready_waker.clone(),
+            )?);
+            self.decoder_revision = cache.content_revision();
+            if rebuild {
+                return Ok(());
+            }
+            if self.config.force_software {
+                self.diagnostics.emit(DiagnosticEvent::SoftwareFallback {
+                    reason: "force_software requested".to_string(),
+                });
+            } else if !self.config.require_hardware && !hardware_decode_supported(self.config.codec)
+            {
+                self.diagnostics.emit(DiagnosticEvent::SoftwareFallback {
+                    reason: format!(
+                        "{} hardware decode is not supported on this machine",
+                        codec_label(self.config.codec)
+                    ),
+                });
+            }
         }
         Ok(())
     }
@@ -309,18 +426,16 @@ impl VtDecoderAdapter {
             if wait {
                 decoder.wait_for_completion()?;
             }
-            let frames = decoder.drain_output_frames();
-            let summary = decoder.snapshot_summary();
+            let mut frames = mem::take(&mut self.retired_frames);
+            frames.extend(decoder.drain_output_frames());
+            let mut summary = decoder.snapshot_summary();
+            summary.decoded_frames += self.retired_decoded_frames;
             let delta = frames.len();
             self.last_summary = summary.clone();
             let processed = self.preprocess_frames_via_pipeline(frames)?;
             if should_report_metrics() {
                 let mut jitter_stats = SampleStats::default();
-                let expected_frame_ms = if self.config.fps > 0 {
-                    1_000.0 / self.config.fps as f64
-                } else {
-                    33.333
-                };
+                let expected_frame_ms = self.config.fps.frame_duration_ms();
                 for frame in &processed {
                     update_jitter_samples(
                         &mut jitter_stats,
@@ -329,19 +444,22 @@ impl VtDecoderAdapter {
                         expected_frame_ms,
                     );
                 }
-                eprintln!(
-                    "[vt.decode] wait={}, delta_frames={}, total_frames={}, width={:?}, height={:?}, elapsed_ms={:.3}, jitter_ms_mean={:.3}, jitter_ms_p95={:.3}, jitter_ms_p99={:.3}, output_copy_frames={}",
-                    wait,
-                    delta,
-                    summary.decoded_frames,
-                    summary.width,
-                    summary.height,
-                    start.elapsed().as_secs_f64() * 1_000.0,
-                    jitter_stats.mean(),
-                    jitter_stats.p95(),
-                    jitter_stats.p99(),
-                    processed.len(),
-                );
+                self.diagnostics.emit(DiagnosticEvent::Metrics {
+                    scope: "vt.decode",
+                    detail: format!(
+                        "wait={}, delta_frames={}, total_frames={}, width={:?}, height={:?}, elapsed_ms={:.3}, jitter_ms_mean={:.3}, jitter_ms_p95={:.3}, jitter_ms_p99={:.3}, output_copy_frames={}",
+                        wait,
+                        delta,
+                        summary.decoded_frames,
+                        summary.width,
+                        summary.height,
+                        start.elapsed().as_secs_f64() * 1_000.0,
+                        jitter_stats.mean(),
+                        jitter_stats.p95(),
+                        jitter_stats.p99(),
+                        processed.len(),
+                    ),
+                });
             }
             return Ok(processed);
         }
@@ -389,12 +507,11 @@ impl VtDecoderAdapter {
 
 impl VideoDecoder for VtDecoderAdapter {
     fn query_capability(&self, codec: Codec) -> Result<CapabilityReport, BackendError> {
-        let cm_codec = to_cm_codec_type(codec);
         Ok(CapabilityReport {
             codec,
             decode_supported: true,
-            encode_supported: true,
-            hardware_acceleration: VTDecompressionSession::is_hardware_decode_supported(cm_codec),
+            encode_supported: codec != Codec::Mjpeg,
+            hardware_acceleration: hardware_decode_supported(codec),
         })
     }
 
@@ -417,12 +534,15 @@ impl VideoDecoder for VtDecoderAdapter {
             }
         }
         if should_report_metrics() {
-            eprintln!(
-                "[vt.decode.submit] flush=false, access_units={}, input_copy_bytes={}, submit_ms={:.3}",
-                access_unit_count,
-                input_copy_bytes,
-                submit_start.elapsed().as_secs_f64() * 1_000.0
-            );
+            self.diagnostics.emit(DiagnosticEvent::Metrics {
+                scope: "vt.decode.submit",
+                detail: format!(
+                    "flush=false, access_units={}, input_copy_bytes={}, submit_ms={:.3}",
+                    access_unit_count,
+                    input_copy_bytes,
+                    submit_start.elapsed().as_secs_f64() * 1_000.0
+                ),
+            });
         }
 
         self.take_delta(false)
@@ -441,26 +561,72 @@ impl VideoDecoder for VtDecoderAdapter {
             }
         }
         if should_report_metrics() {
-            eprintln!(
-                "[vt.decode.submit] flush=true, access_units={}, input_copy_bytes={}, submit_ms={:.3}",
-                access_unit_count,
-                input_copy_bytes,
-                submit_start.elapsed().as_secs_f64() * 1_000.0
-            );
+            self.diagnostics.emit(DiagnosticEvent::Metrics {
+                scope: "vt.decode.submit",
+                detail: format!(
+                    "flush=true, access_units={}, input_copy_bytes={}, submit_ms={:.3}",
+                    access_unit_count,
+                    input_copy_bytes,
+                    submit_start.elapsed().as_secs_f64() * 1_000.0
+                ),
+            });
         }
 
         self.take_delta(true)
     }
 
+    // Frames the callback has queued so far; nothing is submitted or waited for.
+    fn poll_ready(&mut self) -> Result<Vec<Frame>, BackendError> {
+        self.take_delta(false)
+    }
+
+    fn set_ready_waker(&mut self, waker: ReadyWaker) {
+        if let Some(decoder) = self.decoder.as_ref()
+            && let Ok(mut state) = decoder.decode_state.lock()
+        {
+            state.ready_waker = Some(waker.clone());
+        }
+        self.ready_waker = Some(waker);
+    }
+
     fn decode_summary(&self) -> DecodeSummary {
         self.last_summary.clone()
     }
+
+    fn frame_crop(&self) -> Option<FrameCrop> {
+        self.assembler
+            .parameter_sets()
+            .frame_crop(self.config.codec)
+    }
+
+    fn parameter_set_revision(&self) -> u64 {
+        self.assembler.parameter_sets().revision()
+    }
+
+    fn set_diagnostics(&mut self, diagnostics: Diagnostics) {
+        self.diagnostics = diagnostics;
+    }
+
+    fn close(&mut self) -> Result<(), BackendError> {
+        self.pipeline_scheduler = None;
+        self.decoder = None;
+        Ok(())
+    }
 }
 
 pub struct VtEncoderAdapter {
     codec: Codec,
-    fps: i32,
+    fps: FrameRate,
     require_hardware: bool,
+    quality: Option<f32>,
+    max_keyframe_interval: Option<u32>,
+    average_bitrate_bps: Option<u64>,
+    max_qp: Option<u32>,
+    real_time: bool,
+    prioritize_speed: Option<bool>,
+    allow_frame_reordering: Option<bool>,
+    extra_properties: Vec<(String, VtPropertyValue)>,
+    encoder_id: Option<String>,
     pending_frames: Vec<Frame>,
     width: Option<usize>,
     height: Option<usize>,
@@ -471,6 +637,9 @@ pub struct VtEncoderAdapter {
     session_reconfigure_pending: bool,
     pipeline_scheduler: Option<PipelineScheduler>,
     encode_session: Option<VtEncodeSession>,
+    stereo_session: Option<VtStereoSession>,
+    pixel_buffer_pool: Option<Arc<VtPixelBufferPool>>,
+    diagnostics: Diagnostics,
 }
 
 struct VtEncodeSession {
@@ -479,6 +648,12 @@ struct VtEncodeSession {
     height: usize,
 }
 
+struct VtStereoSession {
+    session: StereoCompressionSession,
+    width: usize,
+    height: usize,
+}
+
 #[derive(Clone)]
 struct VtPendingPacket {
     frame_index: usize,
@@ -531,11 +706,31 @@ impl SampleStats {
 }
 
 impl VtEncoderAdapter {
-    pub fn with_config(codec: Codec, fps: i32, require_hardware: bool) -> Self {
+    pub fn with_config(
+        codec: Codec,
+        fps: FrameRate,
+        require_hardware: bool,
+        backend_options: BackendEncoderOptions,
+    ) -> Self {
+        let options = match backend_options {
+            BackendEncoderOptions::VideoToolbox(options) => options,
+            BackendEncoderOptions::Default | BackendEncoderOptions::Nvidia(_) => {
+                crate::VtEncoderOptions::default()
+            }
+        };
         Self {
             codec,
             fps,
             require_hardware,
+            quality: options.quality.map(|v| v.clamp(0.0, 1.0)),
+            max_keyframe_interval: options.max_keyframe_interval.filter(|&v| v > 0),
+            average_bitrate_bps: options.average_bitrate_bps.filter(|&v| v > 0),
+            max_qp: options.max_qp,
+            real_time: options.real_time.unwrap_or(false),
+            prioritize_speed: options.prioritize_speed,
+            allow_frame_reordering: options.allow_frame_reordering,
+            extra_properties: options.extra_properties,
+            encoder_id: options.encoder_id,
             pending_frames: Vec::new(),
             width: None,
             height: None,
@@ -554,6 +749,9 @@ impl VtEncoderAdapter {
                 None
             },
             encode_session: None,
+            stereo_session: None,
+            pixel_buffer_pool: None,
+            diagnostics: Diagnostics::default(),
         }
     }
 
@@ -599,14 +797,9 @@ impl VtEncoderAdapter {
         width: usize,
         height: usize,
     ) -> Result<VTCompressionSession, BackendError> {
-        let mut encoder_specification = CFMutableDictionary::<CFString, CFType>::new();
-        if self.require_hardware {
-            encoder_specification.add(
-                &VideoEncoderSpecification::RequireHardwareAcceleratedVideoEncoder.into(),
-                &CFBoolean::true_value().as_CFType(),
-            );
+        if self.codec == Codec::Mjpeg {
+            return Err(BackendError::UnsupportedCodec(self.codec));
         }
-
         let source_image_buffer_attributes = CFMutableDictionary::<CFString, CFType>::new();
         let allocator = unsafe { CFAllocator::wrap_under_get_rule(kCFAllocatorSystemDefault) };
 
@@ -614,31 +807,18 @@ impl VtEncoderAdapter {
             width as i32,
             height as i32,
             to_cm_codec_type(self.codec),
-            encoder_specification.to_immutable(),
+            self.encoder_specification(),
             source_image_buffer_attributes.to_immutable(),
             allocator,
         )
         .map_err(|status| vt_error("VTCompressionSession::new", status))?;
 
         let session_ref = session.as_session();
-        session_ref
-            .set_property(
-                CompressionPropertyKey::RealTime.into(),
-                CFBoolean::false_value().as_CFType(),
-            )
-            .map_err(|status| vt_error("VTSessionSetProperty(RealTime)", status))?;
-        session_ref
-            .set_property(
-                CompressionPropertyKey::ExpectedFrameRate.into(),
-                CFNumber::from(self.fps).as_CFType(),
-            )
-            .map_err(|status| vt_error("VTSessionSetProperty(ExpectedFrameRate)", status))?;
-        session_ref
-            .set_property(
-                CompressionPropertyKey::MaxKeyFrameInterval.into(),
-                CFNumber::from(self.fps.saturating_mul(2)).as_CFType(),
-            )
-            .map_err(|status| vt_error("VTSessionSetProperty(MaxKeyFrameInterval)", status))?;
+        for (label, key, value) in self.session_properties() {
+            session_ref
+                .set_property(key, value)
+                .map_err(|status| vt_error(&format!("VTSessionSetProperty({label})"), status))?;
+        }
 
         session
             .prepare_to_encode_frames()
@@ -647,6 +827,189 @@ impl VtEncoderAdapter {
         Ok(session)
     }
 
+    fn ensure_stereo_session(
+        &mut self,
+        width: usize,
+        height: usize,
+    ) -> Result<&StereoCompressionSession, BackendError> {
+        let reusable = self.stereo_session.as_ref().is_some_and(|existing| {
+            existing.width == width
+                && existing.height == height
+                && !self.session_reconfigure_pending
+        });
+        if !reusable {
+            // The old session goes first so two hardware sessions never coexist.
+            self.stereo_session = None;
+            let session = StereoCompressionSession::new(
+                width,
+                height,
+                &self.encoder_specification(),
+                self.session_properties(),
+            )?;
+            self.stereo_session = Some(VtStereoSession {
+                session,
+                width,
+                height,
+            });
+            self.session_reconfigure_pending = false;
+        }
+        self.stereo_session
+            .as_ref()
+            .map(|s| &s.session)
+            .ok_or_else(|| BackendError::Backend("active VT stereo session is missing".to_string()))
+    }
+
+    // MV-HEVC pairs go through a separate session one at a time: the left eye is `argb`, the
+    // right eye `stereo_right`, and each pair comes back as one sample holding both layers.
+    fn flush_stereo(
+        &mut self,
+        frames: &[Frame],
+        width: usize,
+        height: usize,
+    ) -> Result<Vec<EncodedPacket>, BackendError> {
+        if self.codec != Codec::Hevc {
+            return Err(BackendError::UnsupportedCodec(self.codec));
+        }
+        let (codec, fps) = (self.codec, self.fps);
+        let pool = self.ensure_pixel_buffer_pool(width, height)?;
+        let session = self.ensure_stereo_session(width, height)?;
+        for (frame_index, frame) in frames.iter().enumerate() {
+            let left = make_bgra_frame(&pool, frame_index, frame.argb.as_deref())?;
+            let right = make_bgra_frame(&pool, frame_index, frame.stereo_right.as_deref())?;
+            let presentation_time_stamp = frame
+                .pts_90k
+                .map(cm_time_from_90k)
+                .unwrap_or_else(|| cm_frame_time(fps, frame_index as i64));
+            session.encode(
+                frame_index,
+                &left,
+                &right,
+                presentation_time_stamp,
+                cm_frame_time(fps, 1),
+                &frame_encode_properties(frame.force_keyframe),
+            )?;
+        }
+        Ok(session
+            .complete()?
+            .into_iter()
+            .filter_map(|(frame_index, data)| {
+                let frame = frames.get(frame_index)?;
+                Some(EncodedPacket {
+                    codec,
+                    data,
+                    pts_90k: frame.pts_90k,
+                    is_keyframe: frame_index == 0 || frame.force_keyframe,
+                })
+            })
+            .collect())
+    }
+
+    fn encoder_specification(&self) -> CFDictionary<CFString, CFType> {
+        let mut encoder_specification = CFMutableDictionary::<CFString, CFType>::new();
+        if self.require_hardware {
+            encoder_specification.add(
+                &VideoEncoderSpecification::RequireHardwareAcceleratedVideoEncoder.into(),
+                &CFBoolean::true_value().as_CFType(),
+            );
+        }
+        if let Some(encoder_id) = &self.encoder_id {
+            encoder_specification.add(
+                &CFString::from_static_string(ENCODER_ID_KEY),
+                &CFString::new(encoder_id).as_CFType(),
+            );
+        }
+        encoder_specification.to_immutable()
+    }
+
+    // Session properties with the label their errors are reported under, shared by the mono
+    // and the stereo session.
+    fn session_properties(&self) -> Vec<(String, CFString, CFType)> {
+        let mut properties = vec![
+            (
+                "RealTime".to_string(),
+                CompressionPropertyKey::RealTime.into(),
+                CFBoolean::from(self.real_time).as_CFType(),
+            ),
+            (
+                "ExpectedFrameRate".to_string(),
+                CompressionPropertyKey::ExpectedFrameRate.into(),
+                CFNumber::from(self.fps.as_f64()).as_CFType(),
+            ),
+            (
+                "MaxKeyFrameInterval".to_string(),
+                CompressionPropertyKey::MaxKeyFrameInterval.into(),
+                CFNumber::from(
+                    self.max_keyframe_interval
+                        .unwrap_or_else(|| self.fps.rounded().saturating_mul(2))
+                        as i32,
+                )
+                .as_CFType(),
+            ),
+        ];
+        if let Some(bitrate) = self.average_bitrate_bps {
+            properties.push((
+                "AverageBitRate".to_string(),
+                CompressionPropertyKey::AverageBitRate.into(),
+                CFNumber::from(bitrate.min(i64::MAX as u64) as i64).as_CFType(),
+            ));
+        }
+        if let Some(qp) = self.max_qp {
+            properties.push((
+                "MaxAllowedFrameQP".to_string(),
+                CFString::new("MaxAllowedFrameQP"),
+                CFNumber::from(qp.min(i32::MAX as u32) as i32).as_CFType(),
+            ));
+        }
+        if let Some(prioritize) = self.prioritize_speed {
+            properties.push((
+                "PrioritizeEncodingSpeedOverQuality".to_string(),
+                CFString::new("PrioritizeEncodingSpeedOverQuality"),
+                CFBoolean::from(prioritize).as_CFType(),
+            ));
+        }
+        if let Some(allow) = self.allow_frame_reordering {
+            properties.push((
+                "AllowFrameReordering".to_string(),
+                CFString::new("AllowFrameReordering"),
+                CFBoolean::from(allow).as_CFType(),
+            ));
+        }
+        if let Some(quality) = self.quality {
+            properties.push((
+                "Quality".to_string(),
+                CompressionPropertyKey::Quality.into(),
+                CFNumber::from(quality).as_CFType(),
+            ));
+        }
+        for (key, value) in &self.extra_properties {
+            let value = match value {
+                VtPropertyValue::Bool(value) => CFBoolean::from(*value).as_CFType(),
+                VtPropertyValue::Int(value) => CFNumber::from(*value).as_CFType(),
+                VtPropertyValue::Float(value) => CFNumber::from(*value).as_CFType(),
+                VtPropertyValue::String(value) => CFString::new(value).as_CFType(),
+            };
+            properties.push((key.clone(), CFString::new(key), value));
+        }
+        properties
+    }
+
+    // Kept across flushes so buffers released by VideoToolbox are recycled instead of
+    // reallocated per frame; a size change starts a fresh pool.
+    fn ensure_pixel_buffer_pool(
+        &mut self,
+        width: usize,
+        height: usize,
+    ) -> Result<Arc<VtPixelBufferPool>, BackendError> {
+        match &self.pixel_buffer_pool {
+            Some(pool) if pool.width == width && pool.height == height => Ok(Arc::clone(pool)),
+            _ => {
+                let pool = Arc::new(VtPixelBufferPool::new(width, height)?);
+                self.pixel_buffer_pool = Some(Arc::clone(&pool));
+                Ok(pool)
+            }
+        }
+    }
+
     fn ensure_encode_session(
         &mut self,
         width: usize,
@@ -713,11 +1076,89 @@ impl VtEncoderAdapter {
         }
     }
 
+    // VTCompressionSession has no in-place resize, so the session and its pixel buffer pool
+    // are recreated at the new size by the next flush, which opens with a keyframe.
+    fn apply_resize(&mut self) -> Result<(), BackendError> {
+        if !self.pending_frames.is_empty() {
+            return Err(BackendError::InvalidInput(
+                "frames of the previous size must be flushed before a resize".to_string(),
+            ));
+        }
+        let target_generation = self.next_generation;
+        self.next_generation = self.next_generation.saturating_add(1);
+        self.config_generation = target_generation;
+        self.force_next_keyframe = true;
+        self.encode_session = None;
+        self.stereo_session = None;
+        self.diagnostics.emit(DiagnosticEvent::Reconfigured {
+            generation: target_generation,
+            force_idr: true,
+        });
+        Ok(())
+    }
+
+    // Sets what `config` asks for on the running compression session, if there is one.
+    fn set_live_properties(&self, config: &VtSessionConfig) -> Result<(), BackendError> {
+        let Some(active) = self.encode_session.as_ref() else {
+            return Ok(());
+        };
+        let mut properties = Vec::new();
+        if let (Some(quality), Some(_)) = (self.quality, config.quality) {
+            properties.push((
+                "Quality",
+                CFString::from(CompressionPropertyKey::Quality),
+                CFNumber::from(quality).as_CFType(),
+            ));
+        }
+        if let (Some(bitrate), Some(_)) = (self.average_bitrate_bps, config.average_bitrate_bps) {
+            properties.push((
+                "AverageBitRate",
+                CompressionPropertyKey::AverageBitRate.into(),
+                CFNumber::from(bitrate.min(i64::MAX as u64) as i64).as_CFType(),
+            ));
+        }
+        if let Some(qp) = config.max_qp {
+            properties.push((
+                "MaxAllowedFrameQP",
+                CFString::new("MaxAllowedFrameQP"),
+                CFNumber::from(qp.min(i32::MAX as u32) as i32).as_CFType(),
+            ));
+        }
+        for (name, key, value) in properties {
+            active
+                .session
+                .as_session()
+                .set_property(key, value)
+                .map_err(|status| vt_error(&format!("VTSessionSetProperty({name})"), status))?;
+        }
+        Ok(())
+    }
+
     fn apply_pending_switch_if_needed(&mut self) -> Result<(), BackendError> {
         let Some(pending) = self.pending_switch.take() else {
             return Ok(());
         };
         self.config_generation = pending.target_generation;
+        if let Some(bitrate) = pending.config.average_bitrate_bps.filter(|&v| v > 0) {
+            self.average_bitrate_bps = Some(bitrate);
+        }
+        if let Some(qp) = pending.config.max_qp {
+            self.max_qp = Some(qp);
+        }
+        // AverageBitRate and MaxAllowedFrameQP may change on a running compression session, so
+        // a rate-only switch keeps it (and its GOP) instead of starting over.
+        if matches!(pending.mode, SessionSwitchMode::Immediate)
+            && pending.config.quality.is_none()
+            && !pending.config.force_keyframe_on_activate
+            && self.encode_session.is_some()
+        {
+            self.set_live_properties(&pending.config)?;
+            self.diagnostics.emit(DiagnosticEvent::Reconfigured {
+                generation: pending.target_generation,
+                force_idr: false,
+            });
+            return Ok(());
+        }
         self.session_reconfigure_pending = true;
         if pending.config.force_keyframe_on_activate
             || matches!(pending.mode, SessionSwitchMode::OnNextKeyframe)
@@ -725,11 +1166,22 @@ impl VtEncoderAdapter {
             self.force_next_keyframe = true;
         }
 
+        if let Some(quality) = pending.config.quality {
+            self.quality = Some(quality.clamp(0.0, 1.0));
+        }
+
         if matches!(pending.mode, SessionSwitchMode::DrainThenSwap)
             || matches!(pending.mode, SessionSwitchMode::Immediate)
         {
             let _ = self.encode_session.take();
+        } else {
+            // A session kept across the switch picks up the new settings from its next frame.
+            self.set_live_properties(&pending.config)?;
         }
+        self.diagnostics.emit(DiagnosticEvent::Reconfigured {
+            generation: pending.target_generation,
+            force_idr: self.force_next_keyframe,
+        });
         Ok(())
     }
 }
@@ -768,7 +1220,7 @@ impl VideoEncoder for VtEncoderAdapter {
         Ok(CapabilityReport {
             codec,
             decode_supported: true,
-            encode_supported: true,
+            encode_supported: codec != Codec::Mjpeg,
             hardware_acceleration: true,
         })
     }
@@ -809,8 +1261,11 @@ impl VideoEncoder for VtEncoderAdapter {
             self.height = Some(frame.height);
         }
 
-        if let Some(argb) = frame.argb.as_ref() {
-            let expected = frame.width.saturating_mul(frame.height).saturating_mul(4);
+        let expected = frame.width.saturating_mul(frame.height).saturating_mul(4);
+        for argb in [frame.argb.as_ref(), frame.stereo_right.as_ref()]
+            .into_iter()
+            .flatten()
+        {
             if argb.len() != expected {
                 return Err(BackendError::InvalidInput(format!(
                     "argb payload size mismatch: expected {expected}, got {}",
@@ -818,6 +1273,13 @@ impl VideoEncoder for VtEncoderAdapter {
                 )));
             }
         }
+        if let Some(first) = self.pending_frames.first()
+            && first.stereo_right.is_some() != frame.stereo_right.is_some()
+        {
+            return Err(BackendError::InvalidInput(
+                "stereo pairs and single frames cannot share one flush cycle".to_string(),
+            ));
+        }
 
         frame = self.preprocess_frame_via_pipeline(frame)?;
         self.pending_frames.push(frame);
@@ -833,93 +1295,109 @@ impl VideoEncoder for VtEncoderAdapter {
         let pending_frames = std::mem::take(&mut self.pending_frames);
         let width = self.width.take().unwrap_or(640);
         let height = self.height.take().unwrap_or(360);
+        if pending_frames[0].stereo_right.is_some() {
+            return self.flush_stereo(&pending_frames, width, height);
+        }
         let codec = self.codec;
-        let fps = self.fps.max(1);
+        let fps = self.fps;
+        let diagnostics = self.diagnostics.clone();
         let ensure_start = Instant::now();
+        let pool = self.ensure_pixel_buffer_pool(width, height)?;
         let session = self.ensure_encode_session(width, height)?;
         let ensure_elapsed = ensure_start.elapsed();
 
         let output_packets = Arc::new(Mutex::new(Vec::<VtPendingPacket>::new()));
         let mut frame_prep_elapsed = Duration::default();
+        let mut prep_wait_elapsed = Duration::default();
         let mut submit_elapsed = Duration::default();
         let mut input_copy_bytes = 0_u64;
         let mut input_copy_frames = 0_u64;
         let queue_depth = Arc::new(AtomicUsize::new(0));
         let queue_depth_peak = Arc::new(AtomicUsize::new(0));
         let queue_depth_samples = Arc::new(Mutex::new(Vec::<f64>::new()));
-        for (frame_index, frame) in pending_frames.iter().enumerate() {
-            let frame_prep_start = Instant::now();
-            let pixel_buffer = make_bgra_frame(width, height, frame_index, frame.argb.as_deref())?;
-            frame_prep_elapsed += frame_prep_start.elapsed();
-            input_copy_bytes = input_copy_bytes
-                .saturating_add(width.saturating_mul(height).saturating_mul(4) as u64);
-            input_copy_frames = input_copy_frames.saturating_add(1);
-            let image_buffer =
-                unsafe { CVImageBuffer::wrap_under_get_rule(pixel_buffer.as_concrete_TypeRef()) };
-
-            let packets_ref = Arc::clone(&output_packets);
-            let queue_depth_ref = Arc::clone(&queue_depth);
-            let queue_depth_peak_ref = Arc::clone(&queue_depth_peak);
-            let queue_depth_samples_ref = Arc::clone(&queue_depth_samples);
-            let packet_codec = codec;
-            let packet_pts_90k = frame.pts_90k;
-            let packet_is_keyframe_hint = frame_index == 0 || frame.force_keyframe;
-            let presentation_time_stamp = frame
-                .pts_90k
-                .map(cm_time_from_90k)
-                .unwrap_or_else(|| CMTime::make(frame_index as i64, fps));
-            let frame_duration = CMTime::make(1, fps);
-            let submit_start = Instant::now();
-            let depth_after_submit = queue_depth_ref.fetch_add(1, Ordering::Relaxed) + 1;
-            update_peak(&queue_depth_peak_ref, depth_after_submit);
-            if let Ok(mut samples) = queue_depth_samples_ref.lock() {
-                samples.push(depth_after_submit as f64);
-            }
-            session
-                .encode_frame_with_closure(
-                    image_buffer,
-                    presentation_time_stamp,
-                    frame_duration,
-                    frame_encode_properties(frame.force_keyframe),
-                    move |status, _info_flags, sample_buffer_ref| {
-                        let depth_after_callback = queue_depth_ref
-                            .fetch_sub(1, Ordering::Relaxed)
-                            .saturating_sub(1);
-                        if let Ok(mut samples) = queue_depth_samples_ref.lock() {
-                            samples.push(depth_after_callback as f64);
-                        }
-                        if status != 0 || sample_buffer_ref.is_null() {
-                            return;
-                        }
-                        let sample_buffer =
-                            unsafe { CMSampleBuffer::wrap_under_get_rule(sample_buffer_ref) };
-                        if let Some(data_buffer) = sample_buffer.get_data_buffer() {
-                            let len = data_buffer.get_data_length();
-                            let mut bytes = vec![0u8; len];
-                            if data_buffer.copy_data_bytes(0, &mut bytes).is_ok() {
-                                let is_keyframe =
-                                    detect_keyframe_from_avcc_hvcc_payload(packet_codec, &bytes)
-                                        .unwrap_or(packet_is_keyframe_hint);
-                                if let Ok(mut packets) = packets_ref.lock() {
-                                    packets.push(VtPendingPacket {
-                                        frame_index,
-                                        packet: EncodedPacket {
-                                            codec: packet_codec,
-                                            data: bytes,
-                                            pts_90k: packet_pts_90k,
-                                            is_keyframe,
-                                        },
-                                    });
+        std::thread::scope(|scope| -> Result<(), BackendError> {
+            // Returning early drops the receiver, which stops the worker before the scope joins it.
+            let (prepared_tx, prepared_rx) = bounded_queue(VT_PREPARED_FRAME_DEPTH);
+            let frames = &pending_frames;
+            let pool = &*pool;
+            scope.spawn(move || prepare_pixel_buffers(pool, frames, prepared_tx));
+            for (frame_index, frame) in pending_frames.iter().enumerate() {
+                let wait_start = Instant::now();
+                let prepared = prepared_rx.recv().map_err(|_| {
+                    BackendError::Backend("VT frame preparation worker stopped".to_string())
+                })??;
+                prep_wait_elapsed += wait_start.elapsed();
+                frame_prep_elapsed += prepared.elapsed;
+                let pixel_buffer = prepared.pixel_buffer;
+                input_copy_bytes = input_copy_bytes
+                    .saturating_add(width.saturating_mul(height).saturating_mul(4) as u64);
+                input_copy_frames = input_copy_frames.saturating_add(1);
+                let image_buffer = unsafe {
+                    CVImageBuffer::wrap_under_get_rule(pixel_buffer.as_concrete_TypeRef())
+                };
+
+                let packets_ref = Arc::clone(&output_packets);
+                let queue_depth_ref = Arc::clone(&queue_depth);
+                let queue_depth_peak_ref = Arc::clone(&queue_depth_peak);
+                let queue_depth_samples_ref = Arc::clone(&queue_depth_samples);
+                let packet_codec = codec;
+                let packet_pts_90k = frame.pts_90k;
+                let packet_is_keyframe_hint = frame_index == 0 || frame.force_keyframe;
+                let presentation_time_stamp = frame
+                    .pts_90k
+                    .map(cm_time_from_90k)
+                    .unwrap_or_else(|| cm_frame_time(fps, frame_index as i64));
+                let frame_duration = cm_frame_time(fps, 1);
+                let submit_start = Instant::now();
+                let depth_after_submit = queue_depth_ref.fetch_add(1, Ordering::Relaxed) + 1;
+                update_peak(&queue_depth_peak_ref, depth_after_submit);
+                if let Ok(mut samples) = queue_depth_samples_ref.lock() {
+                    samples.push(depth_after_submit as f64);
+                }
+                session
+                    .encode_frame_with_closure(
+                        image_buffer,
+                        presentation_time_stamp,
+                        frame_duration,
+                        frame_encode_properties(frame.force_keyframe),
+                        move |status, _info_flags, sample_buffer_ref| {
+                            let depth_after_callback = queue_depth_ref
+                                .fetch_sub(1, Ordering::Relaxed)
+                                .saturating_sub(1);
+                            if let Ok(mut samples) = queue_depth_samples_ref.lock() {
+                                samples.push(depth_after_callback as f64);
+                            }
+                            if status != 0 || sample_buffer_ref.is_null() {
+                                return;
+                            }
+                            let sample_buffer =
+                                unsafe { CMSampleBuffer::wrap_under_get_rule(sample_buffer_ref) };
+                            if let Some(data_buffer) = sample_buffer.get_data_buffer() {
+                                let len = data_buffer.get_data_length();
+                                let mut bytes = vec![0u8; len];
+                                if data_buffer.copy_data_bytes(0, &mut bytes).is_ok() {
+                                    if let Ok(mut packets) = packets_ref.lock() {
+                                        packets.push(VtPendingPacket {
+                                            frame_index,
+                                            packet: EncodedPacket {
+                                                codec: packet_codec,
+                                                data: bytes,
+                                                pts_90k: packet_pts_90k,
+                                                is_keyframe: packet_is_keyframe_hint,
+                                            },
+                                        });
+                                    }
                                 }
                             }
-                        }
-                    },
-                )
-                .map_err(|status| {
-                    vt_error("VTCompressionSession::encode_frame_with_closure", status)
-                })?;
-            submit_elapsed += submit_start.elapsed();
-        }
+                        },
+                    )
+                    .map_err(|status| {
+                        vt_error("VTCompressionSession::encode_frame_with_closure", status)
+                    })?;
+                submit_elapsed += submit_start.elapsed();
+            }
+            Ok(())
+        })?;
 
         let complete_start = Instant::now();
         session
@@ -943,11 +1421,7 @@ impl VideoEncoder for VtEncoderAdapter {
                 }
             }
             let mut jitter_stats = SampleStats::default();
-            let expected_frame_ms = if fps > 0 {
-                1_000.0 / fps as f64
-            } else {
-                33.333
-            };
+            let expected_frame_ms = fps.frame_duration_ms();
             let mut last_pts_90k = None;
             for packet in &packets {
                 update_jitter_samples(
@@ -957,29 +1431,35 @@ impl VideoEncoder for VtEncoderAdapter {
                     expected_frame_ms,
                 );
             }
-            eprintln!(
-                "[vt.encode] frames={}, packets={}, output_bytes={}, width={}, height={}, ensure_ms={:.3}, frame_prep_ms={:.3}, submit_ms={:.3}, complete_ms={:.3}, total_ms={:.3}, queue_peak={}, queue_p95={:.3}, queue_p99={:.3}, jitter_ms_mean={:.3}, jitter_ms_p95={:.3}, jitter_ms_p99={:.3}, input_copy_bytes={}, input_copy_frames={}, output_copy_bytes={}, output_copy_packets={}",
-                pending_frames.len(),
-                packets.len(),
-                output_bytes,
-                width,
-                height,
-                ensure_elapsed.as_secs_f64() * 1_000.0,
-                frame_prep_elapsed.as_secs_f64() * 1_000.0,
-                submit_elapsed.as_secs_f64() * 1_000.0,
-                complete_elapsed.as_secs_f64() * 1_000.0,
-                flush_start.elapsed().as_secs_f64() * 1_000.0,
-                queue_depth_peak.load(Ordering::Relaxed),
-                queue_stats.p95(),
-                queue_stats.p99(),
-                jitter_stats.mean(),
-                jitter_stats.p95(),
-                jitter_stats.p99(),
-                input_copy_bytes,
-                input_copy_frames,
-                output_bytes as u64,
-                packets.len() as u64,
-            );
+            diagnostics.emit(DiagnosticEvent::Metrics {
+                scope: "vt.encode",
+                detail: format!(
+                    "frames={}, packets={}, output_bytes={}, width={}, height={}, ensure_ms={:.3}, frame_prep_ms={:.3}, prep_wait_ms={:.3}, submit_ms={:.3}, complete_ms={:.3}, total_ms={:.3}, queue_peak={}, queue_p95={:.3}, queue_p99={:.3}, jitter_ms_mean={:.3}, jitter_ms_p95={:.3}, jitter_ms_p99={:.3}, input_copy_bytes={}, input_copy_frames={}, output_copy_bytes={}, output_copy_packets={}, pool_allocated={}, pool_reused={}",
+                    pending_frames.len(),
+                    packets.len(),
+                    output_bytes,
+                    width,
+                    height,
+                    ensure_elapsed.as_secs_f64() * 1_000.0,
+                    frame_prep_elapsed.as_secs_f64() * 1_000.0,
+                    prep_wait_elapsed.as_secs_f64() * 1_000.0,
+                    submit_elapsed.as_secs_f64() * 1_000.0,
+                    complete_elapsed.as_secs_f64() * 1_000.0,
+                    flush_start.elapsed().as_secs_f64() * 1_000.0,
+                    queue_depth_peak.load(Ordering::Relaxed),
+                    queue_stats.p95(),
+                    queue_stats.p99(),
+                    jitter_stats.mean(),
+                    jitter_stats.p95(),
+                    jitter_stats.p99(),
+                    input_copy_bytes,
+                    input_copy_frames,
+                    output_bytes as u64,
+                    packets.len() as u64,
+                    pool.allocated(),
+                    pool.reused(),
+                ),
+            });
         }
 
         Ok(packets)
@@ -997,6 +1477,7 @@ impl VideoEncoder for VtEncoderAdapter {
                 "NVIDIA session switch request is not supported by VideoToolbox backend"
                     .to_string(),
             )),
+            SessionSwitchRequest::Resize { .. } => self.apply_resize(),
         }
     }
 
@@ -1009,12 +1490,107 @@ impl VideoEncoder for VtEncoderAdapter {
                 .max(1),
         )
     }
+
+    // complete_frames is a full pipeline barrier; batching about a second of input amortizes it.
+    fn pipeline_depth(&self) -> usize {
+        self.fps.rounded().clamp(1, 60) as usize
+    }
+
+    // Between flushes no pixel buffer is in use; the pool is recreated by the next flush.
+    fn trim_pools(&mut self) {
+        if self.pending_frames.is_empty() {
+            self.pixel_buffer_pool = None;
+        }
+    }
+
+    fn set_diagnostics(&mut self, diagnostics: Diagnostics) {
+        self.diagnostics = diagnostics;
+    }
+
+    fn close(&mut self) -> Result<(), BackendError> {
+        self.pipeline_scheduler = None;
+        self.pending_frames.clear();
+        self.encode_session = None;
+        self.stereo_session = None;
+        self.pixel_buffer_pool = None;
+        Ok(())
+    }
+}
+
+#[link(name = "VideoToolbox", kind = "framework")]
+unsafe extern "C" {
+    fn VTCopyVideoEncoderList(options: CFDictionaryRef, list_out: *mut CFArrayRef) -> i32;
+}
+
+// kVTVideoEncoderList_* keys; EncoderID doubles as kVTVideoEncoderSpecification_EncoderID.
+const ENCODER_ID_KEY: &str = "EncoderID";
+
+// Every encoder VideoToolbox offers on this machine, hardware and software, in the order
+// VideoToolbox lists them.
+pub fn list_video_toolbox_encoders() -> Result<Vec<VtEncoderInfo>, BackendError> {
+    let mut raw: CFArrayRef = std::ptr::null();
+    let status = unsafe { VTCopyVideoEncoderList(std::ptr::null(), &mut raw) };
+    if status != 0 || raw.is_null() {
+        return Err(vt_error("VTCopyVideoEncoderList", status));
+    }
+    let list = unsafe { CFArray::<CFDictionary<CFString, CFType>>::wrap_under_create_rule(raw) };
+    let encoders = list
+        .iter()
+        .filter_map(|entry| {
+            let value = |key: &'static str| entry.find(CFString::from_static_string(key));
+            let string = |key: &'static str| {
+                value(key)
+                    .and_then(|value| value.downcast::<CFString>())
+                    .map(|value| value.to_string())
+            };
+            let number = |key: &'static str| {
+                value(key)
+                    .and_then(|value| value.downcast::<CFNumber>())
+                    .and_then(|value| value.to_i64())
+            };
+            let codec_type = u32::try_from(number("CodecType")?).ok()?;
+            Some(VtEncoderInfo {
+                encoder_id: string(ENCODER_ID_KEY)?,
+                codec: from_cm_codec_type(codec_type),
+                codec_type,
+                name: string("EncoderName").unwrap_or_default(),
+                display_name: string("DisplayName").unwrap_or_default(),
+                hardware_accelerated: value("IsHardwareAccelerated")
+                    .and_then(|value| value.downcast::<CFBoolean>())
+                    .is_some_and(bool::from),
+                gpu_registry_id: number("GPURegistryID").and_then(|id| u64::try_from(id).ok()),
+            })
+        })
+        .collect();
+    Ok(encoders)
+}
+
+fn from_cm_codec_type(codec_type: CMVideoCodecType) -> Option<Codec> {
+    [Codec::H264, Codec::Hevc, Codec::Mjpeg]
+        .into_iter()
+        .find(|&codec| to_cm_codec_type(codec) == codec_type)
+}
+
+// VTIsHardwareDecodeSupported goes to the media server on every call, while the answer is
+// fixed for the life of the process, so it is asked once per codec.
+fn hardware_decode_supported(codec: Codec) -> bool {
+    static SUPPORTED: Mutex<Vec<(Codec, bool)>> = Mutex::new(Vec::new());
+    let mut supported = SUPPORTED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(&(_, answer)) = supported.iter().find(|(cached, _)| *cached == codec) {
+        return answer;
+    }
+    let answer = VTDecompressionSession::is_hardware_decode_supported(to_cm_codec_type(codec));
+    supported.push((codec, answer));
+    answer
 }
 
 fn to_cm_codec_type(codec: Codec) -> CMVideoCodecType {
     match codec {
         Codec::H264 => kCMVideoCodecType_H264,
         Codec::Hevc => kCMVideoCodecType_HEVC,
+        Codec::Mjpeg => kCMVideoCodecType_JPEG,
     }
 }
 
@@ -1022,6 +1598,7 @@ fn codec_label(codec: Codec) -> &'static str {
     match codec {
         Codec::H264 => "h264",
         Codec::Hevc => "hevc",
+        Codec::Mjpeg => "mjpeg",
     }
 }
 
@@ -1045,6 +1622,21 @@ fn create_format_description(
                     cm_error("CMVideoFormatDescription::from_hevc_parameter_sets", status)
                 })
         }
+        Codec::Mjpeg => {
+            let (width, height) = parameter_sets
+                .first()
+                .and_then(|sof| crate::bitstream::jpeg_dimensions(sof))
+                .ok_or_else(|| {
+                    BackendError::InvalidBitstream("JPEG frame header is malformed".to_string())
+                })?;
+            CMVideoFormatDescription::new(
+                kCMVideoCodecType_JPEG,
+                i32::from(width),
+                i32::from(height),
+                None,
+            )
+            .map_err(|status| cm_error("CMVideoFormatDescription::new", status))
+        }
     }
 }
 
@@ -1052,14 +1644,127 @@ fn empty_dictionary() -> CFDictionary<CFString, CFType> {
     CFMutableDictionary::<CFString, CFType>::new().to_immutable()
 }
 
-fn make_bgra_frame(
+// Double buffering: the worker fills the next pixel buffer while VideoToolbox encodes the
+// previous one, without racing arbitrarily far ahead of the encoder.
+const VT_PREPARED_FRAME_DEPTH: usize = 2;
+
+struct PreparedPixelBuffer {
+    pixel_buffer: CVPixelBuffer,
+    elapsed: Duration,
+}
+
+// CVPixelBuffers are reference counted CF objects that may move between threads; the worker
+// unlocks each one before handing it over and never touches it again.
+unsafe impl Send for PreparedPixelBuffer {}
+
+// Runs on the flush's preparation worker so BGRA conversion overlaps the submit loop. Stops at
+// the first error (which is forwarded) or once the submit loop hangs up.
+fn prepare_pixel_buffers(
+    pool: &VtPixelBufferPool,
+    frames: &[Frame],
+    out: BoundedQueueTx<Result<PreparedPixelBuffer, BackendError>>,
+) {
+    for (frame_index, frame) in frames.iter().enumerate() {
+        let start = Instant::now();
+        let prepared =
+            make_bgra_frame(pool, frame_index, frame.argb.as_deref()).map(|pixel_buffer| {
+                PreparedPixelBuffer {
+                    pixel_buffer,
+                    elapsed: start.elapsed(),
+                }
+            });
+        let failed = prepared.is_err();
+        if out.send(prepared).is_err() || failed {
+            break;
+        }
+    }
+}
+
+// BGRA input buffers for one frame size. The pool keeps enough buffers for the prepared-frame
+// double buffer plus the frame being submitted and grows on demand while VideoToolbox still
+// holds earlier frames; released buffers come back for later frames.
+struct VtPixelBufferPool {
+    pool: CVPixelBufferPool,
     width: usize,
     height: usize,
+    seen: Mutex<HashSet<usize>>,
+    takes: AtomicU64,
+}
+
+// CVPixelBufferPool is documented as thread-safe; the set of handed-out buffers is behind a
+// mutex.
+unsafe impl Send for VtPixelBufferPool {}
+unsafe impl Sync for VtPixelBufferPool {}
+
+impl VtPixelBufferPool {
+    fn new(width: usize, height: usize) -> Result<Self, BackendError> {
+        let mut pool_attributes = CFMutableDictionary::<CFString, CFType>::new();
+        pool_attributes.add(
+            &CVPixelBufferPoolKeys::MinimumBufferCount.into(),
+            &CFNumber::from((VT_PREPARED_FRAME_DEPTH + 1) as i32).as_CFType(),
+        );
+        let mut buffer_attributes = CFMutableDictionary::<CFString, CFType>::new();
+        buffer_attributes.add(
+            &CVPixelBufferKeys::PixelFormatType.into(),
+            &CFNumber::from(kCVPixelFormatType_32BGRA as i64).as_CFType(),
+        );
+        buffer_attributes.add(
+            &CVPixelBufferKeys::Width.into(),
+            &CFNumber::from(width as i64).as_CFType(),
+        );
+        buffer_attributes.add(
+            &CVPixelBufferKeys::Height.into(),
+            &CFNumber::from(height as i64).as_CFType(),
+        );
+        let pool = CVPixelBufferPool::new(
+            Some(&pool_attributes.to_immutable()),
+            Some(&buffer_attributes.to_immutable()),
+        )
+        .map_err(|status| cv_error("CVPixelBufferPool::new", status))?;
+        Ok(Self {
+            pool,
+            width,
+            height,
+            seen: Mutex::new(HashSet::new()),
+            takes: AtomicU64::new(0),
+        })
+    }
+
+    fn take(&self) -> Result<CVPixelBuffer, BackendError> {
+        let pixel_buffer = self
+            .pool
+            .create_pixel_buffer()
+            .map_err(|status| cv_error("CVPixelBufferPool::create_pixel_buffer", status))?;
+        self.seen
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(pixel_buffer.as_concrete_TypeRef() as usize);
+        self.takes.fetch_add(1, Ordering::Relaxed);
+        Ok(pixel_buffer)
+    }
+
+    // Distinct buffers the pool has handed out, i.e. how many it had to allocate.
+    fn allocated(&self) -> u64 {
+        self.seen
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .len() as u64
+    }
+
+    fn reused(&self) -> u64 {
+        self.takes
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.allocated())
+    }
+}
+
+fn make_bgra_frame(
+    pool: &VtPixelBufferPool,
     frame_index: usize,
     argb: Option<&[u8]>,
 ) -> Result<CVPixelBuffer, BackendError> {
-    let pixel_buffer = CVPixelBuffer::new(kCVPixelFormatType_32BGRA, width, height, None)
-        .map_err(|status| cv_error("CVPixelBuffer::new", status))?;
+    let (width, height) = (pool.width, pool.height);
+    let pixel_buffer = pool.take()?;
 
     let lock_status = pixel_buffer.lock_base_address(0);
     if lock_status != 0 {
@@ -1142,6 +1847,15 @@ fn cm_time_from_90k(pts_90k: i64) -> CMTime {
     CMTime::make(pts_90k.max(0), 90_000)
 }
 
+// Timescale is the rate numerator, so NTSC frame times are exact multiples of 1001.
+fn cm_frame_time(fps: FrameRate, frame_index: i64) -> CMTime {
+    let rate = fps.normalized();
+    CMTime::make(
+        frame_index.saturating_mul(i64::from(rate.den)),
+        i32::try_from(rate.num).unwrap_or(i32::MAX),
+    )
+}
+
 fn should_enable_pipeline_scheduler() -> bool {
     std::env::var("VIDEO_HW_VT_PIPELINE")
         .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
@@ -1200,60 +1914,11 @@ fn packed_access_units_bytes(access_units: &[AccessUnit]) -> usize {
         .sum()
 }
 
-fn detect_keyframe_from_avcc_hvcc_payload(codec: Codec, payload: &[u8]) -> Option<bool> {
-    let mut offset = 0usize;
-    let mut saw_slice = false;
-    let mut saw_irap = false;
-
-    while offset.saturating_add(4) <= payload.len() {
-        let len = u32::from_be_bytes([
-            payload[offset],
-            payload[offset + 1],
-            payload[offset + 2],
-            payload[offset + 3],
-        ]) as usize;
-        offset = offset.saturating_add(4);
-
-        if len == 0 || offset.saturating_add(len) > payload.len() {
-            break;
-        }
-        let nalu = &payload[offset..offset + len];
-        offset = offset.saturating_add(len);
-        if nalu.is_empty() {
-            continue;
-        }
-
-        match codec {
-            Codec::H264 => {
-                let nalu_type = nalu[0] & 0x1f;
-                if nalu_type == 5 {
-                    saw_irap = true;
-                    saw_slice = true;
-                } else if (1..=5).contains(&nalu_type) {
-                    saw_slice = true;
-                }
-            }
-            Codec::Hevc => {
-                // HEVC nal_unit_type in bits[6:1] of first byte.
-                let nalu_type = (nalu[0] >> 1) & 0x3f;
-                if (16..=21).contains(&nalu_type) {
-                    saw_irap = true;
-                    saw_slice = true;
-                } else if nalu_type <= 31 {
-                    saw_slice = true;
-                }
-            }
-        }
-    }
-
-    if saw_slice { Some(saw_irap) } else { None }
-}
-
-fn vt_error(context: &str, status: i32) -> BackendError {
+pub(crate) fn vt_error(context: &str, status: i32) -> BackendError {
     BackendError::Backend(format!("videotoolbox({context}): {status}"))
 }
 
-fn cm_error(context: &str, status: i32) -> BackendError {
+pub(crate) fn cm_error(context: &str, status: i32) -> BackendError {
     BackendError::Backend(format!("coremedia({context}): {status}"))
 }
 
@@ -1276,23 +1941,70 @@ extern "C" fn vt_decode_output_callback(
 
     let state = unsafe { &*(decompression_output_ref_con as *const Mutex<DecodeOutputState>) };
     let pixel_buffer = unsafe { CVPixelBuffer::wrap_under_get_rule(image_buffer) };
+    push_decoded_image(
+        state,
+        &pixel_buffer,
+        info_flags.bits(),
+        presentation_time_stamp,
+        None,
+    );
+}
+
+// Stereo sessions deliver both views of a frame together, each tagged with its layer.
+extern "C" fn vt_decode_multi_image_callback(
+    decompression_output_ref_con: *mut c_void,
+    _source_frame_ref_con: *mut c_void,
+    status: i32,
+    info_flags: u32,
+    tagged_buffer_group: *const c_void,
+    presentation_time_stamp: CMTime,
+    _presentation_duration: CMTime,
+) {
+    if status != 0 || decompression_output_ref_con.is_null() || tagged_buffer_group.is_null() {
+        return;
+    }
 
+    let state = unsafe { &*(decompression_output_ref_con as *const Mutex<DecodeOutputState>) };
+    for (view, pixel_buffer) in unsafe { vt_multiview::tagged_views(tagged_buffer_group) } {
+        push_decoded_image(
+            state,
+            &pixel_buffer,
+            info_flags,
+            presentation_time_stamp,
+            Some(view),
+        );
+    }
+}
+
+fn push_decoded_image(
+    state: &Mutex<DecodeOutputState>,
+    pixel_buffer: &CVPixelBuffer,
+    info_flags: u32,
+    presentation_time_stamp: CMTime,
+    view: Option<StereoView>,
+) {
     if let Ok(mut s) = state.lock() {
         let width = pixel_buffer.get_width();
         let height = pixel_buffer.get_height();
-        let pixel_format = pixel_buffer.get_pixel_format();
-        let color = extract_color_metadata(&pixel_buffer);
+        let pixel_format = BackendPixelFormat::from_core_video(pixel_buffer.get_pixel_format());
+        let color = extract_color_metadata(pixel_buffer);
+        let planes = extract_plane_layout(pixel_buffer);
         let frame = Frame {
             width,
             height,
             pixel_format: Some(pixel_format),
             pts_90k: cm_time_to_90k(presentation_time_stamp),
-            decode_info_flags: Some(info_flags.bits()),
+            decode_info_flags: Some(decode_info_flags_from_vt(info_flags)),
             color_primaries: color.color_primaries,
             transfer_function: color.transfer_function,
             ycbcr_matrix: color.ycbcr_matrix,
+            planes: Some(planes),
             argb: None,
             force_keyframe: false,
+            dirty_rects: None,
+            luma_histogram: None,
+            view,
+            stereo_right: None,
         };
         s.decoded_frames = s.decoded_frames.saturating_add(1);
         if s.width.is_none() {
@@ -1305,9 +2017,25 @@ extern "C" fn vt_decode_output_callback(
             s.pixel_format = Some(pixel_format);
         }
         s.pending_frames.push_back(frame);
+        if let Some(waker) = &s.ready_waker {
+            waker.wake();
+        }
     }
 }
 
+fn decode_info_flags_from_vt(bits: u32) -> DecodeInfoFlags {
+    // kVTDecodeInfo_Asynchronous / _FrameDropped / _ImageBufferModifiable / _FrameInterrupted.
+    let mut flags = DecodeInfoFlags::empty();
+    flags.set(DecodeInfoFlags::ASYNCHRONOUS, bits & (1 << 0) != 0);
+    flags.set(DecodeInfoFlags::FRAME_DROPPED, bits & (1 << 1) != 0);
+    flags.set(
+        DecodeInfoFlags::IMAGE_BUFFER_MODIFIABLE,
+        bits & (1 << 2) != 0,
+    );
+    flags.set(DecodeInfoFlags::CORRUPTED, bits & (1 << 4) != 0);
+    flags
+}
+
 fn cm_time_to_90k(time: CMTime) -> Option<i64> {
     if time.timescale <= 0 {
         return None;
@@ -1326,6 +2054,26 @@ fn extract_color_metadata(pixel_buffer: &CVPixelBuffer) -> crate::ColorMetadata
     }
 }
 
+// CoreVideo does not promise that planes are adjacent in memory; offsets describe the planes
+// stacked back to back, which is how a readback copy of the surface is laid out.
+fn extract_plane_layout(pixel_buffer: &CVPixelBuffer) -> Vec<PlaneLayout> {
+    if !pixel_buffer.is_planar() {
+        return PlaneLayout::packed(pixel_buffer.get_bytes_per_row(), pixel_buffer.get_height());
+    }
+    let mut offset = 0;
+    (0..pixel_buffer.get_plane_count())
+        .map(|plane| {
+            let layout = PlaneLayout {
+                offset,
+                stride: pixel_buffer.get_bytes_per_row_of_plane(plane),
+                rows: pixel_buffer.get_height_of_plane(plane),
+            };
+            offset = layout.end();
+            layout
+        })
+        .collect()
+}
+
 fn copy_color_primaries(pixel_buffer: &CVPixelBuffer) -> Option<i32> {
     let value = copy_attachment_cfstring(pixel_buffer, CVImageBufferKeys::ColorPrimaries)?;
     Some(unsafe { CVColorPrimariesGetIntegerCodePointForString(value.as_concrete_TypeRef()) })
@@ -1353,53 +2101,51 @@ fn copy_attachment_cfstring(
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use std::num::NonZeroU32;
 
-    #[test]
-    fn detect_h264_keyframe_from_length_prefixed_payload() {
-        let mut payload = Vec::new();
-        let idr = [0x65, 0x88, 0x84, 0x21];
-        payload.extend_from_slice(&(idr.len() as u32).to_be_bytes());
-        payload.extend_from_slice(&idr);
-        assert_eq!(
-            detect_keyframe_from_avcc_hvcc_payload(Codec::H264, &payload),
-            Some(true)
-        );
-    }
+    use super::*;
+    use crate::Dimensions;
 
     #[test]
-    fn detect_h264_non_keyframe_from_length_prefixed_payload() {
-        let mut payload = Vec::new();
-        let non_idr = [0x41, 0x9a, 0x22];
-        payload.extend_from_slice(&(non_idr.len() as u32).to_be_bytes());
-        payload.extend_from_slice(&non_idr);
+    fn decode_info_flags_map_vt_bits() {
+        let flags = decode_info_flags_from_vt((1 << 1) | (1 << 2) | (1 << 4));
         assert_eq!(
-            detect_keyframe_from_avcc_hvcc_payload(Codec::H264, &payload),
-            Some(false)
+            flags,
+            DecodeInfoFlags::FRAME_DROPPED
+                | DecodeInfoFlags::IMAGE_BUFFER_MODIFIABLE
+                | DecodeInfoFlags::CORRUPTED
         );
+        assert_eq!(decode_info_flags_from_vt(1 << 3), DecodeInfoFlags::empty());
     }
 
     #[test]
-    fn detect_hevc_keyframe_from_length_prefixed_payload() {
-        let mut payload = Vec::new();
-        // nal_unit_type=19 (IDR_W_RADL): first byte bits[6:1] = 0b010011.
-        let idr = [0b0010_0110, 0x01, 0xaa, 0xbb];
-        payload.extend_from_slice(&(idr.len() as u32).to_be_bytes());
-        payload.extend_from_slice(&idr);
-        assert_eq!(
-            detect_keyframe_from_avcc_hvcc_payload(Codec::Hevc, &payload),
-            Some(true)
-        );
+    fn pixel_buffer_pool_recycles_released_buffers() {
+        let pool = VtPixelBufferPool::new(64, 36).unwrap();
+        for frame_index in 0..8 {
+            let pixel_buffer = make_bgra_frame(&pool, frame_index, None).unwrap();
+            assert_eq!(pixel_buffer.get_width(), 64);
+            assert_eq!(pixel_buffer.get_height(), 36);
+        }
+        assert_eq!(pool.allocated() + pool.reused(), 8);
+        assert!(pool.allocated() < 8);
     }
 
     #[test]
     fn vt_switch_immediate_updates_generation_hint() {
-        let mut adapter = VtEncoderAdapter::with_config(Codec::H264, 30, false);
+        let mut adapter = VtEncoderAdapter::with_config(
+            Codec::H264,
+            30.into(),
+            false,
+            BackendEncoderOptions::Default,
+        );
         assert_eq!(adapter.pipeline_generation_hint(), Some(1));
         adapter
             .apply_vt_session_switch(
                 VtSessionConfig {
                     force_keyframe_on_activate: false,
+                    quality: None,
+                    average_bitrate_bps: None,
+                    max_qp: None,
                 },
                 SessionSwitchMode::Immediate,
             )
@@ -1408,9 +2154,35 @@ mod tests {
         assert!(adapter.session_reconfigure_pending);
     }
 
+    #[test]
+    fn vt_resize_forces_keyframe_under_new_generation() {
+        let mut adapter = VtEncoderAdapter::with_config(
+            Codec::H264,
+            30.into(),
+            false,
+            BackendEncoderOptions::Default,
+        );
+        adapter
+            .request_session_switch(SessionSwitchRequest::Resize {
+                dims: Dimensions {
+                    width: NonZeroU32::new(640).unwrap(),
+                    height: NonZeroU32::new(360).unwrap(),
+                },
+            })
+            .unwrap();
+        assert_eq!(adapter.pipeline_generation_hint(), Some(2));
+        assert!(adapter.force_next_keyframe);
+        assert!(adapter.encode_session.is_none());
+    }
+
     #[test]
     fn vt_switch_on_next_keyframe_stays_pending_when_frames_are_buffered() {
-        let mut adapter = VtEncoderAdapter::with_config(Codec::H264, 30, false);
+        let mut adapter = VtEncoderAdapter::with_config(
+            Codec::H264,
+            30.into(),
+            false,
+            BackendEncoderOptions::Default,
+        );
         adapter.pending_frames.push(Frame {
             width: 640,
             height: 360,
@@ -1420,13 +2192,21 @@ mod tests {
             color_primaries: None,
             transfer_function: None,
             ycbcr_matrix: None,
+            planes: None,
             argb: None,
             force_keyframe: false,
+            dirty_rects: None,
+            luma_histogram: None,
+            view: None,
+            stereo_right: None,
         });
         adapter
             .apply_vt_session_switch(
                 VtSessionConfig {
                     force_keyframe_on_activate: false,
+                    quality: None,
+                    average_bitrate_bps: None,
+                    max_qp: None,
                 },
                 SessionSwitchMode::OnNextKeyframe,
             )
@@ -1439,7 +2219,12 @@ mod tests {
     #[test]
     fn vt_pending_switch_generation_syncs_to_pipeline_scheduler() {
         let scheduler = PipelineScheduler::new(VtTransformAdapter::new(), 4);
-        let mut adapter = VtEncoderAdapter::with_config(Codec::H264, 30, false);
+        let mut adapter = VtEncoderAdapter::with_config(
+            Codec::H264,
+            30.into(),
+            false,
+            BackendEncoderOptions::Default,
+        );
         adapter.pending_frames.push(Frame {
             width: 640,
             height: 360,
@@ -1449,13 +2234,21 @@ mod tests {
             color_primaries: None,
             transfer_function: None,
             ycbcr_matrix: None,
+            planes: None,
             argb: None,
             force_keyframe: false,
+            dirty_rects: None,
+            luma_histogram: None,
+            view: None,
+            stereo_right: None,
         });
         adapter
             .apply_vt_session_switch(
                 VtSessionConfig {
                     force_keyframe_on_activate: false,
+                    quality: None,
+                    average_bitrate_bps: None,
+                    max_qp: None,
                 },
                 SessionSwitchMode::OnNextKeyframe,
             )
@@ -0,0 +1,457 @@
+use std::{
+    ffi::{CStr, c_void},
+    sync::{Mutex, OnceLock, PoisonError},
+};
+
+use crate::vt_backend::{cm_error, vt_error};
+use crate::{BackendError, MultiViewSupport, StereoView};
+use core_foundation::{
+    array::{CFArray, CFArrayRef},
+    base::{CFAllocatorRef, CFRelease, CFType, CFTypeRef, TCFType},
+    boolean::CFBoolean,
+    dictionary::{CFDictionary, CFDictionaryRef},
+    number::CFNumber,
+    string::{CFString, CFStringRef},
+};
+use core_media::{
+    format_description::kCMVideoCodecType_HEVC,
+    sample_buffer::{CMSampleBuffer, CMSampleBufferRef},
+    time::{CMTime, kCMTimeInvalid},
+};
+use core_video::pixel_buffer::{CVPixelBuffer, CVPixelBufferRef};
+
+type OSStatus = i32;
+type CMItemCount = isize;
+type CMTaggedBufferGroupRef = *const c_void;
+type CMTagCollectionRef = *const c_void;
+
+// kCMTagCategory_VideoLayerID ('vlay') and kCMTagCategory_StereoView ('eyes').
+const TAG_CATEGORY_VIDEO_LAYER_ID: u32 = u32::from_be_bytes(*b"vlay");
+const TAG_CATEGORY_STEREO_VIEW: u32 = u32::from_be_bytes(*b"eyes");
+// kCMTagDataType_SInt64 / _Flags.
+const TAG_DATA_TYPE_SINT64: u32 = 2;
+const TAG_DATA_TYPE_FLAGS: u32 = 7;
+// kCMStereoView_LeftEye / _RightEye.
+const STEREO_VIEW_LEFT_EYE: u64 = 1;
+const STEREO_VIEW_RIGHT_EYE: u64 = 2;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct CMTag {
+    category: u32,
+    data_type: u32,
+    value: u64,
+}
+
+impl CMTag {
+    fn layer(view: StereoView) -> Self {
+        Self {
+            category: TAG_CATEGORY_VIDEO_LAYER_ID,
+            data_type: TAG_DATA_TYPE_SINT64,
+            value: u64::from(view.layer_id()),
+        }
+    }
+
+    fn eye(view: StereoView) -> Self {
+        Self {
+            category: TAG_CATEGORY_STEREO_VIEW,
+            data_type: TAG_DATA_TYPE_FLAGS,
+            value: match view {
+                StereoView::Left => STEREO_VIEW_LEFT_EYE,
+                StereoView::Right => STEREO_VIEW_RIGHT_EYE,
+            },
+        }
+    }
+}
+
+// VTDecompressionOutputMultiImageCallback.
+pub(crate) type MultiImageOutputCallback =
+    extern "C" fn(*mut c_void, *mut c_void, OSStatus, u32, CMTaggedBufferGroupRef, CMTime, CMTime);
+
+type CompressionOutputCallback =
+    extern "C" fn(*mut c_void, *mut c_void, OSStatus, u32, CMSampleBufferRef);
+
+#[link(name = "VideoToolbox", kind = "framework")]
+unsafe extern "C" {
+    fn VTCompressionSessionCreate(
+        allocator: CFAllocatorRef,
+        width: i32,
+        height: i32,
+        codec_type: u32,
+        encoder_specification: CFDictionaryRef,
+        source_image_buffer_attributes: CFDictionaryRef,
+        compressed_data_allocator: CFAllocatorRef,
+        output_callback: Option<CompressionOutputCallback>,
+        output_callback_ref_con: *mut c_void,
+        session_out: *mut *mut c_void,
+    ) -> OSStatus;
+    fn VTSessionSetProperty(session: *mut c_void, key: CFStringRef, value: CFTypeRef) -> OSStatus;
+    fn VTCompressionSessionPrepareToEncodeFrames(session: *mut c_void) -> OSStatus;
+    fn VTCompressionSessionCompleteFrames(session: *mut c_void, complete_until: CMTime)
+    -> OSStatus;
+    fn VTCompressionSessionInvalidate(session: *mut c_void);
+}
+
+// The MV-HEVC entry points arrived with macOS 14; they are looked up at run time so the crate
+// still loads on older systems, where stereo is simply reported as unsupported.
+struct MultiViewApi {
+    encode_supported: unsafe extern "C" fn() -> u8,
+    decode_supported: unsafe extern "C" fn() -> u8,
+    set_multi_image_callback:
+        unsafe extern "C" fn(*mut c_void, MultiImageOutputCallback, *mut c_void) -> OSStatus,
+    encode_multi_image_frame: unsafe extern "C" fn(
+        *mut c_void,
+        CMTaggedBufferGroupRef,
+        CMTime,
+        CMTime,
+        CFDictionaryRef,
+        *mut c_void,
+        *mut u32,
+    ) -> OSStatus,
+    tag_collection_create: unsafe extern "C" fn(
+        CFAllocatorRef,
+        *const CMTag,
+        CMItemCount,
+        *mut CMTagCollectionRef,
+    ) -> OSStatus,
+    tagged_buffer_group_create: unsafe extern "C" fn(
+        CFAllocatorRef,
+        CFArrayRef,
+        CFArrayRef,
+        *mut CMTaggedBufferGroupRef,
+    ) -> OSStatus,
+    group_count: unsafe extern "C" fn(CMTaggedBufferGroupRef) -> CMItemCount,
+    group_tags_at: unsafe extern "C" fn(CMTaggedBufferGroupRef, CMItemCount) -> CMTagCollectionRef,
+    group_pixel_buffer_at:
+        unsafe extern "C" fn(CMTaggedBufferGroupRef, CMItemCount) -> CVPixelBufferRef,
+    tags_with_category: unsafe extern "C" fn(
+        CMTagCollectionRef,
+        u32,
+        *mut CMTag,
+        CMItemCount,
+        *mut CMItemCount,
+    ) -> OSStatus,
+}
+
+impl MultiViewApi {
+    fn load() -> Option<Self> {
+        unsafe {
+            Some(Self {
+                encode_supported: symbol(c"VTIsStereoMVHEVCEncodeSupported")?,
+                decode_supported: symbol(c"VTIsStereoMVHEVCDecodeSupported")?,
+                set_multi_image_callback: symbol(c"VTDecompressionSessionSetMultiImageCallback")?,
+                encode_multi_image_frame: symbol(c"VTCompressionSessionEncodeMultiImageFrame")?,
+                tag_collection_create: symbol(c"CMTagCollectionCreate")?,
+                tagged_buffer_group_create: symbol(c"CMTaggedBufferGroupCreate")?,
+                group_count: symbol(c"CMTaggedBufferGroupGetCount")?,
+                group_tags_at: symbol(c"CMTaggedBufferGroupGetTagCollectionAtIndex")?,
+                group_pixel_buffer_at: symbol(c"CMTaggedBufferGroupGetCVPixelBufferAtIndex")?,
+                tags_with_category: symbol(c"CMTagCollectionGetTagsWithCategory")?,
+            })
+        }
+    }
+}
+
+// T must be the function pointer type of the symbol `name`.
+unsafe fn symbol<T>(name: &CStr) -> Option<T> {
+    let address = unsafe { libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr()) };
+    (!address.is_null()).then(|| unsafe { std::mem::transmute_copy(&address) })
+}
+
+fn api() -> Option<&'static MultiViewApi> {
+    static API: OnceLock<Option<MultiViewApi>> = OnceLock::new();
+    API.get_or_init(MultiViewApi::load).as_ref()
+}
+
+fn unavailable() -> BackendError {
+    BackendError::UnsupportedConfig("MV-HEVC needs VideoToolbox on macOS 14 or later".to_string())
+}
+
+pub(crate) fn support() -> MultiViewSupport {
+    match api() {
+        Some(api) => unsafe {
+            MultiViewSupport {
+                decode: (api.decode_supported)() != 0,
+                encode: (api.encode_supported)() != 0,
+            }
+        },
+        None => MultiViewSupport::default(),
+    }
+}
+
+// Layer and view IDs 0 and 1; the view IDs double as the left/right assignment.
+fn layer_ids() -> CFType {
+    CFArray::from_CFTypes(
+        &[StereoView::Left, StereoView::Right]
+            .map(|view| CFNumber::from(i32::from(view.layer_id()))),
+    )
+    .as_CFType()
+}
+
+fn set_property(session: *mut c_void, key: &str, value: &CFType) -> Result<(), BackendError> {
+    let key_string = CFString::new(key);
+    let status = unsafe {
+        VTSessionSetProperty(
+            session,
+            key_string.as_concrete_TypeRef(),
+            value.as_CFTypeRef(),
+        )
+    };
+    if status != 0 {
+        return Err(vt_error(&format!("VTSessionSetProperty({key})"), status));
+    }
+    Ok(())
+}
+
+// Asks a decompression session for both layers and has it report each frame through `callback`,
+// all views of the frame in one tagged buffer group, instead of the single-image callback.
+pub(crate) unsafe fn enable_stereo_decode(
+    session: *mut c_void,
+    callback: MultiImageOutputCallback,
+    ref_con: *mut c_void,
+) -> Result<(), BackendError> {
+    let api = api().ok_or_else(unavailable)?;
+    set_property(session, "RequestedMVHEVCVideoLayerIDs", &layer_ids())?;
+    let status = unsafe { (api.set_multi_image_callback)(session, callback, ref_con) };
+    if status != 0 {
+        return Err(vt_error(
+            "VTDecompressionSessionSetMultiImageCallback",
+            status,
+        ));
+    }
+    Ok(())
+}
+
+// The pixel buffers of a decoded multi-image frame, each with the view it is tagged as.
+pub(crate) unsafe fn tagged_views(group: *const c_void) -> Vec<(StereoView, CVPixelBuffer)> {
+    let Some(api) = api() else {
+        return Vec::new();
+    };
+    let count = unsafe { (api.group_count)(group) };
+    (0..count)
+        .filter_map(|index| unsafe {
+            let pixel_buffer = (api.group_pixel_buffer_at)(group, index);
+            if pixel_buffer.is_null() {
+                return None;
+            }
+            let view = tagged_view(api, (api.group_tags_at)(group, index))?;
+            Some((view, CVPixelBuffer::wrap_under_get_rule(pixel_buffer)))
+        })
+        .collect()
+}
+
+// By video layer ID, falling back to the stereo-eye flags.
+unsafe fn tagged_view(api: &MultiViewApi, tags: CMTagCollectionRef) -> Option<StereoView> {
+    if tags.is_null() {
+        return None;
+    }
+    let find = |category| {
+        let mut tag = CMTag::default();
+        let mut copied: CMItemCount = 0;
+        let status = unsafe { (api.tags_with_category)(tags, category, &mut tag, 1, &mut copied) };
+        (status == 0 && copied == 1).then_some(tag.value)
+    };
+    if let Some(layer) = find(TAG_CATEGORY_VIDEO_LAYER_ID) {
+        return u8::try_from(layer).ok().and_then(StereoView::from_layer_id);
+    }
+    match find(TAG_CATEGORY_STEREO_VIEW)? {
+        STEREO_VIEW_LEFT_EYE => Some(StereoView::Left),
+        STEREO_VIEW_RIGHT_EYE => Some(StereoView::Right),
+        _ => None,
+    }
+}
+
+// Encoded samples with the index of the frame they belong to.
+type StereoOutputs = Mutex<Vec<(usize, Vec<u8>)>>;
+
+// A two-layer MV-HEVC compression session. The video-toolbox crate only submits single images
+// through an output handler, so this session is created directly with a C callback and fed
+// tagged buffer groups.
+pub(crate) struct StereoCompressionSession {
+    session: *mut c_void,
+    outputs: Box<StereoOutputs>,
+}
+
+// VTCompressionSession is thread-safe; the outputs are behind a mutex.
+unsafe impl Send for StereoCompressionSession {}
+
+impl StereoCompressionSession {
+    // `properties` are the labelled session properties of the mono session; the MV-HEVC layer
+    // and eye properties are set on top.
+    pub(crate) fn new(
+        width: usize,
+        height: usize,
+        encoder_specification: &CFDictionary<CFString, CFType>,
+        properties: Vec<(String, CFString, CFType)>,
+    ) -> Result<Self, BackendError> {
+        api().ok_or_else(unavailable)?;
+        let mut outputs = Box::new(StereoOutputs::default());
+        let ref_con = (&mut *outputs as *mut StereoOutputs).cast::<c_void>();
+        let mut session = std::ptr::null_mut();
+        let status = unsafe {
+            VTCompressionSessionCreate(
+                std::ptr::null(),
+                width as i32,
+                height as i32,
+                kCMVideoCodecType_HEVC,
+                encoder_specification.as_concrete_TypeRef(),
+                std::ptr::null(),
+                std::ptr::null(),
+                Some(stereo_output_callback),
+                ref_con,
+                &mut session,
+            )
+        };
+        if status != 0 || session.is_null() {
+            return Err(vt_error("VTCompressionSessionCreate", status));
+        }
+        let this = Self { session, outputs };
+        for (label, key, value) in properties {
+            let status = unsafe {
+                VTSessionSetProperty(
+                    this.session,
+                    key.as_concrete_TypeRef(),
+                    value.as_CFTypeRef(),
+                )
+            };
+            if status != 0 {
+                return Err(vt_error(&format!("VTSessionSetProperty({label})"), status));
+            }
+        }
+        let layer_ids = layer_ids();
+        let has_eye = CFBoolean::true_value().as_CFType();
+        for (key, value) in [
+            ("MVHEVCVideoLayerIDs", &layer_ids),
+            ("MVHEVCViewIDs", &layer_ids),
+            ("MVHEVCLeftAndRightViewIDs", &layer_ids),
+            ("HasLeftStereoEyeView", &has_eye),
+            ("HasRightStereoEyeView", &has_eye),
+        ] {
+            set_property(this.session, key, value)?;
+        }
+        let status = unsafe { VTCompressionSessionPrepareToEncodeFrames(this.session) };
+        if status != 0 {
+            return Err(vt_error(
+                "VTCompressionSessionPrepareToEncodeFrames",
+                status,
+            ));
+        }
+        Ok(this)
+    }
+
+    pub(crate) fn encode(
+        &self,
+        frame_index: usize,
+        left: &CVPixelBuffer,
+        right: &CVPixelBuffer,
+        presentation_time_stamp: CMTime,
+        duration: CMTime,
+        frame_properties: &CFDictionary<CFString, CFType>,
+    ) -> Result<(), BackendError> {
+        let api = api().ok_or_else(unavailable)?;
+        let group =
+            tagged_buffer_group(api, [(StereoView::Left, left), (StereoView::Right, right)])?;
+        let status = unsafe {
+            (api.encode_multi_image_frame)(
+                self.session,
+                group.as_CFTypeRef(),
+                presentation_time_stamp,
+                duration,
+                frame_properties.as_concrete_TypeRef(),
+                frame_index as *mut c_void,
+                std::ptr::null_mut(),
+            )
+        };
+        if status != 0 {
+            return Err(vt_error(
+                "VTCompressionSessionEncodeMultiImageFrame",
+                status,
+            ));
+        }
+        Ok(())
+    }
+
+    // Waits for every submitted pair and returns the samples in frame order.
+    pub(crate) fn complete(&self) -> Result<Vec<(usize, Vec<u8>)>, BackendError> {
+        let status = unsafe { VTCompressionSessionCompleteFrames(self.session, kCMTimeInvalid) };
+        if status != 0 {
+            return Err(vt_error("VTCompressionSessionCompleteFrames", status));
+        }
+        let mut outputs =
+            std::mem::take(&mut *self.outputs.lock().unwrap_or_else(PoisonError::into_inner));
+        outputs.sort_by_key(|(frame_index, _)| *frame_index);
+        Ok(outputs)
+    }
+}
+
+impl Drop for StereoCompressionSession {
+    fn drop(&mut self) {
+        unsafe {
+            VTCompressionSessionInvalidate(self.session);
+            CFRelease(self.session as CFTypeRef);
+        }
+    }
+}
+
+fn tagged_buffer_group(
+    api: &MultiViewApi,
+    views: [(StereoView, &CVPixelBuffer); 2],
+) -> Result<CFType, BackendError> {
+    let mut collections = Vec::with_capacity(views.len());
+    for (view, _) in views {
+        let tags = [CMTag::layer(view), CMTag::eye(view)];
+        let mut collection = std::ptr::null();
+        let status = unsafe {
+            (api.tag_collection_create)(
+                std::ptr::null(),
+                tags.as_ptr(),
+                tags.len() as CMItemCount,
+                &mut collection,
+            )
+        };
+        if status != 0 || collection.is_null() {
+            return Err(cm_error("CMTagCollectionCreate", status));
+        }
+        collections.push(unsafe { CFType::wrap_under_create_rule(collection) });
+    }
+    let buffers = views.map(|(_, pixel_buffer)| pixel_buffer.as_CFType());
+    let collections = CFArray::from_CFTypes(&collections);
+    let buffers = CFArray::from_CFTypes(&buffers);
+    let mut group = std::ptr::null();
+    let status = unsafe {
+        (api.tagged_buffer_group_create)(
+            std::ptr::null(),
+            collections.as_concrete_TypeRef(),
+            buffers.as_concrete_TypeRef(),
+            &mut group,
+        )
+    };
+    if status != 0 || group.is_null() {
+        return Err(cm_error("CMTaggedBufferGroupCreate", status));
+    }
+    Ok(unsafe { CFType::wrap_under_create_rule(group) })
+}
+
+extern "C" fn stereo_output_callback(
+    output_callback_ref_con: *mut c_void,
+    source_frame_ref_con: *mut c_void,
+    status: OSStatus,
+    _info_flags: u32,
+    sample_buffer: CMSampleBufferRef,
+) {
+    if status != 0 || output_callback_ref_con.is_null() || sample_buffer.is_null() {
+        return;
+    }
+    let outputs = unsafe { &*(output_callback_ref_con as *const StereoOutputs) };
+    let sample_buffer = unsafe { CMSampleBuffer::wrap_under_get_rule(sample_buffer) };
+    let Some(data_buffer) = sample_buffer.get_data_buffer() else {
+        return;
+    };
+    let mut bytes = vec![0u8; data_buffer.get_data_length()];
+    if data_buffer.copy_data_bytes(0, &mut bytes).is_ok() {
+        outputs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((source_frame_ref_con as usize, bytes));
+    }
+}
@@ -0,0 +1,101 @@
+// Runs every vector listed in `$VIDEO_HW_CONFORMANCE_DIR/conformance.tsv` (JM/HM conformance
+// bitstreams or the bundled samples) through each enabled backend:
+//
+//   VIDEO_HW_CONFORMANCE_DIR=sample-videos cargo test --features backend-nvidia,conformance \
+//       --test conformance -- --nocapture
+//
+// Set VIDEO_HW_CONFORMANCE_RECORD=1 to print manifest lines with the observed values instead of
+// failing, e.g. to record checksums for a new driver version.
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+use video_hw::{
+    Backend, Codec, Dimensions, EncoderConfig, check_encode_session_contract,
+    load_conformance_manifest, run_conformance_suite,
+};
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+fn run_conformance(backend: Backend) {
+    let Some(dir) = std::env::var_os("VIDEO_HW_CONFORMANCE_DIR") else {
+        eprintln!("skip: VIDEO_HW_CONFORMANCE_DIR is not set");
+        return;
+    };
+    let vectors = load_conformance_manifest(&dir).expect("conformance manifest should load");
+    let report = run_conformance_suite(backend, &vectors);
+    println!("{report}");
+    if std::env::var_os("VIDEO_HW_CONFORMANCE_RECORD").is_some() {
+        print!("{}", report.to_manifest(&vectors));
+        return;
+    }
+    assert!(
+        report.all_passed(),
+        "conformance failures:\n{}",
+        report
+            .failures()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+}
+
+// The encoder contract needs no vectors: it encodes a synthetic clip.
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+fn run_encode_contract(backend: Backend) {
+    let dims = Dimensions {
+        width: std::num::NonZeroU32::new(640).unwrap(),
+        height: std::num::NonZeroU32::new(360).unwrap(),
+    };
+    for codec in [Codec::H264, Codec::Hevc] {
+        let report =
+            check_encode_session_contract(backend, EncoderConfig::new(codec, 30, true), dims);
+        println!("{report}");
+        assert!(report.all_passed(), "{report}");
+    }
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[test]
+fn conformance_nvidia() {
+    run_conformance(Backend::Nvidia);
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[test]
+fn encode_contract_nvidia() {
+    run_encode_contract(Backend::Nvidia);
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[test]
+fn conformance_video_toolbox() {
+    run_conformance(Backend::VideoToolbox);
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[test]
+fn encode_contract_video_toolbox() {
+    run_encode_contract(Backend::VideoToolbox);
+}
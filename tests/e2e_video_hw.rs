@@ -22,7 +22,23 @@ use rstest::rstest;
         any(target_os = "linux", target_os = "windows")
     )
 ))]
+use video_hw::EncodedLayout;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
 use video_hw::EncoderConfig;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+use video_hw::LatencyTune;
 #[cfg(all(
     feature = "backend-nvidia",
     any(target_os = "linux", target_os = "windows")
@@ -35,6 +51,14 @@ use video_hw::NvidiaSessionConfig;
         any(target_os = "linux", target_os = "windows")
     )
 ))]
+use video_hw::ParallelGopDecoder;
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
 use video_hw::Timestamp90k;
 #[cfg(all(target_os = "macos", feature = "backend-vt"))]
 use video_hw::VtSessionConfig;
@@ -47,13 +71,24 @@ use video_hw::VtSessionConfig;
 ))]
 use video_hw::{
     Backend, BackendDecoderOptions, BackendError, BitstreamInput, Codec, DecodeSession,
-    DecoderConfig,
+    DecoderConfig, FallbackPolicy, StreamEvent,
 };
 #[cfg(all(
     feature = "backend-nvidia",
     any(target_os = "linux", target_os = "windows")
 ))]
-use video_hw::{BackendEncoderOptions, NvidiaEncoderOptions};
+use video_hw::{
+    BackendEncoderOptions, EncodeLatency, NvidiaDecoderOptions, NvidiaEncoderOptions,
+    RingBufferSink,
+};
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+use video_hw::{DiagnosticEvent, Diagnostics, DiagnosticsSink};
 #[cfg(any(
     all(target_os = "macos", feature = "backend-vt"),
     all(
@@ -69,6 +104,14 @@ use video_hw::{Dimensions, EncodeFrame, EncodeSession, RawFrameBuffer};
         any(target_os = "linux", target_os = "windows")
     )
 ))]
+use video_hw::{IntegrityCheckOptions, IntegrityCheckStats};
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
 use video_hw::{SessionSwitchMode, SessionSwitchRequest};
 #[cfg(any(
     all(target_os = "macos", feature = "backend-vt"),
@@ -106,6 +149,8 @@ fn make_argb_frame(index: i64) -> EncodeFrame {
         pts_90k: Some(video_hw::Timestamp90k(index * 3000)),
         buffer: RawFrameBuffer::Argb8888(argb),
         force_keyframe: index == 0,
+        dirty_rects: None,
+        repeat_count: 0,
     }
 }
 
@@ -140,9 +185,14 @@ fn decode_count(
         backend,
         DecoderConfig {
             codec,
-            fps: 30,
+            fps: 30.into(),
             require_hardware,
+            force_software: false,
+            fallback_policy: FallbackPolicy::default(),
             backend_options: BackendDecoderOptions::Default,
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
         },
     );
 
@@ -182,9 +232,14 @@ fn decode_total_and_summary(
         backend,
         DecoderConfig {
             codec,
-            fps: 30,
+            fps: 30.into(),
             require_hardware,
+            force_software: false,
+            fallback_policy: FallbackPolicy::default(),
             backend_options: BackendDecoderOptions::Default,
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
         },
     );
 
@@ -207,6 +262,222 @@ fn decode_total_and_summary(
     Ok((total, summary.decoded_frames))
 }
 
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+fn decode_count_then_close(
+    backend: Backend,
+    codec: Codec,
+    file_name: &str,
+) -> Result<usize, BackendError> {
+    let mut decoder = DecodeSession::new(backend, DecoderConfig::new(codec, 30, true));
+    let data = fs::read(sample_path(file_name)).expect("sample bitstream should exist");
+
+    let mut total = 0usize;
+    for chunk in data.chunks(4096) {
+        decoder.submit(BitstreamInput::AnnexBChunk {
+            chunk: chunk.to_vec(),
+            pts_90k: None,
+        })?;
+        while decoder.try_reap()?.is_some() {
+            total += 1;
+        }
+    }
+    Ok(total + decoder.close()?.len())
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+fn decode_stream_events(
+    backend: Backend,
+    codec: Codec,
+    file_name: &str,
+) -> Result<Vec<StreamEvent>, BackendError> {
+    let mut decoder = DecodeSession::new(backend, DecoderConfig::new(codec, 30, true));
+    let data = fs::read(sample_path(file_name)).expect("sample bitstream should exist");
+
+    for chunk in data.chunks(4096) {
+        decoder.submit(BitstreamInput::AnnexBChunk {
+            chunk: chunk.to_vec(),
+            pts_90k: None,
+        })?;
+        while decoder.try_reap()?.is_some() {}
+    }
+    decoder.flush()?;
+    Ok(decoder.drain_events())
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+fn assert_single_1080p_resolution_event(events: &[StreamEvent]) {
+    let resolutions = events
+        .iter()
+        .filter(|event| matches!(event, StreamEvent::ResolutionChanged { .. }))
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    assert_eq!(resolutions, vec!["[stream.resolution] 1920x1080"]);
+    assert!(matches!(
+        events.first(),
+        Some(StreamEvent::ParameterSetsUpdated { .. })
+    ));
+    assert!(
+        !events
+            .iter()
+            .any(|event| matches!(event, StreamEvent::RecoveredAfterError { .. }))
+    );
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+fn decode_two_segments_with_drain(
+    backend: Backend,
+    codec: Codec,
+    file_name: &str,
+    require_hardware: bool,
+) -> Result<(usize, usize), BackendError> {
+    let mut decoder = DecodeSession::new(
+        backend,
+        DecoderConfig {
+            codec,
+            fps: 30.into(),
+            require_hardware,
+            force_software: false,
+            fallback_policy: FallbackPolicy::default(),
+            backend_options: BackendDecoderOptions::Default,
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
+        },
+    );
+
+    let path = sample_path(file_name);
+    let data = fs::read(&path).expect("sample bitstream should exist");
+
+    let mut first = 0usize;
+    for chunk in data.chunks(4096) {
+        decoder.submit(BitstreamInput::AnnexBChunk {
+            chunk: chunk.to_vec(),
+            pts_90k: None,
+        })?;
+        while decoder.try_reap()?.is_some() {
+            first += 1;
+        }
+    }
+    first += decoder.drain_available()?.len();
+
+    let mut second = 0usize;
+    for chunk in data.chunks(4096) {
+        decoder.submit(BitstreamInput::AnnexBChunk {
+            chunk: chunk.to_vec(),
+            pts_90k: None,
+        })?;
+        while decoder.try_reap()?.is_some() {
+            second += 1;
+        }
+    }
+    second += decoder.flush()?.len();
+    Ok((first, second))
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+fn parallel_decode_count(
+    backend: Backend,
+    codec: Codec,
+    file_name: &str,
+    require_hardware: bool,
+) -> Result<usize, BackendError> {
+    let decoder = ParallelGopDecoder::new(
+        backend,
+        DecoderConfig {
+            codec,
+            fps: 30.into(),
+            require_hardware,
+            force_software: false,
+            fallback_policy: FallbackPolicy::default(),
+            backend_options: BackendDecoderOptions::Default,
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
+        },
+        4,
+    );
+    Ok(decoder.decode_file(sample_path(file_name))?.len())
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+fn split_decode_count(
+    backend: Backend,
+    codec: Codec,
+    file_name: &str,
+    require_hardware: bool,
+) -> Result<usize, BackendError> {
+    let (mut submitter, reaper) = DecodeSession::split(
+        backend,
+        DecoderConfig {
+            codec,
+            fps: 30.into(),
+            require_hardware,
+            force_software: false,
+            fallback_policy: FallbackPolicy::default(),
+            backend_options: BackendDecoderOptions::Default,
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
+        },
+    );
+    let data = fs::read(sample_path(file_name)).expect("sample bitstream should exist");
+
+    let reaper_thread = std::thread::spawn(move || {
+        let mut total = 0usize;
+        for frame in reaper {
+            frame?;
+            total += 1;
+        }
+        Ok::<usize, BackendError>(total)
+    });
+    for chunk in data.chunks(4096) {
+        submitter.submit(BitstreamInput::AnnexBChunk {
+            chunk: chunk.to_vec(),
+            pts_90k: None,
+        })?;
+    }
+    submitter.finish()?;
+    reaper_thread
+        .join()
+        .expect("reaper thread should not panic")
+}
+
 #[cfg(all(
     feature = "backend-nvidia",
     any(target_os = "linux", target_os = "windows")
@@ -291,6 +562,226 @@ fn e2e_nv_decode_summary_matches_observed_frames(#[case] codec: Codec, #[case] f
     }
 }
 
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[rstest]
+#[case(Codec::H264, "sample-10s.h264")]
+#[case(Codec::Hevc, "sample-10s.h265")]
+fn e2e_nv_decode_close_returns_remaining_frames(#[case] codec: Codec, #[case] file_name: &str) {
+    match decode_count_then_close(Backend::Nvidia, codec, file_name) {
+        Ok(decoded) => assert_eq!(decoded, 303),
+        Err(err) if nv_runtime_unsupported(&err) => {
+            eprintln!("skip: NV decode unavailable: {err}");
+        }
+        Err(err) => panic!("unexpected NV decode error: {err:?}"),
+    }
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[rstest]
+#[case(Codec::H264, "sample-10s.h264")]
+#[case(Codec::Hevc, "sample-10s.h265")]
+fn e2e_vt_decode_close_returns_remaining_frames(#[case] codec: Codec, #[case] file_name: &str) {
+    let decoded = decode_count_then_close(Backend::VideoToolbox, codec, file_name)
+        .expect("decode should succeed");
+    assert_eq!(decoded, 303);
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[rstest]
+#[case(Codec::H264, "sample-10s.h264")]
+#[case(Codec::Hevc, "sample-10s.h265")]
+fn e2e_nv_decode_reports_stream_events(#[case] codec: Codec, #[case] file_name: &str) {
+    match decode_stream_events(Backend::Nvidia, codec, file_name) {
+        Ok(events) => assert_single_1080p_resolution_event(&events),
+        Err(err) if nv_runtime_unsupported(&err) => {
+            eprintln!("skip: NV decode unavailable: {err}");
+        }
+        Err(err) => panic!("unexpected NV decode error: {err:?}"),
+    }
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[rstest]
+#[case(Codec::H264, "sample-10s.h264")]
+#[case(Codec::Hevc, "sample-10s.h265")]
+fn e2e_vt_decode_reports_stream_events(#[case] codec: Codec, #[case] file_name: &str) {
+    let events = decode_stream_events(Backend::VideoToolbox, codec, file_name)
+        .expect("decode should succeed");
+    assert_single_1080p_resolution_event(&events);
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[rstest]
+#[case(Codec::H264, "sample-10s.h264")]
+#[case(Codec::Hevc, "sample-10s.h265")]
+fn e2e_decode_drain_available_keeps_session_usable(#[case] codec: Codec, #[case] file_name: &str) {
+    let (first, second) =
+        decode_two_segments_with_drain(Backend::VideoToolbox, codec, file_name, false)
+            .expect("decode should succeed");
+    assert_eq!(first, 303);
+    assert_eq!(second, 303);
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[rstest]
+#[case(Codec::H264, "sample-10s.h264")]
+#[case(Codec::Hevc, "sample-10s.h265")]
+fn e2e_nv_decode_drain_available_keeps_session_usable(
+    #[case] codec: Codec,
+    #[case] file_name: &str,
+) {
+    match decode_two_segments_with_drain(Backend::Nvidia, codec, file_name, true) {
+        Ok((first, second)) => {
+            assert_eq!(first, 303);
+            assert_eq!(second, 303);
+        }
+        Err(err) if nv_runtime_unsupported(&err) => {
+            eprintln!("skip: NV decode unavailable: {err}");
+        }
+        Err(err) => panic!("unexpected NV decode error: {err:?}"),
+    }
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[rstest]
+#[case(Codec::H264, "sample-10s.h264")]
+#[case(Codec::Hevc, "sample-10s.h265")]
+fn e2e_decode_force_software_matches_expected_frames(
+    #[case] codec: Codec,
+    #[case] file_name: &str,
+) {
+    let mut config = DecoderConfig::new(codec, 30, false);
+    config.force_software = true;
+    let mut decoder = DecodeSession::new(Backend::VideoToolbox, config);
+    let data = fs::read(sample_path(file_name)).expect("sample bitstream should exist");
+    let mut total = 0usize;
+    for chunk in data.chunks(4096) {
+        decoder
+            .submit(BitstreamInput::AnnexBChunk {
+                chunk: chunk.to_vec(),
+                pts_90k: None,
+            })
+            .expect("software decode submit should succeed");
+        while decoder
+            .try_reap()
+            .expect("software decode reap should succeed")
+            .is_some()
+        {
+            total += 1;
+        }
+    }
+    total += decoder
+        .flush()
+        .expect("software decode flush should succeed")
+        .len();
+    assert_eq!(total, 303);
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[rstest]
+#[case(Codec::H264, "sample-10s.h264")]
+#[case(Codec::Hevc, "sample-10s.h265")]
+fn e2e_parallel_gop_decode_expected_frames(#[case] codec: Codec, #[case] file_name: &str) {
+    let frames = parallel_decode_count(Backend::VideoToolbox, codec, file_name, false)
+        .expect("parallel decode should succeed");
+    assert_eq!(frames, 303);
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[rstest]
+#[case(Codec::H264, "sample-10s.h264")]
+#[case(Codec::Hevc, "sample-10s.h265")]
+fn e2e_nv_parallel_gop_decode_expected_frames(#[case] codec: Codec, #[case] file_name: &str) {
+    match parallel_decode_count(Backend::Nvidia, codec, file_name, true) {
+        Ok(frames) => assert_eq!(frames, 303),
+        Err(err) if nv_runtime_unsupported(&err) => {
+            eprintln!("skip: NV decode unavailable: {err}");
+        }
+        Err(err) => panic!("unexpected NV decode error: {err:?}"),
+    }
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[rstest]
+#[case(Codec::H264, "sample-10s.h264")]
+#[case(Codec::Hevc, "sample-10s.h265")]
+fn e2e_split_decode_session_expected_frames(#[case] codec: Codec, #[case] file_name: &str) {
+    let frames = split_decode_count(Backend::VideoToolbox, codec, file_name, false)
+        .expect("split decode should succeed");
+    assert_eq!(frames, 303);
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[rstest]
+#[case(Codec::H264, "sample-10s.h264")]
+#[case(Codec::Hevc, "sample-10s.h265")]
+fn e2e_nv_split_decode_session_expected_frames(#[case] codec: Codec, #[case] file_name: &str) {
+    match split_decode_count(Backend::Nvidia, codec, file_name, true) {
+        Ok(frames) => assert_eq!(frames, 303),
+        Err(err) if nv_runtime_unsupported(&err) => {
+            eprintln!("skip: NV decode unavailable: {err}");
+        }
+        Err(err) => panic!("unexpected NV decode error: {err:?}"),
+    }
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+#[test]
+fn e2e_split_session_handles_are_send() {
+    fn assert_send<T: Send>() {}
+    assert_send::<video_hw::DecodeSubmitter>();
+    assert_send::<video_hw::DecodeReaper>();
+    assert_send::<video_hw::EncodeSubmitter>();
+    assert_send::<video_hw::EncodeReaper>();
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[test]
+fn e2e_split_encode_session_reaps_on_another_thread() {
+    let (mut submitter, reaper) = EncodeSession::split(
+        Backend::VideoToolbox,
+        EncoderConfig::new(Codec::H264, 30, false),
+    );
+    let reaper_thread = std::thread::spawn(move || {
+        reaper
+            .map(|chunk| chunk.expect("encode should succeed"))
+            .collect::<Vec<_>>()
+    });
+    for index in 0..30 {
+        submitter
+            .submit(make_argb_frame(index))
+            .expect("submit should succeed");
+    }
+    submitter.finish().expect("finish should succeed");
+
+    let chunks = reaper_thread
+        .join()
+        .expect("reaper thread should not panic");
+    assert!(!chunks.is_empty());
+    assert!(chunks[0].is_keyframe);
+}
+
 #[cfg(all(target_os = "macos", feature = "backend-vt"))]
 #[test]
 fn e2e_vt_decode_metadata_includes_pts_and_decode_flags() {
@@ -298,9 +789,14 @@ fn e2e_vt_decode_metadata_includes_pts_and_decode_flags() {
         Backend::VideoToolbox,
         DecoderConfig {
             codec: Codec::H264,
-            fps: 30,
+            fps: 30.into(),
             require_hardware: false,
+            force_software: false,
+            fallback_policy: FallbackPolicy::default(),
             backend_options: BackendDecoderOptions::Default,
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
         },
     );
     let data = fs::read(sample_path("sample-10s.h264")).expect("sample bitstream should exist");
@@ -328,10 +824,18 @@ fn e2e_vt_decode_metadata_includes_pts_and_decode_flags() {
             pts_90k,
             decode_info_flags,
             color,
+            planes,
             ..
         } => {
             assert!(pts_90k.is_some());
             assert!(decode_info_flags.is_some());
+            let planes = planes.expect("VT frames should report plane layout");
+            assert!(!planes.is_empty());
+            assert!(
+                planes
+                    .iter()
+                    .all(|plane| plane.stride > 0 && plane.rows > 0)
+            );
             if let Some(color) = color {
                 assert!(
                     color.color_primaries.is_some()
@@ -351,9 +855,14 @@ fn e2e_decode_flush_without_input_is_empty() {
         Backend::VideoToolbox,
         DecoderConfig {
             codec: Codec::H264,
-            fps: 30,
+            fps: 30.into(),
             require_hardware: false,
+            force_software: false,
+            fallback_policy: FallbackPolicy::default(),
             backend_options: BackendDecoderOptions::Default,
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
         },
     );
 
@@ -362,6 +871,135 @@ fn e2e_decode_flush_without_input_is_empty() {
     assert_eq!(decoder.summary().decoded_frames, 0);
 }
 
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+#[derive(Default)]
+struct RecordingSink(std::sync::Mutex<Vec<DiagnosticEvent>>);
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+impl DiagnosticsSink for RecordingSink {
+    fn on_event(&self, event: &DiagnosticEvent) {
+        self.0.lock().unwrap().push(event.clone());
+    }
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+fn decode_with_ready_capacity(backend: Backend, capacity: usize) -> Result<usize, BackendError> {
+    let mut decoder = DecodeSession::new(backend, DecoderConfig::new(Codec::H264, 30, false));
+    decoder.set_ready_capacity(Some(capacity));
+    let data = fs::read(sample_path("sample-10s.h264")).expect("sample should be readable");
+
+    let mut total = 0usize;
+    for chunk in data.chunks(4096) {
+        let input = || BitstreamInput::AnnexBChunk {
+            chunk: chunk.to_vec(),
+            pts_90k: None,
+        };
+        match decoder.submit(input()) {
+            Ok(()) => {}
+            Err(BackendError::TemporaryBackpressure(_)) => {
+                assert!(decoder.ready_stats().depth >= capacity);
+                while decoder.try_reap()?.is_some() {
+                    total += 1;
+                }
+                decoder.submit(input())?;
+            }
+            Err(err) => return Err(err),
+        }
+        let stats = decoder.ready_stats();
+        assert!(stats.peak_depth >= stats.depth);
+    }
+    while decoder.try_reap()?.is_some() {
+        total += 1;
+    }
+    assert_eq!(decoder.ready_stats().depth, 0);
+    total += decoder.flush()?.len();
+    Ok(total)
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[test]
+fn e2e_decode_ready_capacity_applies_backpressure_without_dropping_frames() {
+    let decoded =
+        decode_with_ready_capacity(Backend::VideoToolbox, 8).expect("decode should succeed");
+    assert_eq!(decoded, 303);
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[test]
+fn e2e_nv_decode_ready_capacity_applies_backpressure_without_dropping_frames() {
+    match decode_with_ready_capacity(Backend::Nvidia, 8) {
+        Ok(decoded) => assert_eq!(decoded, 303),
+        Err(err) if nv_runtime_unsupported(&err) => {
+            eprintln!("skip: NV decode unavailable: {err}");
+        }
+        Err(err) => panic!("unexpected NV decode error: {err:?}"),
+    }
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[test]
+fn e2e_decode_session_reports_creation_to_diagnostics_sink() {
+    let sink = std::sync::Arc::new(RecordingSink::default());
+    let mut decoder = DecodeSession::with_diagnostics(
+        Backend::VideoToolbox,
+        DecoderConfig {
+            codec: Codec::H264,
+            fps: 30.into(),
+            require_hardware: false,
+            force_software: true,
+            fallback_policy: FallbackPolicy::default(),
+            backend_options: BackendDecoderOptions::Default,
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
+        },
+        Diagnostics::from_arc(sink.clone()),
+    );
+    let data = fs::read(sample_path("sample-10s.h264")).expect("sample should be readable");
+    decoder
+        .submit(BitstreamInput::AnnexBChunk {
+            chunk: data,
+            pts_90k: None,
+        })
+        .expect("submit should succeed");
+    let _ = decoder.flush().expect("flush should succeed");
+
+    let events = sink.0.lock().unwrap();
+    assert!(matches!(
+        events.first(),
+        Some(DiagnosticEvent::SessionCreated {
+            codec: Codec::H264,
+            ..
+        })
+    ));
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, DiagnosticEvent::SoftwareFallback { .. }))
+    );
+}
+
 #[cfg(all(
     feature = "backend-nvidia",
     any(target_os = "linux", target_os = "windows")
@@ -372,9 +1010,14 @@ fn e2e_nv_decode_flush_without_input_is_empty() {
         Backend::Nvidia,
         DecoderConfig {
             codec: Codec::H264,
-            fps: 30,
+            fps: 30.into(),
             require_hardware: true,
+            force_software: false,
+            fallback_policy: FallbackPolicy::default(),
             backend_options: BackendDecoderOptions::Default,
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
         },
     );
 
@@ -386,8 +1029,103 @@ fn e2e_nv_decode_flush_without_input_is_empty() {
         Err(err) if nv_runtime_unsupported(&err) => {
             eprintln!("skip: NV decode unavailable: {err}");
         }
-        Err(err) => panic!("unexpected NV flush error: {err:?}"),
+        Err(err) => panic!("unexpected NV flush error: {err:?}"),
+    }
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[rstest]
+#[case(true)]
+#[case(false)]
+fn e2e_nv_decode_with_tuned_surfaces_expected_frames(#[case] low_latency: bool) {
+    let mut decoder = DecodeSession::new(
+        Backend::Nvidia,
+        DecoderConfig {
+            codec: Codec::H264,
+            fps: 30.into(),
+            require_hardware: true,
+            force_software: false,
+            fallback_policy: FallbackPolicy::default(),
+            backend_options: BackendDecoderOptions::Nvidia(NvidiaDecoderOptions {
+                decode_surfaces: Some(20),
+                output_surfaces: Some(4),
+                low_latency: Some(low_latency),
+                ..Default::default()
+            }),
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
+        },
+    );
+    let data = fs::read(sample_path("sample-10s.h264")).expect("sample should be readable");
+    let decoded = decoder
+        .submit(BitstreamInput::AnnexBChunk {
+            chunk: data,
+            pts_90k: None,
+        })
+        .and_then(|()| decoder.flush());
+    match decoded {
+        Ok(frames) => assert_eq!(frames.len(), 303),
+        Err(err) if nv_runtime_unsupported(&err) => {
+            eprintln!("skip: NV decode unavailable: {err}");
+        }
+        Err(err) => panic!("unexpected NV decode error: {err:?}"),
+    }
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[test]
+fn e2e_nv_decode_falls_back_from_unconfigured_software_to_hardware() {
+    // Software first, but no software decoder is configured: the first push fails and the
+    // session has to move on to NVDEC with the same input.
+    let sink = std::sync::Arc::new(RecordingSink::default());
+    let mut decoder = DecodeSession::with_diagnostics(
+        Backend::Nvidia,
+        DecoderConfig {
+            codec: Codec::H264,
+            fps: 30.into(),
+            require_hardware: false,
+            force_software: false,
+            fallback_policy: FallbackPolicy {
+                prefer_hardware: false,
+                allow_software: true,
+                allow_cross_vendor: false,
+            },
+            backend_options: BackendDecoderOptions::Default,
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
+        },
+        Diagnostics::from_arc(sink.clone()),
+    );
+    let data = fs::read(sample_path("sample-10s.h264")).expect("sample should be readable");
+    let decoded = decoder
+        .submit(BitstreamInput::AnnexBChunk {
+            chunk: data,
+            pts_90k: None,
+        })
+        .and_then(|()| decoder.flush());
+    match decoded {
+        Ok(frames) => assert_eq!(frames.len(), 303),
+        Err(err) if nv_runtime_unsupported(&err) => {
+            eprintln!("skip: NV decode unavailable: {err}");
+            return;
+        }
+        Err(err) => panic!("unexpected NV decode error: {err:?}"),
     }
+
+    let events = sink.0.lock().unwrap();
+    assert!(events.iter().any(|event| matches!(
+        event,
+        DiagnosticEvent::BackendFallback { from, to, .. }
+            if from == "nvidia(software)" && to == "nvidia"
+    )));
 }
 
 #[cfg(all(target_os = "macos", feature = "backend-vt"))]
@@ -426,12 +1164,14 @@ fn e2e_encode_h264_rejects_invalid_argb_payload() {
         pts_90k: Some(Timestamp90k(0)),
         buffer: RawFrameBuffer::Argb8888(vec![0_u8; 16]),
         force_keyframe: false,
+        dirty_rects: None,
+        repeat_count: 0,
     };
 
     let result = encoder.submit(bad_frame);
     match result {
         Err(video_hw::BackendError::InvalidInput(message)) => {
-            assert!(message.contains("argb payload size mismatch"));
+            assert!(message.contains("ARGB8888 buffer for 640x360 has 16 bytes"));
         }
         other => panic!("unexpected result: {other:?}"),
     }
@@ -506,6 +1246,283 @@ fn e2e_nv_encode_h264_packets_are_pts_monotonic() {
     }
 }
 
+// Frames submitted after each one before its chunk could be reaped, worst case over a
+// zero-latency encode.
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+fn zero_latency_pipeline_delay(backend: Backend) -> Result<i64, BackendError> {
+    let mut config = EncoderConfig::new(Codec::H264, 30, true);
+    config.latency_tune = LatencyTune::ZeroLatency;
+    let mut encoder = EncodeSession::new(backend, config);
+    let mut max_delay = 0;
+    for index in 0..30 {
+        encoder.submit(make_argb_frame(index))?;
+        while let Some(chunk) = encoder.try_reap()? {
+            let pts = chunk.pts_90k.expect("chunk pts").0;
+            max_delay = max_delay.max(index - pts / 3000);
+        }
+    }
+    assert!(encoder.flush()?.is_empty());
+    Ok(max_delay)
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[test]
+fn e2e_vt_zero_latency_reaps_each_frame_after_its_submit() {
+    let delay = zero_latency_pipeline_delay(Backend::VideoToolbox).expect("zero-latency encode");
+    assert!(delay <= 1, "pipeline delay of {delay} frames");
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[test]
+fn e2e_nv_zero_latency_reaps_each_frame_after_its_submit() {
+    match zero_latency_pipeline_delay(Backend::Nvidia) {
+        Ok(delay) => assert!(delay <= 1, "pipeline delay of {delay} frames"),
+        Err(err) if nv_runtime_unsupported(&err) => {
+            eprintln!("skip: CUDA/NVENC unavailable: {err}");
+        }
+        Err(err) => panic!("unexpected NV zero-latency error: {err:?}"),
+    }
+}
+
+// Encodes 20 frames with a keyframe forced at frame 10 and returns the output layout and the
+// frame indices tagged as keyframes, after checking every tag against the chunk's NAL units.
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+fn keyframe_golden(
+    backend: Backend,
+    codec: Codec,
+) -> Result<(EncodedLayout, Vec<i64>), BackendError> {
+    let mut encoder = EncodeSession::new(backend, EncoderConfig::new(codec, 30, false));
+    let mut chunks = Vec::new();
+    for index in 0..20 {
+        let mut frame = make_argb_frame(index);
+        frame.force_keyframe |= index == 10;
+        encoder.submit(frame)?;
+        while let Some(chunk) = encoder.try_reap()? {
+            chunks.push(chunk);
+        }
+    }
+    chunks.extend(encoder.flush()?);
+    let layout = chunks.first().expect("encoded chunks").layout;
+    let mut keyframes = Vec::new();
+    for chunk in &chunks {
+        assert_eq!(chunk.layout, layout);
+        let irap = chunk.nal_units()?.iter().any(|nal| match codec {
+            Codec::H264 => nal.nal_type == 5,
+            _ => (16..=21).contains(&nal.nal_type),
+        });
+        let pts = chunk.pts_90k.expect("chunk pts").0;
+        assert_eq!(chunk.is_keyframe, irap, "{codec:?} frame {}", pts / 3000);
+        if chunk.is_keyframe {
+            keyframes.push(pts / 3000);
+        }
+    }
+    keyframes.sort_unstable();
+    Ok((layout, keyframes))
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[rstest]
+#[case(Codec::H264, EncodedLayout::Avcc)]
+#[case(Codec::Hevc, EncodedLayout::Hvcc)]
+fn e2e_vt_keyframe_tags_follow_the_bitstream(#[case] codec: Codec, #[case] layout: EncodedLayout) {
+    let golden = keyframe_golden(Backend::VideoToolbox, codec).expect("VT encode");
+    assert_eq!(golden, (layout, vec![0, 10]));
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[rstest]
+#[case(Codec::H264)]
+#[case(Codec::Hevc)]
+fn e2e_nv_keyframe_tags_follow_the_bitstream(#[case] codec: Codec) {
+    match keyframe_golden(Backend::Nvidia, codec) {
+        Ok(golden) => assert_eq!(golden, (EncodedLayout::AnnexB, vec![0, 10])),
+        Err(err) if nv_runtime_unsupported(&err) => {
+            eprintln!("skip: CUDA/NVENC unavailable: {err}");
+        }
+        Err(err) => panic!("unexpected NV keyframe golden error: {err:?}"),
+    }
+}
+
+#[cfg(any(
+    all(target_os = "macos", feature = "backend-vt"),
+    all(
+        feature = "backend-nvidia",
+        any(target_os = "linux", target_os = "windows")
+    )
+))]
+fn integrity_stats(backend: Backend, codec: Codec) -> Result<IntegrityCheckStats, BackendError> {
+    let mut encoder = EncodeSession::new(backend, EncoderConfig::new(codec, 30, false));
+    // A window on every keyframe, each running to the next one.
+    encoder.set_integrity_check(Some(IntegrityCheckOptions {
+        every_chunks: std::num::NonZeroU32::new(1).expect("non-zero"),
+        window_chunks: std::num::NonZeroU32::new(20).expect("non-zero"),
+        backend: None,
+    }));
+    for index in 0..20 {
+        let mut frame = make_argb_frame(index);
+        frame.force_keyframe |= index == 10;
+        encoder.submit(frame)?;
+        while encoder.try_reap()?.is_some() {}
+    }
+    encoder.flush()?;
+    Ok(encoder
+        .integrity_check_stats()
+        .expect("integrity check enabled"))
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[test]
+fn e2e_vt_integrity_check_skips_out_of_band_parameter_sets() {
+    let stats = integrity_stats(Backend::VideoToolbox, Codec::H264).expect("VT encode");
+    assert_eq!((stats.windows, stats.windows_skipped), (0, 2));
+    assert_eq!(stats.chunks_checked, 0);
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[rstest]
+#[case(Codec::H264)]
+#[case(Codec::Hevc)]
+fn e2e_nv_integrity_check_redecodes_output(#[case] codec: Codec) {
+    match integrity_stats(Backend::Nvidia, codec) {
+        Ok(stats) => {
+            assert_eq!(stats.windows + stats.windows_skipped, 2);
+            assert!(stats.windows >= 1, "{stats:?}");
+            assert_eq!(stats.mismatches, 0, "{stats:?}");
+            assert_eq!(stats.frames_decoded, stats.chunks_checked);
+        }
+        Err(err) if nv_runtime_unsupported(&err) => {
+            eprintln!("skip: CUDA/NVENC unavailable: {err}");
+        }
+        Err(err) => panic!("unexpected NV integrity check error: {err:?}"),
+    }
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[test]
+fn e2e_encode_iter_yields_chunks() {
+    let mut encoder = EncodeSession::new(
+        Backend::VideoToolbox,
+        EncoderConfig::new(Codec::H264, 30, false),
+    );
+    let chunks = encoder
+        .encode_iter((0..90).map(make_argb_frame))
+        .collect::<Result<Vec<_>, _>>()
+        .expect("encode_iter should succeed");
+
+    assert!(!chunks.is_empty());
+    assert!(chunks[0].is_keyframe);
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[test]
+fn e2e_nv_encode_iter_yields_chunks() {
+    let mut encoder =
+        EncodeSession::new(Backend::Nvidia, EncoderConfig::new(Codec::H264, 30, true));
+    match encoder
+        .encode_iter((0..90).map(make_argb_frame))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(chunks) => {
+            assert!(!chunks.is_empty());
+            assert!(chunks[0].is_keyframe);
+        }
+        Err(err) if nv_runtime_unsupported(&err) => {
+            eprintln!("skip: CUDA/NVENC unavailable: {err}");
+        }
+        Err(err) => panic!("unexpected NV encode_iter error: {err:?}"),
+    }
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[test]
+fn e2e_vt_session_info_has_no_buffer_lifetime_mode() {
+    let encoder = EncodeSession::new(
+        Backend::VideoToolbox,
+        EncoderConfig::new(Codec::H264, 30, false),
+    );
+    let info = encoder.session_info();
+    assert_eq!(info.backend, Backend::VideoToolbox);
+    assert_eq!(info.buffer_lifetime_mode, None);
+}
+
+#[cfg(all(target_os = "macos", feature = "backend-vt"))]
+#[test]
+fn e2e_encode_invalidate_reference_is_unsupported_on_vt() {
+    let mut encoder = EncodeSession::new(
+        Backend::VideoToolbox,
+        EncoderConfig::new(Codec::H264, 30, false),
+    );
+    encoder
+        .submit(make_argb_frame(0))
+        .expect("submit should succeed");
+    assert!(matches!(
+        encoder.invalidate_reference(Timestamp90k(0)),
+        Err(BackendError::UnsupportedConfig(_))
+    ));
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[test]
+fn e2e_nv_encode_invalidate_reference_keeps_encoding() {
+    let mut encoder =
+        EncodeSession::new(Backend::Nvidia, EncoderConfig::new(Codec::H264, 30, true));
+
+    for i in 0..30 {
+        if let Err(err) = encoder.submit(make_argb_frame(i as i64)) {
+            if nv_runtime_unsupported(&err) {
+                eprintln!("skip: CUDA/NVENC unavailable: {err}");
+                return;
+            }
+            panic!("unexpected NV encode submit error: {err:?}");
+        }
+        if i == 20 {
+            match encoder.invalidate_reference(Timestamp90k(15 * 3000)) {
+                Ok(()) | Err(BackendError::InvalidInput(_)) => {}
+                Err(err) if nv_runtime_unsupported(&err) => {
+                    eprintln!("skip: NVENC reference invalidation unavailable: {err}");
+                    return;
+                }
+                Err(err) => panic!("unexpected NV invalidate_reference error: {err:?}"),
+            }
+        }
+    }
+
+    match encoder.flush() {
+        Ok(packets) => assert!(!packets.is_empty()),
+        Err(err) if nv_runtime_unsupported(&err) => {
+            eprintln!("skip: CUDA/NVENC unavailable: {err}");
+        }
+        Err(err) => panic!("unexpected NV encode flush error: {err:?}"),
+    }
+}
+
 #[cfg(all(
     feature = "backend-nvidia",
     any(target_os = "linux", target_os = "windows")
@@ -519,14 +1536,13 @@ fn e2e_nv_encode_h264_rejects_invalid_argb_payload() {
         pts_90k: Some(Timestamp90k(0)),
         buffer: RawFrameBuffer::Argb8888(vec![0_u8; 16]),
         force_keyframe: false,
+        dirty_rects: None,
+        repeat_count: 0,
     };
 
-    encoder
-        .submit(bad_frame)
-        .expect("submit should enqueue frame before validation");
-    match encoder.flush() {
+    match encoder.submit(bad_frame) {
         Err(BackendError::InvalidInput(message)) => {
-            assert!(message.contains("argb payload size mismatch"));
+            assert!(message.contains("ARGB8888 buffer for 640x360 has 16 bytes"));
         }
         Err(err) if nv_runtime_unsupported(&err) => {
             eprintln!("skip: CUDA/NVENC unavailable: {err}");
@@ -545,6 +1561,9 @@ fn e2e_vt_backend_accepts_explicit_session_switch_request() {
     let result = encoder.request_session_switch(SessionSwitchRequest::VideoToolbox {
         config: VtSessionConfig {
             force_keyframe_on_activate: true,
+            quality: None,
+            average_bitrate_bps: None,
+            max_qp: None,
         },
         mode: SessionSwitchMode::Immediate,
     });
@@ -561,9 +1580,14 @@ fn e2e_nv_backend_decode_and_encode_work() {
         Backend::Nvidia,
         DecoderConfig {
             codec: Codec::H264,
-            fps: 30,
+            fps: 30.into(),
             require_hardware: true,
+            force_software: false,
+            fallback_policy: FallbackPolicy::default(),
             backend_options: BackendDecoderOptions::Default,
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
         },
     );
 
@@ -636,9 +1660,14 @@ fn e2e_nv_backend_hevc_decode_sample() {
         Backend::Nvidia,
         DecoderConfig {
             codec: Codec::Hevc,
-            fps: 30,
+            fps: 30.into(),
             require_hardware: true,
+            force_software: false,
+            fallback_policy: FallbackPolicy::default(),
             backend_options: BackendDecoderOptions::Default,
+            max_temporal_id: None,
+            output_scale: None,
+            stereo_views: false,
         },
     );
 
@@ -727,6 +1756,175 @@ fn e2e_nv_backend_encode_accepts_backend_specific_options() {
     }
 }
 
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[test]
+fn e2e_nv_session_info_reports_buffer_lifetime_mode() {
+    let mut options = NvidiaEncoderOptions::default();
+    options.buffer_lifetime_mode = Some(video_hw::NvBufferLifetimeMode::PerFrameSafe);
+    let mut config = EncoderConfig::new(Codec::H264, 30, true);
+    config.backend_options = BackendEncoderOptions::Nvidia(options);
+    let mut encoder = EncodeSession::new(Backend::Nvidia, config);
+    assert_eq!(
+        encoder.session_info().buffer_lifetime_mode,
+        Some(video_hw::NvBufferLifetimeMode::PerFrameSafe)
+    );
+
+    let result = encoder.request_session_switch(SessionSwitchRequest::Nvidia {
+        config: NvidiaSessionConfig {
+            gop_length: None,
+            frame_interval_p: None,
+            force_idr_on_activate: true,
+            buffer_lifetime_mode: Some(video_hw::NvBufferLifetimeMode::ReusablePoolUnsafe),
+            average_bitrate_bps: None,
+            max_qp: None,
+        },
+        mode: SessionSwitchMode::Immediate,
+    });
+    assert!(result.is_ok());
+    assert_eq!(
+        encoder.session_info().buffer_lifetime_mode,
+        Some(video_hw::NvBufferLifetimeMode::ReusablePoolUnsafe)
+    );
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[test]
+fn e2e_nv_odd_resolution_is_padded_and_cropped_in_sps() {
+    let dims = Dimensions {
+        width: std::num::NonZeroU32::new(1919).expect("non-zero width"),
+        height: std::num::NonZeroU32::new(1079).expect("non-zero height"),
+    };
+    let mut encoder =
+        EncodeSession::new(Backend::Nvidia, EncoderConfig::new(Codec::H264, 30, true));
+    let mut stream = Vec::new();
+    for i in 0..10 {
+        let mut frame = make_argb_frame(i);
+        frame.dims = dims;
+        frame.buffer = RawFrameBuffer::Argb8888(vec![128; 1919 * 1079 * 4]);
+        match encoder.submit(frame) {
+            Ok(()) => {}
+            Err(err) if nv_runtime_unsupported(&err) => {
+                eprintln!("skip: CUDA/NVENC unavailable: {err}");
+                return;
+            }
+            Err(err) => panic!("unexpected encode error: {err:?}"),
+        }
+        while let Some(chunk) = encoder.try_reap().expect("reap should succeed") {
+            stream.extend_from_slice(&chunk.data);
+        }
+    }
+    for chunk in encoder.flush().expect("flush should succeed") {
+        stream.extend_from_slice(&chunk.data);
+    }
+
+    let mut decoder =
+        DecodeSession::new(Backend::Nvidia, DecoderConfig::new(Codec::H264, 30, true));
+    decoder
+        .submit(BitstreamInput::AnnexBChunk {
+            chunk: stream,
+            pts_90k: None,
+        })
+        .expect("decode should succeed");
+    assert_eq!(decoder.flush().expect("flush should succeed").len(), 10);
+    let crop = decoder.frame_crop().expect("SPS should be cached");
+    assert_eq!(crop.to_string(), "1920x1088 -> 1920x1080+0+0");
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[test]
+fn e2e_nv_encode_close_drains_and_releases_session() {
+    let mut encoder =
+        EncodeSession::new(Backend::Nvidia, EncoderConfig::new(Codec::H264, 30, true));
+    let mut packets = 0usize;
+    for i in 0..30 {
+        match encoder.submit(make_argb_frame(i)) {
+            Ok(()) => {}
+            Err(err) if nv_runtime_unsupported(&err) => {
+                eprintln!("skip: CUDA/NVENC unavailable: {err}");
+                return;
+            }
+            Err(err) => panic!("unexpected encode error: {err:?}"),
+        }
+        while encoder.try_reap().expect("reap should succeed").is_some() {
+            packets += 1;
+        }
+    }
+    packets += encoder.close().expect("close should succeed").len();
+    assert_eq!(packets, 30);
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[test]
+fn e2e_nv_encode_sink_receives_chunks_instead_of_ready_queue() {
+    let mut encoder =
+        EncodeSession::new(Backend::Nvidia, EncoderConfig::new(Codec::H264, 30, true));
+    let sink = RingBufferSink::new(usize::MAX);
+    let ring = sink.handle();
+    encoder.attach_sink(sink).expect("attach should succeed");
+    for i in 0..30 {
+        match encoder.submit(make_argb_frame(i)) {
+            Ok(()) => {}
+            Err(err) if nv_runtime_unsupported(&err) => {
+                eprintln!("skip: CUDA/NVENC unavailable: {err}");
+                return;
+            }
+            Err(err) => panic!("unexpected encode error: {err:?}"),
+        }
+        assert!(encoder.try_reap().expect("reap should succeed").is_none());
+    }
+    assert!(encoder.flush().expect("flush should succeed").is_empty());
+    let chunks = ring.snapshot();
+    assert_eq!(chunks.len(), 30);
+    assert!(chunks[0].is_keyframe);
+}
+
+#[cfg(all(
+    feature = "backend-nvidia",
+    any(target_os = "linux", target_os = "windows")
+))]
+#[rstest]
+#[case(Some(0), EncodeLatency { lookahead_frames: 0, reorder_frames: 0 })]
+#[case(Some(16), EncodeLatency { lookahead_frames: 16, reorder_frames: 0 })]
+#[case(Some(64), EncodeLatency { lookahead_frames: 32, reorder_frames: 0 })]
+fn e2e_nv_encode_reports_lookahead_latency(
+    #[case] lookahead_depth: Option<u32>,
+    #[case] expected: EncodeLatency,
+) {
+    let mut options = NvidiaEncoderOptions::default();
+    options.lookahead_depth = lookahead_depth;
+    options.frame_interval_p = Some(1);
+    let mut config = EncoderConfig::new(Codec::H264, 30, true);
+    config.backend_options = BackendEncoderOptions::Nvidia(options);
+    let mut encoder = EncodeSession::new(Backend::Nvidia, config);
+    assert_eq!(encoder.induced_latency(), None);
+
+    match encoder.submit(make_argb_frame(0)) {
+        Ok(()) => {}
+        Err(err) if nv_runtime_unsupported(&err) => {
+            eprintln!("skip: CUDA/NVENC unavailable: {err}");
+            return;
+        }
+        Err(err) => panic!("unexpected encode error: {err:?}"),
+    }
+    assert_eq!(encoder.induced_latency(), Some(expected));
+    assert_eq!(
+        encoder.induced_latency().unwrap().frames(),
+        expected.frames()
+    );
+}
+
 #[cfg(all(
     feature = "backend-nvidia",
     any(target_os = "linux", target_os = "windows")
@@ -740,6 +1938,9 @@ fn e2e_nv_backend_accepts_explicit_session_switch_request() {
             gop_length: Some(60),
             frame_interval_p: Some(1),
             force_idr_on_activate: true,
+            buffer_lifetime_mode: None,
+            average_bitrate_bps: None,
+            max_qp: None,
         },
         mode: SessionSwitchMode::Immediate,
     });